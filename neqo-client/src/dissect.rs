@@ -0,0 +1,123 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Decrypt the QUIC packets in a packet capture using a key log.
+
+#![cfg_attr(feature = "deny-warnings", deny(warnings))]
+#![warn(clippy::use_self)]
+
+use std::{
+    env,
+    fs::{self, OpenOptions},
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use neqo_common::{self as common, pcap, qlog::NeqoQlog, Role};
+use neqo_crypto::init;
+use neqo_transport::{
+    dissect::{Dissector, KeyLog},
+    ConnectionId,
+};
+use qlog::{events::EventImportance, streamer::QlogStreamer};
+use structopt::StructOpt;
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "neqo-dissect",
+    about = "Decrypt the QUIC packets in a pcap or pcapng capture."
+)]
+struct Args {
+    #[structopt(parse(from_os_str))]
+    /// The packet capture to read.
+    capture: PathBuf,
+
+    #[structopt(long, parse(from_os_str))]
+    /// The key log with the TLS secrets.  The default is the file named by SSLKEYLOGFILE.
+    keylog: Option<PathBuf>,
+
+    #[structopt(name = "qlog-dir", long, parse(from_os_str))]
+    /// Write a qlog trace for each connection to this directory, rather than printing packets.
+    qlog_dir: Option<PathBuf>,
+}
+
+fn qlog_new(dir: &Path, cid: &ConnectionId) -> NeqoQlog {
+    let path = dir.join(format!("{cid}.sqlog"));
+    let f = match OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&path)
+    {
+        Ok(f) => f,
+        Err(e) => {
+            eprintln!("{}: {e}", path.display());
+            return NeqoQlog::disabled();
+        }
+    };
+    let streamer = QlogStreamer::new(
+        qlog::QLOG_VERSION.to_string(),
+        Some("neqo-dissect".to_string()),
+        Some("Packets decrypted from a capture".to_string()),
+        None,
+        std::time::Instant::now(),
+        common::qlog::new_trace(Role::Client),
+        EventImportance::Base,
+        Box::new(f),
+    );
+    NeqoQlog::enabled(streamer, &path).unwrap_or_else(|e| {
+        eprintln!("{}: {e}", path.display());
+        NeqoQlog::disabled()
+    })
+}
+
+fn main() {
+    let args = Args::from_args();
+    init();
+
+    let Some(keylog_path) = args
+        .keylog
+        .clone()
+        .or_else(|| env::var_os("SSLKEYLOGFILE").map(PathBuf::from))
+    else {
+        eprintln!("No key log: use --keylog or set SSLKEYLOGFILE");
+        exit(1);
+    };
+    let keylog = KeyLog::load(&keylog_path).unwrap_or_else(|e| {
+        eprintln!("{}: {e}", keylog_path.display());
+        exit(1);
+    });
+    let datagrams = fs::read(&args.capture)
+        .and_then(|buf| pcap::read_datagrams(&buf))
+        .unwrap_or_else(|e| {
+            eprintln!("{}: {e}", args.capture.display());
+            exit(1);
+        });
+
+    let mut dissector = Dissector::new(keylog);
+    if let Some(dir) = args.qlog_dir.clone() {
+        dissector.set_qlog(move |cid| qlog_new(&dir, cid));
+    }
+
+    let (mut decrypted, mut failed) = (0, 0);
+    for captured in &datagrams {
+        for packet in dissector.process(captured.time, &captured.datagram) {
+            if packet.pn().is_some() {
+                decrypted += 1;
+            } else if packet.error().is_some() {
+                failed += 1;
+            }
+            if args.qlog_dir.is_none() {
+                println!("{packet}");
+            }
+        }
+    }
+    eprintln!(
+        "{} datagrams, {} connections, {decrypted} packets decrypted, {failed} not decrypted",
+        datagrams.len(),
+        dissector.connections(),
+    );
+}
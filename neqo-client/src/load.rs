@@ -0,0 +1,682 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// A load generator, which runs many connections at once and reports on how they went.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::{self, Write as _},
+    fs,
+    io::ErrorKind,
+    net::SocketAddr,
+    path::PathBuf,
+    thread,
+    time::{Duration, Instant},
+};
+
+use mio::{net::UdpSocket, Events, Poll, PollOpt, Ready, Token};
+use neqo_common::{Datagram, IpTos};
+use neqo_crypto::AuthenticationStatus;
+use neqo_http3::{Error, Http3Client, Http3ClientEvent, Http3State, Output, Priority, StreamId};
+use neqo_transport::{Error as TransportError, Stats};
+use structopt::StructOpt;
+
+use super::{
+    create_http3_client, emit_datagram, local_addr_for, resolve, to_headers, Args, ClientError, Res,
+};
+
+/// The longest that the event loop waits for a socket to become readable.
+const MAX_POLL: Duration = Duration::from_secs(1);
+
+#[derive(Debug, StructOpt)]
+pub struct LoadParameters {
+    #[structopt(name = "load-connections", long, conflicts_with = "use-old-http")]
+    /// Run a load test with this many concurrent connections instead of fetching the URLs.
+    /// Results are reported in JSON format.
+    connections: Option<usize>,
+
+    #[structopt(name = "load-requests", long, default_value = "1")]
+    /// The number of requests that each connection in a load test makes.
+    /// Requests cycle through the listed URLs, which need to share an origin.
+    requests: usize,
+
+    #[structopt(name = "load-threads", long, default_value = "1")]
+    /// The number of threads that load test connections are spread over.
+    threads: usize,
+
+    #[structopt(name = "load-rate", long, conflicts_with = "load-think-time")]
+    /// The number of requests per second that each connection makes.
+    /// Without this, requests are limited by `--concurrency`.
+    rate: Option<f64>,
+
+    #[structopt(name = "load-think-time", long)]
+    /// How long, in milliseconds, each connection waits after a response before making the
+    /// next request.  Requests on a connection are made one at a time with this.
+    think_time: Option<u64>,
+
+    #[structopt(name = "load-output", long, parse(from_os_str))]
+    /// Write the results of a load test to this file instead of stdout.
+    output: Option<PathBuf>,
+}
+
+impl LoadParameters {
+    pub fn enabled(&self) -> bool {
+        self.connections.is_some()
+    }
+
+    /// When the request after one made at `now` can be made, if that is limited by a rate.
+    fn next_after_request(&self, now: Instant) -> Option<Instant> {
+        self.rate
+            .filter(|r| *r > 0.0)
+            .map(|r| now + Duration::from_secs_f64(1.0 / r))
+    }
+
+    /// When the request after a response that arrived at `now` can be made.
+    fn next_after_response(&self, now: Instant) -> Option<Instant> {
+        self.think_time.map(|t| now + Duration::from_millis(t))
+    }
+}
+
+/// The timing of a request that hasn't completed.
+struct Request {
+    start: Instant,
+    first_byte: Option<Instant>,
+}
+
+/// What one or more connections measured.
+#[derive(Default)]
+struct LoadReport {
+    connections: usize,
+    connected: usize,
+    completed: usize,
+    failed: usize,
+    bytes: u64,
+    handshake: Vec<Duration>,
+    ttfb: Vec<Duration>,
+    latency: Vec<Duration>,
+    stats: Vec<Stats>,
+}
+
+impl LoadReport {
+    fn merge(mut self, other: Self) -> Self {
+        self.connections += other.connections;
+        self.connected += other.connected;
+        self.completed += other.completed;
+        self.failed += other.failed;
+        self.bytes += other.bytes;
+        self.handshake.extend(other.handshake);
+        self.ttfb.extend(other.ttfb);
+        self.latency.extend(other.latency);
+        self.stats.extend(other.stats);
+        self
+    }
+
+    fn to_json(&mut self, duration: Duration) -> Result<String, fmt::Error> {
+        let secs = duration.as_secs_f64().max(f64::EPSILON);
+        let stats = &self.stats;
+        let sum = |f: fn(&Stats) -> usize| stats.iter().map(f).sum::<usize>();
+        let mean = |f: fn(&Stats) -> Duration| {
+            let n = u32::try_from(stats.len()).unwrap_or(u32::MAX).max(1);
+            millis(stats.iter().map(f).sum::<Duration>() / n)
+        };
+        let mut json = String::from("{\n");
+        writeln!(json, "  \"connections\": {},", self.connections)?;
+        writeln!(json, "  \"connected\": {},", self.connected)?;
+        writeln!(json, "  \"requests_completed\": {},", self.completed)?;
+        writeln!(json, "  \"requests_failed\": {},", self.failed)?;
+        writeln!(json, "  \"bytes\": {},", self.bytes)?;
+        writeln!(json, "  \"duration_ms\": {:.3},", millis(duration))?;
+        #[allow(clippy::cast_precision_loss)]
+        let (request_rate, byte_rate) = (self.completed as f64 / secs, self.bytes as f64 / secs);
+        writeln!(json, "  \"requests_per_second\": {request_rate:.3},")?;
+        writeln!(json, "  \"bytes_per_second\": {byte_rate:.3},")?;
+        writeln!(
+            json,
+            "  \"handshake_ms\": {},",
+            percentiles(&mut self.handshake)
+        )?;
+        writeln!(json, "  \"ttfb_ms\": {},", percentiles(&mut self.ttfb))?;
+        writeln!(
+            json,
+            "  \"latency_ms\": {},",
+            percentiles(&mut self.latency)
+        )?;
+        writeln!(json, "  \"transport\": {{")?;
+        writeln!(json, "    \"packets_tx\": {},", sum(|s| s.packets_tx))?;
+        writeln!(json, "    \"packets_rx\": {},", sum(|s| s.packets_rx))?;
+        writeln!(json, "    \"lost\": {},", sum(|s| s.lost))?;
+        writeln!(json, "    \"dropped_rx\": {},", sum(|s| s.dropped_rx))?;
+        writeln!(json, "    \"pto\": {},", sum(pto_count))?;
+        writeln!(json, "    \"rtt_ms_mean\": {:.3},", mean(|s| s.rtt))?;
+        writeln!(json, "    \"rttvar_ms_mean\": {:.3}", mean(|s| s.rttvar))?;
+        json.push_str("  }\n}");
+        Ok(json)
+    }
+}
+
+fn millis(d: Duration) -> f64 {
+    d.as_secs_f64() * 1000.0
+}
+
+/// The total number of times that the PTO timer fired.
+fn pto_count(stats: &Stats) -> usize {
+    // Each entry counts runs of consecutive PTOs of a given length.
+    stats
+        .pto_counts
+        .iter()
+        .enumerate()
+        .map(|(i, c)| (i + 1) * c)
+        .sum()
+}
+
+/// Summarize a set of durations as JSON, in milliseconds.
+fn percentiles(values: &mut [Duration]) -> String {
+    if values.is_empty() {
+        return String::from("null");
+    }
+    values.sort_unstable();
+    let at = |p: usize| millis(values[(values.len() - 1) * p / 100]);
+    format!(
+        "{{\"min\": {:.3}, \"p50\": {:.3}, \"p90\": {:.3}, \"p99\": {:.3}, \"max\": {:.3}}}",
+        at(0),
+        at(50),
+        at(90),
+        at(99),
+        at(100)
+    )
+}
+
+/// How many of `connections` each thread runs, when spread over `threads`.
+fn spread(connections: usize, threads: usize) -> Vec<usize> {
+    let threads = threads.clamp(1, connections.max(1));
+    (0..threads)
+        .map(|t| connections / threads + usize::from(t < connections % threads))
+        .collect()
+}
+
+/// One connection in a load test.
+struct LoadConnection<'a> {
+    args: &'a Args,
+    socket: UdpSocket,
+    local_addr: SocketAddr,
+    client: Http3Client,
+    start: Instant,
+    /// The number of requests that have been made.
+    issued: usize,
+    /// When the next request can be made, if that is limited.
+    next_request: Option<Instant>,
+    requests: HashMap<StreamId, Request>,
+    callback: Option<Instant>,
+    done: bool,
+    report: LoadReport,
+}
+
+impl<'a> LoadConnection<'a> {
+    fn new(
+        args: &'a Args,
+        remote_addr: SocketAddr,
+        hostname: &str,
+        poll: &Poll,
+        token: Token,
+    ) -> Res<Self> {
+        let socket = UdpSocket::bind(&local_addr_for(&remote_addr))?;
+        poll.register(
+            &socket,
+            token,
+            Ready::readable() | Ready::writable(),
+            PollOpt::edge(),
+        )?;
+        let local_addr = socket.local_addr()?;
+        let client = create_http3_client(args, local_addr, remote_addr, hostname, None)?;
+        Ok(Self {
+            args,
+            socket,
+            local_addr,
+            client,
+            start: Instant::now(),
+            issued: 0,
+            next_request: None,
+            requests: HashMap::new(),
+            callback: None,
+            done: false,
+            report: LoadReport {
+                connections: 1,
+                ..LoadReport::default()
+            },
+        })
+    }
+
+    fn receive(&mut self, buf: &mut [u8]) -> Res<()> {
+        let mut datagrams = Vec::new();
+        loop {
+            match self.socket.recv_from(buf) {
+                Err(e)
+                    if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted =>
+                {
+                    break
+                }
+                Err(e) => return Err(e.into()),
+                Ok((sz, remote)) => {
+                    if sz > 0 && sz < buf.len() {
+                        datagrams.push(Datagram::new(
+                            remote,
+                            self.local_addr,
+                            IpTos::default(),
+                            None,
+                            &buf[..sz],
+                        ));
+                    }
+                }
+            }
+        }
+        if !datagrams.is_empty() {
+            self.client
+                .process_multiple_input(&datagrams, Instant::now());
+        }
+        Ok(())
+    }
+
+    fn complete(&mut self, stream_id: StreamId, now: Instant) {
+        if let Some(req) = self.requests.remove(&stream_id) {
+            self.report
+                .ttfb
+                .push(req.first_byte.unwrap_or(now) - req.start);
+            self.report.latency.push(now - req.start);
+            self.report.completed += 1;
+            if let Some(next) = self.args.load.next_after_response(now) {
+                self.next_request = Some(next);
+            }
+        }
+    }
+
+    fn handle_events(&mut self, now: Instant) {
+        let mut buf = [0; 4096];
+        while let Some(event) = self.client.next_event() {
+            match event {
+                Http3ClientEvent::AuthenticationNeeded => {
+                    self.client.authenticated(AuthenticationStatus::Ok, now);
+                }
+                Http3ClientEvent::StateChange(Http3State::Connected) => {
+                    self.report.handshake.push(now - self.start);
+                    self.report.connected += 1;
+                }
+                Http3ClientEvent::HeaderReady { stream_id, fin, .. } => {
+                    if let Some(req) = self.requests.get_mut(&stream_id) {
+                        req.first_byte.get_or_insert(now);
+                    }
+                    if fin {
+                        self.complete(stream_id, now);
+                    }
+                }
+                Http3ClientEvent::DataReadable { stream_id } => loop {
+                    match self.client.read_data(now, stream_id, &mut buf) {
+                        Ok((sz, fin)) => {
+                            self.report.bytes += sz as u64;
+                            if fin {
+                                self.complete(stream_id, now);
+                                break;
+                            }
+                            if sz == 0 {
+                                break;
+                            }
+                        }
+                        Err(_) => {
+                            self.fail(stream_id);
+                            break;
+                        }
+                    }
+                },
+                Http3ClientEvent::Reset { stream_id, .. }
+                | Http3ClientEvent::StopSending { stream_id, .. } => {
+                    self.fail(stream_id);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn fail(&mut self, stream_id: StreamId) {
+        if self.requests.remove(&stream_id).is_some() {
+            self.report.failed += 1;
+        }
+    }
+
+    fn make_requests(&mut self, now: Instant) {
+        if self.client.state() != Http3State::Connected {
+            return;
+        }
+        let params = &self.args.load;
+        while self.issued < params.requests
+            && self.requests.len() < self.args.concurrency
+            && self.next_request.map_or(true, |t| t <= now)
+            && !(params.think_time.is_some() && !self.requests.is_empty())
+        {
+            let url = &self.args.urls[self.issued % self.args.urls.len()];
+            match self.client.fetch(
+                now,
+                &self.args.method,
+                url,
+                &to_headers(&self.args.header),
+                Priority::default(),
+            ) {
+                Ok(stream_id) => {
+                    _ = self.client.stream_close_send(stream_id);
+                    self.requests.insert(
+                        stream_id,
+                        Request {
+                            start: now,
+                            first_byte: None,
+                        },
+                    );
+                }
+                Err(
+                    Error::TransportError(TransportError::StreamLimitError)
+                    | Error::StreamLimitError
+                    | Error::Unavailable,
+                ) => break,
+                Err(e) => {
+                    eprintln!("Unable to make a request for {url}: {e:?}");
+                    self.report.failed += 1;
+                }
+            }
+            self.issued += 1;
+            self.next_request = params.next_after_request(self.next_request.unwrap_or(now));
+        }
+        if self.issued == params.requests && self.requests.is_empty() {
+            self.client.close(now, 0, "kthxbye!");
+        }
+    }
+
+    /// Handle events, make requests, and send what is ready.
+    fn process(&mut self, now: Instant) -> Res<()> {
+        self.handle_events(now);
+        self.make_requests(now);
+        loop {
+            match self.client.process_output(now) {
+                Output::Datagram(dgram) => {
+                    if let Err(e) = emit_datagram(&self.socket, dgram) {
+                        if e.kind() == ErrorKind::WouldBlock || e.kind() == ErrorKind::Interrupted {
+                            break;
+                        }
+                        return Err(e.into());
+                    }
+                }
+                Output::Callback(t) => {
+                    self.callback = Some(now + t);
+                    break;
+                }
+                Output::None => {
+                    self.callback = None;
+                    break;
+                }
+            }
+        }
+        if matches!(
+            self.client.state(),
+            Http3State::Closing(..) | Http3State::Closed(..)
+        ) {
+            self.finish();
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) {
+        self.done = true;
+        self.report.failed += self.requests.len() + self.args.load.requests - self.issued;
+        self.requests.clear();
+        self.issued = self.args.load.requests;
+        self.report.stats.push(self.client.transport_stats());
+    }
+
+    /// When this connection next needs attention.
+    fn wakeup(&self) -> Option<Instant> {
+        let next = self
+            .next_request
+            .filter(|_| self.issued < self.args.load.requests);
+        match (self.callback, next) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        }
+    }
+}
+
+/// Run `count` connections on this thread.
+fn run_connections(
+    args: &Args,
+    remote_addr: SocketAddr,
+    hostname: &str,
+    count: usize,
+) -> Res<LoadReport> {
+    // Certificates are loaded separately for each thread.
+    args.load_client_certificate()?;
+    let poll = Poll::new()?;
+    let mut conns = (0..count)
+        .map(|i| LoadConnection::new(args, remote_addr, hostname, &poll, Token(i)))
+        .collect::<Res<Vec<_>>>()?;
+    let mut events = Events::with_capacity(1024);
+    let mut buf = vec![0; 2048];
+
+    while conns.iter().any(|c| !c.done) {
+        let now = Instant::now();
+        for c in conns.iter_mut().filter(|c| !c.done) {
+            c.process(now)?;
+        }
+        let timeout = conns
+            .iter()
+            .filter(|c| !c.done)
+            .filter_map(LoadConnection::wakeup)
+            .min()
+            .map_or(MAX_POLL, |t| t.saturating_duration_since(Instant::now()))
+            .min(MAX_POLL);
+        poll.poll(&mut events, Some(timeout))?;
+        for event in &events {
+            if let Some(c) = conns.get_mut(event.token().0) {
+                c.receive(&mut buf)?;
+            }
+        }
+    }
+    Ok(conns
+        .into_iter()
+        .map(|c| c.report)
+        .fold(LoadReport::default(), LoadReport::merge))
+}
+
+/// Run a load test against the origin of the first URL.
+pub fn run(args: &Args) -> Res<()> {
+    let connections = args.load.connections.unwrap_or_default();
+    let url = args
+        .urls
+        .first()
+        .ok_or(ClientError::ArgumentError("a load test needs a URL"))?;
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err(ClientError::ArgumentError(
+            "a load test needs a URL with a host",
+        ));
+    };
+    let remote_addr = resolve(args, host, port)?.ok_or(ClientError::ArgumentError(
+        "no compatible address for the URL",
+    ))?;
+    let counts = spread(connections, args.load.threads);
+    println!(
+        "Load test: {connections} connections to {remote_addr} on {} threads",
+        counts.len()
+    );
+
+    let start = Instant::now();
+    let reports = thread::scope(|s| {
+        let handles = counts
+            .into_iter()
+            .map(|count| s.spawn(move || run_connections(args, remote_addr, host, count)))
+            .collect::<Vec<_>>();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("load test thread panicked"))
+            .collect::<Res<Vec<_>>>()
+    })?;
+    let duration = start.elapsed();
+
+    let json = reports
+        .into_iter()
+        .fold(LoadReport::default(), LoadReport::merge)
+        .to_json(duration)
+        .expect("writing to a String doesn't fail");
+    if let Some(path) = &args.load.output {
+        fs::write(path, json + "\n")?;
+    } else {
+        println!("{json}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use neqo_transport::Stats;
+
+    use super::{percentiles, pto_count, spread, LoadParameters, LoadReport};
+
+    fn ms(v: u64) -> Duration {
+        Duration::from_millis(v)
+    }
+
+    fn params(rate: Option<f64>, think_time: Option<u64>) -> LoadParameters {
+        LoadParameters {
+            connections: Some(1),
+            requests: 1,
+            threads: 1,
+            rate,
+            think_time,
+            output: None,
+        }
+    }
+
+    #[test]
+    fn percentiles_empty() {
+        assert_eq!(percentiles(&mut []), "null");
+    }
+
+    #[test]
+    fn percentiles_one() {
+        assert_eq!(
+            percentiles(&mut [ms(7)]),
+            "{\"min\": 7.000, \"p50\": 7.000, \"p90\": 7.000, \"p99\": 7.000, \"max\": 7.000}"
+        );
+    }
+
+    #[test]
+    fn percentiles_unsorted() {
+        let mut values = (1..=100).rev().map(ms).collect::<Vec<_>>();
+        assert_eq!(
+            percentiles(&mut values),
+            "{\"min\": 1.000, \"p50\": 50.000, \"p90\": 90.000, \"p99\": 99.000, \"max\": 100.000}"
+        );
+    }
+
+    #[test]
+    fn percentiles_round_down() {
+        // With ten values, p99 falls between the last two and rounds down to the ninth.
+        let mut values = (1..=10).map(ms).collect::<Vec<_>>();
+        assert_eq!(
+            percentiles(&mut values),
+            "{\"min\": 1.000, \"p50\": 5.000, \"p90\": 9.000, \"p99\": 9.000, \"max\": 10.000}"
+        );
+    }
+
+    #[test]
+    fn pto_runs() {
+        let mut stats = Stats::default();
+        assert_eq!(pto_count(&stats), 0);
+        // Two single PTOs and a run of three.
+        stats.pto_counts[0] = 2;
+        stats.pto_counts[2] = 1;
+        assert_eq!(pto_count(&stats), 5);
+    }
+
+    #[test]
+    fn json() {
+        let stats = |packets, rtt| {
+            let mut s = Stats::default();
+            s.packets_tx = packets;
+            s.packets_rx = packets + 1;
+            s.lost = 1;
+            s.rtt = rtt;
+            s.pto_counts[1] = 1;
+            s
+        };
+        let mut report = LoadReport {
+            connections: 2,
+            connected: 2,
+            completed: 4,
+            failed: 1,
+            bytes: 1000,
+            handshake: vec![ms(30), ms(10)],
+            ttfb: Vec::new(),
+            latency: vec![ms(5)],
+            stats: vec![stats(3, ms(10)), stats(4, ms(20))],
+        };
+        assert_eq!(
+            report.to_json(Duration::from_secs(2)).unwrap(),
+            concat!(
+                "{\n",
+                "  \"connections\": 2,\n",
+                "  \"connected\": 2,\n",
+                "  \"requests_completed\": 4,\n",
+                "  \"requests_failed\": 1,\n",
+                "  \"bytes\": 1000,\n",
+                "  \"duration_ms\": 2000.000,\n",
+                "  \"requests_per_second\": 2.000,\n",
+                "  \"bytes_per_second\": 500.000,\n",
+                "  \"handshake_ms\": {\"min\": 10.000, \"p50\": 10.000, \"p90\": 10.000, \"p99\": 10.000, \"max\": 30.000},\n",
+                "  \"ttfb_ms\": null,\n",
+                "  \"latency_ms\": {\"min\": 5.000, \"p50\": 5.000, \"p90\": 5.000, \"p99\": 5.000, \"max\": 5.000},\n",
+                "  \"transport\": {\n",
+                "    \"packets_tx\": 7,\n",
+                "    \"packets_rx\": 9,\n",
+                "    \"lost\": 2,\n",
+                "    \"dropped_rx\": 0,\n",
+                "    \"pto\": 4,\n",
+                "    \"rtt_ms_mean\": 15.000,\n",
+                "    \"rttvar_ms_mean\": 0.000\n",
+                "  }\n",
+                "}"
+            )
+        );
+    }
+
+    #[test]
+    fn json_empty() {
+        let json = LoadReport::default().to_json(Duration::ZERO).unwrap();
+        assert!(json.contains("\"requests_per_second\": 0.000,"));
+        assert!(json.contains("\"handshake_ms\": null,"));
+        assert!(json.contains("\"rtt_ms_mean\": 0.000,"));
+    }
+
+    #[test]
+    fn pacing() {
+        let now = Instant::now();
+        let p = params(Some(4.0), None);
+        assert_eq!(p.next_after_request(now), Some(now + ms(250)));
+        assert_eq!(p.next_after_response(now), None);
+        assert_eq!(params(Some(0.0), None).next_after_request(now), None);
+        assert_eq!(params(None, None).next_after_request(now), None);
+
+        let p = params(None, Some(20));
+        assert_eq!(p.next_after_request(now), None);
+        assert_eq!(p.next_after_response(now), Some(now + ms(20)));
+    }
+
+    #[test]
+    fn spread_connections() {
+        assert_eq!(spread(10, 3), vec![4, 3, 3]);
+        assert_eq!(spread(6, 3), vec![2, 2, 2]);
+        // There are never more threads than connections, and always one.
+        assert_eq!(spread(2, 8), vec![1, 1]);
+        assert_eq!(spread(5, 0), vec![5]);
+        assert_eq!(spread(0, 4), vec![0]);
+    }
+}
@@ -12,8 +12,9 @@ use std::{
     collections::{HashMap, VecDeque},
     convert::TryFrom,
     fmt::{self, Display},
-    fs::{create_dir_all, File, OpenOptions},
+    fs::{self, create_dir_all, File, OpenOptions},
     io::{self, ErrorKind, Write},
+    mem,
     net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, ToSocketAddrs},
     path::PathBuf,
     process::exit,
@@ -27,23 +28,28 @@ use mio::{net::UdpSocket, Events, Poll, PollOpt, Ready, Token};
 use neqo_common::{self as common, event::Provider, hex, qlog::NeqoQlog, Datagram, Role};
 use neqo_crypto::{
     constants::{TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256},
-    init, AuthenticationStatus, Cipher, ResumptionToken,
+    init, load_certificate, load_trust_anchors, AuthenticationStatus, CertificateVerifier, Cipher,
+    ClientCertificateSelector, ResumptionToken,
 };
 use neqo_http3::{
     self, Error, Header, Http3Client, Http3ClientEvent, Http3Parameters, Http3State, Output,
-    Priority,
+    Priority, SessionCache,
 };
 use neqo_transport::{
     CongestionControlAlgorithm, Connection, ConnectionId, ConnectionParameters,
-    EmptyConnectionIdGenerator, Error as TransportError, StreamId, StreamType, Version,
+    EmptyConnectionIdGenerator, Error as TransportError, PacketCapture, StreamId, StreamType,
+    Version,
 };
 use qlog::{events::EventImportance, streamer::QlogStreamer};
 use structopt::StructOpt;
 use url::{Origin, Url};
 
+mod load;
+
 #[derive(Debug)]
 pub enum ClientError {
     ArgumentError(&'static str),
+    CryptoError(neqo_crypto::Error),
     Http3Error(neqo_http3::Error),
     IoError(io::Error),
     QlogError,
@@ -56,6 +62,12 @@ impl From<io::Error> for ClientError {
     }
 }
 
+impl From<neqo_crypto::Error> for ClientError {
+    fn from(err: neqo_crypto::Error) -> Self {
+        Self::CryptoError(err)
+    }
+}
+
 impl From<neqo_http3::Error> for ClientError {
     fn from(err: neqo_http3::Error) -> Self {
         Self::Http3Error(err)
@@ -200,6 +212,10 @@ pub struct Args {
     /// Enable QLOG logging and QLOG traces to this directory
     qlog_dir: Option<PathBuf>,
 
+    #[structopt(name = "pcap-dir", long)]
+    /// Write a pcapng capture of each connection, with its TLS secrets, to this directory
+    pcap_dir: Option<PathBuf>,
+
     #[structopt(name = "output-dir", long)]
     /// Save contents of fetched URLs to a directory
     output_dir: Option<PathBuf>,
@@ -214,6 +230,16 @@ pub struct Args {
     /// Use this for 0-RTT: the stack always attempts 0-RTT on resumption.
     resume: bool,
 
+    #[structopt(
+        name = "session-cache",
+        long,
+        parse(from_os_str),
+        conflicts_with = "use-old-http"
+    )]
+    /// Load resumption tokens from this file before connecting and save new ones to it
+    /// when done, so that a later run can resume connections and attempt 0-RTT.
+    session_cache: Option<PathBuf>,
+
     #[structopt(name = "key-update", long)]
     /// Attempt to initiate a key update immediately after confirming the connection.
     key_update: bool,
@@ -231,6 +257,9 @@ pub struct Args {
     #[structopt(flatten)]
     quic_parameters: QuicParameters,
 
+    #[structopt(flatten)]
+    load: load::LoadParameters,
+
     #[structopt(name = "ipv4-only", short = "4", long)]
     /// Connect only over IPv4
     ipv4_only: bool,
@@ -246,6 +275,34 @@ pub struct Args {
     /// The request size that will be used for upload test.
     #[structopt(name = "upload-size", long, default_value = "100")]
     upload_size: usize,
+
+    #[structopt(long, parse(from_os_str))]
+    /// Load trust anchors from this PEM file.
+    /// With this, the server certificate is checked instead of being accepted as is.
+    ca: Option<PathBuf>,
+
+    #[structopt(long, number_of_values = 1)]
+    /// Require that a certificate in the server chain has a public key with this SHA-256 hash,
+    /// in hexadecimal format.  This can be repeated.
+    pin: Vec<HexArg>,
+
+    #[structopt(
+        name = "client-cert",
+        long,
+        parse(from_os_str),
+        requires = "client-key"
+    )]
+    /// Present the certificate chain in this PEM file if the server asks for a certificate.
+    client_cert: Option<PathBuf>,
+
+    #[structopt(
+        name = "client-key",
+        long,
+        parse(from_os_str),
+        requires = "client-cert"
+    )]
+    /// The private key for `--client-cert`, in PEM format.
+    client_key: Option<PathBuf>,
 }
 
 impl Args {
@@ -260,6 +317,37 @@ impl Args {
             })
             .collect::<Vec<_>>()
     }
+
+    /// The name that the certificate from `--client-cert` is loaded as.
+    const CLIENT_CERTIFICATE: &'static str = "client";
+
+    fn load_client_certificate(&self) -> Res<()> {
+        if let (Some(cert), Some(key)) = (&self.client_cert, &self.client_key) {
+            load_certificate(Self::CLIENT_CERTIFICATE, &fs::read(cert)?, &fs::read(key)?)?;
+        }
+        Ok(())
+    }
+
+    /// Select the certificate from `--client-cert`, if there is one.
+    fn client_certificate(&self) -> Option<Rc<dyn ClientCertificateSelector>> {
+        self.client_cert
+            .as_ref()
+            .map(|_| Rc::new(String::from(Self::CLIENT_CERTIFICATE)) as _)
+    }
+
+    /// The verifier for server certificates, if trust anchors or pins are configured.
+    fn certificate_verifier(&self) -> Res<Option<CertificateVerifier>> {
+        if self.ca.is_none() && self.pin.is_empty() {
+            return Ok(None);
+        }
+        let mut verifier = CertificateVerifier::new();
+        for pin in &self.pin {
+            verifier = verifier
+                .pin_sha256(pin)
+                .map_err(|_| ClientError::ArgumentError("pins need to be 32 bytes"))?;
+        }
+        Ok(Some(verifier))
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -343,6 +431,24 @@ impl QuicParameters {
     }
 }
 
+/// Find an address for `host` that is allowed by the `--ipv4-only` and `--ipv6-only` options.
+fn resolve(args: &Args, host: &str, port: u16) -> Res<Option<SocketAddr>> {
+    Ok(format!("{host}:{port}").to_socket_addrs()?.find(|addr| {
+        !matches!(
+            (addr, args.ipv4_only, args.ipv6_only),
+            (SocketAddr::V4(..), false, true) | (SocketAddr::V6(..), true, false)
+        )
+    }))
+}
+
+/// The unspecified local address of the same family as `remote_addr`.
+fn local_addr_for(remote_addr: &SocketAddr) -> SocketAddr {
+    match remote_addr {
+        SocketAddr::V4(..) => SocketAddr::new(IpAddr::V4(Ipv4Addr::from([0; 4])), 0),
+        SocketAddr::V6(..) => SocketAddr::new(IpAddr::V6(Ipv6Addr::from([0; 16])), 0),
+    }
+}
+
 fn emit_datagram(socket: &mio::net::UdpSocket, d: Datagram) -> io::Result<()> {
     let sent = socket.send_to(&d[..], &d.destination())?;
     if sent != d.len() {
@@ -617,6 +723,7 @@ impl StreamHandler for UploadStreamHandler {
 struct URLHandler<'a> {
     url_queue: VecDeque<Url>,
     stream_handlers: HashMap<StreamId, Box<dyn StreamHandler>>,
+    stream_urls: HashMap<StreamId, Url>,
     all_paths: Vec<PathBuf>,
     handler_type: StreamHandlerType,
     args: &'a Args,
@@ -665,6 +772,7 @@ impl<'a> URLHandler<'a> {
                     client_stream_id,
                 );
                 self.stream_handlers.insert(client_stream_id, handler);
+                self.stream_urls.insert(client_stream_id, url);
                 true
             }
             Err(Error::TransportError(TransportError::StreamLimitError))
@@ -683,8 +791,22 @@ impl<'a> URLHandler<'a> {
         self.stream_handlers.is_empty() && self.url_queue.is_empty()
     }
 
+    /// Requests that were sent in 0-RTT are lost if the server rejects it, so queue them again.
+    fn zero_rtt_rejected(&mut self) {
+        let mut lost = mem::take(&mut self.stream_urls)
+            .into_iter()
+            .collect::<Vec<_>>();
+        lost.sort_by_key(|(stream_id, _)| *stream_id);
+        for (_, url) in lost.into_iter().rev() {
+            self.url_queue.push_front(url);
+        }
+        self.stream_handlers.clear();
+        self.all_paths.clear();
+    }
+
     fn on_stream_fin(&mut self, client: &mut Http3Client, stream_id: StreamId) -> bool {
         self.stream_handlers.remove(&stream_id);
+        self.stream_urls.remove(&stream_id);
         self.process_urls(client);
         if self.done() {
             client.close(Instant::now(), 0, "kthxbye!");
@@ -727,6 +849,11 @@ impl<'a> Handler<'a> {
                 Http3ClientEvent::AuthenticationNeeded => {
                     client.authenticated(AuthenticationStatus::Ok, Instant::now());
                 }
+                Http3ClientEvent::CertificateVerified { status } => {
+                    if status != AuthenticationStatus::Ok {
+                        eprintln!("Server certificate rejected: {status:?}");
+                    }
+                }
                 Http3ClientEvent::HeaderReady {
                     stream_id,
                     headers,
@@ -791,10 +918,14 @@ impl<'a> Handler<'a> {
                         }
                     }
                 }
-                Http3ClientEvent::StateChange(Http3State::Connected)
+                Http3ClientEvent::StateChange(Http3State::ZeroRtt | Http3State::Connected)
                 | Http3ClientEvent::RequestsCreatable => {
                     self.url_handler.process_urls(client);
                 }
+                Http3ClientEvent::ZeroRttRejected => {
+                    println!("0-RTT rejected");
+                    self.url_handler.zero_rtt_rejected();
+                }
                 Http3ClientEvent::ResumptionToken(t) => self.token = Some(t),
                 _ => {
                     println!("Unhandled event {event:?}");
@@ -842,6 +973,7 @@ fn handle_test(
         let url_handler = URLHandler {
             url_queue,
             stream_handlers: HashMap::new(),
+            stream_urls: HashMap::new(),
             all_paths: Vec::new(),
             handler_type: StreamHandlerType::Upload,
             args,
@@ -857,7 +989,7 @@ fn handle_test(
 }
 
 fn create_http3_client(
-    args: &mut Args,
+    args: &Args,
     local_addr: SocketAddr,
     remote_addr: SocketAddr,
     hostname: &str,
@@ -887,9 +1019,16 @@ fn create_http3_client(
 
     let qlog = qlog_new(args, hostname, client.connection_id())?;
     client.set_qlog(qlog);
+    client.set_capture(capture_new(args, hostname, client.connection_id())?);
     if let Some(ech) = &args.ech {
         client.enable_ech(ech).expect("enable ECH");
     }
+    if let Some(verifier) = args.certificate_verifier()? {
+        client.set_certificate_verifier(verifier);
+    }
+    if let Some(selector) = args.client_certificate() {
+        client.set_client_certificate_selector(selector)?;
+    }
     if let Some(token) = resumption_token {
         client
             .enable_resumption(Instant::now(), token)
@@ -931,6 +1070,7 @@ fn client(
     let url_handler = URLHandler {
         url_queue,
         stream_handlers: HashMap::new(),
+        stream_urls: HashMap::new(),
         all_paths: Vec::new(),
         handler_type: StreamHandlerType::Download,
         args,
@@ -939,7 +1079,7 @@ fn client(
 
     process_loop(&local_addr, socket, poll, &mut client, &mut h)?;
 
-    let token = if args.resume {
+    let token = if args.resume || args.session_cache.is_some() {
         // If we haven't received an event, take a token if there is one.
         // Lots of servers don't provide NEW_TOKEN, but a session ticket
         // without NEW_TOKEN is better than nothing.
@@ -951,6 +1091,15 @@ fn client(
     Ok(token)
 }
 
+fn capture_new(args: &Args, hostname: &str, cid: &ConnectionId) -> Res<Option<PacketCapture>> {
+    if let Some(pcap_dir) = &args.pcap_dir {
+        let path = pcap_dir.join(format!("{hostname}-{cid}.pcapng"));
+        Ok(Some(PacketCapture::create(path)?))
+    } else {
+        Ok(None)
+    }
+}
+
 fn qlog_new(args: &Args, hostname: &str, cid: &ConnectionId) -> Res<NeqoQlog> {
     if let Some(qlog_dir) = &args.qlog_dir {
         let mut qlog_path = qlog_dir.to_path_buf();
@@ -985,6 +1134,19 @@ fn main() -> Res<()> {
 
     let mut args = Args::from_args();
 
+    if let Some(ca) = &args.ca {
+        load_trust_anchors(&fs::read(ca)?)?;
+    }
+    args.load_client_certificate()?;
+    if args.load.enabled() {
+        return load::run(&args);
+    }
+    let mut session_cache = args
+        .session_cache
+        .as_ref()
+        .map(SessionCache::load)
+        .transpose()?;
+
     if let Some(testcase) = args.qns_test.as_ref() {
         match testcase.as_str() {
             "http3" => {}
@@ -1043,21 +1205,11 @@ fn main() -> Res<()> {
             exit(127);
         }
 
-        let remote_addr = format!("{host}:{port}").to_socket_addrs()?.find(|addr| {
-            !matches!(
-                (addr, args.ipv4_only, args.ipv6_only),
-                (SocketAddr::V4(..), false, true) | (SocketAddr::V6(..), true, false)
-            )
-        });
-        let Some(remote_addr) = remote_addr else {
+        let Some(remote_addr) = resolve(&args, &format!("{host}"), port)? else {
             eprintln!("No compatible address found for: {host}");
             exit(1);
         };
-
-        let local_addr = match remote_addr {
-            SocketAddr::V4(..) => SocketAddr::new(IpAddr::V4(Ipv4Addr::from([0; 4])), 0),
-            SocketAddr::V6(..) => SocketAddr::new(IpAddr::V6(Ipv6Addr::from([0; 16])), 0),
-        };
+        let local_addr = local_addr_for(&remote_addr);
 
         let socket = match UdpSocket::bind(&local_addr) {
             Err(e) => {
@@ -1084,7 +1236,13 @@ fn main() -> Res<()> {
         );
 
         let hostname = format!("{host}");
-        let mut token: Option<ResumptionToken> = None;
+        let authority = format!("{host}:{port}");
+        let mut token: Option<ResumptionToken> = session_cache
+            .as_mut()
+            .and_then(|cache| cache.take(&authority, Instant::now()));
+        if token.is_some() {
+            println!("Resuming a cached session for {authority}");
+        }
         let mut first = true;
         while !urls.is_empty() {
             let to_request = if (args.resume && first) || args.download_in_series {
@@ -1119,6 +1277,14 @@ fn main() -> Res<()> {
                 )?
             };
         }
+
+        if let (Some(cache), Some(token)) = (&mut session_cache, &token) {
+            cache.insert(authority, token, Instant::now());
+        }
+    }
+
+    if let (Some(cache), Some(path)) = (&session_cache, &args.session_cache) {
+        cache.save(path)?;
     }
 
     Ok(())
@@ -1289,6 +1455,12 @@ mod old {
                     ConnectionEvent::AuthenticationNeeded => {
                         client.authenticated(AuthenticationStatus::Ok, Instant::now());
                     }
+                    ConnectionEvent::CertificateVerified { code } => {
+                        let status = AuthenticationStatus::from(code);
+                        if status != AuthenticationStatus::Ok {
+                            eprintln!("Server certificate rejected: {status:?}");
+                        }
+                    }
                     ConnectionEvent::RecvStreamReadable { stream_id } => {
                         if !self.read(client, stream_id)? {
                             self.get_token(client);
@@ -1445,6 +1617,13 @@ mod old {
         }
 
         client.set_qlog(qlog_new(args, origin, client.odcid().unwrap())?);
+        client.set_capture(capture_new(args, origin, client.odcid().unwrap())?);
+        if let Some(verifier) = args.certificate_verifier()? {
+            client.set_certificate_verifier(verifier);
+        }
+        if let Some(selector) = args.client_certificate() {
+            client.client_set_certificate_selector(selector)?;
+        }
 
         let key_update = KeyUpdateState(args.key_update);
         let mut h = HandlerOld {
@@ -0,0 +1,446 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Summarize the qlog files that neqo writes, one connection per file.
+
+#![cfg_attr(feature = "deny-warnings", deny(warnings))]
+#![warn(clippy::use_self)]
+
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    fs::{self, File},
+    io::{self, BufWriter, Write},
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+use serde_json::Value;
+use structopt::StructOpt;
+
+/// The record separator that starts each record in a JSON-SEQ file.
+const RECORD_SEPARATOR: char = '\u{1e}';
+
+#[derive(Debug, StructOpt)]
+#[structopt(
+    name = "neqo-qlog-summary",
+    about = "Summarize the qlog files written by neqo."
+)]
+struct Args {
+    #[structopt(parse(from_os_str), required = true)]
+    /// The qlog files to read, each holding the trace of a single connection.
+    files: Vec<PathBuf>,
+
+    #[structopt(long, parse(from_os_str))]
+    /// Write the congestion window and RTT of each connection over time to a CSV file in this
+    /// directory.  The file is named after the qlog file.
+    csv: Option<PathBuf>,
+}
+
+/// The packet number space that a packet belongs to.
+fn pn_space(packet_type: &str) -> &'static str {
+    match packet_type {
+        "initial" => "initial",
+        "handshake" => "handshake",
+        _ => "application_data",
+    }
+}
+
+/// One row of the time series, in milliseconds since the start of the trace.
+#[derive(Debug, Default, Clone, Copy)]
+struct Metrics {
+    time: f64,
+    congestion_window: Option<u64>,
+    bytes_in_flight: Option<u64>,
+    ssthresh: Option<u64>,
+    smoothed_rtt: Option<f64>,
+    latest_rtt: Option<f64>,
+    min_rtt: Option<f64>,
+}
+
+impl Metrics {
+    /// Apply the values from a `metrics_updated` event, keeping any that it does not include.
+    fn update(&mut self, time: f64, data: &Value) {
+        let int = |k: &str| data.get(k).and_then(Value::as_u64);
+        let float = |k: &str| data.get(k).and_then(Value::as_f64);
+        self.time = time;
+        self.congestion_window = int("congestion_window").or(self.congestion_window);
+        self.bytes_in_flight = int("bytes_in_flight").or(self.bytes_in_flight);
+        self.ssthresh = int("ssthresh").or(self.ssthresh);
+        self.smoothed_rtt = float("smoothed_rtt").or(self.smoothed_rtt);
+        self.latest_rtt = float("latest_rtt").or(self.latest_rtt);
+        self.min_rtt = float("min_rtt").or(self.min_rtt);
+    }
+
+    fn write_csv(&self, w: &mut impl Write) -> io::Result<()> {
+        fn opt<T: Display>(v: Option<T>) -> String {
+            v.map_or_else(String::new, |v| v.to_string())
+        }
+        writeln!(
+            w,
+            "{:.3},{},{},{},{},{},{}",
+            self.time,
+            opt(self.congestion_window),
+            opt(self.bytes_in_flight),
+            opt(self.ssthresh),
+            opt(self.smoothed_rtt),
+            opt(self.latest_rtt),
+            opt(self.min_rtt),
+        )
+    }
+}
+
+#[derive(Debug, Default)]
+struct Summary {
+    vantage_point: Option<String>,
+    events: usize,
+    duration: f64,
+    handshake_completed: Option<f64>,
+    handshake_confirmed: Option<f64>,
+    packets_sent: usize,
+    packets_received: usize,
+    bytes_sent: u64,
+    bytes_received: u64,
+    /// The number of lost packets for each trigger.
+    lost: HashMap<String, usize>,
+    /// Lost packets that were later acknowledged, so their retransmission was not needed.
+    spurious: usize,
+    /// Lost packets that have not been acknowledged (yet), by packet number space.
+    outstanding_lost: HashSet<(&'static str, u64)>,
+    congestion_states: Vec<(f64, String)>,
+    persistent_congestion: usize,
+    max_cwnd: Option<u64>,
+    metrics: Vec<Metrics>,
+    bad_records: usize,
+}
+
+impl Summary {
+    fn read(path: &Path) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// Summarize a qlog in JSON-SEQ format.
+    fn parse(text: &str) -> Self {
+        let mut summary = Self::default();
+        for record in text
+            .split(RECORD_SEPARATOR)
+            .filter(|r| !r.trim().is_empty())
+        {
+            match serde_json::from_str::<Value>(record) {
+                Ok(v) => summary.record(&v),
+                Err(_) => summary.bad_records += 1,
+            }
+        }
+        summary
+    }
+
+    fn record(&mut self, v: &Value) {
+        if let Some(trace) = v.get("trace") {
+            self.vantage_point = trace
+                .pointer("/vantage_point/type")
+                .and_then(Value::as_str)
+                .map(String::from);
+            return;
+        }
+        let (Some(time), Some(name)) = (
+            v.get("time").and_then(Value::as_f64),
+            v.get("name").and_then(Value::as_str),
+        ) else {
+            self.bad_records += 1;
+            return;
+        };
+        let data = v.get("data").unwrap_or(&Value::Null);
+        self.events += 1;
+        self.duration = self.duration.max(time);
+
+        match name {
+            "connectivity:connection_state_updated" => {
+                match data.get("new").and_then(Value::as_str) {
+                    Some("handshake_completed") => {
+                        self.handshake_completed.get_or_insert(time);
+                    }
+                    Some("handshake_confirmed") => {
+                        self.handshake_confirmed.get_or_insert(time);
+                    }
+                    _ => {}
+                }
+            }
+            "transport:packet_sent" => {
+                self.packets_sent += 1;
+                self.bytes_sent += data
+                    .pointer("/raw/length")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0);
+            }
+            "transport:packet_received" => {
+                self.packets_received += 1;
+                self.bytes_received += data
+                    .pointer("/raw/length")
+                    .and_then(Value::as_u64)
+                    .unwrap_or(0);
+                self.acks_received(data);
+            }
+            "recovery:packet_lost" => self.packet_lost(data),
+            "recovery:congestion_state_updated" => {
+                if let Some(new) = data.get("new").and_then(Value::as_str) {
+                    self.congestion_states.push((time, new.to_string()));
+                }
+                if data.get("trigger").and_then(Value::as_str) == Some("persistent_congestion") {
+                    self.persistent_congestion += 1;
+                }
+            }
+            "recovery:metrics_updated" => {
+                let mut m = self.metrics.last().copied().unwrap_or_default();
+                m.update(time, data);
+                self.max_cwnd = self.max_cwnd.max(m.congestion_window);
+                self.metrics.push(m);
+            }
+            _ => {}
+        }
+    }
+
+    fn packet_lost(&mut self, data: &Value) {
+        let trigger = data
+            .get("trigger")
+            .and_then(Value::as_str)
+            .unwrap_or("unknown");
+        *self.lost.entry(trigger.to_string()).or_default() += 1;
+        // A PTO doesn't mean that a packet is lost, only that it might be.
+        if trigger == "pto_expired" {
+            return;
+        }
+        let space = pn_space(
+            data.pointer("/header/packet_type")
+                .and_then(Value::as_str)
+                .unwrap_or_default(),
+        );
+        if let Some(pn) = data
+            .pointer("/header/packet_number")
+            .and_then(Value::as_u64)
+        {
+            self.outstanding_lost.insert((space, pn));
+        }
+    }
+
+    /// Look for acknowledgments of packets that were declared lost.
+    fn acks_received(&mut self, data: &Value) {
+        if self.outstanding_lost.is_empty() {
+            return;
+        }
+        let space = pn_space(
+            data.pointer("/header/packet_type")
+                .and_then(Value::as_str)
+                .unwrap_or_default(),
+        );
+        let frames = data.get("frames").and_then(Value::as_array);
+        for frame in frames.into_iter().flatten() {
+            if frame.get("frame_type").and_then(Value::as_str) != Some("ack") {
+                continue;
+            }
+            let ranges = frame.get("acked_ranges").and_then(Value::as_array);
+            for range in ranges.into_iter().flatten().filter_map(Value::as_array) {
+                let (Some(first), last) = (
+                    range.first().and_then(Value::as_u64),
+                    range.last().and_then(Value::as_u64),
+                ) else {
+                    continue;
+                };
+                let last = last.unwrap_or(first);
+                let before = self.outstanding_lost.len();
+                self.outstanding_lost
+                    .retain(|&(s, pn)| s != space || pn < first || pn > last);
+                self.spurious += before - self.outstanding_lost.len();
+            }
+        }
+    }
+
+    fn write_csv(&self, path: &Path) -> io::Result<()> {
+        let mut w = BufWriter::new(File::create(path)?);
+        writeln!(
+            w,
+            "time_ms,congestion_window,bytes_in_flight,ssthresh,smoothed_rtt_ms,latest_rtt_ms,min_rtt_ms"
+        )?;
+        for m in &self.metrics {
+            m.write_csv(&mut w)?;
+        }
+        w.flush()
+    }
+}
+
+impl Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn ms(t: Option<f64>) -> String {
+            t.map_or_else(|| "-".to_string(), |t| format!("{t:.3} ms"))
+        }
+        let lost: usize = self.lost.values().sum();
+        let mut triggers = self.lost.iter().collect::<Vec<_>>();
+        triggers.sort();
+
+        writeln!(
+            f,
+            "  vantage point:        {}",
+            self.vantage_point.as_deref().unwrap_or("unknown")
+        )?;
+        writeln!(f, "  events:               {}", self.events)?;
+        writeln!(f, "  duration:             {}", ms(Some(self.duration)))?;
+        writeln!(
+            f,
+            "  handshake completed:  {}",
+            ms(self.handshake_completed)
+        )?;
+        writeln!(
+            f,
+            "  handshake confirmed:  {}",
+            ms(self.handshake_confirmed)
+        )?;
+        writeln!(
+            f,
+            "  packets sent:         {} ({} bytes)",
+            self.packets_sent, self.bytes_sent
+        )?;
+        writeln!(
+            f,
+            "  packets received:     {} ({} bytes)",
+            self.packets_received, self.bytes_received
+        )?;
+        #[allow(clippy::cast_precision_loss)] // This is only approximate.
+        let rate = if self.packets_sent > 0 {
+            100.0 * (lost - self.lost.get("pto_expired").copied().unwrap_or(0)) as f64
+                / self.packets_sent as f64
+        } else {
+            0.0
+        };
+        writeln!(
+            f,
+            "  packets lost:         {lost} ({rate:.2}% excluding PTO)"
+        )?;
+        for (trigger, count) in triggers {
+            writeln!(f, "    {trigger}: {count}")?;
+        }
+        writeln!(f, "  spurious losses:      {}", self.spurious)?;
+        writeln!(
+            f,
+            "  congestion events:    {} ({} persistent)",
+            self.congestion_states
+                .iter()
+                .filter(|(_, s)| s == "recovery")
+                .count(),
+            self.persistent_congestion
+        )?;
+        writeln!(
+            f,
+            "  max cwnd:             {}",
+            self.max_cwnd
+                .map_or_else(|| "-".to_string(), |c| c.to_string())
+        )?;
+        let last = self.metrics.last();
+        writeln!(
+            f,
+            "  final rtt:            {} smoothed, {} min",
+            ms(last.and_then(|m| m.smoothed_rtt)),
+            ms(last.and_then(|m| m.min_rtt))
+        )?;
+        if self.bad_records > 0 {
+            writeln!(f, "  unreadable records:   {}", self.bad_records)?;
+        }
+        Ok(())
+    }
+}
+
+fn main() {
+    let args = Args::from_args();
+    let mut failed = false;
+    for file in &args.files {
+        let summary = match Summary::read(file) {
+            Ok(s) => s,
+            Err(e) => {
+                eprintln!("{}: {e}", file.display());
+                failed = true;
+                continue;
+            }
+        };
+        println!("{}:", file.display());
+        print!("{summary}");
+
+        if let Some(dir) = &args.csv {
+            let name = file.file_stem().unwrap_or(file.as_os_str());
+            let csv = dir.join(format!("{}.csv", name.to_string_lossy()));
+            if let Err(e) = summary.write_csv(&csv) {
+                eprintln!("{}: {e}", csv.display());
+                failed = true;
+            } else {
+                println!("  time series:          {}", csv.display());
+            }
+        }
+    }
+    if failed {
+        exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Summary;
+
+    /// A short client trace, with one record that isn't JSON.
+    const QLOG: &str = concat!(
+        "\u{1e}{\"qlog_version\":\"0.3\",\"trace\":{\"vantage_point\":{\"type\":\"client\"}}}\n",
+        "\u{1e}{\"time\":0.0,\"name\":\"transport:packet_sent\",\"data\":{\"header\":{\"packet_type\":\"initial\",\"packet_number\":0},\"raw\":{\"length\":1200}}}\n",
+        "\u{1e}{\"time\":10.0,\"name\":\"transport:packet_received\",\"data\":{\"header\":{\"packet_type\":\"initial\",\"packet_number\":0},\"raw\":{\"length\":1200},\"frames\":[{\"frame_type\":\"ack\",\"acked_ranges\":[[0,0]]}]}}\n",
+        "\u{1e}{\"time\":15.0,\"name\":\"connectivity:connection_state_updated\",\"data\":{\"new\":\"handshake_completed\"}}\n",
+        "\u{1e}{\"time\":20.0,\"name\":\"transport:packet_sent\",\"data\":{\"header\":{\"packet_type\":\"1RTT\",\"packet_number\":1},\"raw\":{\"length\":100}}}\n",
+        "\u{1e}{\"time\":21.0,\"name\":\"transport:packet_sent\",\"data\":{\"header\":{\"packet_type\":\"1RTT\",\"packet_number\":2},\"raw\":{\"length\":100}}}\n",
+        "\u{1e}{\"time\":25.0,\"name\":\"recovery:metrics_updated\",\"data\":{\"congestion_window\":12000,\"smoothed_rtt\":10.0,\"min_rtt\":9.5}}\n",
+        "\u{1e}{\"time\":30.0,\"name\":\"recovery:packet_lost\",\"data\":{\"header\":{\"packet_type\":\"1RTT\",\"packet_number\":1},\"trigger\":\"reordering_threshold\"}}\n",
+        "\u{1e}{\"time\":31.0,\"name\":\"recovery:packet_lost\",\"data\":{\"header\":{\"packet_type\":\"1RTT\",\"packet_number\":2},\"trigger\":\"pto_expired\"}}\n",
+        "\u{1e}{\"time\":32.0,\"name\":\"recovery:congestion_state_updated\",\"data\":{\"new\":\"recovery\"}}\n",
+        "\u{1e}{\"time\":35.0,\"name\":\"recovery:metrics_updated\",\"data\":{\"congestion_window\":6000}}\n",
+        "\u{1e}{\"time\":40.0,\"name\":\"transport:packet_received\",\"data\":{\"header\":{\"packet_type\":\"1RTT\",\"packet_number\":1},\"raw\":{\"length\":50},\"frames\":[{\"frame_type\":\"ack\",\"acked_ranges\":[[1,2]]}]}}\n",
+        "\u{1e}{\"time\":45.0,\"name\":\"connectivity:connection_state_updated\",\"data\":{\"new\":\"handshake_confirmed\"}}\n",
+        "\u{1e}{\"time\":46.0,\"name\":\n",
+    );
+
+    #[test]
+    fn counts() {
+        let summary = Summary::parse(QLOG);
+        assert_eq!(summary.vantage_point.as_deref(), Some("client"));
+        assert_eq!(summary.events, 12);
+        assert_eq!(summary.bad_records, 1);
+        assert_eq!(summary.packets_sent, 3);
+        assert_eq!(summary.bytes_sent, 1400);
+        assert_eq!(summary.packets_received, 2);
+        assert_eq!(summary.bytes_received, 1250);
+        assert_eq!(summary.lost.get("reordering_threshold"), Some(&1));
+        assert_eq!(summary.lost.get("pto_expired"), Some(&1));
+        // Only the packet that was lost to reordering counts, as PTOs aren't losses.
+        assert_eq!(summary.spurious, 1);
+        assert!(summary.outstanding_lost.is_empty());
+        assert_eq!(summary.persistent_congestion, 0);
+        assert_eq!(summary.max_cwnd, Some(12000));
+    }
+
+    #[test]
+    fn timings() {
+        let summary = Summary::parse(QLOG);
+        assert_eq!(summary.duration, 45.0);
+        assert_eq!(summary.handshake_completed, Some(15.0));
+        assert_eq!(summary.handshake_confirmed, Some(45.0));
+        assert_eq!(
+            summary.congestion_states,
+            vec![(32.0, "recovery".to_string())]
+        );
+
+        // Metrics that an update doesn't include are carried over.
+        assert_eq!(summary.metrics.len(), 2);
+        let last = summary.metrics[1];
+        assert_eq!(last.time, 35.0);
+        assert_eq!(last.congestion_window, Some(6000));
+        assert_eq!(last.smoothed_rtt, Some(10.0));
+        let mut csv = Vec::new();
+        last.write_csv(&mut csv).unwrap();
+        assert_eq!(String::from_utf8(csv).unwrap(), "35.000,6000,,,10,,9.5\n");
+    }
+}
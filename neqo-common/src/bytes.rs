@@ -0,0 +1,225 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    fmt::{self, Debug},
+    ops::{Bound, Deref, RangeBounds},
+    rc::Rc,
+};
+
+use crate::hex_snip_middle;
+
+/// An immutable, reference-counted chunk of bytes, similar to `bytes::Bytes`.
+///
+/// Cloning and slicing a `Bytes` does not copy the data, so a chunk can be handed from the
+/// application to a stream and kept there until it has been acknowledged, or from the receive
+/// buffer of a stream to the application, without copying.
+#[derive(Clone, Default)]
+pub struct Bytes {
+    data: Rc<Vec<u8>>,
+    start: usize,
+    end: usize,
+}
+
+impl Bytes {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Whether this is the only reference to the underlying buffer, so that it can be
+    /// extended in place.
+    #[must_use]
+    pub fn is_unique(&self) -> bool {
+        Rc::strong_count(&self.data) == 1
+    }
+
+    /// Returns a chunk that refers to a subrange of this chunk.
+    ///
+    /// # Panics
+    ///
+    /// If the range is out of bounds.
+    #[must_use]
+    pub fn slice(&self, range: impl RangeBounds<usize>) -> Self {
+        let start = match range.start_bound() {
+            Bound::Included(&s) => s,
+            Bound::Excluded(&s) => s + 1,
+            Bound::Unbounded => 0,
+        };
+        let end = match range.end_bound() {
+            Bound::Included(&e) => e + 1,
+            Bound::Excluded(&e) => e,
+            Bound::Unbounded => self.len(),
+        };
+        assert!(start <= end && end <= self.len(), "slice out of bounds");
+        Self {
+            data: Rc::clone(&self.data),
+            start: self.start + start,
+            end: self.start + end,
+        }
+    }
+
+    /// Remove the first `n` bytes.
+    ///
+    /// # Panics
+    ///
+    /// If `n` is larger than the length of the chunk.
+    pub fn advance(&mut self, n: usize) {
+        assert!(n <= self.len(), "advance past the end");
+        self.start += n;
+    }
+
+    /// Split the chunk in two. `self` keeps the bytes from `at` on, the first `at` bytes are
+    /// returned.
+    ///
+    /// # Panics
+    ///
+    /// If `at` is larger than the length of the chunk.
+    #[must_use]
+    pub fn split_to(&mut self, at: usize) -> Self {
+        let head = self.slice(..at);
+        self.start += at;
+        head
+    }
+
+    /// Shorten the chunk to `len` bytes. Nothing happens if the chunk is shorter already.
+    pub fn truncate(&mut self, len: usize) {
+        if len < self.len() {
+            self.end = self.start + len;
+        }
+    }
+
+    /// Append `buf` to the chunk. The data is appended in place if this is the only reference
+    /// to the underlying buffer, otherwise the chunk is copied first.
+    pub fn extend_from_slice(&mut self, buf: &[u8]) {
+        let in_place = self.end == self.data.len();
+        match Rc::get_mut(&mut self.data) {
+            Some(data) if in_place => data.extend_from_slice(buf),
+            _ => {
+                let mut data = Vec::with_capacity(self.len() + buf.len());
+                data.extend_from_slice(self);
+                data.extend_from_slice(buf);
+                *self = Self::from(data);
+                return;
+            }
+        }
+        self.end += buf.len();
+    }
+
+    /// Convert the chunk into a vector. This does not copy if this is the only reference to
+    /// the underlying buffer and the chunk covers all of it.
+    #[must_use]
+    pub fn into_vec(self) -> Vec<u8> {
+        if self.start == 0 && self.end == self.data.len() {
+            Rc::try_unwrap(self.data).unwrap_or_else(|data| data.as_ref().clone())
+        } else {
+            self.to_vec()
+        }
+    }
+}
+
+impl From<Vec<u8>> for Bytes {
+    fn from(data: Vec<u8>) -> Self {
+        let end = data.len();
+        Self {
+            data: Rc::new(data),
+            start: 0,
+            end,
+        }
+    }
+}
+
+impl From<&[u8]> for Bytes {
+    fn from(data: &[u8]) -> Self {
+        Self::from(data.to_vec())
+    }
+}
+
+impl Deref for Bytes {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.data[self.start..self.end]
+    }
+}
+
+impl AsRef<[u8]> for Bytes {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl PartialEq for Bytes {
+    fn eq(&self, other: &Self) -> bool {
+        self[..] == other[..]
+    }
+}
+
+impl Eq for Bytes {}
+
+impl Debug for Bytes {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Bytes {}", hex_snip_middle(self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Bytes;
+
+    #[test]
+    fn slice_and_split() {
+        let mut b = Bytes::from(vec![1, 2, 3, 4, 5]);
+        let s = b.slice(1..=2);
+        assert_eq!(&s[..], &[2, 3]);
+
+        let head = b.split_to(2);
+        assert_eq!(&head[..], &[1, 2]);
+        assert_eq!(&b[..], &[3, 4, 5]);
+
+        b.advance(1);
+        b.truncate(1);
+        assert_eq!(&b[..], &[4]);
+        assert_eq!(b.len(), 1);
+    }
+
+    #[test]
+    fn extend() {
+        let mut b = Bytes::from(vec![1, 2]);
+        b.extend_from_slice(&[3]);
+        assert_eq!(&b[..], &[1, 2, 3]);
+
+        // A shared buffer is copied before it is changed.
+        let shared = b.clone();
+        assert!(!b.is_unique());
+        b.extend_from_slice(&[4]);
+        assert_eq!(&b[..], &[1, 2, 3, 4]);
+        assert_eq!(&shared[..], &[1, 2, 3]);
+    }
+
+    #[test]
+    fn into_vec() {
+        let b = Bytes::from(vec![1, 2, 3]);
+        assert_eq!(b.slice(1..).into_vec(), vec![2, 3]);
+        assert_eq!(b.into_vec(), vec![1, 2, 3]);
+    }
+
+    #[test]
+    #[should_panic(expected = "slice out of bounds")]
+    fn slice_out_of_bounds() {
+        _ = Bytes::from(vec![1]).slice(..2);
+    }
+}
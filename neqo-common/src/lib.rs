@@ -7,6 +7,7 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
+mod bytes;
 mod codec;
 mod datagram;
 pub mod event;
@@ -14,6 +15,7 @@ pub mod header;
 pub mod hrtime;
 mod incrdecoder;
 pub mod log;
+pub mod pcap;
 pub mod qlog;
 pub mod timer;
 pub mod tos;
@@ -23,6 +25,7 @@ use std::fmt::Write;
 use enum_map::Enum;
 
 pub use self::{
+    bytes::Bytes,
     codec::{Decoder, Encoder},
     datagram::Datagram,
     header::Header,
@@ -0,0 +1,736 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Reading UDP datagrams from pcap and pcapng packet captures.
+
+use std::{
+    convert::TryFrom,
+    io::{self, ErrorKind, Write},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    time::Duration,
+};
+
+use crate::{Datagram, Decoder, Encoder, IpTosDscp, IpTosEcn};
+
+const PCAP_MAGIC_MICROS: u32 = 0xa1b2_c3d4;
+const PCAP_MAGIC_NANOS: u32 = 0xa1b2_3c4d;
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const PCAPNG_BYTE_ORDER_MAGIC: u32 = 0x1a2b_3c4d;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+const PCAPNG_DECRYPTION_SECRETS: u32 = 10;
+/// The secrets type for a key log in the format of SSLKEYLOGFILE.
+const PCAPNG_SECRETS_TLS_KEY_LOG: u32 = 0x544c_534b;
+const PCAPNG_OPTION_END: u16 = 0;
+const PCAPNG_OPTION_IF_TSRESOL: u16 = 9;
+
+const LINKTYPE_NULL: u16 = 0;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const LINKTYPE_LINUX_SLL: u16 = 113;
+const LINKTYPE_IPV4: u16 = 228;
+const LINKTYPE_IPV6: u16 = 229;
+const LINKTYPE_LINUX_SLL2: u16 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+const IPPROTO_UDP: u8 = 17;
+/// The TTL or hop limit to use when a datagram doesn't have one.
+const DEFAULT_TTL: u8 = 64;
+
+fn invalid(msg: &str) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, msg)
+}
+
+/// A UDP datagram taken from a capture, with the time that it was captured.
+#[derive(Debug, Clone)]
+pub struct CapturedDatagram {
+    /// The capture time, as an offset from the UNIX epoch.
+    pub time: Duration,
+    pub datagram: Datagram,
+}
+
+/// Read a little- or big-endian integer, depending on the capture.
+fn uint(dec: &mut Decoder, n: usize, little_endian: bool) -> Option<u64> {
+    let v = dec.decode_uint(n)?;
+    Some(if little_endian {
+        v.swap_bytes() >> (64 - 8 * n)
+    } else {
+        v
+    })
+}
+
+fn u16_at(dec: &mut Decoder, little_endian: bool) -> Option<u16> {
+    uint(dec, 2, little_endian).and_then(|v| u16::try_from(v).ok())
+}
+
+fn u32_at(dec: &mut Decoder, little_endian: bool) -> Option<u32> {
+    uint(dec, 4, little_endian).and_then(|v| u32::try_from(v).ok())
+}
+
+fn usize_at(dec: &mut Decoder, little_endian: bool) -> Option<usize> {
+    u32_at(dec, little_endian).and_then(|v| usize::try_from(v).ok())
+}
+
+/// Read all of the UDP datagrams in a pcap or pcapng capture.  Anything other than
+/// UDP over IPv4 or IPv6 is skipped, as are IP fragments and truncated packets.
+///
+/// # Errors
+///
+/// When the capture is not in either format or is corrupted.
+pub fn read_datagrams(capture: &[u8]) -> io::Result<Vec<CapturedDatagram>> {
+    let mut dec = Decoder::new(capture);
+    let magic = u32_at(&mut dec, false).ok_or_else(|| invalid("capture too short"))?;
+    match magic {
+        PCAP_MAGIC_MICROS | PCAP_MAGIC_NANOS => read_pcap(dec, false, magic == PCAP_MAGIC_NANOS),
+        _ if magic.swap_bytes() == PCAP_MAGIC_MICROS || magic.swap_bytes() == PCAP_MAGIC_NANOS => {
+            read_pcap(dec, true, magic.swap_bytes() == PCAP_MAGIC_NANOS)
+        }
+        PCAPNG_SECTION_HEADER => read_pcapng(capture),
+        _ => Err(invalid("not a pcap or pcapng capture")),
+    }
+}
+
+fn read_pcap(mut dec: Decoder, le: bool, nanos: bool) -> io::Result<Vec<CapturedDatagram>> {
+    let truncated = || invalid("truncated pcap header");
+    // Skip the version, time zone, timestamp accuracy, and snapshot length.
+    dec.decode(16).ok_or_else(truncated)?;
+    let link_type = u32_at(&mut dec, le)
+        .and_then(|v| u16::try_from(v & 0xffff).ok())
+        .ok_or_else(truncated)?;
+
+    let mut datagrams = Vec::new();
+    while dec.remaining() > 0 {
+        let (Some(secs), Some(frac), Some(len), Some(_orig_len)) = (
+            u32_at(&mut dec, le),
+            u32_at(&mut dec, le),
+            usize_at(&mut dec, le),
+            u32_at(&mut dec, le),
+        ) else {
+            return Err(invalid("truncated pcap record"));
+        };
+        let data = dec
+            .decode(len)
+            .ok_or_else(|| invalid("truncated pcap record"))?;
+        let time = Duration::from_secs(u64::from(secs))
+            + if nanos {
+                Duration::from_nanos(u64::from(frac))
+            } else {
+                Duration::from_micros(u64::from(frac))
+            };
+        if let Some(datagram) = decode_link(link_type, data) {
+            datagrams.push(CapturedDatagram { time, datagram });
+        }
+    }
+    Ok(datagrams)
+}
+
+/// An interface from a pcapng Interface Description Block.
+#[derive(Debug, Clone, Copy)]
+struct Interface {
+    link_type: u16,
+    /// The number of timestamp units in a second.
+    units_per_second: u64,
+}
+
+impl Interface {
+    fn time(&self, ts: u64) -> Duration {
+        let secs = ts / self.units_per_second;
+        let rem = u128::from(ts % self.units_per_second);
+        let nanos = rem * 1_000_000_000 / u128::from(self.units_per_second);
+        Duration::from_secs(secs) + Duration::from_nanos(u64::try_from(nanos).unwrap_or(0))
+    }
+}
+
+fn read_pcapng(capture: &[u8]) -> io::Result<Vec<CapturedDatagram>> {
+    let truncated = || invalid("truncated pcapng block");
+    let mut dec = Decoder::new(capture);
+    let mut le = false;
+    let mut interfaces = Vec::new();
+    let mut datagrams = Vec::new();
+    while dec.remaining() > 0 {
+        let block_type = u32_at(&mut dec, le).ok_or_else(truncated)?;
+        if block_type == PCAPNG_SECTION_HEADER {
+            // The byte order magic follows the length, which can't be read until the
+            // byte order is known.  Each section can use a different byte order.
+            let mut peek = Decoder::new(dec.decode(8).ok_or_else(truncated)?);
+            peek.skip(4);
+            le = match u32_at(&mut peek, false) {
+                Some(PCAPNG_BYTE_ORDER_MAGIC) => false,
+                Some(m) if m.swap_bytes() == PCAPNG_BYTE_ORDER_MAGIC => true,
+                _ => return Err(invalid("bad pcapng byte order magic")),
+            };
+            let len = usize_at(&mut Decoder::new(&capture[dec.offset() - 8..]), le)
+                .ok_or_else(truncated)?;
+            // The remainder of the block is the version, section length, and options.
+            dec.decode(len.checked_sub(16).ok_or_else(truncated)?)
+                .ok_or_else(truncated)?;
+            dec.decode(4).ok_or_else(truncated)?;
+            interfaces.clear();
+            continue;
+        }
+
+        let len = usize_at(&mut dec, le).ok_or_else(truncated)?;
+        let body = len
+            .checked_sub(12)
+            .and_then(|n| dec.decode(n))
+            .ok_or_else(truncated)?;
+        dec.decode(4).ok_or_else(truncated)?;
+        let mut body = Decoder::new(body);
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                interfaces.push(read_interface(&mut body, le).ok_or_else(truncated)?);
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let (Some(id), Some(ts_high), Some(ts_low), Some(cap_len)) = (
+                    usize_at(&mut body, le),
+                    u32_at(&mut body, le),
+                    u32_at(&mut body, le),
+                    usize_at(&mut body, le),
+                ) else {
+                    return Err(truncated());
+                };
+                let intf: &Interface = interfaces
+                    .get(id)
+                    .ok_or_else(|| invalid("unknown pcapng interface"))?;
+                body.decode(4).ok_or_else(truncated)?; // The original length.
+                let data = body.decode(cap_len).ok_or_else(truncated)?;
+                let time = intf.time((u64::from(ts_high) << 32) | u64::from(ts_low));
+                if let Some(datagram) = decode_link(intf.link_type, data) {
+                    datagrams.push(CapturedDatagram { time, datagram });
+                }
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                // These have no timestamp and always use the first interface.
+                let intf = interfaces
+                    .first()
+                    .ok_or_else(|| invalid("unknown pcapng interface"))?;
+                let orig_len = usize_at(&mut body, le).ok_or_else(truncated)?;
+                let data = body.decode_remainder();
+                let data = &data[..orig_len.min(data.len())];
+                if let Some(datagram) = decode_link(intf.link_type, data) {
+                    datagrams.push(CapturedDatagram {
+                        time: Duration::ZERO,
+                        datagram,
+                    });
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(datagrams)
+}
+
+fn read_interface(body: &mut Decoder, le: bool) -> Option<Interface> {
+    let link_type = u16_at(body, le)?;
+    body.decode(6)?; // Reserved and the snapshot length.
+    let mut units_per_second = 1_000_000;
+    while body.remaining() >= 4 {
+        let code = u16_at(body, le)?;
+        let len = usize::from(u16_at(body, le)?);
+        let value = body.decode(len)?;
+        body.decode((4 - len % 4) % 4)?;
+        match code {
+            PCAPNG_OPTION_END => break,
+            PCAPNG_OPTION_IF_TSRESOL => {
+                let resol = *value.first()?;
+                let exp = u32::from(resol & 0x7f);
+                units_per_second = if resol & 0x80 == 0 {
+                    10_u64.checked_pow(exp)?
+                } else {
+                    2_u64.checked_pow(exp)?
+                };
+            }
+            _ => {}
+        }
+    }
+    Some(Interface {
+        link_type,
+        units_per_second,
+    })
+}
+
+/// Find the IP packet in a link layer frame and decode it.
+fn decode_link(link_type: u16, frame: &[u8]) -> Option<Datagram> {
+    let mut dec = Decoder::new(frame);
+    match link_type {
+        LINKTYPE_NULL => {
+            // The address family is in the byte order of the machine that captured it,
+            // and the value for IPv6 varies, so look at the IP version instead.
+            dec.decode(4)?;
+            decode_link(LINKTYPE_RAW, dec.decode_remainder())
+        }
+        LINKTYPE_ETHERNET => {
+            dec.decode(12)?;
+            let mut ethertype = u16::try_from(dec.decode_uint(2)?).ok()?;
+            while ethertype == ETHERTYPE_VLAN {
+                dec.decode(2)?;
+                ethertype = u16::try_from(dec.decode_uint(2)?).ok()?;
+            }
+            decode_ethertype(ethertype, dec.decode_remainder())
+        }
+        LINKTYPE_LINUX_SLL => {
+            dec.decode(14)?;
+            let ethertype = u16::try_from(dec.decode_uint(2)?).ok()?;
+            decode_ethertype(ethertype, dec.decode_remainder())
+        }
+        LINKTYPE_LINUX_SLL2 => {
+            let ethertype = u16::try_from(dec.decode_uint(2)?).ok()?;
+            dec.decode(18)?;
+            decode_ethertype(ethertype, dec.decode_remainder())
+        }
+        LINKTYPE_RAW => match frame.first()? >> 4 {
+            4 => decode_ipv4(frame),
+            6 => decode_ipv6(frame),
+            _ => None,
+        },
+        LINKTYPE_IPV4 => decode_ipv4(frame),
+        LINKTYPE_IPV6 => decode_ipv6(frame),
+        _ => None,
+    }
+}
+
+fn decode_ethertype(ethertype: u16, packet: &[u8]) -> Option<Datagram> {
+    match ethertype {
+        ETHERTYPE_IPV4 => decode_ipv4(packet),
+        ETHERTYPE_IPV6 => decode_ipv6(packet),
+        _ => None,
+    }
+}
+
+fn decode_ipv4(packet: &[u8]) -> Option<Datagram> {
+    let mut dec = Decoder::new(packet);
+    let first = dec.decode_byte()?;
+    if first >> 4 != 4 {
+        return None;
+    }
+    let header_len = usize::from(first & 0xf) * 4;
+    let tos = dec.decode_byte()?;
+    let total_len = usize::try_from(dec.decode_uint(2)?).ok()?;
+    dec.decode(2)?; // Identification.
+    let fragment = dec.decode_uint(2)?;
+    // Skip fragments: either more fragments follow or this isn't the first.
+    if fragment & 0x3fff != 0 {
+        return None;
+    }
+    let ttl = dec.decode_byte()?;
+    if dec.decode_byte()? != IPPROTO_UDP {
+        return None;
+    }
+    dec.decode(2)?; // Checksum.
+    let src = Ipv4Addr::from(<[u8; 4]>::try_from(dec.decode(4)?).ok()?);
+    let dst = Ipv4Addr::from(<[u8; 4]>::try_from(dec.decode(4)?).ok()?);
+    let end = total_len.min(packet.len());
+    decode_udp(
+        IpAddr::V4(src),
+        IpAddr::V4(dst),
+        tos,
+        ttl,
+        packet.get(header_len..end)?,
+    )
+}
+
+fn decode_ipv6(packet: &[u8]) -> Option<Datagram> {
+    const HOP_BY_HOP: u8 = 0;
+    const ROUTING: u8 = 43;
+    const DESTINATION_OPTIONS: u8 = 60;
+
+    let mut dec = Decoder::new(packet);
+    let first = dec.decode_uint(4)?;
+    if first >> 28 != 6 {
+        return None;
+    }
+    let tos = u8::try_from((first >> 20) & 0xff).ok()?;
+    let payload_len = usize::try_from(dec.decode_uint(2)?).ok()?;
+    let mut next = dec.decode_byte()?;
+    let ttl = dec.decode_byte()?;
+    let src = Ipv6Addr::from(<[u8; 16]>::try_from(dec.decode(16)?).ok()?);
+    let dst = Ipv6Addr::from(<[u8; 16]>::try_from(dec.decode(16)?).ok()?);
+    let payload = dec.decode_remainder();
+    let mut dec = Decoder::new(&payload[..payload_len.min(payload.len())]);
+    // Walk extension headers; this gives up on fragments along with anything else.
+    while matches!(next, HOP_BY_HOP | ROUTING | DESTINATION_OPTIONS) {
+        next = dec.decode_byte()?;
+        let len = usize::from(dec.decode_byte()?) * 8 + 6;
+        dec.decode(len)?;
+    }
+    if next != IPPROTO_UDP {
+        return None;
+    }
+    decode_udp(
+        IpAddr::V6(src),
+        IpAddr::V6(dst),
+        tos,
+        ttl,
+        dec.decode_remainder(),
+    )
+}
+
+fn decode_udp(src: IpAddr, dst: IpAddr, tos: u8, ttl: u8, segment: &[u8]) -> Option<Datagram> {
+    let mut dec = Decoder::new(segment);
+    let src_port = u16::try_from(dec.decode_uint(2)?).ok()?;
+    let dst_port = u16::try_from(dec.decode_uint(2)?).ok()?;
+    let len = usize::try_from(dec.decode_uint(2)?).ok()?;
+    dec.decode(2)?; // Checksum.
+
+    // Drop truncated datagrams rather than pass on a partial payload.
+    let payload = dec.decode(len.checked_sub(8)?)?;
+    Some(Datagram::new(
+        SocketAddr::new(src, src_port),
+        SocketAddr::new(dst, dst_port),
+        (IpTosDscp::from(tos), IpTosEcn::from(tos)).into(),
+        Some(ttl),
+        payload,
+    ))
+}
+
+/// Writes UDP datagrams to a pcapng capture.
+///
+/// Datagrams are written as raw IP packets, so that IPv4 and IPv6 can share an
+/// interface.  A Decryption Secrets Block can carry TLS secrets, which lets tools
+/// like Wireshark decrypt the capture without a separate key log.
+#[derive(Debug)]
+pub struct PcapngWriter<W> {
+    w: W,
+}
+
+impl<W: Write> PcapngWriter<W> {
+    /// Start a capture, writing the section header and the interface description.
+    ///
+    /// # Errors
+    ///
+    /// When writing fails.
+    pub fn new(w: W) -> io::Result<Self> {
+        let mut writer = Self { w };
+        writer.block(PCAPNG_SECTION_HEADER, |enc| {
+            enc.encode_uint(4, PCAPNG_BYTE_ORDER_MAGIC)
+                .encode_uint(2, 1_u16)
+                .encode_uint(2, 0_u16)
+                .encode_uint(8, u64::MAX); // The section length is not known.
+        })?;
+        writer.block(PCAPNG_INTERFACE_DESCRIPTION, |enc| {
+            enc.encode_uint(2, LINKTYPE_RAW)
+                .encode_uint(2, 0_u16)
+                .encode_uint(4, 0_u32); // No limit on the snapshot length.
+
+            // Timestamps are in nanoseconds.
+            enc.encode_uint(2, PCAPNG_OPTION_IF_TSRESOL)
+                .encode_uint(2, 1_u16)
+                .encode(&[9, 0, 0, 0]);
+            enc.encode_uint(2, PCAPNG_OPTION_END).encode_uint(2, 0_u16);
+        })?;
+        Ok(writer)
+    }
+
+    /// Write a block, adding the type, the length before and after, and padding.
+    fn block(&mut self, block_type: u32, f: impl FnOnce(&mut Encoder)) -> io::Result<()> {
+        let mut body = Encoder::default();
+        f(&mut body);
+        let padding = (4 - body.len() % 4) % 4;
+        let len = u32::try_from(body.len() + padding + 12)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "block too large"))?;
+        let mut enc = Encoder::with_capacity(usize::try_from(len).unwrap_or_default());
+        enc.encode_uint(4, block_type)
+            .encode_uint(4, len)
+            .encode(body.as_ref())
+            .encode(&[0; 3][..padding])
+            .encode_uint(4, len);
+        self.w.write_all(enc.as_ref())
+    }
+
+    /// Add TLS secrets, in the format of SSLKEYLOGFILE.  These should be written
+    /// before any packets that they protect.
+    ///
+    /// # Errors
+    ///
+    /// When writing fails.
+    pub fn write_secrets(&mut self, keylog: &[u8]) -> io::Result<()> {
+        let len = u32::try_from(keylog.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "secrets too large"))?;
+        self.block(PCAPNG_DECRYPTION_SECRETS, |enc| {
+            enc.encode_uint(4, PCAPNG_SECRETS_TLS_KEY_LOG)
+                .encode_uint(4, len)
+                .encode(keylog);
+        })
+    }
+
+    /// Write a datagram that was sent or received at `time`, which is measured from
+    /// the UNIX epoch.
+    ///
+    /// # Errors
+    ///
+    /// When writing fails.
+    pub fn write_datagram(&mut self, time: Duration, d: &Datagram) -> io::Result<()> {
+        let packet = encode_ip(d);
+        let len = u32::try_from(packet.len())
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "datagram too large"))?;
+        let ts = u64::try_from(time.as_nanos()).unwrap_or(u64::MAX);
+        self.block(PCAPNG_ENHANCED_PACKET, |enc| {
+            enc.encode_uint(4, 0_u32)
+                .encode_uint(4, ts >> 32)
+                .encode_uint(4, ts & 0xffff_ffff)
+                .encode_uint(4, len)
+                .encode_uint(4, len)
+                .encode(&packet);
+        })
+    }
+
+    /// Write anything that is buffered.
+    ///
+    /// # Errors
+    ///
+    /// When writing fails.
+    pub fn flush(&mut self) -> io::Result<()> {
+        self.w.flush()
+    }
+}
+
+/// The one's complement sum that IP and UDP use as a checksum.
+fn checksum(parts: &[&[u8]]) -> u16 {
+    let mut sum = 0_u32;
+    for part in parts {
+        for pair in part.chunks(2) {
+            let hi = u32::from(pair[0]) << 8;
+            sum += hi | pair.get(1).copied().map_or(0, u32::from);
+        }
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !u16::try_from(sum).unwrap()
+}
+
+/// Build an IP packet holding `d`.  If the addresses don't use the same IP version,
+/// IPv4 addresses are mapped into IPv6.
+fn encode_ip(d: &Datagram) -> Vec<u8> {
+    let (src, dst) = match (d.source().ip(), d.destination().ip()) {
+        (IpAddr::V4(s), IpAddr::V6(d)) => (IpAddr::V6(s.to_ipv6_mapped()), IpAddr::V6(d)),
+        (IpAddr::V6(s), IpAddr::V4(d)) => (IpAddr::V6(s), IpAddr::V6(d.to_ipv6_mapped())),
+        addrs => addrs,
+    };
+    let tos = u8::from(d.tos());
+    let ttl = d.ttl().unwrap_or(DEFAULT_TTL);
+    let udp_len = u16::try_from(d.len() + 8).unwrap_or(u16::MAX);
+
+    let mut udp = Encoder::with_capacity(usize::from(udp_len));
+    udp.encode_uint(2, d.source().port())
+        .encode_uint(2, d.destination().port())
+        .encode_uint(2, udp_len)
+        .encode_uint(2, 0_u16)
+        .encode(&d[..]);
+    let mut udp = Vec::from(udp);
+
+    let mut enc = Encoder::default();
+    let pseudo_header = match (src, dst) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            let mut header = Encoder::with_capacity(20);
+            header
+                .encode_byte(0x45)
+                .encode_byte(tos)
+                .encode_uint(2, udp_len.saturating_add(20))
+                .encode_uint(2, 0_u16) // Identification.
+                .encode_uint(2, 0x4000_u16) // Don't fragment.
+                .encode_byte(ttl)
+                .encode_byte(IPPROTO_UDP)
+                .encode_uint(2, 0_u16)
+                .encode(&src.octets())
+                .encode(&dst.octets());
+            let mut header = Vec::from(header);
+            let sum = checksum(&[&header]);
+            header[10..12].copy_from_slice(&sum.to_be_bytes());
+            enc.encode(&header);
+
+            let mut pseudo = Encoder::with_capacity(12);
+            pseudo
+                .encode(&src.octets())
+                .encode(&dst.octets())
+                .encode_byte(0)
+                .encode_byte(IPPROTO_UDP)
+                .encode_uint(2, udp_len);
+            pseudo
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            enc.encode_uint(4, 0x6000_0000_u32 | (u32::from(tos) << 20))
+                .encode_uint(2, udp_len)
+                .encode_byte(IPPROTO_UDP)
+                .encode_byte(ttl)
+                .encode(&src.octets())
+                .encode(&dst.octets());
+
+            let mut pseudo = Encoder::with_capacity(40);
+            pseudo
+                .encode(&src.octets())
+                .encode(&dst.octets())
+                .encode_uint(4, udp_len)
+                .encode_uint(4, u32::from(IPPROTO_UDP));
+            pseudo
+        }
+        _ => unreachable!(),
+    };
+    // A checksum of zero means that there is no checksum, so use the alternative.
+    let sum = match checksum(&[pseudo_header.as_ref(), &udp]) {
+        0 => 0xffff,
+        sum => sum,
+    };
+    udp[6..8].copy_from_slice(&sum.to_be_bytes());
+    enc.encode(&udp);
+    enc.into()
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, net::SocketAddr, time::Duration};
+
+    use super::{checksum, encode_ip, read_datagrams, PcapngWriter};
+    use crate::{Datagram, Encoder, IpTos};
+
+    const PAYLOAD: &[u8] = b"quic";
+
+    fn addrs() -> (SocketAddr, SocketAddr) {
+        (
+            "192.0.2.1:4433".parse().unwrap(),
+            "192.0.2.2:443".parse().unwrap(),
+        )
+    }
+
+    /// An Ethernet frame holding an IPv4 packet holding a UDP datagram.
+    fn ethernet_frame() -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.encode(&[0; 12]).encode_uint(2, 0x0800_u16);
+        enc.encode_byte(0x45)
+            .encode_byte(0)
+            .encode_uint(2, u64::try_from(20 + 8 + PAYLOAD.len()).unwrap())
+            .encode_uint(4, 0_u32)
+            .encode_byte(64)
+            .encode_byte(17)
+            .encode_uint(2, 0_u16)
+            .encode(&[192, 0, 2, 1])
+            .encode(&[192, 0, 2, 2]);
+        enc.encode_uint(2, 4433_u16)
+            .encode_uint(2, 443_u16)
+            .encode_uint(2, u64::try_from(8 + PAYLOAD.len()).unwrap())
+            .encode_uint(2, 0_u16)
+            .encode(PAYLOAD);
+        enc.into()
+    }
+
+    fn check(capture: &[u8], time: Duration) {
+        let datagrams = read_datagrams(capture).unwrap();
+        assert_eq!(datagrams.len(), 1);
+        let (src, dst) = addrs();
+        assert_eq!(datagrams[0].time, time);
+        assert_eq!(datagrams[0].datagram.source(), src);
+        assert_eq!(datagrams[0].datagram.destination(), dst);
+        assert_eq!(datagrams[0].datagram.ttl(), Some(64));
+        assert_eq!(&datagrams[0].datagram[..], PAYLOAD);
+    }
+
+    #[test]
+    fn pcap_little_endian() {
+        let frame = ethernet_frame();
+        let len = u32::try_from(frame.len()).unwrap();
+        let mut capture = Vec::new();
+        capture.extend_from_slice(&0xa1b2_c3d4_u32.to_le_bytes());
+        capture.extend_from_slice(&[2, 0, 4, 0]); // Version 2.4.
+        for v in &[0, 0, 0xffff, 1, 10, 500, len, len] {
+            capture.extend_from_slice(&u32::to_le_bytes(*v));
+        }
+        capture.extend_from_slice(&frame);
+        check(
+            &capture,
+            Duration::from_secs(10) + Duration::from_micros(500),
+        );
+    }
+
+    #[test]
+    fn pcapng() {
+        let frame = ethernet_frame();
+        let mut enc = Encoder::default();
+        // Section header block.
+        enc.encode_uint(4, 0x0a0d_0d0a_u32)
+            .encode_uint(4, 28_u32)
+            .encode_uint(4, 0x1a2b_3c4d_u32)
+            .encode_uint(2, 1_u16)
+            .encode_uint(2, 0_u16)
+            .encode_uint(8, u64::MAX)
+            .encode_uint(4, 28_u32);
+        // Interface description block, with nanosecond timestamps.
+        enc.encode_uint(4, 1_u32)
+            .encode_uint(4, 32_u32)
+            .encode_uint(2, 1_u16)
+            .encode_uint(2, 0_u16)
+            .encode_uint(4, 0xffff_u32)
+            .encode_uint(2, 9_u16)
+            .encode_uint(2, 1_u16)
+            .encode(&[9, 0, 0, 0])
+            .encode_uint(4, 0_u32)
+            .encode_uint(4, 32_u32);
+        // Enhanced packet block.
+        let padded = (frame.len() + 3) / 4 * 4;
+        let block_len = u64::try_from(32 + padded).unwrap();
+        enc.encode_uint(4, 6_u32)
+            .encode_uint(4, block_len)
+            .encode_uint(4, 0_u32)
+            .encode_uint(4, 0_u32)
+            .encode_uint(4, 1_500_000_000_u32)
+            .encode_uint(4, u64::try_from(frame.len()).unwrap())
+            .encode_uint(4, u64::try_from(frame.len()).unwrap())
+            .encode(&frame)
+            .encode(&vec![0; padded - frame.len()])
+            .encode_uint(4, block_len);
+        check(enc.as_ref(), Duration::from_millis(1500));
+    }
+
+    #[test]
+    fn write_and_read() {
+        let (src, dst) = addrs();
+        let v4 = Datagram::new(src, dst, IpTos::default(), Some(64), PAYLOAD);
+        let v6 = Datagram::new(
+            "[2001:db8::1]:4433".parse().unwrap(),
+            "[2001:db8::2]:443".parse().unwrap(),
+            IpTos::default(),
+            None,
+            PAYLOAD,
+        );
+        let mut w = PcapngWriter::new(Vec::new()).unwrap();
+        w.write_secrets(b"CLIENT_TRAFFIC_SECRET_0 0102 0304\n")
+            .unwrap();
+        w.write_datagram(Duration::from_millis(1500), &v4).unwrap();
+        w.write_datagram(Duration::from_millis(1600), &v6).unwrap();
+
+        let datagrams = read_datagrams(&w.w).unwrap();
+        assert_eq!(datagrams.len(), 2);
+        assert_eq!(datagrams[0].time, Duration::from_millis(1500));
+        assert_eq!(datagrams[0].datagram, v4);
+        assert_eq!(datagrams[1].time, Duration::from_millis(1600));
+        assert_eq!(datagrams[1].datagram.source(), v6.source());
+        assert_eq!(datagrams[1].datagram.destination(), v6.destination());
+        assert_eq!(&datagrams[1].datagram[..], PAYLOAD);
+    }
+
+    #[test]
+    fn ipv4_checksums() {
+        let (src, dst) = addrs();
+        let packet = encode_ip(&Datagram::new(src, dst, IpTos::default(), None, PAYLOAD));
+        // Summing a header that includes a valid checksum produces zero.
+        assert_eq!(checksum(&[&packet[..20]]), 0);
+        let mut pseudo = Encoder::default();
+        pseudo
+            .encode(&packet[12..20])
+            .encode_byte(0)
+            .encode_byte(17)
+            .encode_uint(2, u64::try_from(8 + PAYLOAD.len()).unwrap());
+        assert_eq!(checksum(&[pseudo.as_ref(), &packet[20..]]), 0);
+    }
+
+    #[test]
+    fn not_a_capture() {
+        assert!(read_datagrams(b"not a capture").is_err());
+        assert!(read_datagrams(&[]).is_err());
+    }
+}
@@ -27,18 +27,20 @@ use crate::{
     agentio::{AgentIo, METHODS},
     assert_initialized,
     auth::AuthenticationStatus,
+    compression::{self, CertificateCompression, CertificateCompressionInfo},
     constants::{
         Alert, Cipher, Epoch, Extension, Group, SignatureScheme, Version, TLS_VERSION_1_3,
     },
     ech,
-    err::{is_blocked, secstatus_to_res, Error, PRErrorCode, Res},
+    err::{is_blocked, secstatus_to_res, Error, PRErrorCode, PR_SetError, Res},
     ext::{ExtensionHandler, ExtensionTracker},
     p11::{self, PrivateKey, PublicKey},
-    prio,
+    pem, prio,
     replay::AntiReplay,
     secrets::SecretHolder,
     ssl::{self, PRBool},
-    time::{Time, TimeHolder},
+    time::{PRTime, Time, TimeHolder},
+    verify::CertificateVerifier,
 };
 
 /// The maximum number of tickets to remember for a given connection.
@@ -271,6 +273,45 @@ impl SecretAgentInfo {
     }
 }
 
+fn set_alpn(fd: *mut ssl::PRFileDesc, protocols: &[impl AsRef<str>]) -> Res<()> {
+    // Validate and set length.
+    let mut encoded_len = protocols.len();
+    for v in protocols {
+        assert!(v.as_ref().len() < 256);
+        assert!(!v.as_ref().is_empty());
+        encoded_len += v.as_ref().len();
+    }
+
+    // Prepare to encode.
+    let mut encoded = Vec::with_capacity(encoded_len);
+    let mut add = |v: &str| {
+        if let Ok(s) = u8::try_from(v.len()) {
+            encoded.push(s);
+            encoded.extend_from_slice(v.as_bytes());
+        }
+    };
+
+    // NSS inherited an idiosyncratic API as a result of having implemented NPN
+    // before ALPN.  For that reason, we need to put the "best" option last.
+    let (first, rest) = protocols
+        .split_first()
+        .expect("at least one ALPN value needed");
+    for v in rest {
+        add(v.as_ref());
+    }
+    add(first.as_ref());
+    assert_eq!(encoded_len, encoded.len());
+
+    // Now give the result to NSS.
+    secstatus_to_res(unsafe {
+        ssl::SSL_SetNextProtoNego(
+            fd,
+            encoded.as_slice().as_ptr(),
+            c_uint::try_from(encoded.len())?,
+        )
+    })
+}
+
 /// `SecretAgent` holds the common parts of client and server.
 #[derive(Debug)]
 #[allow(clippy::module_name_repetitions)]
@@ -293,6 +334,9 @@ pub struct SecretAgent {
     /// The encrypted client hello (ECH) configuration that is in use.
     /// Empty if ECH is not enabled.
     ech_config: Vec<u8>,
+
+    /// The sizes of the certificate message, if it was compressed.
+    certificate_compression: Option<CertificateCompressionInfo>,
 }
 
 impl SecretAgent {
@@ -313,6 +357,8 @@ impl SecretAgent {
             extension_handlers: Vec::new(),
 
             ech_config: Vec::new(),
+
+            certificate_compression: None,
         })
     }
 
@@ -524,42 +570,7 @@ impl SecretAgent {
     ///
     /// [RFC7301]: https://datatracker.ietf.org/doc/html/rfc7301
     pub fn set_alpn(&mut self, protocols: &[impl AsRef<str>]) -> Res<()> {
-        // Validate and set length.
-        let mut encoded_len = protocols.len();
-        for v in protocols {
-            assert!(v.as_ref().len() < 256);
-            assert!(!v.as_ref().is_empty());
-            encoded_len += v.as_ref().len();
-        }
-
-        // Prepare to encode.
-        let mut encoded = Vec::with_capacity(encoded_len);
-        let mut add = |v: &str| {
-            if let Ok(s) = u8::try_from(v.len()) {
-                encoded.push(s);
-                encoded.extend_from_slice(v.as_bytes());
-            }
-        };
-
-        // NSS inherited an idiosyncratic API as a result of having implemented NPN
-        // before ALPN.  For that reason, we need to put the "best" option last.
-        let (first, rest) = protocols
-            .split_first()
-            .expect("at least one ALPN value needed");
-        for v in rest {
-            add(v.as_ref());
-        }
-        add(first.as_ref());
-        assert_eq!(encoded_len, encoded.len());
-
-        // Now give the result to NSS.
-        secstatus_to_res(unsafe {
-            ssl::SSL_SetNextProtoNego(
-                self.fd,
-                encoded.as_slice().as_ptr(),
-                c_uint::try_from(encoded.len())?,
-            )
-        })
+        set_alpn(self.fd, protocols)
     }
 
     /// Install an extension handler.
@@ -595,6 +606,38 @@ impl SecretAgent {
         }
     }
 
+    /// Enable certificate compression (RFC 8879) with the given algorithms.
+    /// A server compresses its certificate with the first algorithm in the client's
+    /// list that it also supports.
+    ///
+    /// # Errors
+    ///
+    /// If the handshake has started or NSS doesn't support certificate compression.
+    pub fn set_certificate_compression(
+        &mut self,
+        algorithms: &[CertificateCompression],
+    ) -> Res<()> {
+        if self.state != HandshakeState::New {
+            qwarn!(
+                [self],
+                "Cannot enable certificate compression in state {:?}",
+                self.state
+            );
+            return Err(Error::InternalError);
+        }
+        for alg in algorithms {
+            alg.enable(self.fd)?;
+        }
+        Ok(())
+    }
+
+    /// Get the sizes of the certificate message, if it was compressed.
+    /// For a server, this is the certificate it sent; for a client, the one it received.
+    #[must_use]
+    pub fn certificate_compression(&self) -> Option<CertificateCompressionInfo> {
+        self.certificate_compression
+    }
+
     /// Get information about the connection.
     /// This includes the version, ciphersuite, and ALPN.
     ///
@@ -654,6 +697,9 @@ impl SecretAgent {
     }
 
     fn update_state(&mut self, res: Res<()>) -> Res<()> {
+        if let Some(info) = compression::take_info() {
+            self.certificate_compression = Some(info);
+        }
         self.state = if is_blocked(&res) {
             if *self.auth_required {
                 self.preinfo()?.ech_public_name()?.map_or(
@@ -848,6 +894,8 @@ pub struct Client {
     server_name: String,
     /// Records the resumption tokens we've received.
     resumption: Pin<Box<Vec<ResumptionToken>>>,
+    /// This holds the context for selecting a client certificate.
+    client_certificate: Option<Pin<Box<ClientCertificateState>>>,
 }
 
 impl Client {
@@ -866,6 +914,7 @@ impl Client {
             agent,
             server_name,
             resumption: Box::pin(Vec::new()),
+            client_certificate: None,
         };
         client.ready()?;
         Ok(client)
@@ -957,6 +1006,72 @@ impl Client {
         }
     }
 
+    unsafe extern "C" fn client_auth_data_cb(
+        arg: *mut c_void,
+        fd: *mut ssl::PRFileDesc,
+        _ca_names: *mut ssl::CERTDistNames,
+        cert: *mut *mut ssl::CERTCertificate,
+        key: *mut *mut ssl::SECKEYPrivateKey,
+    ) -> ssl::SECStatus {
+        let state = arg.cast::<ClientCertificateState>().as_ref().unwrap();
+        let Some(name) = state.selector.select(&state.server_name) else {
+            return ssl::SECFailure;
+        };
+        match find_certificate(&name) {
+            Ok((c, k)) => {
+                qdebug!([format!("{fd:p}")], "Using client certificate {}", name);
+                // NSS takes ownership of both.
+                *cert = *c;
+                *key = *k;
+                mem::forget(c);
+                mem::forget(k);
+                ssl::SECSuccess
+            }
+            Err(e) => {
+                qwarn!(
+                    [format!("{fd:p}")],
+                    "No client certificate {}: {:?}",
+                    name,
+                    e
+                );
+                ssl::SECFailure
+            }
+        }
+    }
+
+    /// Pick a client certificate when the server asks for one.
+    ///
+    /// # Errors
+    ///
+    /// If the underlying NSS function fails.
+    pub fn set_client_certificate_selector(
+        &mut self,
+        selector: Rc<dyn ClientCertificateSelector>,
+    ) -> Res<()> {
+        let mut state = Box::pin(ClientCertificateState {
+            selector,
+            server_name: self.server_name.clone(),
+        });
+        secstatus_to_res(unsafe {
+            ssl::SSL_GetClientAuthDataHook(
+                self.agent.fd,
+                Some(Self::client_auth_data_cb),
+                as_c_void(&mut state),
+            )
+        })?;
+        self.client_certificate = Some(state);
+        Ok(())
+    }
+
+    /// Use the certificate with the given nickname when the server asks for one.
+    ///
+    /// # Errors
+    ///
+    /// If the underlying NSS function fails.
+    pub fn set_client_certificate(&mut self, certificate: impl Into<String>) -> Res<()> {
+        self.set_client_certificate_selector(Rc::new(certificate.into()))
+    }
+
     /// Enable encrypted client hello (ECH), using the encoded `ECHConfigList`.
     ///
     /// When ECH is enabled, a client needs to look for `Error::EchRetry` as a
@@ -1052,11 +1167,99 @@ impl ZeroRttCheckState {
     }
 }
 
+/// The configuration that a `ServerNameSelector` picks for a server name.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerNameConfig {
+    /// The nickname of the certificate to use.
+    pub certificate: String,
+    /// The ALPN values to offer instead of those of the server, if not empty.
+    pub protocols: Vec<String>,
+}
+
+/// A `ServerNameSelector` is used by a server to pick a certificate based on the server name
+/// indication (SNI) from the client.
+pub trait ServerNameSelector: std::fmt::Debug {
+    /// Returns the configuration for `server_name`, or `None` to keep the certificates that the
+    /// server was created with.  The name is in lowercase.
+    fn select(&self, server_name: &str) -> Option<ServerNameConfig>;
+}
+
+#[derive(Debug)]
+struct ServerNameState {
+    selector: Rc<dyn ServerNameSelector>,
+    /// The server name that the client asked for.
+    server_name: Option<String>,
+}
+
+// These are macros in ssl.h.
+const SSL_SNI_CURRENT_CONFIG_IS_USED: ssl::PRInt32 = -1;
+const SSL_SNI_SEND_ALERT: ssl::PRInt32 = -2;
+
+/// Find a certificate and its private key, either from those that were loaded with
+/// `load_certificate` or by nickname in the NSS database.
+fn find_certificate(certificate: &str) -> Res<(p11::Certificate, PrivateKey)> {
+    if let Some(res) = pem::with_server_certificate(certificate, |cert, key| {
+        let cert = p11::Certificate::from_ptr(unsafe { p11::CERT_DupCertificate(cert) })?;
+        let key = PrivateKey::from_ptr(unsafe { p11::SECKEY_CopyPrivateKey(key) })?;
+        Ok((cert, key))
+    }) {
+        return res;
+    }
+
+    let c = CString::new(certificate)?;
+    let cert_ptr = unsafe { p11::PK11_FindCertFromNickname(c.as_ptr(), null_mut()) };
+    let Ok(cert) = p11::Certificate::from_ptr(cert_ptr) else {
+        return Err(Error::CertificateLoading);
+    };
+    let key_ptr = unsafe { p11::PK11_FindKeyByAnyCert(*cert, null_mut()) };
+    let Ok(key) = p11::PrivateKey::from_ptr(key_ptr) else {
+        return Err(Error::CertificateLoading);
+    };
+    Ok((cert, key))
+}
+
+fn config_server_cert(fd: *mut ssl::PRFileDesc, certificate: &str) -> Res<()> {
+    let (cert, key) = find_certificate(certificate)?;
+    secstatus_to_res(unsafe { ssl::SSL_ConfigServerCert(fd, *cert, *key, null(), 0) })
+}
+
+/// A `ClientCertificateSelector` is used by a client to pick a certificate when the server
+/// asks for one.
+pub trait ClientCertificateSelector: std::fmt::Debug {
+    /// Returns the nickname of the certificate to use for `server_name`, or `None` to
+    /// continue without a certificate.
+    fn select(&self, server_name: &str) -> Option<String>;
+}
+
+/// A fixed certificate.
+impl ClientCertificateSelector for String {
+    fn select(&self, _server_name: &str) -> Option<String> {
+        Some(self.clone())
+    }
+}
+
+#[derive(Debug)]
+struct ClientCertificateState {
+    selector: Rc<dyn ClientCertificateSelector>,
+    server_name: String,
+}
+
+#[derive(Debug)]
+struct ClientAuthState {
+    verifier: CertificateVerifier,
+    /// This points to the time in the `TimeHolder` of the server.
+    now: *const PRTime,
+}
+
 #[derive(Debug)]
 pub struct Server {
     agent: SecretAgent,
     /// This holds the HRR callback context.
     zero_rtt_check: Option<Pin<Box<ZeroRttCheckState>>>,
+    /// This holds the SNI callback context.
+    server_name: Option<Pin<Box<ServerNameState>>>,
+    /// This holds the context for checking client certificates.
+    client_auth: Option<Pin<Box<ClientAuthState>>>,
 }
 
 impl Server {
@@ -1069,27 +1272,131 @@ impl Server {
         let mut agent = SecretAgent::new()?;
 
         for n in certificates {
-            let c = CString::new(n.as_ref())?;
-            let cert_ptr = unsafe { p11::PK11_FindCertFromNickname(c.as_ptr(), null_mut()) };
-            let Ok(cert) = p11::Certificate::from_ptr(cert_ptr) else {
-                return Err(Error::CertificateLoading);
-            };
-            let key_ptr = unsafe { p11::PK11_FindKeyByAnyCert(*cert, null_mut()) };
-            let Ok(key) = p11::PrivateKey::from_ptr(key_ptr) else {
-                return Err(Error::CertificateLoading);
-            };
-            secstatus_to_res(unsafe {
-                ssl::SSL_ConfigServerCert(agent.fd, *cert, *key, null(), 0)
-            })?;
+            config_server_cert(agent.fd, n.as_ref())?;
         }
 
         agent.ready(true, true)?;
         Ok(Self {
             agent,
             zero_rtt_check: None,
+            server_name: None,
+            client_auth: None,
         })
     }
 
+    unsafe extern "C" fn sni_cb(
+        fd: *mut ssl::PRFileDesc,
+        names: *const ssl::SECItem,
+        count: ssl::PRUint32,
+        arg: *mut c_void,
+    ) -> ssl::PRInt32 {
+        let state = arg.cast::<ServerNameState>().as_mut().unwrap();
+        if names.is_null() {
+            return SSL_SNI_CURRENT_CONFIG_IS_USED;
+        }
+        let names = std::slice::from_raw_parts(names, usize::try_from(count).unwrap());
+        for (i, item) in names.iter().enumerate() {
+            if item.data.is_null() {
+                continue;
+            }
+            let name = std::slice::from_raw_parts(item.data, usize::try_from(item.len).unwrap());
+            let Ok(name) = std::str::from_utf8(name) else {
+                continue;
+            };
+            let name = state.server_name.insert(name.to_ascii_lowercase());
+            let Some(config) = state.selector.select(name) else {
+                return SSL_SNI_CURRENT_CONFIG_IS_USED;
+            };
+            qdebug!("Using certificate {} for {}", config.certificate, name);
+            if config_server_cert(fd, &config.certificate).is_err()
+                || (!config.protocols.is_empty() && set_alpn(fd, &config.protocols).is_err())
+            {
+                qwarn!("Unable to configure the server for {}", name);
+                return SSL_SNI_SEND_ALERT;
+            }
+            return ssl::PRInt32::try_from(i).unwrap();
+        }
+        SSL_SNI_CURRENT_CONFIG_IS_USED
+    }
+
+    /// Pick the certificate, and optionally the ALPN values, based on the server name that the
+    /// client sends.
+    ///
+    /// # Errors
+    ///
+    /// If the underlying NSS function fails.
+    pub fn set_server_name_selector(&mut self, selector: Rc<dyn ServerNameSelector>) -> Res<()> {
+        let mut state = Box::pin(ServerNameState {
+            selector,
+            server_name: None,
+        });
+        secstatus_to_res(unsafe {
+            ssl::SSL_SNISocketConfigHook(self.agent.fd, Some(Self::sni_cb), as_c_void(&mut state))
+        })?;
+        self.server_name = Some(state);
+        Ok(())
+    }
+
+    /// The server name that the client sent, if a `ServerNameSelector` is in use.
+    #[must_use]
+    pub fn server_name_indication(&self) -> Option<&str> {
+        self.server_name
+            .as_ref()
+            .and_then(|state| state.server_name.as_deref())
+    }
+
+    unsafe extern "C" fn client_auth_cb(
+        arg: *mut c_void,
+        fd: *mut ssl::PRFileDesc,
+        _check_sig: ssl::PRBool,
+        _is_server: ssl::PRBool,
+    ) -> ssl::SECStatus {
+        // A server can't wait for the application to check a certificate,
+        // so this happens here.
+        let state = arg.cast::<ClientAuthState>().as_ref().unwrap();
+        let status = Time::try_from(*state.now).map_or(AuthenticationStatus::Unknown, |now| {
+            CertificateInfo::new(fd).map_or(AuthenticationStatus::Unknown, |mut certs| {
+                state.verifier.verify_client(&mut certs, *now)
+            })
+        });
+        qdebug!([format!("{fd:p}")], "Client certificate: {:?}", status);
+        if status == AuthenticationStatus::Ok {
+            ssl::SECSuccess
+        } else {
+            PR_SetError(PRErrorCode::from(status), 0);
+            ssl::SECFailure
+        }
+    }
+
+    /// Ask clients for a certificate, which is checked with `verifier`.  If `required` is
+    /// false, clients can choose not to send one.  A server can then use `peer_certificate`
+    /// to see which certificate a client used, if any.
+    ///
+    /// # Errors
+    ///
+    /// If the underlying NSS functions fail.
+    pub fn request_client_certificate(
+        &mut self,
+        verifier: CertificateVerifier,
+        required: bool,
+    ) -> Res<()> {
+        let mut state = Box::pin(ClientAuthState {
+            verifier,
+            now: self.agent.now.as_ptr(),
+        });
+        secstatus_to_res(unsafe {
+            ssl::SSL_AuthCertificateHook(
+                self.agent.fd,
+                Some(Self::client_auth_cb),
+                as_c_void(&mut state),
+            )
+        })?;
+        self.set_option(ssl::Opt::RequestCertificate, true)?;
+        self.set_option(ssl::Opt::RequireCertificate, required)?;
+        self.client_auth = Some(state);
+        Ok(())
+    }
+
     unsafe extern "C" fn hello_retry_cb(
         first_hello: PRBool,
         client_token: *const u8,
@@ -0,0 +1,334 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Certificate compression, as defined in RFC 8879.
+
+use std::{
+    cell::{Cell, RefCell},
+    collections::VecDeque,
+    convert::TryFrom,
+    io::{self, Read, Write},
+    os::raw::c_uint,
+    ptr::null_mut,
+    slice,
+};
+
+use flate2::{read::ZlibDecoder, write::ZlibEncoder, Compression};
+use neqo_common::qtrace;
+
+use crate::{
+    err::Res,
+    p11,
+    ssl::{self, PRFileDesc, SECFailure, SECStatus, SECSuccess},
+};
+
+/// The size of the buffer that brotli uses internally.
+const BROTLI_BUFFER_SIZE: usize = 4096;
+/// The brotli quality setting, which is the highest available.
+const BROTLI_QUALITY: u32 = 11;
+/// The base 2 logarithm of the brotli window size.
+const BROTLI_WINDOW: u32 = 22;
+/// The number of compressed certificate messages that are kept.
+const CACHE_SIZE: usize = 4;
+
+/// A certificate compression algorithm.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CertificateCompression {
+    Zlib,
+    Brotli,
+}
+
+impl CertificateCompression {
+    /// The codepoint for the algorithm.
+    #[must_use]
+    pub fn id(self) -> u16 {
+        match self {
+            Self::Zlib => 1,
+            Self::Brotli => 2,
+        }
+    }
+
+    fn name(self) -> &'static [u8] {
+        match self {
+            Self::Zlib => b"zlib\0",
+            Self::Brotli => b"brotli\0",
+        }
+    }
+
+    fn compress(self, input: &[u8]) -> io::Result<Vec<u8>> {
+        match self {
+            Self::Zlib => {
+                let mut encoder = ZlibEncoder::new(Vec::new(), Compression::best());
+                encoder.write_all(input)?;
+                encoder.finish()
+            }
+            Self::Brotli => {
+                let mut encoder = brotli::CompressorWriter::new(
+                    Vec::new(),
+                    BROTLI_BUFFER_SIZE,
+                    BROTLI_QUALITY,
+                    BROTLI_WINDOW,
+                );
+                encoder.write_all(input)?;
+                Ok(encoder.into_inner())
+            }
+        }
+    }
+
+    /// Compress `input`, reusing the output from an earlier call with the same input.
+    /// A server sends the same certificate on every connection, and compressing it
+    /// at the highest brotli quality is too slow to repeat for each handshake.
+    fn compress_cached(self, input: &[u8]) -> io::Result<Vec<u8>> {
+        let cached = CACHE.with(|cache| {
+            cache
+                .borrow()
+                .iter()
+                .find(|(alg, i, _)| *alg == self && i == input)
+                .map(|(_, _, c)| c.clone())
+        });
+        if let Some(compressed) = cached {
+            return Ok(compressed);
+        }
+        let compressed = self.compress(input)?;
+        CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            if cache.len() >= CACHE_SIZE {
+                cache.pop_front();
+            }
+            cache.push_back((self, input.to_vec(), compressed.clone()));
+        });
+        Ok(compressed)
+    }
+
+    fn decompress(self, input: &[u8], output: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Self::Zlib => read_exact_size(ZlibDecoder::new(input), output),
+            Self::Brotli => {
+                read_exact_size(brotli::Decompressor::new(input, BROTLI_BUFFER_SIZE), output)
+            }
+        }
+    }
+
+    fn algorithm(self) -> ssl::SSLCertificateCompressionAlgorithm {
+        let (id, name) = (self.id(), self.name().as_ptr().cast());
+        match self {
+            Self::Zlib => ssl::SSLCertificateCompressionAlgorithm {
+                id,
+                name,
+                encode: Some(zlib_encode),
+                decode: Some(zlib_decode),
+            },
+            Self::Brotli => ssl::SSLCertificateCompressionAlgorithm {
+                id,
+                name,
+                encode: Some(brotli_encode),
+                decode: Some(brotli_decode),
+            },
+        }
+    }
+
+    /// Enable this algorithm on the socket.
+    pub(crate) fn enable(self, fd: *mut PRFileDesc) -> Res<()> {
+        unsafe { ssl::SSL_SetCertificateCompressionAlgorithm(fd, self.algorithm()) }
+    }
+}
+
+/// The sizes of the last certificate message that was compressed or decompressed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CertificateCompressionInfo {
+    /// The algorithm that was used.
+    pub algorithm: CertificateCompression,
+    /// The size of the compressed message.
+    pub compressed: usize,
+    /// The size of the message before compression.
+    pub uncompressed: usize,
+}
+
+thread_local! {
+    // The compression callbacks don't take an argument, so the sizes are parked here
+    // until the agent that called into NSS collects them.
+    static LAST: Cell<Option<CertificateCompressionInfo>> = Cell::new(None);
+    // Recently compressed certificate messages, with their input.
+    static CACHE: RefCell<VecDeque<(CertificateCompression, Vec<u8>, Vec<u8>)>> =
+        RefCell::new(VecDeque::new());
+}
+
+fn record(algorithm: CertificateCompression, compressed: usize, uncompressed: usize) {
+    qtrace!(
+        "Certificate compression {:?}: {} -> {}",
+        algorithm,
+        uncompressed,
+        compressed
+    );
+    LAST.with(|last| {
+        last.set(Some(CertificateCompressionInfo {
+            algorithm,
+            compressed,
+            uncompressed,
+        }));
+    });
+}
+
+/// Take any information that was recorded by the compression callbacks.
+pub(crate) fn take_info() -> Option<CertificateCompressionInfo> {
+    LAST.with(Cell::take)
+}
+
+/// Read from `reader` to fill `output`, failing if there is more data than that.
+fn read_exact_size(mut reader: impl Read, output: &mut [u8]) -> io::Result<usize> {
+    let mut used = 0;
+    while used < output.len() {
+        let n = reader.read(&mut output[used..])?;
+        if n == 0 {
+            break;
+        }
+        used += n;
+    }
+    if reader.read(&mut [0])? != 0 {
+        return Err(io::Error::from(io::ErrorKind::InvalidData));
+    }
+    Ok(used)
+}
+
+unsafe fn encode(
+    algorithm: CertificateCompression,
+    input: *const ssl::SECItem,
+    output: *mut ssl::SECItem,
+) -> SECStatus {
+    let input = slice::from_raw_parts((*input).data, (*input).len as usize);
+    let Ok(compressed) = algorithm.compress_cached(input) else {
+        return SECFailure;
+    };
+    let Ok(len) = c_uint::try_from(compressed.len()) else {
+        return SECFailure;
+    };
+    // NSS frees the output, so it has to be allocated by NSS.
+    if p11::SECITEM_AllocItem(null_mut(), output.cast(), len).is_null() {
+        return SECFailure;
+    }
+    (*output)
+        .data
+        .copy_from_nonoverlapping(compressed.as_ptr(), compressed.len());
+    record(algorithm, compressed.len(), input.len());
+    SECSuccess
+}
+
+unsafe fn decode(
+    algorithm: CertificateCompression,
+    input: *const ssl::SECItem,
+    output: *mut u8,
+    output_len: usize,
+    used_len: *mut usize,
+) -> SECStatus {
+    let input = slice::from_raw_parts((*input).data, (*input).len as usize);
+    let output = slice::from_raw_parts_mut(output, output_len);
+    let Ok(used) = algorithm.decompress(input, output) else {
+        return SECFailure;
+    };
+    *used_len = used;
+    record(algorithm, input.len(), used);
+    SECSuccess
+}
+
+unsafe extern "C" fn zlib_encode(
+    input: *const ssl::SECItem,
+    output: *mut ssl::SECItem,
+) -> SECStatus {
+    encode(CertificateCompression::Zlib, input, output)
+}
+
+unsafe extern "C" fn zlib_decode(
+    input: *const ssl::SECItem,
+    output: *mut u8,
+    output_len: usize,
+    used_len: *mut usize,
+) -> SECStatus {
+    decode(
+        CertificateCompression::Zlib,
+        input,
+        output,
+        output_len,
+        used_len,
+    )
+}
+
+unsafe extern "C" fn brotli_encode(
+    input: *const ssl::SECItem,
+    output: *mut ssl::SECItem,
+) -> SECStatus {
+    encode(CertificateCompression::Brotli, input, output)
+}
+
+unsafe extern "C" fn brotli_decode(
+    input: *const ssl::SECItem,
+    output: *mut u8,
+    output_len: usize,
+    used_len: *mut usize,
+) -> SECStatus {
+    decode(
+        CertificateCompression::Brotli,
+        input,
+        output,
+        output_len,
+        used_len,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CertificateCompression, CACHE, CACHE_SIZE};
+
+    #[test]
+    fn round_trip() {
+        let input = b"certificate certificate certificate certificate".repeat(20);
+        for alg in [CertificateCompression::Zlib, CertificateCompression::Brotli] {
+            let compressed = alg.compress(&input).unwrap();
+            assert!(compressed.len() < input.len());
+            let mut output = vec![0; input.len()];
+            assert_eq!(
+                alg.decompress(&compressed, &mut output).unwrap(),
+                input.len()
+            );
+            assert_eq!(output, input);
+            // The message has to decompress to exactly the advertised size.
+            let mut short = vec![0; input.len() - 1];
+            assert!(alg.decompress(&compressed, &mut short).is_err());
+        }
+    }
+
+    #[test]
+    fn cached() {
+        let input = b"certificate certificate certificate certificate".repeat(20);
+        let alg = CertificateCompression::Brotli;
+        let compressed = alg.compress_cached(&input).unwrap();
+        assert_eq!(alg.compress_cached(&input).unwrap(), compressed);
+        let count = |a| {
+            CACHE.with(|cache| {
+                cache
+                    .borrow()
+                    .iter()
+                    .filter(|(alg, _, _)| *alg == a)
+                    .count()
+            })
+        };
+        assert_eq!(count(alg), 1);
+
+        // The algorithm is part of the key.
+        let zlib = CertificateCompression::Zlib;
+        assert_ne!(zlib.compress_cached(&input).unwrap(), compressed);
+        assert_eq!(count(zlib), 1);
+
+        // Old entries are dropped.
+        for i in (0..u8::MAX).take(CACHE_SIZE) {
+            alg.compress_cached(&[i; 10]).unwrap();
+        }
+        CACHE.with(|cache| {
+            let cache = cache.borrow();
+            assert_eq!(cache.len(), CACHE_SIZE);
+            assert!(cache.iter().all(|(_, i, _)| i != &input));
+        });
+    }
+}
@@ -20,9 +20,9 @@ use crate::{
     },
     err::{secstatus_to_res, Error, Res},
     p11::{
-        Context, Item, PK11SymKey, PK11_CipherOp, PK11_CreateContextBySymKey, PK11_Encrypt,
-        PK11_GetBlockSize, SymKey, CKA_ENCRYPT, CKM_AES_ECB, CKM_CHACHA20, CK_ATTRIBUTE_TYPE,
-        CK_CHACHA20_PARAMS, CK_MECHANISM_TYPE,
+        Context, Item, PK11Origin, PK11SymKey, PK11_CipherOp, PK11_CreateContextBySymKey,
+        PK11_Encrypt, PK11_GetBlockSize, PK11_ImportDataKey, Slot, SymKey, CKA_ENCRYPT,
+        CKM_AES_ECB, CKM_CHACHA20, CK_ATTRIBUTE_TYPE, CK_CHACHA20_PARAMS, CK_MECHANISM_TYPE,
     },
 };
 
@@ -45,7 +45,8 @@ pub enum HpKey {
     /// Note: as we need to clone this object, we clone the pointer and
     /// track references using `Rc`.  `PK11Context` can't be used with `PK11_CloneContext`
     /// as that is not supported for these contexts.
-    Aes(Rc<RefCell<Context>>),
+    /// The key is retained so that it can be exported.
+    Aes(Rc<RefCell<Context>>, SymKey),
     /// The ChaCha20 mask has to invoke a new PK11_Encrypt every time as it needs to
     /// change the counter and nonce on each invocation.
     Chacha(SymKey),
@@ -69,20 +70,10 @@ impl HpKey {
     /// # Panics
     ///
     /// When `cipher` is not known to this code.
-    #[allow(clippy::cast_sign_loss)] // Cast for PK11_GetBlockSize is safe.
     pub fn extract(version: Version, cipher: Cipher, prk: &SymKey, label: &str) -> Res<Self> {
-        const ZERO: &[u8] = &[0; 12];
-
         let l = label.as_bytes();
         let mut secret: *mut PK11SymKey = null_mut();
-
-        #[allow(clippy::useless_conversion)] // TODO: Remove when we bump the MSRV to 1.74.0.
-        let (mech, key_size) = match cipher {
-            TLS_AES_128_GCM_SHA256 => (CK_MECHANISM_TYPE::from(CKM_AES_ECB), 16),
-            TLS_AES_256_GCM_SHA384 => (CK_MECHANISM_TYPE::from(CKM_AES_ECB), 32),
-            TLS_CHACHA20_POLY1305_SHA256 => (CK_MECHANISM_TYPE::from(CKM_CHACHA20), 32),
-            _ => unreachable!(),
-        };
+        let (mech, key_size) = Self::mechanism(cipher);
 
         // Note that this doesn't allow for passing null() for the handshake hash.
         // A zero-length slice produces an identical result.
@@ -101,7 +92,54 @@ impl HpKey {
             )
         }?;
         let key = SymKey::from_ptr(secret).or(Err(Error::HkdfError))?;
+        Self::from_key(cipher, key)
+    }
+
+    /// Import a header-protection key from the raw bytes produced by `key_data`.
+    ///
+    /// # Errors
+    ///
+    /// Errors if NSS fails to import the key or the key has the wrong length.
+    ///
+    /// # Panics
+    ///
+    /// When `cipher` is not known to this code.
+    pub fn import(cipher: Cipher, key: &[u8]) -> Res<Self> {
+        let (mech, key_size) = Self::mechanism(cipher);
+        if key.len() != usize::try_from(key_size)? {
+            return Err(Error::CipherInitFailure);
+        }
+        let slot = Slot::internal()?;
+        #[allow(clippy::useless_conversion)] // TODO: Remove when we bump the MSRV to 1.74.0.
+        let key_ptr = unsafe {
+            PK11_ImportDataKey(
+                *slot,
+                mech,
+                PK11Origin::PK11_OriginUnwrap,
+                CK_ATTRIBUTE_TYPE::from(CKA_ENCRYPT),
+                &mut Item::wrap(key),
+                null_mut(),
+            )
+        };
+        Self::from_key(cipher, SymKey::from_ptr(key_ptr)?)
+    }
+
+    /// The mechanism and key size for the header protection key of `cipher`.
+    #[allow(clippy::useless_conversion)] // TODO: Remove when we bump the MSRV to 1.74.0.
+    fn mechanism(cipher: Cipher) -> (CK_MECHANISM_TYPE, c_uint) {
+        match cipher {
+            TLS_AES_128_GCM_SHA256 => (CK_MECHANISM_TYPE::from(CKM_AES_ECB), 16),
+            TLS_AES_256_GCM_SHA384 => (CK_MECHANISM_TYPE::from(CKM_AES_ECB), 32),
+            TLS_CHACHA20_POLY1305_SHA256 => (CK_MECHANISM_TYPE::from(CKM_CHACHA20), 32),
+            _ => unreachable!(),
+        }
+    }
 
+    #[allow(clippy::cast_sign_loss)] // Cast for PK11_GetBlockSize is safe.
+    fn from_key(cipher: Cipher, key: SymKey) -> Res<Self> {
+        const ZERO: &[u8] = &[0; 12];
+
+        let (mech, _) = Self::mechanism(cipher);
         let res = match cipher {
             TLS_AES_128_GCM_SHA256 | TLS_AES_256_GCM_SHA384 => {
                 // TODO: Remove when we bump the MSRV to 1.74.0.
@@ -115,7 +153,7 @@ impl HpKey {
                     )
                 };
                 let context = Context::from_ptr(context_ptr).or(Err(Error::CipherInitFailure))?;
-                Self::Aes(Rc::new(RefCell::new(context)))
+                Self::Aes(Rc::new(RefCell::new(context)), key)
             }
             TLS_CHACHA20_POLY1305_SHA256 => Self::Chacha(key),
             _ => unreachable!(),
@@ -128,6 +166,17 @@ impl HpKey {
         Ok(res)
     }
 
+    /// The raw bytes of the header protection key, which can be passed to `import`.
+    ///
+    /// # Errors
+    ///
+    /// When NSS won't reveal the key.
+    pub fn key_data(&self) -> Res<&[u8]> {
+        match self {
+            Self::Aes(_, key) | Self::Chacha(key) => key.as_bytes(),
+        }
+    }
+
     /// Get the sample size, which is also the output size.
     #[must_use]
     #[allow(clippy::unused_self)] // To maintain an API contract.
@@ -137,7 +186,7 @@ impl HpKey {
 
     fn block_size(&self) -> usize {
         match self {
-            Self::Aes(_) => 16,
+            Self::Aes(..) => 16,
             Self::Chacha(_) => 64,
         }
     }
@@ -156,7 +205,7 @@ impl HpKey {
         let mut output = vec![0_u8; self.block_size()];
 
         match self {
-            Self::Aes(context) => {
+            Self::Aes(context, _) => {
                 let mut output_len: c_int = 0;
                 secstatus_to_res(unsafe {
                     PK11_CipherOp(
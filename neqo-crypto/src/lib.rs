@@ -19,6 +19,7 @@ pub mod agent;
 mod agentio;
 mod auth;
 mod cert;
+mod compression;
 pub mod constants;
 mod ech;
 mod err;
@@ -30,12 +31,14 @@ pub mod hp;
 mod once;
 #[macro_use]
 mod p11;
+mod pem;
 mod prio;
 mod replay;
 mod secrets;
 pub mod selfencrypt;
 mod ssl;
 mod time;
+mod verify;
 
 use std::{
     ffi::CString,
@@ -52,11 +55,12 @@ pub use self::aead_fuzzing::FuzzingAead as Aead;
 use self::once::OnceResult;
 pub use self::{
     agent::{
-        Agent, AllowZeroRtt, Client, HandshakeState, Record, RecordList, ResumptionToken,
-        SecretAgent, SecretAgentInfo, SecretAgentPreInfo, Server, ZeroRttCheckResult,
-        ZeroRttChecker,
+        Agent, AllowZeroRtt, Client, ClientCertificateSelector, HandshakeState, Record, RecordList,
+        ResumptionToken, SecretAgent, SecretAgentInfo, SecretAgentPreInfo, Server,
+        ServerNameConfig, ServerNameSelector, ZeroRttCheckResult, ZeroRttChecker,
     },
     auth::AuthenticationStatus,
+    compression::{CertificateCompression, CertificateCompressionInfo},
     constants::*,
     ech::{
         encode_config as encode_ech_config, generate_keys as generate_ech_keys, AeadId, KdfId,
@@ -65,9 +69,11 @@ pub use self::{
     err::{Error, PRErrorCode, Res},
     ext::{ExtensionHandler, ExtensionHandlerResult, ExtensionWriterResult},
     p11::{random, PrivateKey, PublicKey, SymKey},
+    pem::{load_certificate, load_trust_anchors},
     replay::AntiReplay,
     secrets::SecretDirection,
     ssl::Opt,
+    verify::CertificateVerifier,
 };
 
 const MINIMUM_NSS_VERSION: &str = "3.97";
@@ -133,6 +139,9 @@ pub fn init() {
 
             secstatus_to_res(nss::NSS_NoDB_Init(null())).expect("NSS_NoDB_Init failed");
             secstatus_to_res(nss::NSS_SetDomesticPolicy()).expect("NSS_SetDomesticPolicy failed");
+            // Servers need a session cache, even without a database.
+            secstatus_to_res(ssl::SSL_ConfigServerSessionIDCache(1024, 0, 0, null()))
+                .expect("SSL_ConfigServerSessionIDCache failed");
 
             NssLoaded::NoDb
         });
@@ -0,0 +1,359 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Loading certificates and keys from PEM or DER, without an NSS database.
+
+use std::{cell::RefCell, collections::HashMap, convert::TryFrom, ptr::null_mut};
+
+use neqo_common::qdebug;
+
+use crate::{
+    err::{secstatus_to_res, Error, Res},
+    p11::{
+        self, CERTCertTrust, CERT_ChangeCertTrust, CERT_GetDefaultCertDB, CERT_NewTempCertificate,
+        Certificate, Item, PK11_ImportDERPrivateKeyInfoAndReturnKey, PRBool, PrivateKey, Slot,
+    },
+};
+
+// These are macros in certdb.h and certt.h.
+const CERTDB_VALID_CA: u32 = 1 << 3;
+const CERTDB_TRUSTED_CA: u32 = 1 << 4;
+const CERTDB_TRUSTED_CLIENT_CA: u32 = 1 << 7;
+const KU_DIGITAL_SIGNATURE: u32 = 0x80;
+
+/// id-ecPublicKey from RFC 5480.
+const OID_EC_PUBLIC_KEY: &[u8] = &[0x2a, 0x86, 0x48, 0xce, 0x3d, 0x02, 0x01];
+/// rsaEncryption from RFC 8017.
+const OID_RSA_ENCRYPTION: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x01, 0x01];
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_NULL: u8 = 0x05;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+const TAG_EC_PARAMETERS: u8 = 0xa0;
+
+struct ServerCertificate {
+    cert: Certificate,
+    key: PrivateKey,
+    /// The rest of the chain.  NSS finds these when it builds the chain for the handshake,
+    /// but only for as long as they are referenced.
+    _chain: Vec<Certificate>,
+}
+
+thread_local! {
+    static SERVER_CERTIFICATES: RefCell<HashMap<String, ServerCertificate>> =
+        RefCell::new(HashMap::new());
+    static TRUST_ANCHORS: RefCell<Vec<Certificate>> = RefCell::new(Vec::new());
+}
+
+fn base64_value(c: u8) -> Option<u32> {
+    match c {
+        b'A'..=b'Z' => Some(u32::from(c - b'A')),
+        b'a'..=b'z' => Some(u32::from(c - b'a') + 26),
+        b'0'..=b'9' => Some(u32::from(c - b'0') + 52),
+        b'+' => Some(62),
+        b'/' => Some(63),
+        _ => None,
+    }
+}
+
+fn base64_decode(text: &str) -> Res<Vec<u8>> {
+    let mut out = Vec::with_capacity(text.len() * 3 / 4);
+    let mut acc = 0;
+    let mut bits = 0;
+    for c in text.bytes().filter(|c| !c.is_ascii_whitespace()) {
+        if c == b'=' {
+            break;
+        }
+        acc = (acc << 6) | base64_value(c).ok_or(Error::CertificateLoading)?;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            out.push(u8::try_from((acc >> bits) & 0xff).unwrap());
+        }
+    }
+    Ok(out)
+}
+
+/// Decode the blocks in a PEM file, returning the label and content of each.
+/// Input that isn't PEM is treated as a single DER-encoded item with an empty label.
+fn decode(input: &[u8]) -> Res<Vec<(String, Vec<u8>)>> {
+    const BEGIN: &str = "-----BEGIN ";
+    const END: &str = "-----END ";
+
+    let Some(text) = std::str::from_utf8(input)
+        .ok()
+        .filter(|text| text.contains(BEGIN))
+    else {
+        return Ok(vec![(String::new(), input.to_vec())]);
+    };
+
+    let mut blocks = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(BEGIN) {
+        let after = &rest[start + BEGIN.len()..];
+        let label_end = after.find("-----").ok_or(Error::CertificateLoading)?;
+        let label = &after[..label_end];
+        let body = &after[label_end + 5..];
+        let end_marker = format!("{END}{label}-----");
+        let body_end = body.find(&end_marker).ok_or(Error::CertificateLoading)?;
+        blocks.push((label.to_owned(), base64_decode(&body[..body_end])?));
+        rest = &body[body_end + end_marker.len()..];
+    }
+    Ok(blocks)
+}
+
+fn der_encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut out = vec![tag];
+    if content.len() < 0x80 {
+        out.push(u8::try_from(content.len()).unwrap());
+    } else {
+        let len = content.len().to_be_bytes();
+        let skip = len.iter().take_while(|&&b| b == 0).count();
+        out.push(0x80 | u8::try_from(len.len() - skip).unwrap());
+        out.extend_from_slice(&len[skip..]);
+    }
+    out.extend_from_slice(content);
+    out
+}
+
+/// Read one DER element, returning the tag, the content, and what follows.
+pub(crate) fn der_read(input: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, input) = input.split_first()?;
+    let (&first, mut input) = input.split_first()?;
+    let len = if first < 0x80 {
+        usize::from(first)
+    } else {
+        let n = usize::from(first & 0x7f);
+        if n == 0 || n > 4 || input.len() < n {
+            return None;
+        }
+        let len = input[..n]
+            .iter()
+            .fold(0, |acc, &b| (acc << 8) | usize::from(b));
+        input = &input[n..];
+        len
+    };
+    (input.len() >= len).then(|| (tag, &input[..len], &input[len..]))
+}
+
+/// Wrap a private key that has no PKCS#8 header.
+fn pkcs8_wrap(algorithm: &[u8], key: &[u8]) -> Vec<u8> {
+    let mut content = der_encode(TAG_INTEGER, &[0]);
+    content.extend_from_slice(&der_encode(TAG_SEQUENCE, algorithm));
+    content.extend_from_slice(&der_encode(TAG_OCTET_STRING, key));
+    der_encode(TAG_SEQUENCE, &content)
+}
+
+/// Turn an RFC 5915 `ECPrivateKey` into PKCS#8.  The curve is taken from the parameters,
+/// which are optional in general but are always included by tools that write these files.
+fn ec_to_pkcs8(key: &[u8]) -> Res<Vec<u8>> {
+    let (tag, mut fields, _) = der_read(key).ok_or(Error::CertificateLoading)?;
+    if tag != TAG_SEQUENCE {
+        return Err(Error::CertificateLoading);
+    }
+    while let Some((tag, content, rest)) = der_read(fields) {
+        if tag == TAG_EC_PARAMETERS {
+            let mut algorithm = der_encode(TAG_OID, OID_EC_PUBLIC_KEY);
+            algorithm.extend_from_slice(content);
+            return Ok(pkcs8_wrap(&algorithm, key));
+        }
+        fields = rest;
+    }
+    Err(Error::CertificateLoading)
+}
+
+fn rsa_to_pkcs8(key: &[u8]) -> Vec<u8> {
+    let mut algorithm = der_encode(TAG_OID, OID_RSA_ENCRYPTION);
+    algorithm.extend_from_slice(&der_encode(TAG_NULL, &[]));
+    pkcs8_wrap(&algorithm, key)
+}
+
+/// Make a certificate that isn't stored in the database from its DER encoding.
+pub(crate) fn temp_certificate(der: &[u8]) -> Res<Certificate> {
+    let mut item = Item::wrap(der);
+    Certificate::from_ptr(unsafe {
+        CERT_NewTempCertificate(
+            CERT_GetDefaultCertDB(),
+            &mut item,
+            null_mut(),
+            PRBool::from(false),
+            PRBool::from(true),
+        )
+    })
+}
+
+fn certificates(input: &[u8]) -> Res<Vec<Certificate>> {
+    let certs = decode(input)?
+        .into_iter()
+        .filter(|(label, _)| label.is_empty() || label == "CERTIFICATE")
+        .map(|(_, der)| temp_certificate(&der))
+        .collect::<Res<Vec<_>>>()?;
+    if certs.is_empty() {
+        return Err(Error::CertificateLoading);
+    }
+    Ok(certs)
+}
+
+fn private_key(input: &[u8]) -> Res<PrivateKey> {
+    // Some tools write the curve separately, ahead of the key.
+    let mut blocks = decode(input)?;
+    blocks.retain(|(label, _)| label != "EC PARAMETERS");
+    if blocks.len() != 1 {
+        return Err(Error::CertificateLoading);
+    }
+    let (label, der) = blocks.remove(0);
+    let pkcs8 = match label.as_str() {
+        "" | "PRIVATE KEY" => der,
+        "EC PRIVATE KEY" => ec_to_pkcs8(&der)?,
+        "RSA PRIVATE KEY" => rsa_to_pkcs8(&der),
+        _ => return Err(Error::CertificateLoading),
+    };
+
+    let slot = Slot::internal()?;
+    let mut item = Item::wrap(&pkcs8);
+    let mut key_ptr = null_mut();
+    secstatus_to_res(unsafe {
+        PK11_ImportDERPrivateKeyInfoAndReturnKey(
+            *slot,
+            &mut item,
+            null_mut(),
+            null_mut(),
+            PRBool::from(false),
+            PRBool::from(true),
+            KU_DIGITAL_SIGNATURE,
+            &mut key_ptr,
+            null_mut(),
+        )
+    })?;
+    PrivateKey::from_ptr(key_ptr)
+}
+
+/// Load a certificate chain and its private key so that a server can use it.
+///
+/// The chain starts with the server certificate and can include intermediate certificates.
+/// It can be in PEM format, or a single DER-encoded certificate.  The key can be a PKCS#8
+/// private key, or an EC or RSA key in the older formats; encrypted keys aren't supported.
+/// Afterwards, `nickname` can be passed to `Server::new` in place of a certificate from the
+/// NSS database.  Loading another chain with the same nickname replaces it.
+///
+/// Certificates are loaded for the current thread only.
+///
+/// # Errors
+///
+/// When the input can't be decoded or NSS rejects it.
+pub fn load_certificate(nickname: &str, chain: &[u8], key: &[u8]) -> Res<()> {
+    let mut certs = certificates(chain)?;
+    let key = private_key(key)?;
+    let cert = certs.remove(0);
+    qdebug!("Loaded certificate {} with {} more", nickname, certs.len());
+    SERVER_CERTIFICATES.with(|loaded| {
+        loaded.borrow_mut().insert(
+            nickname.to_owned(),
+            ServerCertificate {
+                cert,
+                key,
+                _chain: certs,
+            },
+        );
+    });
+    Ok(())
+}
+
+/// Load trust anchors from a PEM file or a DER-encoded certificate.  These are trusted for
+/// issuing both server and client certificates.  This returns the number of certificates.
+///
+/// # Errors
+///
+/// When the input can't be decoded or NSS rejects it.
+pub fn load_trust_anchors(certs: &[u8]) -> Res<usize> {
+    let anchors = certificates(certs)?;
+    for cert in &anchors {
+        let mut trust = CERTCertTrust {
+            sslFlags: CERTDB_VALID_CA | CERTDB_TRUSTED_CA | CERTDB_TRUSTED_CLIENT_CA,
+            emailFlags: 0,
+            objectSigningFlags: 0,
+        };
+        secstatus_to_res(unsafe {
+            CERT_ChangeCertTrust(CERT_GetDefaultCertDB(), **cert, &mut trust)
+        })?;
+    }
+    let count = anchors.len();
+    TRUST_ANCHORS.with(|loaded| loaded.borrow_mut().extend(anchors));
+    Ok(count)
+}
+
+/// Call `f` with the certificate and key that were loaded with `nickname`, if there are any.
+pub(crate) fn with_server_certificate<T>(
+    nickname: &str,
+    f: impl FnOnce(*mut p11::CERTCertificate, *mut p11::SECKEYPrivateKey) -> T,
+) -> Option<T> {
+    SERVER_CERTIFICATES.with(|loaded| loaded.borrow().get(nickname).map(|c| f(*c.cert, *c.key)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{base64_decode, decode, der_encode, der_read, ec_to_pkcs8};
+
+    #[test]
+    fn base64() {
+        assert_eq!(base64_decode("").unwrap(), b"");
+        assert_eq!(base64_decode("Zg==").unwrap(), b"f");
+        assert_eq!(base64_decode("Zm8=").unwrap(), b"fo");
+        assert_eq!(base64_decode("Zm9v\nYmFy").unwrap(), b"foobar");
+        assert!(base64_decode("Zm9v!").is_err());
+    }
+
+    #[test]
+    fn pem() {
+        let text = b"junk\n-----BEGIN ONE-----\nAQI=\n-----END ONE-----\n\
+                     -----BEGIN TWO THREE-----\nAw==\n-----END TWO THREE-----\n";
+        let blocks = decode(text).unwrap();
+        assert_eq!(
+            blocks,
+            vec![
+                (String::from("ONE"), vec![1, 2]),
+                (String::from("TWO THREE"), vec![3])
+            ]
+        );
+        assert!(decode(b"-----BEGIN ONE-----\nAQI=\n-----END TWO-----\n").is_err());
+        assert_eq!(
+            decode(&[0x30, 0]).unwrap(),
+            vec![(String::new(), vec![0x30, 0])]
+        );
+    }
+
+    #[test]
+    fn der() {
+        let long = vec![7; 300];
+        let encoded = der_encode(0x04, &long);
+        assert_eq!(&encoded[..4], &[0x04, 0x82, 0x01, 0x2c]);
+        let (tag, content, rest) = der_read(&encoded).unwrap();
+        assert_eq!((tag, content, rest), (0x04, &long[..], &[][..]));
+        assert!(der_read(&encoded[..100]).is_none());
+    }
+
+    #[test]
+    fn ec_key() {
+        // version, privateKey, [0] prime256v1
+        let key: &[u8] = &[
+            0x30, 0x12, 0x02, 0x01, 0x01, 0x04, 0x01, 0x55, 0xa0, 0x0a, 0x06, 0x08, 0x2a, 0x86,
+            0x48, 0xce, 0x3d, 0x03, 0x01, 0x07,
+        ];
+        let pkcs8 = ec_to_pkcs8(key).unwrap();
+        let (_, fields, _) = der_read(&pkcs8).unwrap();
+        let (_, version, fields) = der_read(fields).unwrap();
+        assert_eq!(version, &[0]);
+        let (_, algorithm, fields) = der_read(fields).unwrap();
+        assert_eq!(&algorithm[algorithm.len() - 10..], &key[10..]);
+        let (tag, wrapped, _) = der_read(fields).unwrap();
+        assert_eq!((tag, wrapped), (0x04, key));
+
+        // Without parameters, the curve is unknown.
+        assert!(ec_to_pkcs8(&[0x30, 0x06, 0x02, 0x01, 0x01, 0x04, 0x01, 0x55]).is_err());
+    }
+}
@@ -51,6 +51,8 @@ pub enum Opt {
     HelloDowngradeCheck,
     SuppressEndOfEarlyData,
     Grease,
+    RequestCertificate,
+    RequireCertificate,
 }
 
 impl Opt {
@@ -70,6 +72,8 @@ impl Opt {
             Self::HelloDowngradeCheck => SSLOption::SSL_ENABLE_HELLO_DOWNGRADE_CHECK,
             Self::SuppressEndOfEarlyData => SSLOption::SSL_SUPPRESS_END_OF_EARLY_DATA,
             Self::Grease => SSLOption::SSL_ENABLE_GREASE,
+            Self::RequestCertificate => SSLOption::SSL_REQUEST_CERTIFICATE,
+            Self::RequireCertificate => SSLOption::SSL_REQUIRE_CERTIFICATE,
         };
         i as PRInt32
     }
@@ -115,6 +119,10 @@ experimental_api!(SSL_SendSessionTicket(
     extra: *const u8,
     len: c_uint,
 ));
+experimental_api!(SSL_SetCertificateCompressionAlgorithm(
+    fd: *mut PRFileDesc,
+    alg: SSLCertificateCompressionAlgorithm,
+));
 experimental_api!(SSL_SetMaxEarlyDataSize(fd: *mut PRFileDesc, size: u32));
 experimental_api!(SSL_SetResumptionToken(
     fd: *mut PRFileDesc,
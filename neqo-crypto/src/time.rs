@@ -197,6 +197,12 @@ impl TimeHolder {
         *self.t = Time::from(t).try_into()?;
         Ok(())
     }
+
+    /// The location of the current time, for callbacks that need it.
+    /// This is valid for as long as this `TimeHolder` exists.
+    pub fn as_ptr(&self) -> *const PRTime {
+        &*self.t
+    }
 }
 
 impl Default for TimeHolder {
@@ -0,0 +1,266 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Checking certificates against the trust anchors that NSS knows about.
+
+use std::{
+    convert::{TryFrom, TryInto},
+    ffi::CString,
+    os::raw::c_int,
+    ptr::{addr_of, null_mut},
+    time::Instant,
+};
+
+use neqo_common::{hex, qdebug};
+
+use crate::{
+    auth::AuthenticationStatus,
+    err::{secstatus_to_res, Error, PRErrorCode, Res},
+    p11::{
+        CERTCertListNode, CERT_CheckCertValidTimes, CERT_GetCertChainFromCert,
+        CERT_GetCertificateDer, CERT_GetDefaultCertDB, CERT_VerifyCertName, CERT_VerifyCertificate,
+        CertList, Certificate, Item, PK11_HashBuf, PRBool, SECCertTimeValidity, SECCertUsageEnum,
+        SECOidTag,
+    },
+    pem,
+    time::Time,
+};
+
+// These are macros in certt.h.
+const CERTIFICATE_USAGE_SSL_CLIENT: i64 = 0x0001;
+const CERTIFICATE_USAGE_SSL_SERVER: i64 = 0x0002;
+
+const SHA256_LEN: usize = 32;
+
+/// The parts of a certificate that the verifier looks at, as complete DER elements.
+struct Names<'a> {
+    issuer: &'a [u8],
+    subject: &'a [u8],
+    spki: &'a [u8],
+}
+
+impl<'a> Names<'a> {
+    fn element(input: &'a [u8]) -> Option<(&'a [u8], &'a [u8])> {
+        let (_, _, rest) = pem::der_read(input)?;
+        Some((&input[..input.len() - rest.len()], rest))
+    }
+
+    fn parse(cert: &'a [u8]) -> Option<Self> {
+        let (_, cert, _) = pem::der_read(cert)?;
+        let (_, tbs, _) = pem::der_read(cert)?;
+        let (tag, _, mut fields) = pem::der_read(tbs)?;
+        // The version is optional; the serial number follows it.
+        if tag == 0xa0 {
+            fields = pem::der_read(fields)?.2;
+        }
+        let (_, fields) = Self::element(fields)?; // signature
+        let (issuer, fields) = Self::element(fields)?;
+        let (_, fields) = Self::element(fields)?; // validity
+        let (subject, fields) = Self::element(fields)?;
+        let (spki, _) = Self::element(fields)?;
+        Some(Self {
+            issuer,
+            subject,
+            spki,
+        })
+    }
+}
+
+fn sha256(data: &[u8]) -> Res<[u8; SHA256_LEN]> {
+    let mut out = [0; SHA256_LEN];
+    secstatus_to_res(unsafe {
+        PK11_HashBuf(
+            SECOidTag::SEC_OID_SHA256,
+            out.as_mut_ptr(),
+            data.as_ptr(),
+            c_int::try_from(data.len())?,
+        )
+    })?;
+    Ok(out)
+}
+
+/// A `CertificateVerifier` checks a certificate chain against the trust anchors that NSS has,
+/// either from its database or from `load_trust_anchors`.  It checks that the chain ends in a
+/// trust anchor, that all certificates are valid at the time of the check, and that the end-entity
+/// certificate is valid for the name that was used to connect.
+///
+/// Optionally, a set of pins can be added, which are SHA-256 hashes of the `SubjectPublicKeyInfo`
+/// of certificates.  When there are pins, one of the certificates in the chain needs to match.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct CertificateVerifier {
+    pins: Vec<[u8; SHA256_LEN]>,
+}
+
+impl CertificateVerifier {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a pin: the SHA-256 hash of a `SubjectPublicKeyInfo`.
+    ///
+    /// # Errors
+    ///
+    /// When `hash` isn't the size of a SHA-256 hash.
+    pub fn pin_sha256(mut self, hash: impl AsRef<[u8]>) -> Res<Self> {
+        let pin = <[u8; SHA256_LEN]>::try_from(hash.as_ref()).map_err(|_| Error::InternalError)?;
+        self.pins.push(pin);
+        Ok(self)
+    }
+
+    /// Check a server certificate chain, as presented by the server, for `server_name`.
+    /// The server name can be a DNS name or an IP address.
+    #[must_use]
+    pub fn verify_server<'a>(
+        &self,
+        chain: impl IntoIterator<Item = &'a [u8]>,
+        server_name: &str,
+        now: Instant,
+    ) -> AuthenticationStatus {
+        let status = self
+            .verify(chain, Some(server_name), CERTIFICATE_USAGE_SSL_SERVER, now)
+            .unwrap_or_else(|e| {
+                qdebug!("Certificate verification failed: {:?}", e);
+                AuthenticationStatus::Unknown
+            });
+        qdebug!("Certificate for {}: {:?}", server_name, status);
+        status
+    }
+
+    /// Check a client certificate chain.
+    #[must_use]
+    pub fn verify_client<'a>(
+        &self,
+        chain: impl IntoIterator<Item = &'a [u8]>,
+        now: Instant,
+    ) -> AuthenticationStatus {
+        self.verify(chain, None, CERTIFICATE_USAGE_SSL_CLIENT, now)
+            .unwrap_or(AuthenticationStatus::Unknown)
+    }
+
+    fn verify<'a>(
+        &self,
+        chain: impl IntoIterator<Item = &'a [u8]>,
+        name: Option<&str>,
+        usage: i64,
+        now: Instant,
+    ) -> Res<AuthenticationStatus> {
+        let mut chain = chain.into_iter();
+        let Some(leaf_der) = chain.next() else {
+            return Ok(AuthenticationStatus::Unknown);
+        };
+        let leaf = pem::temp_certificate(leaf_der)?;
+        // NSS only finds intermediate certificates while they are held.
+        let _intermediates = chain.map(pem::temp_certificate).collect::<Res<Vec<_>>>()?;
+        let now: i64 = Time::from(now).try_into()?;
+
+        match unsafe { CERT_CheckCertValidTimes(*leaf, now, PRBool::from(false)) } {
+            SECCertTimeValidity::secCertTimeValid => (),
+            SECCertTimeValidity::secCertTimeExpired => {
+                return Ok(AuthenticationStatus::CertExpired)
+            }
+            SECCertTimeValidity::secCertTimeNotValidYet => {
+                return Ok(AuthenticationStatus::CertNotYetValid)
+            }
+            _ => return Ok(AuthenticationStatus::CertInvalidTime),
+        }
+
+        let rv = unsafe {
+            CERT_VerifyCertificate(
+                CERT_GetDefaultCertDB(),
+                *leaf,
+                PRBool::from(true),
+                usage,
+                now,
+                null_mut(),
+                null_mut(),
+                null_mut(),
+            )
+        };
+        if let Err(e) = secstatus_to_res(rv) {
+            let Error::NssError { code, .. } = e else {
+                return Err(e);
+            };
+            return Ok(Self::chain_error(code, leaf_der));
+        }
+
+        if let Some(name) = name {
+            let name = CString::new(name)?;
+            if secstatus_to_res(unsafe { CERT_VerifyCertName(*leaf, name.as_ptr()) }).is_err() {
+                return Ok(AuthenticationStatus::CertSubjectInvalid);
+            }
+        }
+
+        if !self.pins.is_empty() && !self.check_pins(&leaf, usage, now)? {
+            return Ok(AuthenticationStatus::CertMitm);
+        }
+        Ok(AuthenticationStatus::Ok)
+    }
+
+    fn chain_error(code: PRErrorCode, leaf: &[u8]) -> AuthenticationStatus {
+        let status = AuthenticationStatus::from(code);
+        let self_signed = Names::parse(leaf).map_or(false, |n| n.issuer == n.subject);
+        match status {
+            AuthenticationStatus::IssuerUnknown | AuthenticationStatus::IssuerUntrusted
+                if self_signed =>
+            {
+                AuthenticationStatus::CertSelfSigned
+            }
+            _ => status,
+        }
+    }
+
+    /// Check that some certificate in the chain that NSS built has a pinned key.
+    fn check_pins(&self, leaf: &Certificate, usage: i64, now: i64) -> Res<bool> {
+        let cert_usage = if usage == CERTIFICATE_USAGE_SSL_SERVER {
+            SECCertUsageEnum::certUsageSSLServer
+        } else {
+            SECCertUsageEnum::certUsageSSLClient
+        };
+        let chain =
+            CertList::from_ptr(unsafe { CERT_GetCertChainFromCert(**leaf, now, cert_usage) })?;
+        // Two stars: one for the wrapper, one to deference the pointer.
+        let head: *const CERTCertListNode = unsafe { addr_of!((**chain).list).cast() };
+        let mut cursor = head;
+        loop {
+            cursor = unsafe { *cursor }.links.next.cast();
+            if cursor == head {
+                return Ok(false);
+            }
+            let mut item = Item::make_empty();
+            secstatus_to_res(unsafe { CERT_GetCertificateDer((*cursor).cert, &mut item) })?;
+            let der = unsafe { std::slice::from_raw_parts(item.data, item.len as usize) };
+            let Some(names) = Names::parse(der) else {
+                continue;
+            };
+            let hash = sha256(names.spki)?;
+            if self.pins.contains(&hash) {
+                qdebug!("Matched pin {}", hex(hash));
+                return Ok(true);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Names;
+
+    #[test]
+    fn names() {
+        // A minimal certificate structure, without a version:
+        // serial, signature, issuer, validity, subject, spki, then the signature fields.
+        let cert = [
+            0x30, 0x16, 0x30, 0x12, 0x02, 0x01, 0x01, 0x30, 0x00, 0x30, 0x01, 0x0a, 0x30, 0x00,
+            0x30, 0x01, 0x0b, 0x30, 0x02, 0x05, 0x00, 0x30, 0x00, 0x03, 0x00,
+        ];
+        let names = Names::parse(&cert).unwrap();
+        assert_eq!(names.issuer, &[0x30, 0x01, 0x0a]);
+        assert_eq!(names.subject, &[0x30, 0x01, 0x0b]);
+        assert_eq!(names.spki, &[0x30, 0x02, 0x05, 0x00]);
+        assert!(Names::parse(&cert[..10]).is_none());
+    }
+}
@@ -1,20 +1,26 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
-use std::boxed::Box;
+use std::{
+    boxed::Box,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use neqo_crypto::{
-    generate_ech_keys, AuthenticationStatus, Client, Error, HandshakeState, SecretAgentPreInfo,
-    Server, ZeroRttCheckResult, ZeroRttChecker, TLS_AES_128_GCM_SHA256,
-    TLS_CHACHA20_POLY1305_SHA256, TLS_GRP_EC_SECP256R1, TLS_GRP_EC_X25519, TLS_VERSION_1_3,
+    generate_ech_keys, load_certificate, load_trust_anchors, AuthenticationStatus,
+    CertificateCompression, CertificateVerifier, Client, ClientCertificateSelector, Error,
+    HandshakeState, SecretAgentPreInfo, Server, ServerNameConfig, ServerNameSelector,
+    ZeroRttCheckResult, ZeroRttChecker, TLS_AES_128_GCM_SHA256, TLS_CHACHA20_POLY1305_SHA256,
+    TLS_GRP_EC_SECP256R1, TLS_GRP_EC_X25519, TLS_VERSION_1_3,
 };
 
 mod handshake;
 use test_fixture::{fixture_init, now};
 
 use crate::handshake::{
-    connect, connect_fail, forward_records, resumption_setup, PermissiveZeroRttChecker, Resumption,
-    ZERO_RTT_TOKEN_DATA,
+    connect, connect_fail, connect_server_fail, forward_records, resumption_setup,
+    PermissiveZeroRttChecker, Resumption, ZERO_RTT_TOKEN_DATA,
 };
 
 #[test]
@@ -326,6 +332,285 @@ fn alpn_server_only() {
     assert_eq!(None, server.info().unwrap().alpn());
 }
 
+#[derive(Debug)]
+struct KeySelector {}
+
+impl ServerNameSelector for KeySelector {
+    fn select(&self, server_name: &str) -> Option<ServerNameConfig> {
+        (server_name == "server.example").then(|| ServerNameConfig {
+            certificate: String::from("key"),
+            protocols: Vec::new(),
+        })
+    }
+}
+
+#[test]
+fn server_name_selected() {
+    fixture_init();
+    let mut client = Client::new("server.example", true).expect("should create client");
+    let mut server = Server::new(&[] as &[&str]).expect("should create server");
+    server
+        .set_server_name_selector(Rc::new(KeySelector {}))
+        .expect("should set selector");
+    assert_eq!(server.server_name_indication(), None);
+
+    connect(&mut client, &mut server);
+
+    assert_eq!(server.server_name_indication(), Some("server.example"));
+}
+
+#[test]
+fn server_name_case() {
+    fixture_init();
+    let mut client = Client::new("Server.EXAMPLE", true).expect("should create client");
+    let mut server = Server::new(&[] as &[&str]).expect("should create server");
+    server
+        .set_server_name_selector(Rc::new(KeySelector {}))
+        .expect("should set selector");
+
+    connect(&mut client, &mut server);
+
+    assert_eq!(server.server_name_indication(), Some("server.example"));
+}
+
+#[test]
+fn server_name_default() {
+    fixture_init();
+    let mut client = Client::new("other.example", true).expect("should create client");
+    let mut server = Server::new(&["key"]).expect("should create server");
+    server
+        .set_server_name_selector(Rc::new(KeySelector {}))
+        .expect("should set selector");
+
+    connect(&mut client, &mut server);
+
+    assert_eq!(server.server_name_indication(), Some("other.example"));
+}
+
+const PEM_CHAIN: &[u8] = include_bytes!("../../test-fixture/pem/cert.pem");
+const PEM_KEY: &[u8] = include_bytes!("../../test-fixture/pem/key.pem");
+/// A certificate with lots of names, which compresses well.
+const PEM_BIG_CHAIN: &[u8] = include_bytes!("../../test-fixture/pem/big-cert.pem");
+
+#[test]
+fn pem_certificate() {
+    fixture_init();
+    load_certificate("pem", PEM_CHAIN, PEM_KEY).expect("should load certificate");
+    let mut client = Client::new("server.example", true).expect("should create client");
+    let mut server = Server::new(&["pem"]).expect("should create server");
+
+    connect(&mut client, &mut server);
+
+    // The chain includes the CA certificate.
+    let mut certs = client.peer_certificate().unwrap();
+    assert_eq!(2, certs.count());
+}
+
+#[test]
+fn pem_certificate_ec_key() {
+    fixture_init();
+    load_certificate(
+        "pem-ec",
+        PEM_CHAIN,
+        include_bytes!("../../test-fixture/pem/ec-key.pem"),
+    )
+    .expect("should load certificate");
+    let mut client = Client::new("server.example", true).expect("should create client");
+    let mut server = Server::new(&["pem-ec"]).expect("should create server");
+
+    connect(&mut client, &mut server);
+}
+
+#[test]
+fn pem_invalid() {
+    fixture_init();
+    assert!(load_certificate("bad", PEM_KEY, PEM_KEY).is_err());
+    assert!(load_certificate("bad", PEM_CHAIN, PEM_CHAIN).is_err());
+    assert!(load_certificate("bad", b"", PEM_KEY).is_err());
+    assert!(Server::new(&["bad"]).is_err());
+}
+
+#[test]
+fn trust_anchors() {
+    fixture_init();
+    assert_eq!(
+        load_trust_anchors(include_bytes!("../../test-fixture/pem/ca.pem")).unwrap(),
+        1
+    );
+}
+
+/// The SHA-256 hash of the `SubjectPublicKeyInfo` of the test CA.
+const CA_PIN: [u8; 32] = [
+    0x36, 0xa4, 0x5f, 0x24, 0xcc, 0xb9, 0x20, 0xb9, 0xe5, 0xa5, 0x65, 0xc6, 0x05, 0x24, 0xfe, 0xad,
+    0x0c, 0x68, 0x7b, 0xea, 0xf8, 0xc7, 0xee, 0x30, 0x8b, 0xd1, 0x35, 0xfe, 0x31, 0x50, 0xef, 0x2e,
+];
+
+/// Connect to a server with the PEM certificate and check the chain that the client sees.
+fn verify_pem(verifier: &CertificateVerifier, name: &str, now: Instant) -> AuthenticationStatus {
+    fixture_init();
+    load_certificate("pem", PEM_CHAIN, PEM_KEY).expect("should load certificate");
+    load_trust_anchors(include_bytes!("../../test-fixture/pem/ca.pem")).unwrap();
+    let mut client = Client::new("server.example", true).expect("should create client");
+    let mut server = Server::new(&["pem"]).expect("should create server");
+    connect(&mut client, &mut server);
+
+    let mut certs = client.peer_certificate().unwrap();
+    verifier.verify_server(&mut certs, name, now)
+}
+
+#[test]
+fn verify_ok() {
+    let verifier = CertificateVerifier::new();
+    assert_eq!(
+        verify_pem(&verifier, "server.example", now()),
+        AuthenticationStatus::Ok
+    );
+    assert_eq!(
+        verify_pem(&verifier, "127.0.0.1", now()),
+        AuthenticationStatus::Ok
+    );
+}
+
+#[test]
+fn verify_bad_name() {
+    assert_eq!(
+        verify_pem(&CertificateVerifier::new(), "other.example", now()),
+        AuthenticationStatus::CertSubjectInvalid
+    );
+}
+
+#[test]
+fn verify_expired() {
+    let later = now() + Duration::from_secs(200 * 365 * 24 * 60 * 60);
+    assert_eq!(
+        verify_pem(&CertificateVerifier::new(), "server.example", later),
+        AuthenticationStatus::CertExpired
+    );
+}
+
+#[test]
+fn verify_pin() {
+    let verifier = CertificateVerifier::new().pin_sha256(CA_PIN).unwrap();
+    assert_eq!(
+        verify_pem(&verifier, "server.example", now()),
+        AuthenticationStatus::Ok
+    );
+
+    let verifier = CertificateVerifier::new().pin_sha256([0; 32]).unwrap();
+    assert_eq!(
+        verify_pem(&verifier, "server.example", now()),
+        AuthenticationStatus::CertMitm
+    );
+    assert!(CertificateVerifier::new().pin_sha256([0; 31]).is_err());
+}
+
+/// A server that asks for a client certificate issued by the PEM test CA.
+fn client_auth_server(required: bool) -> Server {
+    fixture_init();
+    load_certificate("pem", PEM_CHAIN, PEM_KEY).expect("should load certificate");
+    load_trust_anchors(include_bytes!("../../test-fixture/pem/ca.pem")).unwrap();
+    let mut server = Server::new(&["key"]).expect("should create server");
+    server
+        .request_client_certificate(CertificateVerifier::new(), required)
+        .unwrap();
+    server
+}
+
+#[test]
+fn client_certificate() {
+    let mut server = client_auth_server(true);
+    let mut client = Client::new("server.example", true).expect("should create client");
+    client.set_client_certificate("pem").unwrap();
+
+    connect(&mut client, &mut server);
+
+    let mut certs = server.peer_certificate().unwrap();
+    assert_eq!(2, certs.count());
+}
+
+#[test]
+fn client_certificate_selector() {
+    #[derive(Debug)]
+    struct Selector;
+    impl ClientCertificateSelector for Selector {
+        fn select(&self, server_name: &str) -> Option<String> {
+            assert_eq!(server_name, "server.example");
+            Some(String::from("pem"))
+        }
+    }
+
+    let mut server = client_auth_server(true);
+    let mut client = Client::new("server.example", true).expect("should create client");
+    client
+        .set_client_certificate_selector(Rc::new(Selector))
+        .unwrap();
+
+    connect(&mut client, &mut server);
+    assert!(server.peer_certificate().is_some());
+}
+
+#[test]
+fn client_certificate_missing() {
+    let mut server = client_auth_server(true);
+    let mut client = Client::new("server.example", true).expect("should create client");
+    connect_server_fail(&mut client, &mut server);
+}
+
+#[test]
+fn client_certificate_optional() {
+    let mut server = client_auth_server(false);
+    let mut client = Client::new("server.example", true).expect("should create client");
+    connect(&mut client, &mut server);
+    assert!(server.peer_certificate().is_none());
+}
+
+#[test]
+fn client_certificate_untrusted() {
+    let mut server = client_auth_server(true);
+    let mut client = Client::new("server.example", true).expect("should create client");
+    // This certificate is from the database and not issued by the PEM test CA.
+    client.set_client_certificate("key").unwrap();
+    connect_server_fail(&mut client, &mut server);
+}
+
+#[test]
+fn certificate_compression() {
+    fixture_init();
+    load_certificate("big", PEM_BIG_CHAIN, PEM_KEY).expect("should load certificate");
+    for alg in [CertificateCompression::Zlib, CertificateCompression::Brotli] {
+        let mut client = Client::new("server.example", true).expect("should create client");
+        client.set_certificate_compression(&[alg]).unwrap();
+        let mut server = Server::new(&["big"]).expect("should create server");
+        server
+            .set_certificate_compression(&[
+                CertificateCompression::Zlib,
+                CertificateCompression::Brotli,
+            ])
+            .unwrap();
+        connect(&mut client, &mut server);
+
+        let sent = server.certificate_compression().unwrap();
+        assert_eq!(sent.algorithm, alg);
+        assert!(sent.compressed * 2 < sent.uncompressed);
+        assert_eq!(client.certificate_compression(), Some(sent));
+    }
+}
+
+#[test]
+fn certificate_compression_not_negotiated() {
+    fixture_init();
+    load_certificate("big", PEM_BIG_CHAIN, PEM_KEY).expect("should load certificate");
+    let mut client = Client::new("server.example", true).expect("should create client");
+    let mut server = Server::new(&["big"]).expect("should create server");
+    server
+        .set_certificate_compression(&[CertificateCompression::Zlib])
+        .unwrap();
+    connect(&mut client, &mut server);
+
+    assert!(server.certificate_compression().is_none());
+    assert!(client.certificate_compression().is_none());
+}
+
 #[test]
 fn resume() {
     let (_, token) = resumption_setup(Resumption::WithoutZeroRtt);
@@ -75,6 +75,13 @@ pub fn connect_fail(client: &mut SecretAgent, server: &mut SecretAgent) {
     assert!(!server.state().is_connected());
 }
 
+/// In TLS 1.3, a client finishes before the server has seen its certificate,
+/// so only the server fails when it rejects that certificate.
+pub fn connect_server_fail(client: &mut SecretAgent, server: &mut SecretAgent) {
+    handshake(now(), client, server);
+    assert!(!server.state().is_connected());
+}
+
 #[derive(Clone, Copy, Debug)]
 pub enum Resumption {
     WithoutZeroRtt,
@@ -32,6 +32,17 @@ fn hp_test(cipher: Cipher, expected: &[u8]) {
 
     let mask = hp.mask(&[0; 16]).expect("should produce a mask again");
     assert_eq!(mask, expected, "second invocation should be the same");
+
+    let key = hp.key_data().expect("key can be exported");
+    let hp3 = HpKey::import(cipher, key).expect("key can be imported");
+    let mask = hp3.mask(&[0; 16]).expect("imported key produces mask");
+    assert_eq!(mask, expected, "imported key should produce the same mask");
+}
+
+#[test]
+fn import_wrong_length() {
+    fixture_init();
+    assert!(HpKey::import(TLS_AES_128_GCM_SHA256, &[0; 32]).is_err());
 }
 
 #[test]
@@ -0,0 +1,304 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::time::Duration;
+
+use crate::{Error, Header, Res};
+
+/// The default freshness lifetime of an alternative service, i.e. 24 hours.
+const ALT_SVC_DEFAULT_MAX_AGE: Duration = Duration::from_secs(86400);
+
+/// A single alternative service from an `Alt-Svc` header field, see
+/// [RFC 7838](https://www.rfc-editor.org/rfc/rfc7838.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AltService {
+    /// The ALPN protocol identifier, e.g. `h3`.
+    pub alpn: String,
+    /// The alternative host. `None` means the host of the origin.
+    pub host: Option<String>,
+    pub port: u16,
+    pub max_age: Duration,
+    pub persist: bool,
+}
+
+/// A parsed `Alt-Svc` header field value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AltSvc {
+    /// The `clear` value, i.e. all alternative services for the origin are invalidated.
+    Clear,
+    Services(Vec<AltService>),
+}
+
+impl AltSvc {
+    /// Parse an `Alt-Svc` header field value.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidHeader` if the value is malformed.
+    pub fn parse(value: &str) -> Res<Self> {
+        let value = value.trim();
+        if value == "clear" {
+            return Ok(Self::Clear);
+        }
+        let services = split_outside_quotes(value, ',')
+            .into_iter()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(parse_alt_value)
+            .collect::<Res<Vec<_>>>()?;
+        if services.is_empty() {
+            return Err(Error::InvalidHeader);
+        }
+        Ok(Self::Services(services))
+    }
+
+    /// Find and parse all `alt-svc` headers in `headers`. Returns `None` if there is no such
+    /// header. Multiple header fields are combined as if they were one comma-separated list.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidHeader` if a value is malformed.
+    pub fn from_headers(headers: &[Header]) -> Option<Res<Self>> {
+        let mut values = headers
+            .iter()
+            .filter(|h| h.name().eq_ignore_ascii_case("alt-svc"))
+            .map(Header::value)
+            .peekable();
+        values.peek()?;
+        let mut services = Vec::new();
+        for v in values {
+            match Self::parse(v) {
+                Ok(Self::Clear) => return Some(Ok(Self::Clear)),
+                Ok(Self::Services(s)) => services.extend(s),
+                Err(e) => return Some(Err(e)),
+            }
+        }
+        Some(Ok(Self::Services(services)))
+    }
+}
+
+/// Split `value` on `sep`, ignoring separators inside quoted strings.
+pub(crate) fn split_outside_quotes(value: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if escaped {
+            escaped = false;
+        } else if in_quotes && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == sep && !in_quotes {
+            parts.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    parts.push(&value[start..]);
+    parts
+}
+
+pub(crate) fn is_token(s: &str) -> bool {
+    !s.is_empty()
+        && s.bytes().all(|b| {
+            b.is_ascii_alphanumeric()
+                || matches!(
+                    b,
+                    b'!' | b'#'
+                        | b'$'
+                        | b'%'
+                        | b'&'
+                        | b'\''
+                        | b'*'
+                        | b'+'
+                        | b'-'
+                        | b'.'
+                        | b'^'
+                        | b'_'
+                        | b'`'
+                        | b'|'
+                        | b'~'
+                )
+        })
+}
+
+/// Decode a `quoted-string` or a `token`.
+pub(crate) fn unquote(s: &str) -> Res<String> {
+    if let Some(inner) = s.strip_prefix('"') {
+        let inner = inner.strip_suffix('"').ok_or(Error::InvalidHeader)?;
+        let mut out = String::with_capacity(inner.len());
+        let mut chars = inner.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => out.push(chars.next().ok_or(Error::InvalidHeader)?),
+                '"' => return Err(Error::InvalidHeader),
+                c => out.push(c),
+            }
+        }
+        Ok(out)
+    } else if is_token(s) {
+        Ok(s.to_string())
+    } else {
+        Err(Error::InvalidHeader)
+    }
+}
+
+/// The protocol id is a percent-encoded ALPN identifier.
+fn percent_decode(s: &str) -> Res<String> {
+    let bytes = s.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = s.get(i + 1..i + 3).ok_or(Error::InvalidHeader)?;
+            out.push(u8::from_str_radix(hex, 16).map_err(|_| Error::InvalidHeader)?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).map_err(|_| Error::InvalidHeader)
+}
+
+fn parse_alt_authority(s: &str) -> Res<(Option<String>, u16)> {
+    let authority = unquote(s)?;
+    let (host, port) = authority.rsplit_once(':').ok_or(Error::InvalidHeader)?;
+    let port = port.parse::<u16>().map_err(|_| Error::InvalidHeader)?;
+    let host = if host.is_empty() {
+        None
+    } else {
+        Some(host.to_string())
+    };
+    Ok((host, port))
+}
+
+fn parse_alt_value(value: &str) -> Res<AltService> {
+    let mut parts = split_outside_quotes(value, ';').into_iter().map(str::trim);
+    let alternative = parts.next().ok_or(Error::InvalidHeader)?;
+    let (protocol_id, authority) = alternative.split_once('=').ok_or(Error::InvalidHeader)?;
+    if !is_token(protocol_id) {
+        return Err(Error::InvalidHeader);
+    }
+    let (host, port) = parse_alt_authority(authority)?;
+    let mut service = AltService {
+        alpn: percent_decode(protocol_id)?,
+        host,
+        port,
+        max_age: ALT_SVC_DEFAULT_MAX_AGE,
+        persist: false,
+    };
+
+    for param in parts.filter(|p| !p.is_empty()) {
+        let (name, value) = param.split_once('=').ok_or(Error::InvalidHeader)?;
+        let name = name.trim();
+        if !is_token(name) {
+            return Err(Error::InvalidHeader);
+        }
+        let value = unquote(value.trim())?;
+        // Unknown parameters are ignored.
+        if name.eq_ignore_ascii_case("ma") {
+            let secs = value.parse::<u64>().map_err(|_| Error::InvalidHeader)?;
+            service.max_age = Duration::from_secs(secs);
+        } else if name.eq_ignore_ascii_case("persist") {
+            // Only the value 1 is defined, all other values are ignored.
+            service.persist = value == "1";
+        }
+    }
+    Ok(service)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{AltService, AltSvc, ALT_SVC_DEFAULT_MAX_AGE};
+    use crate::{Error, Header};
+
+    #[test]
+    fn clear() {
+        assert_eq!(AltSvc::parse("clear"), Ok(AltSvc::Clear));
+    }
+
+    #[test]
+    fn single() {
+        assert_eq!(
+            AltSvc::parse("h3=\":443\""),
+            Ok(AltSvc::Services(vec![AltService {
+                alpn: "h3".to_string(),
+                host: None,
+                port: 443,
+                max_age: ALT_SVC_DEFAULT_MAX_AGE,
+                persist: false,
+            }]))
+        );
+    }
+
+    #[test]
+    fn multiple_with_parameters() {
+        assert_eq!(
+            AltSvc::parse("h3=\"alt.example.com:8443\"; ma=3600; persist=1, h3-29=\":443\";ma=60"),
+            Ok(AltSvc::Services(vec![
+                AltService {
+                    alpn: "h3".to_string(),
+                    host: Some("alt.example.com".to_string()),
+                    port: 8443,
+                    max_age: Duration::from_secs(3600),
+                    persist: true,
+                },
+                AltService {
+                    alpn: "h3-29".to_string(),
+                    host: None,
+                    port: 443,
+                    max_age: Duration::from_secs(60),
+                    persist: false,
+                }
+            ]))
+        );
+    }
+
+    #[test]
+    fn percent_encoded_protocol_and_unknown_parameter() {
+        let AltSvc::Services(s) = AltSvc::parse("w%3Dx%3Ay=\":80\"; foo=\"a,b\"").unwrap() else {
+            panic!("expected services");
+        };
+        assert_eq!(s.len(), 1);
+        assert_eq!(s[0].alpn, "w=x:y");
+        assert_eq!(s[0].port, 80);
+    }
+
+    #[test]
+    fn malformed() {
+        for v in [
+            "",
+            "h3",
+            "h3=:443",
+            "h3=\"443\"",
+            "h3=\":99999\"",
+            "h3=\":443\"; ma=abc",
+            "h3=\":443",
+            "h 3=\":443\"",
+        ] {
+            assert_eq!(AltSvc::parse(v), Err(Error::InvalidHeader), "{v}");
+        }
+    }
+
+    #[test]
+    fn from_headers() {
+        let headers = [
+            Header::new(":status", "200"),
+            Header::new("alt-svc", "h3=\":443\""),
+            Header::new("alt-svc", "h3-29=\":8443\""),
+        ];
+        let AltSvc::Services(s) = AltSvc::from_headers(&headers).unwrap().unwrap() else {
+            panic!("expected services");
+        };
+        assert_eq!(s.len(), 2);
+        assert_eq!(s[1].port, 8443);
+        assert!(AltSvc::from_headers(&[Header::new(":status", "200")]).is_none());
+    }
+}
@@ -9,14 +9,16 @@
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
 use neqo_common::{event::Provider as EventProvider, Header};
-use neqo_crypto::ResumptionToken;
+use neqo_crypto::{AuthenticationStatus, ResumptionToken};
 use neqo_transport::{AppError, StreamId, StreamType};
 
 use crate::{
     connection::Http3State,
-    features::extended_connect::{ExtendedConnectEvents, ExtendedConnectType, SessionCloseReason},
+    features::extended_connect::{
+        ExtendedConnectEvents, ExtendedConnectType, SessionCloseReason, SessionLimit,
+    },
     settings::HSettingType,
-    CloseType, Http3StreamInfo, HttpRecvStreamEvents, RecvStreamEvents, SendStreamEvents,
+    CloseType, Http3StreamInfo, HttpRecvStreamEvents, Link, RecvStreamEvents, SendStreamEvents,
 };
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -40,6 +42,22 @@ pub enum WebTransportEvent {
         session_id: StreamId,
         datagram: Vec<u8>,
     },
+    /// The session cannot open more streams of a type, or send more data, until the peer
+    /// raises its session limit.
+    SessionBlocked {
+        session_id: StreamId,
+        limit: SessionLimit,
+    },
+    /// The peer raised a session limit that was blocking the session.
+    SessionUnblocked {
+        session_id: StreamId,
+        limit: SessionLimit,
+    },
+    /// The server asked for the session to be wound down, or the connection is going away.
+    /// The session still works, but the application should move to a new session.
+    SessionDraining {
+        session_id: StreamId,
+    },
 }
 
 #[derive(Debug, PartialEq, Eq, Clone)]
@@ -51,6 +69,20 @@ pub enum Http3ClientEvent {
         interim: bool,
         fin: bool,
     },
+    /// An informational (1xx) response is received, e.g. 103 Early Hints. This event is only
+    /// used if `Http3Parameters::interim_response_events` is enabled.
+    InterimResponse {
+        stream_id: StreamId,
+        status: u16,
+        headers: Vec<Header>,
+        /// The parsed `link` headers of the response.
+        links: Vec<Link>,
+    },
+    /// Response trailers are received. They are received after all response data.
+    Trailers {
+        stream_id: StreamId,
+        trailers: Vec<Header>,
+    },
     /// A stream can accept new data.
     DataWritable { stream_id: StreamId },
     /// New bytes available for reading.
@@ -94,12 +126,18 @@ pub enum Http3ClientEvent {
     /// name `public_name` needs to be authenticated in order to get
     /// an updated ECH configuration.
     EchFallbackAuthenticationNeeded { public_name: String },
+    /// The server certificate was checked by the verifier from
+    /// `Http3Client::set_certificate_verifier`.
+    CertificateVerified { status: AuthenticationStatus },
     /// A new resumption token.
     ResumptionToken(ResumptionToken),
     /// Zero Rtt has been rejected.
     ZeroRttRejected,
     /// Client has received a GOAWAY frame
     GoawayReceived,
+    /// Client has received an ORIGIN frame that added `origins` to the origin set of the
+    /// connection.
+    OriginsReceived { origins: Vec<String> },
     /// Connection state change.
     StateChange(Http3State),
     /// WebTransport events
@@ -109,6 +147,7 @@ pub enum Http3ClientEvent {
 #[derive(Debug, Default, Clone)]
 pub struct Http3ClientEvents {
     events: Rc<RefCell<VecDeque<Http3ClientEvent>>>,
+    interim_response_events: bool,
 }
 
 impl RecvStreamEvents for Http3ClientEvents {
@@ -155,6 +194,22 @@ impl HttpRecvStreamEvents for Http3ClientEvents {
         interim: bool,
         fin: bool,
     ) {
+        if interim && self.interim_response_events {
+            // The status has been checked by `is_interim` already.
+            let status = headers
+                .iter()
+                .find(|h| h.name() == ":status")
+                .and_then(|h| h.value().parse().ok())
+                .unwrap_or_default();
+            let links = Link::from_headers(&headers);
+            self.insert(Http3ClientEvent::InterimResponse {
+                stream_id: stream_info.stream_id(),
+                status,
+                headers,
+                links,
+            });
+            return;
+        }
         self.insert(Http3ClientEvent::HeaderReady {
             stream_id: stream_info.stream_id(),
             headers,
@@ -162,6 +217,14 @@ impl HttpRecvStreamEvents for Http3ClientEvents {
             fin,
         });
     }
+
+    /// Add a new `Trailers` event.
+    fn trailers_ready(&self, stream_info: Http3StreamInfo, trailers: Vec<Header>) {
+        self.insert(Http3ClientEvent::Trailers {
+            stream_id: stream_info.stream_id(),
+            trailers,
+        });
+    }
 }
 
 impl SendStreamEvents for Http3ClientEvents {
@@ -237,9 +300,34 @@ impl ExtendedConnectEvents for Http3ClientEvents {
             },
         ));
     }
+
+    fn session_blocked(&self, session_id: StreamId, limit: SessionLimit) {
+        self.insert(Http3ClientEvent::WebTransport(
+            WebTransportEvent::SessionBlocked { session_id, limit },
+        ));
+    }
+
+    fn session_unblocked(&self, session_id: StreamId, limit: SessionLimit) {
+        self.insert(Http3ClientEvent::WebTransport(
+            WebTransportEvent::SessionUnblocked { session_id, limit },
+        ));
+    }
+
+    fn session_draining(&self, session_id: StreamId) {
+        self.insert(Http3ClientEvent::WebTransport(
+            WebTransportEvent::SessionDraining { session_id },
+        ));
+    }
 }
 
 impl Http3ClientEvents {
+    pub(crate) fn new(interim_response_events: bool) -> Self {
+        Self {
+            events: Rc::default(),
+            interim_response_events,
+        }
+    }
+
     pub fn push_promise(&self, push_id: u64, request_stream_id: StreamId, headers: Vec<Header>) {
         self.insert(Http3ClientEvent::PushPromise {
             push_id,
@@ -275,6 +363,10 @@ impl Http3ClientEvents {
         self.insert(Http3ClientEvent::EchFallbackAuthenticationNeeded { public_name });
     }
 
+    pub(crate) fn certificate_verified(&self, status: AuthenticationStatus) {
+        self.insert(Http3ClientEvent::CertificateVerified { status });
+    }
+
     /// Add a new resumption token event.
     pub(crate) fn resumption_token(&self, token: ResumptionToken) {
         self.insert(Http3ClientEvent::ResumptionToken(token));
@@ -291,6 +383,11 @@ impl Http3ClientEvents {
         self.insert(Http3ClientEvent::GoawayReceived);
     }
 
+    /// Add a new `OriginsReceived` event.
+    pub(crate) fn origins_received(&self, origins: Vec<String>) {
+        self.insert(Http3ClientEvent::OriginsReceived { origins });
+    }
+
     pub fn insert(&self, event: Http3ClientEvent) {
         self.events.borrow_mut().push_back(event);
     }
@@ -322,6 +419,8 @@ impl Http3ClientEvents {
         self.remove(|evt| {
             matches!(evt,
                 Http3ClientEvent::HeaderReady { stream_id: x, .. }
+                | Http3ClientEvent::InterimResponse { stream_id: x, .. }
+                | Http3ClientEvent::Trailers { stream_id: x, .. }
                 | Http3ClientEvent::DataReadable { stream_id: x }
                 | Http3ClientEvent::PushPromise { request_stream_id: x, .. }
                 | Http3ClientEvent::Reset { stream_id: x, .. } if *x == stream_id)
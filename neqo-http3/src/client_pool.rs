@@ -0,0 +1,777 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::{
+    cell::RefCell,
+    cmp::min,
+    collections::{HashMap, VecDeque},
+    fmt::{self, Display},
+    mem,
+    net::SocketAddr,
+    rc::Rc,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{event::Provider as EventProvider, qdebug, qinfo, Datagram, Header};
+use neqo_crypto::{AuthenticationStatus, ResumptionToken};
+use neqo_transport::{AppError, ConnectionIdGenerator, Output, StreamId};
+use url::Url;
+
+use crate::{Error, Http3Client, Http3ClientEvent, Http3Parameters, Http3State, Priority, Res};
+
+const MAX_CONNECTIONS_DEFAULT: usize = 16;
+const MAX_RETRIES_DEFAULT: usize = 1;
+
+/// Methods that are idempotent according to RFC 9110, Section 9.2.2. Only requests using one of
+/// these methods are retried automatically or sent in 0-RTT.
+const IDEMPOTENT_METHODS: &[&str] = &["GET", "HEAD", "OPTIONS", "TRACE", "PUT", "DELETE"];
+
+/// The pool uses this to find the addresses for a new connection.
+pub trait AddressResolver {
+    /// Returns the local and the remote address that a new connection to `host` and `port`
+    /// should use, or `None` if the host cannot be resolved.
+    fn resolve(&mut self, host: &str, port: u16) -> Option<(SocketAddr, SocketAddr)>;
+}
+
+#[derive(Debug, Clone)]
+pub struct Http3ClientPoolParameters {
+    http3_parameters: Http3Parameters,
+    max_connections: usize,
+    max_retries: usize,
+}
+
+impl Default for Http3ClientPoolParameters {
+    fn default() -> Self {
+        Self {
+            http3_parameters: Http3Parameters::default(),
+            max_connections: MAX_CONNECTIONS_DEFAULT,
+            max_retries: MAX_RETRIES_DEFAULT,
+        }
+    }
+}
+
+impl Http3ClientPoolParameters {
+    /// The parameters used for each new `Http3Client`.
+    #[must_use]
+    pub fn http3_parameters(mut self, http3_parameters: Http3Parameters) -> Self {
+        self.http3_parameters = http3_parameters;
+        self
+    }
+
+    #[must_use]
+    pub fn get_http3_parameters(&self) -> &Http3Parameters {
+        &self.http3_parameters
+    }
+
+    /// The maximum number of connections, including connections that are going away.
+    #[must_use]
+    pub fn max_connections(mut self, max_connections: usize) -> Self {
+        self.max_connections = max_connections;
+        self
+    }
+
+    #[must_use]
+    pub fn get_max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    /// How often an idempotent request that has been rejected by the server is retried.
+    #[must_use]
+    pub fn max_retries(mut self, max_retries: usize) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    #[must_use]
+    pub fn get_max_retries(&self) -> usize {
+        self.max_retries
+    }
+}
+
+/// Identifies a request made using `Http3ClientPool`. The identifier stays the same if the
+/// request is retried on a different connection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct PoolRequestId(u64);
+
+impl Display for PoolRequestId {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub enum Http3ClientPoolEvent {
+    /// The server certificate of a new connection to `authority` needs to be checked, see
+    /// `Http3ClientPool::authenticated`.
+    AuthenticationNeeded { authority: String },
+    /// Response headers are received.
+    HeaderReady {
+        request_id: PoolRequestId,
+        headers: Vec<Header>,
+        interim: bool,
+        fin: bool,
+    },
+    /// New bytes available for reading.
+    DataReadable { request_id: PoolRequestId },
+    /// The request failed, either because the peer reset it or because its connection closed.
+    Reset {
+        request_id: PoolRequestId,
+        error: AppError,
+        local: bool,
+    },
+    /// The request was rejected by the server and has been sent again.
+    Retried { request_id: PoolRequestId },
+    /// The server for `authority` has sent a GOAWAY frame. New requests will use a new connection.
+    GoawayReceived { authority: String },
+    /// A connection has been closed.
+    ConnectionClosed {
+        authority: String,
+        state: Http3State,
+    },
+}
+
+#[derive(Debug)]
+struct PoolRequest {
+    host: String,
+    port: u16,
+    method: String,
+    url: Url,
+    headers: Vec<Header>,
+    priority: Priority,
+    body: Vec<u8>,
+    body_offset: usize,
+    send_closed: bool,
+    /// The connection and the stream the request has been sent on. This is `None` while the
+    /// request is queued.
+    stream: Option<(u64, StreamId)>,
+    retries: usize,
+}
+
+impl PoolRequest {
+    fn idempotent(&self) -> bool {
+        IDEMPOTENT_METHODS.contains(&self.method.as_str())
+    }
+
+    fn reset_for_resend(&mut self) {
+        self.body_offset = 0;
+        self.send_closed = false;
+        self.stream = None;
+    }
+
+    /// Send as much of the body as possible and close the sending side when it is done.
+    fn send_body(&mut self, client: &mut Http3Client, stream_id: StreamId) -> Res<()> {
+        while self.body_offset < self.body.len() {
+            let sent = client.send_data(stream_id, &self.body[self.body_offset..])?;
+            if sent == 0 {
+                return Ok(());
+            }
+            self.body_offset += sent;
+        }
+        if !self.send_closed {
+            self.send_closed = true;
+            client.stream_close_send(stream_id)?;
+        }
+        Ok(())
+    }
+}
+
+struct PoolConnection {
+    id: u64,
+    authority: String,
+    remote_addr: SocketAddr,
+    client: Http3Client,
+    queued: VecDeque<PoolRequestId>,
+}
+
+impl PoolConnection {
+    /// Whether new requests may be added to this connection.
+    fn usable(&self) -> bool {
+        matches!(
+            self.client.state(),
+            Http3State::Initializing | Http3State::ZeroRtt | Http3State::Connected
+        )
+    }
+}
+
+/// # A pool of HTTP/3 client connections
+///
+/// `Http3ClientPool` takes requests for arbitrary `https` URLs and sends them on an `Http3Client`
+/// for the authority of the URL, opening new connections when needed. It:
+/// - queues requests until a connection is able to send them,
+/// - sends idempotent requests that were rejected by the server, e.g. after a GOAWAY frame, again
+///   on another connection,
+/// - resends requests that were sent in 0-RTT if 0-RTT was rejected,
+/// - caches resumption tokens per authority and uses them for new connections to enable 0-RTT.
+///
+/// Incoming datagrams are given to all connections with the remote address the datagram was
+/// received from. Server push is not supported, push promises are canceled.
+pub struct Http3ClientPool {
+    params: Http3ClientPoolParameters,
+    cid_manager: Rc<RefCell<dyn ConnectionIdGenerator>>,
+    resolver: Box<dyn AddressResolver>,
+    connections: Vec<PoolConnection>,
+    requests: HashMap<PoolRequestId, PoolRequest>,
+    streams: HashMap<(u64, StreamId), PoolRequestId>,
+    resumption_tokens: HashMap<String, ResumptionToken>,
+    events: VecDeque<Http3ClientPoolEvent>,
+    next_request_id: u64,
+    next_connection_id: u64,
+    next_output: usize,
+}
+
+impl Display for Http3ClientPool {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Http3 client pool")
+    }
+}
+
+impl Http3ClientPool {
+    #[must_use]
+    pub fn new(
+        params: Http3ClientPoolParameters,
+        cid_manager: Rc<RefCell<dyn ConnectionIdGenerator>>,
+        resolver: Box<dyn AddressResolver>,
+    ) -> Self {
+        Self {
+            params,
+            cid_manager,
+            resolver,
+            connections: Vec::new(),
+            requests: HashMap::new(),
+            streams: HashMap::new(),
+            resumption_tokens: HashMap::new(),
+            events: VecDeque::new(),
+            next_request_id: 0,
+            next_connection_id: 0,
+            next_output: 0,
+        }
+    }
+
+    fn host_port(url: &Url) -> Res<(String, u16)> {
+        if url.scheme() != "https" {
+            return Err(Error::InvalidRequestTarget);
+        }
+        let host = url.host_str().ok_or(Error::InvalidRequestTarget)?;
+        let port = url
+            .port_or_known_default()
+            .ok_or(Error::InvalidRequestTarget)?;
+        Ok((host.to_string(), port))
+    }
+
+    /// Make a request. The request is sent as soon as a connection for the authority of `url` is
+    /// able to send it. `body` is sent after the headers and the sending side of the request is
+    /// closed afterwards.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidRequestTarget` if `url` is not an `https` URL, `Unavailable` if the host cannot be
+    /// resolved or the connection limit is reached, or any error from creating a connection.
+    pub fn fetch(
+        &mut self,
+        now: Instant,
+        method: &str,
+        url: &Url,
+        headers: &[Header],
+        body: &[u8],
+        priority: Priority,
+    ) -> Res<PoolRequestId> {
+        let (host, port) = Self::host_port(url)?;
+        let request_id = PoolRequestId(self.next_request_id);
+        let request = PoolRequest {
+            host,
+            port,
+            method: method.to_string(),
+            url: url.clone(),
+            headers: headers.to_vec(),
+            priority,
+            body: body.to_vec(),
+            body_offset: 0,
+            send_closed: false,
+            stream: None,
+            retries: 0,
+        };
+        let idx = self.connection_for(&request.host, request.port, now)?;
+        qinfo!([self], "fetch {} {} as request {}", method, url, request_id);
+        self.next_request_id += 1;
+        self.connections[idx].queued.push_back(request_id);
+        self.requests.insert(request_id, request);
+        self.dispatch(now);
+        Ok(request_id)
+    }
+
+    /// Find a connection that can take a new request for `host` and `port`, or open a new one.
+    fn connection_for(&mut self, host: &str, port: u16, now: Instant) -> Res<usize> {
+        let authority = format!("{host}:{port}");
+        if let Some(idx) = self
+            .connections
+            .iter()
+            .position(|c| c.authority == authority && c.usable())
+        {
+            return Ok(idx);
+        }
+        if self.connections.len() >= self.params.max_connections {
+            return Err(Error::Unavailable);
+        }
+
+        let (local_addr, remote_addr) = self
+            .resolver
+            .resolve(host, port)
+            .ok_or(Error::Unavailable)?;
+        let cid_manager = Rc::clone(&self.cid_manager);
+        let http3_parameters = self.params.http3_parameters.clone();
+        let new_client = || {
+            Http3Client::new(
+                host,
+                Rc::clone(&cid_manager),
+                local_addr,
+                remote_addr,
+                http3_parameters.clone(),
+                now,
+            )
+        };
+        let mut client = new_client()?;
+        if let Some(token) = self.resumption_tokens.remove(&authority) {
+            if client.enable_resumption(now, &token).is_err() {
+                qdebug!([self], "Resumption token for {} rejected", authority);
+                client = new_client()?;
+            }
+        }
+        qinfo!(
+            [self],
+            "New connection {} for {}",
+            self.next_connection_id,
+            authority
+        );
+        self.connections.push(PoolConnection {
+            id: self.next_connection_id,
+            authority,
+            remote_addr,
+            client,
+            queued: VecDeque::new(),
+        });
+        self.next_connection_id += 1;
+        Ok(self.connections.len() - 1)
+    }
+
+    /// Queue a request that has been rejected on a new connection.
+    fn requeue(&mut self, request_id: PoolRequestId, now: Instant) -> Res<()> {
+        let (host, port) = self
+            .requests
+            .get(&request_id)
+            .map(|r| (r.host.clone(), r.port))
+            .ok_or(Error::Internal)?;
+        let idx = self.connection_for(&host, port, now)?;
+        self.connections[idx].queued.push_back(request_id);
+        Ok(())
+    }
+
+    fn fail_request(&mut self, request_id: PoolRequestId, error: AppError, local: bool) {
+        if let Some(request) = self.requests.remove(&request_id) {
+            if let Some(stream) = request.stream {
+                self.streams.remove(&stream);
+            }
+            self.events.push_back(Http3ClientPoolEvent::Reset {
+                request_id,
+                error,
+                local,
+            });
+        }
+    }
+
+    fn request_done(&mut self, request_id: PoolRequestId) {
+        if let Some(stream) = self.requests.remove(&request_id).and_then(|r| r.stream) {
+            self.streams.remove(&stream);
+        }
+    }
+
+    /// Send queued requests on connections that are able to send them.
+    fn dispatch(&mut self, now: Instant) {
+        let mut failed = Vec::new();
+        for conn in &mut self.connections {
+            let state = conn.client.state();
+            if !matches!(state, Http3State::ZeroRtt | Http3State::Connected) {
+                continue;
+            }
+            while let Some(&request_id) = conn.queued.front() {
+                let Some(request) = self.requests.get_mut(&request_id) else {
+                    conn.queued.pop_front();
+                    continue;
+                };
+                // Requests that are not idempotent are not safe to send in 0-RTT.
+                if state == Http3State::ZeroRtt && !request.idempotent() {
+                    break;
+                }
+                match conn.client.fetch(
+                    now,
+                    &request.method,
+                    &request.url,
+                    &request.headers,
+                    request.priority,
+                ) {
+                    Ok(stream_id) => {
+                        conn.queued.pop_front();
+                        request.stream = Some((conn.id, stream_id));
+                        self.streams.insert((conn.id, stream_id), request_id);
+                        if let Err(e) = request.send_body(&mut conn.client, stream_id) {
+                            failed.push((request_id, e.code()));
+                        }
+                    }
+                    Err(Error::StreamLimitError | Error::AlreadyClosed) => break,
+                    Err(e) => {
+                        conn.queued.pop_front();
+                        failed.push((request_id, e.code()));
+                    }
+                }
+            }
+        }
+        for (request_id, error) in failed {
+            self.fail_request(request_id, error, true);
+        }
+    }
+
+    fn handle_client_event(&mut self, idx: usize, event: Http3ClientEvent, now: Instant) {
+        let conn_id = self.connections[idx].id;
+        let request_for =
+            |s: &Self, stream_id: StreamId| s.streams.get(&(conn_id, stream_id)).copied();
+        match event {
+            Http3ClientEvent::AuthenticationNeeded => {
+                self.events
+                    .push_back(Http3ClientPoolEvent::AuthenticationNeeded {
+                        authority: self.connections[idx].authority.clone(),
+                    });
+            }
+            Http3ClientEvent::HeaderReady {
+                stream_id,
+                headers,
+                interim,
+                fin,
+            } => {
+                if let Some(request_id) = request_for(self, stream_id) {
+                    self.events.push_back(Http3ClientPoolEvent::HeaderReady {
+                        request_id,
+                        headers,
+                        interim,
+                        fin,
+                    });
+                    if fin {
+                        self.request_done(request_id);
+                    }
+                }
+            }
+            Http3ClientEvent::DataReadable { stream_id } => {
+                if let Some(request_id) = request_for(self, stream_id) {
+                    self.events
+                        .push_back(Http3ClientPoolEvent::DataReadable { request_id });
+                }
+            }
+            Http3ClientEvent::DataWritable { stream_id } => {
+                if let Some(request_id) = request_for(self, stream_id) {
+                    let client = &mut self.connections[idx].client;
+                    let res = self
+                        .requests
+                        .get_mut(&request_id)
+                        .map_or(Ok(()), |r| r.send_body(client, stream_id));
+                    if let Err(e) = res {
+                        self.fail_request(request_id, e.code(), true);
+                    }
+                }
+            }
+            Http3ClientEvent::Reset {
+                stream_id,
+                error,
+                local,
+            } => {
+                if let Some(request_id) = request_for(self, stream_id) {
+                    self.handle_reset(request_id, error, local, now);
+                }
+            }
+            Http3ClientEvent::ResumptionToken(token) => {
+                self.resumption_tokens
+                    .insert(self.connections[idx].authority.clone(), token);
+            }
+            Http3ClientEvent::GoawayReceived => {
+                let authority = self.connections[idx].authority.clone();
+                qinfo!([self], "GOAWAY received from {}", authority);
+                let queued = mem::take(&mut self.connections[idx].queued);
+                for request_id in queued {
+                    if self.requeue(request_id, now).is_err() {
+                        self.fail_request(request_id, Error::HttpRequestRejected.code(), true);
+                    }
+                }
+                self.events
+                    .push_back(Http3ClientPoolEvent::GoawayReceived { authority });
+            }
+            Http3ClientEvent::ZeroRttRejected => {
+                // All streams of the connection are gone, send the requests again.
+                let mut resend: Vec<(StreamId, PoolRequestId)> = self
+                    .streams
+                    .iter()
+                    .filter(|((c, _), _)| *c == conn_id)
+                    .map(|((_, s), r)| (*s, *r))
+                    .collect();
+                resend.sort();
+                for (stream_id, request_id) in resend.into_iter().rev() {
+                    self.streams.remove(&(conn_id, stream_id));
+                    if let Some(r) = self.requests.get_mut(&request_id) {
+                        r.reset_for_resend();
+                        self.connections[idx].queued.push_front(request_id);
+                    }
+                }
+            }
+            Http3ClientEvent::PushPromise { push_id, .. } => {
+                // Server push is not supported.
+                mem::drop(self.connections[idx].client.cancel_push(push_id));
+            }
+            _ => {}
+        }
+    }
+
+    fn handle_reset(
+        &mut self,
+        request_id: PoolRequestId,
+        error: AppError,
+        local: bool,
+        now: Instant,
+    ) {
+        let retry = self.requests.get(&request_id).map_or(false, |r| {
+            !local
+                && error == Error::HttpRequestRejected.code()
+                && r.idempotent()
+                && r.retries < self.params.max_retries
+        });
+        if !retry {
+            self.fail_request(request_id, error, local);
+            return;
+        }
+
+        if let Some(r) = self.requests.get_mut(&request_id) {
+            if let Some(stream) = r.stream {
+                self.streams.remove(&stream);
+            }
+            r.reset_for_resend();
+            r.retries += 1;
+        }
+        if self.requeue(request_id, now).is_ok() {
+            qinfo!([self], "Request {} rejected, retrying", request_id);
+            self.events
+                .push_back(Http3ClientPoolEvent::Retried { request_id });
+        } else {
+            self.fail_request(request_id, error, local);
+        }
+    }
+
+    /// Remove closed connections and fail their requests.
+    fn remove_closed_connections(&mut self) {
+        let mut i = 0;
+        while i < self.connections.len() {
+            let state = self.connections[i].client.state();
+            if !matches!(state, Http3State::Closed(..)) {
+                i += 1;
+                continue;
+            }
+            let conn = self.connections.remove(i);
+            qinfo!(
+                [self],
+                "Connection {} to {} closed",
+                conn.id,
+                conn.authority
+            );
+            let mut failed: Vec<PoolRequestId> = self
+                .streams
+                .iter()
+                .filter(|((c, _), _)| *c == conn.id)
+                .map(|(_, r)| *r)
+                .chain(conn.queued.iter().copied())
+                .collect();
+            failed.sort();
+            for request_id in failed {
+                self.fail_request(request_id, Error::HttpRequestCancelled.code(), true);
+            }
+            self.events
+                .push_back(Http3ClientPoolEvent::ConnectionClosed {
+                    authority: conn.authority,
+                    state,
+                });
+        }
+    }
+
+    fn process_events(&mut self, now: Instant) {
+        for idx in 0..self.connections.len() {
+            while let Some(event) = self.connections[idx].client.next_event() {
+                qdebug!(
+                    [self],
+                    "Connection {} event {:?}",
+                    self.connections[idx].id,
+                    event
+                );
+                self.handle_client_event(idx, event, now);
+            }
+        }
+        self.remove_closed_connections();
+        self.dispatch(now);
+    }
+
+    /// Give a received datagram to the connections with the datagram's source address.
+    pub fn process_input(&mut self, dgram: &Datagram, now: Instant) {
+        for conn in self
+            .connections
+            .iter_mut()
+            .filter(|c| c.remote_addr == dgram.source())
+        {
+            conn.client.process_input(dgram, now);
+        }
+        self.process_events(now);
+    }
+
+    /// Produce a datagram to send from one of the connections, or the time after which this
+    /// function must be called again. Connections take turns sending datagrams.
+    pub fn process_output(&mut self, now: Instant) -> Output {
+        self.process_events(now);
+        let mut callback: Option<Duration> = None;
+        let count = self.connections.len();
+        for k in 0..count {
+            let idx = (self.next_output + k) % count;
+            match self.connections[idx].client.process_output(now) {
+                Output::Datagram(d) => {
+                    self.next_output = (idx + 1) % count;
+                    return Output::Datagram(d);
+                }
+                Output::Callback(t) => callback = Some(callback.map_or(t, |c| min(c, t))),
+                Output::None => {}
+            }
+        }
+        // Connections may have closed or produced events.
+        self.process_events(now);
+        callback.map_or(Output::None, Output::Callback)
+    }
+
+    /// A combination of `process_input` and `process_output`.
+    pub fn process(&mut self, dgram: Option<&Datagram>, now: Instant) -> Output {
+        if let Some(d) = dgram {
+            self.process_input(d, now);
+        }
+        self.process_output(now)
+    }
+
+    /// Inform the new connections to `authority` about the result of the certificate check.
+    pub fn authenticated(&mut self, authority: &str, status: AuthenticationStatus, now: Instant) {
+        for conn in self
+            .connections
+            .iter_mut()
+            .filter(|c| c.authority == authority && c.client.state() == Http3State::Initializing)
+        {
+            conn.client.authenticated(status, now);
+        }
+    }
+
+    /// Read response data of a request.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the request does not exist or has not been sent yet, or any error
+    /// from `Http3Client::read_data`.
+    pub fn read_data(
+        &mut self,
+        now: Instant,
+        request_id: PoolRequestId,
+        buf: &mut [u8],
+    ) -> Res<(usize, bool)> {
+        let (conn_id, stream_id) = self
+            .requests
+            .get(&request_id)
+            .and_then(|r| r.stream)
+            .ok_or(Error::InvalidStreamId)?;
+        let conn = self
+            .connections
+            .iter_mut()
+            .find(|c| c.id == conn_id)
+            .ok_or(Error::InvalidStreamId)?;
+        let (amount, fin) = conn.client.read_data(now, stream_id, buf)?;
+        if fin {
+            self.request_done(request_id);
+        }
+        Ok((amount, fin))
+    }
+
+    /// Cancel a request.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the request does not exist.
+    pub fn cancel_fetch(&mut self, request_id: PoolRequestId, error: AppError) -> Res<()> {
+        let request = self
+            .requests
+            .remove(&request_id)
+            .ok_or(Error::InvalidStreamId)?;
+        if let Some((conn_id, stream_id)) = request.stream {
+            self.streams.remove(&(conn_id, stream_id));
+            if let Some(conn) = self.connections.iter_mut().find(|c| c.id == conn_id) {
+                // The stream may be closed already.
+                mem::drop(conn.client.cancel_fetch(stream_id, error));
+            }
+        } else {
+            for conn in &mut self.connections {
+                conn.queued.retain(|r| *r != request_id);
+            }
+        }
+        Ok(())
+    }
+
+    /// Close all connections.
+    pub fn close(&mut self, now: Instant, error: AppError, msg: &str) {
+        for conn in &mut self.connections {
+            conn.client.close(now, error, msg);
+        }
+    }
+
+    /// Add a resumption token for `authority` (in `host:port` form), e.g. one saved from a
+    /// previous run. The token is used for the next new connection to `authority`.
+    pub fn add_resumption_token(&mut self, authority: impl Into<String>, token: ResumptionToken) {
+        self.resumption_tokens.insert(authority.into(), token);
+    }
+
+    /// The latest unused resumption token for `authority`.
+    #[must_use]
+    pub fn resumption_token(&self, authority: &str) -> Option<&ResumptionToken> {
+        self.resumption_tokens.get(authority)
+    }
+
+    /// The number of open connections, including connections that are going away.
+    #[must_use]
+    pub fn connection_count(&self) -> usize {
+        self.connections.len()
+    }
+
+    /// The number of requests that are queued or in progress.
+    #[must_use]
+    pub fn request_count(&self) -> usize {
+        self.requests.len()
+    }
+
+    /// The state of the newest connection to `authority`.
+    #[must_use]
+    pub fn connection_state(&self, authority: &str) -> Option<Http3State> {
+        self.connections
+            .iter()
+            .rev()
+            .find(|c| c.authority == authority)
+            .map(|c| c.client.state())
+    }
+}
+
+impl EventProvider for Http3ClientPool {
+    type Event = Http3ClientPoolEvent;
+
+    fn has_events(&self) -> bool {
+        !self.events.is_empty()
+    }
+
+    fn next_event(&mut self) -> Option<Self::Event> {
+        self.events.pop_front()
+    }
+}
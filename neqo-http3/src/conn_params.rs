@@ -15,6 +15,8 @@ const QPACK_MAX_BLOCKED_STREAMS_DEFAULT: u16 = 20;
 const MAX_PUSH_STREAM_DEFAULT: u64 = 0;
 const WEBTRANSPORT_DEFAULT: bool = false;
 const HTTP3_DATAGRAM_DEFAULT: bool = false;
+const INTERIM_RESPONSE_EVENTS_DEFAULT: bool = false;
+const DATA_CHUNK_EVENTS_DEFAULT: bool = false;
 
 #[derive(Debug, Clone)]
 pub struct Http3Parameters {
@@ -22,7 +24,13 @@ pub struct Http3Parameters {
     qpack_settings: QpackSettings,
     max_concurrent_push_streams: u64,
     webtransport: bool,
+    webtransport_max_data: Option<u64>,
+    webtransport_max_streams_bidi: Option<u64>,
+    webtransport_max_streams_uni: Option<u64>,
     http3_datagram: bool,
+    origins: Vec<String>,
+    interim_response_events: bool,
+    data_chunk_events: bool,
 }
 
 impl Default for Http3Parameters {
@@ -36,7 +44,13 @@ impl Default for Http3Parameters {
             },
             max_concurrent_push_streams: MAX_PUSH_STREAM_DEFAULT,
             webtransport: WEBTRANSPORT_DEFAULT,
+            webtransport_max_data: None,
+            webtransport_max_streams_bidi: None,
+            webtransport_max_streams_uni: None,
             http3_datagram: HTTP3_DATAGRAM_DEFAULT,
+            origins: Vec::new(),
+            interim_response_events: INTERIM_RESPONSE_EVENTS_DEFAULT,
+            data_chunk_events: DATA_CHUNK_EVENTS_DEFAULT,
         }
     }
 }
@@ -123,6 +137,43 @@ impl Http3Parameters {
         self.webtransport
     }
 
+    /// The amount of data the peer may send on all streams of a WebTransport session. If this
+    /// is not set, session flow control is not used, and only QUIC flow control applies.
+    #[must_use]
+    pub fn webtransport_max_data(mut self, max_data: u64) -> Self {
+        self.webtransport_max_data = Some(max_data);
+        self
+    }
+
+    #[must_use]
+    pub fn get_webtransport_max_data(&self) -> Option<u64> {
+        self.webtransport_max_data
+    }
+
+    /// The number of bidirectional streams the peer may open in a WebTransport session.
+    #[must_use]
+    pub fn webtransport_max_streams_bidi(mut self, max_streams: u64) -> Self {
+        self.webtransport_max_streams_bidi = Some(max_streams);
+        self
+    }
+
+    #[must_use]
+    pub fn get_webtransport_max_streams_bidi(&self) -> Option<u64> {
+        self.webtransport_max_streams_bidi
+    }
+
+    /// The number of unidirectional streams the peer may open in a WebTransport session.
+    #[must_use]
+    pub fn webtransport_max_streams_uni(mut self, max_streams: u64) -> Self {
+        self.webtransport_max_streams_uni = Some(max_streams);
+        self
+    }
+
+    #[must_use]
+    pub fn get_webtransport_max_streams_uni(&self) -> Option<u64> {
+        self.webtransport_max_streams_uni
+    }
+
     #[must_use]
     pub fn http3_datagram(mut self, http3_datagram: bool) -> Self {
         self.http3_datagram = http3_datagram;
@@ -133,4 +184,44 @@ impl Http3Parameters {
     pub fn get_http3_datagram(&self) -> bool {
         self.http3_datagram
     }
+
+    /// The origins a server advertises in an `ORIGIN` frame (RFC 9412). The frame is only sent
+    /// by a server and only if the list is not empty.
+    #[must_use]
+    pub fn origins(mut self, origins: Vec<String>) -> Self {
+        self.origins = origins;
+        self
+    }
+
+    #[must_use]
+    pub fn get_origins(&self) -> &[String] {
+        &self.origins
+    }
+
+    /// If enabled, a client reports informational (1xx) responses with an
+    /// `Http3ClientEvent::InterimResponse` event instead of a `HeaderReady` event with `interim`
+    /// set.
+    #[must_use]
+    pub fn interim_response_events(mut self, interim_response_events: bool) -> Self {
+        self.interim_response_events = interim_response_events;
+        self
+    }
+
+    #[must_use]
+    pub fn get_interim_response_events(&self) -> bool {
+        self.interim_response_events
+    }
+
+    /// If enabled, a server reports request data with `Http3ServerEvent::DataChunk` events, which
+    /// hand over the received chunks without copying them, instead of `Data` events.
+    #[must_use]
+    pub fn data_chunk_events(mut self, data_chunk_events: bool) -> Self {
+        self.data_chunk_events = data_chunk_events;
+        self
+    }
+
+    #[must_use]
+    pub fn get_data_chunk_events(&self) -> bool {
+        self.data_chunk_events
+    }
 }
@@ -12,10 +12,17 @@ use std::{
     fmt::Debug,
     mem,
     rc::Rc,
+    time::Instant,
 };
 
-use neqo_common::{qdebug, qerror, qinfo, qtrace, qwarn, Decoder, Header, MessageType, Role};
-use neqo_qpack::{decoder::QPackDecoder, encoder::QPackEncoder};
+use ::qlog::events::h3::{H3Owner, H3StreamType};
+use neqo_common::{
+    qdebug, qerror, qinfo, qlog::NeqoQlog, qtrace, qwarn, Bytes, Decoder, Header, MessageType, Role,
+};
+use neqo_qpack::{
+    decoder::{QPackDecoder, QPACK_UNI_STREAM_TYPE_DECODER},
+    encoder::{QPackEncoder, QPACK_UNI_STREAM_TYPE_ENCODER},
+};
 use neqo_transport::{
     streams::SendOrder, AppError, Connection, ConnectionError, DatagramTracking, State, StreamId,
     StreamType, ZeroRttState,
@@ -26,19 +33,21 @@ use crate::{
     control_stream_local::ControlStreamLocal,
     control_stream_remote::ControlStreamRemote,
     features::extended_connect::{
+        flow_control::SessionFlowControl,
         webtransport_session::WebTransportSession,
         webtransport_streams::{WebTransportRecvStream, WebTransportSendStream},
         ExtendedConnectEvents, ExtendedConnectFeature, ExtendedConnectType,
     },
     frames::HFrame,
     push_controller::PushController,
+    qlog,
     qpack_decoder_receiver::DecoderRecvStream,
     qpack_encoder_receiver::EncoderRecvStream,
     recv_message::{RecvMessage, RecvMessageInfo},
     request_target::{AsRequestTarget, RequestTarget},
     send_message::SendMessage,
     settings::{HSettingType, HSettings, HttpZeroRttChecker},
-    stream_type_reader::NewStreamHeadReader,
+    stream_type_reader::{NewStreamHeadReader, WEBTRANSPORT_STREAM, WEBTRANSPORT_UNI_STREAM},
     CloseType, Error, Http3Parameters, Http3StreamType, HttpRecvStreamEvents, NewStreamType,
     Priority, PriorityHandler, ReceiveOutput, RecvStream, RecvStreamEvents, Res, SendStream,
     SendStreamEvents,
@@ -310,6 +319,7 @@ pub(crate) struct Http3Connection {
     pub send_streams: HashMap<StreamId, Box<dyn SendStream>>,
     pub recv_streams: HashMap<StreamId, Box<dyn RecvStream>>,
     webtransport: ExtendedConnectFeature,
+    qlog: NeqoQlog,
 }
 
 impl ::std::fmt::Display for Http3Connection {
@@ -341,9 +351,18 @@ impl Http3Connection {
             send_streams: HashMap::new(),
             recv_streams: HashMap::new(),
             role,
+            qlog: NeqoQlog::disabled(),
         }
     }
 
+    /// Set the qlog for the parts of HTTP/3 and QPACK that log without the connection at hand.
+    pub fn set_qlog(&mut self, qlog: NeqoQlog) {
+        self.control_stream_local.set_qlog(qlog.clone());
+        self.qpack_encoder.borrow_mut().set_qlog(qlog.clone());
+        self.qpack_decoder.borrow_mut().set_qlog(qlog.clone());
+        self.qlog = qlog;
+    }
+
     /// This function is called when a not default feature needs to be negotiated. This is currently
     /// only used for the `WebTransport` feature. The negotiation is done via the `SETTINGS` frame
     /// and when the peer's `SETTINGS` frame has been received the listener will be called.
@@ -355,6 +374,7 @@ impl Http3Connection {
     /// streams.
     fn initialize_http3_connection(&mut self, conn: &mut Connection) -> Res<()> {
         qinfo!([self], "Initialize the http3 connection.");
+        self.set_qlog(conn.qlog_mut().clone());
         self.control_stream_local.create(conn)?;
 
         self.send_settings();
@@ -364,10 +384,17 @@ impl Http3Connection {
 
     fn send_settings(&mut self) {
         qdebug!([self], "Send settings.");
-        self.control_stream_local.queue_frame(&HFrame::Settings {
-            settings: HSettings::from(&self.local_params),
-        });
+        let settings = HSettings::from(&self.local_params);
+        qlog::h3_parameters_set(&mut self.qlog, H3Owner::Local, &settings);
+        self.control_stream_local
+            .queue_frame(&HFrame::Settings { settings });
         self.control_stream_local.queue_frame(&HFrame::Grease);
+        if self.role == Role::Server && !self.local_params.get_origins().is_empty() {
+            qdebug!([self], "Send ORIGIN frame.");
+            self.control_stream_local.queue_frame(&HFrame::Origin {
+                origins: self.local_params.get_origins().to_vec(),
+            });
+        }
     }
 
     /// Save settings for adding to the session ticket.
@@ -377,12 +404,30 @@ impl Http3Connection {
 
     fn create_qpack_streams(&mut self, conn: &mut Connection) -> Res<()> {
         qdebug!([self], "create_qpack_streams.");
+        let encoder_stream = conn.stream_create(StreamType::UniDi)?;
         self.qpack_encoder
             .borrow_mut()
-            .add_send_stream(conn.stream_create(StreamType::UniDi)?);
+            .add_send_stream(encoder_stream);
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            H3Owner::Local,
+            encoder_stream,
+            H3StreamType::QpackEncode,
+            Some(QPACK_UNI_STREAM_TYPE_ENCODER),
+            None,
+        );
+        let decoder_stream = conn.stream_create(StreamType::UniDi)?;
         self.qpack_decoder
             .borrow_mut()
-            .add_send_stream(conn.stream_create(StreamType::UniDi)?);
+            .add_send_stream(decoder_stream);
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            H3Owner::Local,
+            decoder_stream,
+            H3StreamType::QpackDecode,
+            Some(QPACK_UNI_STREAM_TYPE_DECODER),
+            None,
+        );
         Ok(())
     }
 
@@ -688,6 +733,7 @@ impl Http3Connection {
         stream_type: NewStreamType,
         stream_id: StreamId,
     ) -> Res<ReceiveOutput> {
+        qlog::h3_remote_stream_type_set(conn.qlog_mut(), stream_id, stream_type);
         match stream_type {
             NewStreamType::Control => {
                 self.check_stream_exists(Http3StreamType::Control)?;
@@ -729,16 +775,32 @@ impl Http3Connection {
                 qinfo!([self], "A new http stream {}.", stream_id);
             }
             NewStreamType::WebTransportStream(session_id) => {
-                let session_exists = self
-                    .send_streams
-                    .get(&StreamId::from(session_id))
-                    .map_or(false, |s| {
-                        s.stream_type() == Http3StreamType::ExtendedConnect
-                    });
+                let session_id = StreamId::from(session_id);
+                let session_exists = self.send_streams.get(&session_id).map_or(false, |s| {
+                    s.stream_type() == Http3StreamType::ExtendedConnect
+                });
                 if !session_exists {
                     conn.stream_stop_sending(stream_id, Error::HttpStreamCreation.code())?;
                     return Ok(ReceiveOutput::NoOutput);
                 }
+                let allowed = self
+                    .recv_streams
+                    .get(&session_id)
+                    .and_then(|s| s.webtransport())
+                    .map_or(true, |wt| {
+                        wt.borrow_mut()
+                            .remote_stream_opened(stream_id.stream_type())
+                            .is_ok()
+                    });
+                if !allowed {
+                    let code = Error::WebTransportFlowControl.code();
+                    conn.stream_stop_sending(stream_id, code)?;
+                    if stream_id.is_bidi() {
+                        mem::drop(conn.stream_reset_send(stream_id, code));
+                    }
+                    self.webtransport_flow_control_error(conn, session_id)?;
+                    return Ok(ReceiveOutput::NoOutput);
+                }
                 // set incoming WebTransport streams to be fair (share bandwidth)
                 conn.stream_fairness(stream_id, true).ok();
                 qinfo!(
@@ -804,6 +866,14 @@ impl Http3Connection {
                 self.remove_recv_stream(stream_id, conn);
             }
             Ok((_, false)) => {}
+            Err(Error::WebTransportFlowControl) => {
+                if let Some(Http3StreamType::WebTransport(session_id)) =
+                    self.recv_streams.get(&stream_id).map(|s| s.stream_type())
+                {
+                    self.webtransport_flow_control_error(conn, session_id)?;
+                    return Ok((U::default(), false));
+                }
+            }
             Err(e) => {
                 if e.stream_reset_error() && !self.recv_stream_is_critical(stream_id) {
                     mem::drop(conn.stream_stop_sending(stream_id, e.code()));
@@ -879,6 +949,14 @@ impl Http3Connection {
             .stream_create(StreamType::BiDi)
             .map_err(|e| Error::map_stream_create_errors(&e))?;
         conn.stream_keep_alive(id, true)?;
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            H3Owner::Local,
+            id,
+            H3StreamType::Request,
+            None,
+            None,
+        );
         Ok(id)
     }
 
@@ -956,11 +1034,34 @@ impl Http3Connection {
         buf: &mut [u8],
     ) -> Res<(usize, bool)> {
         qinfo!([self], "read_data from stream {}.", stream_id);
+        let recv_stream = self
+            .recv_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?;
+        let stream_type = recv_stream.stream_type();
+        let res = recv_stream.read_data(conn, buf);
+        let res = self.handle_stream_manipulation_output(res, stream_id, conn);
+        self.maybe_send_webtransport_session(stream_type);
+        res
+    }
+
+    /// Like `read_data`, but the next chunk of stream data is handed over without copying it.
+    ///
+    /// # Errors
+    ///
+    /// It returns an error if a stream does not exist or an error happens while reading a stream,
+    /// e.g. early close, protocol error, etc.
+    pub fn read_data_chunk(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+    ) -> Res<(Option<Bytes>, bool)> {
+        qinfo!([self], "read_data_chunk from stream {}.", stream_id);
         let res = self
             .recv_streams
             .get_mut(&stream_id)
             .ok_or(Error::InvalidStreamId)?
-            .read_data(conn, buf);
+            .read_data_chunk(conn);
         self.handle_stream_manipulation_output(res, stream_id, conn)
     }
 
@@ -1092,6 +1193,23 @@ impl Http3Connection {
         Ok(())
     }
 
+    /// Send trailers on a request or response stream and close the sending side of the stream.
+    pub fn send_trailers(
+        &mut self,
+        conn: &mut Connection,
+        stream_id: StreamId,
+        trailers: &[Header],
+    ) -> Res<()> {
+        qinfo!([self], "Send trailers for stream {}.", stream_id);
+        self.send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .http_stream()
+            .ok_or(Error::InvalidStreamId)?
+            .send_trailers(trailers, conn)?;
+        self.stream_close_send(conn, stream_id)
+    }
+
     /// This is called when an application wants to close the sending side of a stream.
     pub fn stream_close_send(&mut self, conn: &mut Connection, stream_id: StreamId) -> Res<()> {
         qinfo!([self], "Close the sending side for stream {}.", stream_id);
@@ -1134,6 +1252,7 @@ impl Http3Connection {
             self.role,
             Rc::clone(&self.qpack_encoder),
             Rc::clone(&self.qpack_decoder),
+            self.webtransport_flow_control(),
         )));
         self.add_streams(
             id,
@@ -1217,6 +1336,7 @@ impl Http3Connection {
                             self.role,
                             self.recv_streams.remove(&stream_id).unwrap(),
                             self.send_streams.remove(&stream_id).unwrap(),
+                            self.webtransport_flow_control(),
                         )));
                     self.add_streams(
                         stream_id,
@@ -1258,6 +1378,46 @@ impl Http3Connection {
         Ok(())
     }
 
+    pub(crate) fn webtransport_drain_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+    ) -> Res<()> {
+        qtrace!("Drain WebTransport session {:?}", session_id);
+        self.recv_streams
+            .get(&session_id)
+            .ok_or(Error::InvalidStreamId)?
+            .webtransport()
+            .ok_or(Error::InvalidStreamId)?
+            .borrow_mut()
+            .drain(conn)?;
+        self.maybe_send_webtransport_session(Http3StreamType::WebTransport(session_id));
+        Ok(())
+    }
+
+    /// Ask the peer to drain all active `WebTransport` sessions, e.g. because this endpoint is
+    /// sending a GOAWAY.
+    pub(crate) fn webtransport_drain_all_sessions(&mut self, conn: &mut Connection) {
+        let sessions = self
+            .recv_streams
+            .iter()
+            .filter_map(|(id, s)| s.webtransport().map(|wt| (*id, wt)))
+            .filter(|(_, wt)| wt.borrow().is_active())
+            .collect::<Vec<_>>();
+        for (session_id, wt) in sessions {
+            // A session whose control stream cannot be written to anymore is going away anyway.
+            mem::drop(wt.borrow_mut().drain(conn));
+            self.maybe_send_webtransport_session(Http3StreamType::WebTransport(session_id));
+        }
+    }
+
+    /// The peer sent a GOAWAY, inform the application that all sessions are draining.
+    pub(crate) fn webtransport_goaway_received(&mut self) {
+        for wt in self.recv_streams.values().filter_map(|s| s.webtransport()) {
+            wt.borrow_mut().goaway_received();
+        }
+    }
+
     pub fn webtransport_create_stream_local(
         &mut self,
         conn: &mut Connection,
@@ -1281,13 +1441,32 @@ impl Http3Connection {
         if !wt.borrow().is_active() {
             return Err(Error::InvalidStreamId);
         }
+        if !wt.borrow_mut().local_stream_available(stream_type) {
+            wt.borrow_mut().streams_blocked(conn, stream_type)?;
+            self.maybe_send_webtransport_session(Http3StreamType::WebTransport(session_id));
+            return Err(Error::StreamLimitError);
+        }
 
         let stream_id = conn
             .stream_create(stream_type)
             .map_err(|e| Error::map_stream_create_errors(&e))?;
+        wt.borrow_mut().local_stream_opened(stream_type);
         // Set outgoing WebTransport streams to be fair (share bandwidth)
         // This really can't fail, panics if it does
         conn.stream_fairness(stream_id, true).unwrap();
+        // qlog has no stream type for `WebTransport`.
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            H3Owner::Local,
+            stream_id,
+            H3StreamType::Unknown,
+            Some(if stream_id.is_uni() {
+                WEBTRANSPORT_UNI_STREAM
+            } else {
+                WEBTRANSPORT_STREAM
+            }),
+            None,
+        );
 
         self.webtransport_create_stream_internal(
             wt,
@@ -1362,6 +1541,7 @@ impl Http3Connection {
                         session_id,
                         recv_events,
                         webtransport_session,
+                        false,
                     )),
                 );
             }
@@ -1380,6 +1560,7 @@ impl Http3Connection {
                     session_id,
                     recv_events,
                     webtransport_session,
+                    local,
                 )),
             );
         }
@@ -1391,6 +1572,7 @@ impl Http3Connection {
         conn: &mut Connection,
         buf: &[u8],
         id: impl Into<DatagramTracking>,
+        deadline: Option<Instant>,
     ) -> Res<()> {
         self.recv_streams
             .get_mut(&session_id)
@@ -1398,12 +1580,12 @@ impl Http3Connection {
             .webtransport()
             .ok_or(Error::InvalidStreamId)?
             .borrow_mut()
-            .send_datagram(conn, buf, id)
+            .send_datagram(conn, buf, id, deadline)
     }
 
-    /// If the control stream has received frames `MaxPushId`, `Goaway`, `PriorityUpdateRequest` or
-    /// `PriorityUpdateRequestPush` which handling is specific to the client and server, we must
-    /// give them to the specific client/server handler.
+    /// If the control stream has received frames `MaxPushId`, `Goaway`, `PriorityUpdateRequest`,
+    /// `PriorityUpdateRequestPush` or `Origin` which handling is specific to the client and
+    /// server, we must give them to the specific client/server handler.
     fn handle_control_frame(&mut self, f: HFrame) -> Res<Option<HFrame>> {
         qinfo!([self], "Handle a control frame {:?}", f);
         if !matches!(f, HFrame::Settings { .. })
@@ -1423,7 +1605,8 @@ impl Http3Connection {
             | HFrame::MaxPushId { .. }
             | HFrame::CancelPush { .. }
             | HFrame::PriorityUpdateRequest { .. }
-            | HFrame::PriorityUpdatePush { .. } => Ok(Some(f)),
+            | HFrame::PriorityUpdatePush { .. }
+            | HFrame::Origin { .. } => Ok(Some(f)),
             _ => Err(Error::HttpFrameUnexpected),
         }
     }
@@ -1437,6 +1620,7 @@ impl Http3Connection {
 
     fn handle_settings(&mut self, new_settings: HSettings) -> Res<()> {
         qinfo!([self], "Handle SETTINGS frame.");
+        qlog::h3_parameters_set(&mut self.qlog, H3Owner::Remote, &new_settings);
         match &self.settings_state {
             Http3RemoteSettingsState::NotReceived => {
                 self.set_qpack_settings(&new_settings)?;
@@ -1560,6 +1744,7 @@ impl Http3Connection {
     fn close_send(&mut self, stream_id: StreamId, close_type: CloseType, conn: &mut Connection) {
         if let Some(mut s) = self.remove_send_stream(stream_id, conn) {
             s.handle_stop_sending(close_type);
+            self.maybe_send_webtransport_session(s.stream_type());
         }
     }
 
@@ -1571,6 +1756,7 @@ impl Http3Connection {
     ) -> Res<()> {
         if let Some(mut s) = self.remove_recv_stream(stream_id, conn) {
             s.reset(close_type)?;
+            self.maybe_send_webtransport_session(s.stream_type());
         }
         Ok(())
     }
@@ -1636,4 +1822,48 @@ impl Http3Connection {
     pub fn webtransport_enabled(&self) -> bool {
         self.webtransport.enabled()
     }
+
+    fn webtransport_flow_control(&self) -> SessionFlowControl {
+        let remote = match &self.settings_state {
+            Http3RemoteSettingsState::Received(settings)
+            | Http3RemoteSettingsState::ZeroRtt(settings) => Some(settings),
+            Http3RemoteSettingsState::NotReceived => None,
+        };
+        SessionFlowControl::new(&self.local_params, remote)
+    }
+
+    /// Session flow control capsules are written to the session stream while one of its
+    /// sub-streams is read, written or closed. If they could not be sent right away, the session
+    /// stream needs to be added to `streams_with_pending_data`.
+    pub(crate) fn maybe_send_webtransport_session(&mut self, stream_type: Http3StreamType) {
+        if let Http3StreamType::WebTransport(session_id) = stream_type {
+            if self
+                .send_streams
+                .get(&session_id)
+                .map_or(false, |s| s.has_data_to_send())
+            {
+                self.streams_with_pending_data.insert(session_id);
+            }
+        }
+    }
+
+    /// The peer exceeded the stream or data limit of a `WebTransport` session. The session is
+    /// closed with `WEBTRANSPORT_FLOW_CONTROL_ERROR` and all of its streams are reset.
+    fn webtransport_flow_control_error(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+    ) -> Res<()> {
+        qinfo!(
+            [self],
+            "WebTransport session {} exceeded its flow control limits.",
+            session_id
+        );
+        let code = Error::WebTransportFlowControl.code();
+        self.close_recv(session_id, CloseType::LocalError(code), conn)?;
+        // The session may already be closed and we may get an error here, but we do not care.
+        mem::drop(conn.stream_stop_sending(session_id, code));
+        mem::drop(conn.stream_reset_send(session_id, code));
+        Ok(())
+    }
 }
@@ -16,26 +16,30 @@ use std::{
 
 use neqo_common::{
     event::Provider as EventProvider, hex, hex_with_len, qdebug, qinfo, qlog::NeqoQlog, qtrace,
-    Datagram, Decoder, Encoder, Header, MessageType, Role,
+    Bytes, Datagram, Decoder, Encoder, Header, MessageType, Role,
+};
+use neqo_crypto::{
+    agent::CertificateInfo, AuthenticationStatus, CertificateVerifier, ClientCertificateSelector,
+    ResumptionToken, SecretAgentInfo,
 };
-use neqo_crypto::{agent::CertificateInfo, AuthenticationStatus, ResumptionToken, SecretAgentInfo};
 use neqo_qpack::Stats as QpackStats;
 use neqo_transport::{
     streams::SendOrder, AppError, Connection, ConnectionEvent, ConnectionId, ConnectionIdGenerator,
-    DatagramTracking, Output, RecvStreamStats, SendStreamStats, Stats as TransportStats, StreamId,
-    StreamType, Version, ZeroRttState,
+    DatagramTracking, Output, PacketCapture, RecvStreamStats, SendStreamStats,
+    Stats as TransportStats, StreamId, StreamType, Version, ZeroRttState,
 };
 
 use crate::{
     client_events::{Http3ClientEvent, Http3ClientEvents},
     connection::{Http3Connection, Http3State, RequestDescription},
     frames::HFrame,
+    origin::{server_origin, OriginSet},
     push_controller::{PushController, RecvPushEvents},
     recv_message::{RecvMessage, RecvMessageInfo},
     request_target::AsRequestTarget,
     settings::HSettings,
     Error, Http3Parameters, Http3StreamType, NewStreamType, Priority, PriorityHandler,
-    ReceiveOutput, Res,
+    ReceiveOutput, Res, Stream,
 };
 
 // This is used for filtering send_streams and recv_Streams with a stream_ids greater than or equal
@@ -89,6 +93,8 @@ fn alpn_from_quic_version(version: Version) -> &'static str {
 ///   - [`Http3Client::state`]
 ///   - [`Http3Client::take_resumption_token`]
 ///   - [`Http3Client::tls_info`]
+///   - [`Http3Client::origin_set`]
+///   - [`Http3Client::is_authoritative_for`]
 /// - driving HTTP/3 session:
 ///   - [`Http3Client::process_output`]
 ///   - [`Http3Client::process_input`]
@@ -96,7 +102,10 @@ fn alpn_from_quic_version(version: Version) -> &'static str {
 /// - create requests, send/receive data, and cancel requests:
 ///   - [`Http3Client::fetch`]
 ///   - [`Http3Client::send_data`]
+///   - [`Http3Client::send_data_bytes`]
 ///   - [`Http3Client::read_data`]
+///   - [`Http3Client::read_data_chunk`]
+///   - [`Http3Client::send_trailers`]
 ///   - [`Http3Client::stream_close_send`]
 ///   - [`Http3Client::cancel_fetch`]
 ///   - [`Http3Client::stream_reset_send`]
@@ -292,6 +301,7 @@ pub struct Http3Client {
     base_handler: Http3Connection,
     events: Http3ClientEvents,
     push_handler: Rc<RefCell<PushController>>,
+    origin_set: OriginSet,
 }
 
 impl Display for Http3Client {
@@ -313,7 +323,9 @@ impl Http3Client {
         http3_parameters: Http3Parameters,
         now: Instant,
     ) -> Res<Self> {
-        Ok(Self::new_with_conn(
+        let server_name = server_name.into();
+        let origin = server_origin(&server_name, remote_addr.port());
+        let mut client = Self::new_with_conn(
             Connection::new_client(
                 server_name,
                 &[alpn_from_quic_version(
@@ -329,7 +341,9 @@ impl Http3Client {
                 now,
             )?,
             http3_parameters,
-        ))
+        );
+        client.origin_set = OriginSet::new(Some(&origin));
+        Ok(client)
     }
 
     /// This is a similar function to `new`. In this case, `neqo-transport::connection` has been
@@ -338,7 +352,7 @@ impl Http3Client {
     /// It is recommended to use `new` instead.
     #[must_use]
     pub fn new_with_conn(c: Connection, http3_parameters: Http3Parameters) -> Self {
-        let events = Http3ClientEvents::default();
+        let events = Http3ClientEvents::new(http3_parameters.get_interim_response_events());
         let webtransport = http3_parameters.get_webtransport();
         let push_streams = http3_parameters.get_max_concurrent_push_streams();
         let mut base_handler = Http3Connection::new(http3_parameters, Role::Client);
@@ -350,6 +364,7 @@ impl Http3Client {
             events: events.clone(),
             push_handler: Rc::new(RefCell::new(PushController::new(push_streams, events))),
             base_handler,
+            origin_set: OriginSet::default(),
         }
     }
 
@@ -375,6 +390,21 @@ impl Http3Client {
         self.conn.peer_certificate()
     }
 
+    /// The set of origins this connection is authoritative for. The set is initialized with the
+    /// origin the connection was created for and extended by `ORIGIN` frames from the server.
+    #[must_use]
+    pub fn origin_set(&self) -> &OriginSet {
+        &self.origin_set
+    }
+
+    /// Whether requests for `origin` may be sent on this connection according to the origin set.
+    /// The application must additionally check that the server certificate covers the host of
+    /// `origin`, e.g. using `peer_certificate`, before coalescing requests.
+    #[must_use]
+    pub fn is_authoritative_for(&self, origin: &str) -> bool {
+        self.origin_set.contains(origin)
+    }
+
     /// This called when peer certificates have been verified.
     ///
     /// `Http3ClientEvent::AuthenticationNeeded` event is emitted when peer’s certificates are
@@ -384,10 +414,37 @@ impl Http3Client {
         self.conn.authenticated(status, now);
     }
 
+    /// Check the server certificate with `verifier`.  There is no
+    /// `Http3ClientEvent::AuthenticationNeeded` event after this; instead,
+    /// `Http3ClientEvent::CertificateVerified` reports the result.
+    pub fn set_certificate_verifier(&mut self, verifier: CertificateVerifier) {
+        self.conn.set_certificate_verifier(verifier);
+    }
+
+    /// Pick a certificate to present if the server asks for one.
+    ///
+    /// # Errors
+    ///
+    /// When the underlying TLS stack can't be configured.
+    pub fn set_client_certificate_selector(
+        &mut self,
+        selector: Rc<dyn ClientCertificateSelector>,
+    ) -> Res<()> {
+        self.conn.client_set_certificate_selector(selector)?;
+        Ok(())
+    }
+
     pub fn set_qlog(&mut self, qlog: NeqoQlog) {
+        self.base_handler.set_qlog(qlog.clone());
+        self.push_handler.borrow_mut().set_qlog(qlog.clone());
         self.conn.set_qlog(qlog);
     }
 
+    /// Set or clear a pcapng capture of the datagrams of this connection.
+    pub fn set_capture(&mut self, capture: Option<PacketCapture>) {
+        self.conn.set_capture(capture);
+    }
+
     /// Enable encrypted client hello (ECH).
     ///
     /// # Errors
@@ -585,6 +642,19 @@ impl Http3Client {
             .cancel_fetch(stream_id, error, &mut self.conn)
     }
 
+    /// Send trailers after the request body and close the sending side of the stream.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not exist,
+    /// `InvalidInput` if trailers have already been sent or the sending side has been closed (FIN),
+    /// `InvalidHeader` if the trailers contain pseudo-headers.
+    pub fn send_trailers(&mut self, stream_id: StreamId, trailers: &[Header]) -> Res<()> {
+        qinfo!([self], "Send trailers stream={}.", stream_id);
+        self.base_handler
+            .send_trailers(&mut self.conn, stream_id, trailers)
+    }
+
     /// This is call when application is done sending a request.
     ///
     /// # Errors
@@ -633,11 +703,42 @@ impl Http3Client {
             stream_id,
             buf.len()
         );
+        let send_stream = self
+            .base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?;
+        let stream_type = send_stream.stream_type();
+        let sent = send_stream.send_data(&mut self.conn, buf)?;
         self.base_handler
+            .maybe_send_webtransport_session(stream_type);
+        Ok(sent)
+    }
+
+    /// Send request data, like `send_data`, but without copying `buf`. The transport keeps a
+    /// reference to the accepted part of `buf` until it has been acknowledged. Returns how many
+    /// bytes were accepted; the rest can be sent later with `buf.slice(sent..)`.
+    ///
+    /// # Errors
+    ///
+    /// The same as for `send_data`.
+    pub fn send_data_bytes(&mut self, stream_id: StreamId, buf: Bytes) -> Res<usize> {
+        qinfo!(
+            [self],
+            "send_data_bytes from stream {} sending {} bytes.",
+            stream_id,
+            buf.len()
+        );
+        let send_stream = self
+            .base_handler
             .send_streams
             .get_mut(&stream_id)
-            .ok_or(Error::InvalidStreamId)?
-            .send_data(&mut self.conn, buf)
+            .ok_or(Error::InvalidStreamId)?;
+        let stream_type = send_stream.stream_type();
+        let sent = send_stream.send_data_bytes(&mut self.conn, buf)?;
+        self.base_handler
+            .maybe_send_webtransport_session(stream_type);
+        Ok(sent)
     }
 
     /// Response data are read directly into a buffer supplied as a parameter of this function to
@@ -663,6 +764,29 @@ impl Http3Client {
         res
     }
 
+    /// Response data are returned in chunks as they were received, without copying them.
+    /// A chunk is at most the rest of a DATA frame. `None` is returned if no data is available.
+    /// The bool is true when the end of the response body has been reached.
+    ///
+    /// # Errors
+    ///
+    /// It returns an error if a stream does not exist or an error happen while reading a stream,
+    /// e.g. early close, protocol error, etc.
+    pub fn read_data_chunk(
+        &mut self,
+        now: Instant,
+        stream_id: StreamId,
+    ) -> Res<(Option<Bytes>, bool)> {
+        qinfo!([self], "read_data_chunk from stream {}.", stream_id);
+        let res = self.base_handler.read_data_chunk(&mut self.conn, stream_id);
+        if let Err(e) = &res {
+            if e.connection_error() {
+                self.close(now, e.code(), "");
+            }
+        }
+        res
+    }
+
     // API: Push streams
 
     /// Cancel a push
@@ -777,10 +901,26 @@ impl Http3Client {
         session_id: StreamId,
         buf: &[u8],
         id: impl Into<DatagramTracking>,
+    ) -> Res<()> {
+        self.webtransport_send_datagram_with_deadline(session_id, buf, id, None)
+    }
+
+    /// Send `WebTransport` datagram, as with `webtransport_send_datagram`.
+    /// If the datagram has not been sent by `deadline`, it is dropped.
+    ///
+    /// # Errors
+    ///
+    /// As for `webtransport_send_datagram`.
+    pub fn webtransport_send_datagram_with_deadline(
+        &mut self,
+        session_id: StreamId,
+        buf: &[u8],
+        id: impl Into<DatagramTracking>,
+        deadline: Option<Instant>,
     ) -> Res<()> {
         qtrace!("webtransport_send_datagram session:{:?}", session_id);
         self.base_handler
-            .webtransport_send_datagram(session_id, &mut self.conn, buf, id)
+            .webtransport_send_datagram(session_id, &mut self.conn, buf, id, deadline)
     }
 
     /// Returns the current max size of a datagram that can fit into a packet.
@@ -1048,6 +1188,10 @@ impl Http3Client {
                 ConnectionEvent::EchFallbackAuthenticationNeeded { public_name } => {
                     self.events.ech_fallback_authentication_needed(public_name);
                 }
+                ConnectionEvent::CertificateVerified { code } => {
+                    self.events
+                        .certificate_verified(AuthenticationStatus::from(code));
+                }
                 ConnectionEvent::StateChange(state) => {
                     if self
                         .base_handler
@@ -1094,7 +1238,8 @@ impl Http3Client {
     ///     - `HFrame::MaxPushId { .. }`, `HFrame::PriorityUpdateRequest { .. } ` and
     ///       `HFrame::PriorityUpdatePush` can only be receive on the server side,
     ///     - `HFrame::Goaway { stream_id }` needs specific handling by the client by the protocol
-    ///       specification.
+    ///       specification,
+    ///     - `HFrame::Origin { origins }` extends the origin set of the connection.
     ///
     /// [1]: https://github.com/mozilla/neqo/blob/main/neqo-http3/src/connection.rs
     fn handle_stream_readable(&mut self, stream_id: StreamId) -> Res<()> {
@@ -1130,9 +1275,13 @@ impl Http3Client {
                         | HFrame::PriorityUpdateRequest { .. }
                         | HFrame::PriorityUpdatePush { .. } => Err(Error::HttpFrameUnexpected),
                         HFrame::Goaway { stream_id } => self.handle_goaway(stream_id),
+                        HFrame::Origin { origins } => {
+                            self.handle_origin(&origins);
+                            Ok(())
+                        }
                         _ => {
                             unreachable!(
-                                "we should only put MaxPushId, Goaway and PriorityUpdates into control_frames."
+                                "we should only put MaxPushId, Goaway, PriorityUpdates and Origin into control_frames."
                             );
                         }
                     }?;
@@ -1241,11 +1390,21 @@ impl Http3Client {
             ));
         }
 
+        // Sessions below the GOAWAY id keep working, but will not outlive the connection.
+        self.base_handler.webtransport_goaway_received();
         self.events.goaway_received();
 
         Ok(())
     }
 
+    fn handle_origin(&mut self, origins: &[String]) {
+        qinfo!([self], "handle_origin {:?}", origins);
+        let added = self.origin_set.add_origins(origins);
+        if !added.is_empty() {
+            self.events.origins_received(added);
+        }
+    }
+
     /// Increases `max_stream_data` for a `stream_id`.
     ///
     /// # Errors
@@ -1776,6 +1935,9 @@ mod tests {
 
     const HTTP_HEADER_FRAME_0: &[u8] = &[0x01, 0x06, 0x00, 0x00, 0xd9, 0x54, 0x01, 0x30];
 
+    // A trailer frame containing only the header "age: 0".
+    const HTTP_TRAILER_FRAME_0: &[u8] = &[0x01, 0x03, 0x00, 0x00, 0xc2];
+
     // The response header from HTTP_HEADER_FRAME (0x01, 0x06, 0x00, 0x00, 0xd9, 0x54, 0x01, 0x30)
     // are decoded into:
     fn check_response_header_0(header: &[Header]) {
@@ -3604,6 +3766,57 @@ mod tests {
         assert_closed(&client, &Error::HttpId);
     }
 
+    #[test]
+    fn origin_frame() {
+        let (mut client, mut server) = connect();
+        assert!(client.is_authoritative_for("https://example.com"));
+        assert!(!client.origin_set().origin_frame_received());
+
+        let mut enc = Encoder::default();
+        HFrame::Origin {
+            origins: vec!["https://Other.example.com:443".to_string()],
+        }
+        .encode(&mut enc);
+        _ = server
+            .conn
+            .stream_send(server.control_stream_id.unwrap(), enc.as_ref())
+            .unwrap();
+
+        let out = server.conn.process(None, now());
+        client.process(out.as_dgram_ref(), now());
+
+        let origins_received = |e| {
+            matches!(e, Http3ClientEvent::OriginsReceived { origins }
+                if origins == vec!["https://other.example.com".to_string()])
+        };
+        assert!(client.events().any(origins_received));
+        assert!(client.origin_set().origin_frame_received());
+        assert!(client.is_authoritative_for("https://other.example.com"));
+        assert_eq!(client.state(), Http3State::Connected);
+    }
+
+    #[test]
+    fn origin_ipv6_server_name() {
+        fixture_init();
+        let client = Http3Client::new(
+            "fe80::1",
+            Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+            addr(),
+            addr(),
+            Http3Parameters::default(),
+            now(),
+        )
+        .unwrap();
+        assert_eq!(client.origin_set().len(), 1);
+        assert!(client.is_authoritative_for("https://[fe80::1]"));
+        assert!(client.is_authoritative_for("https://[FE80:0::1]:443"));
+    }
+
+    #[test]
+    fn origin_frame_on_request_stream() {
+        test_wrong_frame_on_request_stream(&[0xc, 0x0]);
+    }
+
     // Close stream before headers.
     #[test]
     fn test_stream_fin_wo_headers() {
@@ -4679,7 +4892,7 @@ mod tests {
             &mut client,
             &mut server,
             request_stream_id,
-            HTTP_HEADER_FRAME_0,
+            HTTP_TRAILER_FRAME_0,
             true,
         );
 
@@ -4689,6 +4902,10 @@ mod tests {
         let header_ready: fn(&Http3ClientEvent) -> _ =
             |e| matches!(*e, Http3ClientEvent::HeaderReady { .. });
         assert!(!events.iter().any(header_ready));
+        assert!(events.contains(&Http3ClientEvent::Trailers {
+            stream_id: request_stream_id,
+            trailers: vec![Header::new("age", "0")],
+        }));
 
         // Check that we have a DataReady event. Reading from the stream will return fin=true.
         let data_readable: fn(&Http3ClientEvent) -> _ =
@@ -4740,7 +4957,7 @@ mod tests {
             &mut client,
             &mut server,
             request_stream_id,
-            HTTP_HEADER_FRAME_0,
+            HTTP_TRAILER_FRAME_0,
             false,
         );
 
@@ -4810,7 +5027,7 @@ mod tests {
             &mut client,
             &mut server,
             request_stream_id,
-            HTTP_HEADER_FRAME_0,
+            HTTP_TRAILER_FRAME_0,
             false,
         );
 
@@ -4830,6 +5047,81 @@ mod tests {
         assert_closed(&client, &Error::HttpFrameUnexpected);
     }
 
+    #[test]
+    fn test_trailers_with_pseudo_header() {
+        let (mut client, mut server, request_stream_id) = connect_and_send_request(true);
+
+        // Send HEADER frame.
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            HTTP_HEADER_FRAME_0,
+            false,
+        );
+        mem::drop(client.events().count());
+
+        // Send trailers containing ":status".
+        server_send_response_and_exchange_packet(
+            &mut client,
+            &mut server,
+            request_stream_id,
+            HTTP_HEADER_FRAME_0,
+            true,
+        );
+
+        // Stream has been reset because of the malformed trailers.
+        assert_eq!(
+            client.events().next().unwrap(),
+            Http3ClientEvent::Reset {
+                stream_id: request_stream_id,
+                error: Error::InvalidHeader.code(),
+                local: true,
+            }
+        );
+    }
+
+    #[test]
+    fn send_trailers() {
+        let (mut client, mut server) = connect();
+        let request_stream_id = make_request(&mut client, false, &[]);
+        assert_eq!(client.send_data(request_stream_id, &[0x61]), Ok(1));
+
+        // Pseudo-headers are not allowed in trailers.
+        assert_eq!(
+            client.send_trailers(request_stream_id, &[Header::new(":method", "GET")]),
+            Err(Error::InvalidHeader)
+        );
+        client
+            .send_trailers(request_stream_id, &[Header::new("grpc-status", "0")])
+            .unwrap();
+
+        // No more data or trailers can be sent.
+        assert_eq!(
+            client.send_data(request_stream_id, &[0x61]),
+            Err(Error::InvalidInput)
+        );
+        assert_eq!(
+            client.send_trailers(request_stream_id, &[Header::new("grpc-status", "0")]),
+            Err(Error::InvalidInput)
+        );
+
+        let out = client.process(None, now());
+        mem::drop(server.conn.process(out.as_dgram_ref(), now()));
+
+        // The server receives the headers, a DATA frame and a HEADERS frame carrying the
+        // trailers, followed by a fin.
+        let mut buf = [0_u8; 100];
+        let (amount, fin) = server
+            .conn
+            .stream_recv(request_stream_id, &mut buf)
+            .unwrap();
+        assert!(fin);
+        let data_and_trailers = &buf[EXPECTED_REQUEST_HEADER_FRAME.len()..amount];
+        assert_eq!(&data_and_trailers[..3], &[0x0, 0x1, 0x61]);
+        assert_eq!(data_and_trailers[3], 0x1);
+    }
+
     #[test]
     fn transport_stream_readable_event_after_all_data() {
         let (mut client, mut server, request_stream_id) = connect_and_send_request(false);
@@ -7293,4 +7585,26 @@ mod tests {
             }
         );
     }
+
+    #[test]
+    fn qlog_events() {
+        let mut client = default_http3_client();
+        let (log, contents) = test_fixture::new_neqo_qlog();
+        client.set_qlog(log);
+        let mut server = TestServer::new();
+        connect_with(&mut client, &mut server);
+        make_request(&mut client, true, &[]);
+        mem::drop(client.process(None, now()));
+
+        let contents = contents.to_string();
+        for event in [
+            "stream_type_set",
+            "parameters_set",
+            "frame_created",
+            "frame_parsed",
+            "headers_encoded",
+        ] {
+            assert!(contents.contains(event), "missing {event}");
+        }
+    }
 }
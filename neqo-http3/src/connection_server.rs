@@ -4,9 +4,9 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{rc::Rc, time::Instant};
+use std::{mem, rc::Rc, time::Instant};
 
-use neqo_common::{event::Provider, qdebug, qinfo, qtrace, Header, MessageType, Role};
+use neqo_common::{event::Provider, qdebug, qinfo, qtrace, Bytes, Header, MessageType, Role};
 use neqo_transport::{
     AppError, Connection, ConnectionEvent, DatagramTracking, StreamId, StreamType,
 };
@@ -18,7 +18,7 @@ use crate::{
     send_message::SendMessage,
     server_connection_events::{Http3ServerConnEvent, Http3ServerConnEvents},
     Error, Http3Parameters, Http3StreamType, NewStreamType, Priority, PriorityHandler,
-    ReceiveOutput, Res,
+    ReceiveOutput, Res, Stream,
 };
 
 #[derive(Debug)]
@@ -26,6 +26,10 @@ pub struct Http3ServerHandler {
     base_handler: Http3Connection,
     events: Http3ServerConnEvents,
     needs_processing: bool,
+    /// The lowest client-initiated bidirectional stream id that has not been seen yet.
+    next_request_stream_id: StreamId,
+    /// The stream id sent in a GOAWAY frame, requests on this or later streams are rejected.
+    goaway_stream_id: Option<StreamId>,
 }
 
 impl ::std::fmt::Display for Http3ServerHandler {
@@ -40,6 +44,8 @@ impl Http3ServerHandler {
             base_handler: Http3Connection::new(http3_parameters, Role::Server),
             events: Http3ServerConnEvents::default(),
             needs_processing: false,
+            next_request_stream_id: StreamId::new(0),
+            goaway_stream_id: None,
         }
     }
 
@@ -66,11 +72,41 @@ impl Http3ServerHandler {
     ) -> Res<usize> {
         self.base_handler.stream_has_pending_data(stream_id);
         self.needs_processing = true;
+        let send_stream = self
+            .base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?;
+        let stream_type = send_stream.stream_type();
+        let sent = send_stream.send_data(conn, data)?;
         self.base_handler
+            .maybe_send_webtransport_session(stream_type);
+        Ok(sent)
+    }
+
+    /// Supply response data for a request without copying it.
+    ///
+    /// # Errors
+    ///
+    /// The same as for `send_data`.
+    pub(crate) fn send_data_bytes(
+        &mut self,
+        stream_id: StreamId,
+        data: Bytes,
+        conn: &mut Connection,
+    ) -> Res<usize> {
+        self.base_handler.stream_has_pending_data(stream_id);
+        self.needs_processing = true;
+        let send_stream = self
+            .base_handler
             .send_streams
             .get_mut(&stream_id)
-            .ok_or(Error::InvalidStreamId)?
-            .send_data(conn, data)
+            .ok_or(Error::InvalidStreamId)?;
+        let stream_type = send_stream.stream_type();
+        let sent = send_stream.send_data_bytes(conn, data)?;
+        self.base_handler
+            .maybe_send_webtransport_session(stream_type);
+        Ok(sent)
     }
 
     /// Supply response heeaders for a request.
@@ -92,6 +128,38 @@ impl Http3ServerHandler {
         Ok(())
     }
 
+    /// Supply an informational (1xx) response for a request.
+    pub(crate) fn send_interim_headers(
+        &mut self,
+        stream_id: StreamId,
+        headers: &[Header],
+        conn: &mut Connection,
+    ) -> Res<()> {
+        self.base_handler
+            .send_streams
+            .get_mut(&stream_id)
+            .ok_or(Error::InvalidStreamId)?
+            .http_stream()
+            .ok_or(Error::InvalidStreamId)?
+            .send_interim_headers(headers, conn)?;
+        self.base_handler.stream_has_pending_data(stream_id);
+        self.needs_processing = true;
+        Ok(())
+    }
+
+    /// Supply trailers for a response and close the sending side of the stream.
+    pub(crate) fn send_trailers(
+        &mut self,
+        stream_id: StreamId,
+        trailers: &[Header],
+        conn: &mut Connection,
+    ) -> Res<()> {
+        self.base_handler.send_trailers(conn, stream_id, trailers)?;
+        self.base_handler.stream_has_pending_data(stream_id);
+        self.needs_processing = true;
+        Ok(())
+    }
+
     /// This is called when application is done sending a request.
     ///
     /// # Errors
@@ -182,6 +250,37 @@ impl Http3ServerHandler {
             .webtransport_close_session(conn, session_id, error, message)
     }
 
+    /// Ask the client to wind down a `WebTransport` session.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session does not exist or is not active.
+    pub fn webtransport_drain_session(
+        &mut self,
+        conn: &mut Connection,
+        session_id: StreamId,
+    ) -> Res<()> {
+        self.needs_processing = true;
+        self.base_handler
+            .webtransport_drain_session(conn, session_id)
+    }
+
+    /// Send a GOAWAY frame. Requests the server has already seen are still processed, later
+    /// requests are rejected. All `WebTransport` sessions are asked to drain. Calling this more
+    /// than once has no effect.
+    pub fn goaway(&mut self, conn: &mut Connection) {
+        if self.goaway_stream_id.is_some() || !self.base_handler.state().active() {
+            return;
+        }
+        qinfo!([self], "Send GOAWAY {}.", self.next_request_stream_id);
+        self.goaway_stream_id = Some(self.next_request_stream_id);
+        self.base_handler.queue_control_frame(&HFrame::Goaway {
+            stream_id: self.next_request_stream_id,
+        });
+        self.base_handler.webtransport_drain_all_sessions(conn);
+        self.needs_processing = true;
+    }
+
     pub fn webtransport_create_stream(
         &mut self,
         conn: &mut Connection,
@@ -204,10 +303,11 @@ impl Http3ServerHandler {
         session_id: StreamId,
         buf: &[u8],
         id: impl Into<DatagramTracking>,
+        deadline: Option<Instant>,
     ) -> Res<()> {
         self.needs_processing = true;
         self.base_handler
-            .webtransport_send_datagram(session_id, conn, buf, id)
+            .webtransport_send_datagram(session_id, conn, buf, id, deadline)
     }
 
     /// Process HTTTP3 layer.
@@ -265,6 +365,12 @@ impl Http3ServerHandler {
             qdebug!([self], "check_connection_events - event {e:?}.");
             match e {
                 ConnectionEvent::NewStream { stream_id } => {
+                    if stream_id.is_client_initiated()
+                        && stream_id.is_bidi()
+                        && stream_id >= self.next_request_stream_id
+                    {
+                        self.next_request_stream_id = StreamId::new(stream_id.as_u64() + 4);
+                    }
                     self.base_handler.add_new_stream(stream_id);
                 }
                 ConnectionEvent::RecvStreamReadable { stream_id } => {
@@ -301,6 +407,7 @@ impl Http3ServerHandler {
                 ConnectionEvent::Datagram(dgram) => self.base_handler.handle_datagram(&dgram),
                 ConnectionEvent::AuthenticationNeeded
                 | ConnectionEvent::EchFallbackAuthenticationNeeded { .. }
+                | ConnectionEvent::CertificateVerified { .. }
                 | ConnectionEvent::ZeroRttRejected
                 | ConnectionEvent::ResumptionToken(..) => return Err(Error::HttpInternal(4)),
                 ConnectionEvent::SendStreamComplete { .. }
@@ -315,6 +422,17 @@ impl Http3ServerHandler {
     fn handle_stream_readable(&mut self, conn: &mut Connection, stream_id: StreamId) -> Res<()> {
         match self.base_handler.handle_stream_readable(conn, stream_id)? {
             ReceiveOutput::NewStream(NewStreamType::Push(_)) => Err(Error::HttpStreamCreation),
+            ReceiveOutput::NewStream(NewStreamType::Http)
+                if self.goaway_stream_id.map_or(false, |id| stream_id >= id) =>
+            {
+                qinfo!([self], "Reject request {} sent after GOAWAY.", stream_id);
+                self.base_handler.recv_streams.remove(&stream_id);
+                let code = Error::HttpRequestRejected.code();
+                // The stream may already be closed, we do not care.
+                mem::drop(conn.stream_stop_sending(stream_id, code));
+                mem::drop(conn.stream_reset_send(stream_id, code));
+                Ok(())
+            }
             ReceiveOutput::NewStream(NewStreamType::Http) => {
                 self.base_handler.add_streams(
                     stream_id,
@@ -363,6 +481,10 @@ impl Http3ServerHandler {
                         HFrame::Goaway { .. } | HFrame::CancelPush { .. } => {
                             Err(Error::HttpFrameUnexpected)
                         }
+                        HFrame::Origin { .. } => {
+                            // ORIGIN frames are only meaningful for clients, ignore it.
+                            Ok(())
+                        }
                         HFrame::PriorityUpdatePush { element_id, priority } => {
                             // TODO: check if the element_id references a promised push stream or
                             // is greater than the maximum Push ID.
@@ -384,7 +506,7 @@ impl Http3ServerHandler {
                             Ok(())
                         }
                         _ => unreachable!(
-                            "we should only put MaxPushId, Goaway and PriorityUpdates into control_frames."
+                            "we should only put MaxPushId, Goaway, PriorityUpdates and Origin into control_frames."
                         ),
                     }?;
                 }
@@ -417,4 +539,28 @@ impl Http3ServerHandler {
         }
         res
     }
+
+    /// Request data are returned in chunks as they were received, without copying them.
+    /// A chunk is at most the rest of a DATA frame. `None` is returned if no data is available.
+    /// The bool is true when the end of the request body has been reached.
+    ///
+    /// # Errors
+    ///
+    /// It returns an error if a stream does not exist or an error happen while reading a stream,
+    /// e.g. early close, protocol error, etc.
+    pub fn read_data_chunk(
+        &mut self,
+        conn: &mut Connection,
+        now: Instant,
+        stream_id: StreamId,
+    ) -> Res<(Option<Bytes>, bool)> {
+        qinfo!([self], "read_data_chunk from stream {}.", stream_id);
+        let res = self.base_handler.read_data_chunk(conn, stream_id);
+        if let Err(e) = &res {
+            if e.connection_error() {
+                self.close(conn, now, e);
+            }
+        }
+        res
+    }
 }
@@ -9,10 +9,11 @@ use std::{
     convert::TryFrom,
 };
 
-use neqo_common::{qtrace, Encoder};
+use ::qlog::events::h3::{H3Owner, H3StreamType};
+use neqo_common::{qlog::NeqoQlog, qtrace, Encoder};
 use neqo_transport::{Connection, StreamId, StreamType};
 
-use crate::{frames::HFrame, BufferedStream, Http3StreamType, RecvStream, Res};
+use crate::{frames::HFrame, qlog, BufferedStream, Http3StreamType, RecvStream, Res};
 
 pub const HTTP3_UNI_STREAM_TYPE_CONTROL: u64 = 0x0;
 
@@ -22,6 +23,7 @@ pub(crate) struct ControlStreamLocal {
     stream: BufferedStream,
     /// `stream_id`s of outstanding request streams
     outstanding_priority_update: VecDeque<StreamId>,
+    qlog: NeqoQlog,
 }
 
 impl ::std::fmt::Display for ControlStreamLocal {
@@ -35,13 +37,22 @@ impl ControlStreamLocal {
         Self {
             stream: BufferedStream::default(),
             outstanding_priority_update: VecDeque::new(),
+            qlog: NeqoQlog::disabled(),
         }
     }
 
+    /// Frames are queued without access to the connection, so they are logged here.
+    pub fn set_qlog(&mut self, qlog: NeqoQlog) {
+        self.qlog = qlog;
+    }
+
     /// Add a new frame that needs to be send.
     pub fn queue_frame(&mut self, f: &HFrame) {
         let mut enc = Encoder::default();
         f.encode(&mut enc);
+        if let Some(stream_id) = self.stream_id() {
+            qlog::h3_frame_created(&mut self.qlog, stream_id, f, enc.as_ref());
+        }
         self.stream.buffer(enc.as_ref());
     }
 
@@ -83,6 +94,9 @@ impl ControlStreamLocal {
                 let mut enc = Encoder::new();
                 hframe.encode(&mut enc);
                 if self.stream.send_atomic(conn, enc.as_ref())? {
+                    if let Some(stream_id) = self.stream_id() {
+                        qlog::h3_frame_created(conn.qlog_mut(), stream_id, &hframe, enc.as_ref());
+                    }
                     stream.priority_update_sent();
                 } else {
                     self.outstanding_priority_update.push_front(update_id);
@@ -96,9 +110,18 @@ impl ControlStreamLocal {
     /// Create a control stream.
     pub fn create(&mut self, conn: &mut Connection) -> Res<()> {
         qtrace!([self], "Create a control stream.");
-        self.stream.init(conn.stream_create(StreamType::UniDi)?);
+        let stream_id = conn.stream_create(StreamType::UniDi)?;
+        self.stream.init(stream_id);
         self.stream
             .buffer(&[u8::try_from(HTTP3_UNI_STREAM_TYPE_CONTROL).unwrap()]);
+        qlog::h3_stream_type_set(
+            conn.qlog_mut(),
+            H3Owner::Local,
+            stream_id,
+            H3StreamType::Control,
+            Some(HTTP3_UNI_STREAM_TYPE_CONTROL),
+            None,
+        );
         Ok(())
     }
 
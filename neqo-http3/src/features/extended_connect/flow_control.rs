@@ -0,0 +1,214 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Session-level flow control for WebTransport, draft-ietf-webtrans-http3-09, section 5.
+//
+// A limit is only enforced in a direction if the receiving endpoint advertised it in its
+// SETTINGS. Without the settings only QUIC flow control applies, as before.
+
+use neqo_transport::StreamType;
+
+use crate::{
+    settings::{HSettingType, HSettings},
+    Error, Http3Parameters, Res,
+};
+
+/// The limit a peer gave us, for data or for the number of streams.
+#[derive(Debug, Default)]
+pub(crate) struct SenderLimit {
+    max: Option<u64>,
+    used: u64,
+    blocked_at: Option<u64>,
+}
+
+impl SenderLimit {
+    fn new(max: Option<u64>) -> Self {
+        Self {
+            max,
+            ..Self::default()
+        }
+    }
+
+    /// The remaining credit, `None` if there is no limit.
+    pub fn available(&self) -> Option<u64> {
+        self.max.map(|max| max - self.used)
+    }
+
+    pub fn consume(&mut self, n: u64) {
+        debug_assert!(self.available().map_or(true, |a| a >= n));
+        self.used += n;
+    }
+
+    /// Update the limit. Returns true if this unblocked a sender that was blocked.
+    pub fn update(&mut self, max: u64) -> bool {
+        match self.max {
+            Some(old) if old < max => {
+                self.max = Some(max);
+                self.blocked_at.map_or(false, |b| b < max)
+            }
+            _ => false,
+        }
+    }
+
+    /// Record that the sender is blocked and returns the limit it is blocked at, but only the
+    /// first time for each limit value, so that a BLOCKED capsule is sent only once.
+    pub fn blocked(&mut self) -> Option<u64> {
+        let max = self.max?;
+        if self.blocked_at == Some(max) {
+            None
+        } else {
+            self.blocked_at = Some(max);
+            Some(max)
+        }
+    }
+}
+
+/// A limit we gave the peer, for data or for the number of streams.
+#[derive(Debug, Default)]
+pub(crate) struct ReceiverLimit {
+    max: Option<u64>,
+    window: u64,
+    used: u64,
+    retired: u64,
+    update_pending: bool,
+}
+
+impl ReceiverLimit {
+    fn new(max: Option<u64>) -> Self {
+        Self {
+            max,
+            window: max.unwrap_or(0),
+            ..Self::default()
+        }
+    }
+
+    /// # Errors
+    ///
+    /// `WebTransportFlowControl` if the peer exceeded the limit.
+    pub fn consume(&mut self, n: u64) -> Res<()> {
+        self.used += n;
+        if self.max.map_or(false, |max| self.used > max) {
+            return Err(Error::WebTransportFlowControl);
+        }
+        Ok(())
+    }
+
+    /// The application has read data or a stream is gone. The limit is increased once at least
+    /// half of the window can be given back to the peer.
+    pub fn retire(&mut self, n: u64) {
+        self.retired += n;
+        if let Some(max) = self.max {
+            if self.retired + self.window >= max + (self.window + 1) / 2 {
+                self.max = Some(self.retired + self.window);
+                self.update_pending = true;
+            }
+        }
+    }
+
+    /// Returns the new limit if it needs to be sent to the peer.
+    pub fn pending_update(&mut self) -> Option<u64> {
+        if self.update_pending {
+            self.update_pending = false;
+            self.max
+        } else {
+            None
+        }
+    }
+
+    pub fn has_pending_update(&self) -> bool {
+        self.update_pending
+    }
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SessionFlowControl {
+    pub send_data: SenderLimit,
+    send_streams_bidi: SenderLimit,
+    send_streams_uni: SenderLimit,
+    pub recv_data: ReceiverLimit,
+    recv_streams_bidi: ReceiverLimit,
+    recv_streams_uni: ReceiverLimit,
+}
+
+impl SessionFlowControl {
+    /// `remote` are the peer's settings, if they are known.
+    pub fn new(local: &Http3Parameters, remote: Option<&HSettings>) -> Self {
+        let remote_limit =
+            |setting: HSettingType| remote.and_then(|settings| settings.get_if_present(setting));
+        Self {
+            send_data: SenderLimit::new(remote_limit(HSettingType::WebTransportInitialMaxData)),
+            send_streams_bidi: SenderLimit::new(remote_limit(
+                HSettingType::WebTransportInitialMaxStreamsBidi,
+            )),
+            send_streams_uni: SenderLimit::new(remote_limit(
+                HSettingType::WebTransportInitialMaxStreamsUni,
+            )),
+            recv_data: ReceiverLimit::new(local.get_webtransport_max_data()),
+            recv_streams_bidi: ReceiverLimit::new(local.get_webtransport_max_streams_bidi()),
+            recv_streams_uni: ReceiverLimit::new(local.get_webtransport_max_streams_uni()),
+        }
+    }
+
+    pub fn send_streams(&mut self, stream_type: StreamType) -> &mut SenderLimit {
+        match stream_type {
+            StreamType::BiDi => &mut self.send_streams_bidi,
+            StreamType::UniDi => &mut self.send_streams_uni,
+        }
+    }
+
+    pub fn recv_streams(&mut self, stream_type: StreamType) -> &mut ReceiverLimit {
+        match stream_type {
+            StreamType::BiDi => &mut self.recv_streams_bidi,
+            StreamType::UniDi => &mut self.recv_streams_uni,
+        }
+    }
+
+    pub fn has_pending_update(&self) -> bool {
+        self.recv_data.has_pending_update()
+            || self.recv_streams_bidi.has_pending_update()
+            || self.recv_streams_uni.has_pending_update()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReceiverLimit, SenderLimit};
+    use crate::Error;
+
+    #[test]
+    fn sender_limit() {
+        let mut l = SenderLimit::new(Some(10));
+        l.consume(10);
+        assert_eq!(l.available(), Some(0));
+        assert_eq!(l.blocked(), Some(10));
+        // Blocked is only reported once per limit.
+        assert_eq!(l.blocked(), None);
+        assert!(!l.update(5));
+        assert!(l.update(20));
+        assert_eq!(l.available(), Some(10));
+
+        let mut unlimited = SenderLimit::new(None);
+        assert_eq!(unlimited.available(), None);
+        assert_eq!(unlimited.blocked(), None);
+    }
+
+    #[test]
+    fn receiver_limit() {
+        let mut l = ReceiverLimit::new(Some(10));
+        l.consume(10).unwrap();
+        assert_eq!(l.consume(1), Err(Error::WebTransportFlowControl));
+
+        let mut l = ReceiverLimit::new(Some(10));
+        l.consume(10).unwrap();
+        l.retire(4);
+        assert_eq!(l.pending_update(), None);
+        l.retire(1);
+        assert_eq!(l.pending_update(), Some(15));
+        assert_eq!(l.pending_update(), None);
+        l.consume(5).unwrap();
+        assert_eq!(l.consume(1), Err(Error::WebTransportFlowControl));
+    }
+}
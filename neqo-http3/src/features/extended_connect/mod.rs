@@ -6,13 +6,14 @@
 
 #![allow(clippy::module_name_repetitions)]
 
+pub(crate) mod flow_control;
 pub(crate) mod webtransport_session;
 pub(crate) mod webtransport_streams;
 
 use std::fmt::Debug;
 
 use neqo_common::Header;
-use neqo_transport::{AppError, StreamId};
+use neqo_transport::{AppError, StreamId, StreamType};
 pub(crate) use webtransport_session::WebTransportSession;
 
 use crate::{
@@ -29,6 +30,26 @@ pub enum SessionCloseReason {
     Clean { error: u32, message: String },
 }
 
+/// The session-level limit that blocks, or stopped blocking, a `WebTransport` session.
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum SessionLimit {
+    /// The peer's limit on the number of bidirectional streams.
+    BidiStreams,
+    /// The peer's limit on the number of unidirectional streams.
+    UniStreams,
+    /// The peer's limit on the amount of data sent on all streams of the session.
+    Data,
+}
+
+impl From<StreamType> for SessionLimit {
+    fn from(stream_type: StreamType) -> Self {
+        match stream_type {
+            StreamType::BiDi => Self::BidiStreams,
+            StreamType::UniDi => Self::UniStreams,
+        }
+    }
+}
+
 impl From<CloseType> for SessionCloseReason {
     fn from(close_type: CloseType) -> SessionCloseReason {
         match close_type {
@@ -60,6 +81,9 @@ pub(crate) trait ExtendedConnectEvents: Debug {
     );
     fn extended_connect_new_stream(&self, stream_info: Http3StreamInfo);
     fn new_datagram(&self, session_id: StreamId, datagram: Vec<u8>);
+    fn session_blocked(&self, session_id: StreamId, limit: SessionLimit);
+    fn session_unblocked(&self, session_id: StreamId, limit: SessionLimit);
+    fn session_draining(&self, session_id: StreamId);
 }
 
 #[derive(Debug, PartialEq, Copy, Clone, Eq)]
@@ -4,10 +4,11 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::convert::TryFrom;
+use std::{convert::TryFrom, time::Duration};
 
 use neqo_common::Encoder;
 use neqo_transport::Error as TransportError;
+use test_fixture::now;
 
 use crate::{
     features::extended_connect::tests::webtransport::{
@@ -76,6 +77,41 @@ fn datagrams() {
     do_datagram_test(&mut wt, &mut wt_session);
 }
 
+/// A datagram that is past its deadline is not sent.
+#[test]
+fn datagrams_deadline() {
+    let mut wt = WtTest::new();
+    let mut wt_session = wt.create_wt_session();
+    let session_id = wt_session.stream_id();
+
+    assert_eq!(
+        wt_session.send_datagram_with_deadline(DGRAM, None, Some(now())),
+        Ok(())
+    );
+    assert_eq!(
+        wt.client
+            .webtransport_send_datagram_with_deadline(session_id, DGRAM, None, Some(now())),
+        Ok(())
+    );
+    wt.exchange_packets();
+    wt.check_no_datagram_received_client();
+    wt.check_no_datagram_received_server();
+
+    let deadline = now() + Duration::from_secs(1);
+    assert_eq!(
+        wt_session.send_datagram_with_deadline(DGRAM, None, Some(deadline)),
+        Ok(())
+    );
+    assert_eq!(
+        wt.client
+            .webtransport_send_datagram_with_deadline(session_id, DGRAM, None, Some(deadline)),
+        Ok(())
+    );
+    wt.exchange_packets();
+    wt.check_datagram_received_client(session_id, DGRAM);
+    wt.check_datagram_received_server(&wt_session, DGRAM);
+}
+
 #[test]
 fn datagrams_server_only() {
     let mut wt = WtTest::new_with_params(
@@ -0,0 +1,134 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use neqo_common::event::Provider;
+use neqo_transport::{StreamId, StreamType};
+use test_fixture::now;
+
+use crate::{
+    features::extended_connect::tests::webtransport::WtTest, Error, Http3ClientEvent,
+    Http3ServerEvent, Http3State, Priority, WebTransportEvent,
+};
+
+fn session_draining_events_client(wt: &mut WtTest, session_id: StreamId) -> usize {
+    wt.client
+        .events()
+        .filter(|e| {
+            matches!(
+                e,
+                Http3ClientEvent::WebTransport(WebTransportEvent::SessionDraining {
+                    session_id: id
+                }) if *id == session_id
+            )
+        })
+        .count()
+}
+
+#[test]
+fn wt_session_drain() {
+    let mut wt = WtTest::new();
+    let mut wt_session = wt.create_wt_session();
+
+    wt_session.drain_session().unwrap();
+    // The capsule is only sent once.
+    wt_session.drain_session().unwrap();
+    wt.exchange_packets();
+    assert_eq!(
+        session_draining_events_client(&mut wt, wt_session.stream_id()),
+        1
+    );
+
+    // The session keeps working until it is closed.
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::UniDi);
+    wt.send_data_client(wt_stream, &[1, 2, 3]);
+    wt.close_stream_sending_client(wt_stream);
+    wt.receive_data_server(wt_stream, true, &[1, 2, 3], true);
+}
+
+#[test]
+fn wt_session_drain_closed() {
+    let mut wt = WtTest::new();
+    let mut wt_session = wt.create_wt_session();
+    wt.cancel_session_client(wt_session.stream_id());
+    assert_eq!(wt_session.drain_session(), Err(Error::InvalidStreamId));
+}
+
+#[test]
+fn wt_goaway_drains_sessions() {
+    let mut wt = WtTest::new();
+    let wt_session = wt.create_wt_session();
+
+    wt.server.goaway();
+    wt.exchange_packets();
+    assert_eq!(
+        session_draining_events_client(&mut wt, wt_session.stream_id()),
+        1
+    );
+    assert!(matches!(wt.client.state(), Http3State::GoingAway(_)));
+
+    // A second GOAWAY is not sent.
+    wt.server.goaway();
+    wt.exchange_packets();
+    assert_eq!(
+        session_draining_events_client(&mut wt, wt_session.stream_id()),
+        0
+    );
+
+    // Existing sessions still work.
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::BiDi);
+    wt.send_data_client(wt_stream, &[4, 5]);
+    wt.receive_data_server(wt_stream, true, &[4, 5], false);
+}
+
+#[test]
+fn goaway_rejects_later_requests() {
+    let mut wt = WtTest::new();
+    let before = wt
+        .client
+        .fetch(
+            now(),
+            "GET",
+            &("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    wt.exchange_packets();
+    assert!(wt
+        .server
+        .events()
+        .any(|e| matches!(e, Http3ServerEvent::Headers { .. })));
+
+    // The client sends a request before it sees the GOAWAY.
+    wt.server.goaway();
+    let after = wt
+        .client
+        .fetch(
+            now(),
+            "GET",
+            &("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    wt.exchange_packets();
+    assert!(!wt
+        .server
+        .events()
+        .any(|e| matches!(e, Http3ServerEvent::Headers { .. })));
+    assert_eq!(wt.client.state(), Http3State::GoingAway(after));
+
+    let code = Error::HttpRequestRejected.code();
+    let events = wt.client.events().collect::<Vec<_>>();
+    assert!(events.iter().any(|e| matches!(
+        e,
+        Http3ClientEvent::Reset { stream_id, error, .. } if *stream_id == after && *error == code
+    )));
+    assert!(!events.iter().any(|e| matches!(
+        e,
+        Http3ClientEvent::Reset { stream_id, .. } if *stream_id == before
+    )));
+}
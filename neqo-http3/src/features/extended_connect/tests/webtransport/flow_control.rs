@@ -0,0 +1,132 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use neqo_common::{event::Provider, Encoder};
+use neqo_transport::StreamType;
+
+use crate::{
+    features::extended_connect::{
+        tests::webtransport::{wt_default_parameters, WtTest},
+        webtransport_streams::WEBTRANSPORT_UNI_STREAM,
+        SessionCloseReason, SessionLimit,
+    },
+    Error, Http3ClientEvent, Http3ServerEvent, WebTransportEvent, WebTransportServerEvent,
+};
+
+fn session_blocked_client(wt: &mut WtTest, expected: SessionLimit) -> bool {
+    wt.client.events().any(|e| {
+        matches!(
+            e,
+            Http3ClientEvent::WebTransport(WebTransportEvent::SessionBlocked { limit, .. })
+                if limit == expected
+        )
+    })
+}
+
+fn session_unblocked_client(wt: &mut WtTest, expected: SessionLimit) -> bool {
+    wt.client.events().any(|e| {
+        matches!(
+            e,
+            Http3ClientEvent::WebTransport(WebTransportEvent::SessionUnblocked { limit, .. })
+                if limit == expected
+        )
+    })
+}
+
+#[test]
+fn wt_session_stream_limit() {
+    let mut wt = WtTest::new_with_params(
+        wt_default_parameters(),
+        wt_default_parameters().webtransport_max_streams_uni(1),
+    );
+    let wt_session = wt.create_wt_session();
+
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::UniDi);
+    assert_eq!(
+        wt.client
+            .webtransport_create_stream(wt_session.stream_id(), StreamType::UniDi),
+        Err(Error::StreamLimitError)
+    );
+    assert!(session_blocked_client(&mut wt, SessionLimit::UniStreams));
+    // Bidirectional streams are not limited.
+    wt.create_wt_stream_client(wt_session.stream_id(), StreamType::BiDi);
+
+    // Once the server has read the whole stream, the client may open another one.
+    wt.send_data_client(wt_stream, &[1, 2, 3]);
+    wt.close_stream_sending_client(wt_stream);
+    wt.receive_data_server(wt_stream, true, &[1, 2, 3], true);
+    wt.exchange_packets();
+    assert!(session_unblocked_client(&mut wt, SessionLimit::UniStreams));
+    wt.create_wt_stream_client(wt_session.stream_id(), StreamType::UniDi);
+}
+
+#[test]
+fn wt_session_data_limit() {
+    const DATA: &[u8] = &[7; 20];
+    let mut wt = WtTest::new_with_params(
+        wt_default_parameters(),
+        wt_default_parameters().webtransport_max_data(10),
+    );
+    let wt_session = wt.create_wt_session();
+
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::BiDi);
+    assert_eq!(wt.client.send_data(wt_stream, DATA), Ok(10));
+    assert!(session_blocked_client(&mut wt, SessionLimit::Data));
+    assert_eq!(wt.client.send_data(wt_stream, &DATA[10..]), Ok(0));
+
+    // Reading the data gives the credit back to the client.
+    wt.exchange_packets();
+    wt.receive_data_server(wt_stream, true, &DATA[..10], false);
+    wt.exchange_packets();
+    assert!(session_unblocked_client(&mut wt, SessionLimit::Data));
+    assert_eq!(wt.client.send_data(wt_stream, &DATA[10..]), Ok(10));
+}
+
+#[test]
+fn wt_session_limits_not_advertised() {
+    let mut wt = WtTest::new();
+    let wt_session = wt.create_wt_session();
+    for _ in 0..5 {
+        wt.create_wt_stream_client(wt_session.stream_id(), StreamType::UniDi);
+    }
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::BiDi);
+    wt.send_data_client(wt_stream, &[0; 1000]);
+}
+
+#[test]
+fn wt_session_stream_limit_violation() {
+    let mut wt = WtTest::new_with_params(
+        wt_default_parameters(),
+        wt_default_parameters().webtransport_max_streams_uni(1),
+    );
+    let wt_session = wt.create_wt_session();
+    let wt_stream = wt.create_wt_stream_client(wt_session.stream_id(), StreamType::UniDi);
+    wt.send_data_client(wt_stream, &[1]);
+
+    // Open a second stream, bypassing the client's own checks.
+    let mut enc = Encoder::default();
+    enc.encode_varint(WEBTRANSPORT_UNI_STREAM);
+    enc.encode_varint(wt_session.stream_id().as_u64());
+    let conn = wt.client.conn();
+    let stream_id = conn.stream_create(StreamType::UniDi).unwrap();
+    conn.stream_send(stream_id, enc.as_ref()).unwrap();
+    wt.exchange_packets();
+
+    let code = Error::WebTransportFlowControl.code();
+    assert!(wt.server.events().any(|e| matches!(
+        e,
+        Http3ServerEvent::WebTransport(WebTransportServerEvent::SessionClosed {
+            session,
+            reason: SessionCloseReason::Error(error),
+            ..
+        }) if session.stream_id() == wt_session.stream_id() && error == code
+    )));
+    wt.check_session_closed_event_client(
+        wt_session.stream_id(),
+        &SessionCloseReason::Error(code),
+        &None,
+    );
+}
@@ -5,6 +5,8 @@
 // except according to those terms.
 
 mod datagrams;
+mod drain;
+mod flow_control;
 mod negotiation;
 mod sessions;
 mod streams;
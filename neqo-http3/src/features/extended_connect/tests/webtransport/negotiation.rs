@@ -7,13 +7,13 @@
 use std::time::Duration;
 
 use neqo_common::{event::Provider, Encoder};
-use neqo_crypto::AuthenticationStatus;
+use neqo_crypto::{AuthenticationStatus, ZeroRttCheckResult, ZeroRttChecker};
 use neqo_transport::{Connection, ConnectionError, StreamType};
 use test_fixture::{default_server_h3, now};
 
 use super::{connect, default_http3_client, default_http3_server, exchange_packets};
 use crate::{
-    settings::{HSetting, HSettingType, HSettings},
+    settings::{HSetting, HSettingType, HSettings, HttpZeroRttChecker},
     Error, HFrame, Http3Client, Http3ClientEvent, Http3Parameters, Http3Server, Http3State,
     WebTransportEvent,
 };
@@ -130,6 +130,71 @@ fn zero_rtt(
     check_wt_event(&mut client, client_resumed, server_resumed);
 }
 
+#[test]
+fn zero_rtt_wt_limits() {
+    let unlimited = HttpZeroRttChecker::save(&Http3Parameters::default().webtransport(true));
+    let limited = HttpZeroRttChecker::save(
+        &Http3Parameters::default()
+            .webtransport(true)
+            .webtransport_max_data(10),
+    );
+    let check =
+        |params: Http3Parameters, token: &[u8]| HttpZeroRttChecker::new(params).check(token);
+
+    // Adding a limit that was not remembered lowers it from unlimited.
+    assert_eq!(
+        check(
+            Http3Parameters::default()
+                .webtransport(true)
+                .webtransport_max_data(10),
+            &unlimited
+        ),
+        ZeroRttCheckResult::Reject
+    );
+    assert_eq!(
+        check(
+            Http3Parameters::default()
+                .webtransport(true)
+                .webtransport_max_streams_bidi(1),
+            &limited
+        ),
+        ZeroRttCheckResult::Reject
+    );
+    assert_eq!(
+        check(
+            Http3Parameters::default()
+                .webtransport(true)
+                .webtransport_max_data(9),
+            &limited
+        ),
+        ZeroRttCheckResult::Reject
+    );
+
+    assert_eq!(
+        check(
+            Http3Parameters::default()
+                .webtransport(true)
+                .webtransport_max_data(10),
+            &limited
+        ),
+        ZeroRttCheckResult::Accept
+    );
+    assert_eq!(
+        check(Http3Parameters::default().webtransport(true), &limited),
+        ZeroRttCheckResult::Accept
+    );
+    // Without WebTransport in the remembered settings, its limits do not matter.
+    assert_eq!(
+        check(
+            Http3Parameters::default()
+                .webtransport(true)
+                .webtransport_max_data(10),
+            &HttpZeroRttChecker::save(&Http3Parameters::default())
+        ),
+        ZeroRttCheckResult::Accept
+    );
+}
+
 #[test]
 fn zero_rtt_wt_settings() {
     zero_rtt(
@@ -6,15 +6,19 @@
 
 #![allow(clippy::module_name_repetitions)]
 
-use std::{any::Any, cell::RefCell, collections::BTreeSet, mem, rc::Rc};
+use std::{any::Any, cell::RefCell, collections::BTreeSet, mem, rc::Rc, time::Instant};
 
 use neqo_common::{qtrace, Encoder, Header, MessageType, Role};
 use neqo_qpack::{QPackDecoder, QPackEncoder};
-use neqo_transport::{streams::SendOrder, Connection, DatagramTracking, StreamId};
+use neqo_transport::{streams::SendOrder, Connection, DatagramTracking, StreamId, StreamType};
 
-use super::{ExtendedConnectEvents, ExtendedConnectType, SessionCloseReason};
+use super::{
+    flow_control::SessionFlowControl, ExtendedConnectEvents, ExtendedConnectType,
+    SessionCloseReason, SessionLimit,
+};
 use crate::{
     frames::{FrameReader, StreamReaderRecvStreamWrapper, WebTransportFrame},
+    qlog,
     recv_message::{RecvMessage, RecvMessageInfo},
     send_message::SendMessage,
     CloseType, Error, HFrame, Http3StreamInfo, Http3StreamType, HttpRecvStream,
@@ -48,6 +52,9 @@ pub(crate) struct WebTransportSession {
     send_streams: BTreeSet<StreamId>,
     recv_streams: BTreeSet<StreamId>,
     role: Role,
+    flow_control: SessionFlowControl,
+    drain_sent: bool,
+    draining: bool,
 }
 
 impl ::std::fmt::Display for WebTransportSession {
@@ -64,6 +71,7 @@ impl WebTransportSession {
         role: Role,
         qpack_encoder: Rc<RefCell<QPackEncoder>>,
         qpack_decoder: Rc<RefCell<QPackDecoder>>,
+        flow_control: SessionFlowControl,
     ) -> Self {
         let stream_event_listener = Rc::new(RefCell::new(WebTransportSessionListener::default()));
         Self {
@@ -94,6 +102,9 @@ impl WebTransportSession {
             send_streams: BTreeSet::new(),
             recv_streams: BTreeSet::new(),
             role,
+            flow_control,
+            drain_sent: false,
+            draining: false,
         }
     }
 
@@ -108,6 +119,7 @@ impl WebTransportSession {
         role: Role,
         mut control_stream_recv: Box<dyn RecvStream>,
         mut control_stream_send: Box<dyn SendStream>,
+        flow_control: SessionFlowControl,
     ) -> Self {
         let stream_event_listener = Rc::new(RefCell::new(WebTransportSessionListener::default()));
         control_stream_recv
@@ -129,6 +141,9 @@ impl WebTransportSession {
             send_streams: BTreeSet::new(),
             recv_streams: BTreeSet::new(),
             role,
+            flow_control,
+            drain_sent: false,
+            draining: false,
         }
     }
 
@@ -153,6 +168,7 @@ impl WebTransportSession {
         debug_assert!(out == ReceiveOutput::NoOutput);
         self.maybe_check_headers();
         self.read_control_stream(conn)?;
+        self.send_flow_control_updates(conn)?;
         Ok((ReceiveOutput::NoOutput, self.state == SessionState::Done))
     }
 
@@ -191,6 +207,7 @@ impl WebTransportSession {
 
     fn send(&mut self, conn: &mut Connection) -> Res<()> {
         self.control_stream_send.send(conn)?;
+        self.send_flow_control_updates(conn)?;
         if self.control_stream_send.done() {
             self.state = SessionState::Done;
         }
@@ -199,6 +216,7 @@ impl WebTransportSession {
 
     fn has_data_to_send(&self) -> bool {
         self.control_stream_send.has_data_to_send()
+            || (self.is_active() && self.flow_control.has_pending_update())
     }
 
     fn done(&self) -> bool {
@@ -318,11 +336,130 @@ impl WebTransportSession {
     }
 
     pub fn remove_recv_stream(&mut self, stream_id: StreamId) {
-        self.recv_streams.remove(&stream_id);
+        if self.recv_streams.remove(&stream_id) {
+            self.maybe_retire_remote_stream(stream_id);
+        }
     }
 
     pub fn remove_send_stream(&mut self, stream_id: StreamId) {
-        self.send_streams.remove(&stream_id);
+        if self.send_streams.remove(&stream_id) {
+            self.maybe_retire_remote_stream(stream_id);
+        }
+    }
+
+    /// A stream opened by the peer counts against our stream limit until both of its sides are
+    /// closed.
+    fn maybe_retire_remote_stream(&mut self, stream_id: StreamId) {
+        if !stream_id.is_self_initiated(self.role)
+            && !self.recv_streams.contains(&stream_id)
+            && !self.send_streams.contains(&stream_id)
+        {
+            self.flow_control
+                .recv_streams(stream_id.stream_type())
+                .retire(1);
+        }
+    }
+
+    /// Returns false if the peer's session limit does not allow another stream of this type.
+    pub fn local_stream_available(&mut self, stream_type: StreamType) -> bool {
+        self.flow_control
+            .send_streams(stream_type)
+            .available()
+            .map_or(true, |a| a > 0)
+    }
+
+    pub fn local_stream_opened(&mut self, stream_type: StreamType) {
+        self.flow_control.send_streams(stream_type).consume(1);
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the control stream cannot be written to.
+    pub fn streams_blocked(&mut self, conn: &mut Connection, stream_type: StreamType) -> Res<()> {
+        if let Some(max) = self.flow_control.send_streams(stream_type).blocked() {
+            self.send_capsule(
+                conn,
+                &WebTransportFrame::StreamsBlocked { stream_type, max },
+            )?;
+            self.events
+                .session_blocked(self.session_id, SessionLimit::from(stream_type));
+        }
+        Ok(())
+    }
+
+    /// The amount of data that may be sent on the streams of this session, `None` if the peer
+    /// did not set a limit.
+    #[must_use]
+    pub fn send_credit(&self) -> Option<u64> {
+        self.flow_control.send_data.available()
+    }
+
+    pub fn data_sent(&mut self, amount: u64) {
+        self.flow_control.send_data.consume(amount);
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the control stream cannot be written to.
+    pub fn data_blocked(&mut self, conn: &mut Connection) -> Res<()> {
+        if let Some(max) = self.flow_control.send_data.blocked() {
+            self.send_capsule(conn, &WebTransportFrame::DataBlocked(max))?;
+            self.events
+                .session_blocked(self.session_id, SessionLimit::Data);
+        }
+        Ok(())
+    }
+
+    /// # Errors
+    ///
+    /// `WebTransportFlowControl` if the peer sent more data than the session allows.
+    pub fn data_received(&mut self, amount: u64) -> Res<()> {
+        self.flow_control.recv_data.consume(amount)
+    }
+
+    /// # Errors
+    ///
+    /// Returns an error if the control stream cannot be written to.
+    pub fn data_read(&mut self, conn: &mut Connection, amount: u64) -> Res<()> {
+        self.flow_control.recv_data.retire(amount);
+        self.send_flow_control_updates(conn)
+    }
+
+    /// Unread data of a stream that has been reset. The new limit is sent with the next
+    /// `send`.
+    pub fn data_discarded(&mut self, amount: u64) {
+        self.flow_control.recv_data.retire(amount);
+    }
+
+    /// # Errors
+    ///
+    /// `WebTransportFlowControl` if the peer opened more streams than the session allows.
+    pub fn remote_stream_opened(&mut self, stream_type: StreamType) -> Res<()> {
+        self.flow_control.recv_streams(stream_type).consume(1)
+    }
+
+    fn send_capsule(&mut self, conn: &mut Connection, frame: &WebTransportFrame) -> Res<()> {
+        let mut encoder = Encoder::default();
+        frame.encode(&mut encoder);
+        self.control_stream_send
+            .send_data_atomic(conn, encoder.as_ref())?;
+        qlog::h3_frame_created(conn.qlog_mut(), self.session_id, frame, encoder.as_ref());
+        Ok(())
+    }
+
+    fn send_flow_control_updates(&mut self, conn: &mut Connection) -> Res<()> {
+        if !self.is_active() || !self.flow_control.has_pending_update() {
+            return Ok(());
+        }
+        if let Some(max) = self.flow_control.recv_data.pending_update() {
+            self.send_capsule(conn, &WebTransportFrame::MaxData(max))?;
+        }
+        for stream_type in [StreamType::BiDi, StreamType::UniDi] {
+            if let Some(max) = self.flow_control.recv_streams(stream_type).pending_update() {
+                self.send_capsule(conn, &WebTransportFrame::MaxStreams { stream_type, max })?;
+            }
+        }
+        Ok(())
     }
 
     #[must_use]
@@ -341,41 +478,109 @@ impl WebTransportSession {
     ///
     /// It may return an error if the frame is not correctly decoded.
     pub fn read_control_stream(&mut self, conn: &mut Connection) -> Res<()> {
-        let (f, fin) = self
-            .frame_reader
-            .receive::<WebTransportFrame>(&mut StreamReaderRecvStreamWrapper::new(
-                conn,
-                &mut self.control_stream_recv,
-            ))
-            .map_err(|_| Error::HttpGeneralProtocolStream)?;
-        qtrace!([self], "Received frame: {:?} fin={}", f, fin);
-        if let Some(WebTransportFrame::CloseSession { error, message }) = f {
-            self.events.session_end(
-                ExtendedConnectType::WebTransport,
-                self.session_id,
-                SessionCloseReason::Clean { error, message },
-                None,
-            );
-            self.state = if fin {
-                SessionState::Done
-            } else {
-                SessionState::FinPending
-            };
-        } else if fin {
-            self.events.session_end(
-                ExtendedConnectType::WebTransport,
-                self.session_id,
-                SessionCloseReason::Clean {
-                    error: 0,
-                    message: String::new(),
-                },
-                None,
-            );
-            self.state = SessionState::Done;
+        loop {
+            let (f, fin) = self
+                .frame_reader
+                .receive::<WebTransportFrame>(&mut StreamReaderRecvStreamWrapper::new(
+                    conn,
+                    &mut self.control_stream_recv,
+                    self.session_id,
+                ))
+                .map_err(|_| Error::HttpGeneralProtocolStream)?;
+            qtrace!([self], "Received frame: {:?} fin={}", f, fin);
+            match f {
+                Some(WebTransportFrame::CloseSession { error, message }) => {
+                    self.events.session_end(
+                        ExtendedConnectType::WebTransport,
+                        self.session_id,
+                        SessionCloseReason::Clean { error, message },
+                        None,
+                    );
+                    self.state = if fin {
+                        SessionState::Done
+                    } else {
+                        SessionState::FinPending
+                    };
+                    return Ok(());
+                }
+                Some(WebTransportFrame::DrainSession) => self.set_draining(),
+                Some(WebTransportFrame::MaxData(max)) => {
+                    if self.flow_control.send_data.update(max) {
+                        self.events
+                            .session_unblocked(self.session_id, SessionLimit::Data);
+                    }
+                }
+                Some(WebTransportFrame::MaxStreams { stream_type, max }) => {
+                    if self.flow_control.send_streams(stream_type).update(max) {
+                        self.events
+                            .session_unblocked(self.session_id, SessionLimit::from(stream_type));
+                    }
+                }
+                Some(WebTransportFrame::DataBlocked(max)) => {
+                    qtrace!([self], "Peer is blocked by the session data limit {}", max);
+                }
+                Some(WebTransportFrame::StreamsBlocked { stream_type, max }) => {
+                    qtrace!(
+                        [self],
+                        "Peer is blocked by the session {:?} stream limit {}",
+                        stream_type,
+                        max
+                    );
+                }
+                None => {
+                    if !fin {
+                        return Ok(());
+                    }
+                }
+            }
+            if fin {
+                self.events.session_end(
+                    ExtendedConnectType::WebTransport,
+                    self.session_id,
+                    SessionCloseReason::Clean {
+                        error: 0,
+                        message: String::new(),
+                    },
+                    None,
+                );
+                self.state = SessionState::Done;
+                return Ok(());
+            }
+        }
+    }
+
+    /// Ask the peer to wind down the session. The DRAIN_WEBTRANSPORT_SESSION capsule is sent
+    /// only once.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the session is not active, or an error if the control stream cannot
+    /// be written to.
+    pub fn drain(&mut self, conn: &mut Connection) -> Res<()> {
+        if !self.is_active() {
+            return Err(Error::InvalidStreamId);
+        }
+        if !self.drain_sent {
+            self.send_capsule(conn, &WebTransportFrame::DrainSession)?;
+            self.drain_sent = true;
         }
         Ok(())
     }
 
+    /// The peer sent a GOAWAY, the session will not outlive the connection.
+    pub fn goaway_received(&mut self) {
+        if self.is_active() {
+            self.set_draining();
+        }
+    }
+
+    fn set_draining(&mut self) {
+        if !self.draining {
+            self.draining = true;
+            self.events.session_draining(self.session_id);
+        }
+    }
+
     /// # Errors
     ///
     /// Return an error if the stream was closed on the transport layer, but that information is not
@@ -386,10 +591,7 @@ impl WebTransportSession {
             error,
             message: message.to_string(),
         };
-        let mut encoder = Encoder::default();
-        close_frame.encode(&mut encoder);
-        self.control_stream_send
-            .send_data_atomic(conn, encoder.as_ref())?;
+        self.send_capsule(conn, &close_frame)?;
         self.control_stream_send.close(conn)?;
         self.state = if self.control_stream_send.done() {
             SessionState::Done
@@ -403,6 +605,8 @@ impl WebTransportSession {
         self.control_stream_send.send_data(conn, buf)
     }
 
+    /// Send a datagram, which is dropped if it hasn't been sent by `deadline`.
+    ///
     /// # Errors
     ///
     /// Returns an error if the datagram exceeds the remote datagram size limit.
@@ -411,13 +615,14 @@ impl WebTransportSession {
         conn: &mut Connection,
         buf: &[u8],
         id: impl Into<DatagramTracking>,
+        deadline: Option<Instant>,
     ) -> Res<()> {
         qtrace!([self], "send_datagram state={:?}", self.state);
         if let SessionState::Active = self.state {
             let mut dgram_data = Encoder::default();
             dgram_data.encode_varint(self.session_id.as_u64() / 4);
             dgram_data.encode(buf);
-            conn.send_datagram(dgram_data.as_ref(), id)?;
+            conn.send_datagram_with_deadline(dgram_data.as_ref(), id, deadline)?;
         } else {
             debug_assert!(false);
             return Err(Error::Unavailable);
@@ -4,7 +4,7 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{cell::RefCell, rc::Rc};
+use std::{cell::RefCell, cmp::min, convert::TryFrom, rc::Rc};
 
 use neqo_common::Encoder;
 use neqo_transport::{Connection, RecvStreamStats, SendStreamStats, StreamId};
@@ -25,6 +25,11 @@ pub(crate) struct WebTransportRecvStream {
     session: Rc<RefCell<WebTransportSession>>,
     session_id: StreamId,
     fin: bool,
+    /// The size of the stream type and session id that precede the data on a remote stream.
+    header_size: u64,
+    /// The amount of data that has been counted against the session data limit.
+    received: u64,
+    read: u64,
 }
 
 impl WebTransportRecvStream {
@@ -33,13 +38,27 @@ impl WebTransportRecvStream {
         session_id: StreamId,
         events: Box<dyn RecvStreamEvents>,
         session: Rc<RefCell<WebTransportSession>>,
+        local: bool,
     ) -> Self {
+        let header_size = if local {
+            0
+        } else {
+            let stream_type = if stream_id.is_uni() {
+                WEBTRANSPORT_UNI_STREAM
+            } else {
+                WEBTRANSPORT_STREAM
+            };
+            (Encoder::varint_len(stream_type) + Encoder::varint_len(session_id.as_u64())) as u64
+        };
         Self {
             stream_id,
             events,
             session_id,
             session,
             fin: false,
+            header_size,
+            received: 0,
+            read: 0,
         }
     }
 
@@ -55,7 +74,17 @@ impl Stream for WebTransportRecvStream {
 }
 
 impl RecvStream for WebTransportRecvStream {
-    fn receive(&mut self, _conn: &mut Connection) -> Res<(ReceiveOutput, bool)> {
+    fn receive(&mut self, conn: &mut Connection) -> Res<(ReceiveOutput, bool)> {
+        // The stream may have been closed on the transport layer already.
+        let received = conn.recv_stream_stats(self.stream_id).map_or(0, |stats| {
+            stats.bytes_received().saturating_sub(self.header_size)
+        });
+        if received > self.received {
+            self.session
+                .borrow_mut()
+                .data_received(received - self.received)?;
+            self.received = received;
+        }
         self.events.data_readable(self.get_info());
         Ok((ReceiveOutput::NoOutput, false))
     }
@@ -64,16 +93,25 @@ impl RecvStream for WebTransportRecvStream {
         if !matches!(close_type, CloseType::ResetApp(_)) {
             self.events.recv_closed(self.get_info(), close_type);
         }
-        self.session.borrow_mut().remove_recv_stream(self.stream_id);
+        let mut session = self.session.borrow_mut();
+        session.remove_recv_stream(self.stream_id);
+        // Data that will not be read anymore does not count against the session limit.
+        session.data_discarded(self.received.saturating_sub(self.read));
+        self.read = self.received;
         Ok(())
     }
 
     fn read_data(&mut self, conn: &mut Connection, buf: &mut [u8]) -> Res<(usize, bool)> {
         let (amount, fin) = conn.stream_recv(self.stream_id, buf)?;
         self.fin = fin;
+        let mut session = self.session.borrow_mut();
         if fin {
-            self.session.borrow_mut().remove_recv_stream(self.stream_id);
+            session.remove_recv_stream(self.stream_id);
         }
+        let read = u64::try_from(amount).unwrap();
+        self.read += read;
+        // This also sends new session limits, if any.
+        session.data_read(conn, read)?;
         Ok((amount, fin))
     }
 
@@ -207,12 +245,23 @@ impl SendStream for WebTransportSendStream {
 
     fn send_data(&mut self, conn: &mut Connection, buf: &[u8]) -> Res<usize> {
         self.send(conn)?;
-        if self.state == WebTransportSenderStreamState::SendingData {
-            let sent = conn.stream_send(self.stream_id, buf)?;
-            Ok(sent)
+        if self.state != WebTransportSenderStreamState::SendingData {
+            return Ok(0);
+        }
+        let mut session = self.session.borrow_mut();
+        let credit = session.send_credit().map_or(buf.len(), |c| {
+            min(usize::try_from(c).unwrap_or(usize::MAX), buf.len())
+        });
+        let sent = if credit > 0 {
+            conn.stream_send(self.stream_id, &buf[..credit])?
         } else {
-            Ok(0)
+            0
+        };
+        session.data_sent(u64::try_from(sent).unwrap());
+        if credit < buf.len() {
+            session.data_blocked(conn)?;
         }
+        Ok(sent)
     }
 
     fn set_sendorder(&mut self, conn: &mut Connection, sendorder: Option<i64>) -> Res<()> {
@@ -20,6 +20,7 @@ pub const H3_FRAME_TYPE_CANCEL_PUSH: HFrameType = 0x3;
 pub const H3_FRAME_TYPE_SETTINGS: HFrameType = 0x4;
 pub const H3_FRAME_TYPE_PUSH_PROMISE: HFrameType = 0x5;
 pub const H3_FRAME_TYPE_GOAWAY: HFrameType = 0x7;
+pub const H3_FRAME_TYPE_ORIGIN: HFrameType = 0xc;
 pub const H3_FRAME_TYPE_MAX_PUSH_ID: HFrameType = 0xd;
 pub const H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST: HFrameType = 0xf0700;
 pub const H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH: HFrameType = 0xf0701;
@@ -51,6 +52,11 @@ pub enum HFrame {
     MaxPushId {
         push_id: u64,
     },
+    /// The ORIGIN frame (RFC 9412). Each entry is an ASCII serialization of an origin, e.g.
+    /// `https://example.com` or `https://example.com:8443`.
+    Origin {
+        origins: Vec<String>,
+    },
     Grease,
     PriorityUpdateRequest {
         element_id: u64,
@@ -72,6 +78,7 @@ impl HFrame {
             Self::PushPromise { .. } => H3_FRAME_TYPE_PUSH_PROMISE,
             Self::Goaway { .. } => H3_FRAME_TYPE_GOAWAY,
             Self::MaxPushId { .. } => H3_FRAME_TYPE_MAX_PUSH_ID,
+            Self::Origin { .. } => H3_FRAME_TYPE_ORIGIN,
             Self::PriorityUpdateRequest { .. } => H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST,
             Self::PriorityUpdatePush { .. } => H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH,
             Self::Grease => {
@@ -118,6 +125,13 @@ impl HFrame {
                     enc_inner.encode_varint(*push_id);
                 });
             }
+            Self::Origin { origins } => {
+                enc.encode_vvec_with(|enc_inner| {
+                    for origin in origins {
+                        enc_inner.encode_vec(2, origin.as_bytes());
+                    }
+                });
+            }
             Self::Grease => {
                 // Encode some number of random bytes.
                 let r = random(8);
@@ -187,6 +201,19 @@ impl FrameDecoder<HFrame> for HFrame {
                 H3_FRAME_TYPE_MAX_PUSH_ID => Some(HFrame::MaxPushId {
                     push_id: dec.decode_varint().ok_or(Error::HttpFrame)?,
                 }),
+                H3_FRAME_TYPE_ORIGIN => {
+                    let mut origins = Vec::new();
+                    while dec.remaining() > 0 {
+                        let origin = dec.decode_vec(2).ok_or(Error::HttpFrame)?;
+                        if origin.is_empty() || !origin.is_ascii() {
+                            return Err(Error::HttpFrame);
+                        }
+                        origins.push(
+                            String::from_utf8(origin.to_vec()).map_err(|_| Error::HttpFrame)?,
+                        );
+                    }
+                    Some(HFrame::Origin { origins })
+                }
                 H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST | H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH => {
                     let element_id = dec.decode_varint().ok_or(Error::HttpFrame)?;
                     let priority = dec.decode_remainder();
@@ -220,6 +247,7 @@ impl FrameDecoder<HFrame> for HFrame {
                 | H3_FRAME_TYPE_PUSH_PROMISE
                 | H3_FRAME_TYPE_GOAWAY
                 | H3_FRAME_TYPE_MAX_PUSH_ID
+                | H3_FRAME_TYPE_ORIGIN
                 | H3_FRAME_TYPE_PRIORITY_UPDATE_REQUEST
                 | H3_FRAME_TYPE_PRIORITY_UPDATE_PUSH
         )
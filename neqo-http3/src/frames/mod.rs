@@ -10,7 +10,8 @@ pub(crate) mod wtframe;
 
 #[allow(unused_imports)]
 pub(crate) use hframe::{
-    HFrame, H3_FRAME_TYPE_HEADERS, H3_FRAME_TYPE_SETTINGS, H3_RESERVED_FRAME_TYPES,
+    HFrame, H3_FRAME_TYPE_HEADERS, H3_FRAME_TYPE_ORIGIN, H3_FRAME_TYPE_SETTINGS,
+    H3_RESERVED_FRAME_TYPES,
 };
 pub(crate) use reader::{
     FrameReader, StreamReaderConnectionWrapper, StreamReaderRecvStreamWrapper,
@@ -14,11 +14,14 @@ use neqo_common::{
 };
 use neqo_transport::{Connection, StreamId};
 
-use crate::{Error, RecvStream, Res};
+use crate::{
+    qlog::{self, QlogFrame},
+    Error, RecvStream, Res,
+};
 
 const MAX_READ_SIZE: usize = 4096;
 
-pub(crate) trait FrameDecoder<T> {
+pub(crate) trait FrameDecoder<T>: QlogFrame {
     fn is_known_type(frame_type: u64) -> bool;
     /// # Errors
     ///
@@ -40,6 +43,9 @@ pub(crate) trait StreamReader {
     /// Return an error if the stream was closed on the transport layer, but that information is not
     /// yet consumed on the  http/3 layer.
     fn read_data(&mut self, buf: &mut [u8]) -> Res<(usize, bool)>;
+
+    /// Called when a complete frame with a payload of `length` bytes has been read.
+    fn frame_parsed(&mut self, _frame: &dyn QlogFrame, _length: u64) {}
 }
 
 pub(crate) struct StreamReaderConnectionWrapper<'a> {
@@ -61,16 +67,29 @@ impl<'a> StreamReader for StreamReaderConnectionWrapper<'a> {
         let res = self.conn.stream_recv(self.stream_id, buf)?;
         Ok(res)
     }
+
+    fn frame_parsed(&mut self, frame: &dyn QlogFrame, length: u64) {
+        qlog::h3_frame_parsed(self.conn.qlog_mut(), self.stream_id, frame, length);
+    }
 }
 
 pub(crate) struct StreamReaderRecvStreamWrapper<'a> {
     recv_stream: &'a mut Box<dyn RecvStream>,
     conn: &'a mut Connection,
+    stream_id: StreamId,
 }
 
 impl<'a> StreamReaderRecvStreamWrapper<'a> {
-    pub fn new(conn: &'a mut Connection, recv_stream: &'a mut Box<dyn RecvStream>) -> Self {
-        Self { recv_stream, conn }
+    pub fn new(
+        conn: &'a mut Connection,
+        recv_stream: &'a mut Box<dyn RecvStream>,
+        stream_id: StreamId,
+    ) -> Self {
+        Self {
+            recv_stream,
+            conn,
+            stream_id,
+        }
     }
 }
 
@@ -81,6 +100,10 @@ impl<'a> StreamReader for StreamReaderRecvStreamWrapper<'a> {
     fn read_data(&mut self, buf: &mut [u8]) -> Res<(usize, bool)> {
         self.recv_stream.read_data(self.conn, buf)
     }
+
+    fn frame_parsed(&mut self, frame: &dyn QlogFrame, length: u64) {
+        qlog::h3_frame_parsed(self.conn.qlog_mut(), self.stream_id, frame, length);
+    }
 }
 
 #[derive(Clone, Debug)]
@@ -176,7 +199,8 @@ impl FrameReader {
                 }
             };
 
-            if output.is_some() {
+            if let Some(frame) = &output {
+                stream_reader.frame_parsed(frame, self.frame_len);
                 break Ok((output, fin));
             }
 
@@ -60,6 +60,24 @@ fn test_goaway_frame4() {
     enc_dec_hframe(&f, "070105", 0);
 }
 
+#[test]
+fn test_origin_frame() {
+    let f = HFrame::Origin {
+        origins: vec!["https://a.b".to_string(), "https://c.d:8443".to_string()],
+    };
+    enc_dec_hframe(
+        &f,
+        "0c1f000b68747470733a2f2f612e62001068747470733a2f2f632e643a38343433",
+        0,
+    );
+}
+
+#[test]
+fn test_origin_frame_empty() {
+    let f = HFrame::Origin { origins: vec![] };
+    enc_dec_hframe(&f, "0c00", 0);
+}
+
 #[test]
 fn grease() {
     fn make_grease() -> u64 {
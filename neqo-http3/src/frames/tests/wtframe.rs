@@ -4,8 +4,13 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
+use neqo_transport::StreamType;
+
 use super::enc_dec_wtframe;
-use crate::frames::WebTransportFrame;
+use crate::{
+    frames::{reader::FrameDecoder, WebTransportFrame},
+    Error,
+};
 
 #[test]
 fn test_wt_close_session() {
@@ -15,3 +20,75 @@ fn test_wt_close_session() {
     };
     enc_dec_wtframe(&f, "6843090000000548656c6c6f", 0);
 }
+
+#[test]
+fn test_wt_drain_session() {
+    enc_dec_wtframe(&WebTransportFrame::DrainSession, "800078ae00", 0);
+}
+
+#[test]
+fn test_wt_drain_session_with_payload() {
+    assert_eq!(
+        WebTransportFrame::decode(0x78ae, 1, Some(&[0x00])),
+        Err(Error::HttpMessageError)
+    );
+}
+
+#[test]
+fn test_wt_max_data() {
+    enc_dec_wtframe(&WebTransportFrame::MaxData(0x1234), "990b4d3d025234", 0);
+}
+
+#[test]
+fn test_wt_max_streams() {
+    let f = WebTransportFrame::MaxStreams {
+        stream_type: StreamType::BiDi,
+        max: 10,
+    };
+    enc_dec_wtframe(&f, "990b4d3f010a", 0);
+    let f = WebTransportFrame::MaxStreams {
+        stream_type: StreamType::UniDi,
+        max: 10,
+    };
+    enc_dec_wtframe(&f, "990b4d40010a", 0);
+}
+
+#[test]
+fn test_wt_data_blocked() {
+    enc_dec_wtframe(&WebTransportFrame::DataBlocked(100), "990b4d41024064", 0);
+}
+
+#[test]
+fn test_wt_streams_blocked() {
+    let f = WebTransportFrame::StreamsBlocked {
+        stream_type: StreamType::BiDi,
+        max: 5,
+    };
+    enc_dec_wtframe(&f, "990b4d430105", 0);
+    let f = WebTransportFrame::StreamsBlocked {
+        stream_type: StreamType::UniDi,
+        max: 5,
+    };
+    enc_dec_wtframe(&f, "990b4d440105", 0);
+}
+
+#[test]
+fn test_wt_flow_control_capsule_trailing_data() {
+    assert_eq!(
+        WebTransportFrame::decode(0x190b_4d3d, 2, Some(&[0x05, 0x00])),
+        Err(Error::HttpMessageError)
+    );
+}
+
+#[test]
+fn test_wt_max_streams_too_large() {
+    // 2^60 + 1 does not fit in a stream id.
+    assert_eq!(
+        WebTransportFrame::decode(
+            0x190b_4d3f,
+            8,
+            Some(&[0xd0, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01])
+        ),
+        Err(Error::HttpMessageError)
+    );
+}
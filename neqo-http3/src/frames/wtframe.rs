@@ -7,6 +7,7 @@
 use std::convert::TryFrom;
 
 use neqo_common::{Decoder, Encoder};
+use neqo_transport::StreamType;
 
 use crate::{frames::reader::FrameDecoder, Error, Res};
 
@@ -14,22 +15,94 @@ pub(crate) type WebTransportFrameType = u64;
 
 const WT_FRAME_CLOSE_SESSION: WebTransportFrameType = 0x2843;
 const WT_FRAME_CLOSE_MAX_MESSAGE_SIZE: u64 = 1024;
+const WT_FRAME_DRAIN_SESSION: WebTransportFrameType = 0x78ae;
+// Session flow control capsules, draft-ietf-webtrans-http3-09.
+const WT_FRAME_MAX_DATA: WebTransportFrameType = 0x190b_4d3d;
+const WT_FRAME_MAX_STREAMS_BIDI: WebTransportFrameType = 0x190b_4d3f;
+const WT_FRAME_MAX_STREAMS_UNI: WebTransportFrameType = 0x190b_4d40;
+const WT_FRAME_DATA_BLOCKED: WebTransportFrameType = 0x190b_4d41;
+const WT_FRAME_STREAMS_BLOCKED_BIDI: WebTransportFrameType = 0x190b_4d43;
+const WT_FRAME_STREAMS_BLOCKED_UNI: WebTransportFrameType = 0x190b_4d44;
+/// The same limit as for QUIC stream counts.
+const WT_MAX_STREAMS_LIMIT: u64 = 1 << 60;
 
 #[derive(PartialEq, Eq, Debug)]
 pub enum WebTransportFrame {
     CloseSession { error: u32, message: String },
+    DrainSession,
+    MaxData(u64),
+    MaxStreams { stream_type: StreamType, max: u64 },
+    DataBlocked(u64),
+    StreamsBlocked { stream_type: StreamType, max: u64 },
 }
 
 impl WebTransportFrame {
+    #[must_use]
+    pub fn frame_type(&self) -> WebTransportFrameType {
+        match self {
+            Self::CloseSession { .. } => WT_FRAME_CLOSE_SESSION,
+            Self::DrainSession => WT_FRAME_DRAIN_SESSION,
+            Self::MaxData(_) => WT_FRAME_MAX_DATA,
+            Self::MaxStreams {
+                stream_type: StreamType::BiDi,
+                ..
+            } => WT_FRAME_MAX_STREAMS_BIDI,
+            Self::MaxStreams {
+                stream_type: StreamType::UniDi,
+                ..
+            } => WT_FRAME_MAX_STREAMS_UNI,
+            Self::DataBlocked(_) => WT_FRAME_DATA_BLOCKED,
+            Self::StreamsBlocked {
+                stream_type: StreamType::BiDi,
+                ..
+            } => WT_FRAME_STREAMS_BLOCKED_BIDI,
+            Self::StreamsBlocked {
+                stream_type: StreamType::UniDi,
+                ..
+            } => WT_FRAME_STREAMS_BLOCKED_UNI,
+        }
+    }
+
     pub fn encode(&self, enc: &mut Encoder) {
-        enc.encode_varint(WT_FRAME_CLOSE_SESSION);
-        let WebTransportFrame::CloseSession { error, message } = &self;
-        enc.encode_varint(4 + message.len() as u64);
-        enc.encode_uint(4, *error);
-        enc.encode(message.as_bytes());
+        enc.encode_varint(self.frame_type());
+        let value = match self {
+            Self::CloseSession { error, message } => {
+                enc.encode_varint(4 + message.len() as u64);
+                enc.encode_uint(4, *error);
+                enc.encode(message.as_bytes());
+                return;
+            }
+            Self::DrainSession => {
+                enc.encode_varint(0_u64);
+                return;
+            }
+            Self::MaxData(value)
+            | Self::MaxStreams { max: value, .. }
+            | Self::DataBlocked(value)
+            | Self::StreamsBlocked { max: value, .. } => *value,
+        };
+        enc.encode_varint(u64::try_from(Encoder::varint_len(value)).unwrap());
+        enc.encode_varint(value);
     }
 }
 
+/// Decode the single varint that makes up the payload of a flow control capsule.
+fn decode_value(dec: &mut Decoder) -> Res<u64> {
+    let value = dec.decode_varint().ok_or(Error::HttpMessageError)?;
+    if dec.remaining() > 0 {
+        return Err(Error::HttpMessageError);
+    }
+    Ok(value)
+}
+
+fn decode_stream_count(dec: &mut Decoder) -> Res<u64> {
+    let max = decode_value(dec)?;
+    if max > WT_MAX_STREAMS_LIMIT {
+        return Err(Error::HttpMessageError);
+    }
+    Ok(max)
+}
+
 impl FrameDecoder<WebTransportFrame> for WebTransportFrame {
     fn decode(
         frame_type: u64,
@@ -38,25 +111,61 @@ impl FrameDecoder<WebTransportFrame> for WebTransportFrame {
     ) -> Res<Option<WebTransportFrame>> {
         if let Some(payload) = data {
             let mut dec = Decoder::from(payload);
-            if frame_type == WT_FRAME_CLOSE_SESSION {
-                if frame_len > WT_FRAME_CLOSE_MAX_MESSAGE_SIZE + 4 {
-                    return Err(Error::HttpMessageError);
+            let frame = match frame_type {
+                WT_FRAME_CLOSE_SESSION => {
+                    if frame_len > WT_FRAME_CLOSE_MAX_MESSAGE_SIZE + 4 {
+                        return Err(Error::HttpMessageError);
+                    }
+                    let error =
+                        u32::try_from(dec.decode_uint(4).ok_or(Error::HttpMessageError)?).unwrap();
+                    let Ok(message) = String::from_utf8(dec.decode_remainder().to_vec()) else {
+                        return Err(Error::HttpMessageError);
+                    };
+                    WebTransportFrame::CloseSession { error, message }
                 }
-                let error =
-                    u32::try_from(dec.decode_uint(4).ok_or(Error::HttpMessageError)?).unwrap();
-                let Ok(message) = String::from_utf8(dec.decode_remainder().to_vec()) else {
-                    return Err(Error::HttpMessageError);
-                };
-                Ok(Some(WebTransportFrame::CloseSession { error, message }))
-            } else {
-                Ok(None)
-            }
+                WT_FRAME_DRAIN_SESSION => {
+                    if frame_len != 0 {
+                        return Err(Error::HttpMessageError);
+                    }
+                    WebTransportFrame::DrainSession
+                }
+                WT_FRAME_MAX_DATA => WebTransportFrame::MaxData(decode_value(&mut dec)?),
+                WT_FRAME_MAX_STREAMS_BIDI => WebTransportFrame::MaxStreams {
+                    stream_type: StreamType::BiDi,
+                    max: decode_stream_count(&mut dec)?,
+                },
+                WT_FRAME_MAX_STREAMS_UNI => WebTransportFrame::MaxStreams {
+                    stream_type: StreamType::UniDi,
+                    max: decode_stream_count(&mut dec)?,
+                },
+                WT_FRAME_DATA_BLOCKED => WebTransportFrame::DataBlocked(decode_value(&mut dec)?),
+                WT_FRAME_STREAMS_BLOCKED_BIDI => WebTransportFrame::StreamsBlocked {
+                    stream_type: StreamType::BiDi,
+                    max: decode_stream_count(&mut dec)?,
+                },
+                WT_FRAME_STREAMS_BLOCKED_UNI => WebTransportFrame::StreamsBlocked {
+                    stream_type: StreamType::UniDi,
+                    max: decode_stream_count(&mut dec)?,
+                },
+                _ => return Ok(None),
+            };
+            Ok(Some(frame))
         } else {
             Ok(None)
         }
     }
 
     fn is_known_type(frame_type: u64) -> bool {
-        frame_type == WT_FRAME_CLOSE_SESSION
+        matches!(
+            frame_type,
+            WT_FRAME_CLOSE_SESSION
+                | WT_FRAME_DRAIN_SESSION
+                | WT_FRAME_MAX_DATA
+                | WT_FRAME_MAX_STREAMS_BIDI
+                | WT_FRAME_MAX_STREAMS_UNI
+                | WT_FRAME_DATA_BLOCKED
+                | WT_FRAME_STREAMS_BLOCKED_BIDI
+                | WT_FRAME_STREAMS_BLOCKED_UNI
+        )
     }
 }
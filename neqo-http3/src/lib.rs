@@ -135,8 +135,10 @@ while let Some(event) = client.next_event() {
 
 */
 
+mod alt_svc;
 mod buffered_send_stream;
 mod client_events;
+mod client_pool;
 mod conn_params;
 mod connection;
 mod connection_client;
@@ -146,6 +148,8 @@ mod control_stream_remote;
 pub mod features;
 mod frames;
 mod headers_checks;
+mod link;
+mod origin;
 mod priority;
 mod push_controller;
 mod qlog;
@@ -157,30 +161,39 @@ mod send_message;
 mod server;
 mod server_connection_events;
 mod server_events;
+mod session_cache;
 mod settings;
 mod stream_type_reader;
 
 use std::{any::Any, cell::RefCell, fmt::Debug, rc::Rc};
 
+pub use alt_svc::{AltService, AltSvc};
 use buffered_send_stream::BufferedStream;
 pub use client_events::{Http3ClientEvent, WebTransportEvent};
+pub use client_pool::{
+    AddressResolver, Http3ClientPool, Http3ClientPoolEvent, Http3ClientPoolParameters,
+    PoolRequestId,
+};
 pub use conn_params::Http3Parameters;
 pub use connection::{Http3State, WebTransportSessionAcceptAction};
 pub use connection_client::Http3Client;
 use features::extended_connect::WebTransportSession;
 use frames::HFrame;
+pub use link::Link;
 pub use neqo_common::Header;
-use neqo_common::MessageType;
+use neqo_common::{Bytes, MessageType};
 use neqo_qpack::Error as QpackError;
 pub use neqo_transport::{streams::SendOrder, Output, StreamId};
 use neqo_transport::{
     AppError, Connection, Error as TransportError, RecvStreamStats, SendStreamStats,
 };
+pub use origin::{normalize_origin, OriginSet};
 pub use priority::Priority;
 pub use server::Http3Server;
 pub use server_events::{
     Http3OrWebTransportStream, Http3ServerEvent, WebTransportRequest, WebTransportServerEvent,
 };
+pub use session_cache::SessionCache;
 use stream_type_reader::NewStreamType;
 
 use crate::priority::PriorityHandler;
@@ -211,6 +224,8 @@ pub enum Error {
     HttpVersionFallback,
     HttpMessageError,
     QpackError(neqo_qpack::Error),
+    /// A peer exceeded the data or stream limits of a WebTransport session.
+    WebTransportFlowControl,
 
     // Internal errors from here.
     AlreadyClosed,
@@ -258,6 +273,7 @@ impl Error {
             Self::HttpConnect => 0x10f,
             Self::HttpVersionFallback => 0x110,
             Self::QpackError(e) => e.code(),
+            Self::WebTransportFlowControl => 0x045d_4487,
             // These are all internal errors.
             _ => 3,
         }
@@ -398,6 +414,7 @@ impl From<AppError> for Error {
             0x200 => Self::QpackError(QpackError::DecompressionFailed),
             0x201 => Self::QpackError(QpackError::EncoderStream),
             0x202 => Self::QpackError(QpackError::DecoderStream),
+            0x045d_4487 => Self::WebTransportFlowControl,
             _ => Self::HttpInternal(0),
         }
     }
@@ -477,6 +494,15 @@ trait RecvStream: Stream {
         Err(Error::InvalidStreamId)
     }
 
+    /// Like `read_data`, but returns the next chunk of data without copying it.
+    ///
+    /// # Errors
+    ///
+    /// An error may happen while reading a stream, e.g. early close, protocol error, etc.
+    fn read_data_chunk(&mut self, _conn: &mut Connection) -> Res<(Option<Bytes>, bool)> {
+        Err(Error::InvalidStreamId)
+    }
+
     fn http_stream(&mut self) -> Option<&mut dyn HttpRecvStream> {
         None
     }
@@ -561,6 +587,7 @@ trait HttpRecvStreamEvents: RecvStreamEvents {
         interim: bool,
         fin: bool,
     );
+    fn trailers_ready(&self, _stream_info: Http3StreamInfo, _trailers: Vec<Header>) {}
     fn extended_connect_new_session(&self, _stream_id: StreamId, _headers: Vec<Header>) {}
 }
 
@@ -580,6 +607,16 @@ trait SendStream: Stream {
     /// Error my occur during sending data, e.g. protocol error, etc.
     fn send_data(&mut self, _conn: &mut Connection, _buf: &[u8]) -> Res<usize>;
 
+    /// Like `send_data`, but streams that can hand `buf` to the transport without copying
+    /// do so.
+    ///
+    /// # Errors
+    ///
+    /// Error my occur during sending data, e.g. protocol error, etc.
+    fn send_data_bytes(&mut self, conn: &mut Connection, buf: Bytes) -> Res<usize> {
+        self.send_data(conn, &buf)
+    }
+
     /// # Errors
     ///
     /// It may happen that the transport stream is already close. This is unlikely.
@@ -626,6 +663,22 @@ trait HttpSendStream: SendStream {
     ///
     /// This can also return an error if the underlying stream is closed.
     fn send_headers(&mut self, headers: &[Header], conn: &mut Connection) -> Res<()>;
+    /// This function is used to supply trailers after the message body. No more data can be
+    /// sent afterwards.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidInput` if the message headers have not been sent yet or trailers have already
+    /// been sent, `InvalidHeader` if the trailers contain pseudo-headers.
+    fn send_trailers(&mut self, trailers: &[Header], conn: &mut Connection) -> Res<()>;
+    /// This function is used to supply an informational (1xx) response. It can be called any
+    /// number of times before the final response headers are supplied.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidInput` if this is not a response or the final response headers have already been
+    /// supplied, `InvalidHeader` if the headers are not a valid informational response.
+    fn send_interim_headers(&mut self, headers: &[Header], conn: &mut Connection) -> Res<()>;
     fn set_new_listener(&mut self, _conn_events: Box<dyn SendStreamEvents>) {}
     fn any(&self) -> &dyn Any;
 }
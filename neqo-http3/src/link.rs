@@ -0,0 +1,194 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use crate::{
+    alt_svc::{is_token, split_outside_quotes, unquote},
+    Error, Header, Res,
+};
+
+/// A single link from a `Link` header field, see
+/// [RFC 8288](https://www.rfc-editor.org/rfc/rfc8288.html). This is mostly used for 103 Early
+/// Hints, e.g. `</style.css>; rel=preload; as=style`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Link {
+    /// The target URI reference, without the angle brackets. It is not resolved.
+    pub target: String,
+    /// The link parameters. Names are lowercase, parameters without a value have an empty
+    /// value.
+    pub params: Vec<(String, String)>,
+}
+
+impl Link {
+    /// Parse a `Link` header field value, which can contain multiple links.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidHeader` if the value is malformed.
+    pub fn parse(value: &str) -> Res<Vec<Self>> {
+        split_links(value)
+            .into_iter()
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+            .map(parse_link_value)
+            .collect()
+    }
+
+    /// Parse all `link` headers in `headers`. Malformed values are ignored.
+    #[must_use]
+    pub fn from_headers(headers: &[Header]) -> Vec<Self> {
+        headers
+            .iter()
+            .filter(|h| h.name().eq_ignore_ascii_case("link"))
+            .filter_map(|h| Self::parse(h.value()).ok())
+            .flatten()
+            .collect()
+    }
+
+    /// The value of the first parameter with the name `name`.
+    #[must_use]
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+
+    /// Whether the `rel` parameter contains the relation type `rel`.
+    #[must_use]
+    pub fn has_rel(&self, rel: &str) -> bool {
+        self.param("rel").map_or(false, |r| {
+            r.split_ascii_whitespace()
+                .any(|r| r.eq_ignore_ascii_case(rel))
+        })
+    }
+}
+
+/// Split a field value into link values. Commas are ignored inside quoted strings and inside
+/// the URI references, which can contain both commas and quotes.
+fn split_links(value: &str) -> Vec<&str> {
+    let mut links = Vec::new();
+    let mut start = 0;
+    let mut in_quotes = false;
+    let mut in_uri = false;
+    let mut escaped = false;
+    for (i, c) in value.char_indices() {
+        if in_uri {
+            in_uri = c != '>';
+        } else if escaped {
+            escaped = false;
+        } else if in_quotes && c == '\\' {
+            escaped = true;
+        } else if c == '"' {
+            in_quotes = !in_quotes;
+        } else if c == '<' && !in_quotes {
+            in_uri = true;
+        } else if c == ',' && !in_quotes {
+            links.push(&value[start..i]);
+            start = i + c.len_utf8();
+        }
+    }
+    links.push(&value[start..]);
+    links
+}
+
+fn parse_link_value(value: &str) -> Res<Link> {
+    let rest = value.strip_prefix('<').ok_or(Error::InvalidHeader)?;
+    let (target, rest) = rest.split_once('>').ok_or(Error::InvalidHeader)?;
+    let mut params = Vec::new();
+    let mut parts = split_outside_quotes(rest, ';').into_iter().map(str::trim);
+    // Only whitespace is allowed between the URI reference and the first parameter.
+    if parts.next().map_or(false, |p| !p.is_empty()) {
+        return Err(Error::InvalidHeader);
+    }
+    for param in parts.filter(|p| !p.is_empty()) {
+        let (name, value) = match param.split_once('=') {
+            Some((name, value)) => (name.trim(), unquote(value.trim())?),
+            None => (param, String::new()),
+        };
+        if !is_token(name) {
+            return Err(Error::InvalidHeader);
+        }
+        params.push((name.to_ascii_lowercase(), value));
+    }
+    Ok(Link {
+        target: target.to_string(),
+        params,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Link;
+    use crate::{Error, Header};
+
+    #[test]
+    fn preload() {
+        let links = Link::parse("</style.css>; rel=preload; as=style").unwrap();
+        assert_eq!(
+            links,
+            vec![Link {
+                target: "/style.css".to_string(),
+                params: vec![
+                    ("rel".to_string(), "preload".to_string()),
+                    ("as".to_string(), "style".to_string())
+                ],
+            }]
+        );
+        assert!(links[0].has_rel("preload"));
+        assert_eq!(links[0].param("AS"), Some("style"));
+    }
+
+    #[test]
+    fn multiple() {
+        let links = Link::parse(
+            "<https://cdn.example.com>; rel=\"preconnect dns-prefetch\"; crossorigin, \
+             </script.js>;rel=preload;as=script",
+        )
+        .unwrap();
+        assert_eq!(links.len(), 2);
+        assert!(links[0].has_rel("preconnect"));
+        assert!(links[0].has_rel("dns-prefetch"));
+        assert_eq!(links[0].param("crossorigin"), Some(""));
+        assert_eq!(links[1].target, "/script.js");
+    }
+
+    #[test]
+    fn separators_in_target() {
+        let links = Link::parse("</a,b>; rel=preload, </c\"d>; rel=\"pre,load\"").unwrap();
+        assert_eq!(links.len(), 2);
+        assert_eq!(links[0].target, "/a,b");
+        assert!(links[0].has_rel("preload"));
+        assert_eq!(links[1].target, "/c\"d");
+        assert_eq!(links[1].param("rel"), Some("pre,load"));
+    }
+
+    #[test]
+    fn malformed() {
+        for v in [
+            "/style.css; rel=preload",
+            "</style.css",
+            "</a> x; rel=preload",
+            "</a>; r l=x",
+        ] {
+            assert_eq!(Link::parse(v), Err(Error::InvalidHeader), "{v}");
+        }
+    }
+
+    #[test]
+    fn from_headers() {
+        let headers = [
+            Header::new(":status", "103"),
+            Header::new("link", "</a.css>; rel=preload"),
+            Header::new("link", "malformed"),
+            Header::new("link", "</b.js>; rel=preload"),
+        ];
+        let targets: Vec<_> = Link::from_headers(&headers)
+            .into_iter()
+            .map(|l| l.target)
+            .collect();
+        assert_eq!(targets, vec!["/a.css".to_string(), "/b.js".to_string()]);
+    }
+}
@@ -0,0 +1,152 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{collections::BTreeSet, net::Ipv6Addr};
+
+use url::Url;
+
+use crate::{Error, Res};
+
+/// Normalize an origin to its ASCII serialization, e.g. `HTTPS://Example.COM:443` becomes
+/// `https://example.com`.
+///
+/// # Errors
+///
+/// `InvalidInput` if the value can't be parsed as an URL or does not have a tuple origin.
+pub fn normalize_origin(origin: &str) -> Res<String> {
+    let url = Url::parse(origin).map_err(|_| Error::InvalidInput)?;
+    let origin = url.origin();
+    if !origin.is_tuple() {
+        return Err(Error::InvalidInput);
+    }
+    Ok(origin.ascii_serialization())
+}
+
+/// The `https` origin for a connection to `server_name` on `port`.  A server name that is an
+/// IPv6 literal is enclosed in brackets.
+pub(crate) fn server_origin(server_name: &str, port: u16) -> String {
+    if server_name.parse::<Ipv6Addr>().is_ok() {
+        format!("https://[{server_name}]:{port}")
+    } else {
+        format!("https://{server_name}:{port}")
+    }
+}
+
+/// The set of origins a connection is authoritative for, see
+/// [RFC 9412](https://www.rfc-editor.org/rfc/rfc9412.html).
+///
+/// Before an `ORIGIN` frame has been received, the set only contains the origin the connection
+/// was established for. Origins received in `ORIGIN` frames are added to the set. Note that a
+/// client must still check that the server certificate is valid for an origin before reusing
+/// the connection for it.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct OriginSet {
+    origins: BTreeSet<String>,
+    origin_frame_received: bool,
+}
+
+impl OriginSet {
+    #[must_use]
+    pub fn new(initial_origin: Option<&str>) -> Self {
+        let mut origins = BTreeSet::new();
+        if let Some(o) = initial_origin.and_then(|o| normalize_origin(o).ok()) {
+            origins.insert(o);
+        }
+        Self {
+            origins,
+            origin_frame_received: false,
+        }
+    }
+
+    /// Add the origins from an `ORIGIN` frame. Entries that are not valid origins are ignored as
+    /// required by the specification. Returns the newly added, normalized, origins.
+    pub(crate) fn add_origins(&mut self, origins: &[String]) -> Vec<String> {
+        self.origin_frame_received = true;
+        origins
+            .iter()
+            .filter_map(|o| normalize_origin(o).ok())
+            .filter(|o| self.origins.insert(o.clone()))
+            .collect()
+    }
+
+    /// Whether the connection is authoritative for `origin`.
+    #[must_use]
+    pub fn contains(&self, origin: &str) -> bool {
+        normalize_origin(origin).map_or(false, |o| self.origins.contains(&o))
+    }
+
+    /// Whether at least one `ORIGIN` frame has been received.
+    #[must_use]
+    pub fn origin_frame_received(&self) -> bool {
+        self.origin_frame_received
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &String> {
+        self.origins.iter()
+    }
+
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.origins.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.origins.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{normalize_origin, server_origin, OriginSet};
+    use crate::Error;
+
+    #[test]
+    fn normalize() {
+        assert_eq!(
+            normalize_origin("HTTPS://Example.COM:443").unwrap(),
+            "https://example.com"
+        );
+        assert_eq!(
+            normalize_origin("https://example.com:8443/path").unwrap(),
+            "https://example.com:8443"
+        );
+        assert_eq!(normalize_origin("not an origin"), Err(Error::InvalidInput));
+        assert_eq!(
+            normalize_origin("data:text/plain,a"),
+            Err(Error::InvalidInput)
+        );
+    }
+
+    #[test]
+    fn server_name() {
+        assert_eq!(server_origin("example.com", 443), "https://example.com:443");
+        assert_eq!(server_origin("192.0.2.1", 4433), "https://192.0.2.1:4433");
+        assert_eq!(server_origin("::1", 443), "https://[::1]:443");
+        assert_eq!(
+            normalize_origin(&server_origin("fe80::1", 4433)).unwrap(),
+            "https://[fe80::1]:4433"
+        );
+    }
+
+    #[test]
+    fn add_origins() {
+        let mut set = OriginSet::new(Some("https://example.com"));
+        assert!(!set.origin_frame_received());
+        assert!(set.contains("https://example.com:443"));
+
+        let added = set.add_origins(&[
+            "https://example.com".to_string(),
+            "https://Other.example.com".to_string(),
+            "bogus".to_string(),
+        ]);
+        assert_eq!(added, vec!["https://other.example.com".to_string()]);
+        assert!(set.origin_frame_received());
+        assert!(set.contains("https://other.example.com"));
+        assert!(!set.contains("https://third.example.com"));
+        assert_eq!(set.len(), 2);
+    }
+}
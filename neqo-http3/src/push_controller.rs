@@ -13,14 +13,15 @@ use std::{
     slice::SliceIndex,
 };
 
-use neqo_common::{qerror, qinfo, qtrace, Header};
+use ::qlog::events::h3::H3PushDecision;
+use neqo_common::{qerror, qinfo, qlog::NeqoQlog, qtrace, Header};
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
     client_events::{Http3ClientEvent, Http3ClientEvents},
     connection::Http3Connection,
     frames::HFrame,
-    CloseType, Error, Http3StreamInfo, HttpRecvStreamEvents, RecvStreamEvents, Res,
+    qlog, CloseType, Error, Http3StreamInfo, HttpRecvStreamEvents, RecvStreamEvents, Res,
 };
 
 /// `PushStates`:
@@ -154,6 +155,7 @@ pub(crate) struct PushController {
     // All push_id < next_push_id_to_open are in the push_stream lists. If they are not in the list
     // they have been already closed.
     conn_events: Http3ClientEvents,
+    qlog: NeqoQlog,
 }
 
 impl PushController {
@@ -163,8 +165,13 @@ impl PushController {
             current_max_push_id: 0,
             push_streams: ActivePushStreams::new(),
             conn_events,
+            qlog: NeqoQlog::disabled(),
         }
     }
+
+    pub fn set_qlog(&mut self, qlog: NeqoQlog) {
+        self.qlog = qlog;
+    }
 }
 
 impl Display for PushController {
@@ -225,6 +232,12 @@ impl PushController {
                     for e in events.drain(..) {
                         self.conn_events.insert(e);
                     }
+                    qlog::h3_push_resolved(
+                        &mut self.qlog,
+                        push_id,
+                        Some(stream_id_tmp),
+                        H3PushDecision::Claimed,
+                    );
                     *push_state = PushState::Active {
                         stream_id: stream_id_tmp,
                         headers: new_headers,
@@ -260,6 +273,12 @@ impl PushController {
                 }
                 PushState::PushPromise { headers } => {
                     let tmp = mem::take(headers);
+                    qlog::h3_push_resolved(
+                        &mut self.qlog,
+                        push_id,
+                        Some(stream_id),
+                        H3PushDecision::Claimed,
+                    );
                     *push_state = PushState::Active {
                         stream_id,
                         headers: tmp,
@@ -304,12 +323,24 @@ impl PushController {
             Some(ps) => match ps {
                 PushState::Init => Ok(()),
                 PushState::PushPromise { .. } => {
+                    qlog::h3_push_resolved(
+                        &mut self.qlog,
+                        push_id,
+                        None,
+                        H3PushDecision::Abandoned,
+                    );
                     self.conn_events.remove_events_for_push_id(push_id);
                     self.conn_events.push_canceled(push_id);
                     Ok(())
                 }
                 PushState::OnlyPushStream { stream_id, .. }
                 | PushState::Active { stream_id, .. } => {
+                    qlog::h3_push_resolved(
+                        &mut self.qlog,
+                        push_id,
+                        Some(stream_id),
+                        H3PushDecision::Abandoned,
+                    );
                     mem::drop(base_handler.stream_stop_sending(
                         conn,
                         stream_id,
@@ -357,12 +388,19 @@ impl PushController {
                 }
             }
             Some(PushState::PushPromise { .. }) => {
+                qlog::h3_push_resolved(&mut self.qlog, push_id, None, H3PushDecision::Abandoned);
                 self.conn_events.remove_events_for_push_id(push_id);
                 base_handler.queue_control_frame(&HFrame::CancelPush { push_id });
                 self.push_streams.close(push_id);
                 Ok(())
             }
             Some(PushState::Active { stream_id, .. }) => {
+                qlog::h3_push_resolved(
+                    &mut self.qlog,
+                    push_id,
+                    Some(*stream_id),
+                    H3PushDecision::Abandoned,
+                );
                 self.conn_events.remove_events_for_push_id(push_id);
                 // Cancel the stream. the transport steam may already be done, so ignore an error.
                 mem::drop(base_handler.stream_stop_sending(
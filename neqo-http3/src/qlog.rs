@@ -8,13 +8,108 @@
 
 use std::convert::TryFrom;
 
-use neqo_common::qlog::NeqoQlog;
+use neqo_common::{qlog::NeqoQlog, Decoder};
 use neqo_transport::StreamId;
 use qlog::{
     self,
-    events::{DataRecipient, EventData},
+    events::{
+        h3::{
+            H3FrameCreated, H3FrameParsed, H3Owner, H3ParametersSet, H3PriorityTargetStreamType,
+            H3PushDecision, H3PushResolved, H3StreamType, H3StreamTypeSet, Http3Frame, Setting,
+        },
+        DataRecipient, EventData,
+    },
 };
 
+use crate::{
+    frames::{HFrame, WebTransportFrame, H3_FRAME_TYPE_ORIGIN},
+    settings::{HSettingType, HSettings},
+    stream_type_reader::{NewStreamType, WEBTRANSPORT_STREAM, WEBTRANSPORT_UNI_STREAM},
+};
+
+/// A frame that can be described in a qlog trace.
+pub(crate) trait QlogFrame {
+    fn qlog_frame(&self) -> Http3Frame;
+}
+
+impl QlogFrame for HFrame {
+    fn qlog_frame(&self) -> Http3Frame {
+        match self {
+            // The header blocks are logged when they are decoded by QPACK.
+            Self::Data { .. } => Http3Frame::Data { raw: None },
+            Self::Headers { .. } => Http3Frame::Headers {
+                headers: Vec::new(),
+            },
+            Self::CancelPush { push_id } => Http3Frame::CancelPush { push_id: *push_id },
+            Self::Settings { settings } => Http3Frame::Settings {
+                settings: settings
+                    .iter()
+                    .map(|s| Setting {
+                        name: setting_name(s.setting_type).to_string(),
+                        value: s.value,
+                    })
+                    .collect(),
+            },
+            Self::PushPromise { push_id, .. } => Http3Frame::PushPromise {
+                push_id: *push_id,
+                headers: Vec::new(),
+            },
+            Self::Goaway { stream_id } => Http3Frame::Goaway {
+                id: stream_id.as_u64(),
+            },
+            Self::MaxPushId { push_id } => Http3Frame::MaxPushId { push_id: *push_id },
+            Self::Origin { .. } => Http3Frame::Unknown {
+                frame_type_value: H3_FRAME_TYPE_ORIGIN,
+                raw: None,
+            },
+            Self::Grease => Http3Frame::Reserved { length: None },
+            Self::PriorityUpdateRequest {
+                element_id,
+                priority,
+            } => Http3Frame::PriorityUpdate {
+                target_stream_type: H3PriorityTargetStreamType::Request,
+                prioritized_element_id: *element_id,
+                priority_field_value: priority.to_string(),
+            },
+            Self::PriorityUpdatePush {
+                element_id,
+                priority,
+            } => Http3Frame::PriorityUpdate {
+                target_stream_type: H3PriorityTargetStreamType::Push,
+                prioritized_element_id: *element_id,
+                priority_field_value: priority.to_string(),
+            },
+        }
+    }
+}
+
+impl QlogFrame for WebTransportFrame {
+    fn qlog_frame(&self) -> Http3Frame {
+        // qlog has no definitions for `WebTransport` capsules.
+        Http3Frame::Unknown {
+            frame_type_value: self.frame_type(),
+            raw: None,
+        }
+    }
+}
+
+fn setting_name(setting_type: HSettingType) -> &'static str {
+    match setting_type {
+        HSettingType::MaxHeaderListSize => "SETTINGS_MAX_FIELD_SECTION_SIZE",
+        HSettingType::MaxTableCapacity => "SETTINGS_QPACK_MAX_TABLE_CAPACITY",
+        HSettingType::BlockedStreams => "SETTINGS_QPACK_BLOCKED_STREAMS",
+        HSettingType::EnableWebTransport => "SETTINGS_ENABLE_WEBTRANSPORT",
+        HSettingType::EnableH3Datagram => "SETTINGS_H3_DATAGRAM",
+        HSettingType::WebTransportInitialMaxData => "SETTINGS_WEBTRANSPORT_INITIAL_MAX_DATA",
+        HSettingType::WebTransportInitialMaxStreamsUni => {
+            "SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI"
+        }
+        HSettingType::WebTransportInitialMaxStreamsBidi => {
+            "SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI"
+        }
+    }
+}
+
 pub fn h3_data_moved_up(qlog: &mut NeqoQlog, stream_id: StreamId, amount: usize) {
     qlog.add_event_data(|| {
         let ev_data = EventData::DataMoved(qlog::events::quic::DataMoved {
@@ -44,3 +139,125 @@ pub fn h3_data_moved_down(qlog: &mut NeqoQlog, stream_id: StreamId, amount: usiz
         Some(ev_data)
     });
 }
+
+/// `encoded` is the whole frame, including the type and length.
+pub fn h3_frame_created(
+    qlog: &mut NeqoQlog,
+    stream_id: StreamId,
+    frame: &dyn QlogFrame,
+    encoded: &[u8],
+) {
+    qlog.add_event_data(|| {
+        let mut dec = Decoder::from(encoded);
+        let length = dec.decode_varint().and_then(|_| dec.decode_varint());
+        let ev_data = EventData::H3FrameCreated(H3FrameCreated {
+            stream_id: stream_id.as_u64(),
+            length,
+            frame: frame.qlog_frame(),
+            raw: None,
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// `length` is the length of the frame payload.
+pub fn h3_frame_parsed(
+    qlog: &mut NeqoQlog,
+    stream_id: StreamId,
+    frame: &dyn QlogFrame,
+    length: u64,
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::H3FrameParsed(H3FrameParsed {
+            stream_id: stream_id.as_u64(),
+            length: Some(length),
+            frame: frame.qlog_frame(),
+            raw: None,
+        });
+
+        Some(ev_data)
+    });
+}
+
+pub fn h3_parameters_set(qlog: &mut NeqoQlog, owner: H3Owner, settings: &HSettings) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::H3ParametersSet(H3ParametersSet {
+            owner: Some(owner),
+            max_field_section_size: settings.get_if_present(HSettingType::MaxHeaderListSize),
+            max_table_capacity: settings.get_if_present(HSettingType::MaxTableCapacity),
+            blocked_streams_count: settings.get_if_present(HSettingType::BlockedStreams),
+            enable_connect_protocol: None,
+            h3_datagram: settings.get_if_present(HSettingType::EnableH3Datagram),
+            waits_for_settings: None,
+        });
+
+        Some(ev_data)
+    });
+}
+
+pub fn h3_stream_type_set(
+    qlog: &mut NeqoQlog,
+    owner: H3Owner,
+    stream_id: StreamId,
+    stream_type: H3StreamType,
+    stream_type_value: Option<u64>,
+    associated_push_id: Option<u64>,
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::H3StreamTypeSet(H3StreamTypeSet {
+            owner: Some(owner),
+            stream_id: stream_id.as_u64(),
+            stream_type,
+            stream_type_value,
+            associated_push_id,
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// The peer has opened a stream of type `stream_type`.
+pub fn h3_remote_stream_type_set(
+    qlog: &mut NeqoQlog,
+    stream_id: StreamId,
+    stream_type: NewStreamType,
+) {
+    // `NewStreamType` names streams for the local component that reads them, so the
+    // peer's QPACK encoder stream is a `Decoder` stream.
+    let (qlog_type, value, push_id) = match stream_type {
+        NewStreamType::Control => (H3StreamType::Control, None, None),
+        NewStreamType::Decoder => (H3StreamType::QpackEncode, None, None),
+        NewStreamType::Encoder => (H3StreamType::QpackDecode, None, None),
+        NewStreamType::Push(push_id) => (H3StreamType::Push, None, Some(push_id)),
+        NewStreamType::WebTransportStream(_) => (
+            H3StreamType::Unknown,
+            Some(if stream_id.is_uni() {
+                WEBTRANSPORT_UNI_STREAM
+            } else {
+                WEBTRANSPORT_STREAM
+            }),
+            None,
+        ),
+        NewStreamType::Http => (H3StreamType::Request, None, None),
+        NewStreamType::Unknown => (H3StreamType::Unknown, None, None),
+    };
+    h3_stream_type_set(qlog, H3Owner::Remote, stream_id, qlog_type, value, push_id);
+}
+
+pub fn h3_push_resolved(
+    qlog: &mut NeqoQlog,
+    push_id: u64,
+    stream_id: Option<StreamId>,
+    decision: H3PushDecision,
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::H3PushResolved(H3PushResolved {
+            push_id: Some(push_id),
+            stream_id: stream_id.map(StreamId::as_u64),
+            decision: Some(decision),
+        });
+
+        Some(ev_data)
+    });
+}
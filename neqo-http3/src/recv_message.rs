@@ -8,13 +8,13 @@ use std::{
     any::Any, cell::RefCell, cmp::min, collections::VecDeque, convert::TryFrom, fmt::Debug, rc::Rc,
 };
 
-use neqo_common::{qdebug, qinfo, qtrace, Header};
+use neqo_common::{qdebug, qinfo, qtrace, Bytes, Header};
 use neqo_qpack::decoder::QPackDecoder;
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
     frames::{FrameReader, HFrame, StreamReaderConnectionWrapper, H3_FRAME_TYPE_HEADERS},
-    headers_checks::{headers_valid, is_interim},
+    headers_checks::{headers_valid, is_interim, trailers_valid},
     priority::PriorityHandler,
     push_controller::PushController,
     qlog, CloseType, Error, Http3StreamInfo, Http3StreamType, HttpRecvStream, HttpRecvStreamEvents,
@@ -41,6 +41,10 @@ pub(crate) struct RecvMessageInfo {
  *    ReadingData : we got a DATA frame, now we letting the app read payload.
  *                  From here we will go back to WaitingForData state to wait
  *                  for more data frames or to CLosed state
+ *    DecodingTrailers : In this step the trailers will be decoded. The stream
+ *                       may be blocked in this state on encoder instructions.
+ *    WaitingForFinAfterTrailers : trailers have been received, only a fin
+ *                                 may follow.
  *    ClosePending : waiting for app to pick up data, after that we can delete
  * the TransactionClient.
  *    Closed
@@ -55,6 +59,7 @@ enum RecvMessageState {
     DecodingHeaders { header_block: Vec<u8>, fin: bool },
     WaitingForData { frame_reader: FrameReader },
     ReadingData { remaining_data_len: usize },
+    DecodingTrailers { header_block: Vec<u8>, fin: bool },
     WaitingForFinAfterTrailers { frame_reader: FrameReader },
     ClosePending, // Close must first be read by application
     Closed,
@@ -122,8 +127,10 @@ impl RecvMessage {
                     self.state = RecvMessageState::DecodingHeaders { header_block, fin };
              }
             RecvMessageState::WaitingForData { ..} => {
-                // TODO implement trailers, for now just ignore them.
-                self.state = RecvMessageState::WaitingForFinAfterTrailers{frame_reader: FrameReader::new()};
+                if header_block.is_empty() {
+                    return Err(Error::HttpGeneralProtocolStream);
+                }
+                self.state = RecvMessageState::DecodingTrailers { header_block, fin };
             }
             RecvMessageState::WaitingForFinAfterTrailers {..} => {
                 return Err(Error::HttpFrameUnexpected);
@@ -202,6 +209,17 @@ impl RecvMessage {
         Ok(())
     }
 
+    fn add_trailers(&mut self, trailers: Vec<Header>) -> Res<()> {
+        qtrace!([self], "Add trailers");
+        trailers_valid(&trailers)?;
+        self.conn_events
+            .trailers_ready(self.get_stream_info(), trailers);
+        self.state = RecvMessageState::WaitingForFinAfterTrailers {
+            frame_reader: FrameReader::new(),
+        };
+        Ok(())
+    }
+
     fn set_state_to_close_pending(&mut self, post_readable_event: bool) -> Res<()> {
         // Stream has received fin. Depending on headers state set header_ready
         // or data_readable event so that app can pick up the fin.
@@ -295,7 +313,11 @@ impl RecvMessage {
                                 break Ok(());
                             }
                             if fin
-                                && !matches!(self.state, RecvMessageState::DecodingHeaders { .. })
+                                && !matches!(
+                                    self.state,
+                                    RecvMessageState::DecodingHeaders { .. }
+                                        | RecvMessageState::DecodingTrailers { .. }
+                                )
                             {
                                 break self.set_state_to_close_pending(post_readable_event);
                             }
@@ -336,6 +358,25 @@ impl RecvMessage {
                         break Ok(());
                     }
                 }
+                RecvMessageState::DecodingTrailers {
+                    ref header_block,
+                    fin,
+                } => {
+                    let done = *fin;
+                    let d_trailers = self
+                        .qpack_decoder
+                        .borrow_mut()
+                        .decode_header_block(header_block, self.stream_id)?;
+                    if let Some(trailers) = d_trailers {
+                        self.add_trailers(trailers)?;
+                        if done {
+                            break self.set_state_to_close_pending(post_readable_event);
+                        }
+                    } else {
+                        qinfo!([self], "decoding trailers is blocked.");
+                        break Ok(());
+                    }
+                }
                 RecvMessageState::ReadingData { .. } => {
                     if post_readable_event {
                         self.conn_events.data_readable(self.get_stream_info());
@@ -445,6 +486,47 @@ impl RecvStream for RecvMessage {
         }
     }
 
+    fn read_data_chunk(&mut self, conn: &mut Connection) -> Res<(Option<Bytes>, bool)> {
+        match self.state {
+            RecvMessageState::ReadingData {
+                ref mut remaining_data_len,
+            } => {
+                let (chunk, fin) = conn
+                    .stream_recv_chunk(self.stream_id, *remaining_data_len)
+                    .map_err(|e| Error::map_stream_recv_errors(&Error::from(e)))?;
+                let amount = chunk.as_ref().map_or(0, Bytes::len);
+                qlog::h3_data_moved_up(conn.qlog_mut(), self.stream_id, amount);
+
+                debug_assert!(amount <= *remaining_data_len);
+                *remaining_data_len -= amount;
+
+                if fin {
+                    if *remaining_data_len > 0 {
+                        return Err(Error::HttpFrame);
+                    }
+                    self.set_closed();
+                    return Ok((chunk, true));
+                } else if *remaining_data_len == 0 {
+                    self.state = RecvMessageState::WaitingForData {
+                        frame_reader: FrameReader::new(),
+                    };
+                    self.receive_internal(conn, false)?;
+                }
+                if matches!(self.state, RecvMessageState::ClosePending) {
+                    self.set_closed();
+                    Ok((chunk, true))
+                } else {
+                    Ok((chunk, false))
+                }
+            }
+            RecvMessageState::ClosePending => {
+                self.set_closed();
+                Ok((None, true))
+            }
+            _ => Ok((None, false)),
+        }
+    }
+
     fn http_stream(&mut self) -> Option<&mut dyn HttpRecvStream> {
         Some(self)
     }
@@ -6,7 +6,7 @@
 
 use std::{any::Any, cell::RefCell, cmp::min, fmt::Debug, rc::Rc};
 
-use neqo_common::{qdebug, qinfo, qtrace, Encoder, Header, MessageType};
+use neqo_common::{qdebug, qinfo, qtrace, Bytes, Encoder, Header, MessageType};
 use neqo_qpack::encoder::QPackEncoder;
 use neqo_transport::{streams::SendOrder, Connection, StreamId};
 
@@ -69,15 +69,20 @@ impl MessageState {
                 }
                 Ok(())
             }
-            Self::WaitingForData => {
-                trailers_valid(headers)?;
-                *self = Self::TrailersSet;
-                Ok(())
-            }
+            Self::WaitingForData => self.new_trailers(headers),
             Self::TrailersSet | Self::Done => Err(Error::InvalidInput),
         }
     }
 
+    fn new_trailers(&mut self, trailers: &[Header]) -> Res<()> {
+        if &Self::WaitingForData != self {
+            return Err(Error::InvalidInput);
+        }
+        trailers_valid(trailers)?;
+        *self = Self::TrailersSet;
+        Ok(())
+    }
+
     fn new_data(&self) -> Res<()> {
         if &Self::WaitingForData == self {
             Ok(())
@@ -147,6 +152,7 @@ impl SendMessage {
         };
         let mut d = Encoder::default();
         hframe.encode(&mut d);
+        qlog::h3_frame_created(conn.qlog_mut(), stream_id, &hframe, d.as_ref());
         d.into()
     }
 
@@ -164,33 +170,35 @@ impl Stream for SendMessage {
         self.stream_type
     }
 }
-impl SendStream for SendMessage {
-    fn send_data(&mut self, conn: &mut Connection, buf: &[u8]) -> Res<usize> {
-        qtrace!([self], "send_body: len={}", buf.len());
+impl SendMessage {
+    /// Works out how much of `len` bytes of body fit into the transport stream and sends
+    /// the header of a DATA frame of that size. Returns `None` if no data can be sent now.
+    fn send_data_frame_header(&mut self, conn: &mut Connection, len: usize) -> Res<Option<usize>> {
+        qtrace!([self], "send_body: len={}", len);
 
         self.state.new_data()?;
 
         self.stream.send_buffer(conn)?;
         if self.stream.has_buffered_data() {
-            return Ok(0);
+            return Ok(None);
         }
         let available = conn
             .stream_avail_send_space(self.stream_id())
             .map_err(|e| Error::map_stream_send_errors(&e.into()))?;
         if available <= 2 {
-            return Ok(0);
+            return Ok(None);
         }
         let to_send = if available <= MAX_DATA_HEADER_SIZE_2_LIMIT {
             // 63 + 3
-            min(min(buf.len(), available - 2), MAX_DATA_HEADER_SIZE_2)
+            min(min(len, available - 2), MAX_DATA_HEADER_SIZE_2)
         } else if available <= MAX_DATA_HEADER_SIZE_3_LIMIT {
             // 16383 + 5
-            min(min(buf.len(), available - 3), MAX_DATA_HEADER_SIZE_3)
+            min(min(len, available - 3), MAX_DATA_HEADER_SIZE_3)
         } else if available <= MAX_DATA_HEADER_SIZE_5 {
             // 1073741823 + 9
-            min(min(buf.len(), available - 5), MAX_DATA_HEADER_SIZE_5_LIMIT)
+            min(min(len, available - 5), MAX_DATA_HEADER_SIZE_5_LIMIT)
         } else {
-            min(buf.len(), available - 9)
+            min(len, available - 9)
         };
 
         qinfo!(
@@ -210,7 +218,16 @@ impl SendStream for SendMessage {
             .send_atomic(conn, enc.as_ref())
             .map_err(|e| Error::map_stream_send_errors(&e))?;
         debug_assert!(sent_fh);
+        qlog::h3_frame_created(conn.qlog_mut(), self.stream_id(), &data_frame, enc.as_ref());
+        Ok(Some(to_send))
+    }
+}
 
+impl SendStream for SendMessage {
+    fn send_data(&mut self, conn: &mut Connection, buf: &[u8]) -> Res<usize> {
+        let Some(to_send) = self.send_data_frame_header(conn, buf.len())? else {
+            return Ok(0);
+        };
         let sent = self
             .stream
             .send_atomic(conn, &buf[..to_send])
@@ -220,6 +237,21 @@ impl SendStream for SendMessage {
         Ok(to_send)
     }
 
+    fn send_data_bytes(&mut self, conn: &mut Connection, buf: Bytes) -> Res<usize> {
+        let Some(to_send) = self.send_data_frame_header(conn, buf.len())? else {
+            return Ok(0);
+        };
+        if to_send > 0 {
+            // There is enough space for the whole frame, so the stream takes all of it.
+            let sent = conn
+                .stream_send_bytes(self.stream_id(), buf.slice(..to_send))
+                .map_err(|e| Error::map_stream_send_errors(&Error::from(e)))?;
+            debug_assert_eq!(sent, to_send);
+        }
+        qlog::h3_data_moved_down(conn.qlog_mut(), self.stream_id(), to_send);
+        Ok(to_send)
+    }
+
     fn done(&self) -> bool {
         !self.stream.has_buffered_data() && self.state.done()
     }
@@ -308,6 +340,7 @@ impl SendStream for SendMessage {
         };
         let mut enc = Encoder::default();
         data_frame.encode(&mut enc);
+        qlog::h3_frame_created(conn.qlog_mut(), self.stream_id(), &data_frame, enc.as_ref());
         self.stream.buffer(enc.as_ref());
         self.stream.buffer(buf);
         _ = self.stream.send_buffer(conn)?;
@@ -328,6 +361,31 @@ impl HttpSendStream for SendMessage {
         Ok(())
     }
 
+    fn send_trailers(&mut self, trailers: &[Header], conn: &mut Connection) -> Res<()> {
+        self.state.new_trailers(trailers)?;
+        let buf = SendMessage::encode(
+            &mut self.encoder.borrow_mut(),
+            trailers,
+            conn,
+            self.stream_id(),
+        );
+        self.stream.buffer(&buf);
+        Ok(())
+    }
+
+    fn send_interim_headers(&mut self, headers: &[Header], conn: &mut Connection) -> Res<()> {
+        if self.message_type != MessageType::Response
+            || self.state != MessageState::WaitingForHeaders
+        {
+            return Err(Error::InvalidInput);
+        }
+        headers_valid(headers, MessageType::Response)?;
+        if !is_interim(headers)? {
+            return Err(Error::InvalidHeader);
+        }
+        self.send_headers(headers, conn)
+    }
+
     fn set_new_listener(&mut self, conn_events: Box<dyn SendStreamEvents>) {
         self.stream_type = Http3StreamType::ExtendedConnect;
         self.conn_events = conn_events;
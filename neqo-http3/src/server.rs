@@ -14,8 +14,11 @@ use std::{
     time::Instant,
 };
 
-use neqo_common::{qtrace, Datagram};
-use neqo_crypto::{AntiReplay, Cipher, PrivateKey, PublicKey, ZeroRttChecker};
+use neqo_common::{qtrace, Bytes, Datagram};
+use neqo_crypto::{
+    AntiReplay, CertificateVerifier, Cipher, PrivateKey, PublicKey, ServerNameSelector,
+    ZeroRttChecker,
+};
 use neqo_transport::{
     server::{ActiveConnectionRef, Server, ValidateAddress},
     ConnectionIdGenerator, Output,
@@ -84,6 +87,10 @@ impl Http3Server {
         self.server.set_qlog_dir(dir);
     }
 
+    pub fn set_pcap_dir(&mut self, dir: Option<PathBuf>) {
+        self.server.set_pcap_dir(dir);
+    }
+
     pub fn set_validation(&mut self, v: ValidateAddress) {
         self.server.set_validation(v);
     }
@@ -113,6 +120,17 @@ impl Http3Server {
         self.server.ech_config()
     }
 
+    /// Select certificates based on the server name indication (SNI) of new connections.
+    pub fn set_sni_selector(&mut self, selector: Option<Rc<dyn ServerNameSelector>>) {
+        self.server.set_sni_selector(selector);
+    }
+
+    /// Ask clients of new connections for certificates, which are checked with `verifier`.
+    /// Request handlers can see the certificate with `peer_certificate`.
+    pub fn request_client_certificate(&mut self, verifier: CertificateVerifier, required: bool) {
+        self.server.request_client_certificate(verifier, required);
+    }
+
     pub fn process(&mut self, dgram: Option<&Datagram>, now: Instant) -> Output {
         qtrace!([self], "Process.");
         let out = self.server.process(dgram, now);
@@ -181,15 +199,33 @@ impl Http3Server {
                         headers,
                         fin,
                     ),
+                    Http3ServerConnEvent::Trailers {
+                        stream_info,
+                        trailers,
+                    } => self.events.trailers(
+                        Http3OrWebTransportStream::new(conn.clone(), handler.clone(), stream_info),
+                        trailers,
+                    ),
                     Http3ServerConnEvent::DataReadable { stream_info } => {
-                        prepare_data(
-                            stream_info,
-                            &mut handler_borrowed,
-                            conn,
-                            handler,
-                            now,
-                            &mut self.events,
-                        );
+                        if http3_parameters.get_data_chunk_events() {
+                            prepare_data_chunks(
+                                stream_info,
+                                &mut handler_borrowed,
+                                conn,
+                                handler,
+                                now,
+                                &mut self.events,
+                            );
+                        } else {
+                            prepare_data(
+                                stream_info,
+                                &mut handler_borrowed,
+                                conn,
+                                handler,
+                                now,
+                                &mut self.events,
+                            );
+                        }
                     }
                     Http3ServerConnEvent::DataWritable { stream_info } => self
                         .events
@@ -249,6 +285,23 @@ impl Http3Server {
                         WebTransportRequest::new(conn.clone(), handler.clone(), session_id),
                         datagram,
                     ),
+                    Http3ServerConnEvent::ExtendedConnectBlocked {
+                        session_id,
+                        limit,
+                        blocked,
+                    } => self.events.webtransport_session_blocked(
+                        WebTransportRequest::new(conn.clone(), handler.clone(), session_id),
+                        limit,
+                        blocked,
+                    ),
+                    Http3ServerConnEvent::ExtendedConnectDraining { session_id } => {
+                        self.events
+                            .webtransport_session_draining(WebTransportRequest::new(
+                                conn.clone(),
+                                handler.clone(),
+                                session_id,
+                            ));
+                    }
                 }
             }
         }
@@ -257,6 +310,15 @@ impl Http3Server {
         }
     }
 
+    /// Send a GOAWAY on all connections, e.g. before the server shuts down. Requests that have
+    /// already been received are still served, and all `WebTransport` sessions are asked to
+    /// drain.
+    pub fn goaway(&mut self) {
+        for (conn, handler) in &self.http3_handlers {
+            handler.borrow_mut().goaway(&mut conn.borrow_mut());
+        }
+    }
+
     /// Get all current events. Best used just in debug/testing code, use
     /// `next_event` instead.
     pub fn events(&mut self) -> impl Iterator<Item = Http3ServerEvent> {
@@ -311,6 +373,41 @@ fn prepare_data(
     }
 }
 
+fn prepare_data_chunks(
+    stream_info: Http3StreamInfo,
+    handler_borrowed: &mut RefMut<Http3ServerHandler>,
+    conn: &mut ActiveConnectionRef,
+    handler: &HandlerRef,
+    now: Instant,
+    events: &mut Http3ServerEvents,
+) {
+    loop {
+        let res =
+            handler_borrowed.read_data_chunk(&mut conn.borrow_mut(), now, stream_info.stream_id());
+        match res {
+            Ok((Some(data), fin)) => {
+                events.data_chunk(conn.clone(), handler.clone(), stream_info, data, fin);
+                if fin {
+                    break;
+                }
+            }
+            Ok((None, true)) => {
+                events.data_chunk(
+                    conn.clone(),
+                    handler.clone(),
+                    stream_info,
+                    Bytes::new(),
+                    true,
+                );
+                break;
+            }
+            // Any error will closed the handler, just ignore this event, the next event must
+            // be a state change event.
+            Ok((None, false)) | Err(_) => break,
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::{
@@ -927,10 +1024,12 @@ mod tests {
                     data_received += 1;
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::DataChunk { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::WebTransport(_) => {}
             }
         }
@@ -938,6 +1037,35 @@ mod tests {
         assert_eq!(data_received, 1);
     }
 
+    #[test]
+    fn test_server_request_with_body_chunks() {
+        let mut hconn = create_server(http3params(DEFAULT_SETTINGS).data_chunk_events(true));
+        let mut peer_conn = connect_to(&mut hconn);
+
+        let stream_id = peer_conn.stream_create(StreamType::BiDi).unwrap();
+        peer_conn.stream_send(stream_id, REQUEST_WITH_BODY).unwrap();
+        peer_conn.stream_close_send(stream_id).unwrap();
+
+        let out = peer_conn.process(None, now());
+        hconn.process(out.as_dgram_ref(), now());
+
+        let mut body = Vec::new();
+        let mut fin_received = false;
+        while let Some(event) = hconn.next_event() {
+            match event {
+                Http3ServerEvent::Data { .. } => panic!("unexpected Data event"),
+                Http3ServerEvent::DataChunk { data, fin, .. } => {
+                    assert!(!fin_received);
+                    body.extend_from_slice(&data);
+                    fin_received = fin;
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(body, REQUEST_BODY);
+        assert!(fin_received);
+    }
+
     #[test]
     fn test_server_request_with_body_send_stop_sending() {
         let (mut hconn, mut peer_conn) = connect();
@@ -978,10 +1106,12 @@ mod tests {
                     panic!("We should not have a Data event");
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::DataChunk { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::WebTransport(_) => {}
             }
         }
@@ -1005,10 +1135,12 @@ mod tests {
                     panic!("We should not have a Data event");
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::DataChunk { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::WebTransport(_) => {}
             }
         }
@@ -1049,10 +1181,12 @@ mod tests {
                     panic!("We should not have a Data event");
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::DataChunk { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::WebTransport(_) => {}
             }
         }
@@ -1278,10 +1412,12 @@ mod tests {
                     assert!(requests.get(&stream).is_some());
                 }
                 Http3ServerEvent::DataWritable { .. }
+                | Http3ServerEvent::DataChunk { .. }
                 | Http3ServerEvent::StreamReset { .. }
                 | Http3ServerEvent::StreamStopSending { .. }
                 | Http3ServerEvent::StateChange { .. }
                 | Http3ServerEvent::PriorityUpdate { .. }
+                | Http3ServerEvent::Trailers { .. }
                 | Http3ServerEvent::WebTransport(_) => {}
             }
         }
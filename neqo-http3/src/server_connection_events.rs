@@ -11,7 +11,9 @@ use neqo_transport::{AppError, StreamId};
 
 use crate::{
     connection::Http3State,
-    features::extended_connect::{ExtendedConnectEvents, ExtendedConnectType, SessionCloseReason},
+    features::extended_connect::{
+        ExtendedConnectEvents, ExtendedConnectType, SessionCloseReason, SessionLimit,
+    },
     CloseType, Http3StreamInfo, HttpRecvStreamEvents, Priority, RecvStreamEvents, SendStreamEvents,
 };
 
@@ -27,6 +29,11 @@ pub(crate) enum Http3ServerConnEvent {
         stream_id: StreamId,
         priority: Priority,
     },
+    /// Trailers are ready.
+    Trailers {
+        stream_info: Http3StreamInfo,
+        trailers: Vec<Header>,
+    },
     /// Request data is ready.
     DataReadable {
         stream_info: Http3StreamInfo,
@@ -59,6 +66,14 @@ pub(crate) enum Http3ServerConnEvent {
         session_id: StreamId,
         datagram: Vec<u8>,
     },
+    ExtendedConnectBlocked {
+        session_id: StreamId,
+        limit: SessionLimit,
+        blocked: bool,
+    },
+    ExtendedConnectDraining {
+        session_id: StreamId,
+    },
 }
 
 #[derive(Debug, Default, Clone)]
@@ -114,6 +129,14 @@ impl HttpRecvStreamEvents for Http3ServerConnEvents {
         });
     }
 
+    /// Add a new `Trailers` event.
+    fn trailers_ready(&self, stream_info: Http3StreamInfo, trailers: Vec<Header>) {
+        self.insert(Http3ServerConnEvent::Trailers {
+            stream_info,
+            trailers,
+        });
+    }
+
     fn extended_connect_new_session(&self, stream_id: StreamId, headers: Vec<Header>) {
         self.insert(Http3ServerConnEvent::ExtendedConnect { stream_id, headers });
     }
@@ -154,6 +177,26 @@ impl ExtendedConnectEvents for Http3ServerConnEvents {
             datagram,
         });
     }
+
+    fn session_blocked(&self, session_id: StreamId, limit: SessionLimit) {
+        self.insert(Http3ServerConnEvent::ExtendedConnectBlocked {
+            session_id,
+            limit,
+            blocked: true,
+        });
+    }
+
+    fn session_unblocked(&self, session_id: StreamId, limit: SessionLimit) {
+        self.insert(Http3ServerConnEvent::ExtendedConnectBlocked {
+            session_id,
+            limit,
+            blocked: false,
+        });
+    }
+
+    fn session_draining(&self, session_id: StreamId) {
+        self.insert(Http3ServerConnEvent::ExtendedConnectDraining { session_id });
+    }
 }
 
 impl Http3ServerConnEvents {
@@ -190,7 +233,9 @@ impl Http3ServerConnEvents {
     fn remove_events_for_stream_id(&self, stream_info: Http3StreamInfo) {
         self.remove(|evt| {
             matches!(evt,
-                Http3ServerConnEvent::Headers { stream_info: x, .. } | Http3ServerConnEvent::DataReadable { stream_info: x, .. } if *x == stream_info)
+                Http3ServerConnEvent::Headers { stream_info: x, .. }
+                | Http3ServerConnEvent::Trailers { stream_info: x, .. }
+                | Http3ServerConnEvent::DataReadable { stream_info: x, .. } if *x == stream_info)
         });
     }
 }
@@ -12,9 +12,11 @@ use std::{
     convert::TryFrom,
     ops::{Deref, DerefMut},
     rc::Rc,
+    time::Instant,
 };
 
-use neqo_common::{qdebug, qinfo, Encoder, Header};
+use neqo_common::{qdebug, qinfo, Bytes, Encoder, Header};
+use neqo_crypto::agent::CertificateInfo;
 use neqo_transport::{
     server::ActiveConnectionRef, AppError, Connection, DatagramTracking, StreamId, StreamType,
 };
@@ -22,8 +24,8 @@ use neqo_transport::{
 use crate::{
     connection::{Http3State, WebTransportSessionAcceptAction},
     connection_server::Http3ServerHandler,
-    features::extended_connect::SessionCloseReason,
-    Http3StreamInfo, Http3StreamType, Priority, Res,
+    features::extended_connect::{SessionCloseReason, SessionLimit},
+    Error, Http3StreamInfo, Http3StreamType, Priority, Res,
 };
 
 #[derive(Debug, Clone)]
@@ -61,6 +63,23 @@ impl StreamHandler {
         self.stream_info.stream_id()
     }
 
+    /// The server name that the client indicated for the connection, if the server selects
+    /// certificates by name.
+    #[must_use]
+    pub fn server_name(&self) -> Option<String> {
+        self.conn
+            .borrow()
+            .server_name_indication()
+            .map(String::from)
+    }
+
+    /// The certificate chain that the client presented, if the server asked for one.
+    /// Handlers can use this to authorize requests.
+    #[must_use]
+    pub fn peer_certificate(&self) -> Option<CertificateInfo> {
+        self.conn.borrow().peer_certificate()
+    }
+
     /// Supply a response header to a request.
     ///
     /// # Errors
@@ -85,6 +104,58 @@ impl StreamHandler {
             .send_data(self.stream_id(), buf, &mut self.conn.borrow_mut())
     }
 
+    /// Supply response data to a request, without copying it. Returns how many bytes were
+    /// accepted.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    pub fn send_data_bytes(&mut self, buf: Bytes) -> Res<usize> {
+        self.handler.borrow_mut().send_data_bytes(
+            self.stream_id(),
+            buf,
+            &mut self.conn.borrow_mut(),
+        )
+    }
+
+    /// Send an informational (1xx) response, e.g. 103 Early Hints. This can be done any number
+    /// of times before the final response headers are supplied with `send_headers`. `headers`
+    /// must not contain the `:status` pseudo-header.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore, `InvalidInput` if
+    /// `status` is not an informational status other than 101, `InvalidInput` if the final
+    /// response headers have already been sent, or `InvalidHeader` if the headers are not valid.
+    pub fn send_interim_response(&mut self, status: u16, headers: &[Header]) -> Res<()> {
+        if !(100..200).contains(&status) || status == 101 {
+            return Err(Error::InvalidInput);
+        }
+        let mut interim_headers = vec![Header::new(":status", status.to_string())];
+        interim_headers.extend_from_slice(headers);
+        self.handler.borrow_mut().send_interim_headers(
+            self.stream_id(),
+            &interim_headers,
+            &mut self.conn.borrow_mut(),
+        )
+    }
+
+    /// Supply response trailers and close the sending side.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore, `InvalidInput` if
+    /// the response headers have not been sent yet, `InvalidInput` if trailers have already been
+    /// sent or the sending side has been closed (FIN), or `InvalidHeader` if the trailers contain
+    /// pseudo-headers.
+    pub fn send_trailers(&mut self, trailers: &[Header]) -> Res<()> {
+        self.handler.borrow_mut().send_trailers(
+            self.stream_id(),
+            trailers,
+            &mut self.conn.borrow_mut(),
+        )
+    }
+
     /// Close sending side.
     ///
     /// # Errors
@@ -194,6 +265,38 @@ impl Http3OrWebTransportStream {
         self.stream_handler.send_data(data)
     }
 
+    /// Supply response data to a request, without copying it.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore.
+    pub fn send_data_bytes(&mut self, data: Bytes) -> Res<usize> {
+        self.stream_handler.send_data_bytes(data)
+    }
+
+    /// Send an informational (1xx) response.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore, `InvalidInput` if
+    /// `status` is not an informational status other than 101, `InvalidInput` if the final
+    /// response headers have already been sent, or `InvalidHeader` if the headers are not valid.
+    pub fn send_interim_response(&mut self, status: u16, headers: &[Header]) -> Res<()> {
+        self.stream_handler.send_interim_response(status, headers)
+    }
+
+    /// Supply response trailers and close the sending side.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore, `InvalidInput` if
+    /// the response headers have not been sent yet, `InvalidInput` if trailers have already been
+    /// sent or the sending side has been closed (FIN), or `InvalidHeader` if the trailers contain
+    /// pseudo-headers.
+    pub fn send_trailers(&mut self, trailers: &[Header]) -> Res<()> {
+        self.stream_handler.send_trailers(trailers)
+    }
+
     /// Close sending side.
     ///
     /// # Errors
@@ -299,6 +402,23 @@ impl WebTransportRequest {
             )
     }
 
+    /// Ask the client to wind down the session, e.g. before the server shuts down. The session
+    /// keeps working until it is closed by either side.
+    ///
+    /// # Errors
+    ///
+    /// It may return `InvalidStreamId` if a stream does not exist anymore or the session is
+    /// not active.
+    pub fn drain_session(&mut self) -> Res<()> {
+        self.stream_handler
+            .handler
+            .borrow_mut()
+            .webtransport_drain_session(
+                &mut self.stream_handler.conn.borrow_mut(),
+                self.stream_handler.stream_info.stream_id(),
+            )
+    }
+
     #[must_use]
     pub fn stream_id(&self) -> StreamId {
         self.stream_handler.stream_id()
@@ -336,6 +456,21 @@ impl WebTransportRequest {
     /// The function returns `TooMuchData` if the supply buffer is bigger than
     /// the allowed remote datagram size.
     pub fn send_datagram(&mut self, buf: &[u8], id: impl Into<DatagramTracking>) -> Res<()> {
+        self.send_datagram_with_deadline(buf, id, None)
+    }
+
+    /// Send `WebTransport` datagram, as with `send_datagram`.  If the datagram
+    /// has not been sent by `deadline`, it is dropped.
+    ///
+    /// # Errors
+    ///
+    /// As for `send_datagram`.
+    pub fn send_datagram_with_deadline(
+        &mut self,
+        buf: &[u8],
+        id: impl Into<DatagramTracking>,
+        deadline: Option<Instant>,
+    ) -> Res<()> {
         let session_id = self.stream_handler.stream_id();
         self.stream_handler
             .handler
@@ -345,6 +480,7 @@ impl WebTransportRequest {
                 session_id,
                 buf,
                 id,
+                deadline,
             )
     }
 
@@ -419,6 +555,21 @@ pub enum WebTransportServerEvent {
         session: WebTransportRequest,
         datagram: Vec<u8>,
     },
+    /// The session cannot open more streams of a type, or send more data, until the client
+    /// raises its session limit.
+    SessionBlocked {
+        session: WebTransportRequest,
+        limit: SessionLimit,
+    },
+    /// The client raised a session limit that was blocking the session.
+    SessionUnblocked {
+        session: WebTransportRequest,
+        limit: SessionLimit,
+    },
+    /// The client asked for the session to be wound down.
+    SessionDraining {
+        session: WebTransportRequest,
+    },
 }
 
 #[derive(Debug, Clone)]
@@ -435,6 +586,13 @@ pub enum Http3ServerEvent {
         data: Vec<u8>,
         fin: bool,
     },
+    /// Request data is ready. This is used instead of `Data` if
+    /// `Http3Parameters::data_chunk_events` is enabled. `data` is at most the rest of a DATA frame.
+    DataChunk {
+        stream: Http3OrWebTransportStream,
+        data: Bytes,
+        fin: bool,
+    },
     DataWritable {
         stream: Http3OrWebTransportStream,
     },
@@ -455,6 +613,11 @@ pub enum Http3ServerEvent {
         stream_id: StreamId,
         priority: Priority,
     },
+    /// Request trailers are ready. They are received after all request data.
+    Trailers {
+        stream: Http3OrWebTransportStream,
+        trailers: Vec<Header>,
+    },
     WebTransport(WebTransportServerEvent),
 }
 
@@ -497,6 +660,14 @@ impl Http3ServerEvents {
         });
     }
 
+    /// Insert a `Trailers` event.
+    pub(crate) fn trailers(&self, request: Http3OrWebTransportStream, trailers: Vec<Header>) {
+        self.insert(Http3ServerEvent::Trailers {
+            stream: request,
+            trailers,
+        });
+    }
+
     /// Insert a `StateChange` event.
     pub(crate) fn connection_state_change(&self, conn: ActiveConnectionRef, state: Http3State) {
         self.insert(Http3ServerEvent::StateChange { conn, state });
@@ -518,6 +689,22 @@ impl Http3ServerEvents {
         });
     }
 
+    /// Insert a `DataChunk` event.
+    pub(crate) fn data_chunk(
+        &self,
+        conn: ActiveConnectionRef,
+        handler: Rc<RefCell<Http3ServerHandler>>,
+        stream_info: Http3StreamInfo,
+        data: Bytes,
+        fin: bool,
+    ) {
+        self.insert(Http3ServerEvent::DataChunk {
+            stream: Http3OrWebTransportStream::new(conn, handler, stream_info),
+            data,
+            fin,
+        });
+    }
+
     pub(crate) fn data_writable(
         &self,
         conn: ActiveConnectionRef,
@@ -598,4 +785,23 @@ impl Http3ServerEvents {
             WebTransportServerEvent::Datagram { session, datagram },
         ));
     }
+
+    pub(crate) fn webtransport_session_blocked(
+        &self,
+        session: WebTransportRequest,
+        limit: SessionLimit,
+        blocked: bool,
+    ) {
+        self.insert(Http3ServerEvent::WebTransport(if blocked {
+            WebTransportServerEvent::SessionBlocked { session, limit }
+        } else {
+            WebTransportServerEvent::SessionUnblocked { session, limit }
+        }));
+    }
+
+    pub(crate) fn webtransport_session_draining(&self, session: WebTransportRequest) {
+        self.insert(Http3ServerEvent::WebTransport(
+            WebTransportServerEvent::SessionDraining { session },
+        ));
+    }
 }
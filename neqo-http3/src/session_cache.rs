@@ -0,0 +1,234 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    io::{self, ErrorKind},
+    path::Path,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use neqo_common::{qdebug, Decoder, Encoder};
+use neqo_crypto::ResumptionToken;
+
+/// Identifies the file format of a saved session cache.
+const SESSION_CACHE_MAGIC: &[u8] = b"neqo-session-cache-1";
+/// Tokens can only be used once, so a few are kept for each origin.
+const MAX_TOKENS_PER_ORIGIN: usize = 4;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    token: Vec<u8>,
+    /// The wall clock time at which the token expires.  An `Instant` can't be saved.
+    expires: SystemTime,
+}
+
+impl Entry {
+    fn remaining(&self) -> Option<Duration> {
+        self.expires
+            .duration_since(SystemTime::now())
+            .ok()
+            .filter(|d| !d.is_zero())
+    }
+}
+
+/// A cache of resumption tokens, keyed by origin, that can be saved to a file so that
+/// a later process can resume connections and attempt 0-RTT.
+///
+/// The tokens are those produced by `Http3Client::take_resumption_token` or in a
+/// `Http3ClientEvent::ResumptionToken` event.  Each holds the TLS session ticket, the
+/// address validation token from any `NEW_TOKEN` frame, and the transport parameters and
+/// HTTP/3 settings of the server, which is everything that `Http3Client::enable_resumption`
+/// needs.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct SessionCache {
+    entries: HashMap<String, Vec<Entry>>,
+}
+
+impl SessionCache {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Load a cache from `path`.  If the file does not exist, the cache is empty.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read or it doesn't contain a session cache.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        match fs::read(path) {
+            Ok(buf) => Self::decode(&buf).ok_or_else(|| io::Error::from(ErrorKind::InvalidData)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(Self::new()),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Save the unexpired tokens to `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be written.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        fs::write(path, self.encode())
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut enc = Encoder::default();
+        enc.encode(SESSION_CACHE_MAGIC);
+        for (origin, entries) in &self.entries {
+            for e in entries.iter().filter(|e| e.remaining().is_some()) {
+                let expires = e.expires.duration_since(UNIX_EPOCH).unwrap_or_default();
+                enc.encode_vvec(origin.as_bytes());
+                enc.encode_varint(u64::try_from(expires.as_millis()).unwrap_or(u64::MAX));
+                enc.encode_vvec(&e.token);
+            }
+        }
+        Vec::from(enc)
+    }
+
+    fn decode(buf: &[u8]) -> Option<Self> {
+        let mut dec = Decoder::new(buf);
+        if dec.decode(SESSION_CACHE_MAGIC.len())? != SESSION_CACHE_MAGIC {
+            return None;
+        }
+        let mut cache = Self::new();
+        while dec.remaining() > 0 {
+            let origin = String::from_utf8(dec.decode_vvec()?.to_vec()).ok()?;
+            let expires = UNIX_EPOCH.checked_add(Duration::from_millis(dec.decode_varint()?))?;
+            let token = dec.decode_vvec()?.to_vec();
+            cache.push(origin, Entry { token, expires });
+        }
+        Some(cache)
+    }
+
+    fn push(&mut self, origin: String, entry: Entry) {
+        let entries = self.entries.entry(origin).or_default();
+        entries.push(entry);
+        if entries.len() > MAX_TOKENS_PER_ORIGIN {
+            entries.remove(0);
+        }
+    }
+
+    /// Add a token for `origin`.  Tokens that have already expired are ignored.
+    pub fn insert(&mut self, origin: impl Into<String>, token: &ResumptionToken, now: Instant) {
+        let lifetime = token.expiration_time().saturating_duration_since(now);
+        if lifetime.is_zero() {
+            return;
+        }
+        let origin = origin.into();
+        qdebug!("Caching a session for {} for {:?}", origin, lifetime);
+        self.push(
+            origin,
+            Entry {
+                token: token.as_ref().to_vec(),
+                expires: SystemTime::now() + lifetime,
+            },
+        );
+    }
+
+    /// Take the most recent unexpired token for `origin`, removing it from the cache.
+    pub fn take(&mut self, origin: &str, now: Instant) -> Option<ResumptionToken> {
+        let entries = self.entries.get_mut(origin)?;
+        entries.retain(|e| e.remaining().is_some());
+        let entry = entries.pop();
+        if entries.is_empty() {
+            self.entries.remove(origin);
+        }
+        let entry = entry?;
+        let remaining = entry.remaining()?;
+        Some(ResumptionToken::new(entry.token, now + remaining))
+    }
+
+    /// The number of tokens in the cache, including any that have expired.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.entries.values().map(Vec::len).sum()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{convert::TryFrom, env, fs, process, time::Duration};
+
+    use neqo_crypto::ResumptionToken;
+    use test_fixture::now;
+
+    use super::{SessionCache, MAX_TOKENS_PER_ORIGIN};
+
+    const ORIGIN: &str = "https://example.com";
+    const HOUR: Duration = Duration::from_secs(3600);
+
+    fn token(v: u8) -> ResumptionToken {
+        ResumptionToken::new(vec![v; 8], now() + HOUR)
+    }
+
+    #[test]
+    fn take_newest() {
+        let mut cache = SessionCache::new();
+        cache.insert(ORIGIN, &token(1), now());
+        cache.insert(ORIGIN, &token(2), now());
+        assert_eq!(cache.len(), 2);
+
+        assert!(cache.take("https://example.net", now()).is_none());
+        let t = cache.take(ORIGIN, now()).unwrap();
+        assert_eq!(t.as_ref(), &[2; 8]);
+        assert!(t.expiration_time() > now());
+        assert!(t.expiration_time() <= now() + HOUR);
+        assert_eq!(cache.take(ORIGIN, now()).unwrap().as_ref(), &[1; 8]);
+        assert!(cache.take(ORIGIN, now()).is_none());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn expired() {
+        let mut cache = SessionCache::new();
+        cache.insert(ORIGIN, &ResumptionToken::new(vec![1], now()), now());
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn limit() {
+        let mut cache = SessionCache::new();
+        for i in 0..=MAX_TOKENS_PER_ORIGIN {
+            cache.insert(ORIGIN, &token(u8::try_from(i).unwrap()), now());
+        }
+        assert_eq!(cache.len(), MAX_TOKENS_PER_ORIGIN);
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut cache = SessionCache::new();
+        cache.insert(ORIGIN, &token(1), now());
+        cache.insert("https://example.net:8443", &token(2), now());
+        let mut decoded = SessionCache::decode(&cache.encode()).unwrap();
+        assert_eq!(decoded.len(), 2);
+        let t = decoded.take("https://example.net:8443", now()).unwrap();
+        assert_eq!(t.as_ref(), &[2; 8]);
+        assert!(t.expiration_time() > now());
+        assert!(SessionCache::decode(b"not a session cache").is_none());
+    }
+
+    #[test]
+    fn load_and_save() {
+        let path = env::temp_dir().join(format!("neqo-session-cache-{}", process::id()));
+        assert!(SessionCache::load(&path).unwrap().is_empty());
+
+        let mut cache = SessionCache::new();
+        cache.insert(ORIGIN, &token(1), now());
+        cache.save(&path).unwrap();
+        let mut loaded = SessionCache::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(loaded.take(ORIGIN, now()).unwrap().as_ref(), &[1; 8]);
+    }
+}
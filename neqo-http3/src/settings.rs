@@ -23,6 +23,10 @@ const SETTINGS_MAX_HEADER_LIST_SIZE: SettingsType = 0x6;
 const SETTINGS_QPACK_MAX_TABLE_CAPACITY: SettingsType = 0x1;
 const SETTINGS_QPACK_BLOCKED_STREAMS: SettingsType = 0x7;
 const SETTINGS_ENABLE_WEB_TRANSPORT: SettingsType = 0x2b60_3742;
+// Session flow control, draft-ietf-webtrans-http3-09.
+const SETTINGS_WEBTRANSPORT_INITIAL_MAX_DATA: SettingsType = 0x2b61;
+const SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI: SettingsType = 0x2b64;
+const SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI: SettingsType = 0x2b65;
 // draft-ietf-masque-h3-datagram-04.
 // We also use this old value because the current web-platform test only supports
 // this value.
@@ -39,6 +43,9 @@ pub enum HSettingType {
     BlockedStreams,
     EnableWebTransport,
     EnableH3Datagram,
+    WebTransportInitialMaxData,
+    WebTransportInitialMaxStreamsUni,
+    WebTransportInitialMaxStreamsBidi,
 }
 
 fn hsetting_default(setting_type: HSettingType) -> u64 {
@@ -47,7 +54,10 @@ fn hsetting_default(setting_type: HSettingType) -> u64 {
         HSettingType::MaxTableCapacity
         | HSettingType::BlockedStreams
         | HSettingType::EnableWebTransport
-        | HSettingType::EnableH3Datagram => 0,
+        | HSettingType::EnableH3Datagram
+        | HSettingType::WebTransportInitialMaxData
+        | HSettingType::WebTransportInitialMaxStreamsUni
+        | HSettingType::WebTransportInitialMaxStreamsBidi => 0,
     }
 }
 
@@ -88,6 +98,15 @@ impl HSettings {
         }
     }
 
+    /// Like `get`, but returns `None` if the setting was not sent instead of its default value.
+    #[must_use]
+    pub fn get_if_present(&self, setting: HSettingType) -> Option<u64> {
+        self.settings
+            .iter()
+            .find(|s| s.setting_type == setting)
+            .map(|s| s.value)
+    }
+
     pub fn encode_frame_contents(&self, enc: &mut Encoder) {
         enc.encode_vvec_with(|enc_inner| {
             for iter in &self.settings {
@@ -108,6 +127,18 @@ impl HSettings {
                         enc_inner.encode_varint(SETTINGS_ENABLE_WEB_TRANSPORT);
                         enc_inner.encode_varint(iter.value);
                     }
+                    HSettingType::WebTransportInitialMaxData => {
+                        enc_inner.encode_varint(SETTINGS_WEBTRANSPORT_INITIAL_MAX_DATA);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::WebTransportInitialMaxStreamsUni => {
+                        enc_inner.encode_varint(SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI);
+                        enc_inner.encode_varint(iter.value);
+                    }
+                    HSettingType::WebTransportInitialMaxStreamsBidi => {
+                        enc_inner.encode_varint(SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI);
+                        enc_inner.encode_varint(iter.value);
+                    }
                     HSettingType::EnableH3Datagram => {
                         if iter.value == 1 {
                             enc_inner.encode_varint(SETTINGS_H3_DATAGRAM_DRAFT04);
@@ -177,6 +208,21 @@ impl HSettings {
                             .push(HSetting::new(HSettingType::EnableH3Datagram, value));
                     }
                 }
+                (Some(SETTINGS_WEBTRANSPORT_INITIAL_MAX_DATA), Some(value)) => self.settings.push(
+                    HSetting::new(HSettingType::WebTransportInitialMaxData, value),
+                ),
+                (Some(SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI), Some(value)) => {
+                    self.settings.push(HSetting::new(
+                        HSettingType::WebTransportInitialMaxStreamsUni,
+                        value,
+                    ));
+                }
+                (Some(SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI), Some(value)) => {
+                    self.settings.push(HSetting::new(
+                        HSettingType::WebTransportInitialMaxStreamsBidi,
+                        value,
+                    ));
+                }
                 // other supported settings here
                 (Some(_), Some(_)) => {} // ignore unknown setting, it is fine.
                 _ => return Err(Error::NotEnoughData),
@@ -195,7 +241,7 @@ impl Deref for HSettings {
 
 impl From<&Http3Parameters> for HSettings {
     fn from(conn_param: &Http3Parameters) -> Self {
-        Self {
+        let mut settings = Self {
             settings: vec![
                 HSetting {
                     setting_type: HSettingType::MaxTableCapacity,
@@ -214,7 +260,28 @@ impl From<&Http3Parameters> for HSettings {
                     value: u64::from(conn_param.get_http3_datagram()),
                 },
             ],
+        };
+        if conn_param.get_webtransport() {
+            for (setting_type, value) in [
+                (
+                    HSettingType::WebTransportInitialMaxData,
+                    conn_param.get_webtransport_max_data(),
+                ),
+                (
+                    HSettingType::WebTransportInitialMaxStreamsUni,
+                    conn_param.get_webtransport_max_streams_uni(),
+                ),
+                (
+                    HSettingType::WebTransportInitialMaxStreamsBidi,
+                    conn_param.get_webtransport_max_streams_bidi(),
+                ),
+            ] {
+                if let Some(value) = value {
+                    settings.settings.push(HSetting::new(setting_type, value));
+                }
+            }
         }
+        settings
     }
 }
 
@@ -246,8 +313,50 @@ impl HttpZeroRttChecker {
         if settings.get_http3_datagram() {
             enc.encode_varint(SETTINGS_H3_DATAGRAM).encode_varint(true);
         }
+        if settings.get_webtransport() {
+            if let Some(max) = settings.get_webtransport_max_data() {
+                enc.encode_varint(SETTINGS_WEBTRANSPORT_INITIAL_MAX_DATA)
+                    .encode_varint(max);
+            }
+            if let Some(max) = settings.get_webtransport_max_streams_uni() {
+                enc.encode_varint(SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_UNI)
+                    .encode_varint(max);
+            }
+            if let Some(max) = settings.get_webtransport_max_streams_bidi() {
+                enc.encode_varint(SETTINGS_WEBTRANSPORT_INITIAL_MAX_STREAMS_BIDI)
+                    .encode_varint(max);
+            }
+        }
         enc.into()
     }
+
+    /// A session limit that was absent from the remembered settings was unlimited
+    /// for 0-RTT, so adding one now lowers it.
+    fn check_added_webtransport_limits(&self, remembered: &HSettings) -> bool {
+        if !self.settings.get_webtransport()
+            || remembered.get(HSettingType::EnableWebTransport) != 1
+        {
+            return true;
+        }
+        [
+            (
+                HSettingType::WebTransportInitialMaxData,
+                self.settings.get_webtransport_max_data(),
+            ),
+            (
+                HSettingType::WebTransportInitialMaxStreamsUni,
+                self.settings.get_webtransport_max_streams_uni(),
+            ),
+            (
+                HSettingType::WebTransportInitialMaxStreamsBidi,
+                self.settings.get_webtransport_max_streams_bidi(),
+            ),
+        ]
+        .iter()
+        .all(|(setting_type, current)| {
+            current.is_none() || remembered.get_if_present(*setting_type).is_some()
+        })
+    }
 }
 
 impl ZeroRttChecker for HttpZeroRttChecker {
@@ -289,8 +398,22 @@ impl ZeroRttChecker for HttpZeroRttChecker {
                 let value = setting.value == 1;
                 self.settings.get_http3_datagram() || !value
             }
+            // A server must not lower the session limits it remembered for 0-RTT.
+            HSettingType::WebTransportInitialMaxData => self
+                .settings
+                .get_webtransport_max_data()
+                .map_or(true, |v| v >= setting.value),
+            HSettingType::WebTransportInitialMaxStreamsUni => self
+                .settings
+                .get_webtransport_max_streams_uni()
+                .map_or(true, |v| v >= setting.value),
+            HSettingType::WebTransportInitialMaxStreamsBidi => self
+                .settings
+                .get_webtransport_max_streams_bidi()
+                .map_or(true, |v| v >= setting.value),
             HSettingType::MaxHeaderListSize => true,
-        }) {
+        }) && self.check_added_webtransport_limits(&settings)
+        {
             ZeroRttCheckResult::Accept
         } else {
             ZeroRttCheckResult::Reject
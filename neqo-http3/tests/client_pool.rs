@@ -0,0 +1,321 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{cell::RefCell, collections::HashMap, net::SocketAddr, rc::Rc};
+
+use neqo_common::{event::Provider, Header};
+use neqo_crypto::AuthenticationStatus;
+use neqo_http3::{
+    AddressResolver, Error, Http3ClientPool, Http3ClientPoolEvent, Http3ClientPoolParameters,
+    Http3Server, Http3ServerEvent, Http3State, PoolRequestId, Priority,
+};
+use test_fixture::*;
+use url::Url;
+
+struct Resolver(HashMap<String, SocketAddr>);
+
+impl AddressResolver for Resolver {
+    fn resolve(&mut self, host: &str, _port: u16) -> Option<(SocketAddr, SocketAddr)> {
+        self.0.get(host).map(|remote| (addr(), *remote))
+    }
+}
+
+fn other_addr() -> SocketAddr {
+    SocketAddr::new(addr().ip(), 8443)
+}
+
+struct Network {
+    pool: Http3ClientPool,
+    servers: Vec<(SocketAddr, Http3Server)>,
+    events: Vec<Http3ClientPoolEvent>,
+}
+
+impl Network {
+    fn new(params: Http3ClientPoolParameters) -> Self {
+        let resolver = Resolver(
+            vec![
+                ("a.example.com".to_string(), addr()),
+                ("b.example.com".to_string(), other_addr()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        Self {
+            pool: Http3ClientPool::new(
+                params,
+                Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+                Box::new(resolver),
+            ),
+            servers: vec![
+                (addr(), default_http3_server()),
+                (other_addr(), default_http3_server()),
+            ],
+            events: Vec::new(),
+        }
+    }
+
+    fn get(&mut self, url: &str) -> PoolRequestId {
+        self.pool
+            .fetch(
+                now(),
+                "GET",
+                &Url::parse(url).unwrap(),
+                &[],
+                &[],
+                Priority::default(),
+            )
+            .unwrap()
+    }
+
+    /// Exchange packets until there is nothing left to send. Authentication requests are
+    /// answered, all other pool events are collected in `self.events`.
+    fn exchange(&mut self) {
+        loop {
+            let mut progress = false;
+            while let Some(d) = self.pool.process_output(now()).dgram() {
+                progress = true;
+                let (_, server) = self
+                    .servers
+                    .iter_mut()
+                    .find(|(a, _)| *a == d.destination())
+                    .unwrap();
+                if let Some(r) = server.process(Some(&d), now()).dgram() {
+                    self.pool.process_input(&r, now());
+                }
+            }
+            for (_, server) in &mut self.servers {
+                while let Some(d) = server.process(None, now()).dgram() {
+                    progress = true;
+                    self.pool.process_input(&d, now());
+                }
+            }
+            while let Some(e) = self.pool.next_event() {
+                if let Http3ClientPoolEvent::AuthenticationNeeded { authority } = &e {
+                    self.pool
+                        .authenticated(authority, AuthenticationStatus::Ok, now());
+                    progress = true;
+                } else {
+                    self.events.push(e);
+                }
+            }
+            if !progress {
+                break;
+            }
+        }
+    }
+
+    /// Answer all requests the server `idx` has received. Returns the number of requests.
+    fn respond(&mut self, idx: usize, reject: bool) -> usize {
+        let mut count = 0;
+        while let Some(event) = self.servers[idx].1.next_event() {
+            if let Http3ServerEvent::Headers { mut stream, .. } = event {
+                count += 1;
+                if reject {
+                    stream
+                        .cancel_fetch(Error::HttpRequestRejected.code())
+                        .unwrap();
+                } else {
+                    stream
+                        .send_headers(&[Header::new(":status", "200")])
+                        .unwrap();
+                    stream.send_data(b"abc").unwrap();
+                    stream.stream_close_send().unwrap();
+                }
+            }
+        }
+        count
+    }
+
+    fn read_response(&mut self, request_id: PoolRequestId) -> Vec<u8> {
+        assert!(self.events.iter().any(|e| matches!(
+            e,
+            Http3ClientPoolEvent::HeaderReady { request_id: id, .. } if *id == request_id
+        )));
+        let mut buf = [0; 100];
+        let (amount, fin) = self.pool.read_data(now(), request_id, &mut buf).unwrap();
+        assert!(fin);
+        buf[..amount].to_vec()
+    }
+}
+
+#[test]
+fn reuse_connection_for_authority() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    let first = net.get("https://a.example.com/1");
+    let second = net.get("https://a.example.com/2");
+    assert_eq!(net.pool.connection_count(), 1);
+
+    net.exchange();
+    assert_eq!(net.respond(0, false), 2);
+    net.exchange();
+
+    assert_eq!(net.read_response(first), b"abc");
+    assert_eq!(net.read_response(second), b"abc");
+    assert_eq!(net.pool.connection_count(), 1);
+    assert_eq!(net.pool.request_count(), 0);
+}
+
+#[test]
+fn connection_per_authority() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    let a = net.get("https://a.example.com/");
+    let b = net.get("https://b.example.com/");
+    assert_eq!(net.pool.connection_count(), 2);
+
+    net.exchange();
+    assert_eq!(net.respond(0, false), 1);
+    assert_eq!(net.respond(1, false), 1);
+    net.exchange();
+
+    assert_eq!(net.read_response(a), b"abc");
+    assert_eq!(net.read_response(b), b"abc");
+}
+
+#[test]
+fn connection_limit() {
+    let mut net = Network::new(Http3ClientPoolParameters::default().max_connections(1));
+    net.get("https://a.example.com/");
+    assert_eq!(
+        net.pool.fetch(
+            now(),
+            "GET",
+            &Url::parse("https://b.example.com/").unwrap(),
+            &[],
+            &[],
+            Priority::default(),
+        ),
+        Err(Error::Unavailable)
+    );
+}
+
+#[test]
+fn unsupported_target() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    for url in ["http://a.example.com/", "https://unknown.example.com/"] {
+        assert!(net
+            .pool
+            .fetch(
+                now(),
+                "GET",
+                &Url::parse(url).unwrap(),
+                &[],
+                &[],
+                Priority::default(),
+            )
+            .is_err());
+    }
+    assert_eq!(net.pool.connection_count(), 0);
+}
+
+#[test]
+fn retry_rejected_request() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    let request_id = net.get("https://a.example.com/");
+    net.exchange();
+    assert_eq!(net.respond(0, true), 1);
+    net.exchange();
+    assert!(net
+        .events
+        .contains(&Http3ClientPoolEvent::Retried { request_id }));
+
+    assert_eq!(net.respond(0, false), 1);
+    net.exchange();
+    assert_eq!(net.read_response(request_id), b"abc");
+}
+
+#[test]
+fn rejected_request_not_retried() {
+    let mut net = Network::new(Http3ClientPoolParameters::default().max_retries(0));
+    let request_id = net.get("https://a.example.com/");
+    net.exchange();
+    assert_eq!(net.respond(0, true), 1);
+    net.exchange();
+    assert!(net.events.contains(&Http3ClientPoolEvent::Reset {
+        request_id,
+        error: Error::HttpRequestRejected.code(),
+        local: false,
+    }));
+    assert_eq!(net.pool.request_count(), 0);
+}
+
+#[test]
+fn resumption_token_cached() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    net.get("https://a.example.com/");
+    net.exchange();
+    assert!(net.pool.resumption_token("a.example.com:443").is_some());
+    assert!(net.pool.resumption_token("b.example.com:443").is_none());
+}
+
+#[test]
+fn retry_after_goaway() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    let first = net.get("https://a.example.com/1");
+    net.exchange();
+    assert_eq!(net.respond(0, false), 1);
+    net.exchange();
+    assert_eq!(net.read_response(first), b"abc");
+
+    // The second request is made before the GOAWAY arrives, so the server rejects it.
+    net.servers[0].1.goaway();
+    let second = net.get("https://a.example.com/2");
+    while let Some(d) = net.servers[0].1.process(None, now()).dgram() {
+        net.pool.process_input(&d, now());
+    }
+    net.exchange();
+    assert!(net.events.contains(&Http3ClientPoolEvent::GoawayReceived {
+        authority: "a.example.com:443".to_string()
+    }));
+    assert!(net
+        .events
+        .contains(&Http3ClientPoolEvent::Retried { request_id: second }));
+    assert_eq!(net.pool.connection_count(), 2);
+
+    // It is answered on a new connection.
+    assert_eq!(net.respond(0, false), 1);
+    net.exchange();
+    assert_eq!(net.read_response(second), b"abc");
+    assert_eq!(net.pool.request_count(), 0);
+}
+
+#[test]
+fn zero_rtt_reuse() {
+    let mut net = Network::new(Http3ClientPoolParameters::default());
+    let first = net.get("https://a.example.com/1");
+    net.exchange();
+    assert_eq!(net.respond(0, false), 1);
+    net.exchange();
+    assert_eq!(net.read_response(first), b"abc");
+    assert!(net.pool.resumption_token("a.example.com:443").is_some());
+
+    // Once the connection is closing, a new request opens a new connection that uses the
+    // cached resumption token.
+    net.pool.close(now(), 0, "");
+    net.exchange();
+    let second = net.get("https://a.example.com/2");
+    assert_eq!(net.pool.connection_count(), 2);
+    assert!(net.pool.resumption_token("a.example.com:443").is_none());
+    assert_eq!(
+        net.pool.connection_state("a.example.com:443"),
+        Some(Http3State::ZeroRtt)
+    );
+
+    // The request is sent in the first flight.
+    let mut replies = Vec::new();
+    while let Some(d) = net.pool.process_output(now()).dgram() {
+        replies.extend(net.servers[0].1.process(Some(&d), now()).dgram());
+    }
+    assert_eq!(net.respond(0, false), 1);
+    for d in replies {
+        net.pool.process_input(&d, now());
+    }
+    net.exchange();
+    assert_eq!(net.read_response(second), b"abc");
+    assert_eq!(
+        net.pool.connection_state("a.example.com:443"),
+        Some(Http3State::Connected)
+    );
+}
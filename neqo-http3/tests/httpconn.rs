@@ -7,11 +7,13 @@
 #![allow(unused_assignments)]
 
 use std::{
+    cell::RefCell,
     mem,
+    rc::Rc,
     time::{Duration, Instant},
 };
 
-use neqo_common::{event::Provider, qtrace, Datagram};
+use neqo_common::{event::Provider, qtrace, Bytes, Datagram};
 use neqo_crypto::{AuthenticationStatus, ResumptionToken};
 use neqo_http3::{
     Header, Http3Client, Http3ClientEvent, Http3OrWebTransportStream, Http3Parameters, Http3Server,
@@ -246,6 +248,73 @@ fn test_103_response() {
     process_client_events(&mut hconn_c);
 }
 
+#[test]
+fn interim_response_events() {
+    let mut hconn_c =
+        http3_client_with_params(Http3Parameters::default().interim_response_events(true));
+    let mut hconn_s = default_http3_server();
+    let dgram = connect_peers(&mut hconn_c, &mut hconn_s);
+
+    let req = hconn_c
+        .fetch(
+            now(),
+            "GET",
+            &("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    hconn_c.stream_close_send(req).unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, dgram);
+    let mut request = receive_request(&mut hconn_s).unwrap();
+
+    assert_eq!(
+        request.send_interim_response(101, &[]),
+        Err(neqo_http3::Error::InvalidInput)
+    );
+    assert_eq!(
+        request.send_interim_response(200, &[]),
+        Err(neqo_http3::Error::InvalidInput)
+    );
+    assert_eq!(
+        request.send_interim_response(103, &[Header::new(":path", "/")]),
+        Err(neqo_http3::Error::InvalidHeader)
+    );
+    request.send_interim_response(100, &[]).unwrap();
+    request
+        .send_interim_response(
+            103,
+            &[Header::new("link", "</style.css>; rel=preload; as=style")],
+        )
+        .unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, None);
+
+    let events: Vec<_> = hconn_c.events().collect();
+    let statuses: Vec<_> = events
+        .iter()
+        .filter_map(|e| match e {
+            Http3ClientEvent::InterimResponse {
+                stream_id, status, ..
+            } if *stream_id == req => Some(*status),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(statuses, vec![100, 103]);
+    assert!(events.iter().any(|e| {
+        matches!(e, Http3ClientEvent::InterimResponse { status: 103, links, .. }
+            if links.len() == 1 && links[0].target == "/style.css" && links[0].has_rel("preload"))
+    }));
+    assert!(!events
+        .iter()
+        .any(|e| matches!(e, Http3ClientEvent::HeaderReady { .. })));
+
+    set_response(&mut request);
+    // No interim responses after the final response.
+    assert!(request.send_interim_response(103, &[]).is_err());
+    exchange_packets(&mut hconn_c, &mut hconn_s, None);
+    process_client_events(&mut hconn_c);
+}
+
 #[test]
 fn test_data_writable_events() {
     const STREAM_LIMIT: u64 = 5000;
@@ -461,3 +530,155 @@ fn fetch_noresponse_will_idletimeout() {
         }
     }
 }
+
+#[test]
+fn origin_frame() {
+    let mut hconn_c = default_http3_client();
+    let mut hconn_s = Http3Server::new(
+        now(),
+        DEFAULT_KEYS,
+        DEFAULT_ALPN_H3,
+        anti_replay(),
+        Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+        Http3Parameters::default().origins(vec![
+            "https://example.com".to_string(),
+            "https://other.example.com".to_string(),
+        ]),
+        None,
+    )
+    .unwrap();
+
+    assert!(hconn_c.is_authoritative_for("https://example.com"));
+    assert!(!hconn_c.is_authoritative_for("https://other.example.com"));
+
+    let out = connect_peers(&mut hconn_c, &mut hconn_s);
+    exchange_packets(&mut hconn_c, &mut hconn_s, out);
+
+    let origins_received = |e| {
+        matches!(e, Http3ClientEvent::OriginsReceived { origins }
+            if origins == vec!["https://other.example.com".to_string()])
+    };
+    assert!(hconn_c.events().any(origins_received));
+    assert!(hconn_c.origin_set().origin_frame_received());
+    assert!(hconn_c.is_authoritative_for("https://other.example.com:443"));
+    assert!(!hconn_c.is_authoritative_for("https://third.example.com"));
+}
+
+#[test]
+fn trailers() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let req = hconn_c
+        .fetch(
+            now(),
+            "POST",
+            &("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    assert_eq!(
+        hconn_c.send_data(req, RESPONSE_DATA),
+        Ok(RESPONSE_DATA.len())
+    );
+    hconn_c
+        .send_trailers(req, &[Header::new("request-trailer", "1")])
+        .unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, dgram);
+
+    let mut request = None;
+    let mut request_trailers = None;
+    while let Some(event) = hconn_s.next_event() {
+        match event {
+            Http3ServerEvent::Headers { stream, fin, .. } => {
+                assert!(!fin);
+                request = Some(stream);
+            }
+            Http3ServerEvent::Trailers { trailers, .. } => request_trailers = Some(trailers),
+            _ => {}
+        }
+    }
+    assert_eq!(
+        request_trailers,
+        Some(vec![Header::new("request-trailer", "1")])
+    );
+
+    let mut request = request.unwrap();
+    request
+        .send_headers(&[Header::new(":status", "200")])
+        .unwrap();
+    request.send_data(RESPONSE_DATA).unwrap();
+    request
+        .send_trailers(&[Header::new("grpc-status", "0")])
+        .unwrap();
+    // Trailers close the stream.
+    assert!(request.send_data(RESPONSE_DATA).is_err());
+    exchange_packets(&mut hconn_c, &mut hconn_s, None);
+
+    let mut buf = [0; 10];
+    let (amount, fin) = hconn_c.read_data(now(), req, &mut buf).unwrap();
+    assert_eq!(&buf[..amount], RESPONSE_DATA);
+    assert!(fin);
+    let response_trailers = |e| {
+        matches!(e, Http3ClientEvent::Trailers { stream_id, trailers }
+            if stream_id == req && trailers == vec![Header::new("grpc-status", "0")])
+    };
+    assert!(hconn_c.events().any(response_trailers));
+}
+
+#[test]
+fn zero_copy_data() {
+    let (mut hconn_c, mut hconn_s, dgram) = connect();
+    let req = hconn_c
+        .fetch(
+            now(),
+            "POST",
+            &("https", "something.com", "/"),
+            &[],
+            Priority::default(),
+        )
+        .unwrap();
+    let body = Bytes::from(vec![0x61; 100]);
+    assert_eq!(hconn_c.send_data_bytes(req, body.clone()), Ok(body.len()));
+    hconn_c.stream_close_send(req).unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, dgram);
+
+    let mut request = None;
+    let mut received = Vec::new();
+    while let Some(event) = hconn_s.next_event() {
+        match event {
+            Http3ServerEvent::Headers { stream, .. } => request = Some(stream),
+            Http3ServerEvent::Data { data, .. } => received.extend_from_slice(&data),
+            _ => {}
+        }
+    }
+    assert_eq!(received, &body[..]);
+
+    let mut request = request.unwrap();
+    request
+        .send_headers(&[Header::new(":status", "200")])
+        .unwrap();
+    let response = [Bytes::from(vec![1; 1000]), Bytes::from(vec![2; 2000])];
+    for chunk in &response {
+        assert_eq!(request.send_data_bytes(chunk.clone()), Ok(chunk.len()));
+    }
+    request.stream_close_send().unwrap();
+    exchange_packets(&mut hconn_c, &mut hconn_s, None);
+
+    let mut received = Vec::new();
+    loop {
+        let (chunk, fin) = hconn_c.read_data_chunk(now(), req).unwrap();
+        if let Some(chunk) = chunk {
+            received.extend_from_slice(&chunk);
+        } else {
+            assert!(fin);
+        }
+        if fin {
+            break;
+        }
+    }
+    assert_eq!(received, [&response[0][..], &response[1][..]].concat());
+    assert_eq!(
+        hconn_c.read_data_chunk(now(), req),
+        Err(neqo_http3::Error::InvalidStreamId)
+    );
+}
@@ -0,0 +1,61 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![cfg_attr(feature = "deny-warnings", deny(warnings))]
+
+use std::time::Duration;
+
+use test_fixture::{
+    boxed,
+    sim::{
+        http3::{Fetch, Http3ClientNode, Http3ServerNode, ServeRequests},
+        network::{CoDel, Delay, Drop, TailDrop},
+    },
+    simulate,
+};
+
+const RESPONSE_SIZE: usize = 1 << 18; // 256k
+const DELAY: Duration = Duration::from_millis(50);
+
+simulate!(
+    fetch_direct,
+    [
+        Http3ClientNode::default(boxed![Fetch::new(RESPONSE_SIZE)]),
+        Http3ServerNode::new(boxed![ServeRequests::new(1, RESPONSE_SIZE)]),
+    ]
+);
+
+simulate!(
+    fetch_empty,
+    [
+        Http3ClientNode::default(boxed![Fetch::new(0)]),
+        Delay::new(DELAY..DELAY),
+        Http3ServerNode::new(boxed![ServeRequests::new(1, 0)]),
+        Delay::new(DELAY..DELAY),
+    ]
+);
+
+simulate!(
+    fetch_taildrop,
+    [
+        Http3ClientNode::default(boxed![Fetch::new(RESPONSE_SIZE)]),
+        TailDrop::dsl_uplink(),
+        Http3ServerNode::new(boxed![ServeRequests::new(1, RESPONSE_SIZE)]),
+        TailDrop::dsl_downlink(),
+    ]
+);
+
+simulate!(
+    fetch_codel_drop,
+    [
+        Http3ClientNode::default(boxed![Fetch::new(RESPONSE_SIZE)]),
+        Delay::new(DELAY..DELAY),
+        Drop::percentage(1),
+        Http3ServerNode::new(boxed![ServeRequests::new(1, RESPONSE_SIZE)]),
+        CoDel::fq(200_000, 32_768, DELAY),
+        Drop::percentage(1),
+    ]
+);
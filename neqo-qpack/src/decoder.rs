@@ -6,13 +6,15 @@
 
 use std::convert::TryFrom;
 
-use neqo_common::{qdebug, Header};
+use ::qlog::events::qpack::QpackOwner;
+use neqo_common::{qdebug, qlog::NeqoQlog, Header};
 use neqo_transport::{Connection, StreamId};
 
 use crate::{
     decoder_instructions::DecoderInstruction,
     encoder_instructions::{DecodedEncoderInstruction, EncoderInstructionReader},
     header_block::{HeaderDecoder, HeaderDecoderResult},
+    qlog,
     qpack_send_buf::QpackData,
     reader::ReceiverConnWrapper,
     stats::Stats,
@@ -34,6 +36,7 @@ pub struct QPackDecoder {
     max_blocked_streams: usize,
     blocked_streams: Vec<(StreamId, u64)>, // stream_id and requested inserts count.
     stats: Stats,
+    qlog: NeqoQlog,
 }
 
 impl QPackDecoder {
@@ -56,9 +59,15 @@ impl QPackDecoder {
             max_blocked_streams: usize::from(qpack_settings.max_blocked_streams),
             blocked_streams: Vec::new(),
             stats: Stats::default(),
+            qlog: NeqoQlog::disabled(),
         }
     }
 
+    /// Set the qlog that instructions, table changes and header blocks are logged to.
+    pub fn set_qlog(&mut self, qlog: NeqoQlog) {
+        self.qlog = qlog;
+    }
+
     #[must_use]
     fn capacity(&self) -> u64 {
         self.table.capacity()
@@ -98,6 +107,9 @@ impl QPackDecoder {
             .filter_map(|(id, req)| if *req <= base_new { Some(*id) } else { None })
             .collect::<Vec<_>>();
         self.blocked_streams.retain(|(_, req)| *req > base_new);
+        for stream_id in &r {
+            qlog::qpack_stream_state_updated(&mut self.qlog, *stream_id, false);
+        }
         Ok(r)
     }
 
@@ -113,6 +125,7 @@ impl QPackDecoder {
     }
 
     fn execute_instruction(&mut self, instruction: DecodedEncoderInstruction) -> Res<()> {
+        qlog::qpack_encoder_instruction_parsed(&mut self.qlog, &instruction);
         match instruction {
             DecodedEncoderInstruction::Capacity { value } => self.set_capacity(value)?,
             DecodedEncoderInstruction::InsertWithNameRefStatic { index, value } => {
@@ -144,9 +157,23 @@ impl QPackDecoder {
                 unreachable!("This can be call only with an instruction.");
             }
         }
+        qlog::qpack_state_updated(
+            &mut self.qlog,
+            QpackOwner::Remote,
+            &self.table,
+            self.acked_inserts,
+        );
         Ok(())
     }
 
+    /// Queue a decoder instruction for sending.
+    fn queue_instruction(&mut self, instruction: DecoderInstruction) {
+        let mut buf = QpackData::default();
+        instruction.marshal(&mut buf);
+        qlog::qpack_decoder_instruction_created(&mut self.qlog, &instruction, &buf);
+        self.send_buf.write_bytes(&buf);
+    }
+
     fn set_capacity(&mut self, cap: u64) -> Res<()> {
         qdebug!([self], "received instruction capacity cap={}", cap);
         if cap > self.max_table_size {
@@ -156,7 +183,7 @@ impl QPackDecoder {
     }
 
     fn header_ack(&mut self, stream_id: StreamId, required_inserts: u64) {
-        DecoderInstruction::HeaderAck { stream_id }.marshal(&mut self.send_buf);
+        self.queue_instruction(DecoderInstruction::HeaderAck { stream_id });
         if required_inserts > self.acked_inserts {
             self.acked_inserts = required_inserts;
         }
@@ -165,7 +192,7 @@ impl QPackDecoder {
     pub fn cancel_stream(&mut self, stream_id: StreamId) {
         if self.table.capacity() > 0 {
             self.blocked_streams.retain(|(id, _)| *id != stream_id);
-            DecoderInstruction::StreamCancellation { stream_id }.marshal(&mut self.send_buf);
+            self.queue_instruction(DecoderInstruction::StreamCancellation { stream_id });
         }
     }
 
@@ -181,7 +208,7 @@ impl QPackDecoder {
         // Encode increment instruction if needed.
         let increment = self.table.base() - self.acked_inserts;
         if increment > 0 {
-            DecoderInstruction::InsertCountIncrement { increment }.marshal(&mut self.send_buf);
+            self.queue_instruction(DecoderInstruction::InsertCountIncrement { increment });
             self.acked_inserts = self.table.base();
         }
         if self.send_buf.len() != 0 && self.local_stream_id.is_some() {
@@ -235,10 +262,18 @@ impl QPackDecoder {
                         return Ok(None);
                     }
                     self.blocked_streams.push((stream_id, req_insert_cnt));
+                    qlog::qpack_stream_state_updated(&mut self.qlog, stream_id, true);
                     Ok(None)
                 }
             }
             Ok(HeaderDecoderResult::Headers(h)) => {
+                qlog::qpack_headers_decoded(
+                    &mut self.qlog,
+                    stream_id,
+                    decoder.get_req_insert_cnt(),
+                    decoder.get_base(),
+                    buf,
+                );
                 if decoder.get_req_insert_cnt() != 0 {
                     self.header_ack(stream_id, decoder.get_req_insert_cnt());
                     self.stats.dynamic_table_references += 1;
@@ -822,4 +857,57 @@ mod tests {
 
         decode_headers(&mut decoder, HEADER_BLOCK, &headers, STREAM_0);
     }
+
+    #[test]
+    fn qlog_events() {
+        // Insert "content-length: 1234" with a literal name.
+        const ENCODER_INST: &[u8] = &[
+            0x4e, 0x63, 0x6f, 0x6e, 0x74, 0x65, 0x6e, 0x74, 0x2d, 0x6c, 0x65, 0x6e, 0x67, 0x74,
+            0x68, 0x04, 0x31, 0x32, 0x33, 0x34,
+        ];
+        // A header block that refers to the first insert.
+        const HEADER_BLOCK: &[u8] = &[0x02, 0x00, 0x80];
+
+        let mut decoder = connect();
+        assert!(decoder.decoder.set_capacity(200).is_ok());
+        let (log, contents) = test_fixture::new_neqo_qlog();
+        decoder.decoder.set_qlog(log);
+
+        assert_eq!(
+            decoder
+                .decoder
+                .decode_header_block(HEADER_BLOCK, STREAM_0)
+                .unwrap(),
+            None
+        );
+        assert!(contents.to_string().contains("stream_state_updated"));
+
+        _ = decoder
+            .peer_conn
+            .stream_send(decoder.recv_stream_id, ENCODER_INST)
+            .unwrap();
+        let out = decoder.peer_conn.process(None, now());
+        mem::drop(decoder.conn.process(out.as_dgram_ref(), now()));
+        assert_eq!(
+            decoder
+                .decoder
+                .receive(&mut decoder.conn, decoder.recv_stream_id)
+                .unwrap(),
+            vec![STREAM_0]
+        );
+        decode_headers(
+            &mut decoder,
+            HEADER_BLOCK,
+            &[Header::new("content-length", "1234")],
+            STREAM_0,
+        );
+
+        let log = contents.to_string();
+        assert_eq!(log.matches("stream_state_updated").count(), 2);
+        assert!(log.contains("instruction_parsed"));
+        assert!(log.contains("dynamic_table_size"));
+        assert!(log.contains("headers_decoded"));
+        // The header acknowledgement.
+        assert!(log.contains("instruction_created"));
+    }
 }
@@ -9,6 +9,7 @@ use std::{
     convert::TryFrom,
 };
 
+use ::qlog::events::qpack::QpackOwner;
 use neqo_common::{qdebug, qerror, qlog::NeqoQlog, qtrace, Header};
 use neqo_transport::{Connection, Error as TransportError, StreamId};
 
@@ -59,6 +60,7 @@ pub struct QPackEncoder {
     use_huffman: bool,
     next_capacity: Option<u64>,
     stats: Stats,
+    qlog: NeqoQlog,
 }
 
 impl QPackEncoder {
@@ -76,9 +78,15 @@ impl QPackEncoder {
             use_huffman,
             next_capacity: None,
             stats: Stats::default(),
+            qlog: NeqoQlog::disabled(),
         }
     }
 
+    /// Set the qlog that instructions, table changes and header blocks are logged to.
+    pub fn set_qlog(&mut self, qlog: NeqoQlog) {
+        self.qlog = qlog;
+    }
+
     /// This function is use for setting encoders table max capacity. The value is received as
     /// a `SETTINGS_QPACK_MAX_TABLE_CAPACITY` setting parameter.
     ///
@@ -136,7 +144,7 @@ impl QPackEncoder {
         loop {
             let mut recv = ReceiverConnWrapper::new(conn, stream_id);
             match self.instruction_reader.read_instructions(&mut recv) {
-                Ok(instruction) => self.call_instruction(instruction, conn.qlog_mut())?,
+                Ok(instruction) => self.call_instruction(instruction)?,
                 Err(Error::NeedMoreData) => break Ok(()),
                 Err(e) => break Err(e),
             }
@@ -205,32 +213,32 @@ impl QPackEncoder {
         }
     }
 
-    fn call_instruction(
-        &mut self,
-        instruction: DecoderInstruction,
-        qlog: &mut NeqoQlog,
-    ) -> Res<()> {
+    fn call_instruction(&mut self, instruction: DecoderInstruction) -> Res<()> {
         qdebug!([self], "call intruction {:?}", instruction);
+        qlog::qpack_decoder_instruction_parsed(&mut self.qlog, &instruction);
         match instruction {
             DecoderInstruction::InsertCountIncrement { increment } => {
-                qlog::qpack_read_insert_count_increment_instruction(
-                    qlog,
-                    increment,
-                    &increment.to_be_bytes(),
-                );
-
-                self.insert_count_instruction(increment)
+                self.insert_count_instruction(increment)?;
             }
             DecoderInstruction::HeaderAck { stream_id } => {
                 self.header_ack(stream_id);
-                Ok(())
             }
             DecoderInstruction::StreamCancellation { stream_id } => {
                 self.stream_cancellation(stream_id);
-                Ok(())
             }
-            DecoderInstruction::NoInstruction => Ok(()),
+            DecoderInstruction::NoInstruction => return Ok(()),
         }
+        self.qlog_state_updated();
+        Ok(())
+    }
+
+    fn qlog_state_updated(&mut self) {
+        qlog::qpack_state_updated(
+            &mut self.qlog,
+            QpackOwner::Local,
+            &self.table,
+            self.table.get_acked_inserts_cnt(),
+        );
     }
 
     /// Inserts a new entry into a table and sends the corresponding instruction to a peer. An entry
@@ -262,9 +270,9 @@ impl QPackEncoder {
             return Err(Error::DynamicTableFull);
         }
 
+        let instruction = EncoderInstruction::InsertWithNameLiteral { name, value };
         let mut buf = QpackData::default();
-        EncoderInstruction::InsertWithNameLiteral { name, value }
-            .marshal(&mut buf, self.use_huffman);
+        instruction.marshal(&mut buf, self.use_huffman);
 
         let stream_id = self.local_stream.stream_id().ok_or(Error::Internal)?;
 
@@ -274,11 +282,20 @@ impl QPackEncoder {
         if !sent {
             return Err(Error::EncoderStreamBlocked);
         }
+        qlog::qpack_encoder_instruction_created(
+            &mut self.qlog,
+            &instruction,
+            self.use_huffman,
+            &buf,
+        );
 
         self.stats.dynamic_table_inserts += 1;
 
         match self.table.insert(name, value) {
-            Ok(inx) => Ok(inx),
+            Ok(inx) => {
+                self.qlog_state_updated();
+                Ok(inx)
+            }
             Err(e) => {
                 debug_assert!(false);
                 Err(e)
@@ -302,11 +319,18 @@ impl QPackEncoder {
             if cap < self.table.capacity() && !self.table.can_evict_to(cap) {
                 return Err(Error::DynamicTableFull);
             }
+            let instruction = EncoderInstruction::Capacity { value: cap };
             let mut buf = QpackData::default();
-            EncoderInstruction::Capacity { value: cap }.marshal(&mut buf, self.use_huffman);
+            instruction.marshal(&mut buf, self.use_huffman);
             if !conn.stream_send_atomic(stream_id, &buf)? {
                 return Err(Error::EncoderStreamBlocked);
             }
+            qlog::qpack_encoder_instruction_created(
+                &mut self.qlog,
+                &instruction,
+                self.use_huffman,
+                &buf,
+            );
             if self.table.set_capacity(cap).is_err() {
                 debug_assert!(
                     false,
@@ -316,6 +340,7 @@ impl QPackEncoder {
             }
             self.max_entries = cap / 32;
             self.next_capacity = None;
+            self.qlog_state_updated();
         }
         Ok(())
     }
@@ -459,6 +484,13 @@ impl QPackEncoder {
         }
 
         encoded_h.encode_header_block_prefix();
+        qlog::qpack_headers_encoded(
+            &mut self.qlog,
+            stream_id,
+            encoded_h.req_insert_cnt(),
+            encoded_h.base(),
+            &encoded_h,
+        );
 
         if !stream_is_blocker {
             // The streams was not a blocker, check if the stream is a blocker now.
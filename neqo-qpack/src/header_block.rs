@@ -127,6 +127,15 @@ impl HeaderEncoder {
         self.buf.encode_literal(self.use_huffman, NO_PREFIX, value);
     }
 
+    /// The Required Insert Count of the header block.
+    pub fn req_insert_cnt(&self) -> u64 {
+        self.max_dynamic_index_ref.map_or(0, |r| r + 1)
+    }
+
+    pub fn base(&self) -> u64 {
+        self.base
+    }
+
     pub fn encode_header_block_prefix(&mut self) {
         let tmp = mem::take(&mut self.buf);
         let (enc_insert_cnt, delta, prefix) =
@@ -282,6 +291,10 @@ impl<'a> HeaderDecoder<'a> {
         self.req_insert_cnt
     }
 
+    pub fn get_base(&self) -> u64 {
+        self.base
+    }
+
     fn read_base(&mut self, max_entries: u64, total_num_of_inserts: u64) -> Res<()> {
         let insert_cnt = self.buf.read_prefixed_int(0)?;
         self.req_insert_cnt =
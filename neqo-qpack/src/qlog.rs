@@ -7,28 +7,265 @@
 // Functions that handle capturing QLOG traces.
 
 use neqo_common::{hex, qlog::NeqoQlog};
+use neqo_transport::StreamId;
 use qlog::events::{
-    qpack::{QPackInstruction, QpackInstructionParsed, QpackInstructionTypeName},
+    qpack::{
+        QPackInstruction, QpackHeaderBlockPrefix, QpackHeadersDecoded, QpackHeadersEncoded,
+        QpackInstructionCreated, QpackInstructionParsed, QpackInstructionTypeName, QpackOwner,
+        QpackStateUpdated, QpackStreamState, QpackStreamStateUpdated, QpackTableType,
+    },
     EventData, RawInfo,
 };
 
-pub fn qpack_read_insert_count_increment_instruction(
+use crate::{
+    decoder_instructions::DecoderInstruction,
+    encoder_instructions::{DecodedEncoderInstruction, EncoderInstruction},
+    qpack_send_buf::QpackData,
+    table::HeaderTable,
+};
+
+fn raw_info(data: &[u8]) -> RawInfo {
+    RawInfo {
+        length: Some(data.len() as u64),
+        payload_length: None,
+        data: Some(hex(data)),
+    }
+}
+
+fn string(v: &[u8]) -> String {
+    String::from_utf8_lossy(v).into_owned()
+}
+
+fn decoder_instruction(instruction: &DecoderInstruction) -> Option<QPackInstruction> {
+    match instruction {
+        DecoderInstruction::InsertCountIncrement { increment } => {
+            Some(QPackInstruction::InsertCountIncrementInstruction {
+                instruction_type: QpackInstructionTypeName::InsertCountIncrementInstruction,
+                increment: *increment,
+            })
+        }
+        DecoderInstruction::HeaderAck { stream_id } => {
+            Some(QPackInstruction::HeaderAcknowledgementInstruction {
+                instruction_type: QpackInstructionTypeName::HeaderAcknowledgementInstruction,
+                stream_id: stream_id.to_string(),
+            })
+        }
+        DecoderInstruction::StreamCancellation { stream_id } => {
+            Some(QPackInstruction::StreamCancellationInstruction {
+                instruction_type: QpackInstructionTypeName::StreamCancellationInstruction,
+                stream_id: stream_id.to_string(),
+            })
+        }
+        DecoderInstruction::NoInstruction => None,
+    }
+}
+
+fn insert_with_name_ref(
+    table_type: QpackTableType,
+    index: u64,
+    value: &[u8],
+    huffman: bool,
+) -> QPackInstruction {
+    QPackInstruction::InsertWithNameReferenceInstruction {
+        instruction_type: QpackInstructionTypeName::InsertWithNameReferenceInstruction,
+        table_type,
+        name_index: index,
+        huffman_encoded_value: huffman,
+        value_length: Some(value.len() as u64),
+        value: Some(string(value)),
+    }
+}
+
+/// `huffman` is whether the literals were (or will be) Huffman encoded.  This is not
+/// recorded when instructions are read, so it is only known for instructions that are sent.
+fn encoder_instruction(
+    instruction: &DecodedEncoderInstruction,
+    huffman: bool,
+) -> Option<QPackInstruction> {
+    match instruction {
+        DecodedEncoderInstruction::Capacity { value } => {
+            Some(QPackInstruction::SetDynamicTableCapacityInstruction {
+                instruction_type: QpackInstructionTypeName::SetDynamicTableCapacityInstruction,
+                capacity: *value,
+            })
+        }
+        DecodedEncoderInstruction::InsertWithNameRefStatic { index, value } => Some(
+            insert_with_name_ref(QpackTableType::Static, *index, value, huffman),
+        ),
+        DecodedEncoderInstruction::InsertWithNameRefDynamic { index, value } => Some(
+            insert_with_name_ref(QpackTableType::Dynamic, *index, value, huffman),
+        ),
+        DecodedEncoderInstruction::InsertWithNameLiteral { name, value } => {
+            Some(QPackInstruction::InsertWithoutNameReferenceInstruction {
+                instruction_type: QpackInstructionTypeName::InsertWithoutNameReferenceInstruction,
+                huffman_encoded_name: huffman,
+                name_length: Some(name.len() as u64),
+                name: Some(string(name)),
+                huffman_encoded_value: huffman,
+                value_length: Some(value.len() as u64),
+                value: Some(string(value)),
+            })
+        }
+        DecodedEncoderInstruction::Duplicate { index } => {
+            Some(QPackInstruction::DuplicateInstruction {
+                instruction_type: QpackInstructionTypeName::DuplicateInstruction,
+                index: *index,
+            })
+        }
+        DecodedEncoderInstruction::NoInstruction => None,
+    }
+}
+
+/// The decoder stream instruction `instruction` has been read by the encoder.
+pub fn qpack_decoder_instruction_parsed(qlog: &mut NeqoQlog, instruction: &DecoderInstruction) {
+    qlog.add_event_data(|| {
+        let mut data = QpackData::default();
+        instruction.marshal(&mut data);
+        let ev_data = EventData::QpackInstructionParsed(QpackInstructionParsed {
+            instruction: decoder_instruction(instruction)?,
+            raw: Some(raw_info(&data)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// The decoder has written `instruction`, encoded as `data`, to its stream.
+pub fn qpack_decoder_instruction_created(
     qlog: &mut NeqoQlog,
-    increment: u64,
+    instruction: &DecoderInstruction,
     data: &[u8],
 ) {
     qlog.add_event_data(|| {
-        let raw = RawInfo {
-            length: Some(8),
-            payload_length: None,
-            data: Some(hex(data)),
-        };
+        let ev_data = EventData::QpackInstructionCreated(QpackInstructionCreated {
+            instruction: decoder_instruction(instruction)?,
+            raw: Some(raw_info(data)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// The encoder stream instruction `instruction` has been read by the decoder.
+pub fn qpack_encoder_instruction_parsed(
+    qlog: &mut NeqoQlog,
+    instruction: &DecodedEncoderInstruction,
+) {
+    qlog.add_event_data(|| {
         let ev_data = EventData::QpackInstructionParsed(QpackInstructionParsed {
-            instruction: QPackInstruction::InsertCountIncrementInstruction {
-                instruction_type: QpackInstructionTypeName::InsertCountIncrementInstruction,
-                increment,
+            instruction: encoder_instruction(instruction, false)?,
+            raw: None,
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// The encoder has written `instruction`, encoded as `data`, to its stream.
+pub fn qpack_encoder_instruction_created(
+    qlog: &mut NeqoQlog,
+    instruction: &EncoderInstruction,
+    huffman: bool,
+    data: &[u8],
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::QpackInstructionCreated(QpackInstructionCreated {
+            instruction: encoder_instruction(
+                &DecodedEncoderInstruction::from(instruction),
+                huffman,
+            )?,
+            raw: Some(raw_info(data)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// The dynamic table has changed.  For the encoder, `known_received_count` is the number of
+/// inserts acknowledged by the peer; for the decoder, it is the number that it has acknowledged.
+pub fn qpack_state_updated(
+    qlog: &mut NeqoQlog,
+    owner: QpackOwner,
+    table: &HeaderTable,
+    known_received_count: u64,
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::QpackStateUpdated(QpackStateUpdated {
+            owner: Some(owner),
+            dynamic_table_capacity: Some(table.capacity()),
+            dynamic_table_size: Some(table.used()),
+            known_received_count: Some(known_received_count),
+            current_insert_count: Some(table.base()),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// Decoding the header block of `stream_id` is blocked, or no longer blocked, on inserts.
+pub fn qpack_stream_state_updated(qlog: &mut NeqoQlog, stream_id: StreamId, blocked: bool) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::QpackStreamStateUpdated(QpackStreamStateUpdated {
+            stream_id: stream_id.as_u64(),
+            state: if blocked {
+                QpackStreamState::Blocked
+            } else {
+                QpackStreamState::Unblocked
             },
-            raw: Some(raw),
+        });
+
+        Some(ev_data)
+    });
+}
+
+fn block_prefix(required_insert_count: u64, base: u64) -> QpackHeaderBlockPrefix {
+    QpackHeaderBlockPrefix {
+        required_insert_count,
+        sign_bit: base < required_insert_count,
+        delta_base: if base < required_insert_count {
+            required_insert_count - base - 1
+        } else {
+            base - required_insert_count
+        },
+    }
+}
+
+/// A header block for `stream_id` has been encoded.
+pub fn qpack_headers_encoded(
+    qlog: &mut NeqoQlog,
+    stream_id: StreamId,
+    required_insert_count: u64,
+    base: u64,
+    header_block: &[u8],
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::QpackHeadersEncoded(QpackHeadersEncoded {
+            stream_id: Some(stream_id.as_u64()),
+            headers: None,
+            block_prefix: block_prefix(required_insert_count, base),
+            header_block: Vec::new(),
+            raw: Some(raw_info(header_block)),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// A header block for `stream_id` has been decoded.
+pub fn qpack_headers_decoded(
+    qlog: &mut NeqoQlog,
+    stream_id: StreamId,
+    required_insert_count: u64,
+    base: u64,
+    header_block: &[u8],
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::QpackHeadersDecoded(QpackHeadersDecoded {
+            stream_id: Some(stream_id.as_u64()),
+            headers: None,
+            block_prefix: block_prefix(required_insert_count, base),
+            header_block: Vec::new(),
+            raw: Some(raw_info(header_block)),
         });
 
         Some(ev_data)
@@ -108,6 +108,11 @@ impl HeaderTable {
         self.capacity
     }
 
+    /// Returns the amount of the capacity that is used.
+    pub fn used(&self) -> u64 {
+        self.used
+    }
+
     /// Change the dynamic table capacity.
     ///
     /// # Errors
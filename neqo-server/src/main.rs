@@ -13,7 +13,7 @@ use std::{
     collections::{HashMap, HashSet},
     convert::TryFrom,
     fmt::{self, Display},
-    fs::OpenOptions,
+    fs::{self, OpenOptions},
     io,
     io::Read,
     mem,
@@ -22,6 +22,10 @@ use std::{
     process::exit,
     rc::Rc,
     str::FromStr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
     time::{Duration, Instant},
 };
 
@@ -30,10 +34,12 @@ use mio_extras::timer::{Builder, Timeout, Timer};
 use neqo_common::{hex, qdebug, qinfo, qwarn, Datagram, Header, IpTos};
 use neqo_crypto::{
     constants::{TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256},
-    generate_ech_keys, init_db, random, AntiReplay, Cipher,
+    generate_ech_keys, init, init_db, load_certificate, load_trust_anchors, random, AntiReplay,
+    CertificateVerifier, Cipher, ServerNameSelector,
 };
 use neqo_http3::{
-    Error, Http3OrWebTransportStream, Http3Parameters, Http3Server, Http3ServerEvent, StreamId,
+    Error, Http3OrWebTransportStream, Http3Parameters, Http3Server, Http3ServerEvent, Http3State,
+    StreamId,
 };
 use neqo_transport::{
     server::ValidateAddress, tparams::PreferredAddress, CongestionControlAlgorithm,
@@ -42,16 +48,24 @@ use neqo_transport::{
 };
 use structopt::StructOpt;
 
-use crate::old_https::Http09Server;
+use crate::{
+    old_https::Http09Server,
+    static_files::{FileBody, StaticFiles},
+    vhost::{Config, Listener, VirtualHosts},
+};
 
 const TIMER_TOKEN: Token = Token(0xffff_ffff);
 const ANTI_REPLAY_WINDOW: Duration = Duration::from_secs(10);
 
 mod old_https;
+mod static_files;
+mod vhost;
 
 #[derive(Debug)]
 pub enum ServerError {
     ArgumentError(&'static str),
+    ConfigError(String),
+    CryptoError(neqo_crypto::Error),
     Http3Error(neqo_http3::Error),
     IoError(io::Error),
     QlogError,
@@ -64,6 +78,12 @@ impl From<io::Error> for ServerError {
     }
 }
 
+impl From<neqo_crypto::Error> for ServerError {
+    fn from(err: neqo_crypto::Error) -> Self {
+        Self::CryptoError(err)
+    }
+}
+
 impl From<neqo_http3::Error> for ServerError {
     fn from(err: neqo_http3::Error) -> Self {
         Self::Http3Error(err)
@@ -114,10 +134,27 @@ struct Args {
     /// NSS database directory.
     db: PathBuf,
 
-    #[structopt(short = "k", long, default_value = "key")]
-    /// Name of key from NSS database.
+    #[structopt(short = "k", long = "key", default_value = "key")]
+    /// Name of key from NSS database, or the name to give the certificate from `--cert-file`.
     key: String,
 
+    #[structopt(name = "cert-file", long, parse(from_os_str), requires = "key-file")]
+    /// Load the certificate chain from this PEM file, instead of the NSS database.
+    cert_file: Option<PathBuf>,
+
+    #[structopt(name = "key-file", long, parse(from_os_str), requires = "cert-file")]
+    /// The private key for `--cert-file`, in PEM format.
+    key_file: Option<PathBuf>,
+
+    #[structopt(long, parse(from_os_str))]
+    /// Load trust anchors from this PEM file.
+    ca: Option<PathBuf>,
+
+    #[structopt(name = "client-auth", long, possible_values = &["request", "require"])]
+    /// Ask clients for certificates, which need to be issued by a trust anchor from `--ca` or
+    /// the NSS database.  With "request", clients can choose not to send one.
+    client_auth: Option<String>,
+
     #[structopt(short = "a", long, default_value = "h3")]
     /// ALPN labels to negotiate.
     ///
@@ -128,10 +165,23 @@ struct Args {
     /// Enable QLOG logging and QLOG traces to this directory
     qlog_dir: Option<PathBuf>,
 
+    #[structopt(name = "pcap-dir", long)]
+    /// Write a pcapng capture of each connection, with its TLS secrets, to this directory
+    pcap_dir: Option<PathBuf>,
+
     #[structopt(name = "qns-test", long)]
     /// Enable special behavior for use with QUIC Network Simulator
     qns_test: Option<String>,
 
+    #[structopt(name = "root", long, parse(from_os_str))]
+    /// Serve static files from this directory instead of generated responses.
+    root: Option<PathBuf>,
+
+    #[structopt(long, parse(from_os_str), conflicts_with_all = &["use-old-http", "root"])]
+    /// Read virtual hosts from this TOML file.  Certificates and responses are then selected
+    /// by the server name that clients send.  Send SIGHUP to reload the file.
+    config: Option<PathBuf>,
+
     #[structopt(name = "use-old-http", short = "o", long)]
     /// Use http 0.9 instead of HTTP/3
     use_old_http: bool,
@@ -168,11 +218,27 @@ impl Args {
             .collect::<Vec<_>>()
     }
 
-    fn listen_addresses(&self) -> Vec<SocketAddr> {
+    fn load_certificates(&self) -> Result<(), ServerError> {
+        if let (Some(cert), Some(key)) = (&self.cert_file, &self.key_file) {
+            load_certificate(&self.key, &fs::read(cert)?, &fs::read(key)?)?;
+        }
+        if let Some(ca) = &self.ca {
+            load_trust_anchors(&fs::read(ca)?)?;
+        }
+        Ok(())
+    }
+
+    fn host_addresses(&self) -> Vec<SocketAddr> {
         self.hosts
             .iter()
             .filter_map(|host| host.to_socket_addrs().ok())
             .flatten()
+            .collect()
+    }
+
+    fn listen_addresses(&self) -> Vec<SocketAddr> {
+        self.host_addresses()
+            .into_iter()
             .chain(self.quic_parameters.preferred_address_v4())
             .chain(self.quic_parameters.preferred_address_v6())
             .collect()
@@ -359,8 +425,10 @@ trait HttpServer: Display {
     fn process(&mut self, dgram: Option<&Datagram>, now: Instant) -> Output;
     fn process_events(&mut self, args: &Args, now: Instant);
     fn set_qlog_dir(&mut self, dir: Option<PathBuf>);
+    fn set_pcap_dir(&mut self, dir: Option<PathBuf>);
     fn set_ciphers(&mut self, ciphers: &[Cipher]);
     fn validate_address(&mut self, when: ValidateAddress);
+    fn request_client_certificate(&mut self, required: bool);
     fn enable_ech(&mut self) -> &[u8];
 }
 
@@ -426,6 +494,12 @@ struct SimpleServer {
     /// Progress writing to each stream.
     remaining_data: HashMap<StreamId, ResponseData>,
     posts: HashMap<Http3OrWebTransportStream, usize>,
+    static_files: Option<StaticFiles>,
+    /// Files that are still being sent.
+    remaining_files: HashMap<Http3OrWebTransportStream, FileBody>,
+    /// The virtual hosts, and the name of the host to use when the client sends no name
+    /// or an unknown one.
+    virtual_hosts: Option<(Rc<VirtualHosts>, String)>,
 }
 
 impl SimpleServer {
@@ -442,25 +516,70 @@ impl SimpleServer {
         args: &Args,
         anti_replay: AntiReplay,
         cid_mgr: Rc<RefCell<dyn ConnectionIdGenerator>>,
+        virtual_hosts: Option<(&Listener, &Rc<VirtualHosts>)>,
     ) -> Self {
-        let server = Http3Server::new(
+        let (certs, alpn, conn_params) = if let Some((listener, _)) = virtual_hosts {
+            (
+                vec![listener.certificate.clone()],
+                listener.alpn.clone(),
+                listener.quic.get(),
+            )
+        } else {
+            (
+                vec![args.key.clone()],
+                vec![args.alpn.clone()],
+                args.quic_parameters.get(),
+            )
+        };
+        let mut server = Http3Server::new(
             args.now(),
-            &[args.key.clone()],
-            &[args.alpn.clone()],
+            &certs,
+            &alpn,
             anti_replay,
             cid_mgr,
             Http3Parameters::default()
-                .connection_parameters(args.quic_parameters.get())
+                .connection_parameters(conn_params)
                 .max_table_size_encoder(args.max_table_size_encoder)
                 .max_table_size_decoder(args.max_table_size_decoder)
                 .max_blocked_streams(args.max_blocked_streams),
             None,
         )
         .expect("We cannot make a server!");
+        let virtual_hosts = virtual_hosts.map(|(listener, hosts)| {
+            server.set_sni_selector(Some(Rc::clone(hosts) as Rc<dyn ServerNameSelector>));
+            (Rc::clone(hosts), listener.default_host.clone())
+        });
+        let static_files = args.root.as_ref().map(|root| {
+            StaticFiles::new(root).unwrap_or_else(|e| {
+                eprintln!("Cannot serve files from {}: {e}", root.display());
+                exit(1)
+            })
+        });
         Self {
             server,
             remaining_data: HashMap::new(),
             posts: HashMap::new(),
+            static_files,
+            remaining_files: HashMap::new(),
+            virtual_hosts,
+        }
+    }
+
+    /// Send more of a file, and close the stream once all of it has been sent. Returns false if
+    /// the file has not been sent completely yet.
+    fn send_file(stream: &mut Http3OrWebTransportStream, body: &mut FileBody) -> bool {
+        match body.send(stream) {
+            Ok(()) if body.done() => {
+                stream.stream_close_send().unwrap();
+                true
+            }
+            Ok(()) => false,
+            Err(e) => {
+                qwarn!("Error sending file on {}: {}", stream, e);
+                // The stream may be gone already.
+                mem::drop(stream.stream_reset_send(Error::HttpRequestCancelled.code()));
+                true
+            }
         }
     }
 }
@@ -497,6 +616,24 @@ impl HttpServer for SimpleServer {
                         continue;
                     }
 
+                    let response = if let Some((hosts, default)) = &self.virtual_hosts {
+                        hosts.respond(stream.server_name().as_deref(), default, &headers)
+                    } else {
+                        self.static_files.as_ref().map(|f| f.respond(&headers))
+                    };
+                    if let Some(response) = response {
+                        stream.send_headers(&response.headers).unwrap();
+                        match response.body {
+                            Some(mut body) => {
+                                if !Self::send_file(&mut stream, &mut body) {
+                                    self.remaining_files.insert(stream, body);
+                                }
+                            }
+                            None => stream.stream_close_send().unwrap(),
+                        }
+                        continue;
+                    }
+
                     let mut response =
                         if let Some(path) = headers.iter().find(|&h| h.name() == ":path") {
                             if args.qns_test.is_some() {
@@ -533,7 +670,11 @@ impl HttpServer for SimpleServer {
                     }
                 }
                 Http3ServerEvent::DataWritable { mut stream } => {
-                    if self.posts.get_mut(&stream).is_none() {
+                    if let Some(body) = self.remaining_files.get_mut(&stream) {
+                        if Self::send_file(&mut stream, body) {
+                            self.remaining_files.remove(&stream);
+                        }
+                    } else if self.posts.get_mut(&stream).is_none() {
                         if let Some(remaining) = self.remaining_data.get_mut(&stream.stream_id()) {
                             remaining.send(&mut stream);
                             if remaining.done() {
@@ -563,6 +704,17 @@ impl HttpServer for SimpleServer {
                         }
                     }
                 }
+                Http3ServerEvent::StreamReset { stream, .. }
+                | Http3ServerEvent::StreamStopSending { stream, .. } => {
+                    self.remaining_files.remove(&stream);
+                }
+                Http3ServerEvent::StateChange {
+                    conn,
+                    state: Http3State::Closing(_) | Http3State::Closed(_),
+                } => {
+                    // The streams of a closed connection will not become writable again.
+                    self.remaining_files.retain(|stream, _| stream.conn != conn);
+                }
                 _ => {}
             }
         }
@@ -572,6 +724,10 @@ impl HttpServer for SimpleServer {
         self.server.set_qlog_dir(dir);
     }
 
+    fn set_pcap_dir(&mut self, dir: Option<PathBuf>) {
+        self.server.set_pcap_dir(dir);
+    }
+
     fn validate_address(&mut self, v: ValidateAddress) {
         self.server.set_validation(v);
     }
@@ -580,6 +736,11 @@ impl HttpServer for SimpleServer {
         self.server.set_ciphers(ciphers);
     }
 
+    fn request_client_certificate(&mut self, required: bool) {
+        self.server
+            .request_client_certificate(CertificateVerifier::new(), required);
+    }
+
     fn enable_ech(&mut self) -> &[u8] {
         let (sk, pk) = generate_ech_keys().expect("should create ECH keys");
         self.server
@@ -630,42 +791,88 @@ struct ServersRunner {
     args: Args,
     poll: Poll,
     hosts: Vec<SocketAddr>,
-    server: Box<dyn HttpServer>,
-    timeout: Option<Timeout>,
+    servers: Vec<Box<dyn HttpServer>>,
+    /// The index of the server that handles each socket.
+    socket_servers: Vec<usize>,
+    /// The timeout of each server.
+    timeouts: Vec<Option<Timeout>>,
     sockets: Vec<UdpSocket>,
     active_sockets: HashSet<usize>,
     timer: Timer<usize>,
+    /// The virtual hosts and the servers that were created for them, if `--config` is used.
+    virtual_hosts: Option<(Rc<VirtualHosts>, Vec<Listener>)>,
+    /// Set when the configuration needs to be reloaded.
+    reload: Arc<AtomicBool>,
 }
 
 impl ServersRunner {
     pub fn new(args: Args) -> Result<Self, io::Error> {
-        let server = Self::create_server(&args);
         let mut runner = Self {
             args,
             poll: Poll::new()?,
             hosts: Vec::new(),
-            server,
-            timeout: None,
+            servers: Vec::new(),
+            socket_servers: Vec::new(),
+            timeouts: Vec::new(),
             sockets: Vec::new(),
             active_sockets: HashSet::new(),
             timer: Builder::default()
                 .tick_duration(Duration::from_millis(1))
                 .build::<usize>(),
+            virtual_hosts: None,
+            reload: Arc::new(AtomicBool::new(false)),
         };
         runner.init()?;
         Ok(runner)
     }
 
+    fn config_error(e: &ServerError) -> io::Error {
+        eprintln!("Invalid configuration: {e}");
+        io::Error::new(io::ErrorKind::InvalidInput, "Invalid configuration")
+    }
+
     /// Init Poll for all hosts. Create sockets, and a map of the
     /// socketaddrs to instances of the HttpServer handling that addr.
     fn init(&mut self) -> Result<(), io::Error> {
-        self.hosts = self.args.listen_addresses();
+        if let Some(path) = &self.args.config {
+            let config = Config::load(path).map_err(|e| Self::config_error(&e))?;
+            let listeners = config
+                .listeners(&self.args.host_addresses())
+                .map_err(|e| Self::config_error(&e))?;
+            let virtual_hosts =
+                Rc::new(VirtualHosts::new(&config).map_err(|e| Self::config_error(&e))?);
+            for listener in &listeners {
+                let server = Self::create_server(&self.args, Some((listener, &virtual_hosts)));
+                self.add_server(server, &[listener.address])?;
+            }
+            self.virtual_hosts = Some((virtual_hosts, listeners));
+
+            #[cfg(unix)]
+            signal_hook::flag::register(signal_hook::consts::SIGHUP, Arc::clone(&self.reload))?;
+        } else {
+            let hosts = self.args.listen_addresses();
+            let server = Self::create_server(&self.args, None);
+            self.add_server(server, &hosts)?;
+        }
+
         if self.hosts.is_empty() {
             eprintln!("No valid hosts defined");
             return Err(io::Error::new(io::ErrorKind::InvalidInput, "No hosts"));
         }
 
-        for (i, host) in self.hosts.iter().enumerate() {
+        self.poll
+            .register(&self.timer, TIMER_TOKEN, Ready::readable(), PollOpt::edge())?;
+
+        Ok(())
+    }
+
+    /// Add a server that handles the sockets for `hosts`.
+    fn add_server(
+        &mut self,
+        server: Box<dyn HttpServer>,
+        hosts: &[SocketAddr],
+    ) -> Result<(), io::Error> {
+        for host in hosts {
             let socket = match UdpSocket::bind(host) {
                 Err(err) => {
                     eprintln!("Unable to bind UDP socket: {err}");
@@ -692,21 +899,24 @@ impl ServersRunner {
 
             self.poll.register(
                 &socket,
-                Token(i),
+                Token(self.sockets.len()),
                 Ready::readable() | Ready::writable(),
                 PollOpt::edge(),
             )?;
 
+            self.hosts.push(*host);
             self.sockets.push(socket);
+            self.socket_servers.push(self.servers.len());
         }
-
-        self.poll
-            .register(&self.timer, TIMER_TOKEN, Ready::readable(), PollOpt::edge())?;
-
+        self.servers.push(server);
+        self.timeouts.push(None);
         Ok(())
     }
 
-    fn create_server(args: &Args) -> Box<dyn HttpServer> {
+    fn create_server(
+        args: &Args,
+        virtual_hosts: Option<(&Listener, &Rc<VirtualHosts>)>,
+    ) -> Box<dyn HttpServer> {
         // Note: this is the exception to the case where we use `Args::now`.
         let anti_replay = AntiReplay::new(Instant::now(), ANTI_REPLAY_WINDOW, 7, 14)
             .expect("unable to setup anti-replay");
@@ -725,13 +935,17 @@ impl ServersRunner {
                 .expect("We cannot make a server!"),
             )
         } else {
-            Box::new(SimpleServer::new(args, anti_replay, cid_mgr))
+            Box::new(SimpleServer::new(args, anti_replay, cid_mgr, virtual_hosts))
         };
         svr.set_ciphers(&args.get_ciphers());
         svr.set_qlog_dir(args.qlog_dir.clone());
+        svr.set_pcap_dir(args.pcap_dir.clone());
         if args.retry {
             svr.validate_address(ValidateAddress::Always);
         }
+        if let Some(client_auth) = &args.client_auth {
+            svr.request_client_certificate(client_auth == "require");
+        }
         if args.ech {
             let cfg = svr.enable_ech();
             println!("ECHConfigList: {}", hex(cfg));
@@ -739,6 +953,30 @@ impl ServersRunner {
         svr
     }
 
+    /// Read the configuration file again.  New connections use the new certificates, ALPN
+    /// values and document roots.  Listen addresses and QUIC parameters are only read at startup.
+    fn reload_config(&self) {
+        let (Some(path), Some((virtual_hosts, listeners))) =
+            (&self.args.config, &self.virtual_hosts)
+        else {
+            return;
+        };
+        println!("Reloading {}", path.display());
+        let reloaded = Config::load(path).and_then(|config| {
+            let new_listeners = config.listeners(&self.args.host_addresses())?;
+            Ok((VirtualHosts::new(&config)?, new_listeners))
+        });
+        match reloaded {
+            Ok((hosts, new_listeners)) => {
+                if &new_listeners != listeners {
+                    eprintln!("Listen addresses, QUIC parameters and default hosts need a restart");
+                }
+                virtual_hosts.replace(hosts);
+            }
+            Err(e) => eprintln!("Keeping the old configuration: {e}"),
+        }
+    }
+
     /// Tries to find a socket, but then just falls back to sending from the first.
     fn find_socket(&mut self, addr: SocketAddr) -> &mut UdpSocket {
         let (first, rest) = self.sockets.split_first_mut().unwrap();
@@ -752,19 +990,20 @@ impl ServersRunner {
     }
 
     fn process(&mut self, inx: usize, dgram: Option<&Datagram>) -> bool {
-        match self.server.process(dgram, self.args.now()) {
+        let svr = self.socket_servers[inx];
+        match self.servers[svr].process(dgram, self.args.now()) {
             Output::Datagram(dgram) => {
                 let socket = self.find_socket(dgram.source());
                 emit_packet(socket, dgram);
                 true
             }
             Output::Callback(new_timeout) => {
-                if let Some(to) = &self.timeout {
+                if let Some(to) = &self.timeouts[svr] {
                     self.timer.cancel_timeout(to);
                 }
 
                 qinfo!("Setting timeout of {:?} for socket {}", new_timeout, inx);
-                self.timeout = Some(self.timer.set_timeout(new_timeout, inx));
+                self.timeouts[svr] = Some(self.timer.set_timeout(new_timeout, inx));
                 false
             }
             Output::None => {
@@ -792,7 +1031,7 @@ impl ServersRunner {
             } else {
                 _ = self.process(inx, None);
             }
-            self.server.process_events(&self.args, self.args.now());
+            self.servers[self.socket_servers[inx]].process_events(&self.args, self.args.now());
             if self.process(inx, None) {
                 self.active_sockets.insert(inx);
             }
@@ -819,15 +1058,23 @@ impl ServersRunner {
     pub fn run(&mut self) -> Result<(), io::Error> {
         let mut events = Events::with_capacity(1024);
         loop {
+            if self.reload.swap(false, Ordering::Relaxed) {
+                self.reload_config();
+            }
+
             // If there are active servers do not block in poll.
-            self.poll.poll(
+            // A signal interrupts the poll, so that a reload happens right away.
+            match self.poll.poll_interruptible(
                 &mut events,
                 if self.active_sockets.is_empty() {
                     None
                 } else {
                     Some(Duration::from_millis(0))
                 },
-            )?;
+            ) {
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                r => r?,
+            };
 
             for event in &events {
                 if event.token() == TIMER_TOKEN {
@@ -850,7 +1097,19 @@ fn main() -> Result<(), io::Error> {
     let mut args = Args::from_args();
     assert!(!args.key.is_empty(), "Need at least one key");
 
-    init_db(args.db.clone());
+    if args.db.is_dir() {
+        init_db(args.db.clone());
+    } else {
+        eprintln!(
+            "No NSS database at {}, only certificates from files can be used",
+            args.db.display()
+        );
+        init();
+    }
+    if let Err(e) = args.load_certificates() {
+        eprintln!("Unable to load certificates: {e}");
+        exit(1);
+    }
 
     if let Some(testcase) = args.qns_test.as_ref() {
         if args.quic_parameters.quic_version.is_empty() {
@@ -12,7 +12,9 @@ use std::{
 };
 
 use neqo_common::{event::Provider, hex, qdebug, Datagram};
-use neqo_crypto::{generate_ech_keys, random, AllowZeroRtt, AntiReplay, Cipher};
+use neqo_crypto::{
+    generate_ech_keys, random, AllowZeroRtt, AntiReplay, CertificateVerifier, Cipher,
+};
 use neqo_http3::Error;
 use neqo_transport::{
     server::{ActiveConnectionRef, Server, ValidateAddress},
@@ -235,6 +237,10 @@ impl HttpServer for Http09Server {
         self.server.set_qlog_dir(dir);
     }
 
+    fn set_pcap_dir(&mut self, dir: Option<PathBuf>) {
+        self.server.set_pcap_dir(dir);
+    }
+
     fn validate_address(&mut self, v: ValidateAddress) {
         self.server.set_validation(v);
     }
@@ -243,6 +249,11 @@ impl HttpServer for Http09Server {
         self.server.set_ciphers(ciphers);
     }
 
+    fn request_client_certificate(&mut self, required: bool) {
+        self.server
+            .request_client_certificate(CertificateVerifier::new(), required);
+    }
+
     fn enable_ech(&mut self) -> &[u8] {
         let (sk, pk) = generate_ech_keys().expect("generate ECH keys");
         self.server
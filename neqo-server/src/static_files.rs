@@ -0,0 +1,543 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Serving files from a document root, with support for HEAD, single byte ranges,
+// conditional requests and directory index files.
+
+use std::{
+    cmp::min,
+    convert::TryFrom,
+    fs::{File, Metadata},
+    io::{self, Read, Seek, SeekFrom},
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use neqo_common::{qdebug, Header};
+use neqo_http3::Http3OrWebTransportStream;
+
+/// The amount of file data that is read at once.
+const CHUNK_SIZE: usize = 64 * 1024;
+const INDEX_FILES: &[&str] = &["index.html", "index.htm"];
+const DAYS: &[&str] = &["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+const MONTHS: &[&str] = &[
+    "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+];
+
+#[derive(Debug)]
+pub struct StaticFiles {
+    root: PathBuf,
+}
+
+/// The response to a request: the headers, including `:status`, and the body if there is one.
+pub struct Response {
+    pub headers: Vec<Header>,
+    pub body: Option<FileBody>,
+}
+
+impl Response {
+    fn empty(status: u16) -> Self {
+        Self {
+            headers: vec![
+                Header::new(":status", status.to_string()),
+                Header::new("content-length", "0"),
+            ],
+            body: None,
+        }
+    }
+}
+
+/// The part of a file that still needs to be sent. The file is read in chunks as the stream
+/// accepts more data, so that it is never held in memory as a whole.
+pub struct FileBody {
+    file: File,
+    remaining: u64,
+    buf: Vec<u8>,
+    offset: usize,
+}
+
+impl FileBody {
+    fn new(mut file: File, start: u64, len: u64) -> io::Result<Self> {
+        file.seek(SeekFrom::Start(start))?;
+        Ok(Self {
+            file,
+            remaining: len,
+            buf: Vec::new(),
+            offset: 0,
+        })
+    }
+
+    /// Send as much data as the stream accepts.
+    ///
+    /// # Errors
+    ///
+    /// If the file cannot be read or has been truncated, or if the stream cannot be written to.
+    pub fn send(&mut self, stream: &mut Http3OrWebTransportStream) -> io::Result<()> {
+        loop {
+            if self.offset == self.buf.len() {
+                if self.remaining == 0 {
+                    return Ok(());
+                }
+                self.fill()?;
+            }
+            match stream.send_data(&self.buf[self.offset..]) {
+                Ok(0) => return Ok(()),
+                Ok(sent) => self.offset += sent,
+                Err(e) => {
+                    return Err(io::Error::new(
+                        io::ErrorKind::Other,
+                        format!("error writing to stream {stream}: {e:?}"),
+                    ))
+                }
+            }
+        }
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let len = usize::try_from(self.remaining).map_or(CHUNK_SIZE, |r| min(r, CHUNK_SIZE));
+        self.buf.resize(len, 0);
+        self.file.read_exact(&mut self.buf)?;
+        self.remaining -= u64::try_from(len).unwrap();
+        self.offset = 0;
+        Ok(())
+    }
+
+    pub fn done(&self) -> bool {
+        self.remaining == 0 && self.offset == self.buf.len()
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum ByteRange {
+    /// The header is missing, invalid or asks for more than one range; send the whole file.
+    Full,
+    /// First and last byte, inclusive.
+    Part(u64, u64),
+    Unsatisfiable,
+}
+
+impl ByteRange {
+    fn parse(value: &str, len: u64) -> Self {
+        let Some((unit, spec)) = value.split_once('=') else {
+            return Self::Full;
+        };
+        if !unit.trim().eq_ignore_ascii_case("bytes") || spec.contains(',') {
+            return Self::Full;
+        }
+        let Some((first, last)) = spec.trim().split_once('-') else {
+            return Self::Full;
+        };
+        if first.is_empty() {
+            // A suffix range, the last `n` bytes.
+            return match last.parse::<u64>() {
+                Ok(0) => Self::Unsatisfiable,
+                Ok(_) if len == 0 => Self::Unsatisfiable,
+                Ok(n) => Self::Part(len.saturating_sub(n), len - 1),
+                Err(_) => Self::Full,
+            };
+        }
+        let Ok(first) = first.parse::<u64>() else {
+            return Self::Full;
+        };
+        let last = if last.is_empty() {
+            u64::MAX
+        } else if let Ok(last) = last.parse::<u64>() {
+            last
+        } else {
+            return Self::Full;
+        };
+        if last < first {
+            Self::Full
+        } else if first >= len {
+            Self::Unsatisfiable
+        } else {
+            Self::Part(first, min(last, len - 1))
+        }
+    }
+}
+
+/// Format a time as an IMF-fixdate, e.g. "Sun, 06 Nov 1994 08:49:37 GMT".
+fn http_date(secs: u64) -> String {
+    let days = secs / 86400;
+    let time = secs % 86400;
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[usize::try_from(days % 7).unwrap()],
+        day,
+        MONTHS[usize::try_from(month - 1).unwrap()],
+        year,
+        time / 3600,
+        time / 60 % 60,
+        time % 60
+    )
+}
+
+/// Parse an IMF-fixdate. The obsolete date formats are not supported.
+fn parse_http_date(value: &str) -> Option<u64> {
+    let parts = value.split_whitespace().collect::<Vec<_>>();
+    let [_, day, month, year, time, "GMT"] = parts[..] else {
+        return None;
+    };
+    let month = MONTHS.iter().position(|&m| m == month)?;
+    let day = day.parse::<u64>().ok()?;
+    let year = year.parse::<u64>().ok()?;
+    let hms = time
+        .split(':')
+        .map(|v| v.parse::<u64>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    let [h, m, s] = hms[..] else {
+        return None;
+    };
+    if year < 1970 || !(1..=31).contains(&day) || h > 23 || m > 59 || s > 60 {
+        return None;
+    }
+    let days = days_from_civil(year, u64::try_from(month).unwrap() + 1, day);
+    Some(days * 86400 + h * 3600 + m * 60 + s)
+}
+
+// The calendar conversions are from http://howardhinnant.github.io/date_algorithms.html,
+// limited to dates after 1970.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    let z = days + 719_468;
+    let era = z / 146_097;
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
+
+fn days_from_civil(year: u64, month: u64, day: u64) -> u64 {
+    let year = year - u64::from(month <= 2);
+    let era = year / 400;
+    let yoe = year - era * 400;
+    let mp = if month > 2 { month - 3 } else { month + 9 };
+    let doy = (153 * mp + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+fn content_type(path: &Path) -> &'static str {
+    let ext = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(str::to_ascii_lowercase);
+    match ext.as_deref() {
+        Some("html" | "htm") => "text/html; charset=utf-8",
+        Some("css") => "text/css; charset=utf-8",
+        Some("js" | "mjs") => "text/javascript; charset=utf-8",
+        Some("txt") => "text/plain; charset=utf-8",
+        Some("json") => "application/json",
+        Some("xml") => "application/xml",
+        Some("wasm") => "application/wasm",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        Some("svg") => "image/svg+xml",
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("ico") => "image/x-icon",
+        Some("mp4") => "video/mp4",
+        Some("webm") => "video/webm",
+        Some("woff") => "font/woff",
+        Some("woff2") => "font/woff2",
+        _ => "application/octet-stream",
+    }
+}
+
+fn percent_decode(value: &str) -> Option<String> {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' {
+            let hex = value.get(i + 1..i + 3)?;
+            out.push(u8::from_str_radix(hex, 16).ok()?);
+            i += 3;
+        } else {
+            out.push(bytes[i]);
+            i += 1;
+        }
+    }
+    String::from_utf8(out).ok()
+}
+
+/// Compare entity tags, `weak` allows weak tags to match.
+fn etag_matches(tag: &str, etag: &str, weak: bool) -> bool {
+    match tag.strip_prefix("W/") {
+        Some(tag) => weak && tag == etag,
+        None => tag == etag,
+    }
+}
+
+fn header<'a>(headers: &'a [Header], name: &str) -> Option<&'a str> {
+    headers.iter().find(|h| h.name() == name).map(Header::value)
+}
+
+impl StaticFiles {
+    /// # Errors
+    ///
+    /// If `root` is not a directory.
+    pub fn new(root: &Path) -> io::Result<Self> {
+        let root = root.canonicalize()?;
+        if !root.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the document root is not a directory",
+            ));
+        }
+        Ok(Self { root })
+    }
+
+    /// Map the path of a request to a file below the document root. The status of an error
+    /// response is returned if that is not possible.
+    fn resolve(&self, path: &str) -> Result<(PathBuf, File, Metadata), u16> {
+        let path = path.split(|c| c == '?' || c == '#').next().unwrap_or("");
+        let path = percent_decode(path).ok_or(400_u16)?;
+        if !path.starts_with('/') {
+            return Err(400);
+        }
+        let mut file_path = self.root.clone();
+        for segment in path.split('/') {
+            match segment {
+                "" | "." => {}
+                ".." => return Err(403),
+                s if s.contains(|c| c == '\\' || c == '\0') => return Err(403),
+                s => file_path.push(s),
+            }
+        }
+        let mut file_path = self.canonicalize(&file_path)?;
+        if file_path.is_dir() {
+            file_path = INDEX_FILES
+                .iter()
+                .map(|index| file_path.join(index))
+                .find(|index| index.is_file())
+                .ok_or(404_u16)?;
+            // The index file may be a link to somewhere else.
+            file_path = self.canonicalize(&file_path)?;
+        }
+        let file = File::open(&file_path).map_err(|_| 403_u16)?;
+        let metadata = file.metadata().map_err(|_| 500_u16)?;
+        if !metadata.is_file() {
+            return Err(404);
+        }
+        Ok((file_path, file, metadata))
+    }
+
+    /// Symbolic links must not lead out of the document root.
+    fn canonicalize(&self, path: &Path) -> Result<PathBuf, u16> {
+        let path = path.canonicalize().map_err(|e| {
+            if e.kind() == io::ErrorKind::NotFound {
+                404_u16
+            } else {
+                403
+            }
+        })?;
+        if path.starts_with(&self.root) {
+            Ok(path)
+        } else {
+            Err(403)
+        }
+    }
+
+    /// Build the response to a request.
+    pub fn respond(&self, request: &[Header]) -> Response {
+        let method = header(request, ":method").unwrap_or("");
+        if method != "GET" && method != "HEAD" {
+            let mut response = Response::empty(405);
+            response.headers.push(Header::new("allow", "GET, HEAD"));
+            return response;
+        }
+        let (file_path, file, metadata) = match self.resolve(header(request, ":path").unwrap_or(""))
+        {
+            Ok(f) => f,
+            Err(status) => return Response::empty(status),
+        };
+        qdebug!("Serving {}", file_path.display());
+
+        let len = metadata.len();
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|m| m.duration_since(UNIX_EPOCH).ok())
+            .map(|d| d.as_secs());
+        let etag = format!("\"{:x}-{:x}\"", len, modified.unwrap_or(0));
+        let mut headers = vec![
+            Header::new("etag", &etag),
+            Header::new("accept-ranges", "bytes"),
+        ];
+        if let Some(modified) = modified {
+            headers.push(Header::new("last-modified", http_date(modified)));
+        }
+
+        // If-None-Match takes precedence over If-Modified-Since.
+        let not_modified = if let Some(tags) = header(request, "if-none-match") {
+            tags.split(',')
+                .map(str::trim)
+                .any(|tag| tag == "*" || etag_matches(tag, &etag, true))
+        } else if let Some(since) = header(request, "if-modified-since") {
+            parse_http_date(since)
+                .zip(modified)
+                .map_or(false, |(since, modified)| modified <= since)
+        } else {
+            false
+        };
+        if not_modified {
+            headers.insert(0, Header::new(":status", "304"));
+            return Response {
+                headers,
+                body: None,
+            };
+        }
+
+        // A range is only used if the file has not changed since the client got the validator
+        // in If-Range.
+        let range_valid = header(request, "if-range").map_or(true, |validator| {
+            if validator.starts_with('"') || validator.starts_with("W/") {
+                etag_matches(validator, &etag, false)
+            } else {
+                parse_http_date(validator).map_or(false, |date| Some(date) == modified)
+            }
+        });
+        let range = match header(request, "range") {
+            Some(range) if range_valid => ByteRange::parse(range, len),
+            _ => ByteRange::Full,
+        };
+        let (status, start, count) = match range {
+            ByteRange::Full => (200, 0, len),
+            ByteRange::Part(first, last) => {
+                headers.push(Header::new(
+                    "content-range",
+                    format!("bytes {first}-{last}/{len}"),
+                ));
+                (206, first, last - first + 1)
+            }
+            ByteRange::Unsatisfiable => {
+                let mut response = Response::empty(416);
+                response
+                    .headers
+                    .push(Header::new("content-range", format!("bytes */{len}")));
+                return response;
+            }
+        };
+        headers.insert(0, Header::new(":status", status.to_string()));
+        headers.push(Header::new("content-type", content_type(&file_path)));
+        headers.push(Header::new("content-length", count.to_string()));
+
+        let body = if method == "HEAD" || count == 0 {
+            None
+        } else {
+            match FileBody::new(file, start, count) {
+                Ok(body) => Some(body),
+                Err(_) => return Response::empty(500),
+            }
+        };
+        Response { headers, body }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{env, fs, process};
+
+    use neqo_common::Header;
+
+    use super::{http_date, parse_http_date, ByteRange, StaticFiles};
+
+    #[test]
+    fn byte_range() {
+        assert_eq!(ByteRange::parse("bytes=0-9", 100), ByteRange::Part(0, 9));
+        assert_eq!(ByteRange::parse("bytes=90-", 100), ByteRange::Part(90, 99));
+        assert_eq!(
+            ByteRange::parse("bytes=90-200", 100),
+            ByteRange::Part(90, 99)
+        );
+        assert_eq!(ByteRange::parse("bytes=-10", 100), ByteRange::Part(90, 99));
+        assert_eq!(ByteRange::parse("bytes=-200", 100), ByteRange::Part(0, 99));
+        assert_eq!(
+            ByteRange::parse("bytes=100-", 100),
+            ByteRange::Unsatisfiable
+        );
+        assert_eq!(ByteRange::parse("bytes=-0", 100), ByteRange::Unsatisfiable);
+        assert_eq!(ByteRange::parse("bytes=5-1", 100), ByteRange::Full);
+        assert_eq!(ByteRange::parse("bytes=0-1,5-6", 100), ByteRange::Full);
+        assert_eq!(ByteRange::parse("items=0-1", 100), ByteRange::Full);
+    }
+
+    #[test]
+    fn date() {
+        assert_eq!(http_date(784_111_777), "Sun, 06 Nov 1994 08:49:37 GMT");
+        assert_eq!(
+            parse_http_date("Sun, 06 Nov 1994 08:49:37 GMT"),
+            Some(784_111_777)
+        );
+        assert_eq!(http_date(951_782_400), "Tue, 29 Feb 2000 00:00:00 GMT");
+        assert_eq!(parse_http_date("Sunday, 06-Nov-94 08:49:37 GMT"), None);
+    }
+
+    fn status(files: &StaticFiles, headers: &[Header]) -> String {
+        let response = files.respond(headers);
+        response.headers[0].value().to_string()
+    }
+
+    #[test]
+    fn respond() {
+        let root = env::temp_dir().join(format!("neqo-static-{}", process::id()));
+        fs::create_dir_all(root.join("dir")).unwrap();
+        fs::write(root.join("dir").join("index.html"), "<p>index</p>").unwrap();
+        fs::write(root.join("file.txt"), "0123456789").unwrap();
+        let files = StaticFiles::new(&root).unwrap();
+
+        let get = |path: &str| vec![Header::new(":method", "GET"), Header::new(":path", path)];
+        assert_eq!(status(&files, &get("/file.txt")), "200");
+        assert_eq!(status(&files, &get("/dir/")), "200");
+        assert_eq!(status(&files, &get("/missing")), "404");
+        assert_eq!(status(&files, &get("/../file.txt")), "403");
+        assert_eq!(status(&files, &get("/%2e%2e/file.txt")), "403");
+
+        let mut request = get("/file.txt");
+        request.push(Header::new("range", "bytes=2-4"));
+        let response = files.respond(&request);
+        assert_eq!(response.headers[0].value(), "206");
+        assert!(response
+            .headers
+            .contains(&Header::new("content-range", "bytes 2-4/10")));
+        let etag = response
+            .headers
+            .iter()
+            .find(|h| h.name() == "etag")
+            .unwrap()
+            .value()
+            .to_string();
+
+        // A stale If-Range gets the whole file.
+        request.push(Header::new("if-range", "\"stale\""));
+        assert_eq!(status(&files, &request), "200");
+
+        let mut request = get("/file.txt");
+        request.push(Header::new("if-none-match", etag));
+        assert_eq!(status(&files, &request), "304");
+
+        let head = [
+            Header::new(":method", "HEAD"),
+            Header::new(":path", "/file.txt"),
+        ];
+        assert!(files.respond(&head).body.is_none());
+        let post = [
+            Header::new(":method", "DELETE"),
+            Header::new(":path", "/file.txt"),
+        ];
+        assert_eq!(status(&files, &post), "405");
+
+        fs::remove_dir_all(root).unwrap();
+    }
+}
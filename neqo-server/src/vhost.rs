@@ -0,0 +1,354 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Virtual hosts, which are described by a configuration file and selected by the server name
+// indication (SNI) of a connection.
+
+use std::{
+    cell::RefCell,
+    fs,
+    net::{SocketAddr, ToSocketAddrs},
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use neqo_common::{qdebug, Header};
+use neqo_crypto::{load_certificate, ServerNameConfig, ServerNameSelector};
+use neqo_transport::{CongestionControlAlgorithm, ConnectionParameters, StreamType};
+use serde_derive::Deserialize;
+
+use crate::{
+    static_files::{Response, StaticFiles},
+    ServerError,
+};
+
+/// The configuration file, which is a list of `[[host]]` tables.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    #[serde(rename = "host")]
+    hosts: Vec<HostConfig>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HostConfig {
+    /// The server names of the host.  The first is used in log messages.
+    names: Vec<String>,
+    /// The nickname of the certificate in the NSS database, or the name to load `cert` as.
+    certificate: String,
+    /// A PEM file with the certificate chain, if it isn't in the NSS database.
+    cert: Option<PathBuf>,
+    /// A PEM file with the private key for `cert`.
+    key: Option<PathBuf>,
+    #[serde(default = "HostConfig::default_alpn")]
+    alpn: Vec<String>,
+    /// Serve files from this directory.  Without this, generated responses are sent.
+    root: Option<PathBuf>,
+    /// The addresses to listen on.  Without this, the addresses from the command line are used.
+    #[serde(default)]
+    listen: Vec<String>,
+    #[serde(default)]
+    quic: QuicConfig,
+}
+
+impl HostConfig {
+    fn default_alpn() -> Vec<String> {
+        vec![String::from("h3")]
+    }
+
+    fn name(&self) -> &str {
+        &self.names[0]
+    }
+}
+
+/// The QUIC parameters of a host.  These are fixed before the server name is known,
+/// so all hosts that share a listen address need to use the same values.
+#[derive(Clone, Debug, Deserialize, PartialEq, Eq)]
+#[serde(default, deny_unknown_fields)]
+pub struct QuicConfig {
+    max_streams_bidi: u64,
+    max_streams_uni: u64,
+    /// The idle timeout for connections, in seconds.
+    idle_timeout: u64,
+    congestion_control: String,
+}
+
+impl Default for QuicConfig {
+    fn default() -> Self {
+        Self {
+            max_streams_bidi: 16,
+            max_streams_uni: 16,
+            idle_timeout: 30,
+            congestion_control: String::from("newreno"),
+        }
+    }
+}
+
+impl QuicConfig {
+    fn congestion_control(&self) -> Result<CongestionControlAlgorithm, ServerError> {
+        self.congestion_control
+            .parse()
+            .map_err(|_| ServerError::ConfigError(format!("bad cc {}", self.congestion_control)))
+    }
+
+    pub fn get(&self) -> ConnectionParameters {
+        ConnectionParameters::default()
+            .max_streams(StreamType::BiDi, self.max_streams_bidi)
+            .max_streams(StreamType::UniDi, self.max_streams_uni)
+            .idle_timeout(Duration::from_secs(self.idle_timeout))
+            .cc_algorithm(self.congestion_control().unwrap())
+    }
+}
+
+/// A server on one address.  Its certificate and ALPN are those of the first host that
+/// listens on the address, which is used when the client sends an unknown name, or none.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Listener {
+    pub address: SocketAddr,
+    pub default_host: String,
+    pub certificate: String,
+    pub alpn: Vec<String>,
+    pub quic: QuicConfig,
+}
+
+impl Config {
+    /// Read and check a configuration file.
+    pub fn load(path: &Path) -> Result<Self, ServerError> {
+        let text = fs::read_to_string(path)?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Self, ServerError> {
+        let config: Self =
+            toml::from_str(text).map_err(|e| ServerError::ConfigError(e.to_string()))?;
+        if config.hosts.is_empty() {
+            return Err(ServerError::ConfigError(String::from("no hosts")));
+        }
+        for host in &config.hosts {
+            if host.names.is_empty() {
+                return Err(ServerError::ConfigError(format!(
+                    "host with certificate {} has no names",
+                    host.certificate
+                )));
+            }
+            if host.cert.is_some() != host.key.is_some() {
+                return Err(ServerError::ConfigError(format!(
+                    "{} needs both cert and key, or neither",
+                    host.name()
+                )));
+            }
+            host.quic.congestion_control()?;
+        }
+        Ok(config)
+    }
+
+    /// Work out which servers are needed.  `default` are the addresses to use for hosts that
+    /// don't list any.
+    pub fn listeners(&self, default: &[SocketAddr]) -> Result<Vec<Listener>, ServerError> {
+        let mut listeners: Vec<Listener> = Vec::new();
+        for host in &self.hosts {
+            let addresses = if host.listen.is_empty() {
+                default.to_vec()
+            } else {
+                let mut addresses = Vec::new();
+                for l in &host.listen {
+                    let resolved = l.to_socket_addrs().map_err(|_| {
+                        ServerError::ConfigError(format!("cannot resolve {l} for {}", host.name()))
+                    })?;
+                    addresses.extend(resolved);
+                }
+                addresses
+            };
+
+            for address in addresses {
+                if let Some(l) = listeners.iter().find(|l| l.address == address) {
+                    if l.quic != host.quic {
+                        return Err(ServerError::ConfigError(format!(
+                            "{} and {} share {address} but not their QUIC parameters",
+                            l.default_host,
+                            host.name()
+                        )));
+                    }
+                } else {
+                    listeners.push(Listener {
+                        address,
+                        default_host: host.name().to_owned(),
+                        certificate: host.certificate.clone(),
+                        alpn: host.alpn.clone(),
+                        quic: host.quic.clone(),
+                    });
+                }
+            }
+        }
+        Ok(listeners)
+    }
+}
+
+#[derive(Debug)]
+struct VirtualHost {
+    names: Vec<String>,
+    certificate: String,
+    alpn: Vec<String>,
+    static_files: Option<StaticFiles>,
+}
+
+impl VirtualHost {
+    fn new(config: &HostConfig) -> Result<Self, ServerError> {
+        if let (Some(cert), Some(key)) = (&config.cert, &config.key) {
+            load_certificate(&config.certificate, &fs::read(cert)?, &fs::read(key)?)?;
+        }
+        let static_files = config
+            .root
+            .as_ref()
+            .map(|root| {
+                StaticFiles::new(root).map_err(|e| {
+                    ServerError::ConfigError(format!("cannot serve {}: {e}", root.display()))
+                })
+            })
+            .transpose()?;
+        Ok(Self {
+            names: config
+                .names
+                .iter()
+                .map(|n| n.to_ascii_lowercase())
+                .collect(),
+            certificate: config.certificate.clone(),
+            alpn: config.alpn.clone(),
+            static_files,
+        })
+    }
+}
+
+/// The set of virtual hosts.  This is shared between all servers and connections, so that a
+/// reload affects all new connections.
+#[derive(Debug)]
+pub struct VirtualHosts {
+    hosts: RefCell<Vec<VirtualHost>>,
+}
+
+impl VirtualHosts {
+    pub fn new(config: &Config) -> Result<Self, ServerError> {
+        let hosts = config
+            .hosts
+            .iter()
+            .map(VirtualHost::new)
+            .collect::<Result<_, _>>()?;
+        Ok(Self {
+            hosts: RefCell::new(hosts),
+        })
+    }
+
+    /// Take the hosts from `other`.  Connections that are already established keep their
+    /// certificates, but use the new document roots.
+    pub fn replace(&self, other: Self) {
+        self.hosts.replace(other.hosts.into_inner());
+    }
+
+    fn find<'a>(hosts: &'a [VirtualHost], name: &str) -> Option<&'a VirtualHost> {
+        hosts
+            .iter()
+            .find(|h| h.names.iter().any(|n| n.eq_ignore_ascii_case(name)))
+    }
+
+    /// Respond to a request for `server_name`, or `default` if the name is unknown.
+    /// This returns `None` if the host doesn't serve files.
+    pub fn respond(
+        &self,
+        server_name: Option<&str>,
+        default: &str,
+        request: &[Header],
+    ) -> Option<Response> {
+        let hosts = self.hosts.borrow();
+        let host = server_name
+            .and_then(|name| Self::find(&hosts, name))
+            .or_else(|| Self::find(&hosts, default))?;
+        host.static_files.as_ref().map(|f| f.respond(request))
+    }
+}
+
+impl ServerNameSelector for VirtualHosts {
+    fn select(&self, server_name: &str) -> Option<ServerNameConfig> {
+        let hosts = self.hosts.borrow();
+        let host = Self::find(&hosts, server_name)?;
+        qdebug!("Selected host {} for {}", host.names[0], server_name);
+        Some(ServerNameConfig {
+            certificate: host.certificate.clone(),
+            protocols: host.alpn.clone(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+
+    use neqo_crypto::ServerNameSelector;
+
+    use super::{Config, VirtualHosts};
+
+    const CONFIG: &str = r#"
+        [[host]]
+        names = ["example.com", "www.example.com"]
+        certificate = "example"
+
+        [[host]]
+        names = ["other.example"]
+        certificate = "other"
+        alpn = ["h3", "h3-29"]
+        listen = ["127.0.0.1:4433", "127.0.0.1:4434"]
+        [host.quic]
+        max_streams_bidi = 100
+    "#;
+
+    fn addr(s: &str) -> SocketAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn parse() {
+        let config = Config::parse(CONFIG).unwrap();
+        let listeners = config.listeners(&[addr("[::]:4433")]).unwrap();
+        assert_eq!(listeners.len(), 3);
+        assert_eq!(listeners[0].address, addr("[::]:4433"));
+        assert_eq!(listeners[0].default_host, "example.com");
+        assert_eq!(listeners[0].alpn, vec![String::from("h3")]);
+        assert_eq!(listeners[1].address, addr("127.0.0.1:4433"));
+        assert_eq!(listeners[1].certificate, "other");
+        assert_eq!(listeners[2].address, addr("127.0.0.1:4434"));
+    }
+
+    #[test]
+    fn shared_address() {
+        let config = Config::parse(CONFIG).unwrap();
+        // Both hosts listen on the same address, with different QUIC parameters.
+        assert!(config.listeners(&[addr("127.0.0.1:4433")]).is_err());
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Config::parse("").is_err());
+        assert!(Config::parse("[[host]]\nnames = []\ncertificate = \"a\"").is_err());
+        assert!(Config::parse("[[host]]\nnames = [\"a\"]\ncertificate = \"a\"\nport = 1").is_err());
+        assert!(
+            Config::parse("[[host]]\nnames = [\"a\"]\ncertificate = \"a\"\ncert = \"a\"").is_err()
+        );
+        assert!(Config::parse(
+            "[[host]]\nnames = [\"a\"]\ncertificate = \"a\"\n[host.quic]\ncongestion_control = \"x\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn select() {
+        let hosts = VirtualHosts::new(&Config::parse(CONFIG).unwrap()).unwrap();
+        let selected = hosts.select("WWW.Example.com").unwrap();
+        assert_eq!(selected.certificate, "example");
+        assert_eq!(selected.protocols, vec![String::from("h3")]);
+        assert_eq!(hosts.select("other.example").unwrap().protocols.len(), 2);
+        assert!(hosts.select("unknown.example").is_none());
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Instant;
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use neqo_transport::tracking::{AckTracker, PacketNumberSpace};
+
+const PACKETS: u64 = 100_000;
+/// How many packets later a reordered packet arrives.
+const REORDER: u64 = 500;
+
+/// Receive packets where one in every `gap` is missing, which makes a new range each time.
+/// If `reordered` is set, the missing packets arrive `REORDER` packets late.
+fn receive(c: &mut Criterion, gap: u64, reordered: bool) {
+    let now = Instant::now();
+    let name = if reordered { "reordered" } else { "lost" };
+    c.bench_function(
+        &format!("receive {PACKETS} packets, 1 in {gap} {name}"),
+        |b| {
+            b.iter_batched(
+                AckTracker::default,
+                |mut tracker| {
+                    let space = tracker.get_mut(PacketNumberSpace::ApplicationData).unwrap();
+                    for pn in 0..PACKETS {
+                        if pn % gap != 0 {
+                            space.set_received(now, pn, true);
+                        }
+                        if reordered && pn >= REORDER && (pn - REORDER) % gap == 0 {
+                            space.set_received(now, pn - REORDER, true);
+                        }
+                    }
+                    tracker
+                },
+                BatchSize::SmallInput,
+            );
+        },
+    );
+}
+
+fn benchmark_receive(c: &mut Criterion) {
+    receive(c, 100, false);
+    receive(c, 3, false);
+    receive(c, 100, true);
+    receive(c, 3, true);
+}
+
+criterion_group!(benches, benchmark_receive);
+criterion_main!(benches);
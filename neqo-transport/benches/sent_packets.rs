@@ -0,0 +1,205 @@
+use std::{cmp::min, collections::BTreeMap, iter, ops::RangeInclusive, time::Instant};
+
+use criterion::{criterion_group, criterion_main, BatchSize, Criterion};
+use neqo_transport::{
+    packet::{PacketNumber, PacketType},
+    tracking::{SentPacket, SentPackets},
+};
+
+/// One packet in this many is lost and never acknowledged.
+const LOSS: PacketNumber = 100;
+/// The number of ranges in each ACK frame.
+const ACK_RANGES: usize = 32;
+
+fn packet(pn: PacketNumber, now: Instant) -> SentPacket {
+    SentPacket::new(PacketType::Short, pn, now, true, Vec::new(), 1200)
+}
+
+/// The ranges of an ACK frame with the given largest packet number,
+/// which acknowledges everything other than the lost packets.
+fn ack_ranges(largest: PacketNumber) -> Vec<RangeInclusive<PacketNumber>> {
+    let mut ranges = Vec::with_capacity(ACK_RANGES);
+    let mut hi = largest;
+    while ranges.len() < ACK_RANGES {
+        let lost = hi / LOSS * LOSS;
+        if lost < hi {
+            ranges.push(lost + 1..=hi);
+        }
+        if lost == 0 {
+            break;
+        }
+        hi = lost - 1;
+    }
+    ranges
+}
+
+/// An ACK frame for every second packet in a window of `window` packets.
+fn acks(window: PacketNumber) -> Vec<Vec<RangeInclusive<PacketNumber>>> {
+    (1..window).step_by(2).map(ack_ranges).collect()
+}
+
+/// How `LossRecoverySpace` removed acknowledged packets from a `BTreeMap`.
+fn btree_remove_acked(
+    sent: &mut BTreeMap<PacketNumber, SentPacket>,
+    ranges: &[RangeInclusive<PacketNumber>],
+) -> Vec<SentPacket> {
+    let mut keep = Vec::with_capacity(ranges.len());
+    let mut acked = Vec::new();
+    for range in ranges {
+        let first_keep = *range.end() + 1;
+        if let Some((&first, _)) = sent.range(range.clone()).next() {
+            let mut tail = sent.split_off(&first);
+            if let Some((&next, _)) = tail.range(first_keep..).next() {
+                keep.push(tail.split_off(&next));
+            }
+            acked.extend(tail.into_values().rev());
+        }
+    }
+    for mut k in keep.into_iter().rev() {
+        sent.append(&mut k);
+    }
+    acked
+}
+
+fn sent_packets_remove_acked(
+    sent: &mut SentPackets,
+    ranges: &[RangeInclusive<PacketNumber>],
+) -> Vec<SentPacket> {
+    let mut acked = Vec::new();
+    for range in ranges {
+        sent.take_range(range, &mut acked);
+    }
+    acked
+}
+
+fn ack_window(c: &mut Criterion, window: PacketNumber) {
+    let now = Instant::now();
+    let acks = acks(window);
+    c.bench_function(&format!("ack {window} packets, BTreeMap"), |b| {
+        b.iter_batched(
+            || (0..window).map(|pn| (pn, packet(pn, now))).collect(),
+            |mut sent: BTreeMap<_, _>| {
+                for ranges in &acks {
+                    btree_remove_acked(&mut sent, ranges);
+                }
+                sent
+            },
+            BatchSize::LargeInput,
+        );
+    });
+    c.bench_function(&format!("ack {window} packets, SentPackets"), |b| {
+        b.iter_batched(
+            || {
+                let mut sent = SentPackets::default();
+                for pn in 0..window {
+                    sent.track(packet(pn, now));
+                }
+                sent
+            },
+            |mut sent| {
+                for ranges in &acks {
+                    sent_packets_remove_acked(&mut sent, ranges);
+                }
+                sent
+            },
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+/// The number of packets at the end of the window that are still in flight.
+const IN_FLIGHT: PacketNumber = 100;
+
+/// The packets that remain after acknowledging everything in a window of `window`
+/// packets other than the first packet and the last `IN_FLIGHT` packets.
+/// The first packet holds the front of the window in place.
+fn stale_window(window: PacketNumber) -> impl Iterator<Item = PacketNumber> {
+    iter::once(0).chain(window - IN_FLIGHT..window)
+}
+
+/// The largest acknowledged packet for `stale_window`.
+fn stale_largest_acked(window: PacketNumber) -> PacketNumber {
+    window - IN_FLIGHT - 1
+}
+
+/// Build `SentPackets` for `stale_window`, with each ACK frame acknowledging
+/// two more packets.  This iterates once, as loss detection does after every
+/// ACK frame, so the benchmark measures the steady state.
+fn stale_sent_packets(window: PacketNumber, now: Instant) -> SentPackets {
+    let mut sent = SentPackets::default();
+    for pn in 0..window {
+        sent.track(packet(pn, now));
+    }
+    let mut acked = Vec::new();
+    for first in (1..=stale_largest_acked(window)).step_by(2) {
+        let last = min(first + 1, stale_largest_acked(window));
+        sent.take_range(&(first..=last), &mut acked);
+    }
+    _ = sent.iter_mut().count();
+    sent
+}
+
+fn stale_btree(window: PacketNumber, now: Instant) -> BTreeMap<PacketNumber, SentPacket> {
+    stale_window(window)
+        .map(|pn| (pn, packet(pn, now)))
+        .collect()
+}
+
+/// This is what `LossRecoverySpace::detect_lost_packets` visits.
+fn detect_lost<'a>(
+    packets: impl Iterator<Item = &'a mut SentPacket>,
+    largest_acked: PacketNumber,
+) -> usize {
+    packets.take_while(|p| p.pn < largest_acked).count()
+}
+
+/// This is what `LossRecoverySpace::pto_packets` visits.
+fn pto<'a>(packets: impl Iterator<Item = &'a mut SentPacket>) -> usize {
+    packets.filter(|p| p.pto()).take(2).count()
+}
+
+fn stale_window_iter(c: &mut Criterion, window: PacketNumber) {
+    let now = Instant::now();
+    let largest_acked = stale_largest_acked(window);
+    c.bench_function(&format!("detect loss {window} stale, BTreeMap"), |b| {
+        b.iter_batched_ref(
+            || stale_btree(window, now),
+            |sent| detect_lost(sent.values_mut(), largest_acked),
+            BatchSize::LargeInput,
+        );
+    });
+    c.bench_function(&format!("detect loss {window} stale, SentPackets"), |b| {
+        b.iter_batched_ref(
+            || stale_sent_packets(window, now),
+            |sent| detect_lost(sent.iter_mut(), largest_acked),
+            BatchSize::LargeInput,
+        );
+    });
+    c.bench_function(&format!("pto {window} stale, BTreeMap"), |b| {
+        b.iter_batched_ref(
+            || stale_btree(window, now),
+            |sent| pto(sent.values_mut()),
+            BatchSize::LargeInput,
+        );
+    });
+    c.bench_function(&format!("pto {window} stale, SentPackets"), |b| {
+        b.iter_batched_ref(
+            || stale_sent_packets(window, now),
+            |sent| pto(sent.iter_mut()),
+            BatchSize::LargeInput,
+        );
+    });
+}
+
+fn benchmark_ack(c: &mut Criterion) {
+    ack_window(c, 1_000);
+    ack_window(c, 10_000);
+}
+
+fn benchmark_stale_window(c: &mut Criterion) {
+    stale_window_iter(c, 1_000);
+    stale_window_iter(c, 100_000);
+}
+
+criterion_group!(benches, benchmark_ack, benchmark_stale_window);
+criterion_main!(benches);
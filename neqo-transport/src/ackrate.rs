@@ -9,11 +9,11 @@
 
 use std::{cmp::max, convert::TryFrom, time::Duration};
 
-use neqo_common::qtrace;
+use neqo_common::{qlog::NeqoQlog, qtrace};
 
 use crate::{
     connection::params::ACK_RATIO_SCALE, frame::FRAME_TYPE_ACK_FREQUENCY, packet::PacketBuilder,
-    recovery::RecoveryToken, stats::FrameStats,
+    qlog, recovery::RecoveryToken, stats::FrameStats,
 };
 
 #[derive(Debug, Clone)]
@@ -105,6 +105,7 @@ impl FlexibleAckRate {
 
     fn write_frames(
         &mut self,
+        qlog: &mut NeqoQlog,
         builder: &mut PacketBuilder,
         tokens: &mut Vec<RecoveryToken>,
         stats: &mut FrameStats,
@@ -114,6 +115,12 @@ impl FlexibleAckRate {
             && self.target.write_frame(builder, self.next_frame_seqno)
         {
             qtrace!("FlexibleAckRate: write frame {:?}", self.target);
+            qlog::ack_frequency_sent(
+                qlog,
+                self.next_frame_seqno,
+                self.target.packets + 1,
+                self.target.delay,
+            );
             self.frame_outstanding = true;
             self.next_frame_seqno += 1;
             tokens.push(RecoveryToken::AckFrequency(self.target.clone()));
@@ -121,7 +128,8 @@ impl FlexibleAckRate {
         }
     }
 
-    fn frame_acked(&mut self, acked: &AckRate) {
+    fn frame_acked(&mut self, qlog: &mut NeqoQlog, acked: &AckRate) {
+        qlog::ack_frequency_acked(qlog, acked.packets + 1, acked.delay);
         self.frame_outstanding = false;
         self.current = acked.clone();
     }
@@ -171,18 +179,19 @@ impl PeerAckDelay {
 
     pub fn write_frames(
         &mut self,
+        qlog: &mut NeqoQlog,
         builder: &mut PacketBuilder,
         tokens: &mut Vec<RecoveryToken>,
         stats: &mut FrameStats,
     ) {
         if let Self::Flexible(rate) = self {
-            rate.write_frames(builder, tokens, stats);
+            rate.write_frames(qlog, builder, tokens, stats);
         }
     }
 
-    pub fn frame_acked(&mut self, r: &AckRate) {
+    pub fn frame_acked(&mut self, qlog: &mut NeqoQlog, r: &AckRate) {
         if let Self::Flexible(rate) = self {
-            rate.frame_acked(r);
+            rate.frame_acked(qlog, r);
         }
     }
 
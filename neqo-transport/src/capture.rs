@@ -0,0 +1,78 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Capturing the datagrams of a connection in a pcapng file.
+
+use std::{
+    fmt,
+    fs::{File, OpenOptions},
+    io::{self, BufWriter, Write},
+    path::Path,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use neqo_common::{pcap::PcapngWriter, qwarn, Datagram};
+
+/// A pcapng capture of the datagrams that a connection sends and receives, including
+/// the TLS secrets that are needed to decrypt them.
+pub struct PacketCapture {
+    writer: PcapngWriter<Box<dyn Write>>,
+    /// The `Instant` of the first datagram and the wall clock time at that point.
+    /// A `Connection` doesn't use the wall clock, so this maps `Instant` onto it.
+    start: Option<(Instant, SystemTime)>,
+}
+
+impl PacketCapture {
+    /// Write a capture to `w`.
+    ///
+    /// # Errors
+    ///
+    /// When the headers of the capture can't be written.
+    pub fn new(w: impl Write + 'static) -> io::Result<Self> {
+        Ok(Self {
+            writer: PcapngWriter::new(Box::new(w) as Box<dyn Write>)?,
+            start: None,
+        })
+    }
+
+    /// Create a new file at `path` for the capture.  This fails if the file exists.
+    ///
+    /// # Errors
+    ///
+    /// When the file can't be created.
+    pub fn create(path: impl AsRef<Path>) -> io::Result<Self> {
+        let f: File = OpenOptions::new().write(true).create_new(true).open(path)?;
+        Self::new(BufWriter::new(f))
+    }
+
+    /// Record a datagram that was sent or received at `now`.
+    pub(crate) fn datagram(&mut self, d: &Datagram, now: Instant) -> io::Result<()> {
+        let (start, wall) = *self.start.get_or_insert_with(|| (now, SystemTime::now()));
+        let time = (wall + now.saturating_duration_since(start))
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        self.writer.write_datagram(time, d)
+    }
+
+    /// Record TLS secrets, in the format of SSLKEYLOGFILE.
+    pub(crate) fn secrets(&mut self, keylog: &[u8]) -> io::Result<()> {
+        self.writer.write_secrets(keylog)
+    }
+}
+
+impl fmt::Debug for PacketCapture {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "PacketCapture")
+    }
+}
+
+impl Drop for PacketCapture {
+    fn drop(&mut self) {
+        if let Err(e) = self.writer.flush() {
+            qwarn!("Error flushing packet capture: {}", e);
+        }
+    }
+}
@@ -23,7 +23,10 @@ use crate::{
     tracking::SentPacket,
 };
 #[rustfmt::skip] // to keep `::` and thus prevent conflict with `crate::qlog`
-use ::qlog::events::{quic::CongestionStateUpdated, EventData};
+use ::qlog::events::{
+    quic::{CongestionStateUpdated, CongestionStateUpdatedTrigger},
+    EventData,
+};
 use neqo_common::{const_max, const_min, qdebug, qinfo, qlog::NeqoQlog, qtrace};
 
 pub const CWND_INITIAL_PKTS: usize = 10;
@@ -407,7 +410,8 @@ impl<T: WindowAdjustment> ClassicCongestionControl<T> {
                     let ev_data = EventData::CongestionStateUpdated(CongestionStateUpdated {
                         old: Some(old_state.to_qlog().to_owned()),
                         new: state.to_qlog().to_owned(),
-                        trigger: None,
+                        trigger: (state == State::PersistentCongestion)
+                            .then_some(CongestionStateUpdatedTrigger::PersistentCongestion),
                     });
                     Some(ev_data)
                 }
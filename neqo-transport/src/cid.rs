@@ -294,6 +294,23 @@ impl ConnectionIdEntry<[u8; 16]> {
     pub fn sequence_number(&self) -> u64 {
         self.seqno
     }
+
+    /// Write out the entry for a connection export.
+    pub fn export(&self, enc: &mut Encoder) {
+        // Sequence numbers can be special values that don't fit in a varint.
+        enc.encode_uint(8, self.seqno);
+        enc.encode_vec(1, &self.cid);
+        enc.encode(&self.srt);
+    }
+
+    /// Read an entry that was written by `export`.
+    pub fn import(dec: &mut Decoder) -> Res<Self> {
+        let seqno = dec.decode_uint(8).ok_or(Error::InvalidExport)?;
+        let cid = ConnectionId::from(dec.decode_vec(1).ok_or(Error::InvalidExport)?);
+        let srt = <[u8; 16]>::try_from(dec.decode(16).ok_or(Error::InvalidExport)?)
+            .map_err(|_| Error::InvalidExport)?;
+        Ok(Self::new(seqno, cid, srt))
+    }
 }
 
 impl ConnectionIdEntry<()> {
@@ -358,6 +375,10 @@ impl<SRT: Clone + PartialEq> ConnectionIdStore<SRT> {
     pub fn len(&self) -> usize {
         self.cids.len()
     }
+
+    pub fn iter(&self) -> impl Iterator<Item = &ConnectionIdEntry<SRT>> {
+        self.cids.iter()
+    }
 }
 
 impl ConnectionIdStore<[u8; 16]> {
@@ -381,6 +402,25 @@ impl ConnectionIdStore<[u8; 16]> {
         }
     }
 
+    /// Write out the entries for a connection export.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(u64::try_from(self.cids.len())?);
+        for entry in &self.cids {
+            entry.export(enc);
+        }
+        Ok(())
+    }
+
+    /// Add the entries that were written by `export`.
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        let count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        for _ in 0..count {
+            self.add_remote(ConnectionIdEntry::import(dec)?)
+                .map_err(|_| Error::InvalidExport)?;
+        }
+        Ok(())
+    }
+
     // Retire connection IDs and return the sequence numbers of those that were retired.
     pub fn retire_prior_to(&mut self, retire_prior: u64) -> Vec<u64> {
         let mut retired = Vec::new();
@@ -592,6 +632,57 @@ impl ConnectionIdManager {
         self.lost_new_connection_id
             .retain(|e| e.seqno != entry.seqno);
     }
+
+    /// The connection IDs that are valid for this connection.
+    pub fn connection_ids(&self) -> impl Iterator<Item = &ConnectionId> {
+        self.connection_ids
+            .iter()
+            .map(ConnectionIdEntry::connection_id)
+    }
+
+    /// Write out the local connection IDs for a connection export.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(u64::try_from(self.connection_ids.len())?);
+        for entry in self.connection_ids.iter() {
+            enc.encode_uint(8, entry.seqno);
+            enc.encode_vec(1, &entry.cid);
+        }
+        enc.encode_varint(u64::try_from(self.limit)?);
+        enc.encode_varint(self.next_seqno);
+        enc.encode_varint(u64::try_from(self.lost_new_connection_id.len())?);
+        for entry in &self.lost_new_connection_id {
+            entry.export(enc);
+        }
+        Ok(())
+    }
+
+    /// Replace the local connection IDs with those written by `export`.
+    /// The generator needs to be able to decode all of these.
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        let mut connection_ids = ConnectionIdStore::default();
+        let count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        for _ in 0..count {
+            let seqno = dec.decode_uint(8).ok_or(Error::InvalidExport)?;
+            let cid = ConnectionId::from(dec.decode_vec(1).ok_or(Error::InvalidExport)?);
+            connection_ids.add_local(ConnectionIdEntry::new(seqno, cid, ()));
+        }
+        let limit = usize::try_from(dec.decode_varint().ok_or(Error::InvalidExport)?)?;
+        let next_seqno = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let mut lost_new_connection_id = Vec::new();
+        for _ in 0..count {
+            lost_new_connection_id.push(ConnectionIdEntry::import(dec)?);
+        }
+        if limit > LOCAL_ACTIVE_CID_LIMIT {
+            return Err(Error::InvalidExport);
+        }
+
+        self.connection_ids = connection_ids;
+        self.limit = limit;
+        self.next_seqno = next_seqno;
+        self.lost_new_connection_id = lost_new_connection_id;
+        Ok(())
+    }
 }
 
 #[cfg(test)]
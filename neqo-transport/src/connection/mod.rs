@@ -20,17 +20,19 @@ use std::{
 
 use neqo_common::{
     event::Provider as EventProvider, hex, hex_snip_middle, hrtime, qdebug, qerror, qinfo,
-    qlog::NeqoQlog, qtrace, qwarn, Datagram, Decoder, Encoder, Role,
+    qlog::NeqoQlog, qtrace, qwarn, Bytes, Datagram, Decoder, Encoder, Role,
 };
 use neqo_crypto::{
-    agent::CertificateInfo, random, Agent, AntiReplay, AuthenticationStatus, Cipher, Client, Group,
+    agent::CertificateInfo, random, Agent, AntiReplay, AuthenticationStatus,
+    CertificateCompression, CertificateVerifier, Cipher, Client, ClientCertificateSelector, Group,
     HandshakeState, PrivateKey, PublicKey, ResumptionToken, SecretAgentInfo, SecretAgentPreInfo,
-    Server, ZeroRttChecker,
+    Server, ServerNameSelector, ZeroRttChecker,
 };
 use smallvec::SmallVec;
 
 use crate::{
     addr_valid::{AddressValidation, NewTokenState},
+    capture::PacketCapture,
     cid::{
         ConnectionId, ConnectionIdEntry, ConnectionIdGenerator, ConnectionIdManager,
         ConnectionIdRef, ConnectionIdStore, LOCAL_ACTIVE_CID_LIMIT,
@@ -86,6 +88,8 @@ struct Packet(Vec<u8>);
 /// to receiving an undecryptable packet during the early part of the
 /// handshake.  This is a hack, but a useful one.
 const EXTRA_INITIALS: usize = 4;
+/// The format of the state that `Connection::export` produces.
+const EXPORT_FORMAT: u64 = 1;
 
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 pub enum ZeroRttState {
@@ -272,11 +276,15 @@ pub struct Connection {
     new_token: NewTokenState,
     stats: StatsCell,
     qlog: NeqoQlog,
+    /// A pcapng capture of the datagrams of this connection.
+    capture: Option<PacketCapture>,
     /// A session ticket was received without NEW_TOKEN,
     /// this is when that turns into an event without NEW_TOKEN.
     release_resumption_token_timer: Option<Instant>,
     conn_params: ConnectionParameters,
     hrtime: hrtime::Handle,
+    /// When this is set, the client checks the server certificate itself.
+    certificate_verifier: Option<CertificateVerifier>,
 
     /// For testing purposes it is sometimes necessary to inject frames that wouldn't
     /// otherwise be sent, just to see how a connection handles them.  Inserting them
@@ -389,6 +397,7 @@ impl Connection {
             conn_params.get_datagram_size(),
             conn_params.get_outgoing_datagram_queue(),
             conn_params.get_incoming_datagram_queue(),
+            conn_params.get_datagram_share(),
             events.clone(),
         );
 
@@ -407,7 +416,7 @@ impl Connection {
             saved_datagrams: SavedDatagrams::default(),
             received_untracked: false,
             crypto,
-            acks: AckTracker::default(),
+            acks: AckTracker::new(stats.clone()),
             idle_timeout: IdleTimeout::new(conn_params.get_idle_timeout()),
             streams: Streams::new(tphandler, role, events.clone()),
             connection_ids: ConnectionIdStore::default(),
@@ -417,10 +426,12 @@ impl Connection {
             new_token: NewTokenState::new(role),
             stats,
             qlog: NeqoQlog::disabled(),
+            capture: None,
             release_resumption_token_timer: None,
             conn_params,
             hrtime: hrtime::Time::get(Self::LOOSE_TIMER_RESOLUTION),
             quic_datagrams,
+            certificate_verifier: None,
             #[cfg(test)]
             test_frame_writer: None,
         };
@@ -447,6 +458,50 @@ impl Connection {
         self.crypto.server_enable_ech(config, public_name, sk, pk)
     }
 
+    /// Select the certificate for this connection based on the server name that the client
+    /// sends.
+    ///
+    /// # Errors
+    ///
+    /// When the selector can't be installed.
+    pub fn server_enable_sni(&mut self, selector: Rc<dyn ServerNameSelector>) -> Res<()> {
+        self.crypto.server_enable_sni(selector)
+    }
+
+    /// Ask the client for a certificate, which is checked with `verifier`.  Use
+    /// `peer_certificate` to find which certificate the client used.
+    ///
+    /// # Errors
+    ///
+    /// When the underlying TLS stack can't be configured.
+    pub fn server_request_client_certificate(
+        &mut self,
+        verifier: CertificateVerifier,
+        required: bool,
+    ) -> Res<()> {
+        self.crypto
+            .server_request_client_certificate(verifier, required)
+    }
+
+    /// Pick a certificate to present if the server asks for one.
+    ///
+    /// # Errors
+    ///
+    /// When the underlying TLS stack can't be configured.
+    pub fn client_set_certificate_selector(
+        &mut self,
+        selector: Rc<dyn ClientCertificateSelector>,
+    ) -> Res<()> {
+        self.crypto.client_set_certificate_selector(selector)
+    }
+
+    /// The server name that the client indicated.  This is only available on a server that
+    /// uses `server_enable_sni`.
+    #[must_use]
+    pub fn server_name_indication(&self) -> Option<&str> {
+        self.crypto.server_name_indication()
+    }
+
     /// Get the active ECH configuration, which is empty if ECH is disabled.
     pub fn ech_config(&self) -> &[u8] {
         self.crypto.ech_config()
@@ -456,6 +511,19 @@ impl Connection {
         self.crypto.client_enable_ech(ech_config_list)
     }
 
+    /// Check the server certificate with `verifier` instead of asking the application.
+    /// With this, there is no `AuthenticationNeeded` event; a `CertificateVerified` event
+    /// reports the outcome instead and the handshake continues, or fails, without any
+    /// call to `authenticated`.
+    ///
+    /// # Panics
+    ///
+    /// If this is a server.
+    pub fn set_certificate_verifier(&mut self, verifier: CertificateVerifier) {
+        assert_eq!(self.role, Role::Client);
+        self.certificate_verifier = Some(verifier);
+    }
+
     /// Set or clear the qlog for this connection.
     pub fn set_qlog(&mut self, qlog: NeqoQlog) {
         self.loss_recovery.set_qlog(qlog.clone());
@@ -468,6 +536,31 @@ impl Connection {
         &mut self.qlog
     }
 
+    /// Set or clear a capture of the datagrams that this connection sends and receives.
+    /// The capture includes the TLS secrets, so it should be set before the handshake
+    /// starts and it needs to be protected as much as any key log.
+    pub fn set_capture(&mut self, capture: Option<PacketCapture>) {
+        if capture.is_some() {
+            self.crypto.enable_secret_log();
+        }
+        self.capture = capture;
+    }
+
+    /// Write a datagram to the capture, after any new secrets.
+    fn capture_datagram(&mut self, d: &Datagram, now: Instant) {
+        let Some(capture) = self.capture.as_mut() else {
+            return;
+        };
+        let mut res = Ok(());
+        if let Some(secrets) = self.crypto.take_secret_log() {
+            res = capture.secrets(&secrets);
+        }
+        if let Err(e) = res.and_then(|_| capture.datagram(d, now)) {
+            qwarn!([self], "Packet capture failed: {}; stopping capture", e);
+            self.capture = None;
+        }
+    }
+
     /// Get the original destination connection id for this connection. This
     /// will always be present for Role::Client but not if Role::Server is in
     /// State::Init.
@@ -549,6 +642,23 @@ impl Connection {
         Ok(())
     }
 
+    /// Enable certificate compression with the given algorithms.
+    pub fn set_certificate_compression(
+        &mut self,
+        algorithms: &[CertificateCompression],
+    ) -> Res<()> {
+        if self.state != State::Init {
+            qerror!(
+                [self],
+                "Cannot enable certificate compression in state {:?}",
+                self.state
+            );
+            return Err(Error::ConnectionState);
+        }
+        self.crypto.tls.set_certificate_compression(algorithms)?;
+        Ok(())
+    }
+
     /// Enable a set of key exchange groups.
     pub fn set_groups(&mut self, groups: &[Group]) -> Res<()> {
         if self.state != State::Init {
@@ -733,6 +843,175 @@ impl Connection {
         Ok(())
     }
 
+    /// Quiesce the connection and write out its state, so that another process
+    /// can take it over using `Connection::import`.
+    ///
+    /// This only works for a connection that has confirmed its handshake and
+    /// uses a single, validated path.  All packets that carry frames need to be
+    /// acknowledged, all stream data needs to be acknowledged or read, and
+    /// there can't be a key update in progress.
+    ///
+    /// On success, the connection is closed without notifying the peer and
+    /// this instance can't be used any more.
+    ///
+    /// The exported state includes the 1-RTT traffic secrets in the clear.
+    /// Anyone who can read it can decrypt and forge packets for the connection,
+    /// so it needs to be protected as carefully as a private key.
+    ///
+    /// # Errors
+    ///
+    /// `NotQuiescent` if the connection can't be exported yet.  In that case, the
+    /// connection continues as before and this can be attempted again later.
+    pub fn export(&mut self) -> Res<Vec<u8>> {
+        if self.state != State::Confirmed || !matches!(self.state_signaling, StateSignaling::Idle) {
+            return Err(Error::NotQuiescent);
+        }
+
+        let mut enc = Encoder::default();
+        enc.encode_varint(EXPORT_FORMAT);
+        enc.encode_byte(match self.role {
+            Role::Client => 0,
+            Role::Server => 1,
+        });
+        enc.encode_uint(4, self.version.wire_version());
+        enc.encode_vvec(self.crypto.server_name().unwrap_or_default().as_bytes());
+        // An imported connection has no TLS state, but it retains the protocol.
+        let alpn = self
+            .crypto
+            .tls
+            .info()
+            .and_then(SecretAgentInfo::alpn)
+            .or_else(|| self.crypto.protocols().first())
+            .ok_or(Error::NotQuiescent)?;
+        enc.encode_vvec(alpn.as_bytes());
+        enc.encode_vec(1, &self.local_initial_source_cid);
+        self.paths.export(&mut enc)?;
+        {
+            let tps = self.tps.borrow();
+            let remote = tps.remote.as_ref().ok_or(Error::NotQuiescent)?;
+            enc.encode_vvec_with(|enc_inner| tps.local.encode(enc_inner));
+            enc.encode_vvec_with(|enc_inner| remote.encode(enc_inner));
+        }
+        self.cid_manager.export(&mut enc)?;
+        self.connection_ids.export(&mut enc)?;
+        self.crypto.export(&mut enc)?;
+        self.acks.export(&mut enc)?;
+        self.loss_recovery.export(&mut enc)?;
+        self.streams.export(&mut enc)?;
+
+        qinfo!(
+            [self],
+            "Exported connection state {}",
+            hex_snip_middle(&enc)
+        );
+        self.set_state(State::Closed(ConnectionError::Transport(Error::NoError)));
+        Ok(enc.into())
+    }
+
+    /// Resume a connection from the output of `Connection::export`, sending from
+    /// `local_addr`.  `cid_generator` has to be able to decode the connection IDs
+    /// that the exported connection used.
+    ///
+    /// The imported connection has no TLS state, so `tls_info` and
+    /// `peer_certificate` return nothing, session tickets can't be sent, and
+    /// any post-handshake messages from the peer are discarded.  Congestion
+    /// control starts over, though the RTT estimate is kept.  Events and
+    /// datagrams that were not collected before the export are lost.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidExport` if `state` is not a valid export, or `DisabledVersion`
+    /// if it uses a QUIC version that isn't enabled in `conn_params`.
+    pub fn import(
+        state: impl AsRef<[u8]>,
+        cid_generator: Rc<RefCell<dyn ConnectionIdGenerator>>,
+        local_addr: SocketAddr,
+        conn_params: ConnectionParameters,
+        now: Instant,
+    ) -> Res<Self> {
+        let mut dec = Decoder::from(state.as_ref());
+        if dec.decode_varint() != Some(EXPORT_FORMAT) {
+            return Err(Error::InvalidExport);
+        }
+        let role = match dec.decode_byte() {
+            Some(0) => Role::Client,
+            Some(1) => Role::Server,
+            _ => return Err(Error::InvalidExport),
+        };
+        let version = dec
+            .decode_uint(4)
+            .and_then(|v| WireVersion::try_from(v).ok())
+            .and_then(|v| Version::try_from(v).ok())
+            .ok_or(Error::InvalidExport)?;
+        if !conn_params.get_versions().all().contains(&version) {
+            return Err(Error::DisabledVersion);
+        }
+        let server_name = dec.decode_vvec().ok_or(Error::InvalidExport)?;
+        let server_name = std::str::from_utf8(server_name).map_err(|_| Error::InvalidExport)?;
+        let alpn = dec.decode_vvec().ok_or(Error::InvalidExport)?;
+        let alpn = std::str::from_utf8(alpn).map_err(|_| Error::InvalidExport)?;
+        if alpn.is_empty() || alpn.len() > 255 {
+            return Err(Error::InvalidExport);
+        }
+
+        let agent = match role {
+            Role::Client => Agent::from(Client::new(server_name, conn_params.is_greasing())?),
+            Role::Server => Agent::from(Server::new(&[] as &[&str])?),
+        };
+        let mut c = Self::new(role, agent, cid_generator, &[alpn], conn_params)?;
+        c.version = version;
+        c.local_initial_source_cid =
+            ConnectionId::from(dec.decode_vec(1).ok_or(Error::InvalidExport)?);
+        c.paths.import(
+            &mut dec,
+            local_addr,
+            c.local_initial_source_cid.clone(),
+            c.conn_params.get_cc_algorithm(),
+            c.conn_params.pacing_enabled(),
+            now,
+        )?;
+        let local_tps = dec.decode_vvec().ok_or(Error::InvalidExport)?;
+        let remote_tps = dec.decode_vvec().ok_or(Error::InvalidExport)?;
+        {
+            let mut tps = c.tps.borrow_mut();
+            tps.local = TransportParameters::decode(&mut Decoder::from(local_tps))
+                .map_err(|_| Error::InvalidExport)?;
+            tps.remote = Some(
+                TransportParameters::decode(&mut Decoder::from(remote_tps))
+                    .map_err(|_| Error::InvalidExport)?,
+            );
+        }
+        c.cid_manager.import(&mut dec)?;
+        c.connection_ids.import(&mut dec)?;
+        c.crypto.import(version, &mut dec)?;
+
+        // The handshake packet number spaces are gone.
+        let primary = c.paths.primary();
+        for space in [PacketNumberSpace::Initial, PacketNumberSpace::Handshake] {
+            c.loss_recovery.discard(&primary, space, now);
+            c.acks.drop_space(space);
+        }
+        c.acks.import(&mut dec, now)?;
+        c.loss_recovery.import(&mut dec)?;
+
+        c.set_peer_ack_delay()?;
+        c.set_initial_limits();
+        c.streams.import(&mut dec)?;
+        if dec.remaining() > 0 {
+            return Err(Error::InvalidExport);
+        }
+
+        c.idle_timeout.on_packet_received(now);
+        c.set_state(State::Confirmed);
+        qinfo!([c], "Imported connection state");
+        Ok(c)
+    }
+
+    /// The connection IDs that this endpoint has issued to its peer.
+    pub(crate) fn local_connection_ids(&self) -> impl Iterator<Item = &ConnectionId> {
+        self.cid_manager.connection_ids()
+    }
+
     pub(crate) fn set_validation(&mut self, validation: Rc<RefCell<AddressValidation>>) {
         qtrace!([self], "Enabling NEW_TOKEN");
         assert_eq!(self.role, Role::Server);
@@ -931,7 +1210,6 @@ impl Connection {
 
         let lost = self.loss_recovery.timeout(&self.paths.primary(), now);
         self.handle_lost_packets(&lost);
-        qlog::packets_lost(&mut self.qlog, &lost);
 
         if self.release_resumption_token_timer.is_some() {
             self.create_resumption_token(now);
@@ -948,6 +1226,7 @@ impl Connection {
         self.input(d, now, now);
         self.process_saved(now);
         self.streams.cleanup_closed_streams();
+        self.capture_datagram(d, now);
     }
 
     /// Process new input datagrams on the connection.
@@ -961,11 +1240,18 @@ impl Connection {
             return;
         }
 
+        let mut captured = Vec::new();
         for d in dgrams {
             self.input(d, now, now);
+            if self.capture.is_some() {
+                captured.push(d);
+            }
         }
         self.process_saved(now);
         self.streams.cleanup_closed_streams();
+        for d in captured {
+            self.capture_datagram(d, now);
+        }
     }
 
     /// Get the time that we next need to be called back, relative to `now`.
@@ -978,7 +1264,7 @@ impl Connection {
             return timeout.duration_since(now);
         }
 
-        let mut delays = SmallVec::<[_; 6]>::new();
+        let mut delays = SmallVec::<[_; 7]>::new();
         if let Some(ack_time) = self.acks.ack_time(now) {
             qtrace!([self], "Delayed ACK timer {:?}", ack_time);
             delays.push(ack_time);
@@ -994,7 +1280,7 @@ impl Connection {
             qtrace!([self], "Idle/keepalive timer {:?}", idle_time);
             delays.push(idle_time);
 
-            if let Some(lr_time) = self.loss_recovery.next_timeout(rtt) {
+            if let Some(lr_time) = self.loss_recovery.next_timeout(rtt, now) {
                 qtrace!([self], "Loss recovery timer {:?}", lr_time);
                 delays.push(lr_time);
             }
@@ -1017,6 +1303,11 @@ impl Connection {
             delays.push(key_update_time);
         }
 
+        if let Some(deadline) = self.quic_datagrams.next_deadline() {
+            qtrace!([self], "Datagram deadline {:?}", deadline);
+            delays.push(deadline);
+        }
+
         // `release_resumption_token_timer` is not considered here, because
         // it is not important enough to force the application to set a
         // timeout for it  It is expected that other activities will
@@ -1054,7 +1345,10 @@ impl Connection {
         }
 
         match self.output(now) {
-            SendOption::Yes(dgram) => Output::Datagram(dgram),
+            SendOption::Yes(dgram) => {
+                self.capture_datagram(&dgram, now);
+                Output::Datagram(dgram)
+            }
             SendOption::No(paced) => match self.state {
                 State::Init | State::Closed(_) => Output::None,
                 State::Closing { timeout, .. } | State::Draining { timeout, .. } => {
@@ -1071,6 +1365,7 @@ impl Connection {
         if let Some(d) = dgram {
             self.input(d, now, now);
             self.process_saved(now);
+            self.capture_datagram(d, now);
         }
         self.process_output(now)
     }
@@ -1799,6 +2094,8 @@ impl Connection {
 
     fn output(&mut self, now: Instant) -> SendOption {
         qtrace!([self], "output {:?}", now);
+        self.quic_datagrams
+            .expire(now, &mut self.stats.borrow_mut());
         let res = match &self.state {
             State::Init
             | State::WaitInitial
@@ -1987,22 +2284,35 @@ impl Connection {
             return Ok(());
         }
 
-        self.streams
-            .write_frames(TransmissionPriority::High, builder, tokens, frame_stats);
-        if builder.is_full() {
-            return Ok(());
+        // Datagrams are best-effort and unreliable.  Streams starve them,
+        // unless they have been given a share of the bytes that are sent.
+        let datagrams_first = self.quic_datagrams.ahead_of_streams();
+        if datagrams_first {
+            self.quic_datagrams.write_frames(builder, tokens, stats);
+            if builder.is_full() {
+                return Ok(());
+            }
         }
 
+        let frame_stats = &mut stats.frame_tx;
+        let stream_start = builder.len();
         self.streams
-            .write_frames(TransmissionPriority::Normal, builder, tokens, frame_stats);
+            .write_frames(TransmissionPriority::High, builder, tokens, frame_stats);
+        if !builder.is_full() {
+            self.streams
+                .write_frames(TransmissionPriority::Normal, builder, tokens, frame_stats);
+        }
+        self.quic_datagrams
+            .stream_data_written(builder.len() - stream_start);
         if builder.is_full() {
             return Ok(());
         }
 
-        // Datagrams are best-effort and unreliable.  Let streams starve them for now.
-        self.quic_datagrams.write_frames(builder, tokens, stats);
-        if builder.is_full() {
-            return Ok(());
+        if !datagrams_first {
+            self.quic_datagrams.write_frames(builder, tokens, stats);
+            if builder.is_full() {
+                return Ok(());
+            }
         }
 
         let frame_stats = &mut stats.frame_tx;
@@ -2099,14 +2409,12 @@ impl Connection {
         let mut ack_eliciting = false;
 
         if primary {
-            let stats = &mut self.stats.borrow_mut().frame_tx;
             self.acks.write_frame(
                 space,
                 now,
                 path.borrow().rtt().estimate(),
                 builder,
                 &mut tokens,
-                stats,
             )?;
         }
         let ack_end = builder.len();
@@ -2415,21 +2723,7 @@ impl Connection {
                 .borrow_mut()
                 .set_reset_token(reset_token);
 
-            let max_ad = Duration::from_millis(remote.get_integer(tparams::MAX_ACK_DELAY));
-            let min_ad = if remote.has_value(tparams::MIN_ACK_DELAY) {
-                let min_ad = Duration::from_micros(remote.get_integer(tparams::MIN_ACK_DELAY));
-                if min_ad > max_ad {
-                    return Err(Error::TransportParameterError);
-                }
-                Some(min_ad)
-            } else {
-                None
-            };
-            self.paths.primary().borrow_mut().set_ack_delay(
-                max_ad,
-                min_ad,
-                self.conn_params.get_ack_ratio(),
-            );
+            self.set_peer_ack_delay()?;
 
             let max_active_cids = remote.get_integer(tparams::ACTIVE_CONNECTION_ID_LIMIT);
             self.cid_manager.set_limit(max_active_cids);
@@ -2439,6 +2733,29 @@ impl Connection {
         Ok(())
     }
 
+    /// Pass the acknowledgment delay settings from the peer's transport
+    /// parameters on to the primary path.
+    fn set_peer_ack_delay(&self) -> Res<()> {
+        let tps = self.tps.borrow();
+        let remote = tps.remote();
+        let max_ad = Duration::from_millis(remote.get_integer(tparams::MAX_ACK_DELAY));
+        let min_ad = if remote.has_value(tparams::MIN_ACK_DELAY) {
+            let min_ad = Duration::from_micros(remote.get_integer(tparams::MIN_ACK_DELAY));
+            if min_ad > max_ad {
+                return Err(Error::TransportParameterError);
+            }
+            Some(min_ad)
+        } else {
+            None
+        };
+        self.paths.primary().borrow_mut().set_ack_delay(
+            max_ad,
+            min_ad,
+            self.conn_params.get_ack_ratio(),
+        );
+        Ok(())
+    }
+
     fn validate_cids(&mut self) -> Res<()> {
         let tph = self.tps.borrow();
         let remote_tps = tph.remote.as_ref().unwrap();
@@ -2590,12 +2907,25 @@ impl Connection {
         qtrace!([self], "Handshake space={} data={:0x?}", space, data);
 
         let try_update = data.is_some();
+        let mut verify_name = None;
         match self.crypto.handshake(now, space, data)? {
             HandshakeState::Authenticated(_) | HandshakeState::InProgress => (),
-            HandshakeState::AuthenticationPending => self.events.authentication_needed(),
-            HandshakeState::EchFallbackAuthenticationPending(public_name) => self
-                .events
-                .ech_fallback_authentication_needed(public_name.clone()),
+            HandshakeState::AuthenticationPending => {
+                if let (Some(_), Agent::Client(c)) = (&self.certificate_verifier, &self.crypto.tls)
+                {
+                    verify_name = Some(c.server_name().to_owned());
+                } else {
+                    self.events.authentication_needed();
+                }
+            }
+            HandshakeState::EchFallbackAuthenticationPending(public_name) => {
+                if self.certificate_verifier.is_some() {
+                    verify_name = Some(public_name.clone());
+                } else {
+                    self.events
+                        .ech_fallback_authentication_needed(public_name.clone());
+                }
+            }
             HandshakeState::Complete(_) => {
                 if !self.state.connected() {
                     self.set_connected(now)?;
@@ -2605,6 +2935,9 @@ impl Connection {
                 unreachable!("Crypto state should not be new or failed after successful handshake")
             }
         }
+        if let Some(info) = self.crypto.tls.certificate_compression() {
+            self.stats.borrow_mut().cert_compression = Some(info);
+        }
 
         // There is a chance that this could be called less often, but getting the
         // conditions right is a little tricky, so call whenever CRYPTO data is used.
@@ -2624,9 +2957,30 @@ impl Connection {
             }
         }
 
+        if let Some(name) = verify_name {
+            let status = self.verify_certificate(&name, now);
+            qinfo!([self], "Verified certificate for {}: {:?}", name, status);
+            self.events.certificate_verified(status);
+            self.crypto.tls.authenticated(status);
+            return self.handshake(now, packet_version, PacketNumberSpace::Handshake, None);
+        }
+
         Ok(())
     }
 
+    fn verify_certificate(&self, name: &str, now: Instant) -> AuthenticationStatus {
+        let verifier = self
+            .certificate_verifier
+            .as_ref()
+            .expect("only called with a verifier");
+        self.crypto
+            .tls
+            .peer_certificate()
+            .map_or(AuthenticationStatus::Unknown, |mut certs| {
+                verifier.verify_server(&mut certs, name, now)
+            })
+    }
+
     fn input_frame(
         &mut self,
         path: &PathRef,
@@ -2685,8 +3039,13 @@ impl Connection {
                     let mut buf = Vec::new();
                     let read = self.crypto.streams.read_to_end(space, &mut buf);
                     qdebug!("Read {} bytes", read);
-                    self.handshake(now, packet_version, space, Some(&buf))?;
-                    self.create_resumption_token(now);
+                    if self.crypto.imported() {
+                        // There is no TLS state to process post-handshake messages with.
+                        qdebug!([self], "Discarding CRYPTO data after import");
+                    } else {
+                        self.handshake(now, packet_version, space, Some(&buf))?;
+                        self.create_resumption_token(now);
+                    }
                 } else {
                     // If we get a useless CRYPTO frame send outstanding CRYPTO frames again.
                     self.crypto.resend_unacked(space);
@@ -2882,7 +3241,6 @@ impl Connection {
             }
         }
         self.handle_lost_packets(&lost_packets);
-        qlog::packets_lost(&mut self.qlog, &lost_packets);
         let stats = &mut self.stats.borrow_mut().frame_rx;
         stats.ack += 1;
         stats.largest_acknowledged = max(stats.largest_acknowledged, largest_acknowledged);
@@ -3054,6 +3412,20 @@ impl Connection {
         self.streams.get_send_stream_mut(stream_id)?.send(data)
     }
 
+    /// Send data on a stream, like `stream_send`, but without copying `data`.
+    /// The stream keeps a reference to the accepted bytes until they are acknowledged.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` the stream does not exist,
+    /// `InvalidInput` if length of `data` is zero,
+    /// `FinalSizeError` if the stream has already been closed.
+    pub fn stream_send_bytes(&mut self, stream_id: StreamId, data: Bytes) -> Res<usize> {
+        self.streams
+            .get_send_stream_mut(stream_id)?
+            .send_bytes(data)
+    }
+
     /// Send all data or nothing on a stream. May cause DATA_BLOCKED or
     /// STREAM_DATA_BLOCKED frames to be sent.
     /// Returns true if data was successfully sent, otherwise false.
@@ -3112,6 +3484,23 @@ impl Connection {
         Ok(rb)
     }
 
+    /// Read up to `max` bytes of buffered data from stream, like `stream_recv`, but without
+    /// copying. The data is returned in chunks as it was received, so it might take multiple
+    /// calls to read all available data. bool says whether the read includes the final data
+    /// on stream.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidStreamId` if the stream does not exist.
+    /// `NoMoreData` if data and fin bit were previously read by the application.
+    pub fn stream_recv_chunk(
+        &mut self,
+        stream_id: StreamId,
+        max: usize,
+    ) -> Res<(Option<Bytes>, bool)> {
+        self.streams.get_recv_stream_mut(stream_id)?.read_chunk(max)
+    }
+
     /// Application is no longer interested in this stream.
     pub fn stream_stop_sending(&mut self, stream_id: StreamId, err: AppError) -> Res<()> {
         let stream = self.streams.get_recv_stream_mut(stream_id)?;
@@ -3209,8 +3598,24 @@ impl Connection {
     /// time depending on the encoded size of the packet number, ack frames, etc.
 
     pub fn send_datagram(&mut self, buf: &[u8], id: impl Into<DatagramTracking>) -> Res<()> {
+        self.send_datagram_with_deadline(buf, id, None)
+    }
+
+    /// Queue a datagram for sending, as with `send_datagram`.  If the datagram
+    /// has not been sent by `deadline`, it is dropped and an
+    /// `OutgoingDatagramOutcome::Expired` event is posted.
+    ///
+    /// # Error
+    ///
+    /// As for `send_datagram`.
+    pub fn send_datagram_with_deadline(
+        &mut self,
+        buf: &[u8],
+        id: impl Into<DatagramTracking>,
+        deadline: Option<Instant>,
+    ) -> Res<()> {
         self.quic_datagrams
-            .add_datagram(buf, id.into(), &mut self.stats.borrow_mut())
+            .add_datagram(buf, id.into(), deadline, &mut self.stats.borrow_mut())
     }
 }
 
@@ -76,6 +76,9 @@ pub struct ConnectionParameters {
     datagram_size: u64,
     outgoing_datagram_queue: usize,
     incoming_datagram_queue: usize,
+    /// The percentage of application data that datagrams can take ahead of
+    /// normal and high priority stream data.
+    datagram_share: u8,
     fast_pto: u8,
     fuzzing: bool,
     grease: bool,
@@ -99,6 +102,7 @@ impl Default for ConnectionParameters {
             datagram_size: 0,
             outgoing_datagram_queue: MAX_QUEUED_DATAGRAMS_DEFAULT,
             incoming_datagram_queue: MAX_QUEUED_DATAGRAMS_DEFAULT,
+            datagram_share: 0,
             fast_pto: FAST_PTO_SCALE,
             fuzzing: false,
             grease: true,
@@ -274,6 +278,24 @@ impl ConnectionParameters {
         self
     }
 
+    pub fn get_datagram_share(&self) -> u8 {
+        self.datagram_share
+    }
+
+    /// Set the percentage of the application data that datagrams can use
+    /// ahead of stream data with a normal or higher priority.  With the default
+    /// of 0, datagrams are only sent when there is no such stream data to send.
+    /// At 100, datagrams are always sent first.
+    ///
+    /// # Panics
+    ///
+    /// If `percent` is more than 100.
+    pub fn datagram_share(mut self, percent: u8) -> Self {
+        assert!(percent <= 100);
+        self.datagram_share = percent;
+        self
+    }
+
     pub fn get_fast_pto(&self) -> u8 {
         self.fast_pto
     }
@@ -0,0 +1,105 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    cell::RefCell,
+    convert::TryFrom,
+    io::{self, Write},
+    rc::Rc,
+};
+
+use neqo_common::{pcap, Decoder};
+use test_fixture::now;
+
+use super::{
+    super::State, default_client, default_server, deliver, maybe_authenticate, DEFAULT_STREAM_DATA,
+};
+use crate::{PacketCapture, StreamType};
+
+const SECTION_HEADER: u32 = 0x0a0d_0d0a;
+const INTERFACE_DESCRIPTION: u32 = 1;
+const ENHANCED_PACKET: u32 = 6;
+const DECRYPTION_SECRETS: u32 = 10;
+
+/// A buffer that the test can read while a capture writes to it.
+#[derive(Clone, Default)]
+struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Split a capture into the type and body of each block.
+fn blocks(capture: &[u8]) -> Vec<(u32, &[u8])> {
+    let mut dec = Decoder::from(capture);
+    let mut blocks = Vec::new();
+    while dec.remaining() > 0 {
+        let block_type = u32::try_from(dec.decode_uint(4).unwrap()).unwrap();
+        let len = usize::try_from(dec.decode_uint(4).unwrap()).unwrap();
+        blocks.push((block_type, dec.decode(len - 12).unwrap()));
+        dec.skip(4);
+    }
+    blocks
+}
+
+#[test]
+fn capture() {
+    let mut client = default_client();
+    let mut server = default_server();
+    let buffer = SharedBuffer::default();
+    client.set_capture(Some(PacketCapture::new(buffer.clone()).unwrap()));
+
+    let now = now();
+    let mut datagrams = Vec::new();
+    while *client.state() != State::Confirmed {
+        deliver(&mut client, &mut server, now, &mut datagrams);
+        deliver(&mut server, &mut client, now, &mut datagrams);
+        maybe_authenticate(&mut client);
+        assert!(datagrams.len() < 20, "handshake is not making progress");
+    }
+    let stream_id = client.stream_create(StreamType::BiDi).unwrap();
+    client.stream_send(stream_id, DEFAULT_STREAM_DATA).unwrap();
+    deliver(&mut client, &mut server, now, &mut datagrams);
+    server.stream_send(stream_id, DEFAULT_STREAM_DATA).unwrap();
+    deliver(&mut server, &mut client, now, &mut datagrams);
+
+    let capture = buffer.0.borrow();
+    let blocks = blocks(&capture);
+    assert_eq!(blocks[0].0, SECTION_HEADER);
+    assert_eq!(blocks[1].0, INTERFACE_DESCRIPTION);
+    // The client Initial doesn't need any secrets from the key log, but the server
+    // Handshake packets in the response do, so they follow the first secrets.
+    assert_eq!(blocks[2].0, ENHANCED_PACKET);
+    assert_eq!(blocks[3].0, DECRYPTION_SECRETS);
+    let first_secrets = String::from_utf8_lossy(&blocks[3].1[8..]);
+    assert!(first_secrets.contains("CLIENT_HANDSHAKE_TRAFFIC_SECRET"));
+    assert!(first_secrets.contains("SERVER_HANDSHAKE_TRAFFIC_SECRET"));
+    assert_eq!(blocks[4].0, ENHANCED_PACKET);
+
+    let secrets: String = blocks
+        .iter()
+        .filter(|(t, _)| *t == DECRYPTION_SECRETS)
+        .map(|(_, body)| String::from_utf8_lossy(&body[8..]).into_owned())
+        .collect();
+    assert!(secrets.contains("CLIENT_TRAFFIC_SECRET_0"));
+    assert!(secrets.contains("SERVER_TRAFFIC_SECRET_0"));
+
+    // Every datagram that was sent or received is there, in order.
+    let captured = pcap::read_datagrams(&capture).unwrap();
+    assert_eq!(captured.len(), datagrams.len());
+    for (c, (_, d)) in captured.iter().zip(&datagrams) {
+        assert_eq!(c.datagram.source(), d.source());
+        assert_eq!(c.datagram.destination(), d.destination());
+        assert_eq!(&c.datagram[..], &d[..]);
+    }
+}
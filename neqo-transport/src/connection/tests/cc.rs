@@ -7,6 +7,7 @@
 use std::{convert::TryFrom, mem, time::Duration};
 
 use neqo_common::{qdebug, qinfo, Datagram};
+use test_fixture::new_neqo_qlog;
 
 use super::{
     super::Output, ack_bytes, assert_full_cwnd, connect_rtt_idle, cwnd, cwnd_avail, cwnd_packets,
@@ -268,6 +269,49 @@ fn cc_slow_start_to_persistent_congestion_no_acks() {
     induce_persistent_congestion(&mut client, &mut server, stream, now);
 }
 
+#[test]
+/// Verify that losses and the resulting congestion state changes are logged.
+fn cc_persistent_congestion_qlog() {
+    let mut client = default_client();
+    let (log, contents) = new_neqo_qlog();
+    client.set_qlog(log);
+    let mut server = default_server();
+    let now = connect_rtt_idle(&mut client, &mut server, DEFAULT_RTT);
+
+    let stream = client.stream_create(StreamType::BiDi).unwrap();
+    let (c_tx_dgrams, now) = fill_cwnd(&mut client, stream, now);
+    mem::drop(ack_bytes(&mut server, stream, c_tx_dgrams, now));
+    induce_persistent_congestion(&mut client, &mut server, stream, now);
+
+    let contents = contents.to_string();
+    for expected in [
+        "pto_expired",
+        "time_threshold",
+        "congestion_state_updated",
+        "persistent_congestion",
+    ] {
+        assert!(contents.contains(expected), "missing {expected}");
+    }
+
+    // Each lost packet is logged once, even if a PTO fired for it first.
+    let mut lost = contents
+        .lines()
+        .filter(|line| line.contains("packet_lost"))
+        .map(|line| {
+            let (_, pn) = line.split_once("\"packet_number\":").unwrap();
+            pn.split(|c: char| !c.is_ascii_digit())
+                .next()
+                .unwrap()
+                .parse::<u64>()
+                .unwrap()
+        })
+        .collect::<Vec<_>>();
+    let count = lost.len();
+    lost.sort_unstable();
+    lost.dedup();
+    assert_eq!(lost.len(), count);
+}
+
 #[test]
 /// Verify transition to persistent congestion state if conditions are met.
 fn cc_slow_start_to_persistent_congestion_some_acks() {
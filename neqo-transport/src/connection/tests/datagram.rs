@@ -4,14 +4,14 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-use std::{cell::RefCell, convert::TryFrom, rc::Rc};
+use std::{cell::RefCell, convert::TryFrom, rc::Rc, time::Duration};
 
 use neqo_common::event::Provider;
 use test_fixture::now;
 
 use super::{
-    assert_error, connect_force_idle, default_client, default_server, new_client, new_server,
-    AT_LEAST_PTO,
+    assert_error, connect_force_idle, default_client, default_server, fill_cwnd, new_client,
+    new_server, AT_LEAST_PTO,
 };
 use crate::{
     events::{ConnectionEvent, OutgoingDatagramOutcome},
@@ -310,6 +310,139 @@ fn datagram_before_stream_data() {
     assert_eq!(client.stats().frame_tx.datagram, dgram_sent + 1);
 }
 
+/// With a share of 100%, a datagram is sent ahead of normal priority stream data.
+#[test]
+fn datagram_share() {
+    let mut client = new_client(
+        ConnectionParameters::default()
+            .datagram_size(MAX_QUIC_DATAGRAM)
+            .datagram_share(100),
+    );
+    let mut server = new_server(ConnectionParameters::default().datagram_size(MAX_QUIC_DATAGRAM));
+    connect_force_idle(&mut client, &mut server);
+
+    let stream_id = client.stream_create(StreamType::BiDi).unwrap();
+    client.stream_send(stream_id, &[6; 1200]).unwrap();
+    let dgram_sent = client.stats().frame_tx.datagram;
+    assert_eq!(client.send_datagram(DATA_MTU, Some(1)), Ok(()));
+
+    if let ConnectionEvent::Datagram(data) =
+        &send_packet_and_get_server_event(&mut client, &mut server)
+    {
+        assert_eq!(data, DATA_MTU);
+    } else {
+        panic!();
+    }
+    assert_eq!(client.stats().frame_tx.datagram, dgram_sent + 1);
+
+    assert!(
+        matches!(send_packet_and_get_server_event(&mut client, &mut server), ConnectionEvent::RecvStreamReadable { stream_id: s } if s == stream_id)
+    );
+}
+
+#[test]
+fn datagram_expired() {
+    const DEADLINE: Duration = Duration::from_millis(10);
+    let (mut client, mut server) = connect_datagram();
+
+    let dgram_sent = client.stats().frame_tx.datagram;
+    let dgram_expired = client.stats().datagram_tx.expired;
+    assert_eq!(
+        client.send_datagram_with_deadline(
+            DATA_SMALLER_THAN_MTU_2,
+            Some(1),
+            Some(now() + DEADLINE)
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        client.send_datagram_with_deadline(
+            DATA_SMALLER_THAN_MTU_2,
+            Some(2),
+            Some(now() + DEADLINE * 3)
+        ),
+        Ok(())
+    );
+
+    // Only the datagram with the later deadline is sent.
+    let out = client.process_output(now() + DEADLINE * 2).dgram();
+    assert!(matches!(
+        client.next_event().unwrap(),
+        ConnectionEvent::OutgoingDatagramOutcome { id, outcome } if id == 1 && outcome == OutgoingDatagramOutcome::Expired
+    ));
+    assert_eq!(client.stats().datagram_tx.expired, dgram_expired + 1);
+    assert_eq!(client.stats().frame_tx.datagram, dgram_sent + 1);
+
+    server.process_input(&out.unwrap(), now() + DEADLINE * 2);
+    assert!(matches!(
+        server.next_event().unwrap(),
+        ConnectionEvent::Datagram(data) if data == DATA_SMALLER_THAN_MTU_2
+    ));
+}
+
+/// A datagram that can't be sent is expired at its deadline, without waiting
+/// for any other activity on the connection.
+#[test]
+fn datagram_expired_timer() {
+    const DEADLINE: Duration = Duration::from_millis(10);
+    let (mut client, _server) = connect_datagram();
+
+    // Use all of the congestion window so that the datagram can't be sent.
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    let (_, now) = fill_cwnd(&mut client, stream_id, now());
+    assert_eq!(
+        client.send_datagram_with_deadline(DATA_SMALLER_THAN_MTU_2, Some(1), Some(now + DEADLINE)),
+        Ok(())
+    );
+    let delay = client.process_output(now).callback();
+    assert!(delay <= DEADLINE);
+    _ = client.events().count();
+
+    assert!(client.process_output(now + delay).dgram().is_none());
+    assert!(client.events().any(|e| matches!(
+        e,
+        ConnectionEvent::OutgoingDatagramOutcome { id, outcome } if id == 1 && outcome == OutgoingDatagramOutcome::Expired
+    )));
+}
+
+/// With a share between 0 and 100%, datagrams and stream data are interleaved.
+#[test]
+fn datagram_share_interleaved() {
+    const PACKETS: usize = 6;
+    let mut client = new_client(
+        ConnectionParameters::default()
+            .datagram_size(MAX_QUIC_DATAGRAM)
+            .datagram_share(25)
+            .pacing(false),
+    );
+    let mut server = new_server(ConnectionParameters::default().datagram_size(MAX_QUIC_DATAGRAM));
+    connect_force_idle(&mut client, &mut server);
+
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, &[6; 10_000]).unwrap();
+    for id in 0..PACKETS {
+        let id = u64::try_from(id).unwrap();
+        assert_eq!(
+            client.send_datagram(DATA_SMALLER_THAN_MTU_2, Some(id)),
+            Ok(())
+        );
+    }
+
+    let mut with_datagram = 0;
+    for _ in 0..PACKETS {
+        let dgram_sent = client.stats().frame_tx.datagram;
+        let stream_sent = client.stats().frame_tx.stream;
+        assert!(client.process_output(now()).dgram().is_some());
+        // Every packet carries stream data, but only some carry a datagram.
+        assert!(client.stats().frame_tx.stream > stream_sent);
+        if client.stats().frame_tx.datagram > dgram_sent {
+            with_datagram += 1;
+        }
+    }
+    assert!(with_datagram > 1);
+    assert!(with_datagram < PACKETS);
+}
+
 #[test]
 fn datagram_lost() {
     let (mut client, _) = connect_datagram();
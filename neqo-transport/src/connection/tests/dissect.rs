@@ -0,0 +1,109 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::str;
+
+use neqo_common::Role;
+use test_fixture::now;
+
+use super::{
+    super::{ConnectionParameters, State},
+    deliver, maybe_authenticate, new_client, new_server, DEFAULT_STREAM_DATA,
+};
+use crate::{
+    dissect::{Dissector, KeyLog},
+    packet::PacketType,
+    tracking::DEFAULT_ACK_DELAY,
+    StreamType, Version,
+};
+
+/// Run a connection with a key update, then decrypt all of its packets with the
+/// secrets that the client logged.
+fn dissect(params: &ConnectionParameters, version: Version) {
+    let mut client = new_client(params.clone());
+    let mut server = new_server(params.clone());
+    client.crypto.enable_secret_log();
+    let start = now();
+    let mut now = start;
+    let mut datagrams = Vec::new();
+
+    for _ in 0..10 {
+        deliver(&mut client, &mut server, now, &mut datagrams);
+        deliver(&mut server, &mut client, now, &mut datagrams);
+        maybe_authenticate(&mut client);
+        if *client.state() == State::Confirmed && *server.state() == State::Confirmed {
+            break;
+        }
+    }
+    assert_eq!(*client.state(), State::Confirmed);
+    assert_eq!(client.version(), version);
+
+    // Exchange some data, so that the client gets an acknowledgment for a 1-RTT packet.
+    let stream_id = client.stream_create(StreamType::BiDi).unwrap();
+    client.stream_send(stream_id, DEFAULT_STREAM_DATA).unwrap();
+    deliver(&mut client, &mut server, now, &mut datagrams);
+    now += DEFAULT_ACK_DELAY;
+    deliver(&mut server, &mut client, now, &mut datagrams);
+
+    // Then update keys and send some more.
+    client.initiate_key_update().unwrap();
+    client.stream_send(stream_id, DEFAULT_STREAM_DATA).unwrap();
+    deliver(&mut client, &mut server, now, &mut datagrams);
+    assert_eq!(client.get_epochs().0, Some(4));
+    let mut buf = [0; 32];
+    let (len, _) = server.stream_recv(stream_id, &mut buf).unwrap();
+    assert_eq!(len, 2 * DEFAULT_STREAM_DATA.len());
+
+    let keylog = client.crypto.take_secret_log().unwrap();
+    let mut dissector = Dissector::new(KeyLog::parse(str::from_utf8(&keylog).unwrap()));
+    let packets: Vec<_> = datagrams
+        .iter()
+        .flat_map(|(t, d)| dissector.process(t.duration_since(start), d))
+        .collect();
+    assert_eq!(dissector.connections(), 1);
+    for packet in &packets {
+        assert!(packet.error().is_none(), "{packet}");
+        assert!(packet.pn().is_some(), "{packet}");
+    }
+    for pt in [
+        PacketType::Initial,
+        PacketType::Handshake,
+        PacketType::Short,
+    ] {
+        assert!(packets.iter().any(|p| p.packet_type() == pt), "{pt:?}");
+    }
+
+    // The last packet from the client is protected with the updated keys.
+    let last = packets
+        .iter()
+        .rev()
+        .find(|p| p.sender() == Role::Client)
+        .unwrap();
+    assert_eq!(last.packet_type(), PacketType::Short);
+    assert!(last.frames().iter().any(|f| f.contains("Stream")), "{last}");
+}
+
+#[test]
+fn dissect_version1() {
+    dissect(
+        &ConnectionParameters::default().versions(Version::Version1, vec![Version::Version1]),
+        Version::Version1,
+    );
+}
+
+#[test]
+fn dissect_version2() {
+    dissect(
+        &ConnectionParameters::default().versions(Version::Version2, vec![Version::Version2]),
+        Version::Version2,
+    );
+}
+
+/// The client starts with version 1 and the server upgrades to version 2.
+#[test]
+fn dissect_compatible_upgrade() {
+    dissect(&ConnectionParameters::default(), Version::Version2);
+}
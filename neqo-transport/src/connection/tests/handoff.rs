@@ -0,0 +1,174 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{cell::RefCell, rc::Rc, time::Instant};
+
+use neqo_common::Role;
+use test_fixture::{addr, now};
+
+use super::{
+    super::{Connection, ConnectionParameters, State},
+    connect_force_idle, default_client, default_server, CountingConnectionIdGenerator,
+};
+use crate::{Error, StreamId, StreamType};
+
+const REQUEST: &[u8] = b"request";
+const RESPONSE: &[u8] = b"response";
+
+fn import(state: &[u8], now: Instant) -> Result<Connection, Error> {
+    Connection::import(
+        state,
+        Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+        addr(),
+        ConnectionParameters::default(),
+        now,
+    )
+}
+
+/// Send a request from the client and a partial response from the server,
+/// then exchange acknowledgments so that both are quiescent.
+fn request_response(client: &mut Connection, server: &mut Connection, now: Instant) -> StreamId {
+    let stream_id = client.stream_create(StreamType::BiDi).unwrap();
+    client.stream_send(stream_id, REQUEST).unwrap();
+    client.stream_close_send(stream_id).unwrap();
+    let dgram = client.process_output(now).dgram();
+    server.process_input(&dgram.unwrap(), now);
+
+    let mut buf = [0; 16];
+    assert_eq!(
+        server.stream_recv(stream_id, &mut buf).unwrap(),
+        (REQUEST.len(), true)
+    );
+    server.stream_send(stream_id, RESPONSE).unwrap();
+    let dgram = server.process_output(now).dgram();
+    client.process_input(&dgram.unwrap(), now);
+    assert_eq!(
+        client.stream_recv(stream_id, &mut buf).unwrap(),
+        (RESPONSE.len(), false)
+    );
+
+    // Wait for the client to acknowledge the response.
+    let delay = client.process_output(now).callback();
+    let ack = client.process_output(now + delay).dgram();
+    server.process_input(&ack.unwrap(), now + delay);
+    stream_id
+}
+
+#[test]
+fn server_handoff() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+    let now = now();
+    let stream_id = request_response(&mut client, &mut server, now);
+
+    let state = server.export().unwrap();
+    assert!(matches!(server.state(), State::Closed(_)));
+    let mut server = import(&state, now).unwrap();
+    assert_eq!(*server.state(), State::Confirmed);
+
+    // The imported server can finish the response.
+    server.stream_send(stream_id, RESPONSE).unwrap();
+    server.stream_close_send(stream_id).unwrap();
+    let dgram = server.process_output(now).dgram();
+    client.process_input(&dgram.unwrap(), now);
+    let mut buf = [0; 16];
+    assert_eq!(
+        client.stream_recv(stream_id, &mut buf).unwrap(),
+        (RESPONSE.len(), true)
+    );
+
+    // And the client can open new streams.
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, REQUEST).unwrap();
+    let dgram = client.process_output(now).dgram();
+    server.process_input(&dgram.unwrap(), now);
+    assert_eq!(
+        server.stream_recv(stream_id, &mut buf).unwrap(),
+        (REQUEST.len(), false)
+    );
+}
+
+#[test]
+fn client_handoff() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+    let now = now();
+    let stream_id = request_response(&mut client, &mut server, now);
+
+    let state = client.export().unwrap();
+    let mut client = import(&state, now).unwrap();
+    assert_eq!(client.role(), Role::Client);
+    assert_eq!(*client.state(), State::Confirmed);
+
+    // The response can be finished, and the client can read it.
+    server.stream_send(stream_id, RESPONSE).unwrap();
+    server.stream_close_send(stream_id).unwrap();
+    let dgram = server.process_output(now).dgram();
+    client.process_input(&dgram.unwrap(), now);
+    let mut buf = [0; 16];
+    assert_eq!(
+        client.stream_recv(stream_id, &mut buf).unwrap(),
+        (RESPONSE.len(), true)
+    );
+
+    // The imported client can open new streams.
+    let stream_id = client.stream_create(StreamType::BiDi).unwrap();
+    client.stream_send(stream_id, REQUEST).unwrap();
+    let dgram = client.process_output(now).dgram();
+    server.process_input(&dgram.unwrap(), now);
+    assert_eq!(
+        server.stream_recv(stream_id, &mut buf).unwrap(),
+        (REQUEST.len(), false)
+    );
+}
+
+#[test]
+fn handoff_stream_settings() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+
+    let stream_id = server.stream_create(StreamType::BiDi).unwrap();
+    server.stream_sendorder(stream_id, Some(-3)).unwrap();
+    server.stream_keep_alive(stream_id, true).unwrap();
+    let state = server.export().unwrap();
+
+    let mut server = import(&state, now()).unwrap();
+    let stream = server.streams.get_send_stream(stream_id).unwrap();
+    assert_eq!(stream.sendorder(), Some(-3));
+    assert!(stream.is_fair());
+    assert!(server.streams.need_keep_alive());
+}
+
+#[test]
+fn export_not_quiescent() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+
+    // The stream data has not been acknowledged.
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, REQUEST).unwrap();
+    let dgram = client.process_output(now()).dgram();
+    assert!(dgram.is_some());
+    assert_eq!(client.export(), Err(Error::NotQuiescent));
+    assert_eq!(*client.state(), State::Confirmed);
+}
+
+#[test]
+fn import_truncated() {
+    let mut client = default_client();
+    let mut server = default_server();
+    connect_force_idle(&mut client, &mut server);
+
+    let state = server.export().unwrap();
+    assert_eq!(
+        import(&state[..state.len() - 1], now()).unwrap_err(),
+        Error::InvalidExport
+    );
+}
@@ -10,12 +10,13 @@ use std::{
     mem,
     net::{IpAddr, Ipv6Addr, SocketAddr},
     rc::Rc,
-    time::Duration,
+    time::{Duration, Instant},
 };
 
 use neqo_common::{event::Provider, qdebug, Datagram};
 use neqo_crypto::{
-    constants::TLS_CHACHA20_POLY1305_SHA256, generate_ech_keys, AuthenticationStatus,
+    constants::TLS_CHACHA20_POLY1305_SHA256, generate_ech_keys, load_certificate,
+    load_trust_anchors, AuthenticationStatus, CertificateCompression, CertificateVerifier,
 };
 use test_fixture::{
     self, addr, assertions, assertions::assert_coalesced_0rtt, datagram, fixture_init, now,
@@ -809,6 +810,89 @@ fn anti_amplification() {
     assert_eq!(*server.state(), State::Confirmed);
 }
 
+/// A server with a certificate that is too big to send within the amplification limit,
+/// unless it is compressed.
+fn big_certificate_server() -> Connection {
+    fixture_init();
+    load_certificate(
+        "big",
+        include_bytes!("../../../../test-fixture/pem/big-cert.pem"),
+        include_bytes!("../../../../test-fixture/pem/key.pem"),
+    )
+    .unwrap();
+    Connection::new_server(
+        &["big"],
+        test_fixture::DEFAULT_ALPN,
+        Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+        ConnectionParameters::default(),
+    )
+    .unwrap()
+}
+
+/// Deliver everything that the server can send in response to the client Initial,
+/// then report whether the client is able to authenticate the server.
+fn server_first_flight(
+    client: &mut Connection,
+    server: &mut Connection,
+    now: &mut Instant,
+) -> bool {
+    let c_init = client.process_output(*now).dgram();
+    *now += DEFAULT_RTT / 2;
+    let mut flight = server
+        .process(c_init.as_ref(), *now)
+        .dgram()
+        .into_iter()
+        .collect::<Vec<_>>();
+    loop {
+        match server.process_output(*now) {
+            Output::Datagram(d) => flight.push(d),
+            // Skip the gap for pacing, but not the wait for the client to respond.
+            Output::Callback(t) if t < DEFAULT_RTT / 2 => *now += t,
+            _ => break,
+        }
+    }
+    *now += DEFAULT_RTT / 2;
+    for d in flight {
+        client.process_input(&d, *now);
+    }
+    maybe_authenticate(client)
+}
+
+#[test]
+fn anti_amplification_compressed_certificate() {
+    // Without compression, the client has to acknowledge the first part of the server
+    // flight before it gets the rest of the certificate.
+    let mut client = default_client();
+    let mut server = big_certificate_server();
+    assert!(!server_first_flight(&mut client, &mut server, &mut now()));
+    assert!(server.stats().cert_compression.is_none());
+
+    let mut client = default_client();
+    client
+        .set_certificate_compression(&[CertificateCompression::Brotli])
+        .unwrap();
+    let mut server = big_certificate_server();
+    server
+        .set_certificate_compression(&[
+            CertificateCompression::Zlib,
+            CertificateCompression::Brotli,
+        ])
+        .unwrap();
+    let mut now = now();
+    assert!(server_first_flight(&mut client, &mut server, &mut now));
+
+    let sent = server.stats().cert_compression.unwrap();
+    assert_eq!(sent.algorithm, CertificateCompression::Brotli);
+    assert!(sent.compressed < sent.uncompressed);
+    assert_eq!(client.stats().cert_compression, Some(sent));
+
+    let fin = client.process_output(now).dgram();
+    assert_eq!(*client.state(), State::Connected);
+    now += DEFAULT_RTT / 2;
+    server.process_input(&fin.unwrap(), now);
+    assert_eq!(*server.state(), State::Confirmed);
+}
+
 #[cfg(not(feature = "fuzzing"))]
 #[test]
 fn garbage_initial() {
@@ -1135,3 +1219,64 @@ fn implicit_rtt_server() {
     // an RTT estimate from having discarded the Initial packet number space.
     assert_eq!(server.stats().rtt, RTT);
 }
+
+/// A server that uses the PEM test certificate, which is issued by the PEM test CA.
+fn pem_server() -> Connection {
+    fixture_init();
+    load_certificate(
+        "pem",
+        include_bytes!("../../../../test-fixture/pem/cert.pem"),
+        include_bytes!("../../../../test-fixture/pem/key.pem"),
+    )
+    .unwrap();
+    load_trust_anchors(include_bytes!("../../../../test-fixture/pem/ca.pem")).unwrap();
+    Connection::new_server(
+        &["pem"],
+        test_fixture::DEFAULT_ALPN,
+        Rc::new(RefCell::new(CountingConnectionIdGenerator::default())),
+        ConnectionParameters::default(),
+    )
+    .unwrap()
+}
+
+/// Run the handshake until the client reports the outcome of certificate verification.
+fn certificate_verified(client: &mut Connection, server: &mut Connection) -> AuthenticationStatus {
+    let mut dgram = client.process_output(now()).dgram();
+    for _ in 0..5 {
+        dgram = server.process(dgram.as_ref(), now()).dgram();
+        dgram = client.process(dgram.as_ref(), now()).dgram();
+        let events = client.events().collect::<Vec<_>>();
+        assert!(!events.contains(&ConnectionEvent::AuthenticationNeeded));
+        if let Some(status) = events.into_iter().find_map(|e| match e {
+            ConnectionEvent::CertificateVerified { code } => Some(AuthenticationStatus::from(code)),
+            _ => None,
+        }) {
+            return status;
+        }
+    }
+    panic!("certificate was not verified");
+}
+
+#[test]
+fn certificate_verifier() {
+    let mut server = pem_server();
+    let mut client = default_client();
+    client.set_certificate_verifier(CertificateVerifier::new());
+    assert_eq!(
+        certificate_verified(&mut client, &mut server),
+        AuthenticationStatus::Ok
+    );
+    assert!(client.state().error().is_none());
+}
+
+#[test]
+fn certificate_verifier_pin_mismatch() {
+    let mut server = pem_server();
+    let mut client = default_client();
+    client.set_certificate_verifier(CertificateVerifier::new().pin_sha256([0; 32]).unwrap());
+    assert_eq!(
+        certificate_verified(&mut client, &mut server),
+        AuthenticationStatus::CertMitm
+    );
+    assert!(client.state().error().is_some());
+}
@@ -37,10 +37,13 @@ use crate::{
 
 // All the tests.
 mod ackrate;
+mod capture;
 mod cc;
 mod close;
 mod datagram;
+mod dissect;
 mod fuzzing;
+mod handoff;
 mod handshake;
 mod idle;
 mod keys;
@@ -570,6 +573,19 @@ fn send_and_receive(
     receiver.process(Some(&dgram), now).dgram()
 }
 
+/// Send everything that `from` has to `to`, keeping a copy of each datagram.
+fn deliver(
+    from: &mut Connection,
+    to: &mut Connection,
+    now: Instant,
+    datagrams: &mut Vec<(Instant, Datagram)>,
+) {
+    while let Some(d) = from.process_output(now).dgram() {
+        to.process_input(&d, now);
+        datagrams.push((now, d));
+    }
+}
+
 fn get_tokens(client: &mut Connection) -> Vec<ResumptionToken> {
     client
         .events()
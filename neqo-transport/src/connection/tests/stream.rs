@@ -311,6 +311,70 @@ fn ordergroup_7() {
     fairness_test(source, 5, 3, &result);
 }
 
+#[test]
+// With many open streams, only those with something to send are scheduled,
+// and sendorder and fairness still decide which is sent first.
+fn many_idle_streams() {
+    const STREAMS: usize = 10_000;
+
+    let mut client = default_client();
+    let mut server =
+        new_server(ConnectionParameters::default().max_streams(StreamType::UniDi, STREAMS as u64));
+    connect_force_idle(&mut client, &mut server);
+
+    let streams = (0..STREAMS)
+        .map(|_| client.stream_create(StreamType::UniDi).unwrap())
+        .collect::<Vec<_>>();
+    for (i, stream_id) in streams.iter().enumerate() {
+        client.streams.set_fairness(*stream_id, true).unwrap();
+        let sendorder = SendOrder::try_from(i % 10).unwrap();
+        client
+            .streams
+            .set_sendorder(*stream_id, Some(sendorder))
+            .unwrap();
+    }
+    client.streams.set_fairness(streams[123], false).unwrap();
+    assert_eq!(client.streams.send.ready_count(), 0);
+
+    // Unfair streams go first, then those with the highest sendorder.
+    let expected = [
+        streams[123],
+        streams[9999],
+        streams[17],
+        streams[4242],
+        streams[5000],
+    ];
+    for stream_id in [
+        streams[5000],
+        streams[17],
+        streams[4242],
+        streams[123],
+        streams[9999],
+    ]
+    .iter()
+    {
+        client.stream_send(*stream_id, &[6; 100]).unwrap();
+    }
+    assert_eq!(client.streams.send.ready_count(), expected.len());
+
+    let mut out = client.process_output(now());
+    while let Some(d) = out.dgram() {
+        mem::drop(server.process(Some(&d), now()));
+        out = client.process_output(now());
+    }
+    // Streams that have sent everything are no longer scheduled.
+    assert_eq!(client.streams.send.ready_count(), 0);
+
+    let readable = server
+        .events()
+        .filter_map(|evt| match evt {
+            ConnectionEvent::RecvStreamReadable { stream_id } => Some(stream_id),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(readable, expected);
+}
+
 #[test]
 // Send fin even if a peer closes a reomte bidi send stream before sending any data.
 fn report_fin_when_stream_closed_wo_data() {
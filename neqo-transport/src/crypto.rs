@@ -15,10 +15,11 @@ use std::{
     time::Instant,
 };
 
-use neqo_common::{hex, hex_snip_middle, qdebug, qinfo, qtrace, Encoder, Role};
+use neqo_common::{hex, hex_snip_middle, qdebug, qinfo, qtrace, qwarn, Decoder, Encoder, Role};
 use neqo_crypto::{
-    hkdf, hp::HpKey, Aead, Agent, AntiReplay, Cipher, Epoch, Error as CryptoError, HandshakeState,
-    PrivateKey, PublicKey, Record, RecordList, ResumptionToken, SymKey, ZeroRttChecker,
+    hkdf, hp::HpKey, Aead, Agent, AntiReplay, CertificateVerifier, Cipher,
+    ClientCertificateSelector, Epoch, Error as CryptoError, HandshakeState, PrivateKey, PublicKey,
+    Record, RecordList, ResumptionToken, ServerNameSelector, SymKey, ZeroRttChecker,
     TLS_AES_128_GCM_SHA256, TLS_AES_256_GCM_SHA384, TLS_CHACHA20_POLY1305_SHA256, TLS_CT_HANDSHAKE,
     TLS_EPOCH_APPLICATION_DATA, TLS_EPOCH_HANDSHAKE, TLS_EPOCH_INITIAL, TLS_EPOCH_ZERO_RTT,
     TLS_GRP_EC_SECP256R1, TLS_GRP_EC_SECP384R1, TLS_GRP_EC_SECP521R1, TLS_GRP_EC_X25519,
@@ -39,6 +40,11 @@ use crate::{
 };
 
 const MAX_AUTH_TAG: usize = 32;
+/// The handshake message type of a `ClientHello`.
+const TLS_HS_CLIENT_HELLO: u8 = 1;
+/// Where the random value is in a `ClientHello`, after the message type, length,
+/// and legacy version.
+const CLIENT_HELLO_RANDOM: Range<usize> = 6..38;
 /// The number of invocations remaining on a write cipher before we try
 /// to update keys.  This has to be much smaller than the number returned
 /// by `CryptoDxState::limit` or updates will happen too often.  As we don't
@@ -60,6 +66,14 @@ pub struct Crypto {
     pub(crate) tls: Agent,
     pub(crate) streams: CryptoStreams,
     pub(crate) states: CryptoStates,
+    /// The random value from the `ClientHello`, which identifies secrets in a key log.
+    client_random: Option<Vec<u8>>,
+    /// If enabled, the secrets that were installed since `take_secret_log` was last
+    /// called, in the format of SSLKEYLOGFILE.
+    secret_log: Option<Vec<u8>>,
+    /// Set when keys were imported from an exported connection.  The TLS agent
+    /// never completed a handshake in that case, so it can't process messages.
+    imported: bool,
 }
 
 type TpHandler = Rc<RefCell<TransportParametersHandler>>;
@@ -106,9 +120,61 @@ impl Crypto {
                 fuzzing,
                 ..Default::default()
             },
+            client_random: None,
+            secret_log: None,
+            imported: false,
         })
     }
 
+    fn role(&self) -> Role {
+        match self.tls {
+            Agent::Client(_) => Role::Client,
+            Agent::Server(_) => Role::Server,
+        }
+    }
+
+    /// Start keeping a log of TLS secrets, for `take_secret_log`.
+    pub fn enable_secret_log(&mut self) {
+        self.secret_log.get_or_insert_with(Vec::new);
+    }
+
+    /// Take the TLS secrets that have been installed since the last call, if any, as
+    /// lines in the format of SSLKEYLOGFILE.
+    pub fn take_secret_log(&mut self) -> Option<Vec<u8>> {
+        self.secret_log
+            .as_mut()
+            .filter(|log| !log.is_empty())
+            .map(mem::take)
+    }
+
+    fn note_client_hello(&mut self, epoch: Epoch, data: &[u8]) {
+        if self.client_random.is_none()
+            && epoch == TLS_EPOCH_INITIAL
+            && data.first() == Some(&TLS_HS_CLIENT_HELLO)
+        {
+            self.client_random = data.get(CLIENT_HELLO_RANDOM).map(<[u8]>::to_vec);
+        }
+    }
+
+    /// Add a secret to the key log.  `writer` is the endpoint that protects packets
+    /// with the secret.
+    fn log_secret(&mut self, writer: Role, label: &str, secret: &SymKey) {
+        let (Some(log), Some(random)) = (self.secret_log.as_mut(), &self.client_random) else {
+            return;
+        };
+        let Ok(secret) = secret.as_bytes() else {
+            qwarn!("Unable to log {} secret", label);
+            return;
+        };
+        let prefix = match writer {
+            Role::Client => "CLIENT",
+            Role::Server => "SERVER",
+        };
+        log.extend_from_slice(
+            format!("{}_{} {} {}\n", prefix, label, hex(random), hex(secret)).as_bytes(),
+        );
+    }
+
     /// Get the name of the server.  (Only works for the client currently).
     pub fn server_name(&self) -> Option<&str> {
         if let Agent::Client(c) = &self.tls {
@@ -155,6 +221,49 @@ impl Crypto {
         }
     }
 
+    pub fn server_enable_sni(&mut self, selector: Rc<dyn ServerNameSelector>) -> Res<()> {
+        if let Agent::Server(s) = &mut self.tls {
+            s.set_server_name_selector(selector)?;
+            Ok(())
+        } else {
+            panic!("not a server");
+        }
+    }
+
+    pub fn server_request_client_certificate(
+        &mut self,
+        verifier: CertificateVerifier,
+        required: bool,
+    ) -> Res<()> {
+        if let Agent::Server(s) = &mut self.tls {
+            s.request_client_certificate(verifier, required)?;
+            Ok(())
+        } else {
+            panic!("not a server");
+        }
+    }
+
+    pub fn client_set_certificate_selector(
+        &mut self,
+        selector: Rc<dyn ClientCertificateSelector>,
+    ) -> Res<()> {
+        if let Agent::Client(c) = &mut self.tls {
+            c.set_client_certificate_selector(selector)?;
+            Ok(())
+        } else {
+            panic!("not a client");
+        }
+    }
+
+    /// The server name that the client indicated, if the server selects certificates by name.
+    pub fn server_name_indication(&self) -> Option<&str> {
+        if let Agent::Server(s) = &self.tls {
+            s.server_name_indication()
+        } else {
+            None
+        }
+    }
+
     pub fn client_enable_ech(&mut self, ech_config_list: impl AsRef<[u8]>) -> Res<()> {
         if let Agent::Client(c) = &mut self.tls {
             c.enable_ech(ech_config_list)?;
@@ -189,6 +298,9 @@ impl Crypto {
                 data: d.to_vec(),
             }
         });
+        if let Some(r) = &input {
+            self.note_client_hello(r.epoch, &r.data);
+        }
 
         match self.tls.handshake_raw(now, input) {
             Ok(output) => {
@@ -226,6 +338,7 @@ impl Crypto {
             ),
         };
         let secret = secret.ok_or(Error::InternalError(1))?;
+        self.log_secret(Role::Client, "EARLY_TRAFFIC_SECRET", &secret);
         self.states
             .set_0rtt_keys(version, dir, &secret, cipher.unwrap());
         Ok(true)
@@ -265,6 +378,9 @@ impl Crypto {
             Some(info) => Some(info.cipher_suite()),
         }
         .ok_or(Error::InternalError(3))?;
+        let role = self.role();
+        self.log_secret(role, "HANDSHAKE_TRAFFIC_SECRET", &write_secret);
+        self.log_secret(role.remote(), "HANDSHAKE_TRAFFIC_SECRET", &read_secret);
         self.states
             .set_handshake_keys(self.version, &write_secret, &read_secret, cipher);
         qdebug!([self], "Handshake keys installed");
@@ -274,6 +390,7 @@ impl Crypto {
     fn maybe_install_application_write_key(&mut self, version: Version) -> Res<()> {
         qtrace!([self], "Attempt to install application write key");
         if let Some(secret) = self.tls.write_secret(TLS_EPOCH_APPLICATION_DATA) {
+            self.log_secret(self.role(), "TRAFFIC_SECRET_0", &secret);
             self.states.set_application_write_key(version, secret)?;
             qdebug!([self], "Application write key installed");
         }
@@ -289,6 +406,7 @@ impl Crypto {
             .tls
             .read_secret(TLS_EPOCH_APPLICATION_DATA)
             .ok_or(Error::InternalError(4))?;
+        self.log_secret(self.role().remote(), "TRAFFIC_SECRET_0", &read_secret);
         self.states
             .set_application_read_key(version, read_secret, expire_0rtt)?;
         qdebug!([self], "application read keys installed");
@@ -302,6 +420,7 @@ impl Crypto {
                 return Err(Error::ProtocolViolation);
             }
             qtrace!([self], "Adding CRYPTO data {:?}", r);
+            self.note_client_hello(r.epoch, &r.data);
             self.streams.send(PacketNumberSpace::from(r.epoch), &r.data);
         }
         Ok(())
@@ -350,6 +469,43 @@ impl Crypto {
         self.states.discard(space)
     }
 
+    /// Whether the state was imported, see `import`.
+    pub fn imported(&self) -> bool {
+        self.imported
+    }
+
+    /// Write out the application data keys and the offsets of the application
+    /// data crypto stream.  This fails if the handshake isn't complete or
+    /// there are crypto stream frames outstanding.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        let CryptoStreams::ApplicationData { application } = &self.streams else {
+            return Err(Error::NotQuiescent);
+        };
+        if !application.tx.is_empty() || application.rx.received() != application.rx.retired() {
+            return Err(Error::NotQuiescent);
+        }
+        enc.encode_varint(application.tx.retired());
+        enc.encode_varint(application.rx.retired());
+        self.states.export(enc)
+    }
+
+    /// Restore the state that was written by `export`.  After this, any
+    /// post-handshake messages from the peer are discarded.
+    pub fn import(&mut self, version: Version, dec: &mut Decoder) -> Res<()> {
+        let sent = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let received = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        self.states.import(version, dec)?;
+        self.version = version;
+        self.streams = CryptoStreams::ApplicationData {
+            application: CryptoStream {
+                tx: TxBuffer::with_acked(sent),
+                rx: RxStreamOrderer::with_retired(received),
+            },
+        };
+        self.imported = true;
+        Ok(())
+    }
+
     pub fn create_resumption_token(
         &mut self,
         new_token: Option<&[u8]>,
@@ -758,6 +914,8 @@ impl IndexMut<CryptoDxDirection> for CryptoState {
 pub(crate) struct CryptoDxAppData {
     dx: CryptoDxState,
     cipher: Cipher,
+    // The secret used to create `self.dx`, which is only kept so that it can be exported.
+    secret: SymKey,
     // Not the secret used to create `self.dx`, but the one needed for the next iteration.
     next_secret: SymKey,
     fuzzing: bool,
@@ -782,6 +940,7 @@ impl CryptoDxAppData {
             ),
             cipher,
             next_secret: Self::update_secret(cipher, &secret)?,
+            secret,
             fuzzing,
         })
     }
@@ -800,6 +959,7 @@ impl CryptoDxAppData {
         Ok(Self {
             dx: self.dx.next(&self.next_secret, self.cipher),
             cipher: self.cipher,
+            secret: self.next_secret.clone(),
             next_secret,
             fuzzing: self.fuzzing,
         })
@@ -808,6 +968,68 @@ impl CryptoDxAppData {
     pub fn epoch(&self) -> usize {
         self.dx.epoch
     }
+
+    /// Write out the keys and the packet number state.
+    fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(u64::try_from(self.dx.epoch)?);
+        enc.encode_vvec(self.secret.as_bytes()?);
+        enc.encode_vvec(self.dx.hpkey.key_data()?);
+        enc.encode_varint(self.dx.used_pn.start);
+        enc.encode_varint(self.dx.used_pn.end);
+        enc.encode_varint(self.dx.min_pn);
+        // This can be `PacketNumber::MAX`, which doesn't fit in a varint.
+        enc.encode_uint(8, self.dx.invocations);
+        Ok(())
+    }
+
+    /// Restore keys that were written by `export`.
+    fn import(
+        version: Version,
+        direction: CryptoDxDirection,
+        cipher: Cipher,
+        fuzzing: bool,
+        dec: &mut Decoder,
+    ) -> Res<Self> {
+        let epoch = usize::try_from(dec.decode_varint().ok_or(Error::InvalidExport)?)?;
+        if epoch < usize::from(TLS_EPOCH_APPLICATION_DATA) {
+            return Err(Error::InvalidExport);
+        }
+        let secret = hkdf::import_key(
+            TLS_VERSION_1_3,
+            dec.decode_vvec().ok_or(Error::InvalidExport)?,
+        )?;
+        let hpkey = HpKey::import(cipher, dec.decode_vvec().ok_or(Error::InvalidExport)?)?;
+        let start = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let end = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let min_pn = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let invocations = dec.decode_uint(8).ok_or(Error::InvalidExport)?;
+        if start > end || min_pn > end {
+            return Err(Error::InvalidExport);
+        }
+        Ok(Self {
+            dx: CryptoDxState {
+                version,
+                direction,
+                epoch,
+                aead: Aead::new(
+                    fuzzing,
+                    TLS_VERSION_1_3,
+                    cipher,
+                    &secret,
+                    version.label_prefix(),
+                )?,
+                hpkey,
+                used_pn: start..end,
+                min_pn,
+                invocations,
+                fuzzing,
+            },
+            cipher,
+            next_secret: Self::update_secret(cipher, &secret)?,
+            secret,
+            fuzzing,
+        })
+    }
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
@@ -1286,6 +1508,52 @@ impl CryptoStates {
         Ok(())
     }
 
+    /// Write out the application data keys.  This fails if the handshake
+    /// isn't done or a key update is in progress.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        let (Some(write), Some(read)) = (&self.app_write, &self.app_read) else {
+            return Err(Error::NotQuiescent);
+        };
+        if self.read_update_time.is_some()
+            || self.zero_rtt.is_some()
+            || write.epoch() != read.epoch()
+        {
+            return Err(Error::NotQuiescent);
+        }
+        enc.encode_uint(2, self.cipher);
+        write.export(enc)?;
+        read.export(enc)
+    }
+
+    /// Install the application data keys that were written by `export`,
+    /// discarding all other keys.
+    pub fn import(&mut self, version: Version, dec: &mut Decoder) -> Res<()> {
+        let cipher = Cipher::try_from(dec.decode_uint(2).ok_or(Error::InvalidExport)?)?;
+        if !matches!(
+            cipher,
+            TLS_AES_128_GCM_SHA256 | TLS_AES_256_GCM_SHA384 | TLS_CHACHA20_POLY1305_SHA256
+        ) {
+            return Err(Error::InvalidExport);
+        }
+        let write =
+            CryptoDxAppData::import(version, CryptoDxDirection::Write, cipher, self.fuzzing, dec)?;
+        let read =
+            CryptoDxAppData::import(version, CryptoDxDirection::Read, cipher, self.fuzzing, dec)?;
+        if write.epoch() != read.epoch() {
+            return Err(Error::InvalidExport);
+        }
+
+        self.initials.clear();
+        self.handshake = None;
+        self.zero_rtt = None;
+        self.cipher = cipher;
+        self.app_write = Some(write);
+        self.app_read_next = Some(read.next()?);
+        self.app_read = Some(read);
+        self.read_update_time = None;
+        Ok(())
+    }
+
     /// Make some state for removing protection in tests.
     #[cfg(not(feature = "fuzzing"))]
     #[cfg(test)]
@@ -1299,6 +1567,7 @@ impl CryptoStates {
         let app_read = |epoch| CryptoDxAppData {
             dx: read(epoch),
             cipher: TLS_AES_128_GCM_SHA256,
+            secret: hkdf::import_key(TLS_VERSION_1_3, &[0xaa; 32]).unwrap(),
             next_secret: hkdf::import_key(TLS_VERSION_1_3, &[0xaa; 32]).unwrap(),
             fuzzing: false,
         };
@@ -1358,6 +1627,7 @@ impl CryptoStates {
                 fuzzing: false,
             },
             cipher: TLS_CHACHA20_POLY1305_SHA256,
+            secret: secret.clone(),
             next_secret: secret.clone(),
             fuzzing: false,
         };
@@ -0,0 +1,630 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+// Decryption of captured QUIC packets, using the secrets that NSS writes to
+// the file named by SSLKEYLOGFILE.
+
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    fmt::{self, Display},
+    fs, io,
+    net::SocketAddr,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{hex, qdebug, qinfo, qlog::NeqoQlog, Datagram, Decoder, Role};
+use neqo_crypto::{hkdf, Cipher, SymKey, TLS_VERSION_1_3};
+
+use crate::{
+    cid::{ConnectionId, EmptyConnectionIdGenerator, RandomConnectionIdGenerator},
+    crypto::{CryptoDxDirection, CryptoStates},
+    frame::Frame,
+    packet::{PacketNumber, PacketType, PublicPacket},
+    qlog,
+    recv_stream::RxStreamOrderer,
+    version::Version,
+    Error, Res,
+};
+
+const CLIENT_EARLY_TRAFFIC_SECRET: &str = "CLIENT_EARLY_TRAFFIC_SECRET";
+const CLIENT_HANDSHAKE_TRAFFIC_SECRET: &str = "CLIENT_HANDSHAKE_TRAFFIC_SECRET";
+const SERVER_HANDSHAKE_TRAFFIC_SECRET: &str = "SERVER_HANDSHAKE_TRAFFIC_SECRET";
+const CLIENT_TRAFFIC_SECRET_0: &str = "CLIENT_TRAFFIC_SECRET_0";
+const SERVER_TRAFFIC_SECRET_0: &str = "SERVER_TRAFFIC_SECRET_0";
+
+/// How long old read keys are kept after a key update, and 0-RTT keys are kept
+/// after 1-RTT keys are available, so that reordered packets can be read.
+/// A `Connection` uses a multiple of the PTO, which isn't known here.
+const READ_KEY_RETENTION: Duration = Duration::from_secs(1);
+
+/// The offset of the random value in a `ClientHello` or `ServerHello` message,
+/// after the message type, length, and legacy version.
+const HELLO_RANDOM_OFFSET: usize = 6;
+const HELLO_RANDOM_LEN: usize = 32;
+
+fn from_hex(s: &str) -> Option<Vec<u8>> {
+    if s.len() % 2 != 0 {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// The TLS secrets from a key log file, in the format that NSS writes to the file
+/// named by the SSLKEYLOGFILE environment variable.
+#[derive(Debug, Default)]
+pub struct KeyLog {
+    /// Secrets, keyed by label and the random value from the `ClientHello`.
+    secrets: HashMap<(String, Vec<u8>), Vec<u8>>,
+}
+
+impl KeyLog {
+    /// Parse a key log.  Comments and lines that can't be parsed are ignored.
+    #[must_use]
+    pub fn parse(text: &str) -> Self {
+        let mut secrets = HashMap::new();
+        for line in text.lines().map(str::trim) {
+            if line.starts_with('#') {
+                continue;
+            }
+            let mut fields = line.split_whitespace();
+            let (Some(label), Some(random), Some(secret), None) =
+                (fields.next(), fields.next(), fields.next(), fields.next())
+            else {
+                continue;
+            };
+            if let (Some(random), Some(secret)) = (from_hex(random), from_hex(secret)) {
+                secrets.insert((label.to_string(), random), secret);
+            }
+        }
+        Self { secrets }
+    }
+
+    /// Read a key log from `path`.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read.
+    pub fn load(path: impl AsRef<Path>) -> io::Result<Self> {
+        Ok(Self::parse(&fs::read_to_string(path)?))
+    }
+
+    /// The number of secrets in the log.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.secrets.len()
+    }
+
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.secrets.is_empty()
+    }
+
+    fn secret(&self, label: &str, client_random: &[u8]) -> Option<SymKey> {
+        let secret = self
+            .secrets
+            .get(&(label.to_string(), client_random.to_vec()))?;
+        hkdf::import_key(TLS_VERSION_1_3, secret).ok()
+    }
+}
+
+/// The TLS handshake messages that are needed to find the secrets for a connection.
+#[derive(Debug, Default)]
+struct HelloReader {
+    stream: RxStreamOrderer,
+    buf: Vec<u8>,
+}
+
+impl HelloReader {
+    /// Add CRYPTO frame data and return the start of the handshake message that
+    /// has been received so far.
+    fn add(&mut self, offset: u64, data: &[u8]) -> &[u8] {
+        self.stream.inbound_frame(offset, data);
+        self.stream.read_to_end(&mut self.buf);
+        &self.buf
+    }
+}
+
+/// A connection in the capture, identified by the addresses of the client and server.
+struct Conn {
+    index: usize,
+    client: SocketAddr,
+    server: SocketAddr,
+    /// The connection ID that the client first chose for the server, which might be
+    /// replaced by a Retry.
+    odcid: ConnectionId,
+    /// The length of the connection IDs that the client and server chose, which is
+    /// needed to find the end of the short header.
+    client_cid_len: usize,
+    server_cid_len: usize,
+    /// The version that the client started with, used for 0-RTT.
+    client_version: Version,
+    /// The version that the server chose, used for Handshake and 1-RTT keys.
+    version: Version,
+    /// Keys for packets sent by the client.  This is the view that the server has.
+    from_client: CryptoStates,
+    /// Keys for packets sent by the server.  This is the view that the client has.
+    from_server: CryptoStates,
+    client_hello: HelloReader,
+    server_hello: HelloReader,
+    client_random: Option<Vec<u8>>,
+    cipher: Option<Cipher>,
+    /// 0-RTT packets that arrived before the cipher suite was known.
+    early: Vec<(Duration, Vec<u8>)>,
+    qlog: NeqoQlog,
+}
+
+impl Conn {
+    fn new(index: usize, client: SocketAddr, server: SocketAddr, odcid: ConnectionId) -> Self {
+        let mut c = Self {
+            index,
+            client,
+            server,
+            odcid,
+            client_cid_len: 0,
+            server_cid_len: 0,
+            client_version: Version::default(),
+            version: Version::default(),
+            from_client: CryptoStates::default(),
+            from_server: CryptoStates::default(),
+            client_hello: HelloReader::default(),
+            server_hello: HelloReader::default(),
+            client_random: None,
+            cipher: None,
+            early: Vec::new(),
+            qlog: NeqoQlog::disabled(),
+        };
+        c.init_initial_keys();
+        c
+    }
+
+    /// Create Initial keys for every version, because the server can pick a compatible
+    /// version that is different to the one that the client started with.
+    fn init_initial_keys(&mut self) {
+        let versions = Version::all();
+        self.from_client
+            .init(&versions, Role::Server, self.odcid.as_ref());
+        self.from_server
+            .init(&versions, Role::Client, self.odcid.as_ref());
+    }
+
+    fn crypto(&mut self, sender: Role) -> &mut CryptoStates {
+        match sender {
+            Role::Client => &mut self.from_client,
+            Role::Server => &mut self.from_server,
+        }
+    }
+
+    /// Look at the start of the TLS handshake for the client random and cipher suite.
+    /// Returns true if this learned enough to install keys.
+    fn crypto_frame(&mut self, sender: Role, offset: u64, data: &[u8]) -> bool {
+        let had_keys = self.client_random.is_some() && self.cipher.is_some();
+        match sender {
+            Role::Client if self.client_random.is_none() => {
+                let hello = self.client_hello.add(offset, data);
+                if hello.first() == Some(&1) {
+                    self.client_random = hello
+                        .get(HELLO_RANDOM_OFFSET..HELLO_RANDOM_OFFSET + HELLO_RANDOM_LEN)
+                        .map(<[u8]>::to_vec);
+                }
+            }
+            Role::Server if self.cipher.is_none() => {
+                let hello = self.server_hello.add(offset, data);
+                if hello.first() == Some(&2) {
+                    // The cipher suite follows the legacy session ID.
+                    let session_id = HELLO_RANDOM_OFFSET + HELLO_RANDOM_LEN;
+                    self.cipher = hello.get(session_id).and_then(|&len| {
+                        let mut dec =
+                            Decoder::from(hello.get(session_id + 1 + usize::from(len)..)?);
+                        dec.decode_uint(2).and_then(|c| Cipher::try_from(c).ok())
+                    });
+                }
+            }
+            _ => {}
+        }
+        !had_keys && self.client_random.is_some() && self.cipher.is_some()
+    }
+
+    /// Install the keys for this connection from the key log.
+    fn install_keys(&mut self, keylog: &KeyLog, now: Instant) -> Res<()> {
+        let (Some(random), Some(cipher)) = (self.client_random.as_deref(), self.cipher) else {
+            return Ok(());
+        };
+        let secret = |label| keylog.secret(label, random);
+        qinfo!(
+            "dissect: connection {} client random {} cipher {:04x}",
+            self.index,
+            hex(random),
+            cipher
+        );
+
+        if let Some(early) = secret(CLIENT_EARLY_TRAFFIC_SECRET) {
+            self.from_client.set_0rtt_keys(
+                self.client_version,
+                CryptoDxDirection::Read,
+                &early,
+                cipher,
+            );
+        }
+        let (Some(client_hs), Some(server_hs)) = (
+            secret(CLIENT_HANDSHAKE_TRAFFIC_SECRET),
+            secret(SERVER_HANDSHAKE_TRAFFIC_SECRET),
+        ) else {
+            qinfo!(
+                "dissect: no handshake secrets for connection {}",
+                self.index
+            );
+            return Ok(());
+        };
+        self.from_client
+            .set_handshake_keys(self.version, &server_hs, &client_hs, cipher);
+        self.from_server
+            .set_handshake_keys(self.version, &client_hs, &server_hs, cipher);
+
+        let (Some(client_app), Some(server_app)) = (
+            secret(CLIENT_TRAFFIC_SECRET_0),
+            secret(SERVER_TRAFFIC_SECRET_0),
+        ) else {
+            qinfo!("dissect: no 1-RTT secrets for connection {}", self.index);
+            return Ok(());
+        };
+        let expire = now + READ_KEY_RETENTION;
+        self.from_client
+            .set_application_write_key(self.version, server_app.clone())?;
+        self.from_client
+            .set_application_read_key(self.version, client_app.clone(), expire)?;
+        self.from_server
+            .set_application_write_key(self.version, client_app)?;
+        self.from_server
+            .set_application_read_key(self.version, server_app, expire)?;
+        Ok(())
+    }
+}
+
+/// A packet from a capture, with its frames if it could be decrypted.
+#[derive(Debug)]
+pub struct DissectedPacket {
+    time: Duration,
+    connection: usize,
+    sender: Role,
+    packet_type: PacketType,
+    len: usize,
+    pn: Option<PacketNumber>,
+    frames: Vec<String>,
+    error: Option<Error>,
+}
+
+impl DissectedPacket {
+    /// The time since the first packet in the capture.
+    #[must_use]
+    pub fn time(&self) -> Duration {
+        self.time
+    }
+
+    /// The connection, numbered in order of the first packet from each.
+    #[must_use]
+    pub fn connection(&self) -> usize {
+        self.connection
+    }
+
+    #[must_use]
+    pub fn sender(&self) -> Role {
+        self.sender
+    }
+
+    #[must_use]
+    pub fn packet_type(&self) -> PacketType {
+        self.packet_type
+    }
+
+    #[must_use]
+    pub fn pn(&self) -> Option<PacketNumber> {
+        self.pn
+    }
+
+    /// A description of each frame in the packet.
+    #[must_use]
+    pub fn frames(&self) -> &[String] {
+        &self.frames
+    }
+
+    /// Why the packet couldn't be decrypted, if it should have been.
+    #[must_use]
+    pub fn error(&self) -> Option<&Error> {
+        self.error.as_ref()
+    }
+}
+
+impl Display for DissectedPacket {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{:.6} conn={} {} {:?} len={}",
+            self.time.as_secs_f64(),
+            self.connection,
+            self.sender,
+            self.packet_type,
+            self.len
+        )?;
+        if let Some(pn) = self.pn {
+            write!(f, " pn={pn}")?;
+        }
+        if let Some(e) = &self.error {
+            write!(f, " error={e:?}")?;
+        }
+        for frame in &self.frames {
+            write!(f, "\n  {frame}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Decrypts the QUIC packets in a capture.
+///
+/// Connections are identified by the addresses of the client and server, so a
+/// connection that migrates appears as a new connection, which can't be decrypted.
+/// Initial keys come from the first Initial packet from the client.  The `ClientHello`
+/// and `ServerHello` in those packets provide the client random value, which finds the
+/// other secrets in the `KeyLog`, and the cipher suite.
+pub struct Dissector {
+    keylog: KeyLog,
+    conns: Vec<Conn>,
+    /// The capture time of the first datagram.
+    start: Option<Duration>,
+    /// The crypto code uses `Instant` to manage keys; this maps capture time onto that.
+    base: Instant,
+    new_qlog: Option<Box<dyn FnMut(&ConnectionId) -> NeqoQlog>>,
+}
+
+impl Dissector {
+    #[must_use]
+    pub fn new(keylog: KeyLog) -> Self {
+        Self {
+            keylog,
+            conns: Vec::new(),
+            start: None,
+            base: Instant::now(),
+            new_qlog: None,
+        }
+    }
+
+    /// Log decrypted packets to qlog.  `f` is called for each new connection with the
+    /// connection ID from the first Initial packet of the client.
+    pub fn set_qlog(&mut self, f: impl FnMut(&ConnectionId) -> NeqoQlog + 'static) {
+        self.new_qlog = Some(Box::new(f));
+    }
+
+    /// The number of connections seen so far.
+    #[must_use]
+    pub fn connections(&self) -> usize {
+        self.conns.len()
+    }
+
+    /// Find the connection for a datagram, or create one for a client Initial.
+    fn find_conn(&mut self, d: &Datagram) -> Option<(usize, Role)> {
+        let (src, dst) = (d.source(), d.destination());
+        if let Some(i) = self
+            .conns
+            .iter()
+            .position(|c| c.client == src && c.server == dst)
+        {
+            return Some((i, Role::Client));
+        }
+        if let Some(i) = self
+            .conns
+            .iter()
+            .position(|c| c.client == dst && c.server == src)
+        {
+            return Some((i, Role::Server));
+        }
+
+        let (packet, _) =
+            PublicPacket::decode(&d[..], &EmptyConnectionIdGenerator::default()).ok()?;
+        if packet.packet_type() != PacketType::Initial {
+            return None;
+        }
+        let index = self.conns.len();
+        let odcid = ConnectionId::from(packet.dcid());
+        qinfo!(
+            "dissect: connection {} from {} to {} odcid {}",
+            index,
+            src,
+            dst,
+            odcid
+        );
+        let mut conn = Conn::new(index, src, dst, odcid);
+        if let Some(f) = self.new_qlog.as_mut() {
+            conn.qlog = f(&conn.odcid);
+        }
+        self.conns.push(conn);
+        Some((index, Role::Client))
+    }
+
+    /// Decrypt the QUIC packets in a datagram that was captured at `time`.
+    /// Datagrams that don't belong to a known connection and don't start with an
+    /// Initial packet are ignored.
+    pub fn process(&mut self, time: Duration, d: &Datagram) -> Vec<DissectedPacket> {
+        let start = *self.start.get_or_insert(time);
+        let time = time.saturating_sub(start);
+        let Some((index, sender)) = self.find_conn(d) else {
+            qdebug!("dissect: ignoring datagram from {}", d.source());
+            return Vec::new();
+        };
+
+        let mut out = Vec::new();
+        let mut slc = &d[..];
+        while !slc.is_empty() {
+            match self.packet(index, sender, time, slc, &mut out) {
+                Ok(remainder) => slc = remainder,
+                Err(e) => {
+                    qdebug!(
+                        "dissect: undecodable packet in connection {}: {:?}",
+                        index,
+                        e
+                    );
+                    break;
+                }
+            }
+        }
+        out
+    }
+
+    /// Process one packet, adding the result to `out` and returning the rest of the datagram.
+    fn packet<'a>(
+        &mut self,
+        index: usize,
+        sender: Role,
+        time: Duration,
+        data: &'a [u8],
+        out: &mut Vec<DissectedPacket>,
+    ) -> Res<&'a [u8]> {
+        let now = self.base + time;
+        let conn = &mut self.conns[index];
+        let cid_len = match sender {
+            Role::Client => conn.server_cid_len,
+            Role::Server => conn.client_cid_len,
+        };
+        let (packet, remainder) =
+            PublicPacket::decode(data, &RandomConnectionIdGenerator::new(cid_len))?;
+        let packet_data = &data[..data.len() - remainder.len()];
+        let mut dissected = DissectedPacket {
+            time,
+            connection: index,
+            sender,
+            packet_type: packet.packet_type(),
+            len: packet.len(),
+            pn: None,
+            frames: Vec::new(),
+            error: None,
+        };
+
+        match packet.packet_type() {
+            PacketType::VersionNegotiation => {
+                dissected.frames = packet
+                    .supported_versions()?
+                    .iter()
+                    .map(|v| format!("Version {v:08x}"))
+                    .collect();
+                out.push(dissected);
+                return Ok(remainder);
+            }
+            PacketType::Retry => {
+                // The client uses the connection ID from the Retry for new Initial keys.
+                if sender == Role::Server {
+                    conn.odcid = ConnectionId::from(packet.scid());
+                    conn.init_initial_keys();
+                }
+                out.push(dissected);
+                return Ok(remainder);
+            }
+            PacketType::OtherVersion => {
+                dissected.error = Some(Error::VersionNegotiation);
+                out.push(dissected);
+                return Ok(remainder);
+            }
+            PacketType::ZeroRtt if conn.cipher.is_none() => {
+                // Hold this until the cipher suite is known.
+                conn.early.push((time, packet_data.to_vec()));
+                return Ok(remainder);
+            }
+            PacketType::Short => {}
+            _ => {
+                let version = packet.version().unwrap_or_default();
+                match sender {
+                    Role::Client => {
+                        conn.client_cid_len = packet.scid().len();
+                        if conn.client_random.is_none() {
+                            conn.client_version = version;
+                        }
+                    }
+                    Role::Server => {
+                        conn.server_cid_len = packet.scid().len();
+                        conn.version = version;
+                    }
+                }
+            }
+        }
+
+        let crypto = conn.crypto(sender);
+        crypto.check_key_update(now)?;
+        let decrypted = match packet.decrypt(crypto, now + READ_KEY_RETENTION) {
+            Ok(d) => d,
+            Err(e) => {
+                dissected.error = Some(e);
+                out.push(dissected);
+                return Ok(remainder);
+            }
+        };
+        dissected.pn = Some(decrypted.pn());
+        qlog::packet_dissected(
+            &mut conn.qlog,
+            time,
+            sender == Role::Client,
+            &packet,
+            &decrypted,
+        );
+
+        let mut install = false;
+        let mut dec = Decoder::from(&decrypted[..]);
+        while dec.remaining() > 0 {
+            let Ok(f) = Frame::decode(&mut dec) else {
+                dissected.frames.push(String::from("[broken]"));
+                break;
+            };
+            if let (PacketType::Initial, Frame::Crypto { offset, data }) =
+                (packet.packet_type(), &f)
+            {
+                install |= conn.crypto_frame(sender, *offset, data);
+            }
+            if let Some(s) = f.dump() {
+                dissected.frames.push(s);
+            }
+        }
+        out.push(dissected);
+
+        if install {
+            conn.install_keys(&self.keylog, now)?;
+            for (t, early) in std::mem::take(&mut conn.early) {
+                self.packet(index, Role::Client, t, &early, out)?;
+            }
+        }
+        Ok(remainder)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{from_hex, KeyLog, CLIENT_TRAFFIC_SECRET_0};
+
+    #[test]
+    fn hex() {
+        assert_eq!(from_hex("00ff7a"), Some(vec![0, 0xff, 0x7a]));
+        assert_eq!(from_hex("0"), None);
+        assert_eq!(from_hex("zz"), None);
+    }
+
+    #[test]
+    fn keylog() {
+        let keylog = KeyLog::parse(
+            "# SSL/TLS secrets log file, generated by NSS\n\
+             CLIENT_TRAFFIC_SECRET_0 0102 0a0b0c\n\
+             SERVER_TRAFFIC_SECRET_0 0102 0d0e0f\n\
+             CLIENT_RANDOM 0102\n\
+             EXPORTER_SECRET 01 not-hex\n",
+        );
+        assert_eq!(keylog.len(), 2);
+        assert_eq!(
+            keylog
+                .secrets
+                .get(&(CLIENT_TRAFFIC_SECRET_0.to_string(), vec![1, 2])),
+            Some(&vec![0x0a, 0x0b, 0x0c])
+        );
+    }
+}
@@ -9,7 +9,7 @@
 use std::{cell::RefCell, collections::VecDeque, rc::Rc};
 
 use neqo_common::event::Provider as EventProvider;
-use neqo_crypto::ResumptionToken;
+use neqo_crypto::{AuthenticationStatus, PRErrorCode, ResumptionToken};
 
 use crate::{
     connection::State,
@@ -22,6 +22,8 @@ use crate::{
 pub enum OutgoingDatagramOutcome {
     DroppedTooBig,
     DroppedQueueFull,
+    /// The datagram was not sent before its deadline.
+    Expired,
     Lost,
     Acked,
 }
@@ -35,6 +37,12 @@ pub enum ConnectionEvent {
     EchFallbackAuthenticationNeeded {
         public_name: String,
     },
+    /// The certificate was checked by the verifier that was set with
+    /// `Connection::set_certificate_verifier`.  `code` is zero if the certificate was accepted;
+    /// otherwise the handshake fails and `AuthenticationStatus::from(code)` says why.
+    CertificateVerified {
+        code: PRErrorCode,
+    },
     /// A new uni (read) or bidi stream has been opened by the peer.
     NewStream {
         stream_id: StreamId,
@@ -95,6 +103,12 @@ impl ConnectionEvents {
         self.insert(ConnectionEvent::EchFallbackAuthenticationNeeded { public_name });
     }
 
+    pub fn certificate_verified(&self, status: AuthenticationStatus) {
+        self.insert(ConnectionEvent::CertificateVerified {
+            code: status.into(),
+        });
+    }
+
     pub fn new_stream(&self, stream_id: StreamId) {
         self.insert(ConnectionEvent::NewStream { stream_id });
     }
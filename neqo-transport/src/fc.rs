@@ -13,7 +13,7 @@ use std::{
     ops::{Deref, DerefMut, Index, IndexMut},
 };
 
-use neqo_common::{qtrace, Role};
+use neqo_common::{qtrace, Decoder, Encoder, Role};
 
 use crate::{
     frame::{
@@ -115,6 +115,11 @@ where
         }
     }
 
+    /// Whether a blocking frame needs to be sent.
+    pub fn frame_needed(&self) -> bool {
+        self.blocked_needed().is_some()
+    }
+
     /// Clear the need to send a blocked frame.
     fn blocked_sent(&mut self) {
         self.blocked_frame = false;
@@ -128,6 +133,30 @@ where
             self.blocked_frame = true;
         }
     }
+
+    /// Write out the state, but not the subject, for a connection export.
+    pub fn export(&self, enc: &mut Encoder) {
+        enc.encode_varint(self.limit);
+        enc.encode_varint(self.used);
+        enc.encode_varint(self.blocked_at);
+        enc.encode_byte(u8::from(self.blocked_frame));
+    }
+
+    /// Restore state that was written by `export`.
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        let limit = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let used = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let blocked_at = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let blocked_frame = dec.decode_byte().ok_or(Error::InvalidExport)?;
+        if used > limit || blocked_frame > 1 {
+            return Err(Error::InvalidExport);
+        }
+        self.limit = limit;
+        self.used = used;
+        self.blocked_at = blocked_at;
+        self.blocked_frame = blocked_frame == 1;
+        Ok(())
+    }
 }
 
 impl SenderFlowControl<()> {
@@ -293,6 +322,33 @@ where
     pub fn consumed(&self) -> u64 {
         self.consumed
     }
+
+    /// Write out the state, but not the subject, for a connection export.
+    pub fn export(&self, enc: &mut Encoder) {
+        enc.encode_varint(self.max_active);
+        enc.encode_varint(self.max_allowed);
+        enc.encode_varint(self.consumed);
+        enc.encode_varint(self.retired);
+        enc.encode_byte(u8::from(self.frame_pending));
+    }
+
+    /// Restore state that was written by `export`.
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        let max_active = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let max_allowed = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let consumed = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let retired = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let frame_pending = dec.decode_byte().ok_or(Error::InvalidExport)?;
+        if frame_pending > 1 {
+            return Err(Error::InvalidExport);
+        }
+        self.max_active = max_active;
+        self.max_allowed = max_allowed;
+        self.consumed = consumed;
+        self.retired = retired;
+        self.frame_pending = frame_pending == 1;
+        Ok(())
+    }
 }
 
 impl ReceiverFlowControl<()> {
@@ -470,6 +526,22 @@ impl RemoteStreamLimit {
         assert!(self.is_allowed(new_stream));
         new_stream
     }
+
+    pub fn export(&self, enc: &mut Encoder) {
+        self.streams_fc.export(enc);
+        enc.encode_varint(self.next_stream.as_u64());
+    }
+
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        self.streams_fc.import(dec)?;
+        let next_stream = StreamId::from(dec.decode_varint().ok_or(Error::InvalidExport)?);
+        // The stream has to be of the same type and initiator.
+        if next_stream.as_u64() & 3 != self.next_stream.as_u64() & 3 {
+            return Err(Error::InvalidExport);
+        }
+        self.next_stream = next_stream;
+        Ok(())
+    }
 }
 
 impl Deref for RemoteStreamLimit {
@@ -12,15 +12,20 @@ use neqo_crypto::Error as CryptoError;
 
 mod ackrate;
 mod addr_valid;
+mod capture;
 mod cc;
 mod cid;
 mod connection;
 mod crypto;
+pub mod dissect;
 mod dump;
 mod events;
 mod fc;
 mod frame;
 mod pace;
+#[cfg(feature = "bench")]
+pub mod packet;
+#[cfg(not(feature = "bench"))]
 mod packet;
 mod path;
 mod qlog;
@@ -38,10 +43,14 @@ mod stats;
 pub mod stream_id;
 pub mod streams;
 pub mod tparams;
+#[cfg(feature = "bench")]
+pub mod tracking;
+#[cfg(not(feature = "bench"))]
 mod tracking;
 pub mod version;
 
 pub use self::{
+    capture::PacketCapture,
     cc::CongestionControlAlgorithm,
     cid::{
         ConnectionId, ConnectionIdDecoder, ConnectionIdGenerator, ConnectionIdRef,
@@ -99,6 +108,8 @@ pub enum Error {
     HandshakeFailed,
     IdleTimeout,
     IntegerOverflow,
+    /// Exported connection state could not be imported.
+    InvalidExport,
     InvalidInput,
     InvalidMigration,
     InvalidPacket,
@@ -117,6 +128,8 @@ pub enum Error {
     NoAvailablePath,
     NoMoreData,
     NotConnected,
+    /// A connection can't be exported while it has outstanding state.
+    NotQuiescent,
     PacketNumberOverlap,
     PeerApplicationError(AppError),
     PeerError(TransportError),
@@ -12,18 +12,21 @@ use std::{
     convert::TryFrom,
     fmt::{self, Display},
     mem,
-    net::{IpAddr, SocketAddr},
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
     rc::Rc,
     time::{Duration, Instant},
 };
 
-use neqo_common::{hex, qdebug, qinfo, qlog::NeqoQlog, qtrace, Datagram, Encoder, IpTos};
+use neqo_common::{hex, qdebug, qinfo, qlog::NeqoQlog, qtrace, Datagram, Decoder, Encoder, IpTos};
 use neqo_crypto::random;
 
 use crate::{
     ackrate::{AckRate, PeerAckDelay},
     cc::CongestionControlAlgorithm,
-    cid::{ConnectionId, ConnectionIdRef, ConnectionIdStore, RemoteConnectionIdEntry},
+    cid::{
+        ConnectionId, ConnectionIdEntry, ConnectionIdRef, ConnectionIdStore,
+        RemoteConnectionIdEntry,
+    },
     frame::{FRAME_TYPE_PATH_CHALLENGE, FRAME_TYPE_PATH_RESPONSE, FRAME_TYPE_RETIRE_CONNECTION_ID},
     packet::PacketBuilder,
     recovery::RecoveryToken,
@@ -473,6 +476,86 @@ impl Paths {
         }
         self.qlog = qlog;
     }
+
+    /// Write out the primary path for a connection export: the peer address,
+    /// the connection ID in use, and the RTT.  This fails if there are other
+    /// paths or the primary path is not validated.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        let Some(primary) = &self.primary else {
+            return Err(Error::NotQuiescent);
+        };
+        let path = primary.borrow();
+        if self.paths.len() != 1
+            || self.migration_target.is_some()
+            || !self.to_retire.is_empty()
+            || !path.is_valid()
+            || path.has_probe()
+        {
+            return Err(Error::NotQuiescent);
+        }
+
+        match path.remote.ip() {
+            IpAddr::V4(a) => {
+                enc.encode_byte(4);
+                enc.encode(&a.octets());
+            }
+            IpAddr::V6(a) => {
+                enc.encode_byte(6);
+                enc.encode(&a.octets());
+            }
+        }
+        enc.encode_uint(2, path.remote.port());
+        path.remote_cid
+            .as_ref()
+            .ok_or(Error::NotQuiescent)?
+            .export(enc);
+        let rtt = path.rtt.estimate() + path.rtt.rttvar();
+        enc.encode_varint(u64::try_from(rtt.as_micros())?);
+        Ok(())
+    }
+
+    /// Restore the primary path from a connection export, which is sent from
+    /// `local`.  The path is valid, but congestion control starts over.
+    pub fn import(
+        &mut self,
+        dec: &mut Decoder,
+        local: SocketAddr,
+        local_cid: ConnectionId,
+        cc: CongestionControlAlgorithm,
+        pacing: bool,
+        now: Instant,
+    ) -> Res<()> {
+        let ip = match dec.decode_byte().ok_or(Error::InvalidExport)? {
+            4 => {
+                let a = <[u8; 4]>::try_from(dec.decode(4).ok_or(Error::InvalidExport)?)
+                    .map_err(|_| Error::InvalidExport)?;
+                IpAddr::V4(Ipv4Addr::from(a))
+            }
+            6 => {
+                let a = <[u8; 16]>::try_from(dec.decode(16).ok_or(Error::InvalidExport)?)
+                    .map_err(|_| Error::InvalidExport)?;
+                IpAddr::V6(Ipv6Addr::from(a))
+            }
+            _ => return Err(Error::InvalidExport),
+        };
+        let port = u16::try_from(dec.decode_uint(2).ok_or(Error::InvalidExport)?)?;
+        let remote_cid = ConnectionIdEntry::import(dec)?;
+        let rtt = Duration::from_micros(dec.decode_varint().ok_or(Error::InvalidExport)?);
+
+        let mut path = Path::temporary(
+            local,
+            SocketAddr::new(ip, port),
+            cc,
+            pacing,
+            self.qlog.clone(),
+            now,
+        );
+        path.rtt_mut().set_initial(rtt);
+        let path = Rc::new(RefCell::new(path));
+        self.make_permanent(&path, Some(local_cid), remote_cid);
+        path.borrow_mut().set_valid(now);
+        Ok(())
+    }
 }
 
 /// The state of a path with respect to address validation.
@@ -834,7 +917,8 @@ impl Path {
         tokens: &mut Vec<RecoveryToken>,
         stats: &mut FrameStats,
     ) {
-        self.rtt.write_frames(builder, tokens, stats);
+        self.rtt
+            .write_frames(&mut self.qlog, builder, tokens, stats);
     }
 
     pub fn lost_ack_frequency(&mut self, lost: &AckRate) {
@@ -842,7 +926,7 @@ impl Path {
     }
 
     pub fn acked_ack_frequency(&mut self, acked: &AckRate) {
-        self.rtt.frame_acked(acked);
+        self.rtt.frame_acked(&mut self.qlog, acked);
     }
 
     /// Process a timer for this path.
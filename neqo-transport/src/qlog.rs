@@ -17,10 +17,11 @@ use neqo_common::{hex, qinfo, qlog::NeqoQlog, Decoder};
 use qlog::events::{
     connectivity::{ConnectionStarted, ConnectionState, ConnectionStateUpdated},
     quic::{
-        AckedRanges, ErrorSpace, MetricsUpdated, PacketDropped, PacketHeader, PacketLost,
-        PacketReceived, PacketSent, QuicFrame, StreamType, VersionInformation,
+        AckedRanges, ErrorSpace, LossTimerEventType, LossTimerUpdated, MetricsUpdated,
+        PacketDropped, PacketHeader, PacketLost, PacketLostTrigger, PacketReceived, PacketSent,
+        QuicFrame, StreamType, TimerType, VersionInformation,
     },
-    EventData, RawInfo,
+    Event, EventData, RawInfo,
 };
 use smallvec::SmallVec;
 
@@ -31,7 +32,7 @@ use crate::{
     path::PathRef,
     stream_id::StreamType as NeqoStreamType,
     tparams::{self, TransportParametersHandler},
-    tracking::SentPacket,
+    tracking::{PacketNumberSpace, SentPacket},
     version::{Version, VersionConfig, WireVersion},
 };
 
@@ -257,21 +258,92 @@ pub fn packet_dropped(qlog: &mut NeqoQlog, public_packet: &PublicPacket) {
     });
 }
 
-pub fn packets_lost(qlog: &mut NeqoQlog, pkts: &[SentPacket]) {
-    qlog.add_event_with_stream(|stream| {
-        for pkt in pkts {
-            let header =
-                PacketHeader::with_type(to_qlog_pkt_type(pkt.pt), Some(pkt.pn), None, None, None);
+pub fn packet_lost(qlog: &mut NeqoQlog, pkt: &SentPacket, trigger: PacketLostTrigger) {
+    qlog.add_event_data(|| {
+        let header =
+            PacketHeader::with_type(to_qlog_pkt_type(pkt.pt), Some(pkt.pn), None, None, None);
 
-            let ev_data = EventData::PacketLost(PacketLost {
-                header: Some(header),
-                trigger: None,
-                frames: None,
-            });
+        let ev_data = EventData::PacketLost(PacketLost {
+            header: Some(header),
+            trigger: Some(trigger),
+            frames: None,
+        });
 
-            stream.add_event_data_now(ev_data)?;
-        }
-        Ok(())
+        Some(ev_data)
+    });
+}
+
+/// The loss recovery timer has been set to expire after `delta`.
+/// `pto` is true for the probe timeout and false for time threshold loss detection.
+pub fn loss_timer_set(qlog: &mut NeqoQlog, pto: bool, space: PacketNumberSpace, delta: Duration) {
+    loss_timer_updated(
+        qlog,
+        Some((pto, space)),
+        LossTimerEventType::Set,
+        Some(delta),
+    );
+}
+
+pub fn loss_timer_expired(qlog: &mut NeqoQlog, pto: bool, space: PacketNumberSpace) {
+    loss_timer_updated(qlog, Some((pto, space)), LossTimerEventType::Expired, None);
+}
+
+pub fn loss_timer_cancelled(qlog: &mut NeqoQlog) {
+    loss_timer_updated(qlog, None, LossTimerEventType::Cancelled, None);
+}
+
+fn loss_timer_updated(
+    qlog: &mut NeqoQlog,
+    timer: Option<(bool, PacketNumberSpace)>,
+    event_type: LossTimerEventType,
+    delta: Option<Duration>,
+) {
+    qlog.add_event_data(|| {
+        let ev_data = EventData::LossTimerUpdated(LossTimerUpdated {
+            timer_type: timer.map(|(pto, _)| if pto { TimerType::Pto } else { TimerType::Ack }),
+            packet_number_space: timer.map(|(_, space)| to_qlog_pn_space(space)),
+            event_type,
+            delta: delta.map(|d| d.as_secs_f32() * 1000.0),
+        });
+
+        Some(ev_data)
+    });
+}
+
+/// An `ACK_FREQUENCY` frame asking the peer to use a new acknowledgment rate has been sent.
+pub fn ack_frequency_sent(
+    qlog: &mut NeqoQlog,
+    seqno: u64,
+    packet_tolerance: usize,
+    max_ack_delay: Duration,
+) {
+    ack_frequency_message(
+        qlog,
+        &format!("sent seqno={seqno}"),
+        packet_tolerance,
+        max_ack_delay,
+    );
+}
+
+/// The peer has acknowledged an `ACK_FREQUENCY` frame, so it now uses this rate.
+pub fn ack_frequency_acked(qlog: &mut NeqoQlog, packet_tolerance: usize, max_ack_delay: Duration) {
+    ack_frequency_message(qlog, "acked", packet_tolerance, max_ack_delay);
+}
+
+// qlog has no event for the acknowledgment frequency extension, so use a generic message.
+fn ack_frequency_message(
+    qlog: &mut NeqoQlog,
+    event: &str,
+    packet_tolerance: usize,
+    max_ack_delay: Duration,
+) {
+    qlog.add_event_data(|| {
+        Some(EventData::Message {
+            message: format!(
+                "ack_frequency {event} packet_tolerance={packet_tolerance} max_ack_delay={}",
+                max_ack_delay.as_secs_f32() * 1000.0
+            ),
+        })
     });
 }
 
@@ -323,6 +395,75 @@ pub fn packet_received(
     });
 }
 
+/// A packet that was decrypted from a capture, rather than sent or received by a
+/// `Connection`.  The trace is written from the point of view of the client, so
+/// `sent` is true for packets from the client.  `time` is measured from the start
+/// of the capture rather than the current time.
+pub fn packet_dissected(
+    qlog: &mut NeqoQlog,
+    time: Duration,
+    sent: bool,
+    public_packet: &PublicPacket,
+    payload: &DecryptedPacket,
+) {
+    qlog.add_event_with_stream(|stream| {
+        let mut d = Decoder::from(&payload[..]);
+        let header = PacketHeader::with_type(
+            to_qlog_pkt_type(public_packet.packet_type()),
+            Some(payload.pn()),
+            None,
+            None,
+            None,
+        );
+        let raw = RawInfo {
+            length: Some(public_packet.len() as u64),
+            payload_length: None,
+            data: None,
+        };
+
+        let mut frames = Vec::new();
+        while d.remaining() > 0 {
+            if let Ok(f) = Frame::decode(&mut d) {
+                frames.push(frame_to_qlogframe(&f));
+            } else {
+                qinfo!("qlog: invalid frame");
+                break;
+            }
+        }
+
+        let ev_data = if sent {
+            EventData::PacketSent(PacketSent {
+                header,
+                frames: Some(frames.into_iter().collect()),
+                is_coalesced: None,
+                retry_token: None,
+                stateless_reset_token: None,
+                supported_versions: None,
+                raw: Some(raw),
+                datagram_id: None,
+                send_at_time: None,
+                trigger: None,
+            })
+        } else {
+            EventData::PacketReceived(PacketReceived {
+                header,
+                frames: Some(frames),
+                is_coalesced: None,
+                retry_token: None,
+                stateless_reset_token: None,
+                supported_versions: None,
+                raw: Some(raw),
+                datagram_id: None,
+                trigger: None,
+            })
+        };
+
+        #[allow(clippy::cast_possible_truncation)] // Microsecond precision is enough.
+        let time = (time.as_secs_f64() * 1000.0) as f32;
+        stream.add_event(Event::with_time(time, ev_data))
+    });
+}
+
 #[allow(dead_code)]
 pub enum QlogMetric {
     MinRtt(Duration),
@@ -550,6 +691,16 @@ fn frame_to_qlogframe(frame: &Frame) -> QuicFrame {
     }
 }
 
+fn to_qlog_pn_space(space: PacketNumberSpace) -> qlog::events::quic::PacketNumberSpace {
+    match space {
+        PacketNumberSpace::Initial => qlog::events::quic::PacketNumberSpace::Initial,
+        PacketNumberSpace::Handshake => qlog::events::quic::PacketNumberSpace::Handshake,
+        PacketNumberSpace::ApplicationData => {
+            qlog::events::quic::PacketNumberSpace::ApplicationData
+        }
+    }
+}
+
 fn to_qlog_pkt_type(ptype: PacketType) -> qlog::events::quic::PacketType {
     match ptype {
         PacketType::Initial => qlog::events::quic::PacketType::Initial,
@@ -6,7 +6,7 @@
 
 // https://datatracker.ietf.org/doc/html/draft-ietf-quic-datagram
 
-use std::{cmp::min, collections::VecDeque, convert::TryFrom};
+use std::{cmp::min, collections::VecDeque, convert::TryFrom, time::Instant};
 
 use neqo_common::Encoder;
 
@@ -19,6 +19,10 @@ use crate::{
 };
 
 pub const MAX_QUIC_DATAGRAM: u64 = 65535;
+/// Once this many bytes of datagrams and stream data have been written,
+/// the counts used to apply the datagram share are halved, so that the share
+/// follows recent usage rather than the whole life of the connection.
+const SHARE_WINDOW: u64 = 1 << 16;
 
 #[derive(Debug, Clone, Copy)]
 pub enum DatagramTracking {
@@ -47,6 +51,8 @@ impl From<DatagramTracking> for Option<u64> {
 struct QuicDatagram {
     data: Vec<u8>,
     tracking: DatagramTracking,
+    /// The datagram is dropped if it isn't sent by this time.
+    deadline: Option<Instant>,
 }
 
 impl QuicDatagram {
@@ -73,6 +79,13 @@ pub struct QuicDatagrams {
     max_queued_incoming_datagrams: usize,
     /// Datagram queued for sending.
     datagrams: VecDeque<QuicDatagram>,
+    /// The percentage of application data that datagrams can take ahead of
+    /// stream data.  At 0, datagrams are only sent when there is no stream data.
+    share: u8,
+    /// The bytes of datagrams written recently, for applying `share`.
+    datagram_bytes: u64,
+    /// The bytes of stream data written recently, for applying `share`.
+    stream_bytes: u64,
     conn_events: ConnectionEvents,
 }
 
@@ -81,6 +94,7 @@ impl QuicDatagrams {
         local_datagram_size: u64,
         max_queued_outgoing_datagrams: usize,
         max_queued_incoming_datagrams: usize,
+        share: u8,
         conn_events: ConnectionEvents,
     ) -> Self {
         Self {
@@ -89,6 +103,9 @@ impl QuicDatagrams {
             max_queued_outgoing_datagrams,
             max_queued_incoming_datagrams,
             datagrams: VecDeque::with_capacity(max_queued_outgoing_datagrams),
+            share: min(share, 100),
+            datagram_bytes: 0,
+            stream_bytes: 0,
             conn_events,
         }
     }
@@ -101,6 +118,48 @@ impl QuicDatagrams {
         self.remote_datagram_size = min(v, MAX_QUIC_DATAGRAM);
     }
 
+    /// Whether queued datagrams should be written ahead of stream data.
+    /// This is true while datagrams have had less than their share of
+    /// the bytes that were recently written.
+    pub fn ahead_of_streams(&self) -> bool {
+        self.share > 0
+            && !self.datagrams.is_empty()
+            && self.datagram_bytes * 100
+                <= u64::from(self.share) * (self.datagram_bytes + self.stream_bytes)
+    }
+
+    /// Record that `len` bytes of stream data were written.
+    pub fn stream_data_written(&mut self, len: usize) {
+        self.stream_bytes += u64::try_from(len).unwrap();
+        self.decay_share();
+    }
+
+    fn decay_share(&mut self) {
+        if self.datagram_bytes + self.stream_bytes > SHARE_WINDOW {
+            self.datagram_bytes /= 2;
+            self.stream_bytes /= 2;
+        }
+    }
+
+    /// Drop any datagrams with a deadline that has passed,
+    /// posting an `Expired` outcome for each.
+    pub fn expire(&mut self, now: Instant, stats: &mut Stats) {
+        let events = &self.conn_events;
+        self.datagrams.retain(|dgram| {
+            if dgram.deadline.map_or(true, |d| d > now) {
+                return true;
+            }
+            events.datagram_outcome(dgram.tracking(), OutgoingDatagramOutcome::Expired);
+            stats.datagram_tx.expired += 1;
+            false
+        });
+    }
+
+    /// The earliest deadline of any queued datagram.
+    pub fn next_deadline(&self) -> Option<Instant> {
+        self.datagrams.iter().filter_map(|d| d.deadline).min()
+    }
+
     /// This function tries to write a datagram frame into a packet.
     /// If the frame does not fit into the packet, the datagram will
     /// be dropped and a DatagramLost event will be posted.
@@ -109,6 +168,18 @@ impl QuicDatagrams {
         builder: &mut PacketBuilder,
         tokens: &mut Vec<RecoveryToken>,
         stats: &mut Stats,
+    ) {
+        let start = builder.len();
+        self.write_datagrams(builder, tokens, stats);
+        self.datagram_bytes += u64::try_from(builder.len() - start).unwrap();
+        self.decay_share();
+    }
+
+    fn write_datagrams(
+        &mut self,
+        builder: &mut PacketBuilder,
+        tokens: &mut Vec<RecoveryToken>,
+        stats: &mut Stats,
     ) {
         while let Some(dgram) = self.datagrams.pop_front() {
             let len = dgram.as_ref().len();
@@ -142,7 +213,7 @@ impl QuicDatagrams {
         }
     }
 
-    /// Returns true if there was an unsent datagram that has been dismissed.
+    /// Queue a datagram, which is dropped if it hasn't been sent by `deadline`.
     ///
     /// # Error
     ///
@@ -155,6 +226,7 @@ impl QuicDatagrams {
         &mut self,
         buf: &[u8],
         tracking: DatagramTracking,
+        deadline: Option<Instant>,
         stats: &mut Stats,
     ) -> Res<()> {
         if u64::try_from(buf.len()).unwrap() > self.remote_datagram_size {
@@ -170,6 +242,7 @@ impl QuicDatagrams {
         self.datagrams.push_back(QuicDatagram {
             data: buf.to_vec(),
             tracking,
+            deadline,
         });
         Ok(())
     }
@@ -10,14 +10,14 @@
 
 use std::{
     cmp::{max, min},
-    collections::BTreeMap,
     convert::TryFrom,
-    mem,
     ops::RangeInclusive,
     time::{Duration, Instant},
 };
 
-use neqo_common::{qdebug, qinfo, qlog::NeqoQlog, qtrace, qwarn};
+#[rustfmt::skip] // to keep `::` and thus prevent conflict with `crate::qlog`
+use ::qlog::events::quic::PacketLostTrigger;
+use neqo_common::{qdebug, qinfo, qlog::NeqoQlog, qtrace, qwarn, Decoder, Encoder};
 use smallvec::{smallvec, SmallVec};
 
 use crate::{
@@ -32,7 +32,8 @@ use crate::{
     send_stream::SendStreamRecoveryToken,
     stats::{Stats, StatsCell},
     stream_id::{StreamId, StreamType},
-    tracking::{AckToken, PacketNumberSpace, PacketNumberSpaceSet, SentPacket},
+    tracking::{AckToken, PacketNumberSpace, PacketNumberSpaceSet, SentPacket, SentPackets},
+    Error, Res,
 };
 
 pub(crate) const PACKET_THRESHOLD: u64 = 3;
@@ -183,7 +184,7 @@ pub(crate) struct LossRecoverySpace {
     /// This might be less than the number of ACK-eliciting packets,
     /// because PTO packets don't count.
     in_flight_outstanding: usize,
-    sent_packets: BTreeMap<u64, SentPacket>,
+    sent_packets: SentPackets,
     /// The time that the first out-of-order packet was sent.
     /// This is `None` if there were no out-of-order packets detected.
     /// When set to `Some(T)`, time-based loss detection should be enabled.
@@ -198,7 +199,7 @@ impl LossRecoverySpace {
             largest_acked_sent_time: None,
             last_ack_eliciting: None,
             in_flight_outstanding: 0,
-            sent_packets: BTreeMap::default(),
+            sent_packets: SentPackets::default(),
             first_ooo_time: None,
         }
     }
@@ -223,9 +224,9 @@ impl LossRecoverySpace {
     pub fn pto_packets(&mut self, count: usize) -> impl Iterator<Item = &SentPacket> {
         self.sent_packets
             .iter_mut()
-            .filter_map(|(pn, sent)| {
+            .filter_map(|sent| {
                 if sent.pto() {
-                    qtrace!("PTO: marking packet {} lost ", pn);
+                    qtrace!("PTO: marking packet {} lost ", sent.pn);
                     Some(&*sent)
                 } else {
                     None
@@ -267,7 +268,7 @@ impl LossRecoverySpace {
             // always. See `LossRecoverySpace::pto_base_time()` for details.
             self.last_ack_eliciting = Some(sent_packet.time_sent);
         }
-        self.sent_packets.insert(sent_packet.pn, sent_packet);
+        self.sent_packets.track(sent_packet);
     }
 
     /// If we are only sending ACK frames, send a PING frame after 2 PTOs so that
@@ -300,43 +301,28 @@ impl LossRecoverySpace {
     /// Remove all acknowledged packets.
     /// Returns all the acknowledged packets, with the largest packet number first.
     /// ...and a boolean indicating if any of those packets were ack-eliciting.
-    /// This operates more efficiently because it assumes that the input is sorted
-    /// in the order that an ACK frame is (from the top).
+    /// Packets are returned in order when the input is sorted in the order
+    /// that an ACK frame is (from the top).
     fn remove_acked<R>(&mut self, acked_ranges: R, stats: &mut Stats) -> (Vec<SentPacket>, bool)
     where
         R: IntoIterator<Item = RangeInclusive<u64>>,
-        R::IntoIter: ExactSizeIterator,
     {
-        let acked_ranges = acked_ranges.into_iter();
-        let mut keep = Vec::with_capacity(acked_ranges.len());
-
         let mut acked = Vec::new();
-        let mut eliciting = false;
         for range in acked_ranges {
-            let first_keep = *range.end() + 1;
-            if let Some((&first, _)) = self.sent_packets.range(range).next() {
-                let mut tail = self.sent_packets.split_off(&first);
-                if let Some((&next, _)) = tail.range(first_keep..).next() {
-                    keep.push(tail.split_off(&next));
-                }
-                for (_, p) in tail.into_iter().rev() {
-                    self.remove_packet(&p);
-                    eliciting |= p.ack_eliciting();
-                    if p.lost() {
-                        stats.late_ack += 1;
-                    }
-                    if p.pto_fired() {
-                        stats.pto_ack += 1;
-                    }
-                    acked.push(p);
-                }
-            }
+            self.sent_packets.take_range(&range, &mut acked);
         }
 
-        for mut k in keep.into_iter().rev() {
-            self.sent_packets.append(&mut k);
+        let mut eliciting = false;
+        for p in &acked {
+            self.remove_packet(p);
+            eliciting |= p.ack_eliciting();
+            if p.lost() {
+                stats.late_ack += 1;
+            }
+            if p.pto_fired() {
+                stats.pto_ack += 1;
+            }
         }
-
         (acked, eliciting)
     }
 
@@ -345,12 +331,12 @@ impl LossRecoverySpace {
     /// and when keys are dropped.
     fn remove_ignored(&mut self) -> impl Iterator<Item = SentPacket> {
         self.in_flight_outstanding = 0;
-        mem::take(&mut self.sent_packets).into_values()
+        self.sent_packets.take_all()
     }
 
     /// Remove the primary path marking on any packets this is tracking.
     fn migrate(&mut self) {
-        for pkt in self.sent_packets.values_mut() {
+        for pkt in self.sent_packets.iter_mut() {
             pkt.clear_primary_path();
         }
     }
@@ -359,25 +345,9 @@ impl LossRecoverySpace {
     /// We try to keep these around until a probe is sent for them, so it is
     /// important that `cd` is set to at least the current PTO time; otherwise we
     /// might remove all in-flight packets and stop sending probes.
-    #[allow(clippy::option_if_let_else)] // Hard enough to read as-is.
     fn remove_old_lost(&mut self, now: Instant, cd: Duration) {
-        let mut it = self.sent_packets.iter();
-        // If the first item is not expired, do nothing.
-        if it.next().map_or(false, |(_, p)| p.expired(now, cd)) {
-            // Find the index of the first unexpired packet.
-            let to_remove = if let Some(first_keep) =
-                it.find_map(|(i, p)| if p.expired(now, cd) { None } else { Some(*i) })
-            {
-                // Some packets haven't expired, so keep those.
-                let keep = self.sent_packets.split_off(&first_keep);
-                mem::replace(&mut self.sent_packets, keep)
-            } else {
-                // All packets are expired.
-                mem::take(&mut self.sent_packets)
-            };
-            for (_, p) in to_remove {
-                self.remove_packet(&p);
-            }
+        for p in self.sent_packets.take_oldest(|p| p.expired(now, cd)) {
+            self.remove_packet(&p);
         }
     }
 
@@ -386,6 +356,7 @@ impl LossRecoverySpace {
     /// `cleanup_delay` is the time we will wait before cleaning up a lost packet.
     pub fn detect_lost_packets(
         &mut self,
+        qlog: &mut NeqoQlog,
         now: Instant,
         loss_delay: Duration,
         cleanup_delay: Duration,
@@ -404,30 +375,30 @@ impl LossRecoverySpace {
 
         let largest_acked = self.largest_acked;
 
-        // Lost for retrans/CC purposes
-        let mut lost_pns = SmallVec::<[_; 8]>::new();
-
-        for (pn, packet) in self
+        for packet in self
             .sent_packets
             .iter_mut()
-            // BTreeMap iterates in order of ascending PN
-            .take_while(|(&k, _)| k < largest_acked.unwrap_or(PacketNumber::MAX))
+            // SentPackets iterates in order of ascending PN
+            .take_while(|p| p.pn < largest_acked.unwrap_or(PacketNumber::MAX))
         {
+            let pn = packet.pn;
             // Packets sent before now - loss_delay are deemed lost.
-            if packet.time_sent + loss_delay <= now {
+            let trigger = if packet.time_sent + loss_delay <= now {
                 qtrace!(
                     "lost={}, time sent {:?} is before lost_delay {:?}",
                     pn,
                     packet.time_sent,
                     loss_delay
                 );
-            } else if largest_acked >= Some(*pn + PACKET_THRESHOLD) {
+                PacketLostTrigger::TimeThreshold
+            } else if largest_acked >= Some(pn + PACKET_THRESHOLD) {
                 qtrace!(
                     "lost={}, is >= {} from largest acked {:?}",
                     pn,
                     PACKET_THRESHOLD,
                     largest_acked
                 );
+                PacketLostTrigger::ReorderingThreshold
             } else {
                 if largest_acked.is_some() {
                     self.first_ooo_time = Some(packet.time_sent);
@@ -437,11 +408,14 @@ impl LossRecoverySpace {
             };
 
             if packet.declare_lost(now) {
-                lost_pns.push(*pn);
+                // A packet that the PTO timer fired for was logged as lost then.
+                if !packet.pto_fired() {
+                    qlog::packet_lost(qlog, packet, trigger);
+                }
+                // Lost for retrans/CC purposes
+                lost_packets.push(packet.clone());
             }
         }
-
-        lost_packets.extend(lost_pns.iter().map(|pn| self.sent_packets[pn].clone()));
     }
 }
 
@@ -575,12 +549,22 @@ impl PtoState {
     }
 }
 
+/// The loss recovery timer, as it was last reported to qlog.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LossTimer {
+    /// Whether this is the PTO timer, rather than the timer for time threshold loss detection.
+    pto: bool,
+    space: PacketNumberSpace,
+    time: Instant,
+}
+
 #[derive(Debug)]
 pub(crate) struct LossRecovery {
     /// When the handshake was confirmed, if it has been.
     confirmed_time: Option<Instant>,
     pto_state: Option<PtoState>,
     spaces: LossRecoverySpaces,
+    loss_timer: Option<LossTimer>,
     qlog: NeqoQlog,
     stats: StatsCell,
     /// The factor by which the PTO period is reduced.
@@ -594,6 +578,7 @@ impl LossRecovery {
             confirmed_time: None,
             pto_state: None,
             spaces: LossRecoverySpaces::default(),
+            loss_timer: None,
             qlog: NeqoQlog::default(),
             stats,
             fast_pto,
@@ -604,6 +589,45 @@ impl LossRecovery {
         self.spaces.get(pn_space).and_then(|sp| sp.largest_acked)
     }
 
+    /// Write out the state of the application data space for a connection export.
+    ///
+    /// # Errors
+    ///
+    /// When the handshake isn't confirmed, or when there are ack-eliciting packets
+    /// that are neither acknowledged nor declared lost.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        let space = self
+            .spaces
+            .get(PacketNumberSpace::ApplicationData)
+            .ok_or(Error::NotQuiescent)?;
+        if self.confirmed_time.is_none()
+            || self.spaces.get(PacketNumberSpace::Handshake).is_some()
+            || space
+                .sent_packets
+                .iter()
+                .any(|p| p.ack_eliciting() && !p.lost() && !p.pto_fired())
+        {
+            return Err(Error::NotQuiescent);
+        }
+        enc.encode_varint(space.largest_acked.map_or(0, |pn| pn + 1));
+        Ok(())
+    }
+
+    /// Restore the state written by `export`.  This needs to happen after the
+    /// handshake spaces are discarded.
+    ///
+    /// # Errors
+    ///
+    /// When the exported state is malformed.
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        let largest_acked = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        self.spaces
+            .get_mut(PacketNumberSpace::ApplicationData)
+            .ok_or(Error::InvalidExport)?
+            .largest_acked = largest_acked.checked_sub(1);
+        Ok(())
+    }
+
     pub fn set_qlog(&mut self, qlog: NeqoQlog) {
         self.qlog = qlog;
     }
@@ -728,6 +752,7 @@ impl LossRecovery {
         let cleanup_delay = self.pto_period(primary_path.borrow().rtt(), pn_space);
         let mut lost = Vec::new();
         self.spaces.get_mut(pn_space).unwrap().detect_lost_packets(
+            &mut self.qlog,
             now,
             loss_delay,
             cleanup_delay,
@@ -813,7 +838,7 @@ impl LossRecovery {
 
     /// Calculate when the next timeout is likely to be.  This is the earlier of the loss timer
     /// and the PTO timer; either or both might be disabled, so this can return `None`.
-    pub fn next_timeout(&mut self, rtt: &RttEstimate) -> Option<Instant> {
+    pub fn next_timeout(&mut self, rtt: &RttEstimate, now: Instant) -> Option<Instant> {
         let loss_time = self.earliest_loss_time(rtt);
         let pto_time = self.earliest_pto(rtt);
         qtrace!(
@@ -822,21 +847,47 @@ impl LossRecovery {
             loss_time,
             pto_time
         );
-        match (loss_time, pto_time) {
-            (Some(loss_time), Some(pto_time)) => Some(min(loss_time, pto_time)),
-            (Some(loss_time), None) => Some(loss_time),
-            (None, Some(pto_time)) => Some(pto_time),
+        let timer = match (loss_time, pto_time) {
+            (Some((space, time)), Some((_, pto_time))) if time <= pto_time => Some(LossTimer {
+                pto: false,
+                space,
+                time,
+            }),
+            (_, Some((space, time))) => Some(LossTimer {
+                pto: true,
+                space,
+                time,
+            }),
+            (Some((space, time)), None) => Some(LossTimer {
+                pto: false,
+                space,
+                time,
+            }),
             (None, None) => None,
+        };
+        if timer != self.loss_timer {
+            if let Some(t) = timer {
+                qlog::loss_timer_set(
+                    &mut self.qlog,
+                    t.pto,
+                    t.space,
+                    t.time.saturating_duration_since(now),
+                );
+            } else {
+                qlog::loss_timer_cancelled(&mut self.qlog);
+            }
+            self.loss_timer = timer;
         }
+        timer.map(|t| t.time)
     }
 
     /// Find when the earliest sent packet should be considered lost.
-    fn earliest_loss_time(&self, rtt: &RttEstimate) -> Option<Instant> {
+    fn earliest_loss_time(&self, rtt: &RttEstimate) -> Option<(PacketNumberSpace, Instant)> {
         self.spaces
             .iter()
-            .filter_map(LossRecoverySpace::loss_recovery_timer_start)
-            .min()
-            .map(|val| val + rtt.loss_delay())
+            .filter_map(|sp| sp.loss_recovery_timer_start().map(|t| (sp.space(), t)))
+            .min_by_key(|&(_, t)| t)
+            .map(|(space, t)| (space, t + rtt.loss_delay()))
     }
 
     /// Simple wrapper for the PTO calculation that avoids borrow check rules.
@@ -876,15 +927,15 @@ impl LossRecovery {
 
     /// Find the earliest PTO time for all active packet number spaces.
     /// Ignore Application if either Initial or Handshake have an active PTO.
-    fn earliest_pto(&self, rtt: &RttEstimate) -> Option<Instant> {
+    fn earliest_pto(&self, rtt: &RttEstimate) -> Option<(PacketNumberSpace, Instant)> {
         if self.confirmed_time.is_some() {
             self.pto_time(rtt, PacketNumberSpace::ApplicationData)
+                .map(|t| (PacketNumberSpace::ApplicationData, t))
         } else {
-            self.pto_time(rtt, PacketNumberSpace::Initial)
+            [PacketNumberSpace::Initial, PacketNumberSpace::Handshake]
                 .iter()
-                .chain(self.pto_time(rtt, PacketNumberSpace::Handshake).iter())
-                .min()
-                .copied()
+                .filter_map(|&space| self.pto_time(rtt, space).map(|t| (space, t)))
+                .min_by_key(|&(_, t)| t)
         }
     }
 
@@ -923,6 +974,7 @@ impl LossRecovery {
                 if t <= now {
                     qdebug!([self], "PTO timer fired for {}", pn_space);
                     let space = self.spaces.get_mut(*pn_space).unwrap();
+                    let first = lost.len();
                     lost.extend(
                         space
                             .pto_packets(PtoState::pto_packet_count(
@@ -931,6 +983,9 @@ impl LossRecovery {
                             ))
                             .cloned(),
                     );
+                    for p in &lost[first..] {
+                        qlog::packet_lost(&mut self.qlog, p, PacketLostTrigger::PtoExpired);
+                    }
 
                     pto_space = pto_space.or(Some(*pn_space));
                 }
@@ -948,6 +1003,11 @@ impl LossRecovery {
     pub fn timeout(&mut self, primary_path: &PathRef, now: Instant) -> Vec<SentPacket> {
         qtrace!([self], "timeout {:?}", now);
 
+        if let Some(t) = self.loss_timer.filter(|t| t.time <= now) {
+            qlog::loss_timer_expired(&mut self.qlog, t.pto, t.space);
+            self.loss_timer = None;
+        }
+
         let loss_delay = primary_path.borrow().rtt().loss_delay();
 
         let mut lost_packets = Vec::new();
@@ -959,7 +1019,7 @@ impl LossRecovery {
                 space.space(),
                 self.fast_pto,
             );
-            space.detect_lost_packets(now, loss_delay, pto, &mut lost_packets);
+            space.detect_lost_packets(&mut self.qlog, now, loss_delay, pto, &mut lost_packets);
 
             primary_path.borrow_mut().on_packets_lost(
                 space.largest_acked_sent_time,
@@ -1085,8 +1145,8 @@ mod tests {
             self.lr.timeout(&self.path, now)
         }
 
-        pub fn next_timeout(&mut self) -> Option<Instant> {
-            self.lr.next_timeout(self.path.borrow().rtt())
+        pub fn next_timeout(&mut self, now: Instant) -> Option<Instant> {
+            self.lr.next_timeout(self.path.borrow().rtt(), now)
         }
 
         pub fn discard(&mut self, space: PacketNumberSpace, now: Instant) {
@@ -1400,7 +1460,7 @@ mod tests {
         assert_sent_times(&lr, None, None, Some(pn1_sent_time));
 
         // After time elapses, pn 1 is marked lost.
-        let callback_time = lr.next_timeout();
+        let callback_time = lr.next_timeout(pn2_ack_time);
         assert_eq!(callback_time, Some(pn1_loss_time));
         let packets = lr.timeout(pn1_loss_time);
         assert_eq!(packets.len(), 1);
@@ -1494,6 +1554,7 @@ mod tests {
             lr.on_ack_received(pn_space, 1, vec![1..=1], Duration::from_secs(0), pn_time(3));
             let mut lost = Vec::new();
             lr.spaces.get_mut(pn_space).unwrap().detect_lost_packets(
+                &mut NeqoQlog::default(),
                 pn_time(3),
                 TEST_RTT,
                 TEST_RTT * 3, // unused
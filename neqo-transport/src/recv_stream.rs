@@ -9,14 +9,14 @@
 
 use std::{
     cell::RefCell,
-    cmp::max,
+    cmp::{max, min},
     collections::BTreeMap,
     convert::TryFrom,
     mem,
     rc::{Rc, Weak},
 };
 
-use neqo_common::{qtrace, Role};
+use neqo_common::{qtrace, Bytes, Decoder, Encoder, Role};
 use smallvec::SmallVec;
 
 use crate::{
@@ -88,6 +88,33 @@ impl RecvStreams {
         self.streams.clear();
     }
 
+    /// Write out all of the streams for a connection export.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(u64::try_from(self.streams.len())?);
+        for stream in self.streams.values() {
+            stream.export(enc)?;
+        }
+        Ok(())
+    }
+
+    /// Restore the streams that were written by `export`.
+    pub fn import(
+        &mut self,
+        dec: &mut Decoder,
+        session_fc: &Rc<RefCell<ReceiverFlowControl<()>>>,
+        conn_events: &ConnectionEvents,
+    ) -> Res<()> {
+        let count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        for _ in 0..count {
+            let (stream, keep_alive) =
+                RecvStream::import(dec, Rc::clone(session_fc), conn_events.clone())?;
+            let stream_id = stream.stream_id();
+            self.insert(stream_id, stream);
+            self.keep_alive(stream_id, keep_alive)?;
+        }
+        Ok(())
+    }
+
     pub fn clear_terminal(&mut self, send_streams: &SendStreams, role: Role) -> (u64, u64) {
         let recv_to_remove = self
             .streams
@@ -124,9 +151,9 @@ impl RecvStreams {
 /// from incoming STREAM frames.
 #[derive(Debug, Default)]
 pub struct RxStreamOrderer {
-    data_ranges: BTreeMap<u64, Vec<u8>>, // (start_offset, data)
-    retired: u64,                        // Number of bytes the application has read
-    received: u64,                       // The number of bytes has stored in `data_ranges`
+    data_ranges: BTreeMap<u64, Bytes>, // (start_offset, data)
+    retired: u64,                      // Number of bytes the application has read
+    received: u64,                     // The number of bytes has stored in `data_ranges`
 }
 
 impl RxStreamOrderer {
@@ -134,6 +161,16 @@ impl RxStreamOrderer {
         Self::default()
     }
 
+    /// Make an orderer for a stream where the first `retired` bytes have
+    /// already been received and read.
+    pub fn with_retired(retired: u64) -> Self {
+        Self {
+            data_ranges: BTreeMap::new(),
+            retired,
+            received: retired,
+        }
+    }
+
     /// Process an incoming stream frame off the wire. This may result in data
     /// being available to upper layers if frame is not out of order (ooo) or
     /// if the frame fills a gap.
@@ -181,8 +218,9 @@ impl RxStreamOrderer {
                 new_data = &new_data[usize::try_from(overlap).unwrap()..];
                 // If it is small enough, extend the previous buffer.
                 // This can't always extend, because otherwise the buffer could end up
-                // growing indefinitely without being released.
-                prev_vec.len() < 4096 && prev_end == new_start
+                // growing indefinitely without being released. A buffer that the
+                // application still holds part of is not extended, as that would copy it.
+                prev_vec.len() < 4096 && prev_end == new_start && prev_vec.is_unique()
             } else {
                 // PPPPPP    ->  PPPPPP
                 //   NNNN
@@ -245,7 +283,7 @@ impl RxStreamOrderer {
                     .unwrap();
                 buf.extend_from_slice(to_add);
             } else {
-                self.data_ranges.insert(new_start, to_add.to_vec());
+                self.data_ranges.insert(new_start, Bytes::from(to_add));
             }
         }
     }
@@ -341,6 +379,33 @@ impl RxStreamOrderer {
         copied
     }
 
+    /// Take up to `max` bytes of contiguous data out of the buffer, without copying.
+    /// This returns at most the rest of one received range, so the data might be
+    /// split over multiple calls.
+    fn read_chunk(&mut self, max: usize) -> Option<Bytes> {
+        let mut entry = self.data_ranges.first_entry()?;
+        let range_start = *entry.key();
+        if range_start > self.retired {
+            // The data in the buffer isn't contiguous.
+            return None;
+        }
+        let offset = usize::try_from(self.retired - range_start).unwrap();
+        let available = entry.get().len() - offset;
+        let len = min(available, max);
+        if len == 0 {
+            return None;
+        }
+        let chunk = if len == available {
+            let mut data = entry.remove();
+            data.advance(offset);
+            data
+        } else {
+            entry.get().slice(offset..offset + len)
+        };
+        self.retired += u64::try_from(len).unwrap();
+        Some(chunk)
+    }
+
     /// Extend the given Vector with any available data.
     pub fn read_to_end(&mut self, buf: &mut Vec<u8>) -> usize {
         let orig_len = buf.len();
@@ -564,6 +629,92 @@ impl RecvStream {
         self.state = new_state;
     }
 
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Write out the state of the stream for a connection export.  This fails if
+    /// the stream holds data that the application hasn't read, or if it
+    /// has been closed but the closing isn't complete.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(self.stream_id.as_u64());
+        match &self.state {
+            RecvStreamState::Recv { fc, recv_buf, .. }
+                if recv_buf.received() == recv_buf.retired() =>
+            {
+                enc.encode_byte(0);
+                fc.export(enc);
+                enc.encode_varint(recv_buf.retired());
+            }
+            // These are kept until the sending side of the stream is done.
+            RecvStreamState::DataRead {
+                final_received,
+                final_read,
+            } => {
+                enc.encode_byte(1);
+                enc.encode_varint(*final_received);
+                enc.encode_varint(*final_read);
+            }
+            RecvStreamState::ResetRecvd {
+                final_received,
+                final_read,
+            } => {
+                enc.encode_byte(2);
+                enc.encode_varint(*final_received);
+                enc.encode_varint(*final_read);
+            }
+            _ => return Err(Error::NotQuiescent),
+        }
+        enc.encode_byte(u8::from(self.keep_alive.is_some()));
+        Ok(())
+    }
+
+    /// Restore a stream that was written by `export`.  This also returns whether
+    /// the stream was keeping the connection alive, which `RecvStreams` tracks.
+    fn import(
+        dec: &mut Decoder,
+        session_fc: Rc<RefCell<ReceiverFlowControl<()>>>,
+        conn_events: ConnectionEvents,
+    ) -> Res<(Self, bool)> {
+        let stream_id = StreamId::from(dec.decode_varint().ok_or(Error::InvalidExport)?);
+        let state = match dec.decode_byte().ok_or(Error::InvalidExport)? {
+            0 => {
+                let mut fc = ReceiverFlowControl::new(stream_id, 0);
+                fc.import(dec)?;
+                let retired = dec.decode_varint().ok_or(Error::InvalidExport)?;
+                if retired != fc.retired() {
+                    return Err(Error::InvalidExport);
+                }
+                RecvStreamState::Recv {
+                    fc,
+                    session_fc,
+                    recv_buf: RxStreamOrderer::with_retired(retired),
+                }
+            }
+            1 => RecvStreamState::DataRead {
+                final_received: dec.decode_varint().ok_or(Error::InvalidExport)?,
+                final_read: dec.decode_varint().ok_or(Error::InvalidExport)?,
+            },
+            2 => RecvStreamState::ResetRecvd {
+                final_received: dec.decode_varint().ok_or(Error::InvalidExport)?,
+                final_read: dec.decode_varint().ok_or(Error::InvalidExport)?,
+            },
+            _ => return Err(Error::InvalidExport),
+        };
+        let keep_alive = match dec.decode_byte().ok_or(Error::InvalidExport)? {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::InvalidExport),
+        };
+        let stream = Self {
+            stream_id,
+            state,
+            conn_events,
+            keep_alive: None,
+        };
+        Ok((stream, keep_alive))
+    }
+
     pub fn stats(&self) -> RecvStreamStats {
         match &self.state {
             RecvStreamState::Recv { recv_buf, .. }
@@ -771,6 +922,31 @@ impl RecvStream {
     ///
     /// `NoMoreData` if data and fin bit were previously read by the application.
     pub fn read(&mut self, buf: &mut [u8]) -> Res<(usize, bool)> {
+        self.read_internal(|recv_buf| {
+            let bytes_read = recv_buf.read(buf);
+            (bytes_read, bytes_read)
+        })
+    }
+
+    /// Like `read`, but returns the data as a chunk of up to `max` bytes instead of copying it.
+    ///
+    /// # Errors
+    ///
+    /// `NoMoreData` if data and fin bit were previously read by the application.
+    pub fn read_chunk(&mut self, max: usize) -> Res<(Option<Bytes>, bool)> {
+        self.read_internal(|recv_buf| {
+            let chunk = recv_buf.read_chunk(max);
+            let bytes_read = chunk.as_ref().map_or(0, Bytes::len);
+            (chunk, bytes_read)
+        })
+    }
+
+    /// Lets `read` take data from the receive buffer. `read` returns what was read and
+    /// the number of bytes read.
+    fn read_internal<T>(
+        &mut self,
+        read: impl FnOnce(&mut RxStreamOrderer) -> (T, usize),
+    ) -> Res<(T, bool)> {
         let data_recvd_state = matches!(self.state, RecvStreamState::DataRecvd { .. });
         match &mut self.state {
             RecvStreamState::Recv {
@@ -789,7 +965,7 @@ impl RecvStream {
                 fc,
                 session_fc,
             } => {
-                let bytes_read = recv_buf.read(buf);
+                let (data, bytes_read) = read(recv_buf);
                 Self::flow_control_retire_data(u64::try_from(bytes_read).unwrap(), fc, session_fc);
                 let fin_read = if data_recvd_state {
                     if recv_buf.buffered() == 0 {
@@ -806,7 +982,7 @@ impl RecvStream {
                 } else {
                     false
                 };
-                Ok((bytes_read, fin_read))
+                Ok((data, fin_read))
             }
             RecvStreamState::DataRead { .. }
             | RecvStreamState::AbortReading { .. }
@@ -1147,6 +1323,45 @@ mod tests {
         assert_eq!(count, 80);
     }
 
+    #[test]
+    fn read_chunk() {
+        let mut s = RxStreamOrderer::new();
+        s.inbound_frame(0, &[1; 10]);
+        // This extends the first range.
+        s.inbound_frame(10, &[1; 10]);
+        s.inbound_frame(30, &[3; 10]);
+
+        // The first chunk is split when `max` is smaller.
+        assert_eq!(s.read_chunk(4).unwrap(), Bytes::from(vec![1; 4]));
+        assert_eq!(s.read_chunk(100).unwrap(), Bytes::from(vec![1; 16]));
+        assert_eq!(s.retired(), 20);
+        // Nothing is returned at a gap.
+        assert!(s.read_chunk(100).is_none());
+
+        s.inbound_frame(20, &[4; 10]);
+        assert_eq!(s.read_chunk(100).unwrap(), Bytes::from(vec![4; 10]));
+        assert_eq!(s.read_chunk(100).unwrap(), Bytes::from(vec![3; 10]));
+        assert!(s.data_ranges.is_empty());
+    }
+
+    /// A range that the application holds part of is not extended in place.
+    #[test]
+    fn read_chunk_shared() {
+        let mut s = RxStreamOrderer::new();
+        s.inbound_frame(0, &[1; 10]);
+        let head = s.read_chunk(4).unwrap();
+
+        // This starts a new range, because the first one is shared.
+        s.inbound_frame(10, &[2; 10]);
+        assert_eq!(s.data_ranges.len(), 2);
+        assert_eq!(s.data_ranges.get(&0).unwrap().len(), 10);
+        assert_eq!(head, Bytes::from(vec![1; 4]));
+
+        assert_eq!(s.read_chunk(100).unwrap(), Bytes::from(vec![1; 6]));
+        assert_eq!(s.read_chunk(100).unwrap(), Bytes::from(vec![2; 10]));
+        assert!(s.data_ranges.is_empty());
+    }
+
     /// Reading exactly one chunk works, when there is a gap.
     #[test]
     fn stop_reading_at_gap() {
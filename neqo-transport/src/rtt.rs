@@ -181,19 +181,20 @@ impl RttEstimate {
 
     pub fn write_frames(
         &mut self,
+        qlog: &mut NeqoQlog,
         builder: &mut PacketBuilder,
         tokens: &mut Vec<RecoveryToken>,
         stats: &mut FrameStats,
     ) {
-        self.ack_delay.write_frames(builder, tokens, stats);
+        self.ack_delay.write_frames(qlog, builder, tokens, stats);
     }
 
     pub fn frame_lost(&mut self, lost: &AckRate) {
         self.ack_delay.frame_lost(lost);
     }
 
-    pub fn frame_acked(&mut self, acked: &AckRate) {
-        self.ack_delay.frame_acked(acked);
+    pub fn frame_acked(&mut self, qlog: &mut NeqoQlog, acked: &AckRate) {
+        self.ack_delay.frame_acked(qlog, acked);
     }
 }
 
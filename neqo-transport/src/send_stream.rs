@@ -9,16 +9,16 @@
 use std::{
     cell::RefCell,
     cmp::{max, min, Ordering},
-    collections::{btree_map::Entry, BTreeMap, VecDeque},
+    collections::{btree_map::Entry, BTreeMap, BTreeSet},
     convert::TryFrom,
     hash::{Hash, Hasher},
-    mem,
-    ops::Add,
+    iter, mem,
+    ops::{Add, Bound},
     rc::Rc,
 };
 
 use indexmap::IndexMap;
-use neqo_common::{qdebug, qerror, qtrace, Encoder, Role};
+use neqo_common::{qdebug, qerror, qtrace, Bytes, Decoder, Encoder, Role};
 use smallvec::SmallVec;
 
 use crate::{
@@ -63,6 +63,30 @@ impl Default for TransmissionPriority {
     }
 }
 
+impl TransmissionPriority {
+    /// The value that represents this priority in a connection export.
+    fn export(self) -> u8 {
+        match self {
+            Self::Critical => 0,
+            Self::Important => 1,
+            Self::High => 2,
+            Self::Normal => 3,
+            Self::Low => 4,
+        }
+    }
+
+    fn import(v: u8) -> Res<Self> {
+        match v {
+            0 => Ok(Self::Critical),
+            1 => Ok(Self::Important),
+            2 => Ok(Self::High),
+            3 => Ok(Self::Normal),
+            4 => Ok(Self::Low),
+            _ => Err(Error::InvalidExport),
+        }
+    }
+}
+
 impl PartialOrd for TransmissionPriority {
     fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
         Some(self.cmp(other))
@@ -135,6 +159,28 @@ impl Default for RetransmissionPriority {
     }
 }
 
+impl RetransmissionPriority {
+    /// The value that represents this priority in a connection export.
+    /// Values below 5 are the fixed priorities.
+    fn export(self) -> u8 {
+        match self {
+            Self::Fixed(p) => p.export(),
+            Self::Same => 5,
+            Self::Higher => 6,
+            Self::MuchHigher => 7,
+        }
+    }
+
+    fn import(v: u8) -> Res<Self> {
+        match v {
+            5 => Ok(Self::Same),
+            6 => Ok(Self::Higher),
+            7 => Ok(Self::MuchHigher),
+            _ => TransmissionPriority::import(v).map(Self::Fixed),
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy)]
 enum RangeState {
     Sent,
@@ -466,8 +512,10 @@ impl RangeTracker {
 /// Buffer to contain queued bytes and track their state.
 #[derive(Debug, Default, PartialEq)]
 pub struct TxBuffer {
-    send_buf: VecDeque<u8>, // buffer of not-acked bytes
-    ranges: RangeTracker,   // ranges in buffer that have been sent or acked
+    send_buf: BTreeMap<u64, Bytes>, // chunks of not-acked bytes, by stream offset
+    buffered: usize,                // total length of the chunks in `send_buf`
+    tail_copied: bool,              // whether the last chunk was copied in by `send`
+    ranges: RangeTracker,           // ranges in buffer that have been sent or acked
 }
 
 impl TxBuffer {
@@ -475,12 +523,49 @@ impl TxBuffer {
         Self::default()
     }
 
+    /// Make a buffer where the first `acked` bytes have already been sent and acknowledged.
+    pub fn with_acked(acked: u64) -> Self {
+        Self {
+            ranges: RangeTracker {
+                acked,
+                used: BTreeMap::new(),
+            },
+            ..Self::default()
+        }
+    }
+
     /// Attempt to add some or all of the passed-in buffer to the TxBuffer.
     pub fn send(&mut self, buf: &[u8]) -> usize {
         let can_buffer = min(SEND_BUFFER_SIZE - self.buffered(), buf.len());
         if can_buffer > 0 {
-            self.send_buf.extend(&buf[..can_buffer]);
-            assert!(self.send_buf.len() <= SEND_BUFFER_SIZE);
+            let offset = self.used();
+            // Small writes are appended to the previous copy rather than adding a chunk each.
+            // As with received data, this is limited so the chunk can be released eventually.
+            match self.send_buf.values_mut().next_back() {
+                Some(tail) if self.tail_copied && tail.len() < 4096 => {
+                    tail.extend_from_slice(&buf[..can_buffer]);
+                }
+                _ => {
+                    self.send_buf
+                        .insert(offset, Bytes::from(&buf[..can_buffer]));
+                    self.tail_copied = true;
+                }
+            }
+            self.buffered += can_buffer;
+            assert!(self.buffered <= SEND_BUFFER_SIZE);
+        }
+        can_buffer
+    }
+
+    /// Attempt to add some or all of the passed-in chunk to the TxBuffer, without copying.
+    pub fn send_bytes(&mut self, buf: Bytes) -> usize {
+        let can_buffer = min(SEND_BUFFER_SIZE - self.buffered(), buf.len());
+        if can_buffer > 0 {
+            let offset = self.used();
+            self.send_buf.insert(offset, buf.slice(..can_buffer));
+            self.tail_copied = false;
+            self.buffered += can_buffer;
+            assert!(self.buffered <= SEND_BUFFER_SIZE);
         }
         can_buffer
     }
@@ -492,20 +577,12 @@ impl TxBuffer {
             return None;
         }
 
-        // Convert from ranges-relative-to-zero to
-        // ranges-relative-to-buffer-start
-        let buff_off = usize::try_from(start - self.retired()).unwrap();
-
-        // Deque returns two slices. Create a subslice from whichever
-        // one contains the first unmarked data.
-        let slc = if buff_off < self.send_buf.as_slices().0.len() {
-            &self.send_buf.as_slices().0[buff_off..]
-        } else {
-            &self.send_buf.as_slices().1[buff_off - self.send_buf.as_slices().0.len()..]
-        };
+        // Create a subslice from the chunk that contains the first unmarked data.
+        let (chunk_start, chunk) = self.send_buf.range(..=start).next_back()?;
+        let slc = &chunk[usize::try_from(start - chunk_start).unwrap()..];
 
         let len = if let Some(range_len) = maybe_len {
-            // Truncate if range crosses deque slices
+            // Truncate if range crosses chunks
             min(usize::try_from(range_len).unwrap(), slc.len())
         } else {
             slc.len()
@@ -528,11 +605,27 @@ impl TxBuffer {
         // Any newly-retired bytes can be dropped from the buffer.
         let new_retirable = self.retired() - prev_retired;
         debug_assert!(new_retirable <= self.buffered() as u64);
-        let keep = self.buffered() - usize::try_from(new_retirable).unwrap();
+        let mut retire = usize::try_from(new_retirable).unwrap();
+        self.buffered -= retire;
 
         // Truncate front
-        self.send_buf.rotate_left(self.buffered() - keep);
-        self.send_buf.truncate(keep);
+        while retire > 0 {
+            let mut front = self.send_buf.first_entry().unwrap();
+            if front.get().len() <= retire {
+                retire -= front.get().len();
+                front.remove();
+            } else {
+                let (offset, mut chunk) = front.remove_entry();
+                chunk.advance(retire);
+                self.send_buf
+                    .insert(offset + u64::try_from(retire).unwrap(), chunk);
+                retire = 0;
+            }
+        }
+        if self.send_buf.len() <= 1 {
+            // Don't grow a chunk that has been partially retired.
+            self.tail_copied = false;
+        }
     }
 
     pub fn mark_as_lost(&mut self, offset: u64, len: usize) {
@@ -549,7 +642,12 @@ impl TxBuffer {
     }
 
     fn buffered(&self) -> usize {
-        self.send_buf.len()
+        self.buffered
+    }
+
+    /// Whether all of the data that was added to the buffer has been acknowledged.
+    pub fn is_empty(&self) -> bool {
+        self.buffered == 0
     }
 
     fn avail(&self) -> usize {
@@ -691,6 +789,8 @@ pub struct SendStream {
     sendorder: Option<SendOrder>,
     bytes_sent: u64,
     fair: bool,
+    // The order in which this stream was added to `SendStreams`.
+    seq: u64,
 }
 
 impl Hash for SendStream {
@@ -726,6 +826,7 @@ impl SendStream {
             sendorder: None,
             bytes_sent: 0,
             fair: false,
+            seq: 0,
         };
         if ss.avail() > 0 {
             ss.conn_events.send_stream_writable(stream_id);
@@ -733,6 +834,132 @@ impl SendStream {
         ss
     }
 
+    pub fn stream_id(&self) -> StreamId {
+        self.stream_id
+    }
+
+    /// Write out the state of the stream for a connection export.  This fails
+    /// unless everything that was written to the stream has been acknowledged.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(self.stream_id.as_u64());
+        match &self.state {
+            SendStreamState::Ready { fc, .. } => {
+                enc.encode_byte(0);
+                fc.export(enc);
+                enc.encode_varint(0_u64);
+            }
+            SendStreamState::Send { fc, send_buf, .. } if send_buf.is_empty() => {
+                enc.encode_byte(0);
+                fc.export(enc);
+                enc.encode_varint(send_buf.retired());
+            }
+            // The sending side is done, but the stream is kept for the receiving side.
+            SendStreamState::DataRecvd { retired, written } => {
+                enc.encode_byte(1);
+                enc.encode_varint(*retired);
+                enc.encode_varint(*written);
+            }
+            SendStreamState::ResetRecvd {
+                final_retired,
+                final_written,
+            } => {
+                enc.encode_byte(2);
+                enc.encode_varint(*final_retired);
+                enc.encode_varint(*final_written);
+            }
+            _ => return Err(Error::NotQuiescent),
+        }
+        enc.encode_byte(self.priority.export());
+        enc.encode_byte(self.retransmission_priority.export());
+        if let Some(sendorder) = self.sendorder {
+            enc.encode_byte(1);
+            enc.encode(&sendorder.to_be_bytes());
+        } else {
+            enc.encode_byte(0);
+        }
+        enc.encode_byte(u8::from(self.fair));
+        Ok(())
+    }
+
+    /// Restore a stream that was written by `export`.
+    pub fn import(
+        dec: &mut Decoder,
+        conn_fc: Rc<RefCell<SenderFlowControl<()>>>,
+        conn_events: ConnectionEvents,
+    ) -> Res<Self> {
+        let stream_id = StreamId::from(dec.decode_varint().ok_or(Error::InvalidExport)?);
+        let (state, bytes_sent) = match dec.decode_byte().ok_or(Error::InvalidExport)? {
+            0 => {
+                let mut fc = SenderFlowControl::new(stream_id, 0);
+                fc.import(dec)?;
+                let acked = dec.decode_varint().ok_or(Error::InvalidExport)?;
+                if acked != fc.used() {
+                    return Err(Error::InvalidExport);
+                }
+                let state = if acked == 0 {
+                    SendStreamState::Ready { fc, conn_fc }
+                } else {
+                    SendStreamState::Send {
+                        fc,
+                        conn_fc,
+                        send_buf: TxBuffer::with_acked(acked),
+                    }
+                };
+                (state, acked)
+            }
+            1 => {
+                let retired = dec.decode_varint().ok_or(Error::InvalidExport)?;
+                let written = dec.decode_varint().ok_or(Error::InvalidExport)?;
+                (SendStreamState::DataRecvd { retired, written }, retired)
+            }
+            2 => {
+                let final_retired = dec.decode_varint().ok_or(Error::InvalidExport)?;
+                let final_written = dec.decode_varint().ok_or(Error::InvalidExport)?;
+                let state = SendStreamState::ResetRecvd {
+                    final_retired,
+                    final_written,
+                };
+                (state, final_retired)
+            }
+            _ => return Err(Error::InvalidExport),
+        };
+        let priority =
+            TransmissionPriority::import(dec.decode_byte().ok_or(Error::InvalidExport)?)?;
+        let retransmission_priority =
+            RetransmissionPriority::import(dec.decode_byte().ok_or(Error::InvalidExport)?)?;
+        let sendorder = match dec.decode_byte().ok_or(Error::InvalidExport)? {
+            0 => None,
+            1 => {
+                let v = dec.decode(8).ok_or(Error::InvalidExport)?;
+                Some(SendOrder::from_be_bytes(
+                    <[u8; 8]>::try_from(v).map_err(|_| Error::InvalidExport)?,
+                ))
+            }
+            _ => return Err(Error::InvalidExport),
+        };
+        let fair = match dec.decode_byte().ok_or(Error::InvalidExport)? {
+            0 => false,
+            1 => true,
+            _ => return Err(Error::InvalidExport),
+        };
+        let ss = Self {
+            stream_id,
+            state,
+            conn_events,
+            priority,
+            retransmission_priority,
+            retransmission_offset: 0,
+            sendorder,
+            bytes_sent,
+            fair,
+            seq: 0,
+        };
+        if ss.avail() > 0 {
+            ss.conn_events.send_stream_writable(stream_id);
+        }
+        Ok(ss)
+    }
+
     pub fn write_frames(
         &mut self,
         priority: TransmissionPriority,
@@ -768,6 +995,22 @@ impl SendStream {
         true
     }
 
+    /// Whether there is a frame to write at any priority: stream data (or a FIN),
+    /// a `RESET_STREAM` or a `STREAM_DATA_BLOCKED`.
+    pub fn has_frames_to_write(&self) -> bool {
+        match &self.state {
+            SendStreamState::Ready { fc, .. } => fc.frame_needed(),
+            SendStreamState::Send { fc, send_buf, .. } => {
+                fc.frame_needed() || send_buf.next_bytes().is_some()
+            }
+            SendStreamState::DataSent {
+                send_buf, fin_sent, ..
+            } => !*fin_sent || send_buf.next_bytes().is_some(),
+            SendStreamState::ResetSent { priority, .. } => priority.is_some(),
+            SendStreamState::DataRecvd { .. } | SendStreamState::ResetRecvd { .. } => false,
+        }
+    }
+
     pub fn set_fairness(&mut self, make_fair: bool) {
         self.fair = make_fair;
     }
@@ -1187,11 +1430,18 @@ impl SendStream {
     }
 
     pub fn send(&mut self, buf: &[u8]) -> Res<usize> {
-        self.send_internal(buf, false)
+        self.send_internal(buf.len(), false, |send_buf, len| send_buf.send(&buf[..len]))
     }
 
     pub fn send_atomic(&mut self, buf: &[u8]) -> Res<usize> {
-        self.send_internal(buf, true)
+        self.send_internal(buf.len(), true, |send_buf, len| send_buf.send(&buf[..len]))
+    }
+
+    /// Like `send`, but the data is kept in the stream buffer without being copied.
+    pub fn send_bytes(&mut self, buf: Bytes) -> Res<usize> {
+        self.send_internal(buf.len(), false, |send_buf, len| {
+            send_buf.send_bytes(buf.slice(..len))
+        })
     }
 
     fn send_blocked_if_space_needed(&mut self, needed_space: usize) {
@@ -1208,8 +1458,15 @@ impl SendStream {
         }
     }
 
-    fn send_internal(&mut self, buf: &[u8], atomic: bool) -> Res<usize> {
-        if buf.is_empty() {
+    /// Checks how much of `len` bytes can be sent and lets `buffer` add that many bytes to the
+    /// send buffer.
+    fn send_internal(
+        &mut self,
+        len: usize,
+        atomic: bool,
+        buffer: impl FnOnce(&mut TxBuffer, usize) -> usize,
+    ) -> Res<usize> {
+        if len == 0 {
             qerror!([self], "zero-length send on stream");
             return Err(Error::InvalidInput);
         }
@@ -1228,17 +1485,17 @@ impl SendStream {
             return Err(Error::FinalSizeError);
         }
 
-        let buf = if buf.is_empty() || (self.avail() == 0) {
+        let len = if self.avail() == 0 {
             return Ok(0);
-        } else if self.avail() < buf.len() {
+        } else if self.avail() < len {
             if atomic {
-                self.send_blocked_if_space_needed(buf.len());
+                self.send_blocked_if_space_needed(len);
                 return Ok(0);
             } else {
-                &buf[..self.avail()]
+                self.avail()
             }
         } else {
-            buf
+            len
         };
 
         match &mut self.state {
@@ -1248,7 +1505,7 @@ impl SendStream {
                 conn_fc,
                 send_buf,
             } => {
-                let sent = send_buf.send(buf);
+                let sent = buffer(send_buf, len);
                 fc.consume(sent);
                 conn_fc.borrow_mut().consume(sent);
                 Ok(sent)
@@ -1337,107 +1594,100 @@ impl ::std::fmt::Display for SendStream {
 
 #[derive(Debug, Default)]
 pub struct OrderGroup {
-    // This vector is sorted by StreamId
-    vec: Vec<StreamId>,
-
-    // Since we need to remember where we were, we'll store the iterator next
-    // position in the object.  This means there can only be a single iterator active
-    // at a time!
-    next: usize,
-    // This is used when an iterator is created to set the start/stop point for the
-    // iteration.  The iterator must iterate from this entry to the end, and then
-    // wrap and iterate from 0 until before the initial value of next.
-    // This value may need to be updated after insertion and removal; in theory we should
-    // track the target entry across modifications, but in practice it should be good
-    // enough to simply leave it alone unless it points past the end of the
-    // Vec, and re-initialize to 0 in that case.
+    // The streams in this group, in stream_id order.
+    ids: BTreeSet<StreamId>,
+
+    // Since we need to remember where we were, we'll store the last stream
+    // that an iterator returned in the object.  This means there can only be a
+    // single iterator active at a time!  The next iterator starts with the
+    // stream that follows this one, wrapping around to the start of the set.
+    // As this is a stream_id rather than a position, it stays valid when
+    // streams are added or removed.
+    last: Option<StreamId>,
 }
 
 pub struct OrderGroupIter<'a> {
     group: &'a mut OrderGroup,
-    // We store the next position in the OrderGroup.
-    // Otherwise we'd need an explicit "done iterating" call to be made, or implement Drop to
-    // copy the value back.
-    // This is where next was when we iterated for the first time; when we get back to that we
-    // stop.
-    started_at: Option<usize>,
+    // This is where the last iterator stopped; once we wrap around and get
+    // back to that stream, we stop.
+    stop: Option<StreamId>,
+    wrapped: bool,
 }
 
 impl OrderGroup {
     pub fn iter(&mut self) -> OrderGroupIter {
-        // Ids may have been deleted since we last iterated
-        if self.next >= self.vec.len() {
-            self.next = 0;
-        }
         OrderGroupIter {
-            started_at: None,
+            stop: self.last,
+            wrapped: false,
             group: self,
         }
     }
 
-    pub fn stream_ids(&self) -> &Vec<StreamId> {
-        &self.vec
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
     }
 
     pub fn clear(&mut self) {
-        self.vec.clear();
-    }
-
-    pub fn push(&mut self, stream_id: StreamId) {
-        self.vec.push(stream_id);
+        self.ids.clear();
     }
 
     #[cfg(test)]
     pub fn truncate(&mut self, position: usize) {
-        self.vec.truncate(position);
-    }
-
-    fn update_next(&mut self) -> usize {
-        let next = self.next;
-        self.next = (self.next + 1) % self.vec.len();
-        next
+        self.ids = self.ids.iter().take(position).copied().collect();
     }
 
     pub fn insert(&mut self, stream_id: StreamId) {
-        match self.vec.binary_search(&stream_id) {
-            Ok(_) => {
-                // element already in vector @ `pos`
-                panic!("Duplicate stream_id {}", stream_id)
-            }
-            Err(pos) => self.vec.insert(pos, stream_id),
-        }
+        self.ids.insert(stream_id);
     }
 
-    pub fn remove(&mut self, stream_id: StreamId) {
-        match self.vec.binary_search(&stream_id) {
-            Ok(pos) => {
-                self.vec.remove(pos);
-            }
-            Err(_) => {
-                // element already in vector @ `pos`
-                panic!("Missing stream_id {}", stream_id)
-            }
-        }
+    /// Returns `true` if the stream was in the group.
+    pub fn remove(&mut self, stream_id: StreamId) -> bool {
+        self.ids.remove(&stream_id)
     }
 }
 
 impl<'a> Iterator for OrderGroupIter<'a> {
     type Item = StreamId;
     fn next(&mut self) -> Option<Self::Item> {
-        // Stop when we would return the started_at element on the next
-        // call.  Note that this must take into account wrapping.
-        if self.started_at == Some(self.group.next) || self.group.vec.is_empty() {
-            return None;
-        }
-        self.started_at = self.started_at.or(Some(self.group.next));
-        let orig = self.group.update_next();
-        Some(self.group.vec[orig])
+        // First take everything after the stream where the last iterator
+        // stopped, then wrap and take everything up to and including it.
+        let next = if self.wrapped {
+            let stop = self.stop?;
+            match self.group.last {
+                Some(last) if last < stop => self
+                    .group
+                    .ids
+                    .range((Bound::Excluded(last), Bound::Included(stop)))
+                    .next(),
+                _ => None,
+            }
+        } else {
+            let after = self.group.last.map_or(Bound::Unbounded, Bound::Excluded);
+            let next = self.group.ids.range((after, Bound::Unbounded)).next();
+            if next.is_none() {
+                self.wrapped = true;
+                let stop = self.stop?;
+                self.group.ids.range(..=stop).next()
+            } else {
+                next
+            }
+        };
+        let next = *next?;
+        self.group.last = Some(next);
+        Some(next)
     }
 }
 
+/// The streams that might have something to send, in the order that they are
+/// visited.  A stream is added when it might have a new frame to write and it
+/// is removed when `write_frames` finds that it has nothing left.  This keeps
+/// the cost of building a packet proportional to the number of active streams,
+/// rather than all of the streams that are open.
 #[derive(Debug, Default)]
-pub(crate) struct SendStreams {
-    map: IndexMap<StreamId, SendStream>,
+struct ReadyStreams {
+    // Streams without fairness, in the order that they were added to
+    // `SendStreams`.
+    unfair: BTreeMap<u64, StreamId>,
 
     // What we really want is a Priority Queue that we can do arbitrary
     // removes from (so we can reprioritize). BinaryHeap doesn't work,
@@ -1449,109 +1699,126 @@ pub(crate) struct SendStreams {
     // 'group' (for WebTransport), but for H3 (and other non-WT streams) we
     // tend to get better pageload performance by prioritizing by creation order.
     //
-    // Two options are to walk the 'map' first, ignoring WebTransport
-    // streams, then process the unordered and ordered WebTransport
-    // streams.  The second is to have a sorted Vec for unfair streams (and
-    // use a normal iterator for that), and then chain the iterators for
-    // the unordered and ordered WebTranport streams.  The first works very
-    // well for H3, and for WebTransport nodes are visited twice on every
-    // processing loop.  The second adds insertion and removal costs, but
-    // avoids a CPU penalty for WebTransport streams.  For now we'll do #1.
-    //
-    // So we use a sorted Vec<> for the regular streams (that's usually all of
-    // them), and then a BTreeMap of an entry for each SendOrder value, and
-    // for each of those entries a Vec of the stream_ids at that
+    // So unfair streams are visited first, in the order in which they were
+    // created.  Then we use a sorted set for the fair streams with no
+    // SendOrder, and then a BTreeMap of an entry for each SendOrder value,
+    // and for each of those entries a sorted set of the stream_ids at that
     // sendorder.  In most cases (such as stream-per-frame), there will be
-    // a single stream at a given sendorder.
+    // a single stream at a given sendorder.  Groups are removed when they
+    // empty, so that a SendOrder that is no longer used costs nothing.
 
-    // These both store stream_ids, which need to be looked up in 'map'.
+    // These all store stream_ids, which need to be looked up in 'map'.
     // This avoids the complexity of trying to hold references to the
     // Streams which are owned by the IndexMap.
     sendordered: BTreeMap<SendOrder, OrderGroup>,
     regular: OrderGroup, // streams with no SendOrder set, sorted in stream_id order
 }
 
+impl ReadyStreams {
+    fn insert(&mut self, stream: &SendStream) {
+        if !stream.is_fair() {
+            self.unfair.insert(stream.seq, stream.stream_id);
+        } else if let Some(order) = stream.sendorder() {
+            self.sendordered
+                .entry(order)
+                .or_default()
+                .insert(stream.stream_id);
+        } else {
+            self.regular.insert(stream.stream_id);
+        }
+    }
+
+    /// Returns `true` if the stream was ready.
+    fn remove(&mut self, stream: &SendStream) -> bool {
+        if !stream.is_fair() {
+            self.unfair.remove(&stream.seq).is_some()
+        } else if let Some(order) = stream.sendorder() {
+            if let Entry::Occupied(mut group) = self.sendordered.entry(order) {
+                let removed = group.get_mut().remove(stream.stream_id);
+                if group.get().is_empty() {
+                    group.remove();
+                }
+                removed
+            } else {
+                false
+            }
+        } else {
+            self.regular.remove(stream.stream_id)
+        }
+    }
+
+    fn clear(&mut self) {
+        self.unfair.clear();
+        self.sendordered.clear();
+        self.regular.clear();
+    }
+}
+
+/// All of the send streams.
+///
+/// Every mutable access to a stream through this type marks the stream as
+/// ready, as the caller might have given the stream something to send.
+#[derive(Debug, Default)]
+pub(crate) struct SendStreams {
+    map: IndexMap<StreamId, SendStream>,
+    ready: ReadyStreams,
+    next_seq: u64,
+}
+
 impl SendStreams {
     pub fn get(&self, id: StreamId) -> Res<&SendStream> {
         self.map.get(&id).ok_or(Error::InvalidStreamId)
     }
 
     pub fn get_mut(&mut self, id: StreamId) -> Res<&mut SendStream> {
-        self.map.get_mut(&id).ok_or(Error::InvalidStreamId)
+        let stream = self.map.get_mut(&id).ok_or(Error::InvalidStreamId)?;
+        self.ready.insert(stream);
+        Ok(stream)
     }
 
     pub fn exists(&self, id: StreamId) -> bool {
         self.map.contains_key(&id)
     }
 
-    pub fn insert(&mut self, id: StreamId, stream: SendStream) {
-        self.map.insert(id, stream);
-    }
-
-    fn group_mut(&mut self, sendorder: Option<SendOrder>) -> &mut OrderGroup {
-        if let Some(order) = sendorder {
-            self.sendordered.entry(order).or_default()
-        } else {
-            &mut self.regular
+    pub fn insert(&mut self, id: StreamId, mut stream: SendStream) {
+        stream.seq = self.next_seq;
+        self.next_seq += 1;
+        if stream.has_frames_to_write() {
+            self.ready.insert(&stream);
         }
+        self.map.insert(id, stream);
     }
 
     pub fn set_sendorder(&mut self, stream_id: StreamId, sendorder: Option<SendOrder>) -> Res<()> {
         self.set_fairness(stream_id, true)?;
-        if let Some(stream) = self.map.get_mut(&stream_id) {
-            // don't grab stream here; causes borrow errors
-            let old_sendorder = stream.sendorder();
-            if old_sendorder != sendorder {
-                // we have to remove it from the list it was in, and reinsert it with the new
-                // sendorder key
-                let mut group = self.group_mut(old_sendorder);
-                group.remove(stream_id);
-                self.get_mut(stream_id).unwrap().set_sendorder(sendorder);
-                group = self.group_mut(sendorder);
-                group.insert(stream_id);
-                qtrace!(
-                    "ordering of stream_ids: {:?}",
-                    self.sendordered.values().collect::<Vec::<_>>()
-                );
+        let stream = self.map.get_mut(&stream_id).ok_or(Error::InvalidStreamId)?;
+        if stream.sendorder() != sendorder {
+            // If the stream is ready, we have to remove it from the group it
+            // was in, and reinsert it with the new sendorder key.
+            let ready = self.ready.remove(stream);
+            stream.set_sendorder(sendorder);
+            if ready {
+                self.ready.insert(stream);
             }
-            Ok(())
-        } else {
-            Err(Error::InvalidStreamId)
+            qtrace!(
+                "ordering of ready stream_ids: {:?}",
+                self.ready.sendordered.values().collect::<Vec::<_>>()
+            );
         }
+        Ok(())
     }
 
     pub fn set_fairness(&mut self, stream_id: StreamId, make_fair: bool) -> Res<()> {
         let stream: &mut SendStream = self.map.get_mut(&stream_id).ok_or(Error::InvalidStreamId)?;
-        let was_fair = stream.fair;
-        stream.set_fairness(make_fair);
-        if !was_fair && make_fair {
-            // Move to the regular OrderGroup.
-
-            // We know sendorder can't have been set, since
-            // set_sendorder() will call this routine if it's not
-            // already set as fair.
-
-            // This normally is only called when a new stream is created.  If
-            // so, because of how we allocate StreamIds, it should always have
-            // the largest value.  This means we can just append it to the
-            // regular vector.  However, if we were ever to change this
-            // invariant, things would break subtly.
-
-            // To be safe we can try to insert at the end and if not
-            // fall back to binary-search insertion
-            if matches!(self.regular.stream_ids().last(), Some(last) if stream_id > *last) {
-                self.regular.push(stream_id);
-            } else {
-                self.regular.insert(stream_id);
+        if stream.is_fair() != make_fair {
+            // Move a ready stream between the unfair list and the regular
+            // OrderGroup.  A stream that is made unfair keeps any sendorder
+            // it has, but that is only used if it is made fair again.
+            let ready = self.ready.remove(stream);
+            stream.set_fairness(make_fair);
+            if ready {
+                self.ready.insert(stream);
             }
-        } else if was_fair && !make_fair {
-            // remove from the OrderGroup
-            let group = if let Some(sendorder) = stream.sendorder {
-                self.sendordered.get_mut(&sendorder).unwrap()
-            } else {
-                &mut self.regular
-            };
-            group.remove(stream_id);
         }
         Ok(())
     }
@@ -1569,48 +1836,47 @@ impl SendStreams {
     }
 
     pub fn lost(&mut self, token: &SendStreamRecoveryToken) {
-        if let Some(ss) = self.map.get_mut(&token.id) {
+        if let Ok(ss) = self.get_mut(token.id) {
             ss.mark_as_lost(token.offset, token.length, token.fin);
         }
     }
 
     pub fn reset_lost(&mut self, stream_id: StreamId) {
-        if let Some(ss) = self.map.get_mut(&stream_id) {
+        if let Ok(ss) = self.get_mut(stream_id) {
             ss.reset_lost();
         }
     }
 
     pub fn blocked_lost(&mut self, stream_id: StreamId, limit: u64) {
-        if let Some(ss) = self.map.get_mut(&stream_id) {
+        if let Ok(ss) = self.get_mut(stream_id) {
             ss.blocked_lost(limit);
         }
     }
 
     pub fn clear(&mut self) {
         self.map.clear();
-        self.sendordered.clear();
-        self.regular.clear();
+        self.ready.clear();
+    }
+
+    /// Write out all of the streams for a connection export.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(u64::try_from(self.map.len())?);
+        for stream in self.map.values() {
+            stream.export(enc)?;
+        }
+        Ok(())
     }
 
     pub fn remove_terminal(&mut self) {
         let map: &mut IndexMap<StreamId, SendStream> = &mut self.map;
-        let regular: &mut OrderGroup = &mut self.regular;
-        let sendordered: &mut BTreeMap<SendOrder, OrderGroup> = &mut self.sendordered;
+        let ready: &mut ReadyStreams = &mut self.ready;
 
         // Take refs to all the items we need to modify instead of &mut
         // self to keep the compiler happy (if we use self.map.retain it
         // gets upset due to borrows)
-        map.retain(|stream_id, stream| {
+        map.retain(|_, stream| {
             if stream.is_terminal() {
-                if stream.is_fair() {
-                    match stream.sendorder() {
-                        None => regular.remove(*stream_id),
-                        Some(sendorder) => {
-                            sendordered.get_mut(&sendorder).unwrap().remove(*stream_id);
-                        }
-                    };
-                }
-                // if unfair, we're done
+                ready.remove(stream);
                 return false;
             }
             true
@@ -1647,44 +1913,44 @@ impl SendStreams {
         // SendOrdered streams.
         //
         // Fairness is implemented by a round-robining or "statefully
-        // iterating" within a single sendorder/unordered vector.  We do
+        // iterating" within a single sendorder/unordered set.  We do
         // this by recording where we stopped in the previous pass, and
-        // starting there the next pass.  If we store an index into the
-        // vec, this means we can't use a chained iterator, since we want
-        // to retain our place-in-the-vector.  If we rotate the vector,
-        // that would let us use the chained iterator, but would require
-        // more expensive searches for insertion and removal (since the
-        // sorted order would be lost).
-
-        // Iterate the map, but only those without fairness, then iterate
-        // OrderGroups, then iterate each group
-        qdebug!("processing streams...  unfair:");
-        for stream in self.map.values_mut() {
-            if !stream.is_fair() {
-                qdebug!("   {}", stream);
-                if !stream.write_frames_with_early_return(priority, builder, tokens, stats) {
-                    break;
-                }
-            }
-        }
-        qdebug!("fair streams:");
-        let stream_ids = self.regular.iter().chain(
-            self.sendordered
-                .values_mut()
-                .rev()
-                .flat_map(|group| group.iter()),
-        );
-        for stream_id in stream_ids {
-            let stream = self.map.get_mut(&stream_id).unwrap();
+        // starting there the next pass.
+
+        // Only ready streams are visited: the unfair ones first, then
+        // OrderGroups, then each group.  Any stream that has nothing left to
+        // send is no longer ready, but it can't be removed while we iterate.
+        let map = &mut self.map;
+        let ready = &mut self.ready;
+        let mut idle = Vec::new();
+        let mut visit = |stream_id: StreamId| {
+            let stream = map.get_mut(&stream_id).unwrap();
             if let Some(order) = stream.sendorder() {
-                qdebug!("   {} ({})", stream_id, order)
+                qdebug!("   {} ({})", stream, order);
             } else {
-                qdebug!("   None")
+                qdebug!("   {}", stream);
             }
-            if !stream.write_frames_with_early_return(priority, builder, tokens, stats) {
+            let more = stream.write_frames_with_early_return(priority, builder, tokens, stats);
+            if !stream.has_frames_to_write() {
+                idle.push(stream_id);
+            }
+            more
+        };
+
+        qdebug!("processing streams...  unfair:");
+        ready.unfair.values().all(|stream_id| visit(*stream_id));
+        // Even if the unfair streams filled the packet, the next fair stream is
+        // visited, which uses up its turn.
+        qdebug!("fair streams:");
+        for group in iter::once(&mut ready.regular).chain(ready.sendordered.values_mut().rev()) {
+            if !group.iter().all(&mut visit) {
                 break;
             }
         }
+
+        for stream_id in idle {
+            ready.remove(&map[&stream_id]);
+        }
     }
 
     pub fn update_initial_limit(&mut self, remote: &TransportParameters) {
@@ -1698,6 +1964,18 @@ impl SendStreams {
             ss.set_max_stream_data(limit);
         }
     }
+
+    #[cfg(test)]
+    pub fn ready_count(&self) -> usize {
+        self.ready.unfair.len()
+            + self.ready.regular.ids.len()
+            + self
+                .ready
+                .sendordered
+                .values()
+                .map(|group| group.ids.len())
+                .sum::<usize>()
+    }
 }
 
 impl<'a> IntoIterator for &'a mut SendStreams {
@@ -1719,6 +1997,8 @@ pub struct SendStreamRecoveryToken {
 
 #[cfg(test)]
 mod tests {
+    use std::collections::VecDeque;
+
     use neqo_common::{event::Provider, hex_with_len, qtrace};
 
     use super::*;
@@ -2366,6 +2646,33 @@ mod tests {
         assert_eq!(res, None);
     }
 
+    #[test]
+    fn tx_buffer_send_bytes() {
+        let mut tx = TxBuffer::new();
+        assert_eq!(tx.send(&[1; 10]), 10);
+        assert_eq!(tx.send_bytes(Bytes::from(vec![2; 20])), 20);
+        assert_eq!(tx.send(&[3; 5]), 5);
+        assert_eq!(tx.send(&[4; 5]), 5);
+        assert_eq!(tx.buffered(), 40);
+        assert_eq!(tx.send_buf.len(), 3);
+
+        // Data is returned one chunk at a time.
+        assert_eq!(tx.next_bytes(), Some((0, &[1; 10][..])));
+        tx.mark_as_sent(0, 15);
+        assert_eq!(tx.next_bytes(), Some((15, &[2; 15][..])));
+        tx.mark_as_sent(15, 15);
+        let (offset, data) = tx.next_bytes().unwrap();
+        assert_eq!(offset, 30);
+        assert_eq!(data, &[3, 3, 3, 3, 3, 4, 4, 4, 4, 4][..]);
+
+        // Acknowledging part of a chunk keeps the rest.
+        tx.mark_as_acked(0, 12);
+        assert_eq!(tx.buffered(), 28);
+        assert_eq!(tx.send_buf.len(), 2);
+        tx.mark_as_lost(12, 3);
+        assert_eq!(tx.next_bytes(), Some((12, &[2; 3][..])));
+    }
+
     #[test]
     fn send_stream_writable_event_gen() {
         let conn_fc = connection_fc(2);
@@ -2618,6 +2925,44 @@ mod tests {
         assert!(as_stream_token(&f4_token).fin);
     }
 
+    #[test]
+    // Unfair streams go first, then fair streams without a sendorder, then those with the
+    // highest sendorder.  A packet that an unfair stream fills still uses up a fair turn.
+    fn send_streams_interleave() {
+        let conn_fc = connection_fc(10_000);
+        let conn_events = ConnectionEvents::default();
+        let mut ss = SendStreams::default();
+        for (id, len) in [(2, 8), (6, 8), (10, 8), (14, 100), (18, 100)] {
+            let id = StreamId::from(id);
+            let mut s = SendStream::new(id, 1000, Rc::clone(&conn_fc), conn_events.clone());
+            s.send(&vec![0; len]).unwrap();
+            ss.insert(id, s);
+        }
+        ss.set_fairness(StreamId::from(6), true).unwrap();
+        ss.set_fairness(StreamId::from(10), true).unwrap();
+        ss.set_sendorder(StreamId::from(14), Some(1)).unwrap();
+        ss.set_sendorder(StreamId::from(18), Some(1)).unwrap();
+
+        // Each packet has room for 8 bytes of data at offset 0, so every
+        // stream that has data fills it.
+        let mut builder = PacketBuilder::short(Encoder::new(), false, []);
+        let mut sent = Vec::new();
+        for _ in 0..6 {
+            let mut tokens = Vec::new();
+            builder.set_limit(builder.len() + 10);
+            ss.write_frames(
+                TransmissionPriority::default(),
+                &mut builder,
+                &mut tokens,
+                &mut FrameStats::default(),
+            );
+            assert_eq!(tokens.len(), 1);
+            sent.push(as_stream_token(&tokens[0]).id.as_u64());
+        }
+        // Stream 6 loses its turn to the packet that stream 2 filled.
+        assert_eq!(sent, [2, 10, 6, 14, 18, 14]);
+    }
+
     #[test]
     fn data_blocked() {
         let conn_fc = connection_fc(5);
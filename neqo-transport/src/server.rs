@@ -23,14 +23,15 @@ use neqo_common::{
     timer::Timer, Datagram, Decoder, Role,
 };
 use neqo_crypto::{
-    encode_ech_config, AntiReplay, Cipher, PrivateKey, PublicKey, ZeroRttCheckResult,
-    ZeroRttChecker,
+    encode_ech_config, AntiReplay, CertificateCompression, CertificateVerifier, Cipher, PrivateKey,
+    PublicKey, ServerNameSelector, ZeroRttCheckResult, ZeroRttChecker,
 };
 use qlog::streamer::QlogStreamer;
 
 pub use crate::addr_valid::ValidateAddress;
 use crate::{
     addr_valid::{AddressValidation, AddressValidationResult},
+    capture::PacketCapture,
     cid::{ConnectionId, ConnectionIdDecoder, ConnectionIdGenerator, ConnectionIdRef},
     connection::{Connection, Output, State},
     packet::{PacketBuilder, PacketType, PublicPacket},
@@ -156,6 +157,8 @@ pub struct Server {
     protocols: Vec<String>,
     /// The cipher suites that the server supports.
     ciphers: Vec<Cipher>,
+    /// The certificate compression algorithms that the server supports.
+    cert_compression: Vec<CertificateCompression>,
     /// Anti-replay configuration for 0-RTT.
     anti_replay: AntiReplay,
     /// A function for determining if 0-RTT can be accepted.
@@ -180,8 +183,14 @@ pub struct Server {
     address_validation: Rc<RefCell<AddressValidation>>,
     /// Directory to create qlog traces in
     qlog_dir: Option<PathBuf>,
+    /// Directory to create packet captures in
+    pcap_dir: Option<PathBuf>,
     /// Encrypted client hello (ECH) configuration.
     ech_config: Option<EchConfig>,
+    /// Picks certificates based on the server name indication (SNI).
+    sni_selector: Option<Rc<dyn ServerNameSelector>>,
+    /// Checks client certificates, and whether clients need to present one.
+    client_auth: Option<(CertificateVerifier, bool)>,
 }
 
 impl Server {
@@ -209,6 +218,7 @@ impl Server {
             certs: certs.iter().map(|x| String::from(x.as_ref())).collect(),
             protocols: protocols.iter().map(|x| String::from(x.as_ref())).collect(),
             ciphers: Vec::new(),
+            cert_compression: Vec::new(),
             anti_replay,
             zero_rtt_checker: ServerZeroRttChecker::new(zero_rtt_checker),
             cid_generator,
@@ -220,7 +230,10 @@ impl Server {
             timers: Timer::new(now, TIMER_GRANULARITY, TIMER_CAPACITY),
             address_validation: Rc::new(RefCell::new(validation)),
             qlog_dir: None,
+            pcap_dir: None,
             ech_config: None,
+            sni_selector: None,
+            client_auth: None,
         })
     }
 
@@ -229,6 +242,12 @@ impl Server {
         self.qlog_dir = dir;
     }
 
+    /// Set or clear directory to create pcapng captures of each connection in.
+    /// The captures include the TLS secrets for the connection.
+    pub fn set_pcap_dir(&mut self, dir: Option<PathBuf>) {
+        self.pcap_dir = dir;
+    }
+
     /// Set the policy for address validation.
     pub fn set_validation(&mut self, v: ValidateAddress) {
         self.address_validation.borrow_mut().set_validation(v);
@@ -240,6 +259,14 @@ impl Server {
         self.ciphers = Vec::from(ciphers.as_ref());
     }
 
+    /// Set the certificate compression algorithms that can be used.
+    pub fn set_certificate_compression(
+        &mut self,
+        algorithms: impl AsRef<[CertificateCompression]>,
+    ) {
+        self.cert_compression = Vec::from(algorithms.as_ref());
+    }
+
     pub fn enable_ech(
         &mut self,
         config: u8,
@@ -255,6 +282,18 @@ impl Server {
         self.ech_config.as_ref().map_or(&[], |cfg| &cfg.encoded)
     }
 
+    /// Set or clear the object that selects certificates based on the server name that clients
+    /// send.  This only affects new connections.
+    pub fn set_sni_selector(&mut self, selector: Option<Rc<dyn ServerNameSelector>>) {
+        self.sni_selector = selector;
+    }
+
+    /// Ask clients for certificates, which are checked with `verifier`.  If `required` is
+    /// false, clients can connect without one.  This only affects new connections.
+    pub fn request_client_certificate(&mut self, verifier: CertificateVerifier, required: bool) {
+        self.client_auth = Some((verifier, required));
+    }
+
     fn remove_timer(&mut self, c: &StateRef) {
         let last = c.borrow().last_timer;
         self.timers.remove(last, |t| Rc::ptr_eq(t, c));
@@ -443,6 +482,27 @@ impl Server {
         }
     }
 
+    fn create_capture(&self, odcid: ConnectionIdRef<'_>) -> Option<PacketCapture> {
+        let mut path = self.pcap_dir.as_ref()?.to_path_buf();
+        path.push(format!("{}.pcapng", odcid));
+
+        // As with qlog, this won't overwrite an existing file.
+        match PacketCapture::create(&path) {
+            Ok(capture) => {
+                qinfo!("Packet capture to {}", path.display());
+                Some(capture)
+            }
+            Err(e) => {
+                qerror!(
+                    "Could not open file {} for packet capture: {}",
+                    path.display(),
+                    e
+                );
+                None
+            }
+        }
+    }
+
     fn setup_connection(
         &mut self,
         c: &mut Connection,
@@ -460,6 +520,7 @@ impl Server {
         }
         c.set_validation(Rc::clone(&self.address_validation));
         c.set_qlog(self.create_qlog_trace(attempt_key.odcid.as_cid_ref()));
+        c.set_capture(self.create_capture(attempt_key.odcid.as_cid_ref()));
         if let Some(cfg) = &self.ech_config {
             if c.server_enable_ech(cfg.config, &cfg.public_name, &cfg.sk, &cfg.pk)
                 .is_err()
@@ -467,6 +528,17 @@ impl Server {
                 qwarn!([self], "Unable to enable ECH");
             }
         }
+        if let Some(selector) = &self.sni_selector {
+            if c.server_enable_sni(Rc::clone(selector)).is_err() {
+                qwarn!([self], "Unable to enable SNI");
+            }
+        }
+        if !self.cert_compression.is_empty()
+            && c.set_certificate_compression(&self.cert_compression)
+                .is_err()
+        {
+            qwarn!([self], "Unable to enable certificate compression");
+        }
     }
 
     fn accept_connection(
@@ -495,7 +567,14 @@ impl Server {
             &self.protocols,
             Rc::clone(&cid_mgr) as _,
             params,
-        );
+        )
+        .and_then(|mut c| {
+            // Don't accept connections without client authentication if it was asked for.
+            if let Some((verifier, required)) = &self.client_auth {
+                c.server_request_client_certificate(verifier.clone(), *required)?;
+            }
+            Ok(c)
+        });
 
         match sconn {
             Ok(mut c) => {
@@ -678,6 +757,88 @@ impl Server {
             })
     }
 
+    /// Export the state of every connection that can be handed off to another
+    /// process, see `Connection::export`.  Exported connections are removed from
+    /// this server; connections that aren't quiescent are left in place.
+    /// As with `Connection::export`, the output contains traffic secrets.
+    pub fn export(&mut self) -> Vec<Vec<u8>> {
+        let mut conns: Vec<StateRef> = Vec::new();
+        for c in self.connections.borrow().values() {
+            if !conns.iter().any(|x| Rc::ptr_eq(x, c)) {
+                conns.push(Rc::clone(c));
+            }
+        }
+
+        let mut exported = Vec::new();
+        for c in conns {
+            let res = c.borrow_mut().export();
+            match res {
+                Ok(state) => {
+                    qinfo!([self], "Exported connection {:?}", c);
+                    exported.push(state);
+                    self.remove_timer(&c);
+                    self.waiting.retain(|x| !Rc::ptr_eq(x, &c));
+                    self.active.retain(|x| !Rc::ptr_eq(&x.c, &c));
+                    c.borrow_mut().set_qlog(NeqoQlog::disabled());
+                    self.connections
+                        .borrow_mut()
+                        .retain(|_, v| !Rc::ptr_eq(v, &c));
+                }
+                Err(e) => {
+                    qdebug!([self], "Unable to export connection {:?}: {:?}", c, e);
+                }
+            }
+        }
+        exported
+    }
+
+    /// Resume a connection that was exported by another server, using `local_addr`
+    /// as the local address.  The `cid_generator` for this server has to be able
+    /// to decode the connection IDs of the exported connection.
+    ///
+    /// # Errors
+    ///
+    /// `InvalidExport` if `state` was exported from a client connection; otherwise,
+    /// see `Connection::import`.
+    pub fn import(
+        &mut self,
+        state: impl AsRef<[u8]>,
+        local_addr: SocketAddr,
+        now: Instant,
+    ) -> Res<ActiveConnectionRef> {
+        let cid_mgr = Rc::new(RefCell::new(ServerConnectionIdGenerator {
+            c: Weak::new(),
+            cid_generator: Rc::clone(&self.cid_generator),
+            connections: Rc::clone(&self.connections),
+            saved_cids: Vec::new(),
+        }));
+        let mut c = Connection::import(
+            state,
+            Rc::clone(&cid_mgr) as _,
+            local_addr,
+            self.conn_params.clone(),
+            now,
+        )?;
+        if c.role() != Role::Server {
+            return Err(crate::Error::InvalidExport);
+        }
+        c.set_validation(Rc::clone(&self.address_validation));
+        qinfo!([self], "Imported connection {}", c);
+
+        // Route packets for the connection IDs that the imported connection issued,
+        // rather than those that were generated while creating the connection.
+        cid_mgr.borrow_mut().saved_cids = c.local_connection_ids().cloned().collect();
+        let c = Rc::new(RefCell::new(ServerConnectionState {
+            c,
+            last_timer: now,
+            active_attempt: None,
+        }));
+        cid_mgr.borrow_mut().set_connection(Rc::clone(&c));
+        // The connection is processed on the next call to `process()`.
+        self.waiting.push_back(Rc::clone(&c));
+        Ok(ActiveConnectionRef { c })
+    }
+
     /// This lists the connections that have received new events
     /// as a result of calling `process()`.
     pub fn active_connections(&mut self) -> Vec<ActiveConnectionRef> {
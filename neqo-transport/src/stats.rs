@@ -16,6 +16,7 @@ use std::{
 };
 
 use neqo_common::qinfo;
+use neqo_crypto::CertificateCompressionInfo;
 
 use crate::packet::PacketNumber;
 
@@ -109,6 +110,9 @@ pub struct DatagramStats {
     /// The number of datagrams dropped due to reaching the limit of the
     /// outgoing queue.
     pub dropped_queue_full: usize,
+    /// The number of datagrams dropped because their deadline passed
+    /// before they could be sent.
+    pub expired: usize,
 }
 
 /// Connection statistics
@@ -138,6 +142,9 @@ pub struct Stats {
 
     /// Whether the connection was resumed successfully.
     pub resumed: bool,
+    /// The compressed and uncompressed sizes of the certificate message,
+    /// if the certificate was compressed.
+    pub cert_compression: Option<CertificateCompressionInfo>,
 
     /// The current, estimated round-trip time on the primary path.
     pub rtt: Duration,
@@ -155,6 +162,13 @@ pub struct Stats {
     /// Count frames sent.
     pub frame_tx: FrameStats,
 
+    /// The number of ACK ranges that were discarded because too many ranges were tracked.
+    pub ack_ranges_discarded: usize,
+    /// The number of discarded ACK ranges that the peer hadn't received an ACK for.
+    pub ack_ranges_discarded_unacked: usize,
+    /// The number of times that an ACK range was left out of an ACK frame for lack of space.
+    pub ack_ranges_omitted: usize,
+
     /// The number of incoming datagrams dropped due to reaching the limit
     /// of the incoming queue.
     pub incoming_datagram_dropped: usize,
@@ -208,6 +222,18 @@ impl Debug for Stats {
             self.packets_tx, self.lost, self.late_ack, self.pto_ack
         )?;
         writeln!(f, "  resumed: {} ", self.resumed)?;
+        writeln!(
+            f,
+            "  ack ranges: discarded {} unacked {} omitted {}",
+            self.ack_ranges_discarded, self.ack_ranges_discarded_unacked, self.ack_ranges_omitted
+        )?;
+        if let Some(c) = &self.cert_compression {
+            writeln!(
+                f,
+                "  certificate: {:?} {} -> {}",
+                c.algorithm, c.uncompressed, c.compressed
+            )?;
+        }
         writeln!(f, "  frames rx:")?;
         self.frame_rx.fmt(f)?;
         writeln!(f, "  frames tx:")?;
@@ -5,9 +5,9 @@
 // except according to those terms.
 
 // Stream management for a connection.
-use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+use std::{cell::RefCell, cmp::Ordering, convert::TryFrom, rc::Rc};
 
-use neqo_common::{qtrace, qwarn, Role};
+use neqo_common::{qtrace, qwarn, Decoder, Encoder, Role};
 
 use crate::{
     fc::{LocalStreamLimits, ReceiverFlowControl, RemoteStreamLimits, SenderFlowControl},
@@ -519,6 +519,38 @@ impl Streams {
         }
     }
 
+    /// Write out flow control state and all of the streams for a connection export.
+    /// Streams that are done are removed first.
+    pub fn export(&mut self, enc: &mut Encoder) -> Res<()> {
+        self.cleanup_closed_streams();
+        self.sender_fc.borrow().export(enc);
+        self.receiver_fc.borrow().export(enc);
+        for stream_type in [StreamType::BiDi, StreamType::UniDi] {
+            self.remote_stream_limits[stream_type].export(enc);
+            self.local_stream_limits[stream_type].export(enc);
+        }
+        self.send.export(enc)?;
+        self.recv.export(enc)
+    }
+
+    /// Restore the state that was written by `export`.  This replaces
+    /// what `set_initial_limits` established.
+    pub fn import(&mut self, dec: &mut Decoder) -> Res<()> {
+        self.sender_fc.borrow_mut().import(dec)?;
+        self.receiver_fc.borrow_mut().import(dec)?;
+        for stream_type in [StreamType::BiDi, StreamType::UniDi] {
+            self.remote_stream_limits[stream_type].import(dec)?;
+            self.local_stream_limits[stream_type].import(dec)?;
+        }
+
+        let count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        for _ in 0..count {
+            let stream = SendStream::import(dec, Rc::clone(&self.sender_fc), self.events.clone())?;
+            self.send.insert(stream.stream_id(), stream);
+        }
+        self.recv.import(dec, &self.receiver_fc, &self.events)
+    }
+
     pub fn handle_max_streams(&mut self, stream_type: StreamType, maximum_streams: u64) {
         if self.local_stream_limits[stream_type].update(maximum_streams) {
             self.events.send_stream_creatable(stream_type);
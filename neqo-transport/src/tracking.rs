@@ -9,21 +9,22 @@
 #![deny(clippy::pedantic)]
 
 use std::{
-    cmp::min,
+    cmp::{max, min},
     collections::VecDeque,
     convert::TryFrom,
-    ops::{Index, IndexMut},
+    mem,
+    ops::{Index, IndexMut, RangeInclusive},
     time::{Duration, Instant},
 };
 
-use neqo_common::{qdebug, qinfo, qtrace, qwarn};
+use neqo_common::{qdebug, qinfo, qtrace, qwarn, Decoder, Encoder};
 use neqo_crypto::{Epoch, TLS_EPOCH_HANDSHAKE, TLS_EPOCH_INITIAL};
 use smallvec::{smallvec, SmallVec};
 
 use crate::{
     packet::{PacketBuilder, PacketNumber, PacketType},
     recovery::RecoveryToken,
-    stats::FrameStats,
+    stats::StatsCell,
     Error, Res,
 };
 
@@ -242,6 +243,244 @@ impl SentPacket {
     }
 }
 
+/// An entry in `SentPackets`.
+#[derive(Debug)]
+enum SentSlot {
+    Sent(SentPacket),
+    /// No packet is tracked here.  This holds the packet number of a later
+    /// entry, where all of the entries before that are also gaps.  That lets
+    /// `take_range` skip over ranges that were acknowledged before.
+    Gap(PacketNumber),
+}
+
+/// The packets that were sent in a packet number space and are still tracked,
+/// indexed by packet number.
+///
+/// Packet numbers only increase, so this is a ring buffer that starts at the
+/// oldest packet that is still tracked.  Removing a packet leaves a gap, which
+/// is reclaimed once the packets before it are gone.  That makes adding and
+/// removing packets amortized O(1), and iteration is in packet number order.
+#[derive(Debug, Default)]
+pub struct SentPackets {
+    /// The packet number of the first entry in `packets`.
+    offset: PacketNumber,
+    packets: VecDeque<SentSlot>,
+    /// The number of packets that are tracked, not counting gaps.
+    len: usize,
+}
+
+impl SentPackets {
+    /// The number of packets that are tracked.
+    #[must_use]
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    fn slot(&mut self, pn: PacketNumber) -> &mut SentSlot {
+        &mut self.packets[usize::try_from(pn - self.offset).unwrap()]
+    }
+
+    /// Start tracking a packet.
+    ///
+    /// # Panics
+    ///
+    /// If the packet number is not larger than that of every packet that is tracked.
+    pub fn track(&mut self, packet: SentPacket) {
+        if self.packets.is_empty() {
+            self.offset = packet.pn;
+        }
+        let index = packet
+            .pn
+            .checked_sub(self.offset)
+            .and_then(|i| usize::try_from(i).ok())
+            .filter(|&i| i >= self.packets.len())
+            .expect("sent packets are tracked in order");
+        // Any packet numbers that were skipped are left as gaps.
+        let pn = packet.pn;
+        self.packets.resize_with(index, || SentSlot::Gap(pn));
+        self.packets.push_back(SentSlot::Sent(packet));
+        self.len += 1;
+    }
+
+    /// Drop gaps from both ends.
+    fn trim(&mut self) {
+        while let Some(SentSlot::Gap(_)) = self.packets.front() {
+            self.packets.pop_front();
+            self.offset += 1;
+        }
+        while let Some(SentSlot::Gap(_)) = self.packets.back() {
+            self.packets.pop_back();
+        }
+    }
+
+    /// Stop tracking the packets in `range`.  Those packets are added to `removed`,
+    /// largest packet number first.
+    pub fn take_range(
+        &mut self,
+        range: &RangeInclusive<PacketNumber>,
+        removed: &mut Vec<SentPacket>,
+    ) {
+        let end = self.offset + u64::try_from(self.packets.len()).unwrap();
+        let start = max(*range.start(), self.offset);
+        let stop = min(range.end().saturating_add(1), end);
+        if start >= stop {
+            return;
+        }
+
+        // Take packets in ascending order, following the links in gaps.
+        let first_removed = removed.len();
+        let mut pn = start;
+        while pn < stop {
+            let next = match mem::replace(self.slot(pn), SentSlot::Gap(pn + 1)) {
+                SentSlot::Sent(p) => {
+                    self.len -= 1;
+                    removed.push(p);
+                    pn + 1
+                }
+                SentSlot::Gap(next) => next,
+            };
+            *self.slot(pn) = SentSlot::Gap(next);
+            pn = next;
+        }
+        removed[first_removed..].reverse();
+
+        // Everything from `start` to `pn` is now a gap, so link each
+        // of the gaps that were visited directly to `pn`.
+        let last = min(pn, end);
+        let mut visit = start;
+        while visit < last {
+            let SentSlot::Gap(next) = mem::replace(self.slot(visit), SentSlot::Gap(last)) else {
+                unreachable!();
+            };
+            visit = next;
+        }
+        self.trim();
+    }
+
+    /// Stop tracking the longest run of the oldest packets for which `f` is true.
+    pub fn take_oldest<F>(&mut self, mut f: F) -> Vec<SentPacket>
+    where
+        F: FnMut(&SentPacket) -> bool,
+    {
+        let count = self
+            .packets
+            .iter()
+            .take_while(|s| match s {
+                SentSlot::Sent(p) => f(p),
+                SentSlot::Gap(_) => true,
+            })
+            .count();
+        let removed = self
+            .packets
+            .drain(..count)
+            .filter_map(|s| match s {
+                SentSlot::Sent(p) => Some(p),
+                SentSlot::Gap(_) => None,
+            })
+            .collect::<Vec<_>>();
+        self.offset += u64::try_from(count).unwrap();
+        self.len -= removed.len();
+        removed
+    }
+
+    /// Stop tracking all packets.
+    pub fn take_all(&mut self) -> impl Iterator<Item = SentPacket> {
+        self.len = 0;
+        mem::take(&mut self.packets)
+            .into_iter()
+            .filter_map(|s| match s {
+                SentSlot::Sent(p) => Some(p),
+                SentSlot::Gap(_) => None,
+            })
+    }
+
+    /// Iterate over the tracked packets, in packet number order.
+    /// This follows the links in gaps, so acknowledged packets are skipped.
+    pub fn iter(&self) -> impl Iterator<Item = &SentPacket> {
+        let mut i = 0;
+        std::iter::from_fn(move || loop {
+            match self.packets.get(i)? {
+                SentSlot::Sent(p) => {
+                    i += 1;
+                    return Some(p);
+                }
+                SentSlot::Gap(next) => i = usize::try_from(next - self.offset).unwrap(),
+            }
+        })
+    }
+
+    /// Iterate over the tracked packets, in packet number order.
+    /// As this skips over gaps, it links the first slot of each run of gaps
+    /// to the end of that run, so later iterations take one step for the run.
+    pub fn iter_mut(&mut self) -> impl Iterator<Item = &mut SentPacket> {
+        let (front, back) = self.packets.as_mut_slices();
+        SentPacketsIterMut {
+            front,
+            back,
+            pn: self.offset,
+        }
+    }
+}
+
+/// A mutable iterator over `SentPackets`, see `SentPackets::iter_mut`.
+struct SentPacketsIterMut<'a> {
+    front: &'a mut [SentSlot],
+    back: &'a mut [SentSlot],
+    /// The packet number of the first entry in `front`.
+    pn: PacketNumber,
+}
+
+impl SentPacketsIterMut<'_> {
+    fn get(&self, pn: PacketNumber) -> Option<&SentSlot> {
+        let i = usize::try_from(pn.checked_sub(self.pn)?).ok()?;
+        if i < self.front.len() {
+            Some(&self.front[i])
+        } else {
+            self.back.get(i - self.front.len())
+        }
+    }
+
+    /// Move on to the entry for `pn`.
+    fn advance(&mut self, pn: PacketNumber) {
+        let mut skip = usize::try_from(pn - self.pn).unwrap();
+        if skip > self.front.len() {
+            skip -= self.front.len();
+            self.front = mem::take(&mut self.back);
+        }
+        let front = mem::take(&mut self.front);
+        let skip = min(skip, front.len());
+        self.front = &mut front[skip..];
+        self.pn = pn;
+    }
+}
+
+impl<'a> Iterator for SentPacketsIterMut<'a> {
+    type Item = &'a mut SentPacket;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if self.front.is_empty() {
+                mem::swap(&mut self.front, &mut self.back);
+            }
+            let (first, rest) = mem::take(&mut self.front).split_first_mut()?;
+            self.front = rest;
+            self.pn += 1;
+            match first {
+                SentSlot::Sent(p) => return Some(p),
+                SentSlot::Gap(next) => {
+                    // Find the end of the run of gaps, then link directly to that.
+                    let mut end = *next;
+                    while let Some(SentSlot::Gap(n)) = self.get(end) {
+                        end = *n;
+                    }
+                    *next = end;
+                    self.advance(end);
+                }
+            }
+        }
+    }
+}
+
 impl std::fmt::Display for PacketNumberSpace {
     fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
         f.write_str(match self {
@@ -264,6 +503,8 @@ pub struct PacketRange {
     largest: PacketNumber,
     smallest: PacketNumber,
     ack_needed: bool,
+    /// Whether this has been included in an ACK frame since it last changed.
+    reported: bool,
 }
 
 impl PacketRange {
@@ -273,6 +514,7 @@ impl PacketRange {
             largest: pn,
             smallest: pn,
             ack_needed: true,
+            reported: false,
         }
     }
 
@@ -301,11 +543,13 @@ impl PacketRange {
             qtrace!([self], "Adding largest {}", pn);
             self.largest += 1;
             self.ack_needed = true;
+            self.reported = false;
             InsertionResult::Largest
         } else if self.smallest == (pn + 1) {
             qtrace!([self], "Adding smallest {}", pn);
             self.smallest -= 1;
             self.ack_needed = true;
+            self.reported = false;
             InsertionResult::Smallest
         } else {
             InsertionResult::NotInserted
@@ -320,6 +564,7 @@ impl PacketRange {
 
         self.largest = other.largest;
         self.ack_needed = self.ack_needed || other.ack_needed;
+        self.reported = self.reported && other.reported;
     }
 
     /// When a packet containing the range `other` is acknowledged,
@@ -343,8 +588,13 @@ pub const DEFAULT_ACK_DELAY: Duration = Duration::from_millis(20); // 20ms
 /// The default number of in-order packets we will receive after
 /// largest acknowledged without sending an immediate acknowledgment.
 pub const DEFAULT_ACK_PACKET_TOLERANCE: PacketNumber = 1;
-const MAX_TRACKED_RANGES: usize = 32;
-const MAX_ACKS_PER_FRAME: usize = 32;
+/// The number of ranges that are tracked for each packet number space.
+/// A range takes 24 bytes, so this limits the memory used to 24KiB per space,
+/// which is enough to cover a window with heavy loss on a fast path.
+const MAX_TRACKED_RANGES: usize = 1024;
+/// The most ranges that are included in an ACK frame.  This can't be more
+/// than 64, so that the count of extra ranges is encoded in one byte.
+const MAX_ACKS_PER_FRAME: usize = 64;
 
 /// A structure that tracks what was included in an ACK.
 #[derive(Debug, Clone)]
@@ -381,11 +631,12 @@ pub struct RecvdPackets {
     /// Whether we are ignoring packets that arrive out of order
     /// for the purposes of generating immediate acknowledgment.
     ignore_order: bool,
+    stats: StatsCell,
 }
 
 impl RecvdPackets {
     /// Make a new `RecvdPackets` for the indicated packet number space.
-    pub fn new(space: PacketNumberSpace) -> Self {
+    pub fn new(space: PacketNumberSpace, stats: StatsCell) -> Self {
         Self {
             space,
             ranges: VecDeque::new(),
@@ -398,6 +649,7 @@ impl RecvdPackets {
             unacknowledged_count: 0,
             unacknowledged_tolerance: DEFAULT_ACK_PACKET_TOLERANCE,
             ignore_order: false,
+            stats,
         }
     }
 
@@ -406,6 +658,84 @@ impl RecvdPackets {
         self.ack_time
     }
 
+    /// Write out the received packets and acknowledgment settings for a
+    /// connection export.
+    ///
+    /// # Errors
+    ///
+    /// When the acknowledgment delay is too large to encode.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        enc.encode_varint(u64::try_from(self.ranges.len())?);
+        for r in &self.ranges {
+            enc.encode_varint(r.largest);
+            enc.encode_varint(r.smallest);
+            enc.encode_byte(u8::from(r.ack_needed) | (u8::from(r.reported) << 1));
+        }
+        enc.encode_varint(self.min_tracked);
+        enc.encode_varint(self.ack_frequency_seqno);
+        enc.encode_varint(u64::try_from(self.ack_delay.as_micros())?);
+        enc.encode_varint(self.unacknowledged_count);
+        enc.encode_varint(self.unacknowledged_tolerance);
+        enc.encode_byte(u8::from(self.ignore_order));
+        enc.encode_byte(u8::from(self.ack_time.is_some()));
+        Ok(())
+    }
+
+    /// Restore the state written by `export`.  If an acknowledgment was
+    /// pending, it is sent right away.
+    ///
+    /// # Errors
+    ///
+    /// When the exported state is malformed.
+    pub fn import(&mut self, dec: &mut Decoder, now: Instant) -> Res<()> {
+        let count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let mut ranges = VecDeque::new();
+        for _ in 0..count {
+            let largest = dec.decode_varint().ok_or(Error::InvalidExport)?;
+            let smallest = dec.decode_varint().ok_or(Error::InvalidExport)?;
+            let flags = dec.decode_byte().ok_or(Error::InvalidExport)?;
+            // Ranges are ordered from largest to smallest, with gaps between them.
+            let prev_smallest = ranges.back().map(|r: &PacketRange| r.smallest);
+            if smallest > largest
+                || flags > 3
+                || prev_smallest.map_or(false, |p| largest.saturating_add(1) >= p)
+            {
+                return Err(Error::InvalidExport);
+            }
+            ranges.push_back(PacketRange {
+                largest,
+                smallest,
+                ack_needed: flags & 1 != 0,
+                reported: flags & 2 != 0,
+            });
+        }
+        if ranges.len() > MAX_TRACKED_RANGES {
+            return Err(Error::InvalidExport);
+        }
+        let min_tracked = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let ack_frequency_seqno = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let ack_delay = Duration::from_micros(dec.decode_varint().ok_or(Error::InvalidExport)?);
+        let unacknowledged_count = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let unacknowledged_tolerance = dec.decode_varint().ok_or(Error::InvalidExport)?;
+        let ignore_order = dec.decode_byte().ok_or(Error::InvalidExport)?;
+        let ack_pending = dec.decode_byte().ok_or(Error::InvalidExport)?;
+        if ignore_order > 1 || ack_pending > 1 {
+            return Err(Error::InvalidExport);
+        }
+
+        self.largest_pn_time = ranges.front().map(|_| now);
+        self.ranges = ranges;
+        self.min_tracked = min_tracked;
+        self.ack_frequency_seqno = ack_frequency_seqno;
+        self.ack_delay = ack_delay;
+        self.unacknowledged_count = unacknowledged_count;
+        self.unacknowledged_tolerance = unacknowledged_tolerance;
+        self.ignore_order = ignore_order == 1;
+        self.ack_time = (ack_pending == 1).then_some(now);
+        self.last_ack_time = None;
+        Ok(())
+    }
+
     /// Update acknowledgment delay parameters.
     pub fn ack_freq(
         &mut self,
@@ -435,40 +765,45 @@ impl RecvdPackets {
         })
     }
 
-    // A simple addition of a packet number to the tracked set.
-    // This doesn't do a binary search on the assumption that
-    // new packets will generally be added to the start of the list.
+    /// Find the first range that might contain `pn`, or be extended to include it.
+    /// Ranges are ordered from largest to smallest, so this is a binary search.
+    fn find(&self, pn: PacketNumber) -> usize {
+        self.ranges.partition_point(|r| r.smallest > pn + 1)
+    }
+
+    // Add a packet number to the tracked set.
     fn add(&mut self, pn: PacketNumber) {
-        for i in 0..self.ranges.len() {
-            match self.ranges[i].add(pn) {
-                InsertionResult::Largest => return,
-                InsertionResult::Smallest => {
-                    // If this was the smallest, it might have filled a gap.
-                    let nxt = i + 1;
-                    if (nxt < self.ranges.len()) && (pn - 1 == self.ranges[nxt].largest) {
-                        let larger = self.ranges.remove(i).unwrap();
-                        self.ranges[i].merge_larger(&larger);
-                    }
-                    return;
-                }
-                InsertionResult::NotInserted => {
-                    if self.ranges[i].largest < pn {
-                        self.ranges.insert(i, PacketRange::new(pn));
-                        return;
-                    }
+        let i = self.find(pn);
+        if i == self.ranges.len() {
+            self.ranges.push_back(PacketRange::new(pn));
+            return;
+        }
+        match self.ranges[i].add(pn) {
+            InsertionResult::Largest => {}
+            InsertionResult::Smallest => {
+                // If this was the smallest, it might have filled a gap.
+                let nxt = i + 1;
+                if (nxt < self.ranges.len()) && (pn - 1 == self.ranges[nxt].largest) {
+                    let larger = self.ranges.remove(i).unwrap();
+                    self.ranges[i].merge_larger(&larger);
                 }
             }
+            InsertionResult::NotInserted => {
+                debug_assert!(self.ranges[i].largest < pn);
+                self.ranges.insert(i, PacketRange::new(pn));
+            }
         }
-        self.ranges.push_back(PacketRange::new(pn));
     }
 
     fn trim_ranges(&mut self) {
         // Limit the number of ranges that are tracked to MAX_TRACKED_RANGES.
-        if self.ranges.len() > MAX_TRACKED_RANGES {
+        while self.ranges.len() > MAX_TRACKED_RANGES {
             let oldest = self.ranges.pop_back().unwrap();
+            let mut stats = self.stats.borrow_mut();
+            stats.ack_ranges_discarded += 1;
             if oldest.ack_needed {
                 qwarn!([self], "Dropping unacknowledged ACK range: {}", oldest);
-            // TODO(mt) Record some statistics about this so we can tune MAX_TRACKED_RANGES.
+                stats.ack_ranges_discarded_unacked += 1;
             } else {
                 qdebug!([self], "Drop ACK range: {}", oldest);
             }
@@ -528,10 +863,8 @@ impl RecvdPackets {
         if pn < self.min_tracked {
             return true;
         }
-        self.ranges
-            .iter()
-            .take_while(|r| pn <= r.largest)
-            .any(|r| r.contains(pn))
+        let i = self.ranges.partition_point(|r| r.smallest > pn);
+        self.ranges.get(i).map_or(false, |r| r.contains(pn))
     }
 
     /// Mark the given range as having been acknowledged.
@@ -555,7 +888,8 @@ impl RecvdPackets {
     /// to track what has been sent. This only clears the delayed ACK timer.
     ///
     /// When sending ACKs, we want to always send the most recent ranges,
-    /// even if they have been sent in other packets.
+    /// even if they have been sent in other packets.  If there isn't space
+    /// for all of the ranges, those that haven't been sent yet go first.
     ///
     /// We don't send ranges that have been acknowledged, but they still need
     /// to be tracked so that duplicates can be detected.
@@ -565,7 +899,6 @@ impl RecvdPackets {
         rtt: Duration,
         builder: &mut PacketBuilder,
         tokens: &mut Vec<RecoveryToken>,
-        stats: &mut FrameStats,
     ) {
         // The worst possible ACK frame, assuming only one range.
         // Note that this assumes one byte for the type and count of extra ranges.
@@ -589,20 +922,44 @@ impl RecvdPackets {
             return;
         };
 
-        let ranges = self
-            .ranges
-            .iter()
-            .filter(|r| r.ack_needed())
-            .take(max_ranges)
-            .cloned()
+        // The largest range is always included, which ensures that the peer
+        // gets an RTT sample.  Then choose ranges that haven't been reported,
+        // then those that have, taking the most recent ranges first in each case.
+        let needed = (0..self.ranges.len())
+            .filter(|&i| self.ranges[i].ack_needed())
+            .collect::<Vec<_>>();
+        let Some((&largest, others)) = needed.split_first() else {
+            return;
+        };
+        let mut chosen = Vec::with_capacity(min(needed.len(), max_ranges));
+        chosen.push(largest);
+        for &reported in &[false, true] {
+            let room = max_ranges - chosen.len();
+            chosen.extend(
+                others
+                    .iter()
+                    .filter(|&&i| self.ranges[i].reported == reported)
+                    .take(room),
+            );
+        }
+        chosen.sort_unstable();
+        let omitted = needed.len() - chosen.len();
+        let ranges = chosen
+            .into_iter()
+            .map(|i| {
+                self.ranges[i].reported = true;
+                self.ranges[i].clone()
+            })
             .collect::<Vec<_>>();
 
         builder.encode_varint(crate::frame::FRAME_TYPE_ACK);
         let mut iter = ranges.iter();
-        let Some(first) = iter.next() else { return };
+        let first = iter.next().unwrap();
         builder.encode_varint(first.largest);
-        stats.largest_acknowledged = first.largest;
-        stats.ack += 1;
+        let mut stats = self.stats.borrow_mut();
+        stats.ack_ranges_omitted += omitted;
+        stats.frame_tx.largest_acknowledged = first.largest;
+        stats.frame_tx.ack += 1;
 
         let elapsed = now.duration_since(self.largest_pn_time.unwrap());
         // We use the default exponent, so delay is in multiples of 8 microseconds.
@@ -648,6 +1005,16 @@ pub struct AckTracker {
 }
 
 impl AckTracker {
+    pub fn new(stats: StatsCell) -> Self {
+        Self {
+            spaces: smallvec![
+                RecvdPackets::new(PacketNumberSpace::ApplicationData, stats.clone()),
+                RecvdPackets::new(PacketNumberSpace::Handshake, stats.clone()),
+                RecvdPackets::new(PacketNumberSpace::Initial, stats),
+            ],
+        }
+    }
+
     pub fn drop_space(&mut self, space: PacketNumberSpace) {
         let sp = match space {
             PacketNumberSpace::Initial => self.spaces.pop(),
@@ -689,6 +1056,27 @@ impl AckTracker {
             .immediate_ack(now);
     }
 
+    /// Write out the state of the application data space for a connection export.
+    ///
+    /// # Errors
+    ///
+    /// When the handshake spaces are still present.
+    pub fn export(&self, enc: &mut Encoder) -> Res<()> {
+        if self.spaces.len() != 1 {
+            return Err(Error::NotQuiescent);
+        }
+        self.spaces[0].export(enc)
+    }
+
+    /// Restore the application data space.
+    ///
+    /// # Errors
+    ///
+    /// When the exported state is malformed.
+    pub fn import(&mut self, dec: &mut Decoder, now: Instant) -> Res<()> {
+        self.spaces[0].import(dec, now)
+    }
+
     /// Determine the earliest time that an ACK might be needed.
     pub fn ack_time(&self, now: Instant) -> Option<Instant> {
         for recvd in &self.spaces {
@@ -723,10 +1111,9 @@ impl AckTracker {
         rtt: Duration,
         builder: &mut PacketBuilder,
         tokens: &mut Vec<RecoveryToken>,
-        stats: &mut FrameStats,
     ) -> Res<()> {
         if let Some(space) = self.get_mut(pn_space) {
-            space.write_frame(now, rtt, builder, tokens, stats);
+            space.write_frame(now, rtt, builder, tokens);
             if builder.len() > builder.limit() {
                 return Err(Error::InternalError(24));
             }
@@ -737,13 +1124,7 @@ impl AckTracker {
 
 impl Default for AckTracker {
     fn default() -> Self {
-        Self {
-            spaces: smallvec![
-                RecvdPackets::new(PacketNumberSpace::ApplicationData),
-                RecvdPackets::new(PacketNumberSpace::Handshake),
-                RecvdPackets::new(PacketNumberSpace::Initial),
-            ],
-        }
+        Self::new(StatsCell::default())
     }
 }
 
@@ -756,12 +1137,12 @@ mod tests {
 
     use super::{
         AckTracker, Duration, Instant, PacketNumberSpace, PacketNumberSpaceSet, RecoveryToken,
-        RecvdPackets, MAX_TRACKED_RANGES,
+        RecvdPackets, SentPacket, SentPackets, SentSlot, MAX_TRACKED_RANGES,
     };
     use crate::{
         frame::Frame,
-        packet::{PacketBuilder, PacketNumber},
-        stats::FrameStats,
+        packet::{PacketBuilder, PacketNumber, PacketType},
+        stats::StatsCell,
     };
 
     const RTT: Duration = Duration::from_millis(100);
@@ -770,7 +1151,7 @@ mod tests {
     }
 
     fn test_ack_range(pns: &[PacketNumber], nranges: usize) {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::Initial); // Any space will do.
+        let mut rp = RecvdPackets::new(PacketNumberSpace::Initial, StatsCell::default()); // Any space will do.
         let mut packets = HashSet::new();
 
         for pn in pns {
@@ -825,7 +1206,8 @@ mod tests {
 
     #[test]
     fn too_many_ranges() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::Initial); // Any space will do.
+        let stats = StatsCell::default();
+        let mut rp = RecvdPackets::new(PacketNumberSpace::Initial, stats.clone()); // Any space will do.
 
         // This will add one too many disjoint ranges.
         for i in 0..=MAX_TRACKED_RANGES {
@@ -839,6 +1221,59 @@ mod tests {
         assert!(rp.is_duplicate(0));
         assert!(!rp.is_duplicate(1));
         assert!(rp.is_duplicate(2));
+
+        assert_eq!(stats.borrow().ack_ranges_discarded, 1);
+        assert_eq!(stats.borrow().ack_ranges_discarded_unacked, 1);
+    }
+
+    /// When not all ranges fit, those that haven't been sent are preferred,
+    /// but the largest range is always included.
+    #[test]
+    fn ack_unreported_ranges_first() {
+        const COUNT: u64 = 20;
+        // Enough space for 5 ranges with the worst-case estimate of their size.
+        const LIMIT: usize = 1 + 26 + 16 * 4;
+
+        let stats = StatsCell::default();
+        let mut rp = RecvdPackets::new(PacketNumberSpace::Initial, stats.clone());
+        for pn in 0..COUNT {
+            rp.set_received(*NOW, pn * 2, true);
+        }
+
+        let largest_acked = |rp: &mut RecvdPackets| {
+            let mut builder = PacketBuilder::short(Encoder::new(), false, []);
+            builder.set_limit(LIMIT);
+            let mut tokens = Vec::new();
+            rp.immediate_ack(*NOW);
+            rp.write_frame(*NOW, RTT, &mut builder, &mut tokens);
+            if let Some(RecoveryToken::Ack(tok)) = tokens.first() {
+                tok.ranges.iter().map(|r| r.largest).collect::<Vec<_>>()
+            } else {
+                panic!("not an ACK token");
+            }
+        };
+        assert_eq!(largest_acked(&mut rp), vec![38, 36, 34, 32, 30]);
+        assert_eq!(largest_acked(&mut rp), vec![38, 28, 26, 24, 22]);
+        assert_eq!(stats.borrow().ack_ranges_omitted, 15 * 2);
+    }
+
+    #[test]
+    fn acknowledged_ranges() {
+        let mut rp = RecvdPackets::new(PacketNumberSpace::Initial, StatsCell::default());
+        for pn in &[0, 2, 4] {
+            rp.set_received(*NOW, *pn, true);
+        }
+        let mut builder = PacketBuilder::short(Encoder::new(), false, []);
+        let mut tokens = Vec::new();
+        rp.write_frame(*NOW, RTT, &mut builder, &mut tokens);
+        if let Some(RecoveryToken::Ack(tok)) = tokens.first() {
+            rp.acknowledged(&tok.ranges[1..]);
+        } else {
+            panic!("not an ACK token");
+        }
+        assert!(rp.ranges[0].ack_needed());
+        assert!(!rp.ranges[1].ack_needed());
+        assert!(!rp.ranges[2].ack_needed());
     }
 
     #[test]
@@ -846,7 +1281,7 @@ mod tests {
         const COUNT: PacketNumber = 9;
         const DELAY: Duration = Duration::from_millis(7);
         // Only application data packets are delayed.
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         assert!(rp.ack_time().is_none());
         assert!(!rp.ack_now(*NOW, RTT));
 
@@ -869,7 +1304,7 @@ mod tests {
     #[test]
     fn no_ack_delay() {
         for space in &[PacketNumberSpace::Initial, PacketNumberSpace::Handshake] {
-            let mut rp = RecvdPackets::new(*space);
+            let mut rp = RecvdPackets::new(*space, StatsCell::default());
             assert!(rp.ack_time().is_none());
             assert!(!rp.ack_now(*NOW, RTT));
 
@@ -882,7 +1317,7 @@ mod tests {
 
     #[test]
     fn ooo_no_ack_delay_new() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         assert!(rp.ack_time().is_none());
         assert!(!rp.ack_now(*NOW, RTT));
 
@@ -894,11 +1329,11 @@ mod tests {
 
     fn write_frame_at(rp: &mut RecvdPackets, now: Instant) {
         let mut builder = PacketBuilder::short(Encoder::new(), false, []);
-        let mut stats = FrameStats::default();
+        let acks = rp.stats.borrow().frame_tx.ack;
         let mut tokens = Vec::new();
-        rp.write_frame(now, RTT, &mut builder, &mut tokens, &mut stats);
+        rp.write_frame(now, RTT, &mut builder, &mut tokens);
         assert!(!tokens.is_empty());
-        assert_eq!(stats.ack, 1);
+        assert_eq!(rp.stats.borrow().frame_tx.ack, acks + 1);
     }
 
     fn write_frame(rp: &mut RecvdPackets) {
@@ -907,7 +1342,7 @@ mod tests {
 
     #[test]
     fn ooo_no_ack_delay_fill() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         rp.set_received(*NOW, 1, true);
         write_frame(&mut rp);
 
@@ -922,7 +1357,7 @@ mod tests {
 
     #[test]
     fn immediate_ack_after_rtt() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         rp.set_received(*NOW, 1, true);
         write_frame(&mut rp);
 
@@ -937,7 +1372,7 @@ mod tests {
 
     #[test]
     fn ooo_no_ack_delay_threshold_new() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
 
         // Set tolerance to 2 and then it takes three packets.
         rp.ack_freq(0, 2, Duration::from_millis(10), true);
@@ -952,7 +1387,7 @@ mod tests {
 
     #[test]
     fn ooo_no_ack_delay_threshold_gap() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         rp.set_received(*NOW, 1, true);
         write_frame(&mut rp);
 
@@ -971,7 +1406,7 @@ mod tests {
     /// increase the number of packets needed to cause an ACK.
     #[test]
     fn non_ack_eliciting_skip() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         rp.ack_freq(0, 1, Duration::from_millis(10), true);
 
         // This should be ignored.
@@ -987,7 +1422,7 @@ mod tests {
     /// If a packet that is not ack-eliciting is reordered, that's fine too.
     #[test]
     fn non_ack_eliciting_reorder() {
-        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData);
+        let mut rp = RecvdPackets::new(PacketNumberSpace::ApplicationData, StatsCell::default());
         rp.ack_freq(0, 1, Duration::from_millis(10), false);
 
         // These are out of order, but they are not ack-eliciting.
@@ -1047,7 +1482,8 @@ mod tests {
 
     #[test]
     fn drop_spaces() {
-        let mut tracker = AckTracker::default();
+        let stats = StatsCell::default();
+        let mut tracker = AckTracker::new(stats.clone());
         let mut builder = PacketBuilder::short(Encoder::new(), false, []);
         tracker
             .get_mut(PacketNumberSpace::Initial)
@@ -1059,7 +1495,6 @@ mod tests {
             .is_some());
 
         let mut tokens = Vec::new();
-        let mut stats = FrameStats::default();
         tracker
             .write_frame(
                 PacketNumberSpace::Initial,
@@ -1067,10 +1502,9 @@ mod tests {
                 RTT,
                 &mut builder,
                 &mut tokens,
-                &mut stats,
             )
             .unwrap();
-        assert_eq!(stats.ack, 1);
+        assert_eq!(stats.borrow().frame_tx.ack, 1);
 
         // Mark another packet as received so we have cause to send another ACK in that space.
         tracker
@@ -1095,10 +1529,9 @@ mod tests {
                 RTT,
                 &mut builder,
                 &mut tokens,
-                &mut stats,
             )
             .unwrap();
-        assert_eq!(stats.ack, 1);
+        assert_eq!(stats.borrow().frame_tx.ack, 1);
         if let RecoveryToken::Ack(tok) = &tokens[0] {
             tracker.acked(tok); // Should be a noop.
         } else {
@@ -1108,7 +1541,8 @@ mod tests {
 
     #[test]
     fn no_room_for_ack() {
-        let mut tracker = AckTracker::default();
+        let stats = StatsCell::default();
+        let mut tracker = AckTracker::new(stats.clone());
         tracker
             .get_mut(PacketNumberSpace::Initial)
             .unwrap()
@@ -1120,7 +1554,6 @@ mod tests {
         let mut builder = PacketBuilder::short(Encoder::new(), false, []);
         builder.set_limit(10);
 
-        let mut stats = FrameStats::default();
         tracker
             .write_frame(
                 PacketNumberSpace::Initial,
@@ -1128,16 +1561,16 @@ mod tests {
                 RTT,
                 &mut builder,
                 &mut Vec::new(),
-                &mut stats,
             )
             .unwrap();
-        assert_eq!(stats.ack, 0);
+        assert_eq!(stats.borrow().frame_tx.ack, 0);
         assert_eq!(builder.len(), 1); // Only the short packet header has been added.
     }
 
     #[test]
     fn no_room_for_extra_range() {
-        let mut tracker = AckTracker::default();
+        let stats = StatsCell::default();
+        let mut tracker = AckTracker::new(stats.clone());
         tracker
             .get_mut(PacketNumberSpace::Initial)
             .unwrap()
@@ -1153,7 +1586,6 @@ mod tests {
         let mut builder = PacketBuilder::short(Encoder::new(), false, []);
         builder.set_limit(32);
 
-        let mut stats = FrameStats::default();
         tracker
             .write_frame(
                 PacketNumberSpace::Initial,
@@ -1161,10 +1593,9 @@ mod tests {
                 RTT,
                 &mut builder,
                 &mut Vec::new(),
-                &mut stats,
             )
             .unwrap();
-        assert_eq!(stats.ack, 1);
+        assert_eq!(stats.borrow().frame_tx.ack, 1);
 
         let mut dec = builder.as_decoder();
         _ = dec.decode_byte().unwrap(); // Skip the short header.
@@ -1197,6 +1628,118 @@ mod tests {
         );
     }
 
+    fn sent_packets(pns: &[PacketNumber]) -> SentPackets {
+        let mut sent = SentPackets::default();
+        for &pn in pns {
+            sent.track(SentPacket::new(
+                PacketType::Short,
+                pn,
+                *NOW,
+                true,
+                Vec::new(),
+                100,
+            ));
+        }
+        sent
+    }
+
+    fn pns<'a>(packets: impl IntoIterator<Item = &'a SentPacket>) -> Vec<PacketNumber> {
+        packets.into_iter().map(|p| p.pn).collect()
+    }
+
+    #[test]
+    fn sent_packets_take_range() {
+        let mut sent = sent_packets(&[3, 4, 5, 7, 8, 9]);
+        assert_eq!(sent.len(), 6);
+
+        let mut removed = Vec::new();
+        sent.take_range(&(4..=7), &mut removed);
+        assert_eq!(pns(&removed), vec![7, 5, 4]);
+        assert_eq!(pns(sent.iter_mut().map(|p| &*p)), vec![3, 8, 9]);
+
+        // Ranges that extend beyond the tracked packets are fine.
+        removed.clear();
+        sent.take_range(&(0..=3), &mut removed);
+        sent.take_range(&(9..=100), &mut removed);
+        sent.take_range(&(20..=30), &mut removed);
+        assert_eq!(pns(&removed), vec![3, 9]);
+        assert_eq!(sent.len(), 1);
+
+        // Gaps are reclaimed, so only the one packet remains.
+        assert_eq!(sent.packets.len(), 1);
+        assert_eq!(sent.offset, 8);
+    }
+
+    /// Gaps link to the end of a range that was taken, so taking the same range again is cheap.
+    #[test]
+    fn sent_packets_gap_links() {
+        let mut sent = sent_packets(&(0..10).collect::<Vec<_>>());
+        let mut removed = Vec::new();
+        sent.take_range(&(5..=6), &mut removed);
+        sent.take_range(&(1..=8), &mut removed);
+        assert_eq!(pns(&removed), vec![6, 5, 8, 7, 4, 3, 2, 1]);
+        assert!(matches!(sent.packets[1], SentSlot::Gap(9)));
+        assert!(matches!(sent.packets[5], SentSlot::Gap(9)));
+
+        removed.clear();
+        sent.take_range(&(1..=8), &mut removed);
+        assert!(removed.is_empty());
+        assert_eq!(pns(sent.iter_mut().map(|p| &*p)), vec![0, 9]);
+    }
+
+    /// Iteration skips runs of gaps, even when they are built up one packet at a time.
+    #[test]
+    fn sent_packets_iter_skips_gaps() {
+        let mut sent = sent_packets(&(0..100).collect::<Vec<_>>());
+        let mut removed = Vec::new();
+        for pn in 1..99 {
+            sent.take_range(&(pn..=pn), &mut removed);
+        }
+        // Each packet that was taken links to the next.
+        assert!(matches!(sent.packets[1], SentSlot::Gap(2)));
+        assert_eq!(pns(sent.iter()), vec![0, 99]);
+
+        // Iterating links the start of the run to its end.
+        assert_eq!(pns(sent.iter_mut().map(|p| &*p)), vec![0, 99]);
+        assert!(matches!(sent.packets[1], SentSlot::Gap(99)));
+        assert_eq!(pns(sent.iter_mut().map(|p| &*p)), vec![0, 99]);
+    }
+
+    #[test]
+    fn sent_packets_take_oldest() {
+        let mut sent = sent_packets(&[0, 1, 2, 4, 5]);
+        let mut removed = Vec::new();
+        sent.take_range(&(1..=1), &mut removed);
+
+        let taken = sent.take_oldest(|p| p.pn < 4);
+        assert_eq!(pns(&taken), vec![0, 2]);
+        assert_eq!(sent.len(), 2);
+
+        // This stops at the first packet that doesn't match.
+        let taken = sent.take_oldest(|p| p.pn != 4);
+        assert!(taken.is_empty());
+
+        assert_eq!(pns(&sent.take_all().collect::<Vec<_>>()), vec![4, 5]);
+        assert_eq!(sent.len(), 0);
+
+        // After everything is removed, tracking can start again.
+        sent.track(SentPacket::new(
+            PacketType::Short,
+            10,
+            *NOW,
+            true,
+            Vec::new(),
+            100,
+        ));
+        assert_eq!(sent.len(), 1);
+    }
+
+    #[test]
+    #[should_panic(expected = "sent packets are tracked in order")]
+    fn sent_packets_out_of_order() {
+        _ = sent_packets(&[2, 1]);
+    }
+
     #[test]
     fn pnspaceset_default() {
         let set = PacketNumberSpaceSet::default();
@@ -7,15 +7,18 @@
 #![cfg_attr(feature = "deny-warnings", deny(warnings))]
 #![warn(clippy::pedantic)]
 
-mod sim;
-
 use std::{ops::Range, time::Duration};
 
 use neqo_transport::{ConnectionError, ConnectionParameters, Error, State};
-use sim::{
-    connection::{ConnectionNode, ReachState, ReceiveData, SendData},
-    network::{Delay, Drop, TailDrop},
-    Simulator,
+use test_fixture::{
+    boxed,
+    sim::{
+        asymmetric,
+        connection::{ConnectionNode, ReachState, ReceiveData, SendData},
+        network::{CoDel, Delay, Drop, Duplicate, RateSchedule, Reorder, TailDrop, TraceLink},
+        Simulator,
+    },
+    simulate,
 };
 
 /// The amount of transfer.  Much more than this takes a surprising amount of time.
@@ -25,6 +28,9 @@ const DELAY: Duration = Duration::from_millis(50);
 const DELAY_RANGE: Range<Duration> = DELAY..Duration::from_millis(55);
 const JITTER: Duration = Duration::from_millis(10);
 
+/// A short cellular trace in Mahimahi format: bursts of opportunities with gaps.
+const TRACE: &str = "1\n1\n2\n5\n5\n5\n8\n13\n13\n14\n20\n21\n21\n30\n";
+
 const fn weeks(m: u32) -> Duration {
     Duration::from_secs((m as u64) * 60 * 60 * 24 * 7)
 }
@@ -175,3 +181,86 @@ fn transfer_fixed_seed() {
     sim.seed_str("117f65d90ee5c1a7fb685f3af502c7730ba5d31866b758d98f5e3c2117cf9b86");
     sim.run();
 }
+
+simulate!(
+    transfer_trace,
+    [
+        ConnectionNode::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        TraceLink::parse(TRACE, 65_536, DELAY).unwrap(),
+        ConnectionNode::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        TraceLink::parse(TRACE, 65_536, DELAY).unwrap(),
+    ],
+);
+
+simulate!(
+    transfer_rate_schedule,
+    [
+        ConnectionNode::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        TailDrop::with_rate(
+            RateSchedule::new([
+                (ZERO, 2_000_000),
+                (Duration::from_millis(500), 200_000),
+                (Duration::from_secs(1), 1_000_000),
+            ])
+            .repeat(Duration::from_millis(1500)),
+            65_536,
+            DELAY,
+        ),
+        ConnectionNode::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        TailDrop::dsl_downlink(),
+    ],
+);
+
+simulate!(
+    transfer_reorder_duplicate,
+    [
+        ConnectionNode::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        Delay::new(DELAY_RANGE),
+        Reorder::new(5, JITTER),
+        Duplicate::percentage(2),
+        ConnectionNode::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        Delay::new(DELAY_RANGE),
+        Reorder::new(5, JITTER),
+    ],
+);
+
+simulate!(
+    transfer_codel,
+    [
+        ConnectionNode::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        CoDel::new(1_000_000, 131_072, DELAY),
+        ConnectionNode::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        CoDel::new(200_000, 32_768, DELAY),
+    ],
+);
+
+simulate!(
+    transfer_fq_codel,
+    [
+        ConnectionNode::default_client(boxed![SendData::new(TRANSFER_AMOUNT)]),
+        CoDel::fq(1_000_000, 131_072, DELAY),
+        ConnectionNode::default_server(boxed![ReceiveData::new(TRANSFER_AMOUNT)]),
+        CoDel::fq(200_000, 32_768, DELAY),
+    ],
+);
+
+#[test]
+fn transfer_asymmetric() {
+    let mut sim = Simulator::new(
+        "transfer_asymmetric",
+        asymmetric(
+            Box::new(ConnectionNode::default_client(boxed![SendData::new(
+                TRANSFER_AMOUNT
+            )])),
+            boxed![TailDrop::dsl_uplink(), Delay::new(ZERO..JITTER)],
+            Box::new(ConnectionNode::default_server(boxed![ReceiveData::new(
+                TRANSFER_AMOUNT
+            )])),
+            boxed![
+                TraceLink::parse(TRACE, 32_768, DELAY).unwrap(),
+                Drop::percentage(1)
+            ],
+        ),
+    );
+    sim.run();
+}
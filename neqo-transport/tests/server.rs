@@ -9,7 +9,14 @@
 
 mod common;
 
-use std::{cell::RefCell, convert::TryFrom, mem, net::SocketAddr, rc::Rc, time::Duration};
+use std::{
+    cell::RefCell,
+    convert::TryFrom,
+    mem,
+    net::SocketAddr,
+    rc::Rc,
+    time::{Duration, Instant},
+};
 
 use common::{
     apply_header_protection, connect, connected_server, decode_initial_header, default_server,
@@ -17,7 +24,8 @@ use common::{
 };
 use neqo_common::{qtrace, Datagram, Decoder, Encoder, Role};
 use neqo_crypto::{
-    generate_ech_keys, AllowZeroRtt, AuthenticationStatus, ZeroRttCheckResult, ZeroRttChecker,
+    generate_ech_keys, load_certificate, load_trust_anchors, AllowZeroRtt, AuthenticationStatus,
+    CertificateVerifier, ServerNameConfig, ServerNameSelector, ZeroRttCheckResult, ZeroRttChecker,
 };
 use neqo_transport::{
     server::{ActiveConnectionRef, Server, ValidateAddress},
@@ -777,3 +785,122 @@ fn ech() {
         .ech_accepted()
         .unwrap());
 }
+
+#[derive(Debug)]
+struct DefaultKeySelector {}
+
+impl ServerNameSelector for DefaultKeySelector {
+    fn select(&self, _server_name: &str) -> Option<ServerNameConfig> {
+        Some(ServerNameConfig {
+            certificate: String::from(test_fixture::DEFAULT_KEYS[0]),
+            protocols: Vec::new(),
+        })
+    }
+}
+
+#[test]
+fn sni() {
+    let mut server = default_server();
+    server.set_sni_selector(Some(Rc::new(DefaultKeySelector {})));
+
+    let mut client = default_client();
+    let server_instance = connect(&mut client, &mut server);
+    assert_eq!(
+        server_instance.borrow().server_name_indication(),
+        Some(test_fixture::DEFAULT_SERVER_NAME)
+    );
+}
+
+#[test]
+fn client_certificate() {
+    let mut server = default_server();
+    load_certificate(
+        "pem",
+        include_bytes!("../../test-fixture/pem/cert.pem"),
+        include_bytes!("../../test-fixture/pem/key.pem"),
+    )
+    .unwrap();
+    load_trust_anchors(include_bytes!("../../test-fixture/pem/ca.pem")).unwrap();
+    server.request_client_certificate(CertificateVerifier::new(), true);
+
+    let mut client = default_client();
+    client
+        .client_set_certificate_selector(Rc::new(String::from("pem")))
+        .unwrap();
+    let server_instance = connect(&mut client, &mut server);
+    let mut certs = server_instance.borrow().peer_certificate().unwrap();
+    assert_eq!(certs.count(), 2);
+}
+
+/// Connect, then have the client acknowledge everything so that both the client
+/// and the server connection can be exported.
+fn connect_quiescent(client: &mut Connection, server: &mut Server) -> Instant {
+    connect(client, server);
+    let delay = client.process(None, now()).callback();
+    let now = now() + delay;
+    let ack = client.process(None, now).dgram();
+    assert!(ack.is_some());
+    let out = server.process(ack.as_ref(), now);
+    assert!(out.as_dgram_ref().is_none());
+    now
+}
+
+#[test]
+fn export_import() {
+    let mut server = default_server();
+    let mut client = default_client();
+    let now = connect_quiescent(&mut client, &mut server);
+
+    let exported = server.export();
+    assert_eq!(exported.len(), 1);
+
+    let stream_id = client.stream_create(StreamType::UniDi).unwrap();
+    client.stream_send(stream_id, b"hello").unwrap();
+    let dgram = client.process(None, now).dgram();
+    assert!(dgram.is_some());
+
+    // The original server has forgotten the connection.
+    assert!(server.process(dgram.as_ref(), now).dgram().is_none());
+    assert!(server.active_connections().is_empty());
+
+    // A new server picks it up.
+    let mut server = default_server();
+    let mut server_conn = server
+        .import(&exported[0], test_fixture::addr(), now)
+        .unwrap();
+    assert_eq!(*server_conn.borrow().state(), State::Confirmed);
+    server.process(dgram.as_ref(), now);
+    let mut buf = [0; 16];
+    assert_eq!(
+        server_conn.borrow_mut().stream_recv(stream_id, &mut buf),
+        Ok((5, false))
+    );
+
+    // The new server can send to the client.
+    let stream_id = server_conn
+        .borrow_mut()
+        .stream_create(StreamType::UniDi)
+        .unwrap();
+    server_conn
+        .borrow_mut()
+        .stream_send(stream_id, b"world")
+        .unwrap();
+    server.add_to_waiting(server_conn);
+    let dgram = server.process(None, now).dgram();
+    client.process_input(&dgram.unwrap(), now);
+    assert_eq!(client.stream_recv(stream_id, &mut buf), Ok((5, false)));
+}
+
+#[test]
+fn export_import_wrong_role() {
+    let mut server = default_server();
+    let mut client = default_client();
+    let now = connect_quiescent(&mut client, &mut server);
+
+    let state = client.export().unwrap();
+    let mut server = default_server();
+    assert_eq!(
+        server.import(state, test_fixture::addr(), now).unwrap_err(),
+        Error::InvalidExport
+    );
+}
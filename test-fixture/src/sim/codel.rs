@@ -0,0 +1,423 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::{
+    collections::{HashMap, VecDeque},
+    convert::TryFrom,
+    fmt::{self, Debug},
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{qtrace, Datagram};
+use neqo_transport::Output;
+
+use super::{
+    link::{Link, RateSchedule},
+    Node, Rng,
+};
+
+/// The queuing delay that CoDel aims for.
+const TARGET: Duration = Duration::from_millis(5);
+/// The time that the queuing delay has to exceed `TARGET` before CoDel drops.
+const INTERVAL: Duration = Duration::from_millis(100);
+/// The number of bytes that a flow is allowed to send in each round.
+const QUANTUM: usize = 1514;
+
+/// A single queue that is managed by CoDel, as described in RFC 8289.
+#[derive(Default)]
+struct CoDelQueue {
+    /// Datagrams and the time that they were enqueued.
+    queue: VecDeque<(Instant, Datagram)>,
+    /// The number of bytes in `queue`.
+    used: usize,
+    /// When the queuing delay will have been above `TARGET` for long enough
+    /// to start dropping.
+    first_above_time: Option<Instant>,
+    /// When to drop next.
+    drop_next: Option<Instant>,
+    /// The number of drops since entering the dropping state.
+    count: u32,
+    /// The value of `count` when the dropping state was last entered.
+    last_count: u32,
+    /// Whether we are in the dropping state.
+    dropping: bool,
+    /// The number of bytes that this queue can send, for FQ-CoDel.
+    deficit: isize,
+}
+
+impl CoDelQueue {
+    fn control_law(t: Instant, count: u32) -> Instant {
+        t + INTERVAL.div_f64(f64::from(count).sqrt())
+    }
+
+    fn push(&mut self, d: Datagram, size: usize, now: Instant) {
+        self.used += size;
+        self.queue.push_back((now, d));
+    }
+
+    /// Take the datagram at the head of the queue.  This also returns whether
+    /// the queuing delay has been too high for long enough that the datagram
+    /// could be dropped.
+    fn pop(&mut self, link: &Link, now: Instant) -> (Option<Datagram>, bool) {
+        let Some((enqueued, d)) = self.queue.pop_front() else {
+            self.first_above_time = None;
+            return (None, false);
+        };
+        self.used -= link.size(&d);
+
+        let mut ok_to_drop = false;
+        if now - enqueued < TARGET || self.used <= QUANTUM {
+            self.first_above_time = None;
+        } else if let Some(t) = self.first_above_time {
+            ok_to_drop = now >= t;
+        } else {
+            self.first_above_time = Some(now + INTERVAL);
+        }
+        (Some(d), ok_to_drop)
+    }
+
+    /// Take the next datagram to send, counting any that are dropped.
+    fn dequeue(&mut self, link: &Link, now: Instant, dropped: &mut usize) -> Option<Datagram> {
+        let (mut d, mut ok_to_drop) = self.pop(link, now);
+        if d.is_none() {
+            self.dropping = false;
+            return None;
+        }
+
+        if self.dropping {
+            if !ok_to_drop {
+                self.dropping = false;
+            }
+            while self.dropping && self.drop_next.map_or(false, |t| now >= t) {
+                qtrace!("codel dropping {} bytes", d.as_ref().map_or(0, |d| d.len()));
+                *dropped += 1;
+                self.count += 1;
+                (d, ok_to_drop) = self.pop(link, now);
+                if ok_to_drop {
+                    self.drop_next = Some(Self::control_law(self.drop_next.unwrap(), self.count));
+                } else {
+                    self.dropping = false;
+                }
+            }
+        } else if ok_to_drop {
+            qtrace!("codel dropping {} bytes", d.as_ref().map_or(0, |d| d.len()));
+            *dropped += 1;
+            (d, _) = self.pop(link, now);
+            self.dropping = true;
+            // If we were dropping recently, start from close to the drop rate
+            // that was used then.
+            let delta = self.count - self.last_count;
+            self.count = match self.drop_next {
+                Some(t) if delta > 1 && now < t + INTERVAL * 16 => delta,
+                _ => 1,
+            };
+            self.drop_next = Some(Self::control_law(now, self.count));
+            self.last_count = self.count;
+        }
+        d
+    }
+}
+
+/// This models a link with a CoDel queue at the front of it, or an FQ-CoDel
+/// queue (RFC 8290) that gives each flow its own CoDel queue and shares the
+/// link between them.  Flows are identified by their source and destination
+/// addresses.
+pub struct CoDel {
+    /// Whether there is a queue for each flow.
+    fq: bool,
+    /// The most bytes that can be queued across all flows.
+    capacity: usize,
+    /// A counter for how many bytes are enqueued.
+    used: usize,
+    /// The queue for each flow.
+    flows: Vec<CoDelQueue>,
+    /// The index into `flows` for each pair of addresses.
+    flow_ids: HashMap<(SocketAddr, SocketAddr), usize>,
+    /// Flows that have recently become active.
+    new_flows: VecDeque<usize>,
+    /// The other active flows.
+    old_flows: VecDeque<usize>,
+    /// The link that datagrams are sent on.
+    link: Link,
+
+    /// The number of packets received.
+    received: usize,
+    /// The number of packets dropped because the queue was full.
+    overflow: usize,
+    /// The number of packets dropped by CoDel.
+    dropped: usize,
+    /// The number of packets delivered.
+    delivered: usize,
+}
+
+impl CoDel {
+    fn make(fq: bool, rate: RateSchedule, capacity: usize, delay: Duration) -> Self {
+        Self {
+            fq,
+            capacity,
+            used: 0,
+            flows: Vec::new(),
+            flow_ids: HashMap::new(),
+            new_flows: VecDeque::new(),
+            old_flows: VecDeque::new(),
+            link: Link::new(rate, delay),
+            received: 0,
+            overflow: 0,
+            dropped: 0,
+            delivered: 0,
+        }
+    }
+
+    /// Make a new CoDel node with the given rate, queue capacity, and link delay.
+    #[must_use]
+    pub fn new(rate: impl Into<RateSchedule>, capacity: usize, delay: Duration) -> Self {
+        Self::make(false, rate.into(), capacity, delay)
+    }
+
+    /// Make a new FQ-CoDel node with the given rate, queue capacity, and link delay.
+    #[must_use]
+    pub fn fq(rate: impl Into<RateSchedule>, capacity: usize, delay: Duration) -> Self {
+        Self::make(true, rate.into(), capacity, delay)
+    }
+
+    fn flow(&mut self, d: &Datagram) -> usize {
+        if !self.fq {
+            if self.flows.is_empty() {
+                self.flows.push(CoDelQueue::default());
+            }
+            return 0;
+        }
+        let flows = &mut self.flows;
+        *self
+            .flow_ids
+            .entry((d.source(), d.destination()))
+            .or_insert_with(|| {
+                flows.push(CoDelQueue::default());
+                flows.len() - 1
+            })
+    }
+
+    fn enqueue(&mut self, d: Datagram, now: Instant) {
+        self.received += 1;
+        let size = self.link.size(&d);
+        if !self.fq && self.used + size > self.capacity {
+            qtrace!("codel dropping {} bytes at tail", d.len());
+            self.overflow += 1;
+            return;
+        }
+
+        let id = self.flow(&d);
+        let flow = &mut self.flows[id];
+        let active =
+            !flow.queue.is_empty() || self.new_flows.contains(&id) || self.old_flows.contains(&id);
+        flow.push(d, size, now);
+        self.used += size;
+        if !active {
+            flow.deficit = isize::try_from(QUANTUM).unwrap();
+            self.new_flows.push_back(id);
+        }
+
+        // FQ-CoDel drops from the head of the flow with the most bytes queued.
+        while self.used > self.capacity {
+            let fattest = (0..self.flows.len())
+                .max_by_key(|&i| self.flows[i].used)
+                .unwrap();
+            let flow = &mut self.flows[fattest];
+            let (_, d) = flow.queue.pop_front().unwrap();
+            let size = self.link.size(&d);
+            qtrace!("fq_codel dropping {} bytes at head", d.len());
+            flow.used -= size;
+            self.used -= size;
+            self.overflow += 1;
+        }
+    }
+
+    /// Pick the next datagram to send, sharing the link between flows.
+    fn dequeue(&mut self, now: Instant) -> Option<Datagram> {
+        loop {
+            let (id, new) = if let Some(&id) = self.new_flows.front() {
+                (id, true)
+            } else {
+                (*self.old_flows.front()?, false)
+            };
+            let flow = &mut self.flows[id];
+
+            if flow.deficit <= 0 {
+                flow.deficit += isize::try_from(QUANTUM).unwrap();
+                self.take_flow(new);
+                self.old_flows.push_back(id);
+                continue;
+            }
+
+            let before = flow.used;
+            if let Some(d) = flow.dequeue(&self.link, now, &mut self.dropped) {
+                self.used -= before - flow.used;
+                flow.deficit -= isize::try_from(self.link.size(&d)).unwrap();
+                return Some(d);
+            }
+            self.used -= before;
+
+            // Move a new flow that has emptied behind the old flows, so that
+            // it can't get priority by sending small bursts.
+            self.take_flow(new);
+            if new && !self.old_flows.is_empty() {
+                self.old_flows.push_back(id);
+            }
+        }
+    }
+
+    fn take_flow(&mut self, new: bool) {
+        if new {
+            self.new_flows.pop_front();
+        } else {
+            self.old_flows.pop_front();
+        }
+    }
+
+    /// If the last packet that was sending has been sent, start sending
+    /// the next one.
+    fn maybe_send(&mut self, now: Instant) {
+        if self.link.ready(now) {
+            if let Some(d) = self.dequeue(now) {
+                self.link.send(d, now);
+            } else {
+                self.link.stop();
+            }
+        }
+    }
+}
+
+impl Node for CoDel {
+    fn init(&mut self, _rng: Rng, now: Instant) {
+        self.link.init(now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        if let Some(dgram) = d {
+            self.enqueue(dgram, now);
+        }
+
+        self.maybe_send(now);
+
+        let res = self.link.output(now);
+        if matches!(res, Output::Datagram(_)) {
+            self.delivered += 1;
+        }
+        res
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        println!(
+            "{}: {:?}: rx {} overflow {} drop {} tx {}",
+            test_name, self, self.received, self.overflow, self.dropped, self.delivered,
+        );
+    }
+}
+
+impl Debug for CoDel {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(if self.fq { "fq_codel" } else { "codel" })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        net::SocketAddr,
+        rc::Rc,
+        time::{Duration, Instant},
+    };
+
+    use neqo_common::{Datagram, IpTos};
+    use neqo_transport::Output;
+
+    use super::{CoDel, Node};
+    use crate::{addr, addr_v4, now, sim::rng::Random};
+
+    /// With the overhead of the link, this takes 1000 bytes.
+    fn dgram(src: SocketAddr) -> Datagram {
+        Datagram::new(src, addr(), IpTos::default(), Some(128), vec![0; 936])
+    }
+
+    fn init(node: &mut CoDel) -> Instant {
+        let start = now();
+        node.init(Rc::new(RefCell::new(Random::new([1; 32]))), start);
+        start
+    }
+
+    /// Take everything from `node`, moving time forward as needed.
+    fn drain(node: &mut CoDel, mut now: Instant) -> Vec<Datagram> {
+        let mut out = Vec::new();
+        loop {
+            match node.process(None, now) {
+                Output::Datagram(d) => out.push(d),
+                Output::Callback(t) => now += t,
+                Output::None => return out,
+            }
+        }
+    }
+
+    #[test]
+    fn no_standing_queue() {
+        let mut codel = CoDel::new(10_000, 1_000_000, Duration::ZERO);
+        let start = init(&mut codel);
+        // Each datagram takes 100ms to send, so spacing them by 200ms keeps the queue empty.
+        for i in 0..10 {
+            let t = start + Duration::from_millis(200) * i;
+            codel.process(Some(dgram(addr())), t);
+            assert_eq!(drain(&mut codel, t).len(), 1);
+        }
+        assert_eq!(codel.dropped, 0);
+    }
+
+    #[test]
+    fn standing_queue() {
+        const COUNT: usize = 50;
+        let mut codel = CoDel::new(10_000, 1_000_000, Duration::ZERO);
+        let start = init(&mut codel);
+        for _ in 0..COUNT {
+            codel.process(Some(dgram(addr())), start);
+        }
+        let delivered = drain(&mut codel, start);
+        assert_eq!(codel.overflow, 0);
+        assert!(codel.dropped > 0);
+        assert_eq!(delivered.len() + codel.dropped, COUNT);
+        assert_eq!(codel.delivered, delivered.len());
+    }
+
+    /// Count the datagrams from the first flow in the first `n` that are delivered.
+    fn first_flow_share(mut codel: CoDel, n: usize) -> usize {
+        let start = init(&mut codel);
+        for src in [addr(), addr_v4()] {
+            for _ in 0..20 {
+                codel.process(Some(dgram(src)), start);
+            }
+        }
+        let delivered = drain(&mut codel, start);
+        // This is fast enough that the queuing delay stays below the target.
+        assert_eq!(delivered.len(), 40);
+        delivered[..n]
+            .iter()
+            .filter(|d| d.source() == addr())
+            .count()
+    }
+
+    #[test]
+    fn fq_fairness() {
+        // Without flow queuing, the first flow has to finish before the second starts.
+        assert_eq!(
+            first_flow_share(CoDel::new(10_000_000, 1_000_000, Duration::ZERO), 20),
+            20
+        );
+        // With it, the flows take turns.
+        let share = first_flow_share(CoDel::fq(10_000_000, 1_000_000, Duration::ZERO), 20);
+        assert!((8..=12).contains(&share), "{share}");
+    }
+}
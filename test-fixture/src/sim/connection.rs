@@ -19,6 +19,7 @@ use neqo_transport::{
 };
 
 use super::{Node, Rng};
+use crate::{new_client, new_server, DEFAULT_ALPN};
 
 /// The status of the processing of an event.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -51,65 +52,77 @@ pub struct ConnectionNode {
 }
 
 impl ConnectionNode {
+    #[must_use]
     pub fn new_client(
         params: ConnectionParameters,
         goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>,
     ) -> Self {
         Self {
-            c: test_fixture::new_client(params),
+            c: new_client(params),
             goals: goals.into_iter().collect(),
         }
     }
 
+    #[must_use]
     pub fn new_server(
         params: ConnectionParameters,
         goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>,
     ) -> Self {
         Self {
-            c: test_fixture::new_server(test_fixture::DEFAULT_ALPN, params),
+            c: new_server(DEFAULT_ALPN, params),
             goals: goals.into_iter().collect(),
         }
     }
 
+    #[must_use]
     pub fn default_client(goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>) -> Self {
         Self::new_client(ConnectionParameters::default(), goals)
     }
 
+    #[must_use]
     pub fn default_server(goals: impl IntoIterator<Item = Box<dyn ConnectionGoal>>) -> Self {
         Self::new_server(ConnectionParameters::default(), goals)
     }
 
-    #[allow(dead_code)]
     pub fn clear_goals(&mut self) {
         self.goals.clear();
     }
 
-    #[allow(dead_code)]
     pub fn add_goal(&mut self, goal: Box<dyn ConnectionGoal>) {
         self.goals.push(goal);
     }
 
     /// Process all goals using the given closure and return whether any were active.
-    fn process_goals<F>(&mut self, mut f: F) -> bool
+    fn process_goals<F>(&mut self, f: F) -> bool
     where
         F: FnMut(&mut Box<dyn ConnectionGoal>, &mut Connection) -> GoalStatus,
     {
-        // Waiting on drain_filter...
-        // self.goals.drain_filter(|g| f(g,  &mut self.c, &e)).count();
-        let mut active = false;
-        let mut i = 0;
-        while i < self.goals.len() {
-            let status = f(&mut self.goals[i], &mut self.c);
-            if status == GoalStatus::Done {
-                self.goals.remove(i);
-                active = true;
-            } else {
-                active |= status == GoalStatus::Active;
-                i += 1;
-            }
+        process_goals(&mut self.goals, &mut self.c, f)
+    }
+}
+
+/// Process each goal in `goals` using the given closure, removing those that
+/// are done, and return whether any were active.
+pub(super) fn process_goals<G, C, F>(goals: &mut Vec<Box<G>>, c: &mut C, mut f: F) -> bool
+where
+    G: ?Sized,
+    F: FnMut(&mut Box<G>, &mut C) -> GoalStatus,
+{
+    // Waiting on drain_filter...
+    // goals.drain_filter(|g| f(g, c)).count();
+    let mut active = false;
+    let mut i = 0;
+    while i < goals.len() {
+        let status = f(&mut goals[i], c);
+        if status == GoalStatus::Done {
+            goals.remove(i);
+            active = true;
+        } else {
+            active |= status == GoalStatus::Active;
+            i += 1;
         }
-        active
     }
+    active
 }
 
 impl Node for ConnectionNode {
@@ -166,6 +179,7 @@ pub struct ReachState {
 }
 
 impl ReachState {
+    #[must_use]
     pub fn new(target: State) -> Self {
         Self { target }
     }
@@ -193,6 +207,7 @@ pub struct SendData {
 }
 
 impl SendData {
+    #[must_use]
     pub fn new(amount: usize) -> Self {
         Self {
             remaining: amount,
@@ -276,6 +291,7 @@ pub struct ReceiveData {
 }
 
 impl ReceiveData {
+    #[must_use]
     pub fn new(amount: usize) -> Self {
         Self { remaining: amount }
     }
@@ -27,6 +27,7 @@ impl Drop {
     /// Make a new random drop generator.  Each `drop` is called, this generates a
     /// random value between 0 and `max` (exclusive).  If this value is less than
     /// `threshold` a value of `true` is returned.
+    #[must_use]
     pub fn new(threshold: u64, max: u64) -> Self {
         Self {
             threshold,
@@ -36,11 +37,17 @@ impl Drop {
     }
 
     /// Generate random drops with the given percentage.
+    #[must_use]
     pub fn percentage(pct: u8) -> Self {
         // Multiply by 10 so that the random number generator works more efficiently.
         Self::new(u64::from(pct) * 10, 1000)
     }
 
+    /// Decide whether to drop a datagram.
+    ///
+    /// # Panics
+    ///
+    /// If the node has not been initialized.
     pub fn drop(&mut self) -> bool {
         let mut rng = self.rng.as_ref().unwrap().borrow_mut();
         let r = rng.random_from(0..self.max);
@@ -0,0 +1,313 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::{
+    cmp::min,
+    fmt::{self, Debug},
+    time::Instant,
+};
+
+use neqo_common::{event::Provider, qdebug, qtrace, Datagram, Header};
+use neqo_crypto::AuthenticationStatus;
+use neqo_http3::{
+    Http3Client, Http3ClientEvent, Http3OrWebTransportStream, Http3Parameters, Http3Server,
+    Http3ServerEvent, Http3State, Priority,
+};
+use neqo_transport::{Output, StreamId};
+
+use super::{
+    connection::{process_goals, GoalStatus},
+    Node,
+};
+use crate::{
+    default_http3_client, default_http3_server, http3_client_with_params, DEFAULT_SERVER_NAME,
+};
+
+/// The data that is sent in response bodies.
+const DATA: &[u8] = &[0; 4096];
+
+pub trait Http3ClientGoal {
+    /// Perform some processing.
+    fn process(&mut self, _c: &mut Http3Client, _now: Instant) -> GoalStatus {
+        GoalStatus::Waiting
+    }
+    /// Handle an event from the provided client.
+    fn handle_event(
+        &mut self,
+        c: &mut Http3Client,
+        e: &Http3ClientEvent,
+        now: Instant,
+    ) -> GoalStatus;
+}
+
+pub trait Http3ServerGoal {
+    /// Handle an event from the provided server.
+    fn handle_event(&mut self, s: &mut Http3Server, e: &Http3ServerEvent) -> GoalStatus;
+}
+
+pub struct Http3ClientNode {
+    c: Http3Client,
+    goals: Vec<Box<dyn Http3ClientGoal>>,
+}
+
+impl Http3ClientNode {
+    #[must_use]
+    pub fn new(
+        params: Http3Parameters,
+        goals: impl IntoIterator<Item = Box<dyn Http3ClientGoal>>,
+    ) -> Self {
+        Self {
+            c: http3_client_with_params(params),
+            goals: goals.into_iter().collect(),
+        }
+    }
+
+    #[must_use]
+    pub fn default(goals: impl IntoIterator<Item = Box<dyn Http3ClientGoal>>) -> Self {
+        Self {
+            c: default_http3_client(),
+            goals: goals.into_iter().collect(),
+        }
+    }
+}
+
+impl Node for Http3ClientNode {
+    fn process(&mut self, mut d: Option<Datagram>, now: Instant) -> Output {
+        _ = process_goals(&mut self.goals, &mut self.c, |goal, c| goal.process(c, now));
+        loop {
+            let res = self.c.process(d.take().as_ref(), now);
+
+            let mut active = false;
+            while let Some(e) = self.c.next_event() {
+                qtrace!([self.c], "received event {:?}", e);
+
+                // Perform authentication automatically.
+                if matches!(e, Http3ClientEvent::AuthenticationNeeded) {
+                    self.c.authenticated(AuthenticationStatus::Ok, now);
+                }
+
+                active |= process_goals(&mut self.goals, &mut self.c, |goal, c| {
+                    goal.handle_event(c, &e, now)
+                });
+            }
+            // As with `ConnectionNode`, only loop while goals make progress.
+            if matches!(res, Output::Datagram(_)) || !active {
+                return res;
+            }
+            qdebug!([self.c], "no datagram and goal activity, looping");
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.goals.is_empty()
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        println!("{}: {:?}", test_name, self.c.transport_stats());
+    }
+}
+
+impl Debug for Http3ClientNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.c, f)
+    }
+}
+
+pub struct Http3ServerNode {
+    s: Http3Server,
+    goals: Vec<Box<dyn Http3ServerGoal>>,
+}
+
+impl Http3ServerNode {
+    #[must_use]
+    pub fn new(goals: impl IntoIterator<Item = Box<dyn Http3ServerGoal>>) -> Self {
+        Self {
+            s: default_http3_server(),
+            goals: goals.into_iter().collect(),
+        }
+    }
+}
+
+impl Node for Http3ServerNode {
+    fn process(&mut self, mut d: Option<Datagram>, now: Instant) -> Output {
+        loop {
+            let res = self.s.process(d.take().as_ref(), now);
+
+            let mut active = false;
+            while let Some(e) = self.s.next_event() {
+                qtrace!([self.s], "received event {:?}", e);
+                active |= process_goals(&mut self.goals, &mut self.s, |goal, s| {
+                    goal.handle_event(s, &e)
+                });
+            }
+            if matches!(res, Output::Datagram(_)) || !active {
+                return res;
+            }
+            qdebug!([self.s], "no datagram and goal activity, looping");
+        }
+    }
+
+    fn done(&self) -> bool {
+        self.goals.is_empty()
+    }
+}
+
+impl Debug for Http3ServerNode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fmt::Display::fmt(&self.s, f)
+    }
+}
+
+/// Fetch a resource once the client is connected and read the response body,
+/// which has to be the expected size.
+#[derive(Debug)]
+pub struct Fetch {
+    expected: usize,
+    received: usize,
+    stream_id: Option<StreamId>,
+}
+
+impl Fetch {
+    #[must_use]
+    pub fn new(expected: usize) -> Self {
+        Self {
+            expected,
+            received: 0,
+            stream_id: None,
+        }
+    }
+
+    fn read(&mut self, c: &mut Http3Client, stream_id: StreamId, now: Instant) -> GoalStatus {
+        let mut buf = vec![0; 4096];
+        let mut status = GoalStatus::Waiting;
+        loop {
+            let (received, fin) = c.read_data(now, stream_id, &mut buf).unwrap();
+            qtrace!("Fetch read {} fin={}", received, fin);
+            self.received += received;
+            if fin {
+                assert_eq!(self.received, self.expected);
+                return GoalStatus::Done;
+            }
+            if received == 0 {
+                return status;
+            }
+            status = GoalStatus::Active;
+        }
+    }
+}
+
+impl Http3ClientGoal for Fetch {
+    fn handle_event(
+        &mut self,
+        c: &mut Http3Client,
+        e: &Http3ClientEvent,
+        now: Instant,
+    ) -> GoalStatus {
+        match e {
+            Http3ClientEvent::StateChange(Http3State::Connected) if self.stream_id.is_none() => {
+                let stream_id = c
+                    .fetch(
+                        now,
+                        "GET",
+                        &("https", DEFAULT_SERVER_NAME, "/"),
+                        &[],
+                        Priority::default(),
+                    )
+                    .unwrap();
+                c.stream_close_send(stream_id).unwrap();
+                self.stream_id = Some(stream_id);
+                GoalStatus::Active
+            }
+            Http3ClientEvent::HeaderReady { stream_id, fin, .. }
+                if Some(*stream_id) == self.stream_id && *fin =>
+            {
+                assert_eq!(self.expected, 0);
+                GoalStatus::Done
+            }
+            Http3ClientEvent::DataReadable { stream_id } if Some(*stream_id) == self.stream_id => {
+                self.read(c, *stream_id, now)
+            }
+            _ => GoalStatus::Waiting,
+        }
+    }
+}
+
+/// Respond to a number of requests, each with a body of the given size.
+#[derive(Debug)]
+pub struct ServeRequests {
+    /// The number of requests that are still to be received.
+    requests: usize,
+    size: usize,
+    /// Responses that are still being sent and how much of each body remains.
+    responses: Vec<(Http3OrWebTransportStream, usize)>,
+}
+
+impl ServeRequests {
+    #[must_use]
+    pub fn new(requests: usize, size: usize) -> Self {
+        Self {
+            requests,
+            size,
+            responses: Vec::new(),
+        }
+    }
+
+    /// Send as much of the response at index `i` as possible.
+    fn send(&mut self, i: usize) -> GoalStatus {
+        let (stream, remaining) = &mut self.responses[i];
+        let mut written = false;
+        while *remaining > 0 {
+            let end = min(*remaining, DATA.len());
+            let sent = stream.send_data(&DATA[..end]).unwrap();
+            qtrace!("ServeRequests sent {}", sent);
+            if sent == 0 {
+                return if written {
+                    GoalStatus::Active
+                } else {
+                    GoalStatus::Waiting
+                };
+            }
+            written = true;
+            *remaining -= sent;
+        }
+        stream.stream_close_send().unwrap();
+        self.responses.remove(i);
+        if self.requests == 0 && self.responses.is_empty() {
+            GoalStatus::Done
+        } else {
+            GoalStatus::Active
+        }
+    }
+}
+
+impl Http3ServerGoal for ServeRequests {
+    fn handle_event(&mut self, _s: &mut Http3Server, e: &Http3ServerEvent) -> GoalStatus {
+        match e {
+            Http3ServerEvent::Headers { stream, .. } if self.requests > 0 => {
+                self.requests -= 1;
+                let mut stream = stream.clone();
+                stream
+                    .send_headers(&[
+                        Header::new(":status", "200"),
+                        Header::new("content-length", self.size.to_string()),
+                    ])
+                    .unwrap();
+                self.responses.push((stream, self.size));
+                self.send(self.responses.len() - 1)
+            }
+            Http3ServerEvent::DataWritable { stream } => {
+                if let Some(i) = self.responses.iter().position(|(s, _)| s == stream) {
+                    self.send(i)
+                } else {
+                    GoalStatus::Waiting
+                }
+            }
+            _ => GoalStatus::Waiting,
+        }
+    }
+}
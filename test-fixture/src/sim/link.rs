@@ -0,0 +1,248 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    cmp::min,
+    collections::VecDeque,
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+use neqo_common::Datagram;
+use neqo_transport::Output;
+
+/// One second in nanoseconds.
+const ONE_SECOND_NS: u128 = 1_000_000_000;
+
+/// The rate of a link, in bytes per second, which can change over time.
+#[derive(Debug, Clone)]
+pub struct RateSchedule {
+    /// Each rate applies from the given time after the start of the simulation
+    /// until the time of the next entry.
+    steps: Vec<(Duration, usize)>,
+    /// If set, the schedule starts over after this much time.
+    period: Option<Duration>,
+}
+
+impl RateSchedule {
+    /// Make a schedule from a list of times, measured from the start of the
+    /// simulation, and the rate that applies from that time.
+    ///
+    /// # Panics
+    ///
+    /// If the schedule is empty, the first entry is not at zero, the times are
+    /// not in increasing order, or any rate is zero.
+    #[must_use]
+    pub fn new(steps: impl IntoIterator<Item = (Duration, usize)>) -> Self {
+        let steps = steps.into_iter().collect::<Vec<_>>();
+        assert_eq!(
+            steps.first().map(|(t, _)| *t),
+            Some(Duration::ZERO),
+            "a rate schedule needs to start at zero"
+        );
+        assert!(
+            steps.windows(2).all(|w| w[0].0 < w[1].0),
+            "rate schedule times need to increase"
+        );
+        assert!(steps.iter().all(|(_, r)| *r > 0), "rates can't be zero");
+        Self {
+            steps,
+            period: None,
+        }
+    }
+
+    /// Repeat the schedule every `period`.
+    ///
+    /// # Panics
+    ///
+    /// If the period doesn't extend past the last entry in the schedule.
+    #[must_use]
+    pub fn repeat(mut self, period: Duration) -> Self {
+        assert!(period > self.steps.last().unwrap().0);
+        self.period = Some(period);
+        self
+    }
+
+    /// The rate at `elapsed` after the start of the simulation.
+    fn rate(&self, elapsed: Duration) -> usize {
+        let elapsed = if let Some(period) = self.period {
+            Duration::from_nanos(u64::try_from(elapsed.as_nanos() % period.as_nanos()).unwrap())
+        } else {
+            elapsed
+        };
+        let i = self.steps.partition_point(|(t, _)| *t <= elapsed);
+        self.steps[i - 1].1
+    }
+}
+
+impl From<usize> for RateSchedule {
+    fn from(rate: usize) -> Self {
+        Self::new([(Duration::ZERO, rate)])
+    }
+}
+
+/// The part of a link that follows a queue: datagrams are put on the link at
+/// some rate, then they take a fixed time to reach the other end.
+pub struct Link {
+    /// An overhead associated with each entry.  This accounts for
+    /// layer 2, IP, and UDP overheads.
+    overhead: usize,
+    /// The rate at which bytes egress the link.
+    rate: RateSchedule,
+    /// When the simulation started, which the rate schedule is based on.
+    start: Option<Instant>,
+
+    /// The time that the next datagram can enter the link.
+    next_deque: Option<Instant>,
+    /// Any sub-ns delay from the last enqueue.
+    sub_ns_delay: u32,
+    /// The time it takes a byte to exit the other end of the link.
+    delay: Duration,
+    /// The packets that are on the link and when they can be delivered.
+    on_link: VecDeque<(Instant, Datagram)>,
+}
+
+impl Link {
+    pub fn new(rate: RateSchedule, delay: Duration) -> Self {
+        Self {
+            overhead: 64,
+            rate,
+            start: None,
+            next_deque: None,
+            sub_ns_delay: 0,
+            delay,
+            on_link: VecDeque::new(),
+        }
+    }
+
+    pub fn init(&mut self, now: Instant) {
+        self.start = Some(now);
+    }
+
+    /// How "big" is this datagram, accounting for overheads.
+    /// This approximates by using the same overhead for storing in the queue
+    /// and for sending on the wire.
+    pub fn size(&self, d: &Datagram) -> usize {
+        d.len() + self.overhead
+    }
+
+    /// Whether the link is idle: nothing is being sent and there is no need to
+    /// check the queue.
+    pub fn idle(&self) -> bool {
+        self.next_deque.is_none()
+    }
+
+    /// Whether the last datagram that was sent has been completely sent, so
+    /// that the next datagram can be taken from the queue.
+    pub fn ready(&self, now: Instant) -> bool {
+        self.next_deque.map_or(true, |t| t <= now)
+    }
+
+    /// Note that the queue is empty, so the link becomes idle.
+    pub fn stop(&mut self) {
+        self.next_deque = None;
+        self.sub_ns_delay = 0;
+    }
+
+    /// Start sending a datagram.
+    pub fn send(&mut self, d: Datagram, now: Instant) {
+        // How many bytes are we "transmitting"?
+        let sz = u128::try_from(self.size(&d)).unwrap();
+        let rate = self
+            .rate
+            .rate(now - self.start.expect("link is initialized"));
+
+        // Calculate how long it takes to put the packet on the link.
+        // Perform the calculation based on 2^32 seconds and save any remainder.
+        // This ensures that high rates and small packets don't result in rounding
+        // down times too badly.
+        // Duration consists of a u64 and a u32, so we have 32 high bits to spare.
+        let t = sz * (ONE_SECOND_NS << 32) / u128::try_from(rate).unwrap()
+            + u128::from(self.sub_ns_delay);
+        let send_ns = u64::try_from(t >> 32).unwrap();
+        assert_ne!(send_ns, 0, "sending a packet takes <1ns");
+        self.sub_ns_delay = u32::try_from(t & u128::from(u32::MAX)).unwrap();
+        let deque_time = now + Duration::from_nanos(send_ns);
+        self.next_deque = Some(deque_time);
+
+        // Now work out when the packet is fully received at the other end of
+        // the link. Setup to deliver the packet then.
+        let delivery_time = deque_time + self.delay;
+        self.on_link.push_back((delivery_time, d));
+    }
+
+    /// Deliver a datagram if one has reached the other end of the link.
+    /// Otherwise, ask to be called back when that happens or when the next
+    /// datagram can be taken from the queue.
+    pub fn output(&mut self, now: Instant) -> Output {
+        if let Some((t, _)) = self.on_link.front() {
+            if *t <= now {
+                return Output::Datagram(self.on_link.pop_front().unwrap().1);
+            }
+        }
+        let delivery = self.on_link.front().map(|(t, _)| *t);
+        let deque = self.next_deque.filter(|t| *t > now);
+        match (delivery, deque) {
+            (Some(a), Some(b)) => Output::Callback(min(a, b) - now),
+            (Some(t), None) | (None, Some(t)) => Output::Callback(t - now),
+            (None, None) => Output::None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use neqo_transport::Output;
+
+    use super::{Link, RateSchedule};
+    use crate::{datagram, now};
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn rate_schedule() {
+        let schedule = RateSchedule::new([(Duration::ZERO, 1000), (MS * 100, 2000)]);
+        assert_eq!(schedule.rate(Duration::ZERO), 1000);
+        assert_eq!(schedule.rate(MS * 99), 1000);
+        assert_eq!(schedule.rate(MS * 100), 2000);
+        assert_eq!(schedule.rate(MS * 10_000), 2000);
+
+        let repeating = schedule.repeat(MS * 300);
+        assert_eq!(repeating.rate(MS * 299), 2000);
+        assert_eq!(repeating.rate(MS * 300), 1000);
+        assert_eq!(repeating.rate(MS * 450), 2000);
+    }
+
+    /// A datagram that is sent after the rate changes uses the new rate.
+    #[test]
+    fn rate_switch() {
+        let mut link = Link::new(
+            RateSchedule::new([(Duration::ZERO, 10_000), (MS * 100, 20_000)]),
+            MS * 10,
+        );
+        let start = now();
+        link.init(start);
+
+        // With the overhead, each datagram is 1000 bytes, which takes 100ms at first.
+        let d = datagram(vec![0; 936]);
+        assert_eq!(link.size(&d), 1000);
+        link.send(d.clone(), start);
+        assert!(!link.ready(start + MS * 99));
+        assert_eq!(link.output(start), Output::Callback(MS * 100));
+
+        // The next takes 50ms.
+        let t = start + MS * 100;
+        assert!(link.ready(t));
+        link.send(d, t);
+        assert_eq!(link.output(t), Output::Callback(MS * 10));
+        assert!(link.output(t + MS * 10).dgram().is_some());
+        assert_eq!(link.output(t + MS * 10), Output::Callback(MS * 40));
+        assert!(link.output(t + MS * 60).dgram().is_some());
+        assert!(link.ready(t + MS * 50));
+    }
+}
@@ -4,21 +4,26 @@
 // option. This file may not be copied, modified, or distributed
 // except according to those terms.
 
-// Tests with simulated network
-#![cfg_attr(feature = "deny-warnings", deny(warnings))]
-#![warn(clippy::pedantic)]
+//! A simulated network, for tests that run connections over links with
+//! delay, loss, limited capacity and the like.
 
+mod codel;
 pub mod connection;
 mod delay;
 mod drop;
+pub mod http3;
+mod link;
+mod reorder;
 pub mod rng;
 mod taildrop;
+mod trace;
 
 use std::{
     cell::RefCell,
     cmp::min,
     convert::TryFrom,
     fmt::Debug,
+    iter,
     rc::Rc,
     time::{Duration, Instant},
 };
@@ -26,11 +31,20 @@ use std::{
 use neqo_common::{qdebug, qinfo, qtrace, Datagram, Encoder};
 use neqo_transport::Output;
 use rng::Random;
-use test_fixture::{self, now};
 use NodeState::{Active, Idle, Waiting};
 
+use crate::now;
+
 pub mod network {
-    pub use super::{delay::Delay, drop::Drop, taildrop::TailDrop};
+    pub use super::{
+        codel::CoDel,
+        delay::Delay,
+        drop::Drop,
+        link::RateSchedule,
+        reorder::{Duplicate, Reorder},
+        taildrop::TailDrop,
+        trace::TraceLink,
+    };
 }
 
 type Rng = Rc<RefCell<Random>>;
@@ -53,7 +67,7 @@ macro_rules! boxed {
 #[macro_export]
 macro_rules! simulate {
     ($n:ident, [ $($v:expr),+ $(,)? ] $(,)?) => {
-        simulate!($n, (), [ $(|_| $v),+ ]);
+        $crate::simulate!($n, (), [ $(|_| $v),+ ]);
     };
     ($n:ident, $setup:expr, [ $( $v:expr ),+ $(,)? ] $(,)?) => {
         #[test]
@@ -64,7 +78,7 @@ macro_rules! simulate {
                 let f: Box<dyn FnOnce(&_) -> _> = Box::new($v);
                 nodes.push(Box::new(f(&fixture)));
             )*
-            let mut sim = Simulator::new(stringify!($n), nodes);
+            let mut sim = $crate::sim::Simulator::new(stringify!($n), nodes);
             if let Ok(seed) = std::env::var("SIMULATION_SEED") {
                 sim.seed_str(seed);
             }
@@ -85,6 +99,24 @@ pub trait Node: Debug {
     fn print_summary(&self, _test_name: &str) {}
 }
 
+/// Arrange a client and a server that are connected by different links in
+/// each direction.  Datagrams travel around the simulator in a loop, so the
+/// nodes in `uplink` carry datagrams from the client to the server and the
+/// nodes in `downlink` carry them back.
+#[must_use]
+pub fn asymmetric(
+    client: Box<dyn Node>,
+    uplink: impl IntoIterator<Item = Box<dyn Node>>,
+    server: Box<dyn Node>,
+    downlink: impl IntoIterator<Item = Box<dyn Node>>,
+) -> Vec<Box<dyn Node>> {
+    iter::once(client)
+        .chain(uplink)
+        .chain(iter::once(server))
+        .chain(downlink)
+        .collect()
+}
+
 /// The state of a single node.  Nodes will be activated if they are `Active`
 /// or if the previous node in the loop generated a datagram.  Nodes that return
 /// `true` from `Node::done` will be activated as normal.
@@ -121,6 +153,7 @@ pub struct Simulator {
 }
 
 impl Simulator {
+    #[must_use]
     pub fn new(name: impl AsRef<str>, nodes: impl IntoIterator<Item = Box<dyn Node>>) -> Self {
         let name = String::from(name.as_ref());
         // The first node is marked as Active, the rest are idle.
@@ -146,6 +179,9 @@ impl Simulator {
     }
 
     /// Seed from a hex string.
+    ///
+    /// # Panics
+    ///
     /// Though this is convenient, it panics if this isn't a 64 character hex string.
     pub fn seed_str(&mut self, seed: impl AsRef<str>) {
         let seed = Encoder::from_hex(seed);
@@ -165,6 +201,11 @@ impl Simulator {
     }
 
     /// Runs the simulation.
+    ///
+    /// # Panics
+    ///
+    /// If a node goes idle before it is done, or a node asks to be called
+    /// back without any delay.
     pub fn run(mut self) -> Duration {
         let start = now();
         let mut now = start;
@@ -0,0 +1,205 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+use std::{
+    collections::{BTreeMap, VecDeque},
+    fmt::{self, Debug},
+    time::{Duration, Instant},
+};
+
+use neqo_common::{qtrace, Datagram};
+use neqo_transport::Output;
+
+use super::{drop::Drop, Node, Rng};
+
+/// Reorders datagrams by holding some of them back, so that the datagrams that
+/// follow overtake them.
+pub struct Reorder {
+    /// Decides which datagrams are held back.
+    chooser: Drop,
+    /// How long datagrams are held back for.
+    delay: Duration,
+    /// The datagrams that are held back.
+    held: BTreeMap<Instant, Datagram>,
+    /// The number of datagrams that were held back.
+    reordered: usize,
+}
+
+impl Reorder {
+    /// Hold back the given percentage of datagrams for `delay`.
+    #[must_use]
+    pub fn new(pct: u8, delay: Duration) -> Self {
+        Self {
+            chooser: Drop::percentage(pct),
+            delay,
+            held: BTreeMap::new(),
+            reordered: 0,
+        }
+    }
+}
+
+impl Node for Reorder {
+    fn init(&mut self, rng: Rng, now: Instant) {
+        self.chooser.init(rng, now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        if let Some(dgram) = d {
+            if !self.chooser.drop() {
+                return Output::Datagram(dgram);
+            }
+            qtrace!("reorder holding {}", dgram.len());
+            self.reordered += 1;
+            let mut t = now + self.delay;
+            while self.held.contains_key(&t) {
+                t += Duration::from_nanos(1);
+            }
+            self.held.insert(t, dgram);
+        }
+        if let Some((&t, _)) = self.held.iter().next() {
+            if t <= now {
+                Output::Datagram(self.held.remove(&t).unwrap())
+            } else {
+                Output::Callback(t - now)
+            }
+        } else {
+            Output::None
+        }
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        println!("{}: reorder: {}", test_name, self.reordered);
+    }
+}
+
+impl Debug for Reorder {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("reorder")
+    }
+}
+
+/// Duplicates some datagrams.
+pub struct Duplicate {
+    /// Decides which datagrams are duplicated.
+    chooser: Drop,
+    /// Datagrams that are waiting to be passed on.
+    queue: VecDeque<Datagram>,
+    /// The number of datagrams that were duplicated.
+    duplicated: usize,
+}
+
+impl Duplicate {
+    /// Duplicate the given percentage of datagrams.
+    #[must_use]
+    pub fn percentage(pct: u8) -> Self {
+        Self {
+            chooser: Drop::percentage(pct),
+            queue: VecDeque::new(),
+            duplicated: 0,
+        }
+    }
+}
+
+impl Node for Duplicate {
+    fn init(&mut self, rng: Rng, now: Instant) {
+        self.chooser.init(rng, now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, _now: Instant) -> Output {
+        if let Some(dgram) = d {
+            if self.chooser.drop() {
+                qtrace!("duplicate {}", dgram.len());
+                self.duplicated += 1;
+                self.queue.push_back(dgram.clone());
+            }
+            self.queue.push_back(dgram);
+        }
+        self.queue
+            .pop_front()
+            .map_or(Output::None, Output::Datagram)
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        println!("{}: duplicate: {}", test_name, self.duplicated);
+    }
+}
+
+impl Debug for Duplicate {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("duplicate")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use neqo_transport::Output;
+
+    use super::{Drop, Duplicate, Node, Reorder, Rng};
+    use crate::{datagram, now, sim::rng::Random};
+
+    const DELAY: Duration = Duration::from_millis(10);
+
+    fn rng() -> Rng {
+        Rc::new(RefCell::new(Random::new([1; 32])))
+    }
+
+    #[test]
+    fn reorder() {
+        let mut reorder = Reorder::new(100, DELAY);
+        let start = now();
+        reorder.init(rng(), start);
+
+        // Datagrams that arrive together are held, and released in order.
+        assert_eq!(
+            reorder.process(Some(datagram(vec![1])), start),
+            Output::Callback(DELAY)
+        );
+        assert_eq!(
+            reorder.process(Some(datagram(vec![2])), start),
+            Output::Callback(DELAY)
+        );
+
+        // A datagram that isn't held overtakes the others.
+        reorder.chooser = Drop::percentage(0);
+        reorder.chooser.init(rng(), start);
+        let t = start + DELAY / 2;
+        let d = reorder.process(Some(datagram(vec![3])), t).dgram().unwrap();
+        assert_eq!(&d[..], &[3]);
+        assert_eq!(reorder.process(None, t), Output::Callback(DELAY / 2));
+
+        let t = start + DELAY;
+        assert_eq!(&reorder.process(None, t).dgram().unwrap()[..], &[1]);
+        assert_eq!(
+            reorder.process(None, t),
+            Output::Callback(Duration::from_nanos(1))
+        );
+        let t = t + Duration::from_nanos(1);
+        assert_eq!(&reorder.process(None, t).dgram().unwrap()[..], &[2]);
+        assert_eq!(reorder.process(None, t), Output::None);
+        assert_eq!(reorder.reordered, 2);
+    }
+
+    #[test]
+    fn duplicate() {
+        let mut dup = Duplicate::percentage(100);
+        dup.init(rng(), now());
+        let d = datagram(vec![1, 2, 3]);
+        assert_eq!(
+            dup.process(Some(d.clone()), now()),
+            Output::Datagram(d.clone())
+        );
+        assert_eq!(dup.process(None, now()), Output::Datagram(d.clone()));
+        assert_eq!(dup.process(None, now()), Output::None);
+
+        let mut dup = Duplicate::percentage(0);
+        dup.init(rng(), now());
+        assert_eq!(dup.process(Some(d.clone()), now()), Output::Datagram(d));
+        assert_eq!(dup.process(None, now()), Output::None);
+        assert_eq!(dup.duplicated, 0);
+    }
+}
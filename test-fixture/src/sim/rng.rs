@@ -14,6 +14,10 @@ pub struct Random {
 }
 
 impl Random {
+    /// # Panics
+    ///
+    /// If the seed is all zeros.
+    #[must_use]
     pub fn new(seed: [u8; 32]) -> Self {
         assert!(seed.iter().any(|&x| x != 0));
         let mut dec = Decoder::from(&seed);
@@ -55,7 +59,6 @@ impl Random {
         }
 
         let shift = (max - 1).leading_zeros();
-        assert_ne!(max, 0);
         loop {
             let r = self.random() >> shift;
             if r < max {
@@ -65,6 +68,7 @@ impl Random {
     }
 
     /// Get the seed necessary to continue from this point.
+    #[must_use]
     pub fn seed_str(&self) -> String {
         format!(
             "{:8x}{:8x}{:8x}{:8x}",
@@ -0,0 +1,287 @@
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license
+// <LICENSE-MIT or http://opensource.org/licenses/MIT>, at your
+// option. This file may not be copied, modified, or distributed
+// except according to those terms.
+
+#![allow(clippy::module_name_repetitions)]
+
+use std::{
+    collections::VecDeque,
+    convert::TryFrom,
+    fmt::{self, Debug},
+    fs, io,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use neqo_common::{qtrace, Datagram};
+use neqo_transport::Output;
+
+use super::{Node, Rng};
+
+/// The number of bytes that can be sent at each opportunity in a trace.
+const OPPORTUNITY_SIZE: usize = 1504;
+/// The overhead of IPv6 and UDP headers, which count toward the size of a datagram.
+const HEADER_SIZE: usize = 48;
+
+/// A link that follows a trace in the format used by Mahimahi.  Each line of
+/// a trace is a time in milliseconds, at which the link can carry 1504 bytes.
+/// A datagram can be spread over several of these opportunities, or share one
+/// with other datagrams.  Opportunities that arrive while the queue is empty
+/// are wasted.  Once the trace runs out, it starts over.
+///
+/// This makes it possible to replay the capacity of cellular links, which
+/// changes quickly and often.
+pub struct TraceLink {
+    /// The times of each opportunity to send, relative to the start of the trace.
+    trace: Vec<Duration>,
+    /// The length of the trace, after which it repeats.
+    period: Duration,
+    /// When the current repetition of the trace started.
+    base: Option<Instant>,
+    /// The index of the next opportunity to send in `trace`.
+    next: usize,
+
+    /// The depth of the queue, in bytes.
+    capacity: usize,
+    /// A counter for how many bytes are enqueued.
+    used: usize,
+    /// A queue of datagrams and the number of bytes of each that are still to be sent.
+    queue: VecDeque<(Datagram, usize)>,
+    /// The time it takes a datagram to exit the other end of the link.
+    delay: Duration,
+    /// The packets that are on the link and when they can be delivered.
+    on_link: VecDeque<(Instant, Datagram)>,
+
+    /// The number of packets received.
+    received: usize,
+    /// The number of packets dropped.
+    dropped: usize,
+    /// The number of packets delivered.
+    delivered: usize,
+}
+
+impl TraceLink {
+    /// Make a link from the times, in milliseconds, of each opportunity to send,
+    /// with a tail drop queue of the given capacity in bytes and the given delay.
+    ///
+    /// # Panics
+    ///
+    /// If the trace is empty, ends at zero, or is not in order.
+    #[must_use]
+    pub fn new(trace: impl IntoIterator<Item = u64>, capacity: usize, delay: Duration) -> Self {
+        let trace = trace
+            .into_iter()
+            .map(Duration::from_millis)
+            .collect::<Vec<_>>();
+        assert!(
+            trace.windows(2).all(|w| w[0] <= w[1]),
+            "trace needs to be in order"
+        );
+        let period = *trace.last().expect("trace can't be empty");
+        assert!(period > Duration::ZERO, "trace needs to end after zero");
+        Self {
+            trace,
+            period,
+            base: None,
+            next: 0,
+            capacity,
+            used: 0,
+            queue: VecDeque::new(),
+            delay,
+            on_link: VecDeque::new(),
+            received: 0,
+            dropped: 0,
+            delivered: 0,
+        }
+    }
+
+    /// Make a link from a trace in Mahimahi format.
+    ///
+    /// # Errors
+    ///
+    /// If a line is not a number.
+    pub fn parse(trace: &str, capacity: usize, delay: Duration) -> io::Result<Self> {
+        let trace = trace
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(|l| {
+                l.parse::<u64>()
+                    .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+            })
+            .collect::<io::Result<Vec<_>>>()?;
+        Ok(Self::new(trace, capacity, delay))
+    }
+
+    /// Make a link from a file that contains a trace in Mahimahi format.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be read or a line is not a number.
+    pub fn from_file(path: impl AsRef<Path>, capacity: usize, delay: Duration) -> io::Result<Self> {
+        Self::parse(&fs::read_to_string(path)?, capacity, delay)
+    }
+
+    fn size(d: &Datagram) -> usize {
+        d.len() + HEADER_SIZE
+    }
+
+    /// The time of the next opportunity to send.
+    fn next_opportunity(&self) -> Instant {
+        self.base.expect("link is initialized") + self.trace[self.next]
+    }
+
+    /// Move to the next opportunity, starting the trace over at the end.
+    fn advance(&mut self) {
+        self.next += 1;
+        if self.next == self.trace.len() {
+            self.next = 0;
+            self.base = self.base.map(|t| t + self.period);
+        }
+    }
+
+    /// Use every opportunity up to `now` to send datagrams from the queue.
+    fn send(&mut self, now: Instant) {
+        while !self.queue.is_empty() && self.next_opportunity() <= now {
+            let t = self.next_opportunity();
+            let mut avail = OPPORTUNITY_SIZE;
+            while let Some((_, remaining)) = self.queue.front_mut() {
+                if *remaining > avail {
+                    *remaining -= avail;
+                    break;
+                }
+                avail -= *remaining;
+                let (d, _) = self.queue.pop_front().unwrap();
+                self.used -= Self::size(&d);
+                self.on_link.push_back((t + self.delay, d));
+            }
+            self.advance();
+        }
+
+        // Skip any opportunities that pass while the queue is empty.
+        if self.queue.is_empty() {
+            let base = self.base.expect("link is initialized");
+            if now >= base + self.period {
+                let period = self.period.as_nanos();
+                let skipped = (now - base).as_nanos() / period * period;
+                self.base = Some(base + Duration::from_nanos(u64::try_from(skipped).unwrap()));
+                self.next = 0;
+            }
+            let offset = now - self.base.unwrap();
+            let skip = self.trace[self.next..].partition_point(|t| *t <= offset);
+            for _ in 0..skip {
+                self.advance();
+            }
+        }
+    }
+
+    fn enqueue(&mut self, d: Datagram) {
+        self.received += 1;
+        if self.used + Self::size(&d) <= self.capacity {
+            self.used += Self::size(&d);
+            let remaining = Self::size(&d);
+            self.queue.push_back((d, remaining));
+        } else {
+            qtrace!("trace link dropping {} bytes", d.len());
+            self.dropped += 1;
+        }
+    }
+}
+
+impl Node for TraceLink {
+    fn init(&mut self, _rng: Rng, now: Instant) {
+        self.base = Some(now);
+    }
+
+    fn process(&mut self, d: Option<Datagram>, now: Instant) -> Output {
+        // Opportunities that have passed can't be used by a new datagram.
+        self.send(now);
+        if let Some(dgram) = d {
+            self.enqueue(dgram);
+        }
+
+        if let Some((t, _)) = self.on_link.front() {
+            if *t <= now {
+                self.delivered += 1;
+                return Output::Datagram(self.on_link.pop_front().unwrap().1);
+            }
+        }
+        let delivery = self.on_link.front().map(|(t, _)| *t);
+        let opportunity = if self.queue.is_empty() {
+            None
+        } else {
+            Some(self.next_opportunity())
+        };
+        match (delivery, opportunity) {
+            (Some(a), Some(b)) => Output::Callback(a.min(b) - now),
+            (Some(t), None) | (None, Some(t)) => Output::Callback(t - now),
+            (None, None) => Output::None,
+        }
+    }
+
+    fn print_summary(&self, test_name: &str) {
+        println!(
+            "{}: trace: rx {} drop {} tx {}",
+            test_name, self.received, self.dropped, self.delivered,
+        );
+    }
+}
+
+impl Debug for TraceLink {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("trace")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{cell::RefCell, rc::Rc, time::Duration};
+
+    use neqo_transport::Output;
+
+    use super::{Node, TraceLink, HEADER_SIZE, OPPORTUNITY_SIZE};
+    use crate::{datagram, now, sim::rng::Random};
+
+    const MS: Duration = Duration::from_millis(1);
+
+    #[test]
+    fn delivery_times() {
+        let mut link = TraceLink::new([10, 20, 30], 10_000, MS * 5);
+        let start = now();
+        link.init(Rc::new(RefCell::new(Random::new([1; 32]))), start);
+        let one = datagram(vec![0; OPPORTUNITY_SIZE - HEADER_SIZE]);
+        let two = datagram(vec![0; 2 * OPPORTUNITY_SIZE - HEADER_SIZE]);
+
+        // A datagram that fits in one opportunity goes at the first, then takes 5ms.
+        assert_eq!(
+            link.process(Some(one.clone()), start),
+            Output::Callback(MS * 10)
+        );
+        assert_eq!(
+            link.process(None, start + MS * 10),
+            Output::Callback(MS * 5)
+        );
+        assert!(link.process(None, start + MS * 15).dgram().is_some());
+
+        // A larger datagram needs two opportunities.
+        let t = start + MS * 15;
+        assert_eq!(link.process(Some(two), t), Output::Callback(MS * 5));
+        assert_eq!(link.process(None, t + MS * 5), Output::Callback(MS * 10));
+        assert_eq!(link.process(None, t + MS * 15), Output::Callback(MS * 5));
+        assert!(link.process(None, t + MS * 20).dgram().is_some());
+
+        // The trace then starts over, from 30ms.
+        let t = start + MS * 35;
+        assert_eq!(link.process(Some(one.clone()), t), Output::Callback(MS * 5));
+        assert_eq!(link.process(None, t + MS * 5), Output::Callback(MS * 5));
+        assert!(link.process(None, t + MS * 10).dgram().is_some());
+
+        // Opportunities that pass while the queue is empty are wasted, including
+        // the one at 100ms (10ms into the fourth repetition).
+        let t = start + MS * 100;
+        assert_eq!(link.process(Some(one), t), Output::Callback(MS * 10));
+        assert_eq!(link.delivered, 3);
+    }
+}